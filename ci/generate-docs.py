@@ -140,6 +140,7 @@ TOC = [
             ),
             Page("Scrollback", "scrollback.md"),
             Page("Copy Mode", "copymode.md"),
+            Page("Quick Select Mode", "quickselect.md"),
             Page("Hyperlinks", "hyperlinks.md"),
             Page("Shell Integration", "shell-integration.md"),
             Page("iTerm Image Protocol", "imgcat.md"),
@@ -12,10 +12,12 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 
 use anyhow::{bail, Context as _, Error};
+use config::keyassignment::{PaneDirection, ScrollbackExportFormat};
 use leb128;
 use mux::domain::DomainId;
 use mux::pane::PaneId;
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::stats::StatsSnapshot;
 use mux::tab::{PaneNode, SerdeUrl, SplitDirection, TabId};
 use mux::window::WindowId;
 use portable_pty::{CommandBuilder, PtySize};
@@ -23,11 +25,13 @@ use rangeset::*;
 use serde::{Deserialize, Serialize};
 use smol::io::AsyncWriteExt;
 use smol::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::io::Cursor;
 use std::ops::Range;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use termwiz::hyperlink::Hyperlink;
+use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
 use termwiz::surface::Line;
 use varbincode;
 use wezterm_term::StableRowIndex;
@@ -400,7 +404,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 6;
+pub const CODEC_VERSION: usize = 19;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -434,6 +438,29 @@ pdu! {
     SearchScrollbackResponse: 32,
     SetPaneZoomed: 33,
     SplitPane: 34,
+    GetClientList: 35,
+    GetClientListResponse: 36,
+    SftpStat: 37,
+    SftpStatResponse: 38,
+    SftpRead: 39,
+    SftpReadResponse: 40,
+    SftpWrite: 41,
+    SetLogFilter: 42,
+    GetStats: 43,
+    GetStatsResponse: 44,
+    SetViewedTabs: 45,
+    RestoreSession: 46,
+    RestoreSessionResponse: 47,
+    SubscribeToMuxEvents: 48,
+    MuxEventPdu: 49,
+    CapturePane: 50,
+    CapturePaneResponse: 51,
+    WaitPane: 52,
+    WaitPaneResponse: 53,
+    ChoosePane: 54,
+    ChoosePaneResponse: 55,
+    SwapPanes: 56,
+    MovePane: 57,
 }
 
 impl Pdu {
@@ -657,6 +684,24 @@ pub struct Resize {
     pub size: PtySize,
 }
 
+/// Tells the server which of its tabs the client is displaying,
+/// and at what size.  Each tab is sized to fit the smallest of the
+/// clients that are displaying it.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetViewedTabs {
+    pub tabs: Vec<(TabId, PtySize)>,
+}
+
+/// Asks the server to recreate the windows, tabs and panes from
+/// its most recently saved session snapshot
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RestoreSession {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RestoreSessionResponse {
+    pub tabs_restored: usize,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct SetPaneZoomed {
     pub containing_tab_id: TabId,
@@ -695,6 +740,9 @@ pub struct GetPaneRenderChangesResponse {
 pub struct GetLines {
     pub pane_id: PaneId,
     pub lines: Vec<Range<StableRowIndex>>,
+    /// Images that were referenced by previously sent lines but that
+    /// the client no longer has, and whose data must be sent again
+    pub forget_images: Vec<usize>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -716,22 +764,94 @@ struct LineHyperlink {
 /// This section of code extracts the hyperlinks from the cells and builds
 /// up a mapping that can be used to restore the identity when the `lines()`
 /// method is called.
+/// Image cells are treated similarly: the cells reference the image by
+/// id and the image data is sent alongside, but only if the peer hasn't
+/// already been sent that image.
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct SerializedLines {
     lines: Vec<(StableRowIndex, Line)>,
     hyperlinks: Vec<LineHyperlink>,
-    // TODO: image references
+    images: Vec<LineImageCell>,
+    image_data: Vec<SerializedImageData>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+struct LineImageCell {
+    line_idx: usize,
+    col: usize,
+    image_id: usize,
+    top_left: TextureCoordinate,
+    bottom_right: TextureCoordinate,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+struct SerializedImageData {
+    image_id: usize,
+    data: Vec<u8>,
+}
+
+/// Images larger than this are not sent to the peer; the cells that
+/// they occupy are sent without them.
+pub const MAX_IMAGE_DATA_SIZE: usize = 32 * 1024 * 1024;
+
+/// Tracks which images have been sent to the peer, so that lines
+/// that reference them again don't need to include their data
+#[derive(Default, Debug)]
+pub struct SentImages {
+    ids: HashSet<usize>,
+}
+
+impl SentImages {
+    /// Returns true if the data for `image_id` needs to be sent
+    fn should_send(&mut self, image_id: usize) -> bool {
+        self.ids.insert(image_id)
+    }
+
+    /// Forget that we sent these images, so that their data will
+    /// be sent again the next time that they are referenced
+    pub fn forget(&mut self, image_ids: &[usize]) {
+        for id in image_ids {
+            self.ids.remove(id);
+        }
+    }
+}
+
+/// Holds the images that have been received from the peer, keyed by
+/// the id that the peer assigned to them.  Images are retained only
+/// for as long as some line is displaying them.
+#[derive(Default, Debug)]
+pub struct ReceivedImages {
+    images: HashMap<usize, Weak<ImageData>>,
+    missing: HashSet<usize>,
+    incomplete_rows: Vec<StableRowIndex>,
+}
+
+impl ReceivedImages {
+    /// Returns the ids of images that were referenced but that we
+    /// no longer have; the peer needs to be asked to forget them
+    pub fn take_missing(&mut self) -> Vec<usize> {
+        self.missing.drain().collect()
+    }
+
+    /// Returns the rows that were received with images missing,
+    /// which need to be fetched again
+    pub fn take_incomplete_rows(&mut self) -> Vec<StableRowIndex> {
+        std::mem::take(&mut self.incomplete_rows)
+    }
 }
 
 impl SerializedLines {
     pub fn lines(self) -> Vec<(StableRowIndex, Line)> {
         self.into()
     }
-}
 
-impl From<Vec<(StableRowIndex, Line)>> for SerializedLines {
-    fn from(mut lines: Vec<(StableRowIndex, Line)>) -> Self {
+    /// Prepares lines to be sent to a peer, omitting the data for
+    /// any images that are recorded in `sent` as having already been
+    /// sent to it
+    pub fn with_images(mut lines: Vec<(StableRowIndex, Line)>, sent: &mut SentImages) -> Self {
         let mut hyperlinks = vec![];
+        let mut images = vec![];
+        let mut image_data = vec![];
 
         for (line_idx, (_, line)) in lines.iter_mut().enumerate() {
             let mut current_link: Option<Arc<Hyperlink>> = None;
@@ -744,8 +864,10 @@ impl From<Vec<(StableRowIndex, Line)>> for SerializedLines {
             {
                 // Unset the hyperlink on the cell, if any, and record that
                 // in the hyperlinks data for later restoration.
-                if let Some(link) = cell.attrs_mut().hyperlink().map(Arc::clone) {
-                    cell.attrs_mut().set_hyperlink(None);
+                if let Some(link) = cell.attrs().hyperlink().map(Arc::clone) {
+                    let mut attrs = cell.attrs().clone();
+                    attrs.set_hyperlink(None);
+                    cell.set_attrs(attrs);
                     match current_link.as_ref() {
                         Some(current) if Arc::ptr_eq(&current, &link) => {
                             // Continue the current streak
@@ -781,7 +903,28 @@ impl From<Vec<(StableRowIndex, Line)>> for SerializedLines {
                     current_range = 0..0;
                 }
 
-                // TODO: something smart for image cells
+                if let Some(image) = cell.attrs().image().cloned() {
+                    let mut attrs = cell.attrs().clone();
+                    attrs.set_image(None);
+                    cell.set_attrs(attrs);
+
+                    let data = image.image_data();
+                    if data.data().len() <= MAX_IMAGE_DATA_SIZE {
+                        if sent.should_send(data.id()) {
+                            image_data.push(SerializedImageData {
+                                image_id: data.id(),
+                                data: data.data().to_vec(),
+                            });
+                        }
+                        images.push(LineImageCell {
+                            line_idx,
+                            col: x,
+                            image_id: data.id(),
+                            top_left: image.top_left(),
+                            bottom_right: image.bottom_right(),
+                        });
+                    }
+                }
             }
             if let Some(link) = current_link.take() {
                 // Wrap up final streak
@@ -795,37 +938,91 @@ impl From<Vec<(StableRowIndex, Line)>> for SerializedLines {
             }
         }
 
-        Self { lines, hyperlinks }
+        Self {
+            lines,
+            hyperlinks,
+            images,
+            image_data,
+        }
     }
-}
 
-/// Reconsitute hyperlinks or other attributes that were decomposed for
-/// serialization, and return the line data.
-impl Into<Vec<(StableRowIndex, Line)>> for SerializedLines {
-    fn into(self) -> Vec<(StableRowIndex, Line)> {
-        if self.hyperlinks.is_empty() {
-            self.lines
-        } else {
-            let mut lines = self.lines;
-
-            for link in self.hyperlinks {
-                let url = Arc::new(link.link);
-
-                for coord in link.coords {
-                    if let Some((_, line)) = lines.get_mut(coord.line_idx) {
-                        if let Some(cells) =
-                            line.cells_mut_for_attr_changes_only().get_mut(coord.cols)
-                        {
-                            for cell in cells {
-                                cell.attrs_mut().set_hyperlink(Some(Arc::clone(&url)));
-                            }
+    /// Reconstitute hyperlinks and images that were decomposed for
+    /// serialization, and return the line data.
+    /// Images are resolved against, and added to, `received`.
+    pub fn lines_with_images(self, received: &mut ReceivedImages) -> Vec<(StableRowIndex, Line)> {
+        let mut lines = self.lines;
+
+        for link in self.hyperlinks {
+            let url = Arc::new(link.link);
+
+            for coord in link.coords {
+                if let Some((_, line)) = lines.get_mut(coord.line_idx) {
+                    if let Some(cells) = line.cells_mut_for_attr_changes_only().get_mut(coord.cols)
+                    {
+                        for cell in cells {
+                            cell.attrs_mut().set_hyperlink(Some(Arc::clone(&url)));
                         }
                     }
                 }
             }
+        }
+
+        if self.images.is_empty() {
+            return lines;
+        }
 
-            lines
+        // Hold strong references to the new images until the cells
+        // that display them have been populated
+        let new_images: Vec<Arc<ImageData>> = self
+            .image_data
+            .into_iter()
+            .map(|image| {
+                let data = Arc::new(ImageData::with_raw_data(image.data));
+                received
+                    .images
+                    .insert(image.image_id, Arc::downgrade(&data));
+                data
+            })
+            .collect();
+
+        for image in self.images {
+            let data = match received.images.get(&image.image_id).and_then(Weak::upgrade) {
+                Some(data) => data,
+                None => {
+                    received.missing.insert(image.image_id);
+                    if let Some((stable_row, _)) = lines.get(image.line_idx) {
+                        received.incomplete_rows.push(*stable_row);
+                    }
+                    continue;
+                }
+            };
+            if let Some((_, line)) = lines.get_mut(image.line_idx) {
+                if let Some(cell) = line.cells_mut_for_attr_changes_only().get_mut(image.col) {
+                    cell.attrs_mut().set_image(Some(Box::new(ImageCell::new(
+                        image.top_left,
+                        image.bottom_right,
+                        data,
+                    ))));
+                }
+            }
         }
+
+        drop(new_images);
+        received.images.retain(|_, data| data.strong_count() > 0);
+
+        lines
+    }
+}
+
+impl From<Vec<(StableRowIndex, Line)>> for SerializedLines {
+    fn from(lines: Vec<(StableRowIndex, Line)>) -> Self {
+        Self::with_images(lines, &mut SentImages::default())
+    }
+}
+
+impl Into<Vec<(StableRowIndex, Line)>> for SerializedLines {
+    fn into(self) -> Vec<(StableRowIndex, Line)> {
+        self.lines_with_images(&mut ReceivedImages::default())
     }
 }
 
@@ -839,6 +1036,7 @@ pub struct GetLinesResponse {
 pub struct SearchScrollbackRequest {
     pub pane_id: PaneId,
     pub pattern: mux::pane::Pattern,
+    pub range: Range<StableRowIndex>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -846,6 +1044,175 @@ pub struct SearchScrollbackResponse {
     pub results: Vec<mux::pane::SearchResult>,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetClientList {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetClientListResponse {
+    pub clients: Vec<mux::sshforward::SshClientStatus>,
+}
+
+/// Queries a file on the remote host of a connected ssh domain
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpStat {
+    pub domain: String,
+    pub path: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpStatResponse {
+    pub exists: bool,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpRead {
+    pub domain: String,
+    pub path: String,
+    pub offset: u64,
+    pub len: usize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpReadResponse {
+    /// Empty at the end of the file
+    pub data: Vec<u8>,
+}
+
+/// Writes to a file on the remote host of a connected ssh domain;
+/// the file is truncated when `offset` is zero
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpWrite {
+    pub domain: String,
+    pub path: String,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// Replaces the log filter of the process that receives it.
+/// `filter` has the same syntax as the WEZTERM_LOG environment variable.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetLogFilter {
+    pub filter: String,
+}
+
+/// Requests the performance metrics recorded by the process that
+/// receives it
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetStats {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetStatsResponse {
+    pub stats: StatsSnapshot,
+}
+
+/// Asks the server to send a MuxEventPdu, with serial 0, for each
+/// of the events that happen in its mux from now on
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SubscribeToMuxEvents {}
+
+/// Something that happened in the mux of the server.
+/// PaneOutput is sent at most once per pane in each of the intervals
+/// in which the mux coalesces output notifications.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub enum MuxEvent {
+    PaneOutput {
+        pane_id: PaneId,
+    },
+    PaneAdded {
+        pane_id: PaneId,
+    },
+    PaneRemoved {
+        pane_id: PaneId,
+    },
+    TabAdded {
+        tab_id: TabId,
+        window_id: WindowId,
+    },
+    TabRemoved {
+        tab_id: TabId,
+    },
+    WindowCreated {
+        window_id: WindowId,
+    },
+    WindowRemoved {
+        window_id: WindowId,
+    },
+    TitleChanged {
+        pane_id: PaneId,
+        title: String,
+    },
+    Bell {
+        pane_id: PaneId,
+    },
+    UserVarChanged {
+        pane_id: PaneId,
+        name: String,
+        value: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MuxEventPdu {
+    pub event: MuxEvent,
+}
+
+/// Asks the server to render the viewport of a pane, or its whole
+/// scrollback, in the specified format
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct CapturePane {
+    pub pane_id: PaneId,
+    pub format: ScrollbackExportFormat,
+    pub scrollback: bool,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct CapturePaneResponse {
+    pub text: String,
+}
+
+/// Asks the server to respond once the process in a pane has exited
+/// and the pane has been closed
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitPane {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitPaneResponse {
+    /// None if the domain of the pane doesn't report exit statuses
+    pub exit_code: Option<u32>,
+}
+
+/// Asks the gui window that shows a pane to label the panes of its
+/// tab so that the user can pick one of them
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ChoosePane {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ChoosePaneResponse {
+    /// None if the user dismissed the labels without picking a pane
+    pub pane_id: Option<PaneId>,
+}
+
+/// Exchanges the positions of two panes, which may be in different tabs
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SwapPanes {
+    pub pane_id: PaneId,
+    pub other_pane_id: PaneId,
+}
+
+/// Moves a pane out of its tab and splits it in beside another pane
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MovePane {
+    pub pane_id: PaneId,
+    pub target_pane_id: PaneId,
+    pub side: PaneDirection,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -966,4 +1333,76 @@ mod test {
             Pdu::decode(encoded.as_slice()).unwrap()
         );
     }
+
+    #[test]
+    fn test_mux_event() {
+        let event = MuxEvent::UserVarChanged {
+            pane_id: 3,
+            name: "status".to_string(),
+            value: "building".to_string(),
+        };
+        let mut encoded = Vec::new();
+        Pdu::MuxEventPdu(MuxEventPdu {
+            event: event.clone(),
+        })
+        .encode(&mut encoded, 0)
+        .unwrap();
+        assert_eq!(
+            DecodedPdu {
+                serial: 0,
+                pdu: Pdu::MuxEventPdu(MuxEventPdu { event })
+            },
+            Pdu::decode(encoded.as_slice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_image_lines() {
+        use termwiz::cell::CellAttributes;
+
+        let image = Arc::new(ImageData::with_raw_data(b"image".to_vec()));
+        let mut attrs = CellAttributes::default();
+        attrs.set_image(Some(Box::new(ImageCell::new(
+            TextureCoordinate::new_f32(0., 0.),
+            TextureCoordinate::new_f32(1., 1.),
+            Arc::clone(&image),
+        ))));
+        let line = Line::from_text(" ", &attrs);
+
+        let mut sent = SentImages::default();
+        let mut received = ReceivedImages::default();
+
+        let first = SerializedLines::with_images(vec![(0, line.clone())], &mut sent);
+        assert_eq!(first.image_data.len(), 1);
+        let first = first.lines_with_images(&mut received);
+        let first_image = first[0].1.cells()[0].attrs().image().unwrap().clone();
+        assert_eq!(first_image.image_data().data(), image.data());
+
+        // The data is only sent the first time, but still resolves
+        let second = SerializedLines::with_images(vec![(1, line.clone())], &mut sent);
+        assert!(second.image_data.is_empty());
+        let second = second.lines_with_images(&mut received);
+        assert!(Arc::ptr_eq(
+            second[0].1.cells()[0].attrs().image().unwrap().image_data(),
+            first_image.image_data()
+        ));
+        assert!(received.take_missing().is_empty());
+
+        // Once the receiver no longer displays it, it needs to be resent
+        drop(first);
+        drop(first_image);
+        drop(second);
+        let third = SerializedLines::with_images(vec![(2, line.clone())], &mut sent);
+        let third = third.lines_with_images(&mut received);
+        assert!(third[0].1.cells()[0].attrs().image().is_none());
+        assert_eq!(received.take_incomplete_rows(), vec![2]);
+        let missing = received.take_missing();
+        assert_eq!(missing.len(), 1);
+
+        sent.forget(&missing);
+        let fourth = SerializedLines::with_images(vec![(2, line)], &mut sent);
+        assert_eq!(fourth.image_data.len(), 1);
+        let fourth = fourth.lines_with_images(&mut received);
+        assert!(fourth[0].1.cells()[0].attrs().image().is_some());
+    }
 }
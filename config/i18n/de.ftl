@@ -0,0 +1,40 @@
+## The launcher menu
+
+launcher-title = Starter
+launcher-help = Wähle einen Eintrag und drücke Enter, um ihn zu starten.  Escape bricht ab
+launcher-new-tab = Neuer Tab ({ $domain })
+launcher-attach = { $domain } verbinden
+launcher-attach-container = Mit { $runtime }-Container { $container } verbinden
+launcher-serial-port = Mit serieller Schnittstelle { $port } verbinden
+launcher-default-shell = (Standard-Shell)
+
+## Confirmation dialogs
+
+confirm-close-pane = 🛑 Diesen Bereich wirklich beenden?
+confirm-close-tab = 🛑 Diesen Tab und alle enthaltenen Bereiche wirklich beenden?
+confirm-close-window = 🛑 Dieses Fenster und alle enthaltenen Tabs und Bereiche wirklich beenden?
+confirm-quit = 🛑 WezTerm wirklich beenden?
+confirm-yes = [Y] Ja
+confirm-no = [N] Nein
+confirm-kill-selected = [K] Ausgewählte beenden, Fenster behalten
+confirm-processes = Diese Programme laufen noch; mit der Leertaste wird ausgewählt, ob sie beendet werden:
+confirm-unknown-process = (unbekanntes Programm)
+confirm-paste = ⚠️ Der einzufügende Text enthält Zeilenumbrüche oder Steuerzeichen, die Befehle ausführen könnten, ohne dass Enter gedrückt wird.  Wirklich einfügen?
+
+## Ssh authentication
+
+auth-form-help = Tab wechselt zwischen den Feldern, Enter bestätigt und Escape bricht ab
+
+## Error windows and notifications
+
+error-configuration-title = wezterm Konfigurationsfehler
+error-failed = Fehlgeschlagen: { $error }
+error-window-will-close = (dieses Fenster wird automatisch geschlossen)
+error-notification-title = Wezterm Fehler
+
+## File transfers over ssh
+
+sftp-confirm-upload = { $files } nach { $destination } hochladen?
+sftp-confirm-download = { $file } nach { $destination } herunterladen?
+sftp-progress = Übertrage { $file }: { $percent } %
+sftp-press-any-key = Zum Schließen eine beliebige Taste drücken
@@ -0,0 +1,47 @@
+# The strings shown by the wezterm gui, in Fluent syntax:
+# <https://projectfluent.org/fluent/guide/>
+# This is the source language; a message that is missing from
+# another translation is shown in English instead.
+
+## The launcher menu
+
+launcher-title = Launcher
+launcher-help = Select an item and press Enter to launch it.  Press Escape to cancel
+launcher-new-tab = New Tab ({ $domain })
+launcher-attach = Attach { $domain }
+launcher-attach-container = Attach to { $runtime } container { $container }
+launcher-serial-port = Connect to serial port { $port }
+launcher-default-shell = (default shell)
+
+## Confirmation dialogs
+## The Y, N and K keys answer the question, whatever the language,
+## so the labels of the buttons should mention them.
+
+confirm-close-pane = 🛑 Really kill this pane?
+confirm-close-tab = 🛑 Really kill this tab and all contained panes?
+confirm-close-window = 🛑 Really kill this window and all contained tabs and panes?
+confirm-quit = 🛑 Really Quit WezTerm?
+confirm-yes = [Y]es
+confirm-no = [N]o
+confirm-kill-selected = [K]ill selected, keep window
+confirm-processes = These programs are still running; Space toggles whether they are killed:
+confirm-unknown-process = (unknown program)
+confirm-paste = ⚠️ The text that you are pasting contains newlines or control characters, which could run commands without you pressing Enter.  Really paste it?
+
+## Ssh authentication
+
+auth-form-help = Tab moves between fields, Enter submits and Escape cancels
+
+## Error windows and notifications
+
+error-configuration-title = wezterm Configuration Error
+error-failed = Failed: { $error }
+error-window-will-close = (this window will close automatically)
+error-notification-title = Wezterm Error
+
+## File transfers over ssh
+
+sftp-confirm-upload = Upload { $files } to { $destination }?
+sftp-confirm-download = Download { $file } to { $destination }?
+sftp-progress = Transferring { $file }: { $percent }%
+sftp-press-any-key = Press any key to close
@@ -0,0 +1,40 @@
+## The launcher menu
+
+launcher-title = Lanceur
+launcher-help = Sélectionnez un élément et appuyez sur Entrée pour le lancer.  Échap pour annuler
+launcher-new-tab = Nouvel onglet ({ $domain })
+launcher-attach = Se connecter à { $domain }
+launcher-attach-container = Se connecter au conteneur { $runtime } { $container }
+launcher-serial-port = Se connecter au port série { $port }
+launcher-default-shell = (shell par défaut)
+
+## Confirmation dialogs
+
+confirm-close-pane = 🛑 Vraiment fermer ce panneau ?
+confirm-close-tab = 🛑 Vraiment fermer cet onglet et tous ses panneaux ?
+confirm-close-window = 🛑 Vraiment fermer cette fenêtre et tous ses onglets et panneaux ?
+confirm-quit = 🛑 Vraiment quitter WezTerm ?
+confirm-yes = [Y] Oui
+confirm-no = [N] Non
+confirm-kill-selected = [K] Tuer la sélection, garder la fenêtre
+confirm-processes = Ces programmes sont encore en cours ; Espace choisit s'ils sont tués :
+confirm-unknown-process = (programme inconnu)
+confirm-paste = ⚠️ Le texte que vous collez contient des retours à la ligne ou des caractères de contrôle, qui pourraient exécuter des commandes sans que vous appuyiez sur Entrée.  Vraiment le coller ?
+
+## Ssh authentication
+
+auth-form-help = Tab passe d'un champ à l'autre, Entrée valide et Échap annule
+
+## Error windows and notifications
+
+error-configuration-title = Erreur de configuration de wezterm
+error-failed = Échec : { $error }
+error-window-will-close = (cette fenêtre se fermera automatiquement)
+error-notification-title = Erreur de Wezterm
+
+## File transfers over ssh
+
+sftp-confirm-upload = Envoyer { $files } vers { $destination } ?
+sftp-confirm-download = Télécharger { $file } vers { $destination } ?
+sftp-progress = Transfert de { $file } : { $percent } %
+sftp-press-any-key = Appuyez sur une touche pour fermer
@@ -0,0 +1,70 @@
+//! Caches the compiled form of the configuration file, keyed by its
+//! path, size and modification time, so that starting wezterm with an
+//! unchanged configuration can skip parsing and compiling it.
+use crate::{create_user_owned_dirs, wezterm_version, CACHE_DIR};
+use anyhow::{anyhow, Context};
+use mlua::Lua;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+const PREFIX: &str = "config-";
+const SUFFIX: &str = ".luac";
+
+fn cache_path(config_path: &Path) -> Option<PathBuf> {
+    let meta = std::fs::metadata(config_path).ok()?;
+    let modified = meta.modified().ok()?;
+    let mut hasher = DefaultHasher::new();
+    config_path.hash(&mut hasher);
+    meta.len().hash(&mut hasher);
+    modified.hash(&mut hasher);
+    wezterm_version().hash(&mut hasher);
+    Some(CACHE_DIR.join(format!("{}{:016x}{}", PREFIX, hasher.finish(), SUFFIX)))
+}
+
+/// Returns the cached bytecode for the configuration file, provided
+/// that it was compiled from the file as it is now
+pub fn load(config_path: &Path) -> Option<Vec<u8>> {
+    let path = cache_path(config_path)?;
+    let bytecode = std::fs::read(&path).ok()?;
+    log::trace!("using compiled configuration {}", path.display());
+    Some(bytecode)
+}
+
+/// Compiles `source`, the contents of the configuration file, and
+/// caches the bytecode in place of any that was previously cached
+pub fn store(lua: &Lua, config_path: &Path, source: &str) -> anyhow::Result<()> {
+    let path = cache_path(config_path)
+        .ok_or_else(|| anyhow!("unable to stat {}", config_path.display()))?;
+
+    let func = lua
+        .load(source)
+        .set_name(config_path.to_string_lossy().as_bytes())?
+        .into_function()?;
+    let string: mlua::Table = lua.globals().get("string")?;
+    let dump: mlua::Function = string.get("dump")?;
+    let bytecode: mlua::String = dump.call(func)?;
+
+    create_user_owned_dirs(&CACHE_DIR)?;
+    for entry in std::fs::read_dir(&*CACHE_DIR)?.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(PREFIX) && name.ends_with(SUFFIX) {
+            std::fs::remove_file(entry.path()).ok();
+        }
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    std::io::Write::write_all(&mut file, bytecode.as_bytes())
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Implements `wezterm.http`, a small HTTP(S) client for lua code such
+//! as status bar callbacks.  The requests themselves are blocking, so
+//! they run on the blocking thread pool while the calling lua coroutine
+//! waits for them, which keeps the gui responsive.
+use anyhow::{anyhow, bail};
+use http_req::request::{HttpVersion, Method, Request};
+use http_req::uri::Uri;
+use luahelper::{from_lua_value, to_lua_value};
+use mlua::{Lua, Table, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+
+struct HttpRequest {
+    method: Method,
+    url: String,
+    headers: HashMap<String, String>,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+}
+
+struct HttpResponse {
+    status: u16,
+    reason: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+fn parse_method(method: &str) -> anyhow::Result<Method> {
+    Ok(match method.to_ascii_uppercase().as_str() {
+        "GET" => Method::GET,
+        "HEAD" => Method::HEAD,
+        "POST" => Method::POST,
+        "PUT" => Method::PUT,
+        "DELETE" => Method::DELETE,
+        "OPTIONS" => Method::OPTIONS,
+        "PATCH" => Method::PATCH,
+        _ => bail!("unsupported http method {}", method),
+    })
+}
+
+impl HttpRequest {
+    /// Builds a request from the optional table of options that
+    /// follows the url in the lua functions
+    fn new(method: Method, url: String, options: Option<Table>) -> mlua::Result<Self> {
+        let mut request = Self {
+            method,
+            url,
+            headers: HashMap::new(),
+            body: None,
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let options = match options {
+            Some(options) => options,
+            None => return Ok(request),
+        };
+
+        if let Some(headers) = options.get::<_, Option<HashMap<String, String>>>("headers")? {
+            request.headers = headers;
+        }
+        if let Some(timeout) = options.get::<_, Option<f64>>("timeout")? {
+            if !timeout.is_finite() || timeout <= 0. {
+                return Err(mlua::Error::external(anyhow!(
+                    "{} is not a valid timeout",
+                    timeout
+                )));
+            }
+            request.timeout = Duration::from_secs_f64(timeout);
+        }
+        if let Some(body) = options.get::<_, Option<mlua::String>>("body")? {
+            request.body.replace(body.as_bytes().to_vec());
+        }
+        match options.get::<_, Value>("json")? {
+            Value::Nil => {}
+            json => {
+                if request.body.is_some() {
+                    return Err(mlua::Error::external(anyhow!(
+                        "only one of body or json may be given"
+                    )));
+                }
+                let json: serde_json::Value =
+                    from_lua_value(json).map_err(|e| mlua::Error::external(e))?;
+                request
+                    .body
+                    .replace(serde_json::to_vec(&json).map_err(|e| mlua::Error::external(e))?);
+                request
+                    .headers
+                    .entry("Content-Type".to_string())
+                    .or_insert_with(|| "application/json".to_string());
+            }
+        }
+        Ok(request)
+    }
+
+    fn send(self) -> anyhow::Result<HttpResponse> {
+        let mut url = self.url.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let parsed = url
+                .parse::<Uri>()
+                .map_err(|e| anyhow!("invalid url {}: {}", url, e))?;
+            let mut body = Vec::new();
+            let mut request = Request::new(&parsed);
+            // http_req doesn't decode chunked responses, which
+            // servers only send for HTTP/1.1 requests
+            request
+                .version(HttpVersion::Http10)
+                .method(self.method.clone())
+                .connect_timeout(Some(self.timeout))
+                .read_timeout(Some(self.timeout))
+                .write_timeout(Some(self.timeout))
+                .header(
+                    "User-Agent",
+                    &format!("wezterm/{}", crate::wezterm_version()),
+                );
+            for (name, value) in &self.headers {
+                request.header(name, value);
+            }
+            if let Some(data) = &self.body {
+                request.header("Content-Length", &data.len());
+                request.body(data);
+            }
+            let res = request
+                .send(&mut body)
+                .map_err(|e| anyhow!("failed to fetch {}: {}", url, e))?;
+
+            // Only requests without a body are repeated for a redirect
+            let follow = matches!(self.method, Method::GET | Method::HEAD);
+            if follow && res.status_code().is_redirect() {
+                if let Some(location) = res.headers().get("Location") {
+                    url = location.to_string();
+                    continue;
+                }
+            }
+
+            return Ok(HttpResponse {
+                status: res.status_code().into(),
+                reason: res.reason().to_string(),
+                headers: res
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body,
+            });
+        }
+        bail!("too many redirects while fetching {}", self.url);
+    }
+}
+
+impl HttpResponse {
+    fn into_lua<'lua>(self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        table.set("status", self.status)?;
+        table.set("ok", (200..300).contains(&self.status))?;
+        table.set("reason", self.reason)?;
+        table.set("headers", self.headers)?;
+        table.set("body", lua.create_string(&self.body)?)?;
+        Ok(table)
+    }
+}
+
+async fn perform<'lua>(lua: &'lua Lua, request: HttpRequest) -> mlua::Result<Table<'lua>> {
+    let response = smol::unblock(move || request.send())
+        .await
+        .map_err(|e| mlua::Error::external(e))?;
+    response.into_lua(lua)
+}
+
+/// `wezterm.http.get(url [, options])`
+pub async fn get<'lua>(
+    lua: &'lua Lua,
+    (url, options): (String, Option<Table<'lua>>),
+) -> mlua::Result<Table<'lua>> {
+    perform(lua, HttpRequest::new(Method::GET, url, options)?).await
+}
+
+/// `wezterm.http.post(url [, options])`
+pub async fn post<'lua>(
+    lua: &'lua Lua,
+    (url, options): (String, Option<Table<'lua>>),
+) -> mlua::Result<Table<'lua>> {
+    perform(lua, HttpRequest::new(Method::POST, url, options)?).await
+}
+
+/// `wezterm.http.request(method, url [, options])`
+pub async fn request<'lua>(
+    lua: &'lua Lua,
+    (method, url, options): (String, String, Option<Table<'lua>>),
+) -> mlua::Result<Table<'lua>> {
+    let method = parse_method(&method).map_err(|e| mlua::Error::external(e))?;
+    perform(lua, HttpRequest::new(method, url, options)?).await
+}
+
+/// `wezterm.http.json_decode(text)` turns a JSON document into lua values
+pub fn json_decode<'lua>(lua: &'lua Lua, text: mlua::String) -> mlua::Result<Value<'lua>> {
+    let json: serde_json::Value =
+        serde_json::from_slice(text.as_bytes()).map_err(|e| mlua::Error::external(e))?;
+    to_lua_value(lua, json)
+}
+
+/// `wezterm.http.json_encode(value)` turns lua values into a JSON document
+pub fn json_encode<'lua>(_: &'lua Lua, value: Value<'lua>) -> mlua::Result<String> {
+    let json: serde_json::Value = from_lua_value(value).map_err(|e| mlua::Error::external(e))?;
+    serde_json::to_string(&json).map_err(|e| mlua::Error::external(e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn methods() {
+        assert!(matches!(parse_method("get").unwrap(), Method::GET));
+        assert!(matches!(parse_method("PATCH").unwrap(), Method::PATCH));
+        assert!(parse_method("BREW").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Translates the strings shown by the gui, such as the launcher menu,
+//! confirmation dialogs and error windows, using Fluent:
+//! <https://projectfluent.org/>
+//!
+//! The locale is taken from the `ui_locale` configuration, falling
+//! back to the `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables.
+//! Translations are built in for a handful of languages, and more can
+//! be supplied from lua via `wezterm.add_translations`.
+use crate::configuration;
+use anyhow::anyhow;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::sync::Mutex;
+use unic_langid::LanguageIdentifier;
+
+/// The language that the messages are written in; it is used for
+/// any message that is missing from the selected translation
+const SOURCE_LOCALE: &str = "en-US";
+
+const BUILTIN_TRANSLATIONS: &[(&str, &str)] = &[
+    ("en-US", include_str!("../i18n/en-US.ftl")),
+    ("de", include_str!("../i18n/de.ftl")),
+    ("fr", include_str!("../i18n/fr.ftl")),
+];
+
+struct Translations {
+    /// Incremented when `extra` changes, so that the
+    /// cached bundles can be rebuilt
+    generation: usize,
+    /// (locale, fluent source) supplied via `add_translations`
+    extra: Vec<(String, String)>,
+}
+
+struct Localizer {
+    locale: String,
+    generation: usize,
+    /// Ordered from the most to the least specific locale
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+lazy_static::lazy_static! {
+    static ref TRANSLATIONS: Mutex<Translations> = Mutex::new(Translations {
+        generation: 0,
+        extra: vec![],
+    });
+    static ref LOCALIZER: Mutex<Option<Localizer>> = Mutex::new(None);
+}
+
+/// Converts a POSIX locale such as `de_DE.UTF-8@euro` into a
+/// language tag such as `de-DE`.  Returns None for the C locale.
+fn normalize_locale(locale: &str) -> Option<String> {
+    let locale = locale
+        .split(|c| c == '.' || c == '@')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .replace('_', "-");
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        None
+    } else {
+        Some(locale)
+    }
+}
+
+/// Returns the locale that the gui should use
+fn current_locale() -> String {
+    if let Some(locale) = configuration()
+        .ui_locale
+        .as_deref()
+        .and_then(normalize_locale)
+    {
+        return locale;
+    }
+    for var in &["LC_ALL", "LC_MESSAGES", "LANG"] {
+        if let Some(locale) = std::env::var(var)
+            .ok()
+            .as_deref()
+            .and_then(normalize_locale)
+        {
+            return locale;
+        }
+    }
+    SOURCE_LOCALE.to_string()
+}
+
+/// Returns the locales to look up messages in, in order of preference;
+/// eg: `de-DE` yields `de-DE`, `de`, `en-US`
+fn locale_chain(locale: &str) -> Vec<String> {
+    let mut chain = vec![locale.to_string()];
+    if let Some(language) = locale.split('-').next() {
+        if language != locale {
+            chain.push(language.to_string());
+        }
+    }
+    if !chain.iter().any(|l| l == SOURCE_LOCALE) {
+        chain.push(SOURCE_LOCALE.to_string());
+    }
+    chain
+}
+
+fn parse_resource(locale: &str, source: &str) -> FluentResource {
+    match FluentResource::try_new(source.to_string()) {
+        Ok(resource) => resource,
+        Err((resource, errors)) => {
+            log::error!("errors in the {} translations: {:?}", locale, errors);
+            resource
+        }
+    }
+}
+
+impl Localizer {
+    fn new(locale: &str, translations: &Translations) -> Self {
+        let mut bundles = vec![];
+        for candidate in locale_chain(locale) {
+            let sources: Vec<&str> = BUILTIN_TRANSLATIONS
+                .iter()
+                .map(|(l, source)| (*l, *source))
+                .chain(
+                    translations
+                        .extra
+                        .iter()
+                        .map(|(l, source)| (l.as_str(), source.as_str())),
+                )
+                .filter(|(l, _)| l.eq_ignore_ascii_case(&candidate))
+                .map(|(_, source)| source)
+                .collect();
+            if sources.is_empty() {
+                continue;
+            }
+
+            let langid: LanguageIdentifier = candidate
+                .parse()
+                .unwrap_or_else(|_| SOURCE_LOCALE.parse().unwrap());
+            let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+            // The unicode isolation marks that fluent places around
+            // arguments are visible in the terminal
+            bundle.set_use_isolating(false);
+            for source in sources {
+                // Later sources, which come from lua, take precedence
+                bundle.add_resource_overriding(parse_resource(&candidate, source));
+            }
+            bundles.push(bundle);
+        }
+
+        Self {
+            locale: locale.to_string(),
+            generation: translations.generation,
+            bundles,
+        }
+    }
+
+    fn translate(&self, id: &str, args: &[(&str, &str)]) -> Option<String> {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, *value);
+        }
+
+        for bundle in &self.bundles {
+            let pattern = match bundle.get_message(id).and_then(|msg| msg.value()) {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let mut errors = vec![];
+            let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+            if !errors.is_empty() {
+                log::debug!("while translating {}: {:?}", id, errors);
+            }
+            return Some(text.into_owned());
+        }
+        None
+    }
+}
+
+/// Adds translations, in Fluent syntax, for `locale`.
+/// They take precedence over the built in translations.
+pub fn add_translations(locale: &str, source: &str) -> anyhow::Result<()> {
+    let locale = normalize_locale(locale).ok_or_else(|| anyhow!("invalid locale {}", locale))?;
+    if let Err((_, errors)) = FluentResource::try_new(source.to_string()) {
+        anyhow::bail!("invalid translations for {}: {:?}", locale, errors);
+    }
+
+    let mut translations = TRANSLATIONS.lock().unwrap();
+    // The configuration is evaluated again when it is reloaded,
+    // so don't accumulate copies of the same translations
+    if !translations
+        .extra
+        .iter()
+        .any(|(l, s)| *l == locale && s == source)
+    {
+        translations.extra.push((locale, source.to_string()));
+        translations.generation += 1;
+    }
+    Ok(())
+}
+
+/// Returns the translation of the message `id`, substituting the
+/// named `args` into it.  If there is no translation, `id` is returned.
+pub fn tr_args(id: &str, args: &[(&str, &str)]) -> String {
+    let locale = current_locale();
+    let translations = TRANSLATIONS.lock().unwrap();
+    let mut localizer = LOCALIZER.lock().unwrap();
+
+    let stale = match localizer.as_ref() {
+        Some(l) => l.locale != locale || l.generation != translations.generation,
+        None => true,
+    };
+    if stale {
+        localizer.replace(Localizer::new(&locale, &translations));
+    }
+
+    localizer
+        .as_ref()
+        .and_then(|l| l.translate(id, args))
+        .unwrap_or_else(|| {
+            log::debug!("no translation for {}", id);
+            id.to_string()
+        })
+}
+
+/// Returns the translation of the message `id`
+pub fn tr(id: &str) -> String {
+    tr_args(id, &[])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locales() {
+        assert_eq!(normalize_locale("de_DE.UTF-8"), Some("de-DE".to_string()));
+        assert_eq!(normalize_locale("fr_FR@euro"), Some("fr-FR".to_string()));
+        assert_eq!(normalize_locale("C.UTF-8"), None);
+        assert_eq!(normalize_locale(""), None);
+
+        assert_eq!(locale_chain("de-DE"), vec!["de-DE", "de", "en-US"]);
+        assert_eq!(locale_chain("en-GB"), vec!["en-GB", "en", "en-US"]);
+    }
+
+    #[test]
+    fn translate() {
+        let translations = Translations {
+            generation: 0,
+            extra: vec![(
+                "de".to_string(),
+                "launcher-title = Menü\nextra = Extra { $name }\n".to_string(),
+            )],
+        };
+        let localizer = Localizer::new("de-AT", &translations);
+
+        // Lua translations override the built in ones
+        assert_eq!(
+            localizer.translate("launcher-title", &[]),
+            Some("Menü".to_string())
+        );
+        assert_eq!(
+            localizer.translate("extra", &[("name", "wez")]),
+            Some("Extra wez".to_string())
+        );
+        assert_eq!(
+            localizer.translate("launcher-new-tab", &[("domain", "local")]),
+            Some("Neuer Tab (local)".to_string())
+        );
+        assert_eq!(localizer.translate("no-such-message", &[]), None);
+
+        // Messages missing from a translation fall back to English
+        let localizer = Localizer::new("xx", &translations);
+        assert_eq!(
+            localizer.translate("confirm-quit", &[]),
+            Some("🛑 Really Quit WezTerm?".to_string())
+        );
+    }
+}
@@ -1,5 +1,4 @@
-use crate::configuration;
-use crate::LeaderKey;
+use crate::{ConfigHandle, LeaderKey};
 use luahelper::impl_lua_conversion;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -148,11 +147,113 @@ impl Default for ClipboardPasteSource {
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ScrollbackExportFormat {
+    /// Plain text
+    Text,
+    /// Text with the escape sequences that reproduce its colors and styles
+    Escapes,
+    /// An HTML document
+    Html,
+    /// An asciicast v2 recording, for playback with asciinema
+    Asciicast,
+}
+
+impl Default for ScrollbackExportFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ExportScrollback {
+    #[serde(default)]
+    pub format: ScrollbackExportFormat,
+    /// The file to write.  The ExportScrollback key assignment
+    /// creates a file in the home directory when this is omitted.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// When true, each line is prefixed with the time at which
+    /// it was first written
+    #[serde(default)]
+    pub timestamps: bool,
+}
+impl_lua_conversion!(ExportScrollback);
+
+/// Which text the EditScrollbackInEditor key assignment opens
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum EditScrollbackContent {
+    /// The scrollback and viewport of the pane
+    Scrollback,
+    /// The output of the most recent command, as delimited using OSC 133
+    LastCommandOutput,
+}
+
+impl Default for EditScrollbackContent {
+    fn default() -> Self {
+        Self::Scrollback
+    }
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct EditScrollback {
+    #[serde(default)]
+    pub content: EditScrollbackContent,
+    /// Open the editor in a pane below the current pane, as
+    /// SplitVertical does, rather than to its right
+    #[serde(default)]
+    pub vertical: bool,
+}
+impl_lua_conversion!(EditScrollback);
+
+/// Identifies the capture group of a quick select pattern whose
+/// text is acted upon; either by number or by name
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CaptureGroup {
+    Index(usize),
+    Name(String),
+}
+
+/// What to do with the text chosen in the quick select overlay
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum QuickSelectAction {
+    /// Copy the text to the clipboard
+    CopyTo(ClipboardCopyDestination),
+    /// Open the text as a URL using the default handler for it
+    OpenUrl,
+    /// Paste the text into the pane
+    Paste,
+    /// Spawn a command in a new tab, with the text appended to its arguments
+    SpawnCommandInNewTab(SpawnCommand),
+}
+
+impl Default for QuickSelectAction {
+    fn default() -> Self {
+        Self::CopyTo(ClipboardCopyDestination::default())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct QuickSelectPattern {
+    /// The regex to match
+    pub regex: String,
+    /// The capture group whose text is acted upon.
+    /// If omitted, the whole match is used.
+    #[serde(default)]
+    pub group: Option<CaptureGroup>,
+    #[serde(default)]
+    pub action: QuickSelectAction,
+}
+impl_lua_conversion!(QuickSelectPattern);
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum KeyAssignment {
     SpawnTab(SpawnTabDomain),
     SpawnWindow,
     ToggleFullScreen,
+    ToggleDropDown,
+    ToggleKeyboardShortcutsInhibit,
     Copy,
     CopyTo(ClipboardCopyDestination),
     Paste,
@@ -174,6 +275,10 @@ pub enum KeyAssignment {
     MoveTab(usize),
     ScrollByPage(isize),
     ScrollToPrompt(isize),
+    ShowPromptMarks,
+    SetMark,
+    JumpToMark(isize),
+    ListMarks,
     ShowTabNavigator,
     HideApplication,
     QuitApplication,
@@ -183,14 +288,31 @@ pub enum KeyAssignment {
     SplitVertical(SpawnCommand),
     ShowLauncher,
     ClearScrollback(ScrollbackEraseMode),
+    ExportScrollback(ExportScrollback),
+    EditScrollbackInEditor(EditScrollback),
     Search(Pattern),
+    SearchAllPanes(Pattern),
+    SearchAndSend(Pattern),
     ActivateCopyMode,
+    QuickSelect,
+    ShowUrlList,
+    ClipboardHistory,
+    ShowDebugLog,
+    ShowUpdateInfo,
+    ToggleRecording,
+    ToggleRecordingPaused,
+    ToggleLineTimestamps,
+    StartKeyMacroRecording(String),
+    StopKeyMacroRecording,
+    ReplayKeyMacro(String),
 
     SelectTextAtMouseCursor(SelectionMode),
     ExtendSelectionToMouseCursor(Option<SelectionMode>),
+    ExpandSelectionToSemanticZone,
     OpenLinkAtMouseCursor,
     CompleteSelection(ClipboardCopyDestination),
     CompleteSelectionOrOpenLinkAtMouseCursor(ClipboardCopyDestination),
+    DragPane,
 
     AdjustPaneSize(PaneDirection, usize),
     ActivatePaneDirection(PaneDirection),
@@ -207,8 +329,7 @@ pub struct InputMap {
 }
 
 impl InputMap {
-    pub fn new() -> Self {
-        let config = configuration();
+    pub fn new(config: &ConfigHandle) -> Self {
         let mut mouse = config
             .mouse_bindings()
             .expect("mouse_bindings section of the config to be valid");
@@ -379,6 +500,7 @@ impl InputMap {
                 [Modifiers::SHIFT, KeyCode::PageDown, ScrollByPage(1)],
                 [Modifiers::ALT, KeyCode::Char('9'), ShowTabNavigator],
                 [Modifiers::CTRL, KeyCode::Char('X'), ActivateCopyMode],
+                [ctrl_shift, KeyCode::Char(' '), QuickSelect],
                 [
                     Modifiers::CTRL | Modifiers::ALT | Modifiers::SHIFT,
                     KeyCode::Char('"'),
@@ -443,6 +565,7 @@ impl InputMap {
         }
 
         if !config.disable_default_mouse_bindings {
+            let copy_on_select = config.copy_on_select_destination();
             m!(
                 [
                     Modifiers::NONE,
@@ -452,6 +575,14 @@ impl InputMap {
                     },
                     SelectTextAtMouseCursor(SelectionMode::Line)
                 ],
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Down {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    SelectTextAtMouseCursor(SelectionMode::SemanticZone)
+                ],
                 [
                     Modifiers::NONE,
                     MouseEventTrigger::Down {
@@ -482,9 +613,7 @@ impl InputMap {
                         streak: 1,
                         button: MouseButton::Left
                     },
-                    CompleteSelectionOrOpenLinkAtMouseCursor(
-                        ClipboardCopyDestination::PrimarySelection
-                    )
+                    CompleteSelectionOrOpenLinkAtMouseCursor(copy_on_select)
                 ],
                 [
                     Modifiers::NONE,
@@ -492,7 +621,7 @@ impl InputMap {
                         streak: 2,
                         button: MouseButton::Left
                     },
-                    CompleteSelection(ClipboardCopyDestination::PrimarySelection)
+                    CompleteSelection(copy_on_select)
                 ],
                 [
                     Modifiers::NONE,
@@ -500,7 +629,15 @@ impl InputMap {
                         streak: 3,
                         button: MouseButton::Left
                     },
-                    CompleteSelection(ClipboardCopyDestination::PrimarySelection)
+                    CompleteSelection(copy_on_select)
+                ],
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Up {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    CompleteSelection(copy_on_select)
                 ],
                 [
                     Modifiers::NONE,
@@ -526,6 +663,22 @@ impl InputMap {
                     },
                     ExtendSelectionToMouseCursor(Some(SelectionMode::Line))
                 ],
+                [
+                    Modifiers::NONE,
+                    MouseEventTrigger::Drag {
+                        streak: 4,
+                        button: MouseButton::Left
+                    },
+                    ExtendSelectionToMouseCursor(Some(SelectionMode::SemanticZone))
+                ],
+                [
+                    Modifiers::CTRL | Modifiers::SHIFT,
+                    MouseEventTrigger::Drag {
+                        streak: 1,
+                        button: MouseButton::Left
+                    },
+                    DragPane
+                ],
                 [
                     Modifiers::NONE,
                     MouseEventTrigger::Down {
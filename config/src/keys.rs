@@ -38,6 +38,15 @@ pub struct Mouse {
 }
 impl_lua_conversion!(Mouse);
 
+/// A button that is shown in the tab bar, after the new tab button,
+/// and that performs `action` when it is clicked
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TabBarButton {
+    pub text: String,
+    pub action: KeyAssignment,
+}
+impl_lua_conversion!(TabBarButton);
+
 fn make_map() -> HashMap<String, KeyCode> {
     let mut map = HashMap::new();
 
@@ -133,23 +142,16 @@ lazy_static::lazy_static! {
     static ref KEYCODE_MAP: HashMap<String, KeyCode> = make_map();
 }
 
-fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-
-    if let Some(c) = KEYCODE_MAP.get(&s) {
+/// Parses the name of a key, as used in key assignments
+pub fn parse_keycode(s: &str) -> Result<KeyCode, String> {
+    if let Some(c) = KEYCODE_MAP.get(s) {
         return Ok(c.clone());
     }
 
     if s.len() > 4 && s.starts_with("raw:") {
-        let num: u32 = s[4..].parse().map_err(|_| {
-            serde::de::Error::custom(format!(
-                "expected raw:<NUMBER> raw keycode string, got: {}",
-                s
-            ))
-        })?;
+        let num: u32 = s[4..]
+            .parse()
+            .map_err(|_| format!("expected raw:<NUMBER> raw keycode string, got: {}", s))?;
         return Ok(KeyCode::RawCode(num));
     }
 
@@ -157,18 +159,12 @@ where
     if chars.len() == 1 {
         Ok(KeyCode::Char(chars[0]))
     } else {
-        Err(serde::de::Error::custom(format!(
-            "invalid KeyCode string {}",
-            s
-        )))
+        Err(format!("invalid KeyCode string {}", s))
     }
 }
 
-fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
+/// Parses modifier names separated by `|`, as used in key assignments
+pub fn parse_modifiers(s: &str) -> Result<Modifiers, String> {
     let mut mods = Modifiers::NONE;
     for ele in s.split('|') {
         // Allow for whitespace; debug printing Modifiers includes spaces
@@ -188,11 +184,77 @@ where
         } else if ele == "NONE" || ele == "" {
             mods |= Modifiers::NONE;
         } else {
-            return Err(serde::de::Error::custom(format!(
-                "invalid modifier name {} in {}",
-                ele, s
-            )));
+            return Err(format!("invalid modifier name {} in {}", ele, s));
         }
     }
     Ok(mods)
 }
+
+/// Parses a key with optional modifiers, such as `CTRL+c`,
+/// `CTRL|SHIFT+F5` or `Enter`.  The modifiers may be separated
+/// by either `+` or `|`, and `+` may itself be the key, as in `CTRL++`.
+pub fn parse_key_combo(s: &str) -> Result<(KeyCode, Modifiers), String> {
+    // The final character is always part of the key
+    let last = s.char_indices().last().map(|(idx, _)| idx).unwrap_or(0);
+    match s[..last].rfind('+') {
+        Some(idx) => {
+            let mods = parse_modifiers(&s[..idx].replace('+', "|"))?;
+            let key = parse_keycode(&s[idx + 1..])?;
+            Ok((key, mods))
+        }
+        None => Ok((parse_keycode(s)?, Modifiers::NONE)),
+    }
+}
+
+fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_keycode(&s).map_err(serde::de::Error::custom)
+}
+
+fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_modifiers(&s).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_combos() {
+        assert_eq!(
+            parse_key_combo("CTRL++").unwrap(),
+            (KeyCode::Char('+'), Modifiers::CTRL)
+        );
+        assert_eq!(
+            parse_key_combo("CTRL|SHIFT+F5").unwrap(),
+            (KeyCode::Function(5), Modifiers::CTRL | Modifiers::SHIFT)
+        );
+        assert_eq!(
+            parse_key_combo("Enter").unwrap(),
+            (KeyCode::Char('\r'), Modifiers::NONE)
+        );
+        assert_eq!(
+            parse_key_combo("a").unwrap(),
+            (KeyCode::Char('a'), Modifiers::NONE)
+        );
+        assert!(parse_key_combo("hello").is_err());
+        assert!(parse_key_combo("HYPER+a").is_err());
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(
+            parse_modifiers("CTRL | SHIFT").unwrap(),
+            Modifiers::CTRL | Modifiers::SHIFT
+        );
+        assert_eq!(parse_modifiers("NONE").unwrap(), Modifiers::NONE);
+        assert!(parse_modifiers("CTRL|HYPER").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 //! Configuration for the gui portion of the terminal
 
-use crate::keyassignment::{KeyAssignment, MouseEventTrigger, SpawnCommand};
+use crate::keyassignment::{
+    ClipboardCopyDestination, KeyAssignment, MouseEventTrigger, QuickSelectPattern, SpawnCommand,
+};
 use anyhow::{anyhow, bail, Context, Error};
 use lazy_static::lazy_static;
 use luahelper::impl_lua_conversion;
@@ -20,6 +22,7 @@ use std::io::prelude::*;
 use std::os::unix::fs::DirBuilderExt;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use termwiz::hyperlink;
@@ -27,15 +30,21 @@ use termwiz::surface::CursorShape;
 use toml;
 use wezterm_input_types::{KeyCode, Modifiers};
 
+mod bytecode;
 mod color;
 mod daemon;
 mod font;
 mod frontend;
+mod http;
+pub mod i18n;
 pub mod keyassignment;
 mod keys;
 pub mod lua;
+mod overrides;
+pub mod plugin;
 mod ssh;
 mod terminal;
+mod timer;
 mod tls;
 mod unix;
 mod version;
@@ -45,6 +54,7 @@ pub use daemon::*;
 pub use font::*;
 pub use frontend::*;
 pub use keys::*;
+pub use overrides::*;
 pub use ssh::*;
 pub use terminal::*;
 pub use tls::*;
@@ -58,14 +68,19 @@ lazy_static! {
     pub static ref HOME_DIR: PathBuf = dirs_next::home_dir().expect("can't find HOME dir");
     pub static ref CONFIG_DIR: PathBuf = xdg_config_home();
     pub static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
+    pub static ref CACHE_DIR: PathBuf = compute_cache_dir();
     static ref CONFIG: Configuration = Configuration::new();
     static ref MAKE_LUA: Mutex<Option<LuaFactory>> = Mutex::new(Some(lua::make_lua_context));
     static ref SHOW_ERROR: Mutex<Option<ErrorCallback>> =
         Mutex::new(Some(|e| log::error!("{}", e)));
     static ref LUA_PIPE: LuaPipe = LuaPipe::new();
+    static ref PATH_WATCHER: Mutex<Option<notify::RecommendedWatcher>> = Mutex::new(None);
     static ref COLOR_SCHEMES: HashMap<String, Palette> = build_default_schemes();
 }
 
+/// A digest of the effective configuration; see `configuration_digest`
+static CONFIG_DIGEST: AtomicU64 = AtomicU64::new(0);
+
 thread_local! {
     static LUA_CONFIG: RefCell<Option<LuaConfigState>> = RefCell::new(None);
 }
@@ -169,6 +184,19 @@ where
     func(lua).await
 }
 
+/// Returns the lua state from the most recently loaded lua configuration,
+/// or an error if this isn't the main thread
+fn lua_config_on_main_thread() -> anyhow::Result<Option<Rc<mlua::Lua>>> {
+    LUA_CONFIG.with(|lc| {
+        let mut lc = lc.borrow_mut();
+        let lc = lc
+            .as_mut()
+            .ok_or_else(|| anyhow!("the lua configuration is only available on the main thread"))?;
+        lc.update_to_latest();
+        Ok(lc.get_lua())
+    })
+}
+
 fn schedule_with_lua<F, RETF, RET>(func: F) -> promise::spawn::Task<anyhow::Result<RET>>
 where
     F: 'static,
@@ -255,6 +283,22 @@ pub fn reload() {
     CONFIG.reload();
 }
 
+/// Returns a digest of the effective configuration.  Two processes
+/// that report the same digest are running with the same settings,
+/// which is useful context in crash reports without having to include
+/// the configuration itself.
+/// This is safe to call from a signal handler.
+pub fn configuration_digest() -> u64 {
+    CONFIG_DIGEST.load(Ordering::Relaxed)
+}
+
+fn update_configuration_digest(config: &Config) {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    format!("{:?}", config).hash(&mut hasher);
+    CONFIG_DIGEST.store(hasher.finish(), Ordering::Relaxed);
+}
+
 /// If there was an error loading the preferred configuration,
 /// return it, otherwise return the current configuration
 pub fn configuration_result() -> Result<ConfigHandle, Error> {
@@ -264,6 +308,83 @@ pub fn configuration_result() -> Result<ConfigHandle, Error> {
     Ok(CONFIG.get())
 }
 
+/// Makes a watcher that calls `on_change` from a thread of its own
+/// when any of the paths that it watches change.  Changes are debounced
+/// so that writing a file produces a single call.
+fn new_watcher<F: Fn(PathBuf) + Send + 'static>(on_change: F) -> notify::RecommendedWatcher {
+    let (tx, rx) = std::sync::mpsc::channel();
+    const DELAY: Duration = Duration::from_millis(200);
+    let watcher = notify::watcher(tx, DELAY).unwrap();
+    std::thread::spawn(move || {
+        // block until we get an event
+        use notify::DebouncedEvent;
+
+        fn extract_path(event: DebouncedEvent) -> Option<PathBuf> {
+            match event {
+                // Defer acting until `Write`, otherwise we'll
+                // reload twice in quick succession
+                DebouncedEvent::NoticeWrite(_) => None,
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Chmod(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(path, _) => Some(path),
+                DebouncedEvent::NoticeRemove(path) => {
+                    // In theory, `notify` should deliver DebouncedEvent::Remove
+                    // shortly after this, but it doesn't always do so.
+                    // Let's just wait a bit and report the path changed
+                    // for ourselves.
+                    std::thread::sleep(DELAY);
+                    Some(path)
+                }
+                DebouncedEvent::Error(_, path) => path,
+                DebouncedEvent::Rescan => None,
+            }
+        }
+
+        while let Ok(event) = rx.recv() {
+            log::trace!("event:{:?}", event);
+            if let Some(path) = extract_path(event) {
+                on_change(path);
+            }
+        }
+    });
+    watcher
+}
+
+/// Watches `path` on behalf of `wezterm.watch_path`, emitting the
+/// `path-changed` event each time that it changes.  Paths stay
+/// watched for the life of the process, across configuration reloads.
+pub(crate) fn watch_path_for_lua(path: PathBuf) -> anyhow::Result<()> {
+    use notify::Watcher;
+    let mut watcher = PATH_WATCHER.lock().unwrap();
+    let watcher = watcher.get_or_insert_with(|| new_watcher(emit_path_changed));
+    watcher
+        .watch(&path, notify::RecursiveMode::NonRecursive)
+        .with_context(|| format!("watching {}", path.display()))
+}
+
+fn emit_path_changed(path: PathBuf) {
+    log::debug!("path {} changed, emit path-changed", path.display());
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            let result = with_lua_config_on_main_thread(move |lua| async move {
+                if let Some(lua) = lua {
+                    let args = lua.pack_multi(path.to_string_lossy().to_string())?;
+                    lua::emit_event(&lua, ("path-changed".to_string(), args)).await?;
+                }
+                Ok(())
+            })
+            .await;
+            if let Err(err) = result {
+                log::error!("while processing path-changed event: {:#}", err);
+            }
+        })
+        .detach();
+    })
+    .detach();
+}
+
 struct ConfigInner {
     config: Arc<Config>,
     error: Option<String>,
@@ -283,45 +404,10 @@ impl ConfigInner {
 
     fn watch_path(&mut self, path: PathBuf) {
         if self.watcher.is_none() {
-            let (tx, rx) = std::sync::mpsc::channel();
-            const DELAY: Duration = Duration::from_millis(200);
-            let watcher = notify::watcher(tx, DELAY).unwrap();
-            std::thread::spawn(move || {
-                // block until we get an event
-                use notify::DebouncedEvent;
-
-                fn extract_path(event: DebouncedEvent) -> Option<PathBuf> {
-                    match event {
-                        // Defer acting until `Write`, otherwise we'll
-                        // reload twice in quick succession
-                        DebouncedEvent::NoticeWrite(_) => None,
-                        DebouncedEvent::Create(path)
-                        | DebouncedEvent::Write(path)
-                        | DebouncedEvent::Chmod(path)
-                        | DebouncedEvent::Remove(path)
-                        | DebouncedEvent::Rename(path, _) => Some(path),
-                        DebouncedEvent::NoticeRemove(path) => {
-                            // In theory, `notify` should deliver DebouncedEvent::Remove
-                            // shortly after this, but it doesn't always do so.
-                            // Let's just wait a bit and report the path changed
-                            // for ourselves.
-                            std::thread::sleep(DELAY);
-                            Some(path)
-                        }
-                        DebouncedEvent::Error(_, path) => path,
-                        DebouncedEvent::Rescan => None,
-                    }
-                }
-
-                while let Ok(event) = rx.recv() {
-                    log::trace!("event:{:?}", event);
-                    if let Some(path) = extract_path(event) {
-                        log::debug!("path {} changed, reload config", path.display());
-                        reload();
-                    }
-                }
-            });
-            self.watcher.replace(watcher);
+            self.watcher.replace(new_watcher(|path| {
+                log::debug!("path {} changed, reload config", path.display());
+                reload();
+            }));
         }
         if let Some(watcher) = self.watcher.as_mut() {
             use notify::Watcher;
@@ -346,6 +432,7 @@ impl ConfigInner {
                 self.config = Arc::new(config);
                 self.error.take();
                 self.generation += 1;
+                update_configuration_digest(&self.config);
 
                 // If we loaded a user config, publish this latest version of
                 // the lua state to the LUA_PIPE.  This allows a subsequent
@@ -360,6 +447,12 @@ impl ConfigInner {
                     if let Some(path) = file_name {
                         self.watch_path(path);
                     }
+                    // `wezterm plugin update` changes the lockfile, and
+                    // the plugins are loaded again by reloading
+                    let lockfile = plugin::lockfile_path();
+                    if lockfile.exists() {
+                        self.watch_path(lockfile);
+                    }
                 }
             }
             Err(err) => {
@@ -380,6 +473,7 @@ impl ConfigInner {
         self.config = Arc::new(Config::default_config());
         self.error.take();
         self.generation += 1;
+        update_configuration_digest(&self.config);
     }
 }
 
@@ -479,6 +573,12 @@ pub struct Config {
     /// The DPI to assume
     pub dpi: Option<f64>,
 
+    /// Overrides the DPI for particular screens, keyed by the name
+    /// of the screen as reported by `wezterm.gui.screens()`.
+    /// This is currently only respected on Wayland.
+    #[serde(default)]
+    pub dpi_by_screen: HashMap<String, f64>,
+
     /// The baseline font to use
     #[serde(default)]
     pub font: TextStyle,
@@ -512,6 +612,37 @@ pub struct Config {
     #[serde(default = "default_scrollback_lines")]
     pub scrollback_lines: usize,
 
+    /// An approximate limit on the memory used by the scrollback of
+    /// each pane, which may retain fewer than `scrollback_lines` when
+    /// the pane is wide.  0 means that there is no such limit.
+    #[serde(default)]
+    pub scrollback_bytes: usize,
+
+    /// When true, identical consecutive lines that scroll into the
+    /// scrollback are folded into a single line with a repeat count
+    #[serde(default)]
+    pub scrollback_dedup: bool,
+
+    /// Overrides the scrollback settings for panes in particular
+    /// domains, or that are running particular programs
+    #[serde(default)]
+    pub scrollback_overrides: Vec<ScrollbackOverride>,
+
+    /// How many additional lines of scrollback to retain in a
+    /// compressed file on disk once they have scrolled beyond
+    /// `scrollback_lines`.  0 disables this.
+    #[serde(default)]
+    pub disk_scrollback_lines: usize,
+
+    /// Where the recordings made by the ToggleRecording key assignment
+    /// are written.  Defaults to `wezterm-recordings` in the home directory.
+    pub recording_dir: Option<PathBuf>,
+
+    /// Panes spawned into any of these domains are recorded from the
+    /// moment that they are created
+    #[serde(default)]
+    pub auto_record_domains: Vec<String>,
+
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
     /// For example, to have `wezterm` always run `top` by default,
@@ -632,6 +763,40 @@ pub struct Config {
     #[serde(default = "default_ratelimit_line_prefetches_per_second")]
     pub ratelimit_mux_line_prefetches_per_second: u32,
 
+    /// Typed characters are locally echoed in panes whose output
+    /// comes from a remote host, such as those in multiplexer and
+    /// ssh domains, when the round trip time to the host is at
+    /// least this many milliseconds.
+    #[serde(default = "default_local_echo_threshold_ms")]
+    pub local_echo_threshold_ms: u64,
+
+    /// When a client attaches to the multiplexer server, send up to
+    /// this many lines of scrollback along with the initial contents
+    /// of each pane, so that the client can scroll back through it
+    /// without waiting to fetch it on demand.
+    /// This is taken from the configuration of the server.
+    #[serde(default)]
+    pub mux_scrollback_lines_on_attach: usize,
+
+    /// How often to save a snapshot of the windows, tabs and panes
+    /// so that they can be restored later.  0 disables snapshots.
+    #[serde(default)]
+    pub session_snapshot_interval_secs: u64,
+
+    /// How many lines of output to save with each pane in a snapshot
+    #[serde(default = "default_session_snapshot_scrollback_lines")]
+    pub session_snapshot_scrollback_lines: usize,
+
+    /// When restoring a pane whose foreground process was one of
+    /// these programs, that program is run in place of the shell
+    #[serde(default = "default_session_restore_programs")]
+    pub session_restore_programs: Vec<String>,
+
+    /// If true, restore the most recent session snapshot at startup
+    /// rather than spawning a single new tab
+    #[serde(default)]
+    pub restore_session_on_startup: bool,
+
     #[serde(default)]
     pub keys: Vec<Key>,
     #[serde(default)]
@@ -689,6 +854,11 @@ pub struct Config {
     #[serde(default = "default_tab_max_width")]
     pub tab_max_width: usize,
 
+    /// Buttons to show in the tab bar, each of which performs
+    /// a key assignment when it is clicked
+    #[serde(default)]
+    pub tab_bar_buttons: Vec<TabBarButton>,
+
     /// If true, hide the tab bar if the window only has a single tab.
     #[serde(default)]
     pub hide_tab_bar_if_only_one_tab: bool,
@@ -698,11 +868,13 @@ pub struct Config {
 
     /// If false, do not try to use a Wayland protocol connection
     /// when starting the gui frontend, and instead use X11.
+    /// If "auto", use Wayland, but fall back to X11 if either the
+    /// Wayland connection or EGL cannot be initialized.
     /// This option is only considered on X11/Wayland systems and
     /// has no effect on macOS or Windows.
-    /// The default is true.
+    /// The default is false.
     #[serde(default)]
-    pub enable_wayland: bool,
+    pub enable_wayland: EnableWayland,
 
     /// Whether to prefer EGL over other GL implementations.
     /// EGL on Windows has jankier resize behavior than WGL (which
@@ -711,10 +883,29 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub prefer_egl: bool,
 
+    /// On systems with more than one GPU, selects the one that is
+    /// used for EGL rendering.  This may be "integrated", "discrete",
+    /// the path of a DRM device such as "/dev/dri/renderD129", a PCI
+    /// address such as "0000:01:00.0", or a case insensitive
+    /// substring of the vendor or renderer name, eg: "nvidia".
+    /// If unset, the EGL implementation picks the device.
+    #[serde(default)]
+    pub prefer_egl_device: Option<String>,
+
     /// Controls the amount of padding to use around the terminal cell area
     #[serde(default)]
     pub window_padding: WindowPadding,
 
+    /// Whether windows have a title bar and borders
+    #[serde(default)]
+    pub window_decorations: WindowDecorations,
+
+    /// When set, the first window is shown as a drop-down that is
+    /// attached to the top of the screen.  This is only supported on
+    /// Wayland compositors that implement wlr-layer-shell.
+    #[serde(default)]
+    pub drop_down: Option<DropDown>,
+
     /// Specifies the path to a background image attachment file.
     /// The file can be any image format that the rust `image`
     /// crate is able to identify and load.
@@ -740,6 +931,20 @@ pub struct Config {
     #[serde(default = "default_one_point_oh")]
     pub window_background_opacity: f32,
 
+    /// Specifies the opacity of the whole window, including its text.
+    /// Unlike window_background_opacity, this is applied by the compositor,
+    /// which is currently only possible on Wayland compositors that support
+    /// the alpha-modifier protocol.  It is ignored elsewhere.
+    #[serde(default = "default_one_point_oh")]
+    pub window_opacity: f32,
+
+    /// If true, ask the compositor to blur whatever is behind a
+    /// translucent window.  This is currently only possible on
+    /// Wayland compositors that support either the KDE blur or
+    /// the ext-background-effect protocol.
+    #[serde(default)]
+    pub wayland_background_blur: bool,
+
     /// inactive_pane_hue, inactive_pane_saturation and
     /// inactive_pane_brightness allow for transforming the color
     /// of inactive panes.
@@ -827,14 +1032,61 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub add_wsl_distributions_to_launch_menu: bool,
 
+    /// If true, the launcher lists the running containers of each
+    /// of the `container_runtimes` so that a tab can be spawned in them
+    #[serde(default = "default_true")]
+    pub add_containers_to_launch_menu: bool,
+
+    #[serde(default = "default_container_runtimes")]
+    pub container_runtimes: Vec<String>,
+
+    /// If true, the launcher lists the serial ports that are present
+    /// so that a tab can be connected to one of them
+    #[serde(default = "default_true")]
+    pub add_serial_ports_to_launch_menu: bool,
+
+    /// The baud rate used by serial ports opened from the launcher
+    #[serde(default = "default_serial_port_baud_rate")]
+    pub serial_port_baud_rate: usize,
+
     #[serde(default = "default_true")]
     pub check_for_updates: bool,
     #[serde(default)]
     pub show_update_window: bool,
 
+    /// When true, offer to help report a crash from the previous run
+    /// the next time that a window is opened
+    #[serde(default = "default_true")]
+    pub offer_crash_reports: bool,
+
+    /// Rules that decide whether a desktop notification requested by
+    /// an application (using OSC 9, OSC 777 or OSC 99) is shown.
+    /// The first matching rule wins; if none match, it is shown.
+    #[serde(default)]
+    pub notification_rules: Vec<NotificationRule>,
+
+    /// While the local time is within this schedule, notifications
+    /// requested by applications are not shown
+    #[serde(default)]
+    pub notification_do_not_disturb: Option<DoNotDisturbSchedule>,
+
+    /// The locale used for the strings shown by the gui, such as
+    /// "de-DE".  If unset, it is taken from the environment.
+    #[serde(default)]
+    pub ui_locale: Option<String>,
+
     #[serde(default = "default_update_interval")]
     pub check_for_updates_interval_seconds: u64,
 
+    /// Which release channel the update checker follows
+    #[serde(default)]
+    pub update_channel: UpdateChannel,
+
+    /// Settings that apply to the update checker when it is
+    /// following a particular release channel
+    #[serde(default)]
+    pub update_channel_settings: HashMap<UpdateChannel, UpdateChannelSettings>,
+
     /// When set to true, use the CSI-U encoding scheme as described
     /// in http://www.leonerd.org.uk/hacks/fixterms/
     /// This is off by default because @wez and @jsgf find the shift-space
@@ -845,23 +1097,113 @@ pub struct Config {
     #[serde(default)]
     pub window_close_confirmation: WindowCloseConfirmation,
 
+    /// Panes whose foreground process has one of these names, such
+    /// as an idle shell, can be closed without asking for confirmation
+    #[serde(default = "default_skip_close_confirmation_for_processes_named")]
+    pub skip_close_confirmation_for_processes_named: Vec<String>,
+
+    /// Decide whether closing a pane needs confirmation based on its
+    /// foreground process.  The first matching rule takes precedence
+    /// over skip_close_confirmation_for_processes_named.
+    #[serde(default)]
+    pub close_confirmation_rules: Vec<CloseConfirmationRule>,
+
+    /// While any pane in a window has a foreground process with one
+    /// of these names, such as a video player or a long build, the
+    /// system is prevented from going idle
+    #[serde(default)]
+    pub idle_inhibit_processes: Vec<String>,
+
     #[serde(default)]
     pub native_macos_fullscreen_mode: bool,
 
     #[serde(default = "default_word_boundary")]
     pub selection_word_boundary: String,
 
+    /// Additional patterns for the quick select overlay; these take
+    /// precedence over the built-in patterns
+    #[serde(default)]
+    pub quick_select_patterns: Vec<QuickSelectPattern>,
+
+    /// When true, only quick_select_patterns are used by the quick
+    /// select overlay
+    #[serde(default)]
+    pub disable_default_quick_select_patterns: bool,
+
+    /// The characters used to compose the labels in the quick
+    /// select overlay
+    #[serde(default = "default_quick_select_alphabet")]
+    pub quick_select_alphabet: String,
+
     #[serde(default = "default_true")]
     pub adjust_window_size_when_changing_font_size: bool,
 
+    /// If true, interactively resizing the window changes its size
+    /// by whole cells, rather than by pixels
+    #[serde(default)]
+    pub use_resize_increments: bool,
+
     #[serde(default = "default_alternate_buffer_wheel_scroll_speed")]
     pub alternate_buffer_wheel_scroll_speed: u8,
+
+    /// When true, the prompt line of commands that exited with a
+    /// non-zero status, as reported by OSC 133, is highlighted
+    #[serde(default)]
+    pub mark_failed_commands: bool,
+
+    /// The command used by EditScrollbackInEditor to edit the
+    /// scrollback; the path of the file is appended to it.
+    /// When not set, $VISUAL or $EDITOR are used.
+    #[serde(default)]
+    pub scrollback_editor: Option<Vec<String>>,
+
+    /// Where the text of a mouse selection is copied when the
+    /// selection is completed by the default mouse bindings.
+    /// See copy_on_select_destination() for the default.
+    #[serde(default)]
+    pub copy_on_select: Option<ClipboardCopyDestination>,
+
+    /// How the times shown by ToggleLineTimestamps are formatted
+    #[serde(default)]
+    pub line_timestamp_format: LineTimestampFormat,
+
+    /// Whether to ask before pasting text that contains newlines or
+    /// control characters into a pane whose program hasn't enabled
+    /// bracketed paste mode
+    #[serde(default = "default_true")]
+    pub confirm_unsafe_paste: bool,
+
+    /// How many of the most recent copies are kept for the
+    /// ClipboardHistory overlay; 0 disables the history
+    #[serde(default = "default_clipboard_history_size")]
+    pub clipboard_history_size: usize,
 }
 
 fn default_alternate_buffer_wheel_scroll_speed() -> u8 {
     3
 }
 
+fn default_quick_select_alphabet() -> String {
+    "asdfqwerzxcvjklmiuopghtybn".to_string()
+}
+
+fn default_skip_close_confirmation_for_processes_named() -> Vec<String> {
+    [
+        "bash",
+        "sh",
+        "zsh",
+        "fish",
+        "tmux",
+        "nu",
+        "cmd.exe",
+        "pwsh.exe",
+        "powershell.exe",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
 fn default_word_boundary() -> String {
     " \t\n{[}]()\"'`".to_string()
 }
@@ -936,6 +1278,75 @@ pub struct WindowPadding {
 }
 impl_lua_conversion!(WindowPadding);
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowDecorations {
+    /// A title bar with buttons to minimize, maximize and close the
+    /// window, and borders that can be dragged to resize it.  These are
+    /// drawn by the window manager, or by wezterm itself on Wayland
+    /// compositors that expect clients to decorate their windows.
+    Full,
+    /// No title bar or borders
+    None,
+}
+impl_lua_conversion!(WindowDecorations);
+
+impl Default for WindowDecorations {
+    fn default() -> Self {
+        WindowDecorations::Full
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropDownKeyboardInteractivity {
+    /// The drop-down takes the keyboard focus whenever it is shown,
+    /// and keeps it until it is hidden
+    Exclusive,
+    /// The drop-down is focused and unfocused like any other window.
+    /// Compositors that don't support this use Exclusive instead.
+    OnDemand,
+}
+impl_lua_conversion!(DropDownKeyboardInteractivity);
+
+impl Default for DropDownKeyboardInteractivity {
+    fn default() -> Self {
+        DropDownKeyboardInteractivity::OnDemand
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct DropDown {
+    /// The height of the window, as a percentage of the height
+    /// of the screen
+    #[serde(default = "default_drop_down_height")]
+    pub height: u8,
+    /// The distance between the edges of the screen and the window
+    #[serde(default)]
+    pub margin_top: u16,
+    #[serde(default)]
+    pub margin_left: u16,
+    #[serde(default)]
+    pub margin_right: u16,
+    #[serde(default)]
+    pub keyboard_interactivity: DropDownKeyboardInteractivity,
+}
+impl_lua_conversion!(DropDown);
+
+impl Default for DropDown {
+    fn default() -> Self {
+        Self {
+            height: default_drop_down_height(),
+            margin_top: 0,
+            margin_left: 0,
+            margin_right: 0,
+            keyboard_interactivity: DropDownKeyboardInteractivity::default(),
+        }
+    }
+}
+
+fn default_drop_down_height() -> u8 {
+    50
+}
+
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 pub enum WindowCloseConfirmation {
     AlwaysPrompt,
@@ -951,6 +1362,268 @@ impl Default for WindowCloseConfirmation {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseConfirmationAction {
+    Prompt,
+    Skip,
+}
+impl_lua_conversion!(CloseConfirmationAction);
+
+impl Default for CloseConfirmationAction {
+    fn default() -> Self {
+        CloseConfirmationAction::Skip
+    }
+}
+
+/// Decides whether closing the panes that match all of the specified
+/// criteria needs to be confirmed
+#[derive(Default, Deserialize, Serialize, Clone, Debug)]
+pub struct CloseConfirmationRule {
+    /// The name of the domain in which the pane was spawned
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// The name of the foreground process, such as `"vim"`, which
+    /// may use `*` to match any sequence of characters: `"python*"`
+    #[serde(default)]
+    pub process_name: Option<String>,
+    #[serde(default)]
+    pub action: CloseConfirmationAction,
+}
+impl_lua_conversion!(CloseConfirmationRule);
+
+/// Matches `text` against `pattern`, in which `*` matches any
+/// sequence of characters, including an empty one
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut remain = match text.strip_prefix(first) {
+        Some(remain) => remain,
+        None => return false,
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // There was no `*`
+        None => return remain.is_empty(),
+    };
+    for part in parts {
+        match remain.find(part) {
+            Some(idx) => remain = &remain[idx + part.len()..],
+            None => return false,
+        }
+    }
+    remain.ends_with(last)
+}
+
+impl CloseConfirmationRule {
+    pub fn matches(&self, domain: &str, process_name: Option<&str>) -> bool {
+        let domain_matches = self.domain.as_ref().map(|d| d == domain).unwrap_or(true);
+        let process_matches = match (&self.process_name, process_name) {
+            (Some(pattern), Some(name)) => wildcard_match(pattern, name),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        domain_matches && process_matches
+    }
+}
+
+/// Scrollback settings that apply to the panes that match all of the
+/// specified `domain` and `program` criteria.  Settings that are not
+/// specified fall through to the next matching override, and then to
+/// the main configuration.
+#[derive(Default, Deserialize, Serialize, Clone, Debug)]
+pub struct ScrollbackOverride {
+    /// The name of the domain in which the pane was spawned
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// The file name of the program that the pane was spawned to run,
+    /// without any directory or extension, such as `"ssh"`
+    #[serde(default)]
+    pub program: Option<String>,
+    #[serde(default)]
+    pub scrollback_lines: Option<usize>,
+    #[serde(default)]
+    pub scrollback_bytes: Option<usize>,
+    #[serde(default)]
+    pub disk_scrollback_lines: Option<usize>,
+    #[serde(default)]
+    pub scrollback_dedup: Option<bool>,
+}
+impl_lua_conversion!(ScrollbackOverride);
+
+impl ScrollbackOverride {
+    pub fn matches(&self, domain: Option<&str>, program: Option<&str>) -> bool {
+        fn matches(want: &Option<String>, have: Option<&str>) -> bool {
+            match want {
+                Some(want) => have == Some(want.as_str()),
+                None => true,
+            }
+        }
+        matches(&self.domain, domain) && matches(&self.program, program)
+    }
+}
+
+/// The value of `enable_wayland`, which may be `true`, `false` or `"auto"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnableWayland {
+    Enabled,
+    Disabled,
+    /// Use Wayland, but fall back to X11 if it cannot be initialized
+    Auto,
+}
+impl_lua_conversion!(EnableWayland);
+
+impl Default for EnableWayland {
+    fn default() -> Self {
+        EnableWayland::Disabled
+    }
+}
+
+impl<'de> Deserialize<'de> for EnableWayland {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Bool(bool),
+            String(String),
+        }
+
+        match Value::deserialize(deserializer)? {
+            Value::Bool(true) => Ok(EnableWayland::Enabled),
+            Value::Bool(false) => Ok(EnableWayland::Disabled),
+            Value::String(s) if s == "auto" => Ok(EnableWayland::Auto),
+            Value::String(s) => Err(serde::de::Error::custom(format!(
+                "invalid enable_wayland value {:?}; expected true, false or \"auto\"",
+                s
+            ))),
+        }
+    }
+}
+
+impl Serialize for EnableWayland {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            EnableWayland::Enabled => serializer.serialize_bool(true),
+            EnableWayland::Disabled => serializer.serialize_bool(false),
+            EnableWayland::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UpdateChannel {
+    Stable,
+    Nightly,
+}
+impl_lua_conversion!(UpdateChannel);
+
+impl Default for UpdateChannel {
+    fn default() -> Self {
+        UpdateChannel::Stable
+    }
+}
+
+#[derive(Default, Deserialize, Serialize, Clone, Debug)]
+pub struct UpdateChannelSettings {
+    /// Overrides check_for_updates_interval_seconds
+    #[serde(default)]
+    pub check_interval_seconds: Option<u64>,
+    /// When an update is found, download the package for this system.
+    /// It is not installed.
+    #[serde(default)]
+    pub download: bool,
+    /// Where packages are downloaded to.  Defaults to the `Downloads`
+    /// directory in the home directory.
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+    /// A PEM encoded public key.  When set, a downloaded package is only
+    /// kept if the `.sig` file published alongside it is a valid signature
+    /// made with the corresponding private key.
+    #[serde(default)]
+    pub signing_public_key: Option<PathBuf>,
+}
+impl_lua_conversion!(UpdateChannelSettings);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationAction {
+    Show,
+    Suppress,
+}
+impl_lua_conversion!(NotificationAction);
+
+impl Default for NotificationAction {
+    fn default() -> Self {
+        NotificationAction::Suppress
+    }
+}
+
+/// Decides what happens to the notifications that match all of the
+/// specified criteria
+#[derive(Default, Deserialize, Serialize, Clone, Debug)]
+pub struct NotificationRule {
+    /// The name of the domain in which the pane was spawned
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Matches panes whose title contains this text
+    #[serde(default)]
+    pub pane_title: Option<String>,
+    /// Matches notifications whose title contains this text
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Matches notifications whose body contains this text
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub action: NotificationAction,
+}
+impl_lua_conversion!(NotificationRule);
+
+impl NotificationRule {
+    pub fn matches(&self, domain: &str, pane_title: &str, title: &str, body: &str) -> bool {
+        fn contains(want: &Option<String>, have: &str) -> bool {
+            match want {
+                Some(want) => have.contains(want.as_str()),
+                None => true,
+            }
+        }
+        self.domain.as_ref().map(|d| d == domain).unwrap_or(true)
+            && contains(&self.pane_title, pane_title)
+            && contains(&self.title, title)
+            && contains(&self.body, body)
+    }
+}
+
+/// A daily period, in local time, during which notifications are
+/// not shown.  The times are written as `HH:MM`; if `end` is earlier
+/// than `start` then the period spans midnight.
+#[derive(Default, Deserialize, Serialize, Clone, Debug)]
+pub struct DoNotDisturbSchedule {
+    pub start: String,
+    pub end: String,
+}
+impl_lua_conversion!(DoNotDisturbSchedule);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineTimestampFormat {
+    /// How long ago the line was written, such as `5m03s`
+    Relative,
+    /// The local time of day at which the line was written
+    Absolute,
+}
+impl_lua_conversion!(LineTimestampFormat);
+
+impl Default for LineTimestampFormat {
+    fn default() -> Self {
+        LineTimestampFormat::Relative
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         // Ask serde to provide the defaults based on the attributes
@@ -1012,12 +1685,17 @@ impl Config {
 
             let cfg: Self;
 
+            let start = std::time::Instant::now();
             let lua = make_lua_context(p)?;
-            let config: mlua::Value = smol::block_on(
-                lua.load(&s)
-                    .set_name(p.to_string_lossy().as_bytes())?
-                    .eval_async(),
-            )?;
+            let bytecode = bytecode::load(p);
+            let chunk = match bytecode.as_ref() {
+                Some(bytecode) => lua.load(bytecode.as_slice()),
+                None => lua.load(s.as_bytes()),
+            };
+            let config: mlua::Value =
+                smol::block_on(chunk.set_name(p.to_string_lossy().as_bytes())?.eval_async())?;
+            log::debug!("evaluated {} in {:?}", p.display(), start.elapsed());
+            overrides::remember_config_table(&lua, config.clone())?;
             cfg = luahelper::from_lua_value(config).with_context(|| {
                 format!(
                     "Error converting lua value returned by script {} to Config struct",
@@ -1029,6 +1707,12 @@ impl Config {
             // problems earlier than we use them.
             let _ = cfg.key_bindings()?;
 
+            if bytecode.is_none() {
+                if let Err(err) = bytecode::store(&lua, p, &s) {
+                    log::warn!("unable to cache compiled {}: {:#}", p.display(), err);
+                }
+            }
+
             std::env::set_var("WEZTERM_CONFIG_FILE", p);
             if let Some(dir) = p.parent() {
                 std::env::set_var("WEZTERM_CONFIG_DIR", dir);
@@ -1208,6 +1892,50 @@ impl Config {
         Ok(())
     }
 
+    /// Returns the destination for copy-on-select.  X11 and Wayland
+    /// have a primary selection of their own, so by default we copy
+    /// only to that.  Elsewhere the primary selection is emulated within
+    /// wezterm, so we also copy to the clipboard to make the text
+    /// available to other applications, as has always been the case there.
+    pub fn copy_on_select_destination(&self) -> ClipboardCopyDestination {
+        if let Some(dest) = self.copy_on_select {
+            return dest;
+        }
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            ClipboardCopyDestination::PrimarySelection
+        } else {
+            ClipboardCopyDestination::ClipboardAndPrimarySelection
+        }
+    }
+
+    /// Returns true if closing a pane from `domain`, whose foreground
+    /// process is `process_name`, should be confirmed.  Panes whose
+    /// process is unknown are always confirmed.
+    pub fn close_needs_confirmation(&self, domain: &str, process_name: Option<&str>) -> bool {
+        if let Some(rule) = self
+            .close_confirmation_rules
+            .iter()
+            .find(|rule| rule.matches(domain, process_name))
+        {
+            return rule.action == CloseConfirmationAction::Prompt;
+        }
+        match process_name {
+            Some(name) => !self
+                .skip_close_confirmation_for_processes_named
+                .iter()
+                .any(|skip| skip == name),
+            None => true,
+        }
+    }
+
+    /// Returns the settings for the update channel that is in use
+    pub fn current_update_channel_settings(&self) -> UpdateChannelSettings {
+        self.update_channel_settings
+            .get(&self.update_channel)
+            .cloned()
+            .unwrap_or_default()
+    }
+
     pub fn resolve_color_scheme(&self) -> Option<&Palette> {
         let scheme_name = self.color_scheme.as_ref()?;
 
@@ -1286,6 +2014,29 @@ fn default_ratelimit_line_prefetches_per_second() -> u32 {
     10
 }
 
+fn default_local_echo_threshold_ms() -> u64 {
+    100
+}
+
+fn default_container_runtimes() -> Vec<String> {
+    vec!["docker".to_string(), "podman".to_string()]
+}
+
+fn default_serial_port_baud_rate() -> usize {
+    9600
+}
+
+fn default_session_snapshot_scrollback_lines() -> usize {
+    200
+}
+
+fn default_session_restore_programs() -> Vec<String> {
+    ["vi", "vim", "nvim", "emacs", "htop", "top"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
 fn default_true() -> bool {
     true
 }
@@ -1300,6 +2051,10 @@ fn default_swap_backspace_and_delete() -> bool {
     false
 }
 
+fn default_clipboard_history_size() -> usize {
+    50
+}
+
 fn default_scrollback_lines() -> usize {
     3500
 }
@@ -1344,6 +2099,21 @@ fn compute_runtime_dir() -> Result<PathBuf, Error> {
     Ok(HOME_DIR.join(".local/share/wezterm"))
 }
 
+fn compute_cache_dir() -> PathBuf {
+    match dirs_next::cache_dir() {
+        Some(cache) => cache.join("wezterm"),
+        None => HOME_DIR.join(".cache").join("wezterm"),
+    }
+}
+
+/// Where wezterm keeps the data that it saves for later, such as
+/// plugins and keyboard macros
+pub fn data_dir() -> PathBuf {
+    dirs_next::data_dir()
+        .unwrap_or_else(|| HOME_DIR.join(".local").join("share"))
+        .join("wezterm")
+}
+
 pub fn pki_dir() -> anyhow::Result<PathBuf> {
     compute_runtime_dir().map(|d| d.join("pki"))
 }
@@ -1355,3 +2125,46 @@ fn default_read_timeout() -> Duration {
 fn default_write_timeout() -> Duration {
     Duration::from_secs(60)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wildcard() {
+        assert!(wildcard_match("vim", "vim"));
+        assert!(!wildcard_match("vim", "nvim"));
+        assert!(wildcard_match("*vim", "nvim"));
+        assert!(wildcard_match("python*", "python3.9"));
+        assert!(!wildcard_match("python*", "ipython"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYc"));
+        assert!(wildcard_match("a*b*c", "abc"));
+        assert!(!wildcard_match("a*b*c", "acb"));
+        assert!(!wildcard_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn close_confirmation() {
+        let mut config = Config::default_config();
+        assert!(!config.close_needs_confirmation("local", Some("bash")));
+        assert!(config.close_needs_confirmation("local", Some("vim")));
+        assert!(config.close_needs_confirmation("local", None));
+
+        config.close_confirmation_rules = vec![
+            CloseConfirmationRule {
+                domain: Some("prod".to_string()),
+                process_name: None,
+                action: CloseConfirmationAction::Prompt,
+            },
+            CloseConfirmationRule {
+                domain: None,
+                process_name: Some("*top".to_string()),
+                action: CloseConfirmationAction::Skip,
+            },
+        ];
+        assert!(!config.close_needs_confirmation("local", Some("htop")));
+        assert!(config.close_needs_confirmation("prod", Some("bash")));
+        assert!(config.close_needs_confirmation("prod", Some("htop")));
+    }
+}
@@ -2,8 +2,8 @@ use crate::{FontAttributes, TextStyle};
 use anyhow::anyhow;
 use bstr::BString;
 pub use luahelper::*;
-use mlua::ToLua;
 use mlua::{Lua, Table, Value};
+use mlua::{ToLua, ToLuaMulti};
 use serde::*;
 use smol::prelude::*;
 use std::path::Path;
@@ -115,6 +115,32 @@ pub fn make_lua_context(config_dir: &Path) -> anyhow::Result<Lua> {
         wezterm_mod.set("on", lua.create_function(register_event)?)?;
         wezterm_mod.set("emit", lua.create_async_function(emit_event)?)?;
         wezterm_mod.set("sleep_ms", lua.create_async_function(sleep_ms)?)?;
+        wezterm_mod.set("watch_path", lua.create_function(watch_path)?)?;
+        wezterm_mod.set("add_translations", lua.create_function(add_translations)?)?;
+
+        let plugin_mod = lua.create_table()?;
+        plugin_mod.set("require", lua.create_async_function(plugin_require)?)?;
+        wezterm_mod.set("plugin", plugin_mod)?;
+
+        let http_mod = lua.create_table()?;
+        http_mod.set("get", lua.create_async_function(crate::http::get)?)?;
+        http_mod.set("post", lua.create_async_function(crate::http::post)?)?;
+        http_mod.set("request", lua.create_async_function(crate::http::request)?)?;
+        http_mod.set(
+            "json_decode",
+            lua.create_function(crate::http::json_decode)?,
+        )?;
+        http_mod.set(
+            "json_encode",
+            lua.create_function(crate::http::json_encode)?,
+        )?;
+        wezterm_mod.set("http", http_mod)?;
+
+        let time_mod = lua.create_table()?;
+        time_mod.set("call_after", lua.create_function(crate::timer::call_after)?)?;
+        time_mod.set("call_every", lua.create_function(crate::timer::call_every)?)?;
+        wezterm_mod.set("time", time_mod)?;
+        crate::timer::assign_generation(&lua)?;
 
         package.set("path", path_array.join(";"))?;
 
@@ -131,6 +157,20 @@ async fn sleep_ms<'lua>(_: &'lua Lua, milliseconds: u64) -> mlua::Result<()> {
     Ok(())
 }
 
+/// Emits the `path-changed` event, passing the path that changed,
+/// whenever the file or directory at `path` changes.
+/// `wezterm.watch_path("/home/user/.cache/theme.lua")`
+fn watch_path<'lua>(_: &'lua Lua, path: String) -> mlua::Result<()> {
+    crate::watch_path_for_lua(path.into()).map_err(|e| mlua::Error::external(e))
+}
+
+/// Adds translations, in Fluent syntax, of the strings shown by
+/// the gui for the specified locale.
+/// `wezterm.add_translations("de", "launcher-title = Menü")`
+fn add_translations<'lua>(_: &'lua Lua, (locale, source): (String, String)) -> mlua::Result<()> {
+    crate::i18n::add_translations(&locale, &source).map_err(|e| mlua::Error::external(e))
+}
+
 /// Returns the system hostname.
 /// Errors may occur while retrieving the hostname from the system,
 /// or if the hostname isn't a UTF-8 string.
@@ -372,6 +412,65 @@ pub async fn emit_event<'lua>(
     }
 }
 
+/// This is similar to `emit_event`, but is intended for events whose
+/// handler computes a value rather than overriding a default action.
+/// The first handler that was registered for the event via `wezterm.on`
+/// is called and its return value is passed back to the caller.
+/// If no handler has been registered then `nil` is returned.
+pub async fn emit_async_callback<'lua>(
+    lua: &'lua Lua,
+    (name, args): (String, mlua::MultiValue<'lua>),
+) -> mlua::Result<mlua::Value<'lua>> {
+    let decorated_name = format!("wezterm-event-{}", name);
+    let tbl: mlua::Value = lua.named_registry_value(&decorated_name)?;
+    match tbl {
+        mlua::Value::Table(tbl) => match tbl.sequence_values::<mlua::Function>().next() {
+            Some(func) => func?.call_async(args).await,
+            None => Ok(mlua::Value::Nil),
+        },
+        _ => Ok(mlua::Value::Nil),
+    }
+}
+
+/// This implements `wezterm.plugin.require`, which fetches the plugin
+/// at the git repository `url`, checks out the commit chosen by the
+/// optional `spec` table, such as `{tag="v1.0"}`, `{branch="main"}` or
+/// `{commit="0123abc"}`, and returns the result of evaluating its
+/// `plugin/init.lua`.
+/// When a plugin is first installed, or a different commit is checked
+/// out than before, the `plugin-changed` event is emitted with the url
+/// and the commit.
+///
+/// ```lua
+/// local wezterm = require 'wezterm';
+/// wezterm.on("plugin-changed", function(url, commit)
+///   wezterm.log_error(url .. " is now at " .. commit)
+/// end);
+/// local plugin = wezterm.plugin.require("https://github.com/owner/repo", {tag="v1.0"})
+/// ```
+async fn plugin_require<'lua>(
+    lua: &'lua Lua,
+    (url, spec): (String, Option<Value<'lua>>),
+) -> mlua::Result<Value<'lua>> {
+    let spec: crate::plugin::PluginSpec = match spec {
+        Some(spec) => from_lua_value(spec).map_err(|e| mlua::Error::external(e))?,
+        None => Default::default(),
+    };
+    let installed = crate::plugin::install(&url, &spec).map_err(|e| mlua::Error::external(e))?;
+    if installed.changed {
+        let args = (url.clone(), installed.commit.clone()).to_lua_multi(lua)?;
+        emit_event(lua, ("plugin-changed".to_string(), args)).await?;
+    }
+
+    let init = installed.dir.join("plugin").join("init.lua");
+    let code = std::fs::read(&init)
+        .map_err(|e| mlua::Error::external(anyhow!("reading {}: {}", init.display(), e)))?;
+    lua.load(&code)
+        .set_name(init.to_string_lossy().as_bytes())?
+        .eval_async()
+        .await
+}
+
 /// Ungh: https://github.com/microsoft/WSL/issues/4456
 fn utf16_to_utf8<'lua>(_: &'lua Lua, text: mlua::String) -> mlua::Result<String> {
     let bytes = text.as_bytes();
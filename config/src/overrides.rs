@@ -0,0 +1,213 @@
+//! Per-window configuration overrides, as set by
+//! `window:set_config_overrides`.  The table that the configuration
+//! file evaluated to is kept in the registry of its lua context, so
+//! that a window can produce its own configuration by layering its
+//! overrides over that table and converting the result in the same
+//! way as the configuration file itself.
+use crate::{Config, ConfigHandle};
+use anyhow::Context;
+use mlua::{Lua, Table, Value};
+use std::path::PathBuf;
+
+const CONFIG_TABLE_KEY: &str = "wezterm-config-table";
+
+/// The options that a window honors when they are overridden.  The
+/// rest are read from the global configuration by code that doesn't
+/// belong to any one window, and so they can only be changed in the
+/// configuration file.
+pub const PER_WINDOW_OPTIONS: &[&str] = &[
+    "adjust_window_size_when_changing_font_size",
+    "allow_square_glyphs_to_overflow_width",
+    "bold_brightens_ansi_colors",
+    "color_scheme",
+    "color_scheme_dirs",
+    "color_schemes",
+    "colors",
+    "confirm_unsafe_paste",
+    "copy_on_select",
+    "cursor_blink_rate",
+    "debug_key_events",
+    "disable_default_key_bindings",
+    "disable_default_mouse_bindings",
+    "dpi",
+    "enable_scroll_bar",
+    "enable_tab_bar",
+    "font",
+    "font_dirs",
+    "font_rules",
+    "font_size",
+    "hide_tab_bar_if_only_one_tab",
+    "idle_inhibit_processes",
+    "inactive_pane_hsb",
+    "keys",
+    "leader",
+    "line_height",
+    "line_timestamp_format",
+    "mark_failed_commands",
+    "mouse_bindings",
+    "scroll_to_bottom_on_input",
+    "show_tab_index_in_tab_bar",
+    "swap_backspace_and_delete",
+    "tab_and_split_indices_are_zero_based",
+    "tab_bar_buttons",
+    "tab_max_width",
+    "text_background_opacity",
+    "use_resize_increments",
+    "window_background_image",
+    "window_background_image_hsb",
+    "window_close_confirmation",
+    "window_padding",
+];
+
+/// The options that are only consulted when wezterm starts up
+pub const RESTART_REQUIRED_OPTIONS: &[&str] = &[
+    "daemon_options",
+    "enable_wayland",
+    "font_locator",
+    "font_rasterizer",
+    "font_shaper",
+    "front_end",
+    "prefer_egl",
+    "prefer_egl_device",
+    "ssh_domains",
+    "tls_clients",
+    "tls_servers",
+    "unix_domains",
+];
+
+/// The overridden options that a window cannot honor
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UnsupportedOverrides {
+    /// Options that only take effect when wezterm is restarted
+    pub restart_required: Vec<String>,
+    /// Options that can only be changed for all windows at once,
+    /// by changing the configuration file
+    pub not_per_window: Vec<String>,
+}
+
+impl UnsupportedOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.restart_required.is_empty() && self.not_per_window.is_empty()
+    }
+}
+
+impl std::fmt::Display for UnsupportedOverrides {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut sep = "";
+        if !self.restart_required.is_empty() {
+            write!(
+                fmt,
+                "these options require restarting wezterm and were ignored: {}",
+                self.restart_required.join(", ")
+            )?;
+            sep = "; ";
+        }
+        if !self.not_per_window.is_empty() {
+            write!(
+                fmt,
+                "{}these options cannot be overridden per-window and were ignored: {}",
+                sep,
+                self.not_per_window.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Sorts the keys of `overrides` that a window cannot honor
+pub fn check_overrides(
+    overrides: &serde_json::Map<String, serde_json::Value>,
+) -> UnsupportedOverrides {
+    let mut unsupported = UnsupportedOverrides::default();
+    for key in overrides.keys() {
+        if RESTART_REQUIRED_OPTIONS.contains(&key.as_str()) {
+            unsupported.restart_required.push(key.clone());
+        } else if !PER_WINDOW_OPTIONS.contains(&key.as_str()) {
+            unsupported.not_per_window.push(key.clone());
+        }
+    }
+    unsupported
+}
+
+/// Keeps the table that the configuration file evaluated to, so that
+/// overrides can be layered over it later on
+pub(crate) fn remember_config_table<'lua>(lua: &'lua Lua, config: Value<'lua>) -> mlua::Result<()> {
+    lua.set_named_registry_value(CONFIG_TABLE_KEY, config)
+}
+
+fn config_table<'lua>(lua: &'lua Lua) -> anyhow::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    if let Value::Table(config) = lua.named_registry_value::<_, Value>(CONFIG_TABLE_KEY)? {
+        for pair in config.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            table.set(key, value)?;
+        }
+    }
+    Ok(table)
+}
+
+/// Returns the current configuration with the per-window options in
+/// `overrides` applied to it; the other keys in `overrides` are
+/// ignored, and can be found with `check_overrides`.
+/// The result has the same generation as the current configuration.
+/// This MUST only be called from the main thread.
+pub fn configuration_with_overrides(
+    overrides: &serde_json::Map<String, serde_json::Value>,
+) -> anyhow::Result<ConfigHandle> {
+    let base = crate::configuration();
+    if !overrides
+        .keys()
+        .any(|key| PER_WINDOW_OPTIONS.contains(&key.as_str()))
+    {
+        return Ok(base);
+    }
+
+    // When there is no configuration file, there is no lua context
+    // either, and the overrides apply to the defaults
+    let lua = match crate::lua_config_on_main_thread()? {
+        Some(lua) => lua,
+        None => std::rc::Rc::new(Lua::new()),
+    };
+    let table = config_table(&lua)?;
+    for (key, value) in overrides {
+        if PER_WINDOW_OPTIONS.contains(&key.as_str()) {
+            table.set(key.as_str(), luahelper::to_lua_value(&lua, value.clone())?)?;
+        }
+    }
+
+    let config: Config = luahelper::from_lua_value(Value::Table(table))
+        .context("Error applying configuration overrides")?;
+    // As when loading the configuration, raise any problems with
+    // the key bindings now rather than when they are used
+    let _ = config.key_bindings()?;
+
+    let config_path = std::env::var_os("WEZTERM_CONFIG_FILE").map(PathBuf::from);
+    Ok(ConfigHandle {
+        config: std::sync::Arc::new(config.compute_extra_defaults(config_path.as_deref())),
+        generation: base.generation(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify_overrides() {
+        let overrides: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+            r#"{"font_size": 14, "front_end": "Software", "scrollback_lines": 10}"#,
+        )
+        .unwrap();
+        let unsupported = check_overrides(&overrides);
+        assert_eq!(unsupported.restart_required, vec!["front_end".to_string()]);
+        assert_eq!(
+            unsupported.not_per_window,
+            vec!["scrollback_lines".to_string()]
+        );
+        assert_eq!(
+            unsupported.to_string(),
+            "these options require restarting wezterm and were ignored: front_end; \
+             these options cannot be overridden per-window and were ignored: scrollback_lines"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Plugins are lua modules that live in git repositories and that are
+//! loaded by `wezterm.plugin.require(url, spec)`.  Each repository is
+//! cloned into the plugins directory, and the commit that was checked
+//! out for it is recorded in a lockfile alongside the configuration, so
+//! that copying the configuration to another machine reproduces the
+//! same plugins.  `wezterm plugin update` moves the lockfile on to the
+//! latest commits that match the specs.
+use crate::CONFIG_DIR;
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Chooses the commit of a plugin to use.  At most one of the fields
+/// may be set; when none are, the default branch is followed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PluginSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+impl PluginSpec {
+    fn validate(&self) -> anyhow::Result<()> {
+        let count = [&self.tag, &self.branch, &self.commit]
+            .iter()
+            .filter(|f| f.is_some())
+            .count();
+        if count > 1 {
+            bail!("only one of tag, branch or commit may be given for a plugin");
+        }
+        Ok(())
+    }
+
+    /// The git revision that resolves to the commit to use, once the
+    /// remote has been fetched
+    fn revision(&self) -> String {
+        if let Some(tag) = &self.tag {
+            format!("refs/tags/{}", tag)
+        } else if let Some(branch) = &self.branch {
+            format!("refs/remotes/origin/{}", branch)
+        } else if let Some(commit) = &self.commit {
+            commit.clone()
+        } else {
+            "refs/remotes/origin/HEAD".to_string()
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        if let Some(tag) = &self.tag {
+            format!("tag {}", tag)
+        } else if let Some(branch) = &self.branch {
+            format!("branch {}", branch)
+        } else if let Some(commit) = &self.commit {
+            format!("commit {}", commit)
+        } else {
+            "default branch".to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LockedPlugin {
+    pub url: String,
+    /// The commit that `spec` resolved to
+    pub commit: String,
+    #[serde(default)]
+    pub spec: PluginSpec,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub plugin: Vec<LockedPlugin>,
+}
+
+impl Lockfile {
+    pub fn load() -> anyhow::Result<Self> {
+        let path = lockfile_path();
+        match std::fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).with_context(|| format!("parsing {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = lockfile_path();
+        let s = toml::to_string(self)?;
+        crate::create_user_owned_dirs(&CONFIG_DIR)?;
+        std::fs::write(&path, s).with_context(|| format!("writing {}", path.display()))
+    }
+
+    fn get(&self, url: &str) -> Option<&LockedPlugin> {
+        self.plugin.iter().find(|p| p.url == url)
+    }
+
+    fn set(&mut self, locked: LockedPlugin) {
+        match self.plugin.iter_mut().find(|p| p.url == locked.url) {
+            Some(p) => *p = locked,
+            None => self.plugin.push(locked),
+        }
+    }
+}
+
+/// The lockfile is kept with the configuration so that it can be
+/// shared along with it
+pub fn lockfile_path() -> PathBuf {
+    CONFIG_DIR.join("wezterm-plugins.lock")
+}
+
+pub fn plugins_dir() -> PathBuf {
+    crate::data_dir().join("plugins")
+}
+
+/// Makes a directory name from a repository url, for example
+/// `https://github.com/owner/repo.git` becomes `github_com_owner_repo`
+fn repo_dir_name(url: &str) -> String {
+    let url = match url.find("://") {
+        Some(idx) => &url[idx + 3..],
+        None => url,
+    };
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    url.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn repo_dir(url: &str) -> PathBuf {
+    plugins_dir().join(repo_dir_name(url))
+}
+
+fn git(dir: Option<&Path>, args: &[&str]) -> anyhow::Result<String> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    cmd.args(args);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(winapi::um::winbase::CREATE_NO_WINDOW);
+    }
+
+    let output = cmd
+        .output()
+        .with_context(|| format!("running git {}", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn fetch(dir: &Path) -> anyhow::Result<()> {
+    git(
+        Some(dir),
+        &["fetch", "--quiet", "--tags", "--force", "origin"],
+    )?;
+    // Follow the remote if its default branch has changed
+    git(Some(dir), &["remote", "set-head", "origin", "--auto"]).ok();
+    Ok(())
+}
+
+fn resolve(dir: &Path, revision: &str) -> anyhow::Result<String> {
+    git(
+        Some(dir),
+        &["rev-parse", "--verify", &format!("{}^{{commit}}", revision)],
+    )
+}
+
+/// Resolves `revision`, fetching from the remote if it isn't known yet
+fn resolve_or_fetch(dir: &Path, revision: &str) -> anyhow::Result<String> {
+    match resolve(dir, revision) {
+        Ok(commit) => Ok(commit),
+        Err(_) => {
+            fetch(dir)?;
+            resolve(dir, revision)
+        }
+    }
+}
+
+/// Clones the repository at `url` into `dir`, returning true if it
+/// wasn't already there
+fn clone_if_missing(url: &str, dir: &Path) -> anyhow::Result<bool> {
+    if dir.exists() {
+        return Ok(false);
+    }
+    std::fs::create_dir_all(plugins_dir())?;
+    let dest = dir.to_str().ok_or_else(|| anyhow!("path is not UTF-8"))?;
+    git(None, &["clone", "--quiet", url, dest])?;
+    Ok(true)
+}
+
+/// Checks out `commit`, returning true if it wasn't already checked out
+fn checkout(dir: &Path, commit: &str) -> anyhow::Result<bool> {
+    if resolve(dir, "HEAD").ok().as_deref() == Some(commit) {
+        return Ok(false);
+    }
+    git(Some(dir), &["checkout", "--quiet", "--detach", commit])?;
+    Ok(true)
+}
+
+pub struct InstalledPlugin {
+    pub dir: PathBuf,
+    pub commit: String,
+    /// True if the plugin was cloned, or a different commit was
+    /// checked out, for this call
+    pub changed: bool,
+}
+
+/// Ensures that the plugin at `url` is cloned and that the commit that
+/// is recorded for it in the lockfile is checked out.  Plugins that are
+/// not in the lockfile, or whose spec has changed, are resolved afresh
+/// and recorded.
+pub fn install(url: &str, spec: &PluginSpec) -> anyhow::Result<InstalledPlugin> {
+    spec.validate()?;
+    let dir = repo_dir(url);
+    let mut changed = clone_if_missing(url, &dir)?;
+
+    let mut lockfile = Lockfile::load()?;
+    let commit = match lockfile.get(url) {
+        Some(locked) if locked.spec == *spec => resolve_or_fetch(&dir, &locked.commit)?,
+        _ => resolve_or_fetch(&dir, &spec.revision())?,
+    };
+    changed |= checkout(&dir, &commit)?;
+
+    let locked = LockedPlugin {
+        url: url.to_string(),
+        commit: commit.clone(),
+        spec: spec.clone(),
+    };
+    if lockfile.get(url) != Some(&locked) {
+        lockfile.set(locked);
+        lockfile.save()?;
+    }
+
+    Ok(InstalledPlugin {
+        dir,
+        commit,
+        changed,
+    })
+}
+
+pub struct UpdatedPlugin {
+    pub url: String,
+    pub old_commit: String,
+    pub new_commit: String,
+}
+
+/// Fetches the plugins in the lockfile, or just the one whose url
+/// is `only`, and moves each of them to the latest commit that
+/// matches its spec
+pub fn update(only: Option<&str>) -> anyhow::Result<Vec<UpdatedPlugin>> {
+    let mut lockfile = Lockfile::load()?;
+    if let Some(url) = only {
+        if lockfile.get(url).is_none() {
+            bail!("{} is not in {}", url, lockfile_path().display());
+        }
+    }
+
+    let mut updated = vec![];
+    for locked in lockfile.plugin.iter_mut() {
+        if only.map(|url| url != locked.url).unwrap_or(false) {
+            continue;
+        }
+        let dir = repo_dir(&locked.url);
+        clone_if_missing(&locked.url, &dir)?;
+        fetch(&dir).with_context(|| format!("updating {}", locked.url))?;
+        let commit = resolve(&dir, &locked.spec.revision())?;
+        checkout(&dir, &commit)?;
+        if commit != locked.commit {
+            updated.push(UpdatedPlugin {
+                url: locked.url.clone(),
+                old_commit: std::mem::replace(&mut locked.commit, commit.clone()),
+                new_commit: commit,
+            });
+        }
+    }
+
+    if !updated.is_empty() {
+        lockfile.save()?;
+    }
+    Ok(updated)
+}
+
+/// Deletes the clone of the plugin at `url` and forgets it
+pub fn remove(url: &str) -> anyhow::Result<()> {
+    let mut lockfile = Lockfile::load()?;
+    let len = lockfile.plugin.len();
+    lockfile.plugin.retain(|p| p.url != url);
+    let dir = repo_dir(url);
+    if lockfile.plugin.len() == len && !dir.exists() {
+        bail!("{} is not installed", url);
+    }
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).with_context(|| format!("removing {}", dir.display()))?;
+    }
+    lockfile.save()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repo_dir_names() {
+        assert_eq!(
+            repo_dir_name("https://github.com/owner/repo.git"),
+            "github_com_owner_repo"
+        );
+        assert_eq!(
+            repo_dir_name("https://github.com/owner/repo/"),
+            "github_com_owner_repo"
+        );
+        assert_eq!(
+            repo_dir_name("git@example.com:owner/repo"),
+            "git_example_com_owner_repo"
+        );
+    }
+
+    #[test]
+    fn lockfile_round_trip() {
+        let lockfile = Lockfile {
+            plugin: vec![LockedPlugin {
+                url: "https://github.com/owner/repo".to_string(),
+                commit: "0123456789abcdef".to_string(),
+                spec: PluginSpec {
+                    tag: Some("v1.0".to_string()),
+                    ..Default::default()
+                },
+            }],
+        };
+        let s = toml::to_string(&lockfile).unwrap();
+        let parsed: Lockfile = toml::from_str(&s).unwrap();
+        assert_eq!(parsed.plugin, lockfile.plugin);
+    }
+}
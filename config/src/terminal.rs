@@ -1,11 +1,51 @@
 //! Bridge our gui config into the terminal crate configuration
 
-use crate::configuration;
+use crate::{configuration, ScrollbackOverride};
+use portable_pty::CommandBuilder;
+use std::path::Path;
 use termwiz::hyperlink::Rule as HyperlinkRule;
 use wezterm_term::color::ColorPalette;
 
-#[derive(Debug)]
-pub struct TermConfig;
+#[derive(Debug, Default)]
+pub struct TermConfig {
+    /// The domain and program of the pane, which select the
+    /// scrollback_overrides that apply to it
+    domain: Option<String>,
+    program: Option<String>,
+}
+
+impl TermConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the configuration for a pane spawned in the named domain
+    /// to run `cmd`; the default program has no program name
+    pub fn for_pane(domain: &str, cmd: &CommandBuilder) -> Self {
+        let program = cmd.get_argv().get(0).and_then(|argv0| {
+            Path::new(argv0)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        });
+        Self {
+            domain: Some(domain.to_string()),
+            program,
+        }
+    }
+
+    /// Returns the first value selected by `get` from the overrides
+    /// that match this pane
+    fn override_value<T, F>(&self, get: F) -> Option<T>
+    where
+        F: Fn(&ScrollbackOverride) -> Option<T>,
+    {
+        configuration()
+            .scrollback_overrides
+            .iter()
+            .filter(|o| o.matches(self.domain.as_deref(), self.program.as_deref()))
+            .find_map(get)
+    }
+}
 
 impl wezterm_term::TerminalConfiguration for TermConfig {
     fn generation(&self) -> usize {
@@ -13,7 +53,23 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
     }
 
     fn scrollback_size(&self) -> usize {
-        configuration().scrollback_lines
+        self.override_value(|o| o.scrollback_lines)
+            .unwrap_or_else(|| configuration().scrollback_lines)
+    }
+
+    fn scrollback_bytes(&self) -> usize {
+        self.override_value(|o| o.scrollback_bytes)
+            .unwrap_or_else(|| configuration().scrollback_bytes)
+    }
+
+    fn scrollback_dedup(&self) -> bool {
+        self.override_value(|o| o.scrollback_dedup)
+            .unwrap_or_else(|| configuration().scrollback_dedup)
+    }
+
+    fn disk_scrollback_size(&self) -> usize {
+        self.override_value(|o| o.disk_scrollback_lines)
+            .unwrap_or_else(|| configuration().disk_scrollback_lines)
     }
 
     fn hyperlink_rules(&self) -> (usize, Vec<HyperlinkRule>) {
@@ -0,0 +1,163 @@
+//! Implements `wezterm.time.call_after` and `wezterm.time.call_every`.
+//! Each timer belongs to the lua context of the configuration that
+//! started it: once the configuration has been reloaded, the timers
+//! of the previous one stop, so that a configuration that starts a
+//! timer each time it is evaluated doesn't accumulate duplicates.
+use anyhow::anyhow;
+use mlua::{Function, Lua, RegistryKey, UserData, UserDataMethods};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+static LUA_GENERATION: AtomicUsize = AtomicUsize::new(0);
+const GENERATION_KEY: &str = "wezterm-timer-generation";
+
+/// How long a timer waits before trying again when the configuration
+/// that started it hasn't been published yet
+const PENDING_RETRY: Duration = Duration::from_millis(100);
+
+/// Numbers a newly made lua context, so that timers can tell whether
+/// the context that started them has since been replaced
+pub(crate) fn assign_generation(lua: &Lua) -> mlua::Result<()> {
+    let generation = LUA_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    lua.set_named_registry_value(GENERATION_KEY, generation)
+}
+
+fn generation(lua: &Lua) -> mlua::Result<usize> {
+    lua.named_registry_value(GENERATION_KEY)
+}
+
+/// The handle that lua code uses to cancel a timer
+#[derive(Clone)]
+pub struct TimerObject {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl UserData for TimerObject {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("cancel", |_, this, _: ()| {
+            this.cancelled.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+        methods.add_method("is_cancelled", |_, this, _: ()| {
+            Ok(this.cancelled.load(Ordering::SeqCst))
+        });
+    }
+}
+
+enum Tick {
+    Fired,
+    /// The configuration that started the timer isn't the current one yet
+    Pending,
+    /// The configuration that started the timer has been replaced
+    Stale,
+}
+
+async fn fire(
+    lua: Option<Rc<Lua>>,
+    timer_generation: usize,
+    key: Rc<RegistryKey>,
+) -> anyhow::Result<Tick> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(Tick::Pending),
+    };
+    let current = generation(&lua)?;
+    if current > timer_generation {
+        return Ok(Tick::Stale);
+    }
+    if current < timer_generation {
+        return Ok(Tick::Pending);
+    }
+    let callback: Function = lua.registry_value(&key)?;
+    callback.call_async::<_, ()>(()).await?;
+    Ok(Tick::Fired)
+}
+
+async fn run_timer(
+    timer_generation: usize,
+    key: RegistryKey,
+    interval: Duration,
+    repeat: bool,
+    cancelled: Arc<AtomicBool>,
+) {
+    let key = Rc::new(key);
+    let mut wait = interval;
+    loop {
+        smol::Timer::after(wait).await;
+        if cancelled.load(Ordering::SeqCst) {
+            break;
+        }
+        let key = Rc::clone(&key);
+        let tick =
+            crate::with_lua_config_on_main_thread(move |lua| fire(lua, timer_generation, key))
+                .await;
+        wait = interval;
+        match tick {
+            Ok(Tick::Fired) if repeat => {}
+            Ok(Tick::Fired) | Ok(Tick::Stale) => break,
+            Ok(Tick::Pending) => wait = PENDING_RETRY.min(interval),
+            Err(err) => {
+                log::error!("while running timer callback: {:#}", err);
+                if !repeat {
+                    break;
+                }
+            }
+        }
+    }
+    cancelled.store(true, Ordering::SeqCst);
+}
+
+fn start_timer(
+    lua: &Lua,
+    seconds: f64,
+    callback: Function,
+    repeat: bool,
+) -> mlua::Result<TimerObject> {
+    if !seconds.is_finite() || seconds < 0. || (repeat && seconds == 0.) {
+        return Err(mlua::Error::external(anyhow!(
+            "{} is not a valid number of seconds for a timer",
+            seconds
+        )));
+    }
+    let interval = Duration::from_secs_f64(seconds);
+    let timer_generation = generation(lua)?;
+    let key = lua.create_registry_value(callback)?;
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let timer = TimerObject {
+        cancelled: Arc::clone(&cancelled),
+    };
+    // The configuration may be evaluated away from the main thread,
+    // but the callbacks must run on it
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(run_timer(
+            timer_generation,
+            key,
+            interval,
+            repeat,
+            cancelled,
+        ))
+        .detach();
+    })
+    .detach();
+    Ok(timer)
+}
+
+/// Calls `callback` once, after `seconds` have elapsed
+pub fn call_after<'lua>(
+    lua: &'lua Lua,
+    (seconds, callback): (f64, Function<'lua>),
+) -> mlua::Result<TimerObject> {
+    start_timer(lua, seconds, callback, false)
+}
+
+/// Calls `callback` each time that `seconds` elapse, until the timer
+/// is cancelled or the configuration is reloaded
+pub fn call_every<'lua>(
+    lua: &'lua Lua,
+    (seconds, callback): (f64, Function<'lua>),
+) -> mlua::Result<TimerObject> {
+    start_timer(lua, seconds, callback, true)
+}
@@ -13,6 +13,13 @@ pub struct UnixDomain {
     /// value will be computed.
     pub socket_path: Option<PathBuf>,
 
+    /// If set, the server will also listen on this path for clients
+    /// that may only observe the session; keyboard and mouse input
+    /// from those clients is discarded.  Access to it is governed by
+    /// the filesystem permissions of the socket and its directory,
+    /// so it can be shared with other users.
+    pub read_only_socket_path: Option<PathBuf>,
+
     /// If true, connect to this domain automatically at startup
     #[serde(default)]
     pub connect_automatically: bool,
@@ -51,6 +58,7 @@ impl Default for UnixDomain {
         Self {
             name: String::new(),
             socket_path: None,
+            read_only_socket_path: None,
             connect_automatically: false,
             no_serve_automatically: false,
             serve_command: None,
@@ -0,0 +1,344 @@
+//! Writes a crash report when the process panics or is killed by a
+//! fatal signal, so that the next time wezterm starts it can offer to
+//! help report the problem.
+//!
+//! Reports are plain text files in `crash_dir()`.  Each begins with a
+//! header describing the build and environment, followed by either the
+//! panic message and a symbolized backtrace, or, for a fatal signal, the
+//! raw return addresses from the stack along with the memory map of the
+//! process so that they can be symbolized offline.
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::SystemTime;
+
+/// How many reports that have already been offered to the user to keep
+const MAX_SEEN_REPORTS: usize = 10;
+
+/// The extension of a report that has not been offered to the user
+const PENDING_EXTENSION: &str = "txt";
+/// The extension of a report that has been offered to the user
+const SEEN_EXTENSION: &str = "seen";
+
+/// What the signal handler needs to write a report.  This is prepared
+/// ahead of time because allocating or formatting is not safe from
+/// within a signal handler.
+struct CrashContext {
+    path: CString,
+    header: String,
+}
+
+static CONTEXT: AtomicPtr<CrashContext> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Returns the directory that holds crash reports
+pub fn crash_dir() -> PathBuf {
+    config::CACHE_DIR.join("crash-reports")
+}
+
+/// Returns the reports that were written by processes that crashed and
+/// that have not yet been offered to the user, oldest first
+pub fn pending_crash_reports() -> Vec<PathBuf> {
+    reports_with_extension(PENDING_EXTENSION)
+}
+
+/// Marks the supplied reports as having been offered to the user, so
+/// that they are not offered again, and prunes older reports
+pub fn mark_crash_reports_seen(reports: &[PathBuf]) {
+    for report in reports {
+        if let Err(err) = std::fs::rename(report, report.with_extension(SEEN_EXTENSION)) {
+            log::error!("while marking {} as seen: {}", report.display(), err);
+        }
+    }
+
+    let seen = reports_with_extension(SEEN_EXTENSION);
+    if seen.len() > MAX_SEEN_REPORTS {
+        for report in &seen[..seen.len() - MAX_SEEN_REPORTS] {
+            std::fs::remove_file(report).ok();
+        }
+    }
+}
+
+fn reports_with_extension(extension: &str) -> Vec<PathBuf> {
+    let mut reports: Vec<PathBuf> = match std::fs::read_dir(crash_dir()) {
+        Ok(dir) => dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(extension))
+            .collect(),
+        Err(_) => vec![],
+    };
+    // Report names begin with a timestamp, so this puts them in
+    // chronological order
+    reports.sort();
+    reports
+}
+
+fn header(started: u64) -> String {
+    let args: Vec<String> = std::env::args().collect();
+    format!(
+        "wezterm crash report\n\
+         version: {}\n\
+         os: {} {}\n\
+         executable: {}\n\
+         args: {:?}\n\
+         pid: {}\n\
+         started: {} (seconds since the unix epoch)\n\
+         reference address: {:#x} (env_bootstrap::crash::install_crash_handler)\n",
+        config::wezterm_version(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::current_exe()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "unknown".to_string()),
+        args,
+        std::process::id(),
+        started,
+        install_crash_handler as usize,
+    )
+}
+
+fn config_digest_line() -> String {
+    format!("config digest: {:016x}\n", config::configuration_digest())
+}
+
+/// Installs a panic hook and, on unix systems, handlers for the signals
+/// that indicate a crash, that write a report to `crash_dir()`.
+/// The previously installed hook and handlers continue to run after the
+/// report has been written.
+pub fn install_crash_handler() {
+    let dir = crash_dir();
+    if let Err(err) = config::create_user_owned_dirs(&dir) {
+        log::error!("crash reports are disabled: {}: {:#}", dir.display(), err);
+        return;
+    }
+
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!(
+        "{}-{}.{}",
+        started,
+        std::process::id(),
+        PENDING_EXTENSION
+    ));
+    let context = Box::new(CrashContext {
+        path: match CString::new(path.to_string_lossy().into_owned()) {
+            Ok(path) => path,
+            Err(_) => return,
+        },
+        header: header(started),
+    });
+    // This is never freed; it must remain valid for as long as a
+    // signal might arrive
+    CONTEXT.store(Box::into_raw(context), Ordering::SeqCst);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        write_panic_report(&path, info);
+        default_hook(info);
+    }));
+
+    #[cfg(unix)]
+    signal::install();
+}
+
+fn write_panic_report(path: &Path, info: &std::panic::PanicInfo) {
+    use std::io::Write;
+
+    let context = CONTEXT.load(Ordering::SeqCst);
+    if context.is_null() {
+        return;
+    }
+    let context = unsafe { &*context };
+
+    let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<Any>".to_string()
+    };
+    let location = info
+        .location()
+        .map(|loc| format!("{}:{}:{}", loc.file(), loc.line(), loc.column()))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let report = format!(
+        "{}{}\npanic in thread '{}' at {}:\n{}\n\n{:?}\n",
+        context.header,
+        config_digest_line(),
+        std::thread::current().name().unwrap_or("<unnamed>"),
+        location,
+        message,
+        backtrace::Backtrace::new(),
+    );
+
+    // Append rather than truncate, so that a panic that leads to an
+    // abort produces a single report with both parts
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(report.as_bytes()));
+    if let Err(err) = result {
+        log::error!("failed to write crash report {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(unix)]
+mod signal {
+    use super::CONTEXT;
+    use std::sync::atomic::Ordering;
+
+    const SIGNALS: &[libc::c_int] = &[
+        libc::SIGSEGV,
+        libc::SIGBUS,
+        libc::SIGILL,
+        libc::SIGFPE,
+        libc::SIGABRT,
+    ];
+
+    /// The handlers that were installed before ours, indexed the same
+    /// as SIGNALS
+    static mut PREVIOUS: [Option<libc::sigaction>; 5] = [None; 5];
+
+    pub fn install() {
+        for (idx, &signum) in SIGNALS.iter().enumerate() {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = fatal_signal as usize;
+                // Run on the alternate stack that the rust runtime sets up
+                // for each thread, so that we can report stack overflows
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                let mut previous: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(signum, &action, &mut previous) == 0 {
+                    PREVIOUS[idx] = Some(previous);
+                }
+            }
+        }
+    }
+
+    /// Only async-signal-safe functions may be used from here on down,
+    /// which rules out allocation, locking and formatting.
+    extern "C" fn fatal_signal(
+        signum: libc::c_int,
+        _info: *mut libc::siginfo_t,
+        _ucontext: *mut libc::c_void,
+    ) {
+        let context = CONTEXT.load(Ordering::SeqCst);
+        if !context.is_null() {
+            let context = unsafe { &*context };
+            unsafe {
+                let fd = libc::open(
+                    context.path.as_ptr(),
+                    libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND | libc::O_CLOEXEC,
+                    0o600,
+                );
+                if fd >= 0 {
+                    write_report(fd, &context.header, signum);
+                    libc::close(fd);
+                }
+            }
+        }
+
+        // Put back the handler that was there before us and let it deal
+        // with the signal.  For a fault, returning re-executes the
+        // faulting instruction, which faults again; an abort has to be
+        // raised again explicitly.
+        if let Some(idx) = SIGNALS.iter().position(|&s| s == signum) {
+            unsafe {
+                if let Some(previous) = PREVIOUS[idx].as_ref() {
+                    libc::sigaction(signum, previous, std::ptr::null_mut());
+                } else {
+                    libc::signal(signum, libc::SIG_DFL);
+                }
+            }
+        }
+        if signum == libc::SIGABRT {
+            unsafe {
+                libc::raise(signum);
+            }
+        }
+    }
+
+    unsafe fn write_report(fd: libc::c_int, header: &str, signum: libc::c_int) {
+        write_all(fd, header.as_bytes());
+        // The digest is formatted by hand; it may have changed since
+        // the header was prepared if the configuration was reloaded
+        write_all(fd, b"config digest: ");
+        write_hex(fd, config::configuration_digest() as usize, 16);
+        write_all(fd, b"\n\nfatal signal ");
+        write_decimal(fd, signum as usize);
+        write_all(fd, b"\n\nstack (return addresses):\n");
+        // Walking the stack without resolving symbols doesn't allocate
+        backtrace::trace_unsynchronized(|frame| {
+            write_all(fd, b"  ");
+            write_hex(fd, frame.ip() as usize, 0);
+            write_all(fd, b"\n");
+            true
+        });
+
+        #[cfg(target_os = "linux")]
+        {
+            write_all(fd, b"\nmemory map:\n");
+            let maps = libc::open(
+                b"/proc/self/maps\0".as_ptr() as *const libc::c_char,
+                libc::O_RDONLY | libc::O_CLOEXEC,
+            );
+            if maps >= 0 {
+                let mut buf = [0u8; 4096];
+                loop {
+                    let len = libc::read(maps, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+                    if len <= 0 {
+                        break;
+                    }
+                    write_all(fd, &buf[..len as usize]);
+                }
+                libc::close(maps);
+            }
+        }
+    }
+
+    unsafe fn write_all(fd: libc::c_int, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let len = libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len());
+            if len <= 0 {
+                return;
+            }
+            bytes = &bytes[len as usize..];
+        }
+    }
+
+    /// Writes `value` in hex, padded with zeroes to at least `width` digits
+    unsafe fn write_hex(fd: libc::c_int, mut value: usize, width: usize) {
+        let mut buf = [b'0'; 18];
+        let mut pos = buf.len();
+        while value != 0 || buf.len() - pos < width.max(1) {
+            pos -= 1;
+            buf[pos] = b"0123456789abcdef"[value & 0xf];
+            value >>= 4;
+        }
+        if width == 0 {
+            pos -= 2;
+            buf[pos..pos + 2].copy_from_slice(b"0x");
+        }
+        write_all(fd, &buf[pos..]);
+    }
+
+    unsafe fn write_decimal(fd: libc::c_int, mut value: usize) {
+        let mut buf = [b'0'; 20];
+        let mut pos = buf.len();
+        loop {
+            pos -= 1;
+            buf[pos] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        write_all(fd, &buf[pos..]);
+    }
+}
@@ -1,5 +1,10 @@
 use std::path::{Path, PathBuf};
 
+pub mod crash;
+pub mod logging;
+
+pub use logging::setup_logger;
+
 pub fn set_wezterm_executable() {
     if let Ok(exe) = std::env::current_exe() {
         if let Some(dir) = exe.parent() {
@@ -9,16 +14,6 @@ pub fn set_wezterm_executable() {
     }
 }
 
-pub fn setup_logger() {
-    let mut builder = pretty_env_logger::formatted_timed_builder();
-    if let Ok(s) = std::env::var("WEZTERM_LOG") {
-        builder.parse_filters(&s);
-    } else {
-        builder.filter(None, log::LevelFilter::Info);
-    }
-    builder.init();
-}
-
 pub fn fixup_appimage() {
     if let Some(appimage) = std::env::var_os("APPIMAGE") {
         let appimage = std::path::PathBuf::from(appimage);
@@ -130,4 +125,6 @@ pub fn bootstrap() {
     fixup_appimage();
 
     setup_logger();
+
+    crash::install_crash_handler();
 }
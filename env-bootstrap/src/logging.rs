@@ -0,0 +1,167 @@
+//! The logger used by all of the wezterm executables.
+//!
+//! It formats records the same way as pretty_env_logger, but its filter
+//! can be replaced while the process is running (eg: by
+//! `wezterm cli set-log-level mux=debug`) and it keeps the most recent
+//! records in memory so that they can be viewed from within the GUI.
+use anyhow::bail;
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+/// How many of the most recent records to keep in memory
+const MAX_ENTRIES: usize = 4096;
+
+/// The filter used when WEZTERM_LOG is not set
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub when: SystemTime,
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+}
+
+struct FilterState {
+    spec: String,
+    filter: Filter,
+}
+
+lazy_static! {
+    static ref FILTER: RwLock<FilterState> = RwLock::new(FilterState {
+        spec: DEFAULT_FILTER.to_string(),
+        filter: FilterBuilder::new().parse(DEFAULT_FILTER).build(),
+    });
+    static ref ENTRIES: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+}
+
+struct Logger {
+    /// Does the formatting and output; it is configured to accept
+    /// everything as we do the filtering ourselves
+    inner: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FILTER.read().unwrap().filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !FILTER.read().unwrap().filter.matches(record) {
+            return;
+        }
+
+        {
+            let mut entries = ENTRIES.lock().unwrap();
+            if entries.len() == MAX_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(LogEntry {
+                when: SystemTime::now(),
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Checks that each directive in `spec` is of the form accepted by
+/// env_logger: `LEVEL`, `MODULE` or `MODULE=LEVEL`, optionally followed
+/// by `/REGEX` to filter on the message.  env_logger itself silently
+/// ignores directives that it doesn't understand.
+fn validate_filter(spec: &str) -> anyhow::Result<()> {
+    let directives = spec.splitn(2, '/').next().unwrap_or("");
+    for directive in directives.split(',').map(str::trim) {
+        if directive.is_empty() {
+            continue;
+        }
+        let mut parts = directive.splitn(2, '=');
+        let module = parts.next().unwrap_or("");
+        match parts.next() {
+            Some(level) => {
+                if module.is_empty() || level.parse::<LevelFilter>().is_err() {
+                    bail!("invalid log filter directive `{}`", directive);
+                }
+            }
+            None => {
+                if module.contains(char::is_whitespace) {
+                    bail!("invalid log filter directive `{}`", directive);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the filter that decides which records are logged.
+/// `spec` uses the same syntax as the WEZTERM_LOG environment variable,
+/// such as `info,mux=debug`.
+pub fn set_log_filter(spec: &str) -> anyhow::Result<()> {
+    validate_filter(spec)?;
+    let filter = FilterBuilder::new().parse(spec).build();
+    let max_level = filter.filter();
+    {
+        let mut state = FILTER.write().unwrap();
+        state.spec = spec.to_string();
+        state.filter = filter;
+    }
+    log::set_max_level(max_level);
+    log::info!("log filter is now `{}`", spec);
+    Ok(())
+}
+
+/// Returns the filter that is currently in effect
+pub fn log_filter() -> String {
+    FILTER.read().unwrap().spec.clone()
+}
+
+/// Returns a copy of the most recently logged records, oldest first
+pub fn recent_log_entries() -> Vec<LogEntry> {
+    ENTRIES.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn setup_logger() {
+    let inner = pretty_env_logger::formatted_timed_builder()
+        .filter(None, LevelFilter::Trace)
+        .build();
+
+    let spec = std::env::var("WEZTERM_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+    {
+        let mut state = FILTER.write().unwrap();
+        state.filter = FilterBuilder::new().parse(&spec).build();
+        state.spec = spec;
+    }
+    let max_level = FILTER.read().unwrap().filter.filter();
+
+    if log::set_boxed_logger(Box::new(Logger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        assert!(validate_filter("info").is_ok());
+        assert!(validate_filter("info,mux=debug").is_ok());
+        assert!(validate_filter("mux").is_ok());
+        assert!(validate_filter("mux=trace,wezterm_gui::gui=off/paint").is_ok());
+        assert!(validate_filter("").is_ok());
+        assert!(validate_filter("mux=loud").is_err());
+        assert!(validate_filter("=debug").is_err());
+        assert!(validate_filter("mux debug").is_err());
+    }
+}
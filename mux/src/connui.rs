@@ -1,14 +1,18 @@
+use crate::ssh::AuthPrompt;
 use crate::termwiztermtab;
 use anyhow::{anyhow, bail, Context as _};
+use config::i18n::{tr, tr_args};
 use crossbeam::channel::{bounded, Receiver, Sender};
 use portable_pty::PtySize;
 use promise::spawn::block_on;
 use promise::Promise;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
-use termwiz::cell::{unicode_column_width, CellAttributes};
+use termwiz::cell::{unicode_column_width, AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
 use termwiz::lineedit::*;
-use termwiz::surface::{Change, Position};
+use termwiz::surface::{Change, CursorVisibility, Position};
 use termwiz::terminal::*;
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -34,6 +38,11 @@ impl LineEditorHost for PasswordPromptHost {
     }
 }
 
+/// Obscures a secret response in the same way as `PasswordPromptHost`
+fn mask_response(response: &str) -> String {
+    "🔑".repeat(unicode_column_width(response))
+}
+
 pub enum UIRequest {
     /// Display something
     Output(Vec<Change>),
@@ -43,6 +52,14 @@ pub enum UIRequest {
         echo: bool,
         respond: Promise<String>,
     },
+    /// Request the responses to a keyboard-interactive
+    /// authentication request, all together in one form
+    AuthForm {
+        instructions: String,
+        prompts: Vec<AuthPrompt>,
+        answers: Vec<String>,
+        respond: Promise<Vec<String>>,
+    },
     /// Sleep with a progress bar
     Sleep {
         reason: String,
@@ -83,6 +100,14 @@ impl ConnectionUIImpl {
                 }) => {
                     respond.result(self.password_prompt(&prompt));
                 }
+                Ok(UIRequest::AuthForm {
+                    instructions,
+                    prompts,
+                    answers,
+                    mut respond,
+                }) => {
+                    respond.result(self.auth_form(&instructions, &prompts, answers));
+                }
                 Ok(UIRequest::Sleep {
                     reason,
                     duration,
@@ -120,6 +145,142 @@ impl ConnectionUIImpl {
         }
     }
 
+    fn render_auth_form(
+        &mut self,
+        instructions: &str,
+        prompts: &[AuthPrompt],
+        answers: &[String],
+        current: usize,
+    ) -> anyhow::Result<()> {
+        let width = self.term.get_screen_size().map(|s| s.cols).unwrap_or(80);
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorVisibility(CursorVisibility::Hidden),
+        ];
+        let mut row = 0;
+        let mut cursor = (0, 0);
+
+        let text_line = |changes: &mut Vec<Change>, row: &mut usize, text: &str| {
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(*row),
+            });
+            changes.push(Change::Text(text.to_string()));
+            *row += 1;
+        };
+
+        if !instructions.is_empty() {
+            for line in textwrap::fill(instructions, width.saturating_sub(1).max(1)).lines() {
+                text_line(&mut changes, &mut row, line);
+            }
+            row += 1;
+        }
+
+        for (idx, (prompt, answer)) in prompts.iter().zip(answers.iter()).enumerate() {
+            // The response goes after the last line of the prompt
+            let mut lines: Vec<&str> = prompt.prompt.trim_end_matches('\n').lines().collect();
+            let last = lines.pop().unwrap_or("");
+            for line in lines {
+                text_line(&mut changes, &mut row, line);
+            }
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(row),
+            });
+            changes.push(Change::Text(last.to_string()));
+
+            let response = if prompt.echo {
+                answer.clone()
+            } else {
+                mask_response(answer)
+            };
+            let x = unicode_column_width(last) + unicode_column_width(&response);
+            if idx == current {
+                changes.push(AttributeChange::Reverse(true).into());
+                changes.push(Change::Text(response));
+                changes.push(AttributeChange::Reverse(false).into());
+                cursor = (x, row);
+            } else {
+                changes.push(Change::Text(response));
+            }
+            row += 1;
+        }
+
+        row += 1;
+        text_line(&mut changes, &mut row, &tr("auth-form-help"));
+
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(cursor.0),
+            y: Position::Absolute(cursor.1),
+        });
+        changes.push(Change::CursorVisibility(CursorVisibility::Visible));
+        self.term.render(&changes)?;
+        Ok(())
+    }
+
+    /// Shows the prompts of a keyboard-interactive authentication
+    /// request together, starting with the first that has no response
+    /// in `answers`, and returns the responses once they are submitted
+    fn auth_form(
+        &mut self,
+        instructions: &str,
+        prompts: &[AuthPrompt],
+        mut answers: Vec<String>,
+    ) -> anyhow::Result<Vec<String>> {
+        answers.resize(prompts.len(), String::new());
+        if prompts.is_empty() {
+            return Ok(answers);
+        }
+        let count = prompts.len();
+        let mut current = answers
+            .iter()
+            .position(|answer| answer.is_empty())
+            .unwrap_or(0);
+
+        let result = loop {
+            self.render_auth_form(instructions, prompts, &answers, current)?;
+            match self.term.poll_input(None)? {
+                Some(InputEvent::Key(KeyEvent { key, modifiers })) => match (key, modifiers) {
+                    (KeyCode::Escape, _) | (KeyCode::Char('c'), Modifiers::CTRL) => {
+                        break Err(anyhow!("authentication was cancelled"));
+                    }
+                    (KeyCode::Enter, _) => {
+                        if current + 1 < count {
+                            current += 1;
+                        } else {
+                            break Ok(answers);
+                        }
+                    }
+                    (KeyCode::Tab, Modifiers::NONE) | (KeyCode::DownArrow, _) => {
+                        current = (current + 1) % count;
+                    }
+                    (KeyCode::Tab, Modifiers::SHIFT) | (KeyCode::UpArrow, _) => {
+                        current = (current + count - 1) % count;
+                    }
+                    (KeyCode::Backspace, _) => {
+                        answers[current].pop();
+                    }
+                    (KeyCode::Char('u'), Modifiers::CTRL) => answers[current].clear(),
+                    (KeyCode::Char(c), Modifiers::NONE) | (KeyCode::Char(c), Modifiers::SHIFT) => {
+                        answers[current].push(c);
+                    }
+                    _ => {}
+                },
+                Some(InputEvent::Paste(text)) => {
+                    answers[current].push_str(text.trim_end_matches(&['\r', '\n'][..]));
+                }
+                _ => {}
+            }
+        };
+
+        // Leave the window clear for whatever happens next
+        self.term.render(&[
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorVisibility(CursorVisibility::Visible),
+        ])?;
+        result
+    }
+
     fn sleep(&mut self, reason: &str, duration: Duration) -> anyhow::Result<()> {
         let start = Instant::now();
         let deadline = start + duration;
@@ -214,6 +375,9 @@ impl HeadlessImpl {
                 Ok(UIRequest::Input { mut respond, .. }) => {
                     respond.result(Err(anyhow!("Input requested from headless context")));
                 }
+                Ok(UIRequest::AuthForm { mut respond, .. }) => {
+                    respond.result(Err(anyhow!("Input requested from headless context")));
+                }
                 Ok(UIRequest::Sleep {
                     mut respond,
                     reason,
@@ -253,11 +417,8 @@ impl ConnectionUI {
             });
 
             if enable_close_delay && status == CloseStatus::Implicit {
-                ui.sleep(
-                    "(this window will close automatically)",
-                    Duration::new(120, 0),
-                )
-                .ok();
+                ui.sleep(&tr("error-window-will-close"), Duration::new(120, 0))
+                    .ok();
             }
             Ok(())
         }))
@@ -285,7 +446,10 @@ impl ConnectionUI {
     {
         match f() {
             Err(e) => {
-                let what = format!("\r\nFailed: {:?}\r\n", e);
+                let what = format!(
+                    "\r\n{}\r\n",
+                    tr_args("error-failed", &[("error", &format!("{:?}", e))])
+                );
                 log::error!("{}", what);
                 self.output_str(&what);
                 Err(e)
@@ -300,7 +464,10 @@ impl ConnectionUI {
     {
         match f.await {
             Err(e) => {
-                let what = format!("\r\nFailed: {:?}\r\n", e);
+                let what = format!(
+                    "\r\n{}\r\n",
+                    tr_args("error-failed", &[("error", &format!("{:?}", e))])
+                );
                 self.output_str(&what);
                 Err(e)
             }
@@ -393,6 +560,30 @@ impl ConnectionUI {
         block_on(future)
     }
 
+    /// Asks for the responses to a keyboard-interactive authentication
+    /// request in a single form.  `answers` holds the responses to the
+    /// leading prompts if they are already known; they can be edited.
+    pub fn auth_form(
+        &self,
+        instructions: &str,
+        prompts: &[AuthPrompt],
+        answers: Vec<String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut promise = Promise::new();
+        let future = promise.get_future().unwrap();
+
+        self.tx
+            .send(UIRequest::AuthForm {
+                instructions: instructions.to_string(),
+                prompts: prompts.to_vec(),
+                answers,
+                respond: promise,
+            })
+            .context("send to ConnectionUI failed")?;
+
+        block_on(future)
+    }
+
     pub fn close(&self) {
         self.tx.send(UIRequest::Close).ok();
     }
@@ -420,7 +611,7 @@ fn get_error_window() -> ConnectionUI {
     }
 
     let ui = ConnectionUI::new_with_no_close_delay();
-    ui.title("wezterm Configuration Error");
+    ui.title(&tr("error-configuration-title"));
     err.replace(ui.clone());
     ui
 }
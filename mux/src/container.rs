@@ -0,0 +1,398 @@
+//! Domains that spawn panes inside running Docker or Podman
+//! containers by way of `docker exec -it`.
+//! The launcher enumerates the running containers in a similar way
+//! to the WSL distributions on Windows, and registers a domain for
+//! each one that is chosen.
+use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::localpane::LocalPane;
+use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::tab::{SplitDirection, Tab, TabId};
+use crate::window::WindowId;
+use crate::Mux;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use config::configuration;
+use luahelper::impl_lua_conversion;
+use luahelper::mlua;
+use portable_pty::{native_pty_system, CommandBuilder, PtySize, PtySystem};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Describes the container that a pane is being spawned into,
+/// for the `container-shell` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    /// The container runtime, such as `docker` or `podman`
+    pub runtime: String,
+    /// The name of the container
+    pub container: String,
+}
+impl_lua_conversion!(ContainerInfo);
+
+/// Returns the names of the running containers known to `runtime`
+pub fn enumerate_containers(runtime: &str) -> anyhow::Result<Vec<String>> {
+    let output = std::process::Command::new(runtime)
+        .args(&["ps", "--format", "{{.Names}}"])
+        .output()
+        .with_context(|| format!("running {} ps", runtime))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{} ps failed: {}",
+        runtime,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Returns the bind mounts of a container as (host, container) path pairs
+fn container_mounts(runtime: &str, container: &str) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    #[derive(Deserialize)]
+    struct Mount {
+        #[serde(rename = "Source")]
+        source: PathBuf,
+        #[serde(rename = "Destination")]
+        destination: PathBuf,
+    }
+
+    let output = std::process::Command::new(runtime)
+        .args(&["inspect", "--format", "{{json .Mounts}}", container])
+        .output()
+        .with_context(|| format!("running {} inspect {}", runtime, container))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{} inspect {} failed: {}",
+        runtime,
+        container,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mounts: Option<Vec<Mount>> = serde_json::from_slice(&output.stdout)?;
+    Ok(mounts
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| (m.source, m.destination))
+        .collect())
+}
+
+/// Translates a path on the host into the corresponding path in the
+/// container, if it lies within one of the bind `mounts`.
+/// The most specific mount wins.
+fn map_host_path(mounts: &[(PathBuf, PathBuf)], host_path: &Path) -> Option<PathBuf> {
+    mounts
+        .iter()
+        .filter_map(|(source, destination)| {
+            host_path
+                .strip_prefix(source)
+                .ok()
+                .map(|rest| (source.components().count(), destination.join(rest)))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, path)| path)
+}
+
+/// Returns the name of the domain for a container
+pub fn container_domain_name(runtime: &str, container: &str) -> String {
+    format!("{}:{}", runtime, container)
+}
+
+/// Returns the domain for a container, creating and registering it
+/// with the mux if this is the first time that it has been seen.
+/// Must be called on the main thread.
+pub fn get_or_create_container_domain(
+    runtime: &str,
+    container: &str,
+) -> anyhow::Result<Arc<dyn Domain>> {
+    let mux = Mux::get().ok_or_else(|| anyhow!("must be called on the main thread"))?;
+    let name = container_domain_name(runtime, container);
+    if let Some(domain) = mux.get_domain_by_name(&name) {
+        return Ok(domain);
+    }
+    let domain: Arc<dyn Domain> = Arc::new(ContainerDomain::new(runtime, container));
+    mux.add_domain(&domain);
+    Ok(domain)
+}
+
+async fn container_shell_event(
+    lua: Option<Rc<mlua::Lua>>,
+    info: ContainerInfo,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(None),
+    };
+    let args = lua.pack_multi(info)?;
+    let result =
+        config::lua::emit_async_callback(&lua, ("container-shell".to_string(), args)).await?;
+    Ok(lua.unpack(result)?)
+}
+
+pub struct ContainerDomain {
+    pty_system: Box<dyn PtySystem>,
+    id: DomainId,
+    name: String,
+    label: String,
+    info: ContainerInfo,
+    /// The bind mounts of the container, which are used to map the
+    /// working directory of a host pane into the container.
+    /// These are looked up when the first pane is spawned.
+    mounts: RefCell<Option<Rc<Vec<(PathBuf, PathBuf)>>>>,
+}
+
+impl ContainerDomain {
+    pub fn new(runtime: &str, container: &str) -> Self {
+        Self {
+            pty_system: native_pty_system(),
+            id: alloc_domain_id(),
+            name: container_domain_name(runtime, container),
+            label: format!("{} container {}", runtime, container),
+            info: ContainerInfo {
+                runtime: runtime.to_string(),
+                container: container.to_string(),
+            },
+            mounts: RefCell::new(None),
+        }
+    }
+
+    async fn mounts(&self) -> Rc<Vec<(PathBuf, PathBuf)>> {
+        if let Some(mounts) = self.mounts.borrow().as_ref() {
+            return Rc::clone(mounts);
+        }
+        let runtime = self.info.runtime.clone();
+        let container = self.info.container.clone();
+        let mounts = smol::unblock(move || container_mounts(&runtime, &container))
+            .await
+            .unwrap_or_else(|err| {
+                log::warn!("{:#}", err);
+                vec![]
+            });
+        let mounts = Rc::new(mounts);
+        self.mounts.borrow_mut().replace(Rc::clone(&mounts));
+        mounts
+    }
+
+    /// Maps a directory from a pane on the host into the container.
+    /// It only makes sense there if it is bind mounted.
+    async fn map_host_dir(&self, dir: Option<String>) -> Option<PathBuf> {
+        let dir = dir?;
+        map_host_path(&self.mounts().await, Path::new(&dir))
+    }
+
+    /// Computes the `docker exec` command that runs `command`, or a
+    /// shell if that is not specified, inside the container.
+    /// `cwd` is a path that is already meaningful inside the container.
+    async fn build_command(
+        &self,
+        command: Option<CommandBuilder>,
+        cwd: Option<PathBuf>,
+    ) -> anyhow::Result<CommandBuilder> {
+        let program: Vec<OsString> = match command {
+            Some(cmd) if !cmd.is_default_prog() => cmd.get_argv().clone(),
+            _ => {
+                let info = self.info.clone();
+                match config::with_lua_config_on_main_thread(move |lua| {
+                    container_shell_event(lua, info)
+                })
+                .await
+                {
+                    Ok(Some(argv)) if !argv.is_empty() => {
+                        argv.into_iter().map(Into::into).collect()
+                    }
+                    Ok(_) => default_shell(),
+                    Err(err) => {
+                        log::error!("while processing container-shell event: {:#}", err);
+                        default_shell()
+                    }
+                }
+            }
+        };
+
+        let config = configuration();
+        let mut cmd = CommandBuilder::new(&self.info.runtime);
+        cmd.args(&["exec", "-it"]);
+        cmd.arg("-e");
+        cmd.arg(format!("TERM={}", config.term));
+        if let Some(cwd) = cwd {
+            cmd.arg("-w");
+            cmd.arg(cwd);
+        }
+        cmd.arg(&self.info.container);
+        cmd.args(program);
+        Ok(cmd)
+    }
+
+    fn spawn_pane(&self, size: PtySize, mut cmd: CommandBuilder) -> anyhow::Result<Rc<dyn Pane>> {
+        let pair = self.pty_system.openpty(size)?;
+        let pane_id = alloc_pane_id();
+        cmd.env("WEZTERM_PANE", pane_id.to_string());
+        let term_config = config::TermConfig::for_pane(&self.name, &cmd);
+        let child = pair.slave.spawn_command(cmd)?;
+        log::trace!("spawned: {:?}", child);
+
+        let writer = pair.master.try_clone_writer()?;
+
+        let terminal = wezterm_term::Terminal::new(
+            crate::pty_size_to_terminal_size(size),
+            std::sync::Arc::new(term_config),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(writer),
+        );
+
+        Ok(Rc::new(LocalPane::new(
+            pane_id,
+            terminal,
+            child,
+            pair.master,
+            self.id,
+        )))
+    }
+}
+
+/// Runs bash if the container has it, otherwise sh
+fn default_shell() -> Vec<OsString> {
+    vec![
+        "sh".into(),
+        "-c".into(),
+        "command -v bash >/dev/null && exec bash -l || exec sh -l".into(),
+    ]
+}
+
+#[async_trait(?Send)]
+impl Domain for ContainerDomain {
+    async fn spawn(
+        &self,
+        size: PtySize,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
+        window: WindowId,
+    ) -> anyhow::Result<Rc<Tab>> {
+        let cwd = self.map_host_dir(command_dir).await;
+        let cmd = self.build_command(command, cwd).await?;
+        let pane = self.spawn_pane(size, cmd)?;
+
+        let mux = Mux::get().unwrap();
+        let tab = Rc::new(Tab::new(&size));
+        tab.assign_pane(&pane);
+
+        mux.add_tab_and_active_pane(&tab)?;
+        mux.add_tab_to_window(&tab, window)?;
+
+        Ok(tab)
+    }
+
+    async fn split_pane(
+        &self,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
+        tab: TabId,
+        pane_id: PaneId,
+        direction: SplitDirection,
+    ) -> anyhow::Result<Rc<dyn Pane>> {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_tab(tab) {
+            Some(t) => t,
+            None => anyhow::bail!("Invalid tab id {}", tab),
+        };
+
+        let pane_index = match tab
+            .iter_panes()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+        {
+            Some(p) => p.index,
+            None => anyhow::bail!("invalid pane id {}", pane_id),
+        };
+
+        let split_size = match tab.compute_split_size(pane_index, direction) {
+            Some(s) => s,
+            None => anyhow::bail!("invalid pane index {}", pane_index),
+        };
+
+        // When splitting a pane in this container, its directory is
+        // already a path in the container
+        let splitting_own_pane = mux
+            .get_pane(pane_id)
+            .map(|pane| pane.domain_id() == self.id)
+            .unwrap_or(false);
+        let cwd = if splitting_own_pane {
+            command_dir.map(PathBuf::from)
+        } else {
+            self.map_host_dir(command_dir).await
+        };
+
+        let cmd = self.build_command(command, cwd).await?;
+        let pane = self.spawn_pane(split_size.second, cmd)?;
+
+        tab.split_and_insert(pane_index, direction, Rc::clone(&pane))?;
+
+        mux.add_pane(&pane)?;
+
+        Ok(pane)
+    }
+
+    /// The launcher lists the containers that are running at the time
+    /// that it is opened, so we don't want it to also offer the domains
+    /// of containers that may since have been stopped
+    fn spawnable(&self) -> bool {
+        false
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.id
+    }
+
+    fn domain_name(&self) -> &str {
+        &self.name
+    }
+
+    fn domain_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn attach(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn detach(&self) -> anyhow::Result<()> {
+        anyhow::bail!("detach not implemented");
+    }
+
+    fn state(&self) -> DomainState {
+        DomainState::Attached
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_paths_map_through_mounts() {
+        let mounts = vec![
+            (PathBuf::from("/home/me"), PathBuf::from("/root")),
+            (
+                PathBuf::from("/home/me/src/proj"),
+                PathBuf::from("/workspace"),
+            ),
+        ];
+
+        assert_eq!(
+            map_host_path(&mounts, Path::new("/home/me/src/proj/lib")),
+            Some(PathBuf::from("/workspace/lib"))
+        );
+        assert_eq!(
+            map_host_path(&mounts, Path::new("/home/me/notes")),
+            Some(PathBuf::from("/root/notes"))
+        );
+        assert_eq!(map_host_path(&mounts, Path::new("/home/mel")), None);
+        assert_eq!(map_host_path(&mounts, Path::new("/etc")), None);
+    }
+}
@@ -133,6 +133,7 @@ impl Domain for LocalDomain {
         let pane_id = alloc_pane_id();
         cmd.env("WEZTERM_PANE", pane_id.to_string());
 
+        let term_config = config::TermConfig::for_pane(&self.name, &cmd);
         let child = pair.slave.spawn_command(cmd)?;
         log::trace!("spawned: {:?}", child);
 
@@ -140,7 +141,7 @@ impl Domain for LocalDomain {
 
         let terminal = wezterm_term::Terminal::new(
             crate::pty_size_to_terminal_size(size),
-            std::sync::Arc::new(config::TermConfig {}),
+            std::sync::Arc::new(term_config),
             "WezTerm",
             config::wezterm_version(),
             Box::new(writer),
@@ -212,6 +213,7 @@ impl Domain for LocalDomain {
         let pair = self.pty_system.openpty(split_size.second)?;
         let pane_id = alloc_pane_id();
         cmd.env("WEZTERM_PANE", pane_id.to_string());
+        let term_config = config::TermConfig::for_pane(&self.name, &cmd);
         let child = pair.slave.spawn_command(cmd)?;
         log::trace!("spawned: {:?}", child);
 
@@ -219,7 +221,7 @@ impl Domain for LocalDomain {
 
         let terminal = wezterm_term::Terminal::new(
             crate::pty_size_to_terminal_size(split_size.second),
-            std::sync::Arc::new(config::TermConfig {}),
+            std::sync::Arc::new(term_config),
             "WezTerm",
             config::wezterm_version(),
             Box::new(writer),
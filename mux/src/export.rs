@@ -0,0 +1,524 @@
+//! Exports the scrollback of a pane as plain text, as text with escape
+//! sequences, as HTML or as an asciicast recording.  This is used by
+//! the gui, and by the mux server to capture panes for `wezterm cli`.
+use crate::pane::{Pane, PaneId};
+use anyhow::Context;
+use config::keyassignment::ScrollbackExportFormat;
+use config::LineTimestampFormat;
+use std::fmt::Write;
+use std::path::PathBuf;
+use termwiz::cell::{Blink, CellAttributes, Intensity, SemanticType, Underline};
+use termwiz::color::ColorAttribute;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{Line, StableRowIndex};
+
+/// Returns all of the lines in the scrollback and viewport of the pane,
+/// omitting any blank lines at the bottom
+fn scrollback_lines(pane: &dyn Pane) -> Vec<Line> {
+    let dims = pane.get_dimensions();
+    let top = dims.scrollback_top;
+    let (_, lines) = pane.get_lines(top..top + dims.scrollback_rows as isize);
+    without_trailing_blank_lines(lines)
+}
+
+/// Returns the lines in the viewport of the pane, omitting any blank
+/// lines at the bottom
+fn viewport_lines(pane: &dyn Pane) -> Vec<Line> {
+    let dims = pane.get_dimensions();
+    let top = dims.physical_top;
+    let (_, lines) = pane.get_lines(top..top + dims.viewport_rows as isize);
+    without_trailing_blank_lines(lines)
+}
+
+fn without_trailing_blank_lines(mut lines: Vec<Line>) -> Vec<Line> {
+    while lines
+        .last()
+        .map(|line| line.as_str().trim().is_empty())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+    lines
+}
+
+/// Groups the visible cells of a line into runs of text that share
+/// the same attributes
+fn runs(line: &Line) -> Vec<(CellAttributes, String)> {
+    let mut runs: Vec<(CellAttributes, String)> = vec![];
+    for (_, cell) in line.visible_cells() {
+        match runs.last_mut() {
+            Some((attrs, text)) if attrs == cell.attrs() => text.push_str(cell.str()),
+            _ => runs.push((cell.attrs().clone(), cell.str().to_string())),
+        }
+    }
+    // Trailing whitespace with default attributes is not interesting
+    if let Some((attrs, text)) = runs.last_mut() {
+        if attrs.background == ColorAttribute::Default && !attrs.reverse() {
+            let len = text.trim_end().len();
+            text.truncate(len);
+        }
+    }
+    runs
+}
+
+fn sgr_color(out: &mut String, color: ColorAttribute, base: u8, bright_base: u8, extended: u8) {
+    match color {
+        ColorAttribute::Default => {}
+        ColorAttribute::PaletteIndex(idx) if idx < 8 => {
+            write!(out, ";{}", base + idx).ok();
+        }
+        ColorAttribute::PaletteIndex(idx) if idx < 16 => {
+            write!(out, ";{}", bright_base + idx - 8).ok();
+        }
+        ColorAttribute::PaletteIndex(idx) => {
+            write!(out, ";{};5;{}", extended, idx).ok();
+        }
+        ColorAttribute::TrueColorWithPaletteFallback(c, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(c) => {
+            write!(out, ";{};2;{};{};{}", extended, c.red, c.green, c.blue).ok();
+        }
+    }
+}
+
+/// Returns the SGR sequence that resets the attributes and then
+/// sets them to `attrs`
+fn sgr(attrs: &CellAttributes) -> String {
+    let mut out = "\x1b[0".to_string();
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => out.push_str(";1"),
+        Intensity::Half => out.push_str(";2"),
+    }
+    if attrs.italic() {
+        out.push_str(";3");
+    }
+    match attrs.underline() {
+        Underline::None => {}
+        Underline::Double => out.push_str(";21"),
+        _ => out.push_str(";4"),
+    }
+    if attrs.blink() != Blink::None {
+        out.push_str(";5");
+    }
+    if attrs.reverse() {
+        out.push_str(";7");
+    }
+    if attrs.invisible() {
+        out.push_str(";8");
+    }
+    if attrs.strikethrough() {
+        out.push_str(";9");
+    }
+    sgr_color(&mut out, attrs.foreground, 30, 90, 38);
+    sgr_color(&mut out, attrs.background, 40, 100, 48);
+    out.push('m');
+    out
+}
+
+/// Renders the lines as text with SGR and OSC 8 hyperlink escape
+/// sequences, separating lines with `eol`
+fn lines_with_escapes(lines: &[Line], eol: &str) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let mut link: Option<String> = None;
+        for (attrs, text) in runs(line) {
+            let this_link = attrs.hyperlink().map(|link| link.uri().to_string());
+            if this_link != link {
+                write!(out, "\x1b]8;;{}\x1b\\", this_link.as_deref().unwrap_or("")).ok();
+                link = this_link;
+            }
+            out.push_str(&sgr(&attrs));
+            out.push_str(&text);
+        }
+        if link.is_some() {
+            out.push_str("\x1b]8;;\x1b\\");
+        }
+        out.push_str("\x1b[0m");
+        out.push_str(eol);
+    }
+    out
+}
+
+fn lines_as_text(lines: &[Line]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(line.as_str().trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn html_style(attrs: &CellAttributes, palette: &ColorPalette) -> String {
+    let mut fg = palette.resolve_fg(attrs.foreground);
+    let mut bg = palette.resolve_bg(attrs.background);
+    if attrs.reverse() {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.invisible() {
+        fg = bg;
+    }
+
+    let mut style = String::new();
+    if fg != palette.foreground || attrs.reverse() || attrs.invisible() {
+        write!(style, "color:{};", fg.to_rgb_string()).ok();
+    }
+    if bg != palette.background || attrs.reverse() {
+        write!(style, "background-color:{};", bg.to_rgb_string()).ok();
+    }
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => style.push_str("font-weight:bold;"),
+        Intensity::Half => style.push_str("opacity:0.5;"),
+    }
+    if attrs.italic() {
+        style.push_str("font-style:italic;");
+    }
+    let underline = attrs.underline() != Underline::None;
+    match (underline, attrs.strikethrough()) {
+        (true, true) => style.push_str("text-decoration:underline line-through;"),
+        (true, false) => style.push_str("text-decoration:underline;"),
+        (false, true) => style.push_str("text-decoration:line-through;"),
+        (false, false) => {}
+    }
+    style
+}
+
+fn lines_as_html(lines: &[Line], palette: &ColorPalette, title: &str) -> String {
+    let mut out = String::new();
+    write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+         <body style=\"background-color:{bg};\">\n\
+         <pre style=\"color:{fg};background-color:{bg};font-family:monospace;\">",
+        html_escape(title),
+        fg = palette.foreground.to_rgb_string(),
+        bg = palette.background.to_rgb_string(),
+    )
+    .ok();
+
+    for line in lines {
+        for (attrs, text) in runs(line) {
+            let text = html_escape(&text);
+            let style = html_style(&attrs, palette);
+            let text = if style.is_empty() {
+                text
+            } else {
+                format!("<span style=\"{}\">{}</span>", style, text)
+            };
+            match attrs.hyperlink() {
+                Some(link) => {
+                    write!(out, "<a href=\"{}\">{}</a>", html_escape(link.uri()), text).ok();
+                }
+                None => out.push_str(&text),
+            }
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
+
+/// Produces an asciicast v2 recording that shows the scrollback
+/// as a single frame, for playback with asciinema
+fn lines_as_asciicast(lines: &[Line], pane: &dyn Pane) -> String {
+    let dims = pane.get_dimensions();
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let header = serde_json::json!({
+        "version": 2,
+        "width": dims.cols,
+        "height": dims.viewport_rows,
+        "timestamp": timestamp,
+        "title": pane.get_title(),
+    });
+    let output = lines_with_escapes(lines, "\r\n");
+    let event = serde_json::json!([0.0, "o", output]);
+    format!("{}\n{}\n", header, event)
+}
+
+/// Returns the current time in milliseconds since the unix epoch
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn local_time(timestamp: u64) -> chrono::DateTime<chrono::Local> {
+    use chrono::TimeZone;
+    chrono::Local.timestamp_millis(timestamp as i64)
+}
+
+fn format_elapsed(secs: u64) -> String {
+    let days = secs / 86400;
+    let hours = secs / 3600 % 24;
+    let mins = secs / 60 % 60;
+    let secs = secs % 60;
+    if days > 0 {
+        format!("{}d{:02}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{:02}m", hours, mins)
+    } else if mins > 0 {
+        format!("{}m{:02}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Formats the time at which a line was first written, in milliseconds
+/// since the unix epoch, for display alongside the line.
+/// Relative times are computed with respect to `now`.
+pub fn format_line_timestamp(timestamp: u64, format: LineTimestampFormat, now: u64) -> String {
+    match format {
+        LineTimestampFormat::Relative => format_elapsed(now.saturating_sub(timestamp) / 1000),
+        LineTimestampFormat::Absolute => local_time(timestamp).format("%H:%M:%S").to_string(),
+    }
+}
+
+/// Returns the text that follows a line into which `count` identical
+/// lines were folded, showing how many times it appeared
+pub fn repeat_marker(count: u32) -> String {
+    format!(" \u{d7}{}", u64::from(count) + 1)
+}
+
+/// Returns the number of cells up to the end of the last non-blank
+/// cell of the line
+pub fn text_width(line: &Line) -> usize {
+    line.cells()
+        .iter()
+        .rposition(|cell| cell.str() != " ")
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
+
+/// Appends the repeat count to the lines into which identical lines
+/// were folded by the scrollback_dedup option
+fn with_repeat_markers(lines: Vec<Line>) -> Vec<Line> {
+    lines
+        .into_iter()
+        .map(|mut line| {
+            let count = line.repeat_count();
+            if count > 0 {
+                line.resize(text_width(&line));
+                line.append_line(Line::from_text(
+                    &repeat_marker(count),
+                    &CellAttributes::default(),
+                ));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Prefixes each line with the local date and time at which it was
+/// first written, or with blanks for lines that were never written
+fn with_timestamps(lines: Vec<Line>) -> Vec<Line> {
+    lines
+        .into_iter()
+        .map(|line| {
+            let stamp = match line.timestamp() {
+                Some(timestamp) => {
+                    format!("[{}] ", local_time(timestamp).format("%Y-%m-%d %H:%M:%S"))
+                }
+                None => " ".repeat(22),
+            };
+            let mut prefixed = Line::from_text(&stamp, &CellAttributes::default());
+            prefixed.append_line(line);
+            prefixed
+        })
+        .collect()
+}
+
+/// Exports the scrollback of the pane in the specified format,
+/// optionally prefixing each line with the time that it was written
+pub fn export_scrollback(
+    pane: &dyn Pane,
+    format: ScrollbackExportFormat,
+    timestamps: bool,
+) -> String {
+    let mut lines = with_repeat_markers(scrollback_lines(pane));
+    if timestamps {
+        lines = with_timestamps(lines);
+    }
+    render(pane, &lines, format)
+}
+
+/// Exports the lines in the viewport of the pane in the specified format
+pub fn export_viewport(pane: &dyn Pane, format: ScrollbackExportFormat) -> String {
+    let lines = with_repeat_markers(viewport_lines(pane));
+    render(pane, &lines, format)
+}
+
+fn render(pane: &dyn Pane, lines: &[Line], format: ScrollbackExportFormat) -> String {
+    match format {
+        ScrollbackExportFormat::Text => lines_as_text(lines),
+        ScrollbackExportFormat::Escapes => lines_with_escapes(lines, "\n"),
+        ScrollbackExportFormat::Html => lines_as_html(lines, &pane.palette(), &pane.get_title()),
+        ScrollbackExportFormat::Asciicast => lines_as_asciicast(lines, pane),
+    }
+}
+
+/// Returns the text of the output of the most recent command that
+/// produced any, as delimited using OSC 133 semantic zones
+pub fn last_command_output(pane: &dyn Pane) -> Option<String> {
+    let zones = pane.get_semantic_zones().ok()?;
+    for zone in zones
+        .iter()
+        .rev()
+        .filter(|zone| zone.semantic_type == SemanticType::Output)
+    {
+        let (first_row, lines) = pane.get_lines(zone.start_y..zone.end_y + 1);
+        let mut text = String::new();
+        let mut last_was_wrapped = false;
+        for (idx, line) in lines.iter().enumerate() {
+            let row = first_row + idx as StableRowIndex;
+            let start = if row == zone.start_y { zone.start_x } else { 0 };
+            let end = if row == zone.end_y {
+                zone.end_x + 1
+            } else {
+                usize::max_value()
+            };
+            if idx > 0 && !last_was_wrapped {
+                text.push('\n');
+            }
+            text.push_str(line.columns_as_str(start..end).trim_end());
+            last_was_wrapped = line
+                .cells()
+                .last()
+                .map(|cell| cell.attrs().wrapped())
+                .unwrap_or(false);
+        }
+        if !text.trim().is_empty() {
+            text.push('\n');
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// Writes `text` to a new file, readable only by the current user,
+/// in the temporary directory so that it can be opened in an editor
+pub fn write_temp_file(pane_id: PaneId, text: &str) -> anyhow::Result<PathBuf> {
+    use std::io::Write;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path =
+        std::env::temp_dir().join(format!("wezterm-scrollback-{}-{}.txt", pane_id, timestamp));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    file.write_all(text.as_bytes())
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+/// Returns the command line of the editor that EditScrollbackInEditor
+/// should use; the `scrollback_editor` option, then $VISUAL or $EDITOR,
+/// falling back to a reasonable default for the system
+pub fn editor_command() -> Vec<String> {
+    if let Some(editor) = config::configuration().scrollback_editor.as_ref() {
+        if !editor.is_empty() {
+            return editor.clone();
+        }
+    }
+    for var in &["VISUAL", "EDITOR"] {
+        if let Ok(editor) = std::env::var(var) {
+            let args: Vec<String> = editor.split_whitespace().map(str::to_string).collect();
+            if !args.is_empty() {
+                return args;
+            }
+        }
+    }
+    if cfg!(windows) {
+        vec!["notepad.exe".to_string()]
+    } else {
+        vec!["vi".to_string()]
+    }
+}
+
+/// Returns the path of a new file in the home directory to which the
+/// scrollback of the pane can be exported
+pub fn default_path(pane_id: PaneId, format: ScrollbackExportFormat) -> PathBuf {
+    let extension = match format {
+        ScrollbackExportFormat::Text => "txt",
+        ScrollbackExportFormat::Escapes => "ansi",
+        ScrollbackExportFormat::Html => "html",
+        ScrollbackExportFormat::Asciicast => "cast",
+    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    config::HOME_DIR.join(format!(
+        "wezterm-scrollback-{}-{}.{}",
+        pane_id, timestamp, extension
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::color::AnsiColor;
+
+    #[test]
+    fn sgr_sequences() {
+        assert_eq!(sgr(&CellAttributes::default()), "\x1b[0m");
+        let attrs = CellAttributes::default()
+            .set_intensity(Intensity::Bold)
+            .set_foreground(AnsiColor::Maroon)
+            .set_background(AnsiColor::Blue)
+            .clone();
+        assert_eq!(sgr(&attrs), "\x1b[0;1;31;104m");
+    }
+
+    #[test]
+    fn elapsed() {
+        assert_eq!(format_elapsed(7), "7s");
+        assert_eq!(format_elapsed(5 * 60 + 3), "5m03s");
+        assert_eq!(format_elapsed(2 * 3600 + 15 * 60 + 59), "2h15m");
+        assert_eq!(format_elapsed(3 * 86400 + 4 * 3600), "3d04h");
+        assert_eq!(
+            format_line_timestamp(10_000, LineTimestampFormat::Relative, 75_500),
+            "1m05s"
+        );
+    }
+
+    #[test]
+    fn repeated_lines() {
+        let mut line = Line::from_text("hello   ", &CellAttributes::default());
+        line.set_repeat_count(2);
+        let lines = with_repeat_markers(vec![line]);
+        assert_eq!(lines_as_text(&lines), "hello \u{d7}3\n");
+    }
+
+    #[test]
+    fn escape_html() {
+        assert_eq!(html_escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
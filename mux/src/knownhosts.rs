@@ -0,0 +1,299 @@
+//! Helpers for verifying and managing the ssh host keys recorded
+//! in the user's `known_hosts` file.
+use anyhow::{anyhow, bail, Context};
+use luahelper::impl_lua_conversion;
+use luahelper::mlua;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Returns the path to the user's `~/.ssh/known_hosts` file
+pub fn known_hosts_path() -> anyhow::Result<PathBuf> {
+    let varname = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    let var = std::env::var_os(varname)
+        .ok_or_else(|| anyhow!("environment variable {} is missing", varname))?;
+    Ok(PathBuf::from(var).join(".ssh").join("known_hosts"))
+}
+
+/// Extracts the key type (eg: `ssh-ed25519`) from the wire format
+/// of a public key
+pub fn public_key_type(blob: &[u8]) -> Option<&str> {
+    if blob.len() < 4 {
+        return None;
+    }
+    let len = u32::from_be_bytes([blob[0], blob[1], blob[2], blob[3]]) as usize;
+    blob.get(4..4 + len)
+        .and_then(|key_type| std::str::from_utf8(key_type).ok())
+}
+
+/// Returns the short form of a key type that ssh-keygen uses
+/// when describing a key, eg: `ED25519` for `ssh-ed25519`
+pub fn short_key_type(key_type: &str) -> String {
+    if let Some(curve) = key_type.strip_prefix("ecdsa-sha2-nistp") {
+        format!("ECDSA {}", curve)
+    } else {
+        key_type
+            .strip_prefix("ssh-")
+            .unwrap_or(key_type)
+            .to_ascii_uppercase()
+    }
+}
+
+/// Computes the `SHA256:...` fingerprint of a public key blob,
+/// in the same format as `ssh-keygen -l`
+pub fn sha256_fingerprint(blob: &[u8]) -> String {
+    let hash = Sha256::digest(blob);
+    format!(
+        "SHA256:{}",
+        base64::encode_config(
+            hash,
+            base64::Config::new(base64::CharacterSet::Standard, false)
+        )
+    )
+}
+
+/// Renders the "randomart" visualization of a key fingerprint,
+/// using the same "drunken bishop" algorithm as OpenSSH, so that
+/// the result can be compared against the output of `ssh-keygen -lv`.
+pub fn randomart(title: &str, hash_name: &str, hash: &[u8]) -> String {
+    const WIDTH: usize = 17;
+    const HEIGHT: usize = 9;
+    const SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^SE";
+    let len = SYMBOLS.len() - 1;
+
+    let mut field = [[0usize; HEIGHT]; WIDTH];
+    let mut x = WIDTH / 2;
+    let mut y = HEIGHT / 2;
+
+    for byte in hash {
+        let mut input = *byte;
+        for _ in 0..4 {
+            x = if input & 0x1 != 0 {
+                (x + 1).min(WIDTH - 1)
+            } else {
+                x.saturating_sub(1)
+            };
+            y = if input & 0x2 != 0 {
+                (y + 1).min(HEIGHT - 1)
+            } else {
+                y.saturating_sub(1)
+            };
+            if field[x][y] < len - 2 {
+                field[x][y] += 1;
+            }
+            input >>= 2;
+        }
+    }
+
+    field[WIDTH / 2][HEIGHT / 2] = len - 1;
+    field[x][y] = len;
+
+    fn border(label: &str) -> String {
+        let label: String = label.chars().take(WIDTH).collect();
+        let pad = (WIDTH - label.len()) / 2;
+        format!(
+            "+{}{}{}+\n",
+            "-".repeat(pad),
+            label,
+            "-".repeat(WIDTH - pad - label.len())
+        )
+    }
+
+    let mut result = border(&format!("[{}]", title));
+    for y in 0..HEIGHT {
+        result.push('|');
+        for x in 0..WIDTH {
+            result.push(SYMBOLS[field[x][y].min(len)] as char);
+        }
+        result.push_str("|\n");
+    }
+    result.push_str(&border(&format!("[{}]", hash_name)));
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostKeyStatus {
+    /// There is no entry for the host in the known_hosts file
+    Unknown,
+    /// The known_hosts file has a different key for the host
+    Changed,
+}
+
+/// Describes a host key that needs to be verified by the user,
+/// or by their `ssh-host-key` event handler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostKeyInfo {
+    pub host: String,
+    pub port: u16,
+    pub key_type: String,
+    pub fingerprint: String,
+    pub status: HostKeyStatus,
+}
+impl_lua_conversion!(HostKeyInfo);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyDecision {
+    /// Trust the key for this connection only
+    AcceptOnce,
+    /// Trust the key and record it in the known_hosts file,
+    /// replacing any prior key for the host
+    AcceptPermanently,
+    Reject,
+}
+
+impl FromStr for HostKeyDecision {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "accept-once" => Ok(Self::AcceptOnce),
+            "accept-permanently" => Ok(Self::AcceptPermanently),
+            "reject" => Ok(Self::Reject),
+            _ => bail!(
+                "invalid host key decision {}; expected one of \
+                 accept-once, accept-permanently or reject",
+                s
+            ),
+        }
+    }
+}
+
+async fn host_key_event(
+    lua: Option<Rc<mlua::Lua>>,
+    info: HostKeyInfo,
+) -> anyhow::Result<Option<HostKeyDecision>> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(None),
+    };
+    let args = lua.pack_multi(info)?;
+    match config::lua::emit_async_callback(&lua, ("ssh-host-key".to_string(), args)).await? {
+        mlua::Value::String(decision) => Ok(Some(decision.to_str()?.parse()?)),
+        _ => Ok(None),
+    }
+}
+
+/// Gives the `ssh-host-key` event handler, if any, the opportunity
+/// to decide whether to trust a host key.
+/// Returns None if the user should be asked instead.
+/// This must not be called on the main thread, as it blocks waiting
+/// for the handler to run there.
+pub fn host_key_policy(info: &HostKeyInfo) -> Option<HostKeyDecision> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let info = info.clone();
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            let result =
+                config::with_lua_config_on_main_thread(move |lua| host_key_event(lua, info)).await;
+            tx.send(result).ok();
+        })
+        .detach();
+    })
+    .detach();
+
+    match rx.recv() {
+        Ok(Ok(decision)) => decision,
+        Ok(Err(err)) => {
+            log::error!("while processing ssh-host-key event: {:#}", err);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// An entry from the known_hosts file
+#[derive(Debug, Clone)]
+pub struct KnownHostEntry {
+    /// The host name, or None if the entry is hashed
+    pub host: Option<String>,
+    pub key_type: String,
+    pub fingerprint: String,
+}
+
+fn load_known_hosts() -> anyhow::Result<(ssh2::KnownHosts, PathBuf)> {
+    let file = known_hosts_path()?;
+    let sess = ssh2::Session::new()?;
+    let mut known_hosts = sess.known_hosts()?;
+    if file.exists() {
+        known_hosts
+            .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+    }
+    Ok((known_hosts, file))
+}
+
+/// Returns the entries from the user's known_hosts file
+pub fn list_known_hosts() -> anyhow::Result<Vec<KnownHostEntry>> {
+    let (known_hosts, _file) = load_known_hosts()?;
+    let mut entries = vec![];
+    for host in known_hosts.hosts()? {
+        let blob = base64::decode(host.key()).unwrap_or_default();
+        entries.push(KnownHostEntry {
+            host: host.name().map(|name| name.to_string()),
+            key_type: public_key_type(&blob).unwrap_or("unknown").to_string(),
+            fingerprint: sha256_fingerprint(&blob),
+        });
+    }
+    Ok(entries)
+}
+
+/// Removes the entries for `name` from the user's known_hosts file.
+/// `name` is matched in the same form as it appears in the file,
+/// so hosts on a non-standard port are written as `[host]:port`.
+/// Returns the number of entries that were removed.
+pub fn remove_known_host(name: &str) -> anyhow::Result<usize> {
+    let (known_hosts, file) = load_known_hosts()?;
+    let mut removed = 0;
+    for host in known_hosts.hosts()? {
+        let matches = host
+            .name()
+            .map(|names| names.split(',').any(|n| n == name))
+            .unwrap_or(false);
+        if matches {
+            known_hosts.remove(&host)?;
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        known_hosts
+            .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("writing known_hosts file {}", file.display()))?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn randomart_matches_ssh_keygen() {
+        let hash = base64::decode_config(
+            "TghrmEDpQrXo7giDXTj/fPkd2NT/KdiAzKKOzF/a8tk",
+            base64::STANDARD_NO_PAD,
+        )
+        .unwrap();
+        assert_eq!(
+            randomart("ED25519 256", "SHA256", &hash),
+            "+--[ED25519 256]--+\n\
+             | .o.             |\n\
+             |.o. .            |\n\
+             |+. ..            |\n\
+             |oo + o .     .   |\n\
+             |..= + . S . . .  |\n\
+             |o. =   + + =   . |\n\
+             |+.. . ..o.. *   .|\n\
+             |+.o .=+ =  o +  o|\n\
+             |.. +o+== E. . ...|\n\
+             +----[SHA256]-----+\n"
+        );
+    }
+
+    #[test]
+    fn key_types() {
+        assert_eq!(short_key_type("ssh-ed25519"), "ED25519");
+        assert_eq!(short_key_type("ssh-rsa"), "RSA");
+        assert_eq!(short_key_type("ecdsa-sha2-nistp256"), "ECDSA 256");
+    }
+}
@@ -1,41 +1,87 @@
 use crate::pane::{Pane, PaneId};
 use crate::tab::{Tab, TabId};
 use crate::window::{Window, WindowId};
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
+use config::configuration;
+use config::keyassignment::PaneDirection;
 use domain::{Domain, DomainId};
 use log::error;
 use portable_pty::ExitStatus;
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::io::Read;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{sync_channel, Receiver, TryRecvError};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use thiserror::*;
 
 pub mod activity;
 pub mod connui;
+pub mod container;
 pub mod domain;
+pub mod export;
+pub mod knownhosts;
+pub mod localecho;
 pub mod localpane;
 pub mod pane;
+pub mod recording;
 pub mod renderable;
+pub mod serial;
+pub mod sftp;
+pub mod snapshot;
 pub mod ssh;
+pub mod sshconfig;
+pub mod sshforward;
+pub mod sshproxy;
+pub mod stats;
 pub mod tab;
 pub mod termwiztermtab;
 pub mod tmux;
+mod tmux_pty;
 pub mod window;
 
 use crate::activity::Activity;
+use crate::recording::{Recording, RecordingState};
 
 #[derive(Clone, Debug)]
 pub enum MuxNotification {
     PaneOutput(PaneId),
+    PaneAdded(PaneId),
+    PaneRemoved(PaneId),
+    TabAddedToWindow {
+        tab_id: TabId,
+        window_id: WindowId,
+    },
+    TabRemoved(TabId),
     WindowCreated(WindowId),
+    WindowRemoved(WindowId),
+    Alert {
+        pane_id: PaneId,
+        alert: wezterm_term::Alert,
+    },
+    /// Asks the gui window that shows `pane_id` to label the panes of
+    /// its tab and to report the one that is picked via `Mux::pane_chosen`
+    ChoosePane {
+        pane_id: PaneId,
+        request_id: usize,
+    },
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
+static CHOOSE_PANE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How long the gui shows the pane labels of `Mux::choose_pane`
+/// before giving up on a pane being picked
+pub const PANE_CHOOSER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// PaneOutput notifications are delivered no more often than this.
+/// Without this, a pane that is streaming output would generate a
+/// notification for every chunk that is read from its pty.
+const OUTPUT_NOTIFY_INTERVAL: Duration = Duration::from_millis(5);
 
 pub struct Mux {
     tabs: RefCell<HashMap<TabId, Rc<Tab>>>,
@@ -46,6 +92,14 @@ pub struct Mux {
     domains_by_name: RefCell<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RefCell<HashMap<usize, Box<dyn Fn(MuxNotification) -> bool>>>,
     banner: RefCell<Option<String>>,
+    pending_output: RefCell<Vec<PaneId>>,
+    last_output_notify: Cell<Instant>,
+    recordings: RefCell<HashMap<PaneId, Recording>>,
+    /// The exit status of the processes in the panes that have
+    /// been removed, for `wezterm cli wait-pane`
+    exit_statuses: RefCell<HashMap<PaneId, ExitStatus>>,
+    /// The outstanding `choose_pane` requests
+    pane_choices: RefCell<HashMap<usize, smol::channel::Sender<Option<PaneId>>>>,
 }
 
 /// This function bounces the data over to the main thread to feed to
@@ -57,8 +111,12 @@ fn send_to_mux(pane_id: PaneId, dead: &Arc<AtomicBool>, data: Vec<u8>) {
         async move {
             let mux = Mux::get().unwrap();
             if let Some(pane) = mux.get_pane(pane_id) {
+                let start = Instant::now();
                 pane.advance_bytes(&data);
-                mux.notify(MuxNotification::PaneOutput(pane_id));
+                metrics::histogram!("mux.parse", start.elapsed());
+                metrics::counter!("mux.parse.bytes", data.len() as u64);
+                mux.record_pane_output(&pane, &data);
+                mux.notify_pane_output(pane_id);
             } else {
                 // Something else removed the pane from
                 // the mux, so we should stop trying to
@@ -238,6 +296,11 @@ impl Mux {
             domains: RefCell::new(domains),
             subscribers: RefCell::new(HashMap::new()),
             banner: RefCell::new(None),
+            pending_output: RefCell::new(vec![]),
+            last_output_notify: Cell::new(Instant::now()),
+            recordings: RefCell::new(HashMap::new()),
+            exit_statuses: RefCell::new(HashMap::new()),
+            pane_choices: RefCell::new(HashMap::new()),
         }
     }
 
@@ -256,6 +319,155 @@ impl Mux {
         subscribers.retain(|_, notify| notify(notification.clone()));
     }
 
+    /// Notifies the subscribers once the main thread is idle.  This is
+    /// used for changes to the model, which may be made by a subscriber
+    /// while it is being notified.
+    fn notify_later(notification: MuxNotification) {
+        promise::spawn::spawn_into_main_thread(async move {
+            if let Some(mux) = Mux::get() {
+                mux.notify(notification);
+            }
+        })
+        .detach();
+    }
+
+    /// Arranges to notify the subscribers that `pane_id` has new output.
+    /// Notifications are coalesced: each pane that produced output is
+    /// reported once per OUTPUT_NOTIFY_INTERVAL, and the first output
+    /// after a quiet period is reported as soon as the main thread is
+    /// idle.
+    pub fn notify_pane_output(&self, pane_id: PaneId) {
+        let mut pending = self.pending_output.borrow_mut();
+        if pending.contains(&pane_id) {
+            return;
+        }
+        pending.push(pane_id);
+        if pending.len() > 1 {
+            // A flush is already scheduled
+            return;
+        }
+
+        let when = self.last_output_notify.get() + OUTPUT_NOTIFY_INTERVAL;
+        promise::spawn::spawn_into_main_thread_with_low_priority(async move {
+            if when > Instant::now() {
+                smol::Timer::at(when).await;
+            }
+            if let Some(mux) = Mux::get() {
+                mux.flush_pane_output();
+            }
+        })
+        .detach();
+    }
+
+    /// Starts recording the output of the pane to a new file in
+    /// the recording directory, returning the path to that file.
+    /// Does nothing if the pane is already being recorded.
+    pub fn start_recording(&self, pane_id: PaneId) -> anyhow::Result<PathBuf> {
+        if let Some(recording) = self.recordings.borrow().get(&pane_id) {
+            return Ok(recording.path().to_path_buf());
+        }
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+        let dims = pane.get_dimensions();
+        let recording = Recording::create(
+            &recording::recording_dir(),
+            pane_id,
+            dims.cols,
+            dims.viewport_rows,
+            &pane.get_title(),
+        )?;
+        let path = recording.path().to_path_buf();
+        log::info!("recording pane {} to {}", pane_id, path.display());
+        self.recordings.borrow_mut().insert(pane_id, recording);
+        Ok(path)
+    }
+
+    /// Stops recording the output of the pane, returning the path
+    /// to the completed recording
+    pub fn stop_recording(&self, pane_id: PaneId) -> Option<PathBuf> {
+        let recording = self.recordings.borrow_mut().remove(&pane_id)?;
+        match recording.finish() {
+            Ok(path) => {
+                log::info!("saved recording of pane {} to {}", pane_id, path.display());
+                Some(path)
+            }
+            Err(err) => {
+                log::error!("while finishing recording of pane {}: {:#}", pane_id, err);
+                None
+            }
+        }
+    }
+
+    pub fn toggle_recording(&self, pane_id: PaneId) -> anyhow::Result<()> {
+        if self.recording_state(pane_id).is_some() {
+            self.stop_recording(pane_id);
+        } else {
+            self.start_recording(pane_id)?;
+        }
+        Ok(())
+    }
+
+    /// Pauses a recording that is in progress, or resumes it if it
+    /// is paused.  Output produced while paused is not recorded.
+    pub fn toggle_recording_paused(&self, pane_id: PaneId) {
+        if let Some(recording) = self.recordings.borrow_mut().get_mut(&pane_id) {
+            let paused = recording.state() == RecordingState::Paused;
+            recording.set_paused(!paused);
+        }
+    }
+
+    pub fn recording_state(&self, pane_id: PaneId) -> Option<RecordingState> {
+        self.recordings
+            .borrow()
+            .get(&pane_id)
+            .map(|recording| recording.state())
+    }
+
+    fn record_pane_output(&self, pane: &Rc<dyn Pane>, data: &[u8]) {
+        let pane_id = pane.pane_id();
+        let mut recordings = self.recordings.borrow_mut();
+        if let Some(recording) = recordings.get_mut(&pane_id) {
+            let dims = pane.get_dimensions();
+            if let Err(err) = recording.output(data, dims.cols, dims.viewport_rows) {
+                log::error!("stopping recording of pane {}: {:#}", pane_id, err);
+                recordings.remove(&pane_id);
+            }
+        }
+    }
+
+    /// Starts recording the pane if it belongs to one of the domains
+    /// listed in the `auto_record_domains` configuration
+    fn maybe_auto_record(&self, pane: &Rc<dyn Pane>) {
+        let config = configuration();
+        let auto_record = &config.auto_record_domains;
+        if auto_record.is_empty() {
+            return;
+        }
+        let domain_name = match self.get_domain(pane.domain_id()) {
+            Some(domain) => domain.domain_name().to_string(),
+            None => return,
+        };
+        if auto_record.iter().any(|name| *name == domain_name) {
+            if let Err(err) = self.start_recording(pane.pane_id()) {
+                log::error!(
+                    "failed to start recording pane {} in domain {}: {:#}",
+                    pane.pane_id(),
+                    domain_name,
+                    err
+                );
+            }
+        }
+    }
+
+    fn flush_pane_output(&self) {
+        self.last_output_notify.set(Instant::now());
+        let panes = std::mem::take(&mut *self.pending_output.borrow_mut());
+        for pane_id in panes {
+            self.notify(MuxNotification::PaneOutput(pane_id));
+        }
+    }
+
     pub fn default_domain(&self) -> Arc<dyn Domain> {
         self.default_domain
             .borrow()
@@ -312,6 +524,12 @@ impl Mux {
         self.panes.borrow().get(&pane_id).map(Rc::clone)
     }
 
+    /// Returns the exit status of the process in a pane that has been
+    /// removed, if its domain was able to report it
+    pub fn removed_pane_exit_status(&self, pane_id: PaneId) -> Option<ExitStatus> {
+        self.exit_statuses.borrow().get(&pane_id).cloned()
+    }
+
     pub fn get_tab(&self, tab_id: TabId) -> Option<Rc<Tab>> {
         self.tabs.borrow().get(&tab_id).map(Rc::clone)
     }
@@ -324,6 +542,8 @@ impl Mux {
         let pane_id = pane.pane_id();
         let banner = self.banner.borrow().clone();
         thread::spawn(move || read_from_pane_pty(pane_id, banner, reader));
+        self.maybe_auto_record(pane);
+        Self::notify_later(MuxNotification::PaneAdded(pane_id));
         Ok(())
     }
 
@@ -341,9 +561,14 @@ impl Mux {
 
     fn remove_pane_internal(&self, pane_id: PaneId) {
         log::debug!("removing pane {}", pane_id);
+        self.stop_recording(pane_id);
         if let Some(pane) = self.panes.borrow_mut().remove(&pane_id) {
             log::debug!("killing pane {}", pane_id);
             pane.kill();
+            if let Some(status) = pane.exit_status() {
+                self.exit_statuses.borrow_mut().insert(pane_id, status);
+            }
+            Self::notify_later(MuxNotification::PaneRemoved(pane_id));
         }
     }
 
@@ -359,6 +584,7 @@ impl Mux {
         for pane_id in pane_ids {
             self.remove_pane_internal(pane_id);
         }
+        Self::notify_later(MuxNotification::TabRemoved(tab_id));
 
         Some(tab)
     }
@@ -370,6 +596,7 @@ impl Mux {
             for tab in window.iter() {
                 self.remove_tab_internal(tab.tab_id());
             }
+            Self::notify_later(MuxNotification::WindowRemoved(window_id));
         }
     }
 
@@ -460,9 +687,209 @@ impl Mux {
             .get_window_mut(window_id)
             .ok_or_else(|| anyhow!("add_tab_to_window: no such window_id {}", window_id))?;
         window.push(tab);
+        Self::notify_later(MuxNotification::TabAddedToWindow {
+            tab_id: tab.tab_id(),
+            window_id,
+        });
         Ok(())
     }
 
+    /// Moves a tab out of the window that holds it and into `window_id`,
+    /// where it becomes the active tab, or into a new window if that is
+    /// None.  The window that held the tab is removed if it is left empty.
+    /// Returns the id of the window that now holds the tab.
+    pub fn move_tab_to_window(
+        &self,
+        tab_id: TabId,
+        window_id: Option<WindowId>,
+    ) -> anyhow::Result<WindowId> {
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("move_tab_to_window: no such tab_id {}", tab_id))?;
+        let src_window_id = self
+            .window_containing_tab(tab_id)
+            .ok_or_else(|| anyhow!("move_tab_to_window: tab {} is not in a window", tab_id))?;
+        if window_id == Some(src_window_id) {
+            return Ok(src_window_id);
+        }
+        if let Some(window_id) = window_id {
+            if self.get_window(window_id).is_none() {
+                bail!("move_tab_to_window: no such window_id {}", window_id);
+            }
+        }
+
+        if let Some(mut src) = self.get_window_mut(src_window_id) {
+            src.remove_by_id(tab_id);
+        }
+
+        let dest_window_id = match window_id {
+            Some(window_id) => {
+                let mut window = self.get_window_mut(window_id).unwrap();
+                window.push(&tab);
+                let idx = window.len() - 1;
+                window.set_active(idx);
+                Self::notify_later(MuxNotification::TabAddedToWindow { tab_id, window_id });
+                window_id
+            }
+            None => {
+                // The gui creates the window for this when the
+                // builder is dropped, so the tab must be in it by then
+                let builder = self.new_empty_window();
+                self.add_tab_to_window(&tab, *builder)?;
+                *builder
+            }
+        };
+
+        self.prune_dead_windows();
+        Ok(dest_window_id)
+    }
+
+    /// Moves a pane out of the tab that holds it and splits it in beside
+    /// `target_pane_id`, on the side of that pane given by `side`.
+    /// The tab that held the pane is removed if it is left empty.
+    pub fn move_pane_beside(
+        &self,
+        pane_id: PaneId,
+        target_pane_id: PaneId,
+        side: PaneDirection,
+    ) -> anyhow::Result<()> {
+        if pane_id == target_pane_id {
+            bail!(
+                "move_pane_beside: cannot move pane {} beside itself",
+                pane_id
+            );
+        }
+        let (_, src_window_id, src_tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("move_pane_beside: no such pane_id {}", pane_id))?;
+        let (_, _, dest_tab_id) = self
+            .resolve_pane_id(target_pane_id)
+            .ok_or_else(|| anyhow!("move_pane_beside: no such pane_id {}", target_pane_id))?;
+        let src_tab = self
+            .get_tab(src_tab_id)
+            .ok_or_else(|| anyhow!("move_pane_beside: no such tab_id {}", src_tab_id))?;
+        let dest_tab = self
+            .get_tab(dest_tab_id)
+            .ok_or_else(|| anyhow!("move_pane_beside: no such tab_id {}", dest_tab_id))?;
+
+        src_tab.set_zoomed(false);
+        dest_tab.set_zoomed(false);
+
+        let pane = src_tab
+            .remove_pane(pane_id)
+            .ok_or_else(|| anyhow!("move_pane_beside: pane {} is not in a tab", pane_id))?;
+
+        // Look this up after removing the pane, as that shifts
+        // the indices when both panes are in the same tab
+        let target_idx = dest_tab
+            .iter_panes()
+            .into_iter()
+            .find(|p| p.pane.pane_id() == target_pane_id)
+            .map(|p| p.index);
+
+        let result = match target_idx {
+            Some(idx) => dest_tab
+                .insert_pane_beside(idx, side, Rc::clone(&pane))
+                .map(|_| ()),
+            None => Err(anyhow!(
+                "move_pane_beside: pane {} is no longer in tab {}",
+                target_pane_id,
+                dest_tab_id
+            )),
+        };
+
+        if let Err(err) = result {
+            // Rather than lose the pane, give it a tab of its own
+            // alongside the one that it came from
+            let tab = Rc::new(Tab::new(&src_tab.get_size()));
+            tab.assign_pane(&pane);
+            self.add_tab_no_panes(&tab);
+            self.add_tab_to_window(&tab, src_window_id)?;
+            if src_tab.count_panes() == 0 {
+                self.remove_tab(src_tab_id);
+            }
+            return Err(err);
+        }
+
+        if src_tab.count_panes() == 0 {
+            self.remove_tab(src_tab_id);
+        }
+        Ok(())
+    }
+
+    /// Exchanges the positions of two panes, which may be in different
+    /// tabs, resizing each of them to fit the space of the other
+    pub fn swap_panes(&self, pane_id: PaneId, other_pane_id: PaneId) -> anyhow::Result<()> {
+        if pane_id == other_pane_id {
+            bail!("swap_panes: cannot swap pane {} with itself", pane_id);
+        }
+        let (_, _, tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such pane_id {}", pane_id))?;
+        let (_, _, other_tab_id) = self
+            .resolve_pane_id(other_pane_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such pane_id {}", other_pane_id))?;
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such tab_id {}", tab_id))?;
+        let other_tab = self
+            .get_tab(other_tab_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such tab_id {}", other_tab_id))?;
+
+        tab.set_zoomed(false);
+        other_tab.set_zoomed(false);
+
+        if tab_id == other_tab_id {
+            return tab.swap_panes(pane_id, other_pane_id);
+        }
+
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such pane_id {}", pane_id))?;
+        let other_pane = self
+            .get_pane(other_pane_id)
+            .ok_or_else(|| anyhow!("swap_panes: no such pane_id {}", other_pane_id))?;
+        tab.replace_pane(pane_id, other_pane)
+            .ok_or_else(|| anyhow!("swap_panes: pane {} is not in a tab", pane_id))?;
+        other_tab
+            .replace_pane(other_pane_id, pane)
+            .ok_or_else(|| anyhow!("swap_panes: pane {} is not in a tab", other_pane_id))?;
+        Ok(())
+    }
+
+    /// Asks the gui window that shows `pane_id` to label the panes of
+    /// its tab so that the user can pick one of them.  The receiver
+    /// yields the picked pane, or None if the user didn't pick one.
+    /// Nothing is received if no gui window shows the pane.
+    pub fn choose_pane(
+        &self,
+        pane_id: PaneId,
+    ) -> anyhow::Result<smol::channel::Receiver<Option<PaneId>>> {
+        if self.resolve_pane_id(pane_id).is_none() {
+            bail!("choose_pane: no such pane_id {}", pane_id);
+        }
+        let request_id = CHOOSE_PANE_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = smol::channel::bounded(1);
+        {
+            let mut choices = self.pane_choices.borrow_mut();
+            // Forget the requests that are no longer waited upon
+            choices.retain(|_, tx| !tx.is_closed());
+            choices.insert(request_id, tx);
+        }
+        self.notify(MuxNotification::ChoosePane {
+            pane_id,
+            request_id,
+        });
+        Ok(rx)
+    }
+
+    /// Reports the pane that was picked for a `choose_pane` request
+    pub fn pane_chosen(&self, request_id: usize, pane_id: Option<PaneId>) {
+        if let Some(tx) = self.pane_choices.borrow_mut().remove(&request_id) {
+            tx.try_send(pane_id).ok();
+        }
+    }
+
     pub fn window_containing_tab(&self, tab_id: TabId) -> Option<WindowId> {
         for w in self.windows.borrow().values() {
             for t in w.iter() {
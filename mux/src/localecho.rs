@@ -0,0 +1,184 @@
+//! Predictive local echo for panes whose output comes from a remote
+//! host.  When the round trip to the host is slow, typing feels sluggish
+//! because each character only appears once the host has echoed it.
+//! Here we predict the effect of a keypress on the displayed line and
+//! cursor so that it can be shown immediately, with an underline to
+//! indicate that it is unconfirmed.  The prediction is discarded as soon
+//! as real output arrives from the host.
+use crate::renderable::StableCursorPosition;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use termwiz::cell::{Cell, CellAttributes, Underline};
+use termwiz::surface::Line;
+use wezterm_term::{KeyCode, KeyModifiers, StableRowIndex};
+
+/// Returns true if `config.local_echo_threshold_ms` indicates that
+/// we should predict echo given the observed round trip time
+pub fn latency_warrants_prediction(rtt: Duration) -> bool {
+    let config = config::configuration();
+    rtt >= Duration::from_millis(config.local_echo_threshold_ms)
+}
+
+/// Returns the attributes used to show predicted text
+pub fn unconfirmed_attributes() -> CellAttributes {
+    CellAttributes::default()
+        .set_underline(Underline::Double)
+        .clone()
+}
+
+/// Returns true if this is a key press whose effect we know how
+/// to predict
+pub fn is_predictable_key(key: KeyCode, mods: KeyModifiers) -> bool {
+    if mods != KeyModifiers::NONE && mods != KeyModifiers::SHIFT {
+        return false;
+    }
+    matches!(
+        key,
+        KeyCode::LeftArrow
+            | KeyCode::RightArrow
+            | KeyCode::UpArrow
+            | KeyCode::DownArrow
+            | KeyCode::Delete
+            | KeyCode::Backspace
+            | KeyCode::Enter
+            | KeyCode::Char(_)
+    )
+}
+
+/// Applies the predicted effect of `key` to `line`, which is the line
+/// that holds the cursor, and to the cursor position.
+/// Open questions:
+/// how do we tell if the intent is to suppress local echo during eg:
+///  * password prompt?  One option is to look back and see if the line
+///                      looks like a password prompt.
+///  * normal mode in vim: letter presses are typically movement or
+///                        other editor commands
+/// There are bound to be a number of other edge cases that we should
+/// handle.
+pub fn predict_key(key: KeyCode, cursor: &mut StableCursorPosition, line: &mut Line) {
+    let text = line.as_str();
+    if text.contains("sword") {
+        // This line might be a password prompt.  Don't force
+        // on local echo here, as we don't want to reveal content
+        // from their password
+        return;
+    }
+
+    match key {
+        KeyCode::Enter => {
+            cursor.x = 0;
+            cursor.y += 1;
+        }
+        KeyCode::UpArrow => {
+            cursor.y = cursor.y.saturating_sub(1);
+        }
+        KeyCode::DownArrow => {
+            cursor.y += 1;
+        }
+        KeyCode::RightArrow => {
+            cursor.x += 1;
+        }
+        KeyCode::LeftArrow => {
+            cursor.x = cursor.x.saturating_sub(1);
+        }
+        KeyCode::Delete => {
+            line.erase_cell(cursor.x);
+        }
+        KeyCode::Backspace => {
+            if cursor.x > 0 {
+                line.erase_cell(cursor.x - 1);
+                cursor.x -= 1;
+            }
+        }
+        KeyCode::Char(c) => {
+            let cell = Cell::new(c, unconfirmed_attributes());
+            let cell = line.set_cell(cursor.x, cell);
+            // Adjust the cursor to reflect the width of this new cell
+            cursor.x += cell.width();
+        }
+        _ => {}
+    }
+}
+
+/// Tracks the round trip time and the current prediction for a pane
+/// that is hosted by a `LocalPane`, such as one in an ssh domain.
+#[derive(Default)]
+pub struct LocalEcho {
+    /// When the oldest input that hasn't yet been followed by
+    /// output was sent
+    awaiting_output_since: Option<Instant>,
+    /// Smoothed round trip time
+    rtt: Option<Duration>,
+    /// The predicted content of the lines that we have changed
+    lines: HashMap<StableRowIndex, Line>,
+    cursor: Option<StableCursorPosition>,
+}
+
+impl LocalEcho {
+    /// Records that input was sent to the host
+    pub fn input_sent(&mut self) {
+        if self.awaiting_output_since.is_none() {
+            self.awaiting_output_since.replace(Instant::now());
+        }
+    }
+
+    /// Records that output arrived from the host, which supersedes
+    /// any prediction.  Returns true if there was a prediction.
+    pub fn output_received(&mut self) -> bool {
+        if let Some(sent) = self.awaiting_output_since.take() {
+            let sample = sent.elapsed();
+            self.rtt = Some(match self.rtt {
+                Some(rtt) => (rtt * 7 + sample) / 8,
+                None => sample,
+            });
+        }
+        self.cursor.take();
+        let had_prediction = !self.lines.is_empty();
+        self.lines.clear();
+        had_prediction
+    }
+
+    pub fn should_predict(&self) -> bool {
+        self.rtt.map(latency_warrants_prediction).unwrap_or(false)
+    }
+
+    /// Predicts the effect of `key`.  `cursor` is the actual cursor
+    /// position and `get_line` returns the actual content of a line.
+    pub fn predict<F>(&mut self, key: KeyCode, cursor: StableCursorPosition, get_line: F)
+    where
+        F: FnOnce(StableRowIndex) -> Option<Line>,
+    {
+        let mut cursor = self.cursor.unwrap_or(cursor);
+        let row = cursor.y;
+        let line = match self.lines.remove(&row) {
+            Some(line) => Some(line),
+            None => get_line(row),
+        };
+        if let Some(mut line) = line {
+            predict_key(key, &mut cursor, &mut line);
+            self.lines.insert(row, line);
+            self.cursor.replace(cursor);
+        }
+    }
+
+    pub fn cursor(&self) -> Option<StableCursorPosition> {
+        self.cursor
+    }
+
+    /// Replaces lines with their predicted content.
+    /// `first` is the stable row index of the first of the lines.
+    pub fn apply_to_lines(&self, first: StableRowIndex, lines: &mut [Line]) {
+        for (row, predicted) in &self.lines {
+            let idx = row - first;
+            if idx >= 0 {
+                if let Some(line) = lines.get_mut(idx as usize) {
+                    *line = predicted.clone();
+                }
+            }
+        }
+    }
+
+    pub fn predicted_rows(&self) -> impl Iterator<Item = StableRowIndex> + '_ {
+        self.lines.keys().copied()
+    }
+}
@@ -1,12 +1,13 @@
 use crate::domain::DomainId;
+use crate::localecho::{is_predictable_key, LocalEcho};
 use crate::pane::{Pane, PaneId, Pattern, SearchResult};
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
-use crate::{Domain, Mux};
+use crate::{Domain, Mux, MuxNotification};
 use anyhow::Error;
 use async_trait::async_trait;
 use config::keyassignment::ScrollbackEraseMode;
-use portable_pty::{Child, MasterPty, PtySize};
+use portable_pty::{Child, ExitStatus, MasterPty, PtySize};
 use rangeset::RangeSet;
 use std::cell::{RefCell, RefMut};
 use std::ops::Range;
@@ -16,8 +17,8 @@ use termwiz::surface::Line;
 use url::Url;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    CellAttributes, Clipboard, KeyCode, KeyModifiers, MouseEvent, SemanticZone, StableRowIndex,
-    Terminal,
+    Alert, CellAttributes, Clipboard, KeyCode, KeyModifiers, MouseEvent, PromptMark, SemanticZone,
+    StableRowIndex, Terminal,
 };
 
 pub struct LocalPane {
@@ -27,6 +28,7 @@ pub struct LocalPane {
     pty: RefCell<Box<dyn MasterPty>>,
     domain_id: DomainId,
     tmux_domain: RefCell<Option<Arc<TmuxDomainState>>>,
+    local_echo: Option<RefCell<LocalEcho>>,
 }
 
 #[async_trait(?Send)]
@@ -40,16 +42,36 @@ impl Pane for LocalPane {
         if self.tmux_domain.borrow().is_some() {
             cursor.visibility = termwiz::surface::CursorVisibility::Hidden;
         }
+        if let Some(predicted) = self
+            .local_echo
+            .as_ref()
+            .and_then(|echo| echo.borrow().cursor())
+        {
+            cursor.x = predicted.x;
+            cursor.y = predicted.y;
+        }
         cursor
     }
 
     fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
-        terminal_get_dirty_lines(&mut self.terminal.borrow_mut(), lines)
+        let mut dirty = terminal_get_dirty_lines(&mut self.terminal.borrow_mut(), lines.clone());
+        if let Some(echo) = self.local_echo.as_ref() {
+            for row in echo.borrow().predicted_rows() {
+                if lines.contains(&row) {
+                    dirty.add(row);
+                }
+            }
+        }
+        dirty
     }
 
     fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
         let (first, mut lines) = terminal_get_lines(&mut self.terminal.borrow_mut(), lines);
 
+        if let Some(echo) = self.local_echo.as_ref() {
+            echo.borrow().apply_to_lines(first, &mut lines);
+        }
+
         if self.tmux_domain.borrow().is_some() {
             let cursor = terminal_get_cursor_position(&mut self.terminal.borrow_mut());
             let idx = cursor.y as isize - first as isize;
@@ -76,6 +98,10 @@ impl Pane for LocalPane {
         self.process.borrow_mut().kill().ok();
     }
 
+    fn exit_status(&self) -> Option<ExitStatus> {
+        self.process.borrow_mut().try_wait().ok().flatten()
+    }
+
     fn is_dead(&self) -> bool {
         if let Ok(None) = self.process.borrow_mut().try_wait() {
             false
@@ -90,7 +116,14 @@ impl Pane for LocalPane {
     }
 
     fn advance_bytes(&self, buf: &[u8]) {
-        self.terminal.borrow_mut().advance_bytes(buf)
+        self.terminal.borrow_mut().advance_bytes(buf);
+        if let Some(echo) = self.local_echo.as_ref() {
+            if echo.borrow_mut().output_received() {
+                // Lines that were predicted but not touched by the
+                // output need to be rendered again
+                self.terminal.borrow_mut().make_all_lines_dirty();
+            }
+        }
     }
 
     fn mouse_event(&self, event: MouseEvent) -> Result<(), Error> {
@@ -104,9 +137,9 @@ impl Pane for LocalPane {
                 self.terminal.borrow_mut().send_paste("detach\n")?;
             }
             return Ok(());
-        } else {
-            self.terminal.borrow_mut().key_down(key, mods)
         }
+        self.predict_key(key, mods);
+        self.terminal.borrow_mut().key_down(key, mods)
     }
 
     fn resize(&self, size: PtySize) -> Result<(), Error> {
@@ -132,6 +165,9 @@ impl Pane for LocalPane {
         if self.tmux_domain.borrow().is_some() {
             Ok(())
         } else {
+            if let Some(echo) = self.local_echo.as_ref() {
+                echo.borrow_mut().input_sent();
+            }
             self.terminal.borrow_mut().send_paste(text)
         }
     }
@@ -179,6 +215,14 @@ impl Pane for LocalPane {
         }
     }
 
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        if self.tmux_domain.borrow().is_some() {
+            false
+        } else {
+            self.terminal.borrow().bracketed_paste_enabled()
+        }
+    }
+
     fn get_current_working_dir(&self) -> Option<Url> {
         self.terminal
             .borrow()
@@ -187,12 +231,40 @@ impl Pane for LocalPane {
             .or_else(|| self.divine_current_working_dir())
     }
 
+    fn approx_memory_usage(&self) -> Option<usize> {
+        Some(self.terminal.borrow().approx_memory_usage())
+    }
+
+    fn get_foreground_process_name(&self) -> Option<String> {
+        #[cfg(target_os = "linux")]
+        {
+            return self.divine_foreground_process_name_linux();
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            return self.divine_foreground_process_name_macos();
+        }
+
+        #[allow(unreachable_code)]
+        None
+    }
+
     fn get_semantic_zones(&self) -> anyhow::Result<Vec<SemanticZone>> {
         let term = self.terminal.borrow();
         term.get_semantic_zones()
     }
 
-    async fn search(&self, mut pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+    fn get_prompt_marks(&self, range: Range<StableRowIndex>) -> anyhow::Result<Vec<PromptMark>> {
+        let term = self.terminal.borrow();
+        Ok(term.get_prompt_marks(range))
+    }
+
+    async fn search(
+        &self,
+        mut pattern: Pattern,
+        range: Range<StableRowIndex>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
         let term = self.terminal.borrow();
         let screen = term.screen();
 
@@ -262,9 +334,7 @@ impl Pane for LocalPane {
             }
         }
 
-        for (idx, line) in screen.lines.iter().enumerate() {
-            let stable_row = screen.phys_to_stable_row_index(idx);
-
+        let mut search_line = |stable_row: StableRowIndex, line: &Line| {
             let mut wrapped = false;
             for (grapheme_idx, cell) in line.visible_cells() {
                 coords.push(Coord {
@@ -292,6 +362,60 @@ impl Pane for LocalPane {
                     coords.clear();
                 }
             }
+        };
+
+        let memory_top = screen.phys_to_stable_row_index(0);
+        let memory_end = screen.phys_to_stable_row_index(screen.lines.len());
+        let is_wrapped = |row: StableRowIndex| match screen.stable_row_to_phys(row) {
+            Some(phys) => screen.lines[phys].last_cell_was_wrapped(),
+            None => screen
+                .disk_scrollback_lines(row..row + 1)
+                .1
+                .first()
+                .map(Line::last_cell_was_wrapped)
+                .unwrap_or(false),
+        };
+
+        // Widen the range to take in the whole of the logical lines
+        // at either end, so that matches that wrap across its edges
+        // are found in their entirety
+        let mut start = range.start.max(screen.scrollback_top());
+        let mut end = range.end.min(memory_end);
+        if start >= end {
+            return Ok(vec![]);
+        }
+        while start > screen.scrollback_top() && is_wrapped(start - 1) {
+            start -= 1;
+        }
+        while end < memory_end && is_wrapped(end - 1) {
+            end += 1;
+        }
+
+        // Page in the disk scrollback a chunk at a time, so that we
+        // don't need to hold all of it in memory at once
+        let mut row = start;
+        while row < memory_top.min(end) {
+            let chunk_end = (row + 1024).min(memory_top).min(end);
+            let (first, lines) = screen.disk_scrollback_lines(row..chunk_end);
+            if lines.is_empty() {
+                break;
+            }
+            for (idx, line) in lines.iter().enumerate() {
+                search_line(first + idx as StableRowIndex, line);
+            }
+            row = first + lines.len() as StableRowIndex;
+        }
+
+        let first_phys = (start.max(memory_top) - memory_top) as usize;
+        let end_phys = (end - memory_top).max(0) as usize;
+        for (idx, line) in screen
+            .lines
+            .iter()
+            .enumerate()
+            .take(end_phys)
+            .skip(first_phys)
+        {
+            search_line(screen.phys_to_stable_row_index(idx), line);
         }
 
         collect_matches(&mut results, &pattern, &haystack, &coords);
@@ -331,10 +455,6 @@ impl wezterm_term::DeviceControlHandler for LocalPaneDCSHandler {
                     }
 
                     self.tmux_domain.replace(tmux_domain);
-
-                // TODO: do we need to proactively list available tabs here?
-                // if so we should arrange to call domain.attach() and make
-                // it do the right thing.
                 } else {
                     log::error!("unknown DeviceControlMode::Enter {:?}", mode,);
                 }
@@ -367,6 +487,23 @@ impl wezterm_term::DeviceControlHandler for LocalPaneDCSHandler {
     }
 }
 
+struct LocalPaneAlertHandler {
+    pane_id: PaneId,
+}
+
+impl wezterm_term::AlertHandler for LocalPaneAlertHandler {
+    fn alert(&mut self, alert: Alert) {
+        // The terminal is borrowed while it processes output, so
+        // let the subscribers hear about this once that is done
+        let pane_id = self.pane_id;
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().expect("to be called on main thread");
+            mux.notify(MuxNotification::Alert { pane_id, alert });
+        })
+        .detach();
+    }
+}
+
 impl LocalPane {
     pub fn new(
         pane_id: PaneId,
@@ -379,6 +516,7 @@ impl LocalPane {
             pane_id,
             tmux_domain: None,
         }));
+        terminal.set_alert_handler(Box::new(LocalPaneAlertHandler { pane_id }));
         Self {
             pane_id,
             terminal: RefCell::new(terminal),
@@ -386,9 +524,40 @@ impl LocalPane {
             pty: RefCell::new(pty),
             domain_id,
             tmux_domain: RefCell::new(None),
+            local_echo: None,
         }
     }
 
+    /// Enables predictive local echo, which is worthwhile for panes
+    /// whose output comes from a remote host
+    pub fn with_local_echo(mut self) -> Self {
+        self.local_echo.replace(RefCell::new(LocalEcho::default()));
+        self
+    }
+
+    fn predict_key(&self, key: KeyCode, mods: KeyModifiers) {
+        let echo = match self.local_echo.as_ref() {
+            Some(echo) => echo,
+            None => return,
+        };
+        let mut echo = echo.borrow_mut();
+        echo.input_sent();
+
+        if !echo.should_predict() || !is_predictable_key(key, mods) {
+            return;
+        }
+        let mut terminal = self.terminal.borrow_mut();
+        if terminal.is_alt_screen_active() {
+            // Full screen applications are unlikely to simply echo
+            return;
+        }
+        let cursor = terminal_get_cursor_position(&mut terminal);
+        echo.predict(key, cursor, |row| {
+            let (_, mut lines) = terminal_get_lines(&mut terminal, row..row + 1);
+            lines.pop()
+        });
+    }
+
     #[cfg(target_os = "macos")]
     fn divine_current_working_dir_macos(&self) -> Option<Url> {
         if let Some(pid) = self.pty.borrow().process_group_leader() {
@@ -471,6 +640,39 @@ impl LocalPane {
         None
     }
 
+    #[cfg(target_os = "linux")]
+    fn divine_foreground_process_name_linux(&self) -> Option<String> {
+        let pid = self.pty.borrow().process_group_leader()?;
+        if let Ok(path) = std::fs::read_link(format!("/proc/{}/exe", pid)) {
+            if let Some(name) = path.file_name() {
+                return Some(name.to_string_lossy().into_owned());
+            }
+        }
+        // The executable may be inaccessible, eg: if it is setuid, but
+        // the short name of the command is always available
+        std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .ok()
+            .map(|name| name.trim_end().to_string())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn divine_foreground_process_name_macos(&self) -> Option<String> {
+        let pid = self.pty.borrow().process_group_leader()?;
+        extern "C" {
+            fn proc_pidpath(pid: libc::pid_t, buffer: *mut u8, buffersize: u32) -> libc::c_int;
+        }
+        const PROC_PIDPATHINFO_MAXSIZE: usize = 4096;
+        let mut buffer = vec![0u8; PROC_PIDPATHINFO_MAXSIZE];
+        let len = unsafe { proc_pidpath(pid, buffer.as_mut_ptr(), buffer.len() as u32) };
+        if len <= 0 {
+            return None;
+        }
+        buffer.truncate(len as usize);
+        let path = std::path::PathBuf::from(String::from_utf8_lossy(&buffer).into_owned());
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
     fn divine_current_working_dir(&self) -> Option<Url> {
         #[cfg(target_os = "linux")]
         {
@@ -4,7 +4,7 @@ use crate::Mux;
 use async_trait::async_trait;
 use config::keyassignment::ScrollbackEraseMode;
 use downcast_rs::{impl_downcast, Downcast};
-use portable_pty::PtySize;
+use portable_pty::{ExitStatus, PtySize};
 use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
 use std::cell::RefMut;
@@ -13,7 +13,9 @@ use std::sync::{Arc, Mutex};
 use termwiz::surface::Line;
 use url::Url;
 use wezterm_term::color::ColorPalette;
-use wezterm_term::{Clipboard, KeyCode, KeyModifiers, MouseEvent, SemanticZone, StableRowIndex};
+use wezterm_term::{
+    Clipboard, KeyCode, KeyModifiers, MouseEvent, PromptMark, SemanticZone, StableRowIndex,
+};
 
 static PANE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type PaneId = usize;
@@ -115,6 +117,11 @@ pub trait Pane: Downcast {
     fn advance_bytes(&self, buf: &[u8]);
     fn is_dead(&self) -> bool;
     fn kill(&self) {}
+    /// Returns the exit status of the process in the pane, if it
+    /// has exited and the pane is able to report it
+    fn exit_status(&self) -> Option<ExitStatus> {
+        None
+    }
     fn palette(&self) -> ColorPalette;
     fn domain_id(&self) -> DomainId;
 
@@ -123,10 +130,16 @@ pub trait Pane: Downcast {
     /// Called to advise on whether this tab has focus
     fn focus_changed(&self, _focused: bool) {}
 
-    /// Performs a search.
+    /// Performs a search of the rows in `range`.
     /// If the result is empty then there are no matches.
     /// Otherwise, the result shall contain all possible matches.
-    async fn search(&self, _pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+    /// A match on a logical line that wraps into `range` from
+    /// above may be included even though it starts before `range`.
+    async fn search(
+        &self,
+        _pattern: Pattern,
+        _range: Range<StableRowIndex>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
         Ok(vec![])
     }
 
@@ -135,6 +148,17 @@ pub trait Pane: Downcast {
         Ok(vec![])
     }
 
+    /// Retrieve the shell prompts that start within `range`, oldest first
+    fn get_prompt_marks(&self, _range: Range<StableRowIndex>) -> anyhow::Result<Vec<PromptMark>> {
+        Ok(vec![])
+    }
+
+    /// Returns an estimate of the number of bytes used to hold the
+    /// screen and scrollback, if the pane is able to provide one
+    fn approx_memory_usage(&self) -> Option<usize> {
+        None
+    }
+
     /// Returns true if the terminal has grabbed the mouse and wants to
     /// give the embedded application a chance to process events.
     /// In practice this controls whether the gui will perform local
@@ -142,10 +166,22 @@ pub trait Pane: Downcast {
     fn is_mouse_grabbed(&self) -> bool;
     fn is_alt_screen_active(&self) -> bool;
 
+    /// Returns true if the program in the pane has enabled bracketed
+    /// paste mode, which lets it tell pasted text apart from typing
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        false
+    }
+
     fn set_clipboard(&self, _clipboard: &Arc<dyn Clipboard>) {}
 
     fn get_current_working_dir(&self) -> Option<Url>;
 
+    /// Returns the name of the executable of the process that is in the
+    /// foreground of the pane, such as `vim`, if it can be determined
+    fn get_foreground_process_name(&self) -> Option<String> {
+        None
+    }
+
     fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
         if text.len() <= PASTE_CHUNK_SIZE {
             // Send it all now
@@ -0,0 +1,219 @@
+//! Records the output of a pane to a file in the asciicast v2 format
+//! used by asciinema, so that it can be replayed with `asciinema play`
+//! or embedded in a web page.
+//! <https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md>
+use anyhow::Context;
+use config::configuration;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingState {
+    Recording,
+    Paused,
+}
+
+pub struct Recording {
+    file: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+    /// When the recording was paused, if it is currently paused
+    paused_at: Option<Instant>,
+    /// The total time spent paused; this is excluded from the
+    /// timestamps so that playback doesn't sit idle for that time
+    paused_for: Duration,
+    /// The dimensions most recently written to the recording
+    size: (usize, usize),
+    /// The start of a UTF-8 sequence that was split across two reads
+    partial: Vec<u8>,
+}
+
+/// Returns the directory that recordings are written to
+pub fn recording_dir() -> PathBuf {
+    configuration()
+        .recording_dir
+        .clone()
+        .unwrap_or_else(|| config::HOME_DIR.join("wezterm-recordings"))
+}
+
+impl Recording {
+    /// Creates a new recording file in `dir`, and writes the header
+    /// describing a terminal of the supplied size
+    pub fn create(
+        dir: &Path,
+        pane_id: usize,
+        cols: usize,
+        rows: usize,
+        title: &str,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = dir.join(format!(
+            "wezterm-{}-pane-{}.cast",
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            pane_id
+        ));
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+
+        let header = serde_json::json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "title": title,
+            "env": {
+                "TERM": configuration().term,
+                "SHELL": std::env::var("SHELL").unwrap_or_default(),
+            },
+        });
+        writeln!(file, "{}", header)?;
+
+        Ok(Self {
+            file,
+            path,
+            started: Instant::now(),
+            paused_at: None,
+            paused_for: Duration::default(),
+            size: (cols, rows),
+            partial: vec![],
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn state(&self) -> RecordingState {
+        if self.paused_at.is_some() {
+            RecordingState::Paused
+        } else {
+            RecordingState::Recording
+        }
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => {
+                self.paused_at.replace(Instant::now());
+                // Don't hold on to the start of a character that we
+                // may never see the end of
+                self.partial.clear();
+            }
+            (false, Some(paused_at)) => {
+                self.paused_for += paused_at.elapsed();
+                self.paused_at.take();
+            }
+            _ => {}
+        }
+        self.file.flush().ok();
+    }
+
+    fn elapsed(&self) -> f64 {
+        (self.started.elapsed() - self.paused_for).as_secs_f64()
+    }
+
+    fn write_event(&mut self, code: &str, data: &str) -> anyhow::Result<()> {
+        let event = serde_json::json!([self.elapsed(), code, data]);
+        writeln!(self.file, "{}", event)?;
+        Ok(())
+    }
+
+    /// Records output from the pane, which now has the supplied size
+    pub fn output(&mut self, data: &[u8], cols: usize, rows: usize) -> anyhow::Result<()> {
+        if self.paused_at.is_some() {
+            return Ok(());
+        }
+
+        if (cols, rows) != self.size {
+            self.size = (cols, rows);
+            self.write_event("r", &format!("{}x{}", cols, rows))?;
+        }
+
+        self.partial.extend_from_slice(data);
+        // asciicast output events are strings, so only write out complete
+        // UTF-8 sequences and keep any trailing partial sequence until
+        // the rest of it arrives.  Invalid sequences are replaced.
+        let complete = match std::str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => self.partial.len(),
+        };
+        if complete == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.partial[..complete]).into_owned();
+        self.partial.drain(..complete);
+        self.write_event("o", &text)
+    }
+
+    /// Writes out any buffered data and closes the recording
+    pub fn finish(mut self) -> anyhow::Result<PathBuf> {
+        if !self.partial.is_empty() {
+            let text = String::from_utf8_lossy(&self.partial).into_owned();
+            self.partial.clear();
+            self.write_event("o", &text)?;
+        }
+        self.file.flush()?;
+        Ok(self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_events(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn asciicast() {
+        let dir = std::env::temp_dir().join(format!("wezterm-recording-{}", std::process::id()));
+        let mut recording = Recording::create(&dir, 1, 80, 24, "bash").unwrap();
+        recording.output(b"hello \xe2\x98", 80, 24).unwrap();
+        recording.output(b"\x83\r\n", 80, 24).unwrap();
+        recording.set_paused(true);
+        recording.output(b"secret\r\n", 80, 24).unwrap();
+        recording.set_paused(false);
+        recording.output(b"\x1b[1mbye", 100, 30).unwrap();
+        let path = recording.finish().unwrap();
+
+        let events = read_events(&path);
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(events[0]["version"], 2);
+        assert_eq!(events[0]["width"], 80);
+        assert_eq!(events[0]["height"], 24);
+        assert_eq!(events[0]["title"], "bash");
+
+        let events: Vec<(String, String)> = events[1..]
+            .iter()
+            .map(|event| {
+                (
+                    event[1].as_str().unwrap().to_string(),
+                    event[2].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("o".to_string(), "hello ".to_string()),
+                ("o".to_string(), "\u{2603}\r\n".to_string()),
+                ("r".to_string(), "100x30".to_string()),
+                ("o".to_string(), "\u{1b}[1mbye".to_string()),
+            ]
+        );
+    }
+}
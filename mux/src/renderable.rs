@@ -3,7 +3,7 @@ use luahelper::impl_lua_conversion;
 use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
-use wezterm_term::{Line, StableRowIndex, Terminal};
+use wezterm_term::{Line, Screen, StableRowIndex, Terminal};
 
 /// Describes the location of the cursor
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
@@ -76,6 +76,33 @@ pub fn terminal_get_lines(
     lines: Range<StableRowIndex>,
 ) -> (StableRowIndex, Vec<Line>) {
     let screen = term.screen_mut();
+    let config = configuration();
+    let memory_top = screen.phys_to_stable_row_index(0);
+
+    // Lines prior to the in-memory scrollback are paged in from
+    // the disk scrollback
+    if lines.start < memory_top && screen.disk_scrollback_len() > 0 {
+        let (first, mut disk_lines) = screen.disk_scrollback_lines(lines.clone());
+        let expected = (lines.end.min(memory_top) - first) as usize;
+        if disk_lines.len() == expected {
+            for line in &mut disk_lines {
+                line.scan_and_create_hyperlinks(&config.hyperlink_rules);
+            }
+            if lines.end > memory_top {
+                let (_, memory_lines) = get_memory_lines(screen, memory_top..lines.end);
+                disk_lines.extend(memory_lines);
+            }
+            return (first, disk_lines);
+        }
+    }
+
+    get_memory_lines(screen, lines)
+}
+
+fn get_memory_lines(
+    screen: &mut Screen,
+    lines: Range<StableRowIndex>,
+) -> (StableRowIndex, Vec<Line>) {
     let phys_range = screen.stable_range(&lines);
     let config = configuration();
     (
@@ -101,8 +128,8 @@ pub fn terminal_get_dimensions(term: &mut Terminal) -> RenderableDimensions {
     RenderableDimensions {
         cols: screen.physical_cols,
         viewport_rows: screen.physical_rows,
-        scrollback_rows: screen.lines.len(),
+        scrollback_rows: screen.lines.len() + screen.disk_scrollback_len(),
         physical_top: screen.visible_row_to_stable_row(0),
-        scrollback_top: screen.phys_to_stable_row_index(0),
+        scrollback_top: screen.scrollback_top(),
     }
 }
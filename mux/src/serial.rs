@@ -0,0 +1,304 @@
+//! Domains that connect a pane to a serial port, such as the console
+//! of an embedded board attached by way of a USB serial adapter.
+//! The launcher enumerates the serial ports that are present and
+//! registers a domain for each one that is chosen.
+use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::localpane::LocalPane;
+use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::tab::{SplitDirection, Tab, TabId};
+use crate::window::WindowId;
+use crate::Mux;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use config::configuration;
+use portable_pty::serial::SerialTty;
+use portable_pty::{CommandBuilder, PtySize, PtySystem};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Describes a serial port that is present on this system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialPortInfo {
+    /// The name of the device, such as `/dev/ttyUSB0` or `COM3`
+    pub port: String,
+    /// A description of the device, such as the name of the USB adapter
+    pub description: Option<String>,
+}
+
+impl SerialPortInfo {
+    pub fn label(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{} ({})", self.port, description),
+            None => self.port.clone(),
+        }
+    }
+}
+
+/// Makes a name from /dev/serial/by-id readable, for example
+/// `usb-FTDI_FT232R_USB_UART_A50285BI-if00-port0` becomes
+/// `FTDI FT232R USB UART A50285BI`
+#[cfg(target_os = "linux")]
+fn friendly_name_from_id(id: &str) -> String {
+    let id = id
+        .strip_prefix("usb-")
+        .or_else(|| id.strip_prefix("pci-"))
+        .unwrap_or(id);
+    let id = match id.rfind("-if") {
+        Some(idx) => &id[..idx],
+        None => id,
+    };
+    id.replace('_', " ")
+}
+
+/// Returns the USB serial adapters and modems.  Their descriptions
+/// come from the symlinks that udev maintains in /dev/serial/by-id.
+#[cfg(target_os = "linux")]
+pub fn enumerate_serial_ports() -> anyhow::Result<Vec<SerialPortInfo>> {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    let mut descriptions: HashMap<PathBuf, String> = HashMap::new();
+    if let Ok(dir) = std::fs::read_dir("/dev/serial/by-id") {
+        for entry in dir.flatten() {
+            if let Ok(target) = std::fs::canonicalize(entry.path()) {
+                descriptions.insert(
+                    target,
+                    friendly_name_from_id(&entry.file_name().to_string_lossy()),
+                );
+            }
+        }
+    }
+
+    let mut ports = vec![];
+    for entry in std::fs::read_dir("/dev")?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("ttyUSB") || name.starts_with("ttyACM") {
+            let path = entry.path();
+            ports.push(SerialPortInfo {
+                port: path.to_string_lossy().to_string(),
+                description: descriptions.get(&path).cloned(),
+            });
+        }
+    }
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    Ok(ports)
+}
+
+/// Returns the callout devices, whose names usually describe
+/// the adapter, such as `/dev/cu.usbserial-A50285BI`
+#[cfg(target_os = "macos")]
+pub fn enumerate_serial_ports() -> anyhow::Result<Vec<SerialPortInfo>> {
+    let mut ports = vec![];
+    for entry in std::fs::read_dir("/dev")?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("cu.")
+            && name != "cu.Bluetooth-Incoming-Port"
+            && name != "cu.debug-console"
+        {
+            ports.push(SerialPortInfo {
+                port: entry.path().to_string_lossy().to_string(),
+                description: None,
+            });
+        }
+    }
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    Ok(ports)
+}
+
+/// Returns the COM ports listed in the registry, described by the
+/// name of the kernel device, such as `\Device\VCP0` for an FTDI
+/// adapter or `\Device\USBSER000` for a USB modem
+#[cfg(windows)]
+pub fn enumerate_serial_ports() -> anyhow::Result<Vec<SerialPortInfo>> {
+    use anyhow::Context;
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let output = std::process::Command::new("reg")
+        .args(&["query", r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .context("running reg query")?;
+    // There is no SERIALCOMM key at all when there are no ports
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+
+    let mut ports = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let [device, "REG_SZ", port] = fields.as_slice() {
+            ports.push(SerialPortInfo {
+                port: port.to_string(),
+                description: Some(device.to_string()),
+            });
+        }
+    }
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    Ok(ports)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn enumerate_serial_ports() -> anyhow::Result<Vec<SerialPortInfo>> {
+    Ok(vec![])
+}
+
+pub fn serial_domain_name(port: &str) -> String {
+    format!("serial:{}", port)
+}
+
+/// Returns the domain for a serial port, creating and registering it
+/// with the mux if this is the first time that it has been used.
+/// Must be called on the main thread.
+pub fn get_or_create_serial_domain(port: &SerialPortInfo) -> anyhow::Result<Arc<dyn Domain>> {
+    let mux = Mux::get().ok_or_else(|| anyhow!("must be called on the main thread"))?;
+    let name = serial_domain_name(&port.port);
+    if let Some(domain) = mux.get_domain_by_name(&name) {
+        return Ok(domain);
+    }
+    let domain: Arc<dyn Domain> = Arc::new(SerialDomain::new(port));
+    mux.add_domain(&domain);
+    Ok(domain)
+}
+
+pub struct SerialDomain {
+    port: String,
+    id: DomainId,
+    name: String,
+    label: String,
+}
+
+impl SerialDomain {
+    pub fn new(port: &SerialPortInfo) -> Self {
+        Self {
+            port: port.port.clone(),
+            id: alloc_domain_id(),
+            name: serial_domain_name(&port.port),
+            label: format!("Serial port {}", port.label()),
+        }
+    }
+
+    /// The port is opened with the configured baud rate each time
+    /// that a pane is spawned, so that a changed configuration
+    /// takes effect the next time
+    fn pty_system(&self) -> SerialTty {
+        let mut serial = SerialTty::new(&self.port);
+        serial.set_baud_rate(::serial::BaudRate::from_speed(
+            configuration().serial_port_baud_rate,
+        ));
+        serial
+    }
+}
+
+#[async_trait(?Send)]
+impl Domain for SerialDomain {
+    async fn spawn(
+        &self,
+        size: PtySize,
+        command: Option<CommandBuilder>,
+        _command_dir: Option<String>,
+        window: WindowId,
+    ) -> anyhow::Result<Rc<Tab>> {
+        if let Some(cmd) = command {
+            anyhow::ensure!(
+                cmd.is_default_prog(),
+                "a program cannot be run on serial port {}",
+                self.port
+            );
+        }
+
+        let pair = self.pty_system().openpty(size)?;
+        let pane_id = alloc_pane_id();
+        let cmd = CommandBuilder::new_default_prog();
+        let term_config = config::TermConfig::for_pane(&self.name, &cmd);
+        let child = pair.slave.spawn_command(cmd)?;
+        log::trace!("spawned: {:?}", child);
+
+        let writer = pair.master.try_clone_writer()?;
+
+        let terminal = wezterm_term::Terminal::new(
+            crate::pty_size_to_terminal_size(size),
+            std::sync::Arc::new(term_config),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(writer),
+        );
+
+        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
+            pane_id,
+            terminal,
+            child,
+            pair.master,
+            self.id,
+        ));
+
+        let mux = Mux::get().unwrap();
+        let tab = Rc::new(Tab::new(&size));
+        tab.assign_pane(&pane);
+
+        mux.add_tab_and_active_pane(&tab)?;
+        mux.add_tab_to_window(&tab, window)?;
+
+        Ok(tab)
+    }
+
+    async fn split_pane(
+        &self,
+        _command: Option<CommandBuilder>,
+        _command_dir: Option<String>,
+        _tab: TabId,
+        _pane_id: PaneId,
+        _split_direction: SplitDirection,
+    ) -> anyhow::Result<Rc<dyn Pane>> {
+        anyhow::bail!("serial port {} can only be used by one pane", self.port);
+    }
+
+    /// The launcher lists the serial ports that are present at the time
+    /// that it is opened, so we don't want it to also offer the domains
+    /// of adapters that may since have been unplugged
+    fn spawnable(&self) -> bool {
+        false
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.id
+    }
+
+    fn domain_name(&self) -> &str {
+        &self.name
+    }
+
+    fn domain_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn attach(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn detach(&self) -> anyhow::Result<()> {
+        anyhow::bail!("detach not implemented");
+    }
+
+    fn state(&self) -> DomainState {
+        DomainState::Attached
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn serial_ids_are_made_friendly() {
+        use super::friendly_name_from_id;
+        assert_eq!(
+            friendly_name_from_id("usb-FTDI_FT232R_USB_UART_A50285BI-if00-port0"),
+            "FTDI FT232R USB UART A50285BI"
+        );
+        assert_eq!(
+            friendly_name_from_id("usb-Arduino__www.arduino.cc__0043_75834-if00"),
+            "Arduino  www.arduino.cc  0043 75834"
+        );
+        assert_eq!(friendly_name_from_id("my-device"), "my-device");
+    }
+}
@@ -0,0 +1,155 @@
+//! Provides file transfers over the ssh sessions of connected ssh
+//! domains, so that `wezterm cli scp` can copy files without
+//! establishing and authenticating a new connection, and so that
+//! files can be dragged to and from ssh panes in the gui.
+use crate::sshforward::retry_eagain;
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<String, ssh2::Session>> = Mutex::new(HashMap::new());
+}
+
+/// Makes the session of an ssh domain available for file transfers
+/// until it is dropped
+pub struct SftpRegistration {
+    domain_name: String,
+}
+
+impl SftpRegistration {
+    pub fn new(domain_name: &str, sess: ssh2::Session) -> Self {
+        SESSIONS
+            .lock()
+            .unwrap()
+            .insert(domain_name.to_string(), sess);
+        Self {
+            domain_name: domain_name.to_string(),
+        }
+    }
+}
+
+impl Drop for SftpRegistration {
+    fn drop(&mut self) {
+        SESSIONS.lock().unwrap().remove(&self.domain_name);
+    }
+}
+
+/// Returns true if the ssh domain named `domain_name` is connected
+/// and so can transfer files
+pub fn is_available(domain_name: &str) -> bool {
+    SESSIONS.lock().unwrap().contains_key(domain_name)
+}
+
+/// The session may be in non-blocking mode if it has port forwards,
+/// so retry file operations that would block
+fn retry_io<T, F: FnMut() -> std::io::Result<T>>(mut f: F) -> std::io::Result<T> {
+    loop {
+        match f() {
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(5))
+            }
+            result => return result,
+        }
+    }
+}
+
+fn with_sftp<T, F>(domain_name: &str, f: F) -> anyhow::Result<T>
+where
+    F: FnOnce(&ssh2::Sftp) -> anyhow::Result<T>,
+{
+    let sess = SESSIONS
+        .lock()
+        .unwrap()
+        .get(domain_name)
+        .cloned()
+        .ok_or_else(|| anyhow!("ssh domain {} is not connected", domain_name))?;
+    let sftp = retry_eagain(|| sess.sftp()).context("starting sftp")?;
+    f(&sftp)
+}
+
+/// sftp resolves relative paths from the home directory,
+/// so `~/` can simply be removed
+fn remote_path(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(path) => PathBuf::from(path),
+        None if path == "~" => PathBuf::from("."),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Describes a remote file
+pub struct SftpFileInfo {
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// Returns information about a remote file, or None if it doesn't exist
+pub fn stat(domain_name: &str, path: &str) -> anyhow::Result<Option<SftpFileInfo>> {
+    with_sftp(domain_name, |sftp| {
+        match retry_eagain(|| sftp.stat(&remote_path(path))) {
+            Ok(stat) => Ok(Some(SftpFileInfo {
+                size: stat.size.unwrap_or(0),
+                is_dir: stat.is_dir(),
+            })),
+            Err(err) => match err.downcast_ref::<ssh2::Error>() {
+                // LIBSSH2_FX_NO_SUCH_FILE
+                Some(e) if e.code() == ssh2::ErrorCode::SFTP(2) => Ok(None),
+                _ => Err(err).with_context(|| format!("stat {}", path)),
+            },
+        }
+    })
+}
+
+/// Reads up to `len` bytes from the remote file starting at `offset`.
+/// Returns an empty buffer at the end of the file.
+pub fn read(domain_name: &str, path: &str, offset: u64, len: usize) -> anyhow::Result<Vec<u8>> {
+    with_sftp(domain_name, |sftp| {
+        let mut file = retry_eagain(|| sftp.open(&remote_path(path)))
+            .with_context(|| format!("opening {}", path))?;
+        retry_io(|| file.seek(SeekFrom::Start(offset)))?;
+
+        let mut data = vec![0u8; len];
+        let mut filled = 0;
+        while filled < len {
+            let n = retry_io(|| file.read(&mut data[filled..]))
+                .with_context(|| format!("reading {}", path))?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        data.truncate(filled);
+        Ok(data)
+    })
+}
+
+/// Writes `data` to the remote file at `offset`.  The file is created,
+/// or truncated, when `offset` is zero.
+pub fn write(domain_name: &str, path: &str, offset: u64, data: &[u8]) -> anyhow::Result<()> {
+    with_sftp(domain_name, |sftp| {
+        let path_buf = remote_path(path);
+        let flags = if offset == 0 {
+            ssh2::OpenFlags::WRITE | ssh2::OpenFlags::TRUNCATE
+        } else {
+            ssh2::OpenFlags::WRITE
+        };
+        let mut file =
+            retry_eagain(|| sftp.open_mode(&path_buf, flags, 0o644, ssh2::OpenType::File))
+                .with_context(|| format!("opening {}", path))?;
+        retry_io(|| file.seek(SeekFrom::Start(offset)))?;
+
+        let mut written = 0;
+        while written < data.len() {
+            match retry_io(|| file.write(&data[written..])) {
+                Ok(0) => bail!("writing {}: no progress", path),
+                Ok(n) => written += n,
+                Err(err) => return Err(err).with_context(|| format!("writing {}", path)),
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,397 @@
+//! Periodically saves the layout of the windows, tabs and panes in
+//! the mux to disk, so that it can be recreated after wezterm is
+//! restarted, or after a reboot or crash.
+//! Only tabs in the local domain are saved; the processes running in
+//! them cannot be saved, so restoring a pane spawns a fresh shell in
+//! its last known working directory, or re-runs the program that was
+//! in the foreground if it is listed in `session_restore_programs`.
+use crate::domain::{Domain, LocalDomain};
+use crate::pane::{Pane, PaneId};
+use crate::tab::{PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, Tab};
+use crate::window::WindowId;
+use crate::Mux;
+use anyhow::Context;
+use config::configuration;
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use wezterm_term::StableRowIndex;
+
+lazy_static::lazy_static! {
+    static ref SNAPSHOT_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MuxSnapshot {
+    pub windows: Vec<WindowSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WindowSnapshot {
+    pub tabs: Vec<TabSnapshot>,
+    pub active_tab: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TabSnapshot {
+    pub root: PaneNode,
+    pub panes: HashMap<PaneId, PaneSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PaneSnapshot {
+    /// The path to the program that was in the foreground
+    pub foreground_process: Option<String>,
+    /// The text of the last few lines of output
+    pub scrollback: Vec<String>,
+}
+
+/// Returns the path of the snapshot file for the named kind of process,
+/// which distinguishes eg: the gui from the mux server
+pub fn default_snapshot_path(name: &str) -> PathBuf {
+    config::CACHE_DIR.join(format!("session-{}.json", name))
+}
+
+/// Returns the path that this process saves its snapshots to,
+/// if snapshots have been enabled by `start_snapshots`
+pub fn snapshot_path() -> Option<PathBuf> {
+    SNAPSHOT_PATH.lock().unwrap().clone()
+}
+
+/// Arranges to save a snapshot of the mux to `path` every
+/// `session_snapshot_interval_secs` seconds.
+/// Must be called on the main thread.
+pub fn start_snapshots(path: PathBuf) {
+    SNAPSHOT_PATH.lock().unwrap().replace(path.clone());
+
+    promise::spawn::spawn(async move {
+        loop {
+            let interval = configuration().session_snapshot_interval_secs;
+            if interval == 0 {
+                // Disabled; check again later in case the config changes
+                smol::Timer::after(Duration::from_secs(60)).await;
+                continue;
+            }
+            smol::Timer::after(Duration::from_secs(interval)).await;
+
+            let mux = match Mux::get() {
+                Some(mux) => mux,
+                None => return,
+            };
+            let snapshot = MuxSnapshot::capture(&mux);
+            // Don't replace a useful snapshot with an empty one;
+            // the mux may be empty because it is shutting down
+            if snapshot.windows.is_empty() {
+                continue;
+            }
+            if let Err(err) = snapshot.save(&path) {
+                log::error!("saving session snapshot: {:#}", err);
+            }
+        }
+    })
+    .detach();
+}
+
+/// Restores the snapshot at `path` into `domain` if
+/// `restore_session_on_startup` is enabled.
+/// Returns true if any tabs were restored.
+pub async fn restore_on_startup(path: &Path, domain: &Arc<dyn Domain>) -> bool {
+    if !configuration().restore_session_on_startup || !path.exists() {
+        return false;
+    }
+    let snapshot = match MuxSnapshot::load(path) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            log::error!("{:#}", err);
+            return false;
+        }
+    };
+    match snapshot.restore(domain).await {
+        Ok(restored) => restored > 0,
+        Err(err) => {
+            log::error!("restoring session: {:#}", err);
+            false
+        }
+    }
+}
+
+fn is_local_tab(mux: &Mux, tab: &Tab) -> bool {
+    tab.get_active_pane()
+        .and_then(|pane| mux.get_domain(pane.domain_id()))
+        .map(|domain| domain.downcast_ref::<LocalDomain>().is_some())
+        .unwrap_or(false)
+}
+
+fn capture_pane(pane: &Rc<dyn Pane>, scrollback_lines: usize) -> PaneSnapshot {
+    let mut scrollback = vec![];
+    if scrollback_lines > 0 && !pane.is_alt_screen_active() {
+        let dims = pane.get_dimensions();
+        let bottom = dims.physical_top + dims.viewport_rows as StableRowIndex;
+        let top = bottom
+            .saturating_sub(scrollback_lines as StableRowIndex)
+            .max(dims.scrollback_top);
+        let (_, lines) = pane.get_lines(top..bottom);
+        scrollback = lines
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect();
+        while scrollback.last().map(String::is_empty).unwrap_or(false) {
+            scrollback.pop();
+        }
+    }
+
+    PaneSnapshot {
+        foreground_process: pane.get_foreground_process_name(),
+        scrollback,
+    }
+}
+
+/// Returns the leftmost leaf of `node`, which is the pane that
+/// occupies the region before it is split
+fn first_entry(node: &PaneNode) -> Option<&PaneEntry> {
+    match node {
+        PaneNode::Empty => None,
+        PaneNode::Leaf(entry) => Some(entry),
+        PaneNode::Split { left, .. } => first_entry(left),
+    }
+}
+
+fn collect_splits<'a>(node: &'a PaneNode, splits: &mut Vec<&'a SplitDirectionAndSize>) {
+    if let PaneNode::Split { left, right, node } = node {
+        splits.push(node);
+        collect_splits(left, splits);
+        collect_splits(right, splits);
+    }
+}
+
+fn collect_entries<'a>(node: &'a PaneNode, entries: &mut Vec<&'a PaneEntry>) {
+    match node {
+        PaneNode::Empty => {}
+        PaneNode::Leaf(entry) => entries.push(entry),
+        PaneNode::Split { left, right, .. } => {
+            collect_entries(left, entries);
+            collect_entries(right, entries);
+        }
+    }
+}
+
+impl MuxSnapshot {
+    /// Captures the state of the local tabs in the mux.
+    /// Must be called on the main thread.
+    pub fn capture(mux: &Mux) -> Self {
+        let scrollback_lines = configuration().session_snapshot_scrollback_lines;
+        let mut windows = vec![];
+
+        for window_id in mux.iter_windows() {
+            let window = match mux.get_window(window_id) {
+                Some(window) => window,
+                None => continue,
+            };
+            let active_tab_id = window.get_active().map(|tab| tab.tab_id());
+            let mut tabs = vec![];
+            let mut active_tab = 0;
+
+            for tab in window.iter() {
+                if !is_local_tab(mux, tab) {
+                    continue;
+                }
+                if Some(tab.tab_id()) == active_tab_id {
+                    active_tab = tabs.len();
+                }
+                let panes = tab
+                    .iter_panes()
+                    .into_iter()
+                    .map(|pos| {
+                        (
+                            pos.pane.pane_id(),
+                            capture_pane(&pos.pane, scrollback_lines),
+                        )
+                    })
+                    .collect();
+                tabs.push(TabSnapshot {
+                    root: tab.codec_pane_tree(),
+                    panes,
+                });
+            }
+
+            if !tabs.is_empty() {
+                windows.push(WindowSnapshot { tabs, active_tab });
+            }
+        }
+
+        Self { windows }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            config::create_user_owned_dirs(dir)?;
+        }
+        // Write to a temporary file and then rename it into place, so
+        // that a crash part way through doesn't leave a truncated snapshot
+        let temp = path.with_extension("tmp");
+        let data = serde_json::to_vec(self)?;
+        std::fs::write(&temp, data).with_context(|| format!("writing {}", temp.display()))?;
+        std::fs::rename(&temp, path).with_context(|| format!("replacing {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_slice(&data).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Recreates the saved windows, tabs and panes in `domain`.
+    /// Returns the number of tabs that were restored.
+    /// Must be called on the main thread.
+    pub async fn restore(self, domain: &Arc<dyn Domain>) -> anyhow::Result<usize> {
+        let mux = Mux::get().unwrap();
+        let mut restored = 0;
+
+        for window in self.windows {
+            let window_id = mux.new_empty_window();
+            for tab in window.tabs {
+                match restore_tab(domain, *window_id, tab).await {
+                    Ok(()) => restored += 1,
+                    Err(err) => log::error!("restoring tab: {:#}", err),
+                }
+            }
+            if let Some(mut win) = mux.get_window_mut(*window_id) {
+                if window.active_tab < win.len() {
+                    win.set_active(window.active_tab);
+                }
+            }
+        }
+        mux.prune_dead_windows();
+
+        Ok(restored)
+    }
+}
+
+fn command_for(pane: Option<&PaneSnapshot>) -> Option<CommandBuilder> {
+    let process = pane?.foreground_process.as_ref()?;
+    let name = Path::new(process).file_name()?.to_str()?;
+    if configuration()
+        .session_restore_programs
+        .iter()
+        .any(|prog| prog == name)
+    {
+        Some(CommandBuilder::from_argv(vec![process.into()]))
+    } else {
+        None
+    }
+}
+
+fn cwd_for(entry: &PaneEntry) -> Option<String> {
+    match entry.working_dir.as_ref() {
+        Some(url) if url.url.scheme() == "file" => {
+            let path = url.url.path();
+            // On Windows the file URI can produce a path like `/C:\Users`;
+            // strip off the leading slash that the file APIs don't like
+            let bytes = path.as_bytes();
+            if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+                Some(path[1..].to_string())
+            } else {
+                Some(path.to_string())
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Shows the saved output in a restored pane, so that it resembles
+/// the pane that it replaces
+fn restore_scrollback(pane: &Rc<dyn Pane>, snapshot: Option<&PaneSnapshot>) {
+    if let Some(snapshot) = snapshot {
+        if !snapshot.scrollback.is_empty() {
+            let mut text = snapshot.scrollback.join("\r\n");
+            text.push_str("\r\n");
+            pane.advance_bytes(text.as_bytes());
+        }
+    }
+}
+
+async fn restore_tab(
+    domain: &Arc<dyn Domain>,
+    window_id: WindowId,
+    tab: TabSnapshot,
+) -> anyhow::Result<()> {
+    let TabSnapshot { root, panes } = tab;
+    let size = root
+        .root_size()
+        .unwrap_or_else(|| configuration().initial_size());
+    let first = first_entry(&root).ok_or_else(|| anyhow::anyhow!("tab has no panes"))?;
+
+    let new_tab = domain
+        .spawn(
+            size,
+            command_for(panes.get(&first.pane_id)),
+            cwd_for(first),
+            window_id,
+        )
+        .await?;
+    let pane = new_tab
+        .get_active_pane()
+        .ok_or_else(|| anyhow::anyhow!("spawned tab has no pane"))?;
+    restore_scrollback(&pane, panes.get(&first.pane_id));
+
+    // Splitting a pane replaces it with a split whose left side is the
+    // original pane, so working through the saved tree and splitting
+    // the pane that occupies each split node reproduces its shape
+    let mut stack = vec![(&root, pane)];
+    while let Some((node, pane)) = stack.pop() {
+        if let PaneNode::Split {
+            left,
+            right,
+            node: split,
+        } = node
+        {
+            let second = first_entry(right).ok_or_else(|| anyhow::anyhow!("split has no panes"))?;
+            let new_pane = domain
+                .split_pane(
+                    command_for(panes.get(&second.pane_id)),
+                    cwd_for(second),
+                    new_tab.tab_id(),
+                    pane.pane_id(),
+                    split.direction,
+                )
+                .await?;
+            restore_scrollback(&new_pane, panes.get(&second.pane_id));
+            stack.push((right, new_pane));
+            stack.push((left, pane));
+        }
+    }
+
+    // Splits are made evenly; move them back to where they were
+    let mut saved_splits = vec![];
+    collect_splits(&root, &mut saved_splits);
+    for (index, saved) in saved_splits.iter().enumerate() {
+        let current = new_tab.codec_pane_tree();
+        let mut splits = vec![];
+        collect_splits(&current, &mut splits);
+        if let Some(split) = splits.get(index) {
+            let delta = match saved.direction {
+                SplitDirection::Horizontal => saved.first.cols as isize - split.first.cols as isize,
+                SplitDirection::Vertical => saved.first.rows as isize - split.first.rows as isize,
+            };
+            if delta != 0 {
+                new_tab.resize_split_by(index, delta);
+            }
+        }
+    }
+
+    let mut entries = vec![];
+    collect_entries(&root, &mut entries);
+    if let Some(zoomed) = entries.iter().position(|entry| entry.is_zoomed_pane) {
+        new_tab.set_active_idx(zoomed);
+        new_tab.set_zoomed(true);
+    } else if let Some(active) = entries.iter().position(|entry| entry.is_active_pane) {
+        new_tab.set_active_idx(active);
+    }
+
+    Ok(())
+}
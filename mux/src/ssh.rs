@@ -1,49 +1,165 @@
 use crate::connui::ConnectionUI;
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::knownhosts::{
+    host_key_policy, known_hosts_path, public_key_type, randomart, short_key_type, HostKeyDecision,
+    HostKeyInfo, HostKeyStatus,
+};
 use crate::localpane::LocalPane;
 use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::sftp::SftpRegistration;
+use crate::sshconfig::{ResolvedSshConfig, SshConfig};
 use crate::tab::{SplitDirection, Tab, TabId};
 use crate::window::WindowId;
 use crate::Mux;
 use anyhow::{anyhow, bail, Context, Error};
 use async_trait::async_trait;
+use luahelper::impl_lua_conversion;
+use luahelper::mlua;
 use portable_pty::cmdbuilder::CommandBuilder;
 use portable_pty::{PtySize, PtySystem};
 use promise::{Future, Promise};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::io::Write;
 use std::net::TcpStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A single prompt from a keyboard-interactive authentication request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthPrompt {
+    pub prompt: String,
+    /// Whether the response may be shown as it is typed
+    pub echo: bool,
+}
+
+/// Describes a keyboard-interactive authentication request, such as
+/// a password or 2FA code prompt, for the `ssh-auth-prompt` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthPromptInfo {
+    pub host: String,
+    pub username: String,
+    /// The identification string of the ssh server, eg: `SSH-2.0-OpenSSH_8.4`
+    pub server_banner: Option<String>,
+    pub instructions: String,
+    pub prompts: Vec<AuthPrompt>,
+}
+impl_lua_conversion!(AuthPromptInfo);
+
+async fn auth_prompt_event(
+    lua: Option<Rc<mlua::Lua>>,
+    info: AuthPromptInfo,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(None),
+    };
+    let args = lua.pack_multi(info)?;
+    let result =
+        config::lua::emit_async_callback(&lua, ("ssh-auth-prompt".to_string(), args)).await?;
+    Ok(lua.unpack(result)?)
+}
 
-impl ssh2::KeyboardInteractivePrompt for ConnectionUI {
+/// Gives the `ssh-auth-prompt` event handler, if any, the opportunity
+/// to answer a keyboard-interactive authentication request.
+/// Returns None if the user should be asked instead.
+/// Like `host_key_policy`, this must not be called on the main thread.
+fn auth_prompt_policy(info: &AuthPromptInfo) -> Option<Vec<String>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let info = info.clone();
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            let result =
+                config::with_lua_config_on_main_thread(move |lua| auth_prompt_event(lua, info))
+                    .await;
+            tx.send(result).ok();
+        })
+        .detach();
+    })
+    .detach();
+
+    match rx.recv() {
+        Ok(Ok(answers)) => answers,
+        Ok(Err(err)) => {
+            log::error!("while processing ssh-auth-prompt event: {:#}", err);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Answers keyboard-interactive prompts using the `ssh-auth-prompt`
+/// event, asking the user via a form in the connection UI for
+/// any responses that it doesn't provide
+struct InteractivePrompter<'a> {
+    ui: &'a mut ConnectionUI,
+    host: &'a str,
+    server_banner: Option<String>,
+}
+
+impl<'a> ssh2::KeyboardInteractivePrompt for InteractivePrompter<'a> {
     fn prompt<'b>(
         &mut self,
-        _username: &str,
+        username: &str,
         instructions: &str,
         prompts: &[ssh2::Prompt<'b>],
     ) -> Vec<String> {
-        prompts
-            .iter()
-            .map(|p| {
-                self.output_str(&format!("{}\n", instructions));
-                if p.echo {
-                    self.input(&p.text)
-                } else {
-                    self.password(&p.text)
-                }
-                .unwrap_or_else(|_| String::new())
+        let info = AuthPromptInfo {
+            host: self.host.to_string(),
+            username: username.to_string(),
+            server_banner: self.server_banner.clone(),
+            instructions: instructions.to_string(),
+            prompts: prompts
+                .iter()
+                .map(|p| AuthPrompt {
+                    prompt: p.text.to_string(),
+                    echo: p.echo,
+                })
+                .collect(),
+        };
+
+        let answers = match auth_prompt_policy(&info) {
+            Some(answers) if answers.len() == prompts.len() => return answers,
+            Some(answers) if answers.len() < prompts.len() => answers,
+            Some(answers) => {
+                log::error!(
+                    "ssh-auth-prompt event returned {} responses for {} prompts; \
+                     asking the user instead",
+                    answers.len(),
+                    prompts.len()
+                );
+                vec![]
+            }
+            None => vec![],
+        };
+
+        self.ui
+            .auth_form(instructions, &info.prompts, answers)
+            .unwrap_or_else(|err| {
+                log::error!("while asking for ssh authentication: {:#}", err);
+                vec![String::new(); prompts.len()]
             })
-            .collect()
     }
 }
 
-pub fn async_ssh_connect(remote_address: &str, username: &str) -> Future<ssh2::Session> {
+pub fn async_ssh_connect(
+    proxy_jump: Option<&str>,
+    remote_address: &str,
+    username: &str,
+) -> Future<ssh2::Session> {
     let mut promise = Promise::new();
     let future = promise.get_future().unwrap();
+    let proxy_jump = proxy_jump.map(str::to_owned);
     let remote_address = remote_address.to_owned();
     let username = username.to_owned();
-    std::thread::spawn(move || promise.result(ssh_connect(&remote_address, &username)));
+    std::thread::spawn(move || {
+        promise.result(ssh_connect(
+            proxy_jump.as_deref(),
+            &remote_address,
+            &username,
+        ))
+    });
     future
 }
 
@@ -51,89 +167,300 @@ pub fn ssh_connect_with_ui(
     remote_address: &str,
     username: &str,
     ui: &mut ConnectionUI,
+) -> anyhow::Result<ssh2::Session> {
+    ssh_connect_via_with_ui(None, None, remote_address, username, ui)
+}
+
+/// Splits a `[user@]host[:port]` jump host specification into the
+/// username, if any, and address to use for it.
+fn parse_jump_host(spec: &str) -> (Option<&str>, &str) {
+    match spec.find('@') {
+        Some(idx) => (Some(&spec[..idx]), &spec[idx + 1..]),
+        None => (None, spec),
+    }
+}
+
+/// Splits a `host[:port]` address into its host and optional port.
+/// IPv6 addresses may be given with a port as `[addr]:port`; a bare
+/// IPv6 address, which has more than one colon, has no port.
+fn split_host_port(address: &str) -> anyhow::Result<(&str, Option<u16>)> {
+    let parse_port = |port: &str| -> anyhow::Result<u16> {
+        port.parse()
+            .with_context(|| format!("invalid port in {}", address))
+    };
+
+    if let Some(rest) = address.strip_prefix('[') {
+        let idx = rest
+            .find(']')
+            .ok_or_else(|| anyhow!("missing ] in {}", address))?;
+        let host = &rest[..idx];
+        return match &rest[idx + 1..] {
+            "" => Ok((host, None)),
+            port => match port.strip_prefix(':') {
+                Some(port) => Ok((host, Some(parse_port(port)?))),
+                None => bail!("unexpected {} after ] in {}", port, address),
+            },
+        };
+    }
+
+    match address.find(':') {
+        Some(idx) if address[idx + 1..].contains(':') => Ok((address, None)),
+        Some(idx) => Ok((&address[..idx], Some(parse_port(&address[idx + 1..])?))),
+        None => Ok((address, None)),
+    }
+}
+
+/// Applies the user's ssh_config to `address`.  An empty `username`
+/// allows the `User` from the config to take effect.
+fn resolve_host(
+    ssh_config: &SshConfig,
+    address: &str,
+    username: &str,
+) -> anyhow::Result<ResolvedSshConfig> {
+    let (host, port) = split_host_port(address)?;
+    let username = if username.is_empty() {
+        None
+    } else {
+        Some(username)
+    };
+    ssh_config
+        .resolve(host, username, port)
+        .with_context(|| format!("applying ssh config for {}", host))
+}
+
+/// Like `ssh_connect_with_ui`, but when `proxy_jump` is specified the
+/// connection is made by first connecting to each of the comma separated
+/// `[user@]host[:port]` jump hosts in turn, with each hop tunnelled
+/// through the session established with the one before it.
+/// This is equivalent to the `ProxyJump` option of `ssh`; if `proxy_jump`
+/// is not specified, the `ProxyJump` from the ssh_config file is used.
+/// The destination and each jump host are resolved through the user's
+/// ssh_config file, so `HostName`, `Port`, `User`, `IdentityFile` and
+/// `IdentityAgent` are respected.
+/// The first hop is connected through the `ProxyCommand` from the
+/// ssh_config file, if any, or else through `proxy`, which may be a
+/// `socks5://host:port` or `http://host:port` proxy server url.
+pub fn ssh_connect_via_with_ui(
+    proxy_jump: Option<&str>,
+    proxy: Option<&str>,
+    remote_address: &str,
+    username: &str,
+    ui: &mut ConnectionUI,
 ) -> anyhow::Result<ssh2::Session> {
     let cloned_ui = ui.clone();
     cloned_ui.run_and_log_error(move || {
-        let mut sess = ssh2::Session::new()?;
+        let ssh_config = SshConfig::load_default();
+        let destination = resolve_host(&ssh_config, remote_address, username)?;
+        let proxy_jump = proxy_jump.or_else(|| destination.proxy_jump());
+
+        let mut jump = None;
+        for spec in proxy_jump
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+        {
+            let (jump_username, jump_address) = parse_jump_host(spec);
+            let jump_config = resolve_host(&ssh_config, jump_address, jump_username.unwrap_or(""))?;
+            ui.output_str(&format!("Using jump host {}\n", jump_address));
+            let sess = establish_session(jump.take(), proxy, &jump_config, ui)?;
+            jump.replace(sess);
+        }
+        establish_session(jump, proxy, &destination, ui)
+    })
+}
 
-        let (remote_address, remote_host_name, port) = {
-            let parts: Vec<&str> = remote_address.split(':').collect();
+/// Presents an untrusted host key to the user and asks them
+/// what to do about it
+fn prompt_host_key(
+    ui: &mut ConnectionUI,
+    info: &HostKeyInfo,
+    remote_address: &str,
+    file: &Path,
+    art: Option<String>,
+) -> anyhow::Result<HostKeyDecision> {
+    match info.status {
+        HostKeyStatus::Unknown => ui.output_str(&format!(
+            "SSH host {} is not yet trusted.\n",
+            remote_address
+        )),
+        HostKeyStatus::Changed => ui.output_str(&format!(
+            "🛑 host key mismatch for ssh server {}.\n\
+             The key is different from the one recorded in the known_hosts\n\
+             file {}.\n\
+             Someone could be eavesdropping on you (a man-in-the-middle attack),\n\
+             or the host key may have just been changed.\n",
+            remote_address,
+            file.display()
+        )),
+    }
+    ui.output_str(&format!(
+        "{} key fingerprint is {}\n",
+        short_key_type(&info.key_type),
+        info.fingerprint
+    ));
+    if let Some(art) = art {
+        ui.output_str(&art);
+    }
+
+    loop {
+        let line = ui.input("Trust this key [o]nce, [p]ermanently or [r]eject?> ")?;
 
-            if parts.len() == 2 {
-                (remote_address.to_string(), parts[0], parts[1].parse()?)
-            } else {
-                (format!("{}:22", remote_address), remote_address, 22)
+        match line.trim() {
+            "o" | "O" | "once" => return Ok(HostKeyDecision::AcceptOnce),
+            "p" | "P" | "permanently" | "y" | "Y" | "yes" => {
+                return Ok(HostKeyDecision::AcceptPermanently)
             }
-        };
+            "r" | "R" | "reject" | "n" | "N" | "no" => return Ok(HostKeyDecision::Reject),
+            _ => continue,
+        }
+    }
+}
 
-        ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
-
-        let tcp = TcpStream::connect(&remote_address)
-            .with_context(|| format!("ssh connecting to {}", remote_address))?;
-        ui.output_str("SSH: Connected OK!\n");
-        tcp.set_nodelay(true)?;
-        sess.set_tcp_stream(tcp);
-        sess.handshake()
-            .with_context(|| format!("ssh handshake with {}", remote_address))?;
-
-        if let Ok(mut known_hosts) = sess.known_hosts() {
-            let varname = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
-            let var = std::env::var_os(varname)
-                .ok_or_else(|| anyhow!("environment variable {} is missing", varname))?;
-            let file = Path::new(&var).join(".ssh/known_hosts");
-            if file.exists() {
-                known_hosts
-                    .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                    .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+fn establish_session(
+    via: Option<ssh2::Session>,
+    proxy: Option<&str>,
+    ssh_config: &ResolvedSshConfig,
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<ssh2::Session> {
+    let mut sess = ssh2::Session::new()?;
+
+    let remote_host_name = ssh_config.hostname();
+    let port = ssh_config.port();
+    let username = ssh_config.user();
+    let remote_address = format!("{}:{}", remote_host_name, port);
+
+    ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
+
+    match via {
+        Some(jump) => {
+            // Tunnel through the jump host; the relay thread owns the
+            // jump session and shuttles data between the channel and
+            // one end of a socketpair, while the other end serves as
+            // the transport for this session.
+            let (mut ours, theirs) = filedescriptor::socketpair()?;
+            ours.set_non_blocking(true)?;
+            crate::sshforward::relay_to(jump, remote_host_name, port, ours)
+                .with_context(|| format!("ssh connecting to {} via jump host", remote_address))?;
+            ui.output_str("SSH: Connected OK!\n");
+            sess.set_tcp_stream(theirs);
+        }
+        None => match (ssh_config.proxy_command(), proxy) {
+            (Some(command), _) => {
+                ui.output_str(&format!("Using ProxyCommand {}\n", command));
+                let stream = crate::sshproxy::spawn_proxy_command(command)?;
+                sess.set_tcp_stream(stream);
             }
+            (None, Some(proxy)) => {
+                ui.output_str(&format!("Using proxy {}\n", proxy));
+                let tcp = crate::sshproxy::connect_via_proxy(proxy, remote_host_name, port)?;
+                ui.output_str("SSH: Connected OK!\n");
+                tcp.set_nodelay(true)?;
+                sess.set_tcp_stream(tcp);
+            }
+            (None, None) => {
+                let tcp = TcpStream::connect(&remote_address)
+                    .with_context(|| format!("ssh connecting to {}", remote_address))?;
+                ui.output_str("SSH: Connected OK!\n");
+                tcp.set_nodelay(true)?;
+                sess.set_tcp_stream(tcp);
+            }
+        },
+    }
+    sess.handshake()
+        .with_context(|| format!("ssh handshake with {}", remote_address))?;
+
+    if let Ok(mut known_hosts) = sess.known_hosts() {
+        let file = known_hosts_path()?;
+        if file.exists() {
+            known_hosts
+                .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+                .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+        }
+
+        let (key, key_type) = sess
+            .host_key()
+            .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
 
-            let (key, key_type) = sess
-                .host_key()
-                .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
-
-            let fingerprint = sess
-                .host_key_hash(ssh2::HashType::Sha256)
-                .map(|fingerprint| {
-                    format!(
-                        "SHA256:{}",
-                        base64::encode_config(
-                            fingerprint,
-                            base64::Config::new(base64::CharacterSet::Standard, false)
-                        )
+        let fingerprint = sess
+            .host_key_hash(ssh2::HashType::Sha256)
+            .map(|fingerprint| {
+                format!(
+                    "SHA256:{}",
+                    base64::encode_config(
+                        fingerprint,
+                        base64::Config::new(base64::CharacterSet::Standard, false)
                     )
+                )
+            })
+            .or_else(|| {
+                // Querying for the Sha256 can fail if for example we were linked
+                // against libssh < 1.9, so let's fall back to Sha1 in that case.
+                sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
+                    let mut res = vec![];
+                    write!(&mut res, "SHA1").ok();
+                    for b in fingerprint {
+                        write!(&mut res, ":{:02x}", *b).ok();
+                    }
+                    String::from_utf8(res).unwrap()
                 })
-                .or_else(|| {
-                    // Querying for the Sha256 can fail if for example we were linked
-                    // against libssh < 1.9, so let's fall back to Sha1 in that case.
-                    sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
-                        let mut res = vec![];
-                        write!(&mut res, "SHA1").ok();
-                        for b in fingerprint {
-                            write!(&mut res, ":{:02x}", *b).ok();
-                        }
-                        String::from_utf8(res).unwrap()
-                    })
-                })
-                .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
-
-            use ssh2::CheckResult;
-            match known_hosts.check_port(&remote_host_name, port, key) {
-                CheckResult::Match => {}
-                CheckResult::NotFound => {
-                    ui.output_str(&format!(
-                        "SSH host {} is not yet trusted.\n\
-                         {:?} Fingerprint: {}.\n\
-                         Trust and continue connecting?\n",
-                        remote_address, key_type, fingerprint
-                    ));
-
-                    loop {
-                        let line = ui.input("Enter [Y/n]> ")?;
-
-                        match line.as_ref() {
-                            "y" | "Y" | "yes" | "YES" => break,
-                            "n" | "N" | "no" | "NO" => bail!("user declined to trust host"),
-                            _ => continue,
+            })
+            .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
+
+        use ssh2::CheckResult;
+        let status = match known_hosts.check_port(&remote_host_name, port, key) {
+            CheckResult::Match => None,
+            CheckResult::NotFound => Some(HostKeyStatus::Unknown),
+            CheckResult::Mismatch => Some(HostKeyStatus::Changed),
+            CheckResult::Failure => {
+                ui.output_str("🛑 Failed to load and check known ssh hosts\n");
+                bail!("failed to check the known hosts");
+            }
+        };
+
+        if let Some(status) = status {
+            let wire_key_type = public_key_type(key).unwrap_or("unknown");
+            let info = HostKeyInfo {
+                host: remote_host_name.to_string(),
+                port,
+                key_type: wire_key_type.to_string(),
+                fingerprint: fingerprint.clone(),
+                status,
+            };
+
+            let decision = match host_key_policy(&info) {
+                Some(decision) => {
+                    log::info!(
+                        "ssh-host-key event chose {:?} for {} {}",
+                        decision,
+                        remote_address,
+                        fingerprint
+                    );
+                    decision
+                }
+                None => {
+                    let art = sess
+                        .host_key_hash(ssh2::HashType::Sha256)
+                        .map(|hash| randomart(&short_key_type(wire_key_type), "SHA256", hash));
+                    prompt_host_key(ui, &info, &remote_address, &file, art)?
+                }
+            };
+
+            match decision {
+                HostKeyDecision::Reject => bail!("user declined to trust host"),
+                HostKeyDecision::AcceptOnce => {}
+                HostKeyDecision::AcceptPermanently => {
+                    if status == HostKeyStatus::Changed {
+                        for host in known_hosts.hosts()? {
+                            let matches = host
+                                .name()
+                                .map(|names| names.split(',').any(|n| n == remote_host_name))
+                                .unwrap_or(false);
+                            if matches {
+                                known_hosts
+                                    .remove(&host)
+                                    .context("removing stale known_hosts entry")?;
+                            }
                         }
                     }
 
@@ -145,74 +472,354 @@ pub fn ssh_connect_with_ui(
                         .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
                         .with_context(|| format!("writing known_hosts file {}", file.display()))?;
                 }
-                CheckResult::Mismatch => {
-                    ui.output_str(&format!(
-                        "🛑 host key mismatch for ssh server {}.\n\
-                         Got fingerprint {} instead of expected value from known_hosts\n\
-                         file {}.\n\
-                         Refusing to connect.\n",
-                        remote_address,
-                        fingerprint,
-                        file.display()
-                    ));
-                    bail!("host mismatch, man in the middle attack?!");
+            }
+        }
+    }
+
+    let mut offered_gssapi = false;
+    for _ in 0..3 {
+        if sess.authenticated() {
+            break;
+        }
+
+        // Re-query the auth methods on each loop as a successful method
+        // may unlock a new method on a subsequent iteration (eg: password
+        // auth may then unlock 2fac)
+        let methods: HashSet<&str> = sess.auth_methods(&username)?.split(',').collect();
+        log::trace!("ssh auth methods: {:?}", methods);
+        offered_gssapi |= methods.contains("gssapi-with-mic");
+
+        if !sess.authenticated() && methods.contains("publickey") {
+            match ssh_config.identity_agent() {
+                Some("none") => {}
+                identity_agent => {
+                    if let Err(err) = agent_auth(&sess, &username, identity_agent, ui) {
+                        log::warn!("while attempting agent auth: {:#}", err);
+                    }
                 }
-                CheckResult::Failure => {
-                    ui.output_str("🛑 Failed to load and check known ssh hosts\n");
-                    bail!("failed to check the known hosts");
+            }
+            if !sess.authenticated() {
+                if let Err(err) = identity_file_auth(
+                    &sess,
+                    &username,
+                    &ssh_config.identity_files(),
+                    &ssh_config.certificate_files(),
+                    ui,
+                ) {
+                    log::warn!("while attempting identity file auth: {:#}", err);
                 }
             }
+            if sess.authenticated() {
+                ui.output_str("publickey auth successful!\n");
+            }
         }
 
-        for _ in 0..3 {
-            if sess.authenticated() {
-                break;
+        if !sess.authenticated() && methods.contains("password") {
+            ui.output_str(&format!(
+                "Password authentication for {}@{}\n",
+                username, remote_address
+            ));
+            let pass = ui.password("🔐 Password: ")?;
+            if let Err(err) = sess.userauth_password(username, &pass) {
+                log::error!("while attempting password auth: {}", err);
             }
+        }
 
-            // Re-query the auth methods on each loop as a successful method
-            // may unlock a new method on a subsequent iteration (eg: password
-            // auth may then unlock 2fac)
-            let methods: HashSet<&str> = sess.auth_methods(&username)?.split(',').collect();
-            log::trace!("ssh auth methods: {:?}", methods);
-
-            if !sess.authenticated() && methods.contains("publickey") {
-                if let Err(err) = sess.userauth_agent(&username) {
-                    log::warn!("while attempting agent auth: {}", err);
-                } else if sess.authenticated() {
-                    ui.output_str("publickey auth successful!\n");
-                }
+        if !sess.authenticated() && methods.contains("keyboard-interactive") {
+            let mut prompter = InteractivePrompter {
+                ui: &mut *ui,
+                host: remote_host_name,
+                server_banner: sess.banner().map(str::to_string),
+            };
+            if let Err(err) = sess.userauth_keyboard_interactive(&username, &mut prompter) {
+                log::error!("while attempting keyboard-interactive auth: {}", err);
             }
+        }
+    }
+
+    if !sess.authenticated() {
+        if offered_gssapi {
+            // libssh2 doesn't implement GSSAPI, so there is nothing we can
+            // do here other than make it clear why we failed.
+            ui.output_str(
+                "The server offered GSSAPI (Kerberos) authentication, \
+                 which is not supported by wezterm's ssh client.\n\
+                 Ask your administrator to enable publickey, password \
+                 or keyboard-interactive authentication.\n",
+            );
+        }
+        bail!("unable to authenticate session");
+    }
+
+    Ok(sess)
+}
+
+/// FIDO2 security keys (eg: `sk-ssh-ed25519@openssh.com`) require that
+/// the user touch the key to confirm their presence when signing
+fn is_security_key(key_type: &str) -> bool {
+    key_type.starts_with("sk-")
+}
 
-            if !sess.authenticated() && methods.contains("password") {
+/// Authenticate using the identities held by the ssh agent.
+/// This is equivalent to `Session::userauth_agent` except that we try
+/// each identity ourselves so that we can prompt the user to touch
+/// their security key when the agent is about to use an `sk-*` identity;
+/// libssh2 cannot use those keys directly, but the agent can.
+fn agent_auth(
+    sess: &ssh2::Session,
+    username: &str,
+    identity_agent: Option<&str>,
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<()> {
+    let mut agent = sess.agent()?;
+    connect_agent(&mut agent, identity_agent)?;
+    agent.list_identities()?;
+
+    let mut have_security_key = false;
+    for identity in agent.identities()? {
+        let key_type = public_key_type(identity.blob()).unwrap_or("unknown");
+        if is_security_key(key_type) {
+            have_security_key = true;
+            ui.output_str(&format!(
+                "🔑 Touch your security key to authenticate using {} {}\n",
+                key_type,
+                identity.comment()
+            ));
+        }
+        match agent.userauth(username, &identity) {
+            Ok(_) => break,
+            Err(err) => log::trace!(
+                "agent identity {} {} was not accepted: {}",
+                key_type,
+                identity.comment(),
+                err
+            ),
+        }
+    }
+    agent.disconnect().ok();
+
+    if !have_security_key && !sess.authenticated() {
+        for name in &["id_ecdsa_sk", "id_ed25519_sk"] {
+            let path = config::HOME_DIR.join(".ssh").join(name);
+            if path.exists() {
                 ui.output_str(&format!(
-                    "Password authentication for {}@{}\n",
-                    username, remote_address
+                    "Security key {} is not loaded into your ssh agent; \
+                     wezterm can only use security keys via the agent.\n\
+                     Run `ssh-add {}` and try again.\n",
+                    path.display(),
+                    path.display()
                 ));
-                let pass = ui.password("🔐 Password: ")?;
-                if let Err(err) = sess.userauth_password(username, &pass) {
-                    log::error!("while attempting password auth: {}", err);
-                }
             }
+        }
+    }
 
-            if !sess.authenticated() && methods.contains("keyboard-interactive") {
-                if let Err(err) = sess.userauth_keyboard_interactive(&username, ui) {
-                    log::error!("while attempting keyboard-interactive auth: {}", err);
-                }
+    if sess.authenticated() {
+        Ok(())
+    } else {
+        bail!("no agent identity was accepted by the server")
+    }
+}
+
+/// Connects to the ssh agent.  libssh2 only knows how to find the
+/// agent via the `SSH_AUTH_SOCK` environment variable and the ssh2
+/// crate offers no way to give it a different socket, so an
+/// `IdentityAgent` other than `none` or `SSH_AUTH_SOCK` is not
+/// supported; we say so once and use the usual agent instead.
+fn connect_agent(agent: &mut ssh2::Agent, identity_agent: Option<&str>) -> anyhow::Result<()> {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    match identity_agent {
+        None | Some("SSH_AUTH_SOCK") | Some("$SSH_AUTH_SOCK") => {}
+        Some(identity_agent) => {
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                log::warn!(
+                    "IdentityAgent {} is not supported; \
+                     using the agent named by SSH_AUTH_SOCK instead",
+                    identity_agent
+                );
             }
         }
+    }
+    Ok(agent.connect()?)
+}
 
-        if !sess.authenticated() {
-            bail!("unable to authenticate session");
+/// Returns true if the private key at `path` is protected by a passphrase
+fn key_file_is_encrypted(path: &Path) -> bool {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    if text.contains("ENCRYPTED") {
+        // Traditional PEM format keys declare their encryption
+        return true;
+    }
+    if text.contains("BEGIN OPENSSH PRIVATE KEY") {
+        // The OpenSSH format has a magic string followed by
+        // the name of the cipher, which is "none" when unencrypted
+        let body: String = text.lines().filter(|l| !l.starts_with("-----")).collect();
+        if let Ok(blob) = base64::decode(body.trim()) {
+            if let Some(rest) = blob.strip_prefix(b"openssh-key-v1\0".as_ref()) {
+                return public_key_type(rest) != Some("none");
+            }
         }
+    }
+    false
+}
 
-        Ok(sess)
-    })
+fn read_u32(blob: &mut &[u8]) -> Option<u32> {
+    if blob.len() < 4 {
+        return None;
+    }
+    let (value, rest) = blob.split_at(4);
+    *blob = rest;
+    Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+fn read_u64(blob: &mut &[u8]) -> Option<u64> {
+    let high = read_u32(blob)? as u64;
+    let low = read_u32(blob)? as u64;
+    Some((high << 32) | low)
 }
 
-pub fn ssh_connect(remote_address: &str, username: &str) -> anyhow::Result<ssh2::Session> {
+fn read_string<'a>(blob: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_u32(blob)? as usize;
+    if blob.len() < len {
+        return None;
+    }
+    let (value, rest) = blob.split_at(len);
+    *blob = rest;
+    Some(value)
+}
+
+/// Returns the validity period of an OpenSSH certificate, in seconds
+/// since the epoch, as `(valid_after, valid_before)`.
+/// See PROTOCOL.certkeys in the OpenSSH sources for the format.
+fn certificate_validity(blob: &[u8]) -> Option<(u64, u64)> {
+    let mut blob = blob;
+    let cert_type = std::str::from_utf8(read_string(&mut blob)?).ok()?;
+    let key_type = cert_type.strip_suffix("-cert-v01@openssh.com")?;
+    // The number of fields that hold the public key itself
+    let key_fields = match key_type {
+        "ssh-rsa" => 2,
+        "ssh-dss" => 4,
+        "ssh-ed25519" => 1,
+        "sk-ssh-ed25519" => 2,
+        t if t.starts_with("ecdsa-sha2-") => 2,
+        t if t.starts_with("sk-ecdsa-sha2-") => 3,
+        _ => return None,
+    };
+
+    // nonce, then the key fields
+    for _ in 0..1 + key_fields {
+        read_string(&mut blob)?;
+    }
+    let _serial = read_u64(&mut blob)?;
+    let _cert_kind = read_u32(&mut blob)?;
+    let _key_id = read_string(&mut blob)?;
+    let _principals = read_string(&mut blob)?;
+    let valid_after = read_u64(&mut blob)?;
+    let valid_before = read_u64(&mut blob)?;
+    Some((valid_after, valid_before))
+}
+
+/// Returns false, after explaining why, if the certificate at `path`
+/// is outside of its validity period.  Certificates that can't be
+/// parsed are assumed to be usable and left for the server to judge.
+fn certificate_is_current(path: &Path, ui: &mut ConnectionUI) -> bool {
+    let validity = std::fs::read_to_string(path).ok().and_then(|text| {
+        let encoded = text.split_whitespace().nth(1)?;
+        let blob = base64::decode(encoded).ok()?;
+        certificate_validity(&blob)
+    });
+    let (valid_after, valid_before) = match validity {
+        Some(validity) => validity,
+        None => return true,
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if now < valid_after {
+        ui.output_str(&format!(
+            "Certificate {} is not valid for another {} seconds\n",
+            path.display(),
+            valid_after - now
+        ));
+        false
+    } else if now >= valid_before {
+        ui.output_str(&format!(
+            "Certificate {} expired {} seconds ago; obtain a new one and try again\n",
+            path.display(),
+            now - valid_before
+        ));
+        false
+    } else {
+        true
+    }
+}
+
+/// Authenticate using the private key files from `IdentityFile`,
+/// or the default key locations, prompting for passphrases as needed.
+/// OpenSSH certificates, from `CertificateFile` or alongside the key
+/// as `KEY-cert.pub`, are offered before the plain key.
+fn identity_file_auth(
+    sess: &ssh2::Session,
+    username: &str,
+    files: &[PathBuf],
+    certificates: &[PathBuf],
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<()> {
+    for file in files {
+        if !file.exists() {
+            continue;
+        }
+        let passphrase = if key_file_is_encrypted(file) {
+            Some(ui.password(&format!("🔐 Passphrase for {}: ", file.display()))?)
+        } else {
+            None
+        };
+
+        let mut public_keys: Vec<Option<PathBuf>> = certificates
+            .iter()
+            .cloned()
+            .chain(std::iter::once(PathBuf::from(format!(
+                "{}-cert.pub",
+                file.display()
+            ))))
+            .filter(|cert| cert.exists() && certificate_is_current(cert, ui))
+            .map(Some)
+            .collect();
+        public_keys.push(None);
+
+        for public_key in public_keys {
+            match sess.userauth_pubkey_file(
+                username,
+                public_key.as_deref(),
+                file,
+                passphrase.as_deref(),
+            ) {
+                Ok(_) if sess.authenticated() => return Ok(()),
+                Ok(_) => {}
+                Err(err) => log::trace!(
+                    "identity {} with {:?} was not accepted: {}",
+                    file.display(),
+                    public_key,
+                    err
+                ),
+            }
+        }
+    }
+    bail!("no identity file was accepted by the server")
+}
+
+pub fn ssh_connect(
+    proxy_jump: Option<&str>,
+    remote_address: &str,
+    username: &str,
+) -> anyhow::Result<ssh2::Session> {
     let mut ui = ConnectionUI::new();
     ui.title("🔐 wezterm: SSH authentication");
-    let sess = ssh_connect_with_ui(remote_address, username, &mut ui)?;
+    let sess = ssh_connect_via_with_ui(proxy_jump, None, remote_address, username, &mut ui)?;
     ui.close();
     Ok(sess)
 }
@@ -221,6 +828,7 @@ pub struct RemoteSshDomain {
     pty_system: Box<dyn PtySystem>,
     id: DomainId,
     name: String,
+    _sftp: Option<SftpRegistration>,
 }
 
 impl RemoteSshDomain {
@@ -230,19 +838,26 @@ impl RemoteSshDomain {
             pty_system,
             id,
             name: format!("SSH to {}", name),
+            _sftp: None,
         }
     }
+
+    /// Allows files to be transferred over `sess` to and from
+    /// the panes of this domain
+    pub fn with_sftp_session(mut self, sess: ssh2::Session) -> Self {
+        self._sftp.replace(SftpRegistration::new(&self.name, sess));
+        self
+    }
 }
 
-#[async_trait(?Send)]
-impl Domain for RemoteSshDomain {
-    async fn spawn(
+impl RemoteSshDomain {
+    /// Opens a new channel over the existing ssh session and runs
+    /// `command` in it, so that no further authentication is needed
+    fn spawn_pane(
         &self,
         size: PtySize,
         command: Option<CommandBuilder>,
-        _command_dir: Option<String>,
-        window: WindowId,
-    ) -> Result<Rc<Tab>, Error> {
+    ) -> anyhow::Result<Rc<dyn Pane>> {
         let mut cmd = match command {
             Some(c) => c,
             None => CommandBuilder::new_default_prog(),
@@ -250,6 +865,7 @@ impl Domain for RemoteSshDomain {
         let pair = self.pty_system.openpty(size)?;
         let pane_id = alloc_pane_id();
         cmd.env("WEZTERM_PANE", pane_id.to_string());
+        let term_config = config::TermConfig::for_pane(&self.name, &cmd);
         let child = pair.slave.spawn_command(cmd)?;
         log::trace!("spawned: {:?}", child);
 
@@ -257,20 +873,30 @@ impl Domain for RemoteSshDomain {
 
         let terminal = wezterm_term::Terminal::new(
             crate::pty_size_to_terminal_size(size),
-            std::sync::Arc::new(config::TermConfig {}),
+            std::sync::Arc::new(term_config),
             "WezTerm",
             config::wezterm_version(),
             Box::new(writer),
         );
 
+        Ok(Rc::new(
+            LocalPane::new(pane_id, terminal, child, pair.master, self.id).with_local_echo(),
+        ))
+    }
+}
+
+#[async_trait(?Send)]
+impl Domain for RemoteSshDomain {
+    async fn spawn(
+        &self,
+        size: PtySize,
+        command: Option<CommandBuilder>,
+        _command_dir: Option<String>,
+        window: WindowId,
+    ) -> Result<Rc<Tab>, Error> {
+        let pane = self.spawn_pane(size, command)?;
+
         let mux = Mux::get().unwrap();
-        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
-            pane_id,
-            terminal,
-            child,
-            pair.master,
-            self.id,
-        ));
         let tab = Rc::new(Tab::new(&size));
         tab.assign_pane(&pane);
 
@@ -282,13 +908,39 @@ impl Domain for RemoteSshDomain {
 
     async fn split_pane(
         &self,
-        _command: Option<CommandBuilder>,
+        command: Option<CommandBuilder>,
         _command_dir: Option<String>,
-        _tab: TabId,
-        _pane_id: PaneId,
-        _split_direction: SplitDirection,
+        tab: TabId,
+        pane_id: PaneId,
+        direction: SplitDirection,
     ) -> anyhow::Result<Rc<dyn Pane>> {
-        bail!("spawn_pane not implemented for RemoteSshDomain");
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_tab(tab) {
+            Some(t) => t,
+            None => anyhow::bail!("Invalid tab id {}", tab),
+        };
+
+        let pane_index = match tab
+            .iter_panes()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+        {
+            Some(p) => p.index,
+            None => anyhow::bail!("invalid pane id {}", pane_id),
+        };
+
+        let split_size = match tab.compute_split_size(pane_index, direction) {
+            Some(s) => s,
+            None => anyhow::bail!("invalid pane index {}", pane_index),
+        };
+
+        let pane = self.spawn_pane(split_size.second, command)?;
+
+        tab.split_and_insert(pane_index, direction, Rc::clone(&pane))?;
+
+        mux.add_pane(&pane)?;
+
+        Ok(pane)
     }
 
     fn domain_id(&self) -> DomainId {
@@ -311,3 +963,51 @@ impl Domain for RemoteSshDomain {
         DomainState::Attached
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn put_string(blob: &mut Vec<u8>, value: &[u8]) {
+        blob.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        blob.extend_from_slice(value);
+    }
+
+    #[test]
+    fn host_and_port() {
+        assert_eq!(
+            split_host_port("example.com").unwrap(),
+            ("example.com", None)
+        );
+        assert_eq!(
+            split_host_port("example.com:2222").unwrap(),
+            ("example.com", Some(2222))
+        );
+        assert_eq!(split_host_port("[::1]:2222").unwrap(), ("::1", Some(2222)));
+        assert_eq!(split_host_port("[fe80::1]").unwrap(), ("fe80::1", None));
+        assert_eq!(split_host_port("fe80::1").unwrap(), ("fe80::1", None));
+        assert!(split_host_port("example.com:ssh").is_err());
+        assert!(split_host_port("[::1").is_err());
+        assert!(split_host_port("[::1]2222").is_err());
+    }
+
+    #[test]
+    fn certificate_validity_period() {
+        let mut blob = vec![];
+        put_string(&mut blob, b"ssh-ed25519-cert-v01@openssh.com");
+        put_string(&mut blob, &[0u8; 32]); // nonce
+        put_string(&mut blob, &[1u8; 32]); // public key
+        blob.extend_from_slice(&7u64.to_be_bytes()); // serial
+        blob.extend_from_slice(&1u32.to_be_bytes()); // user certificate
+        put_string(&mut blob, b"key-id");
+        put_string(&mut blob, b"");
+        blob.extend_from_slice(&1_600_000_000u64.to_be_bytes());
+        blob.extend_from_slice(&1_700_000_000u64.to_be_bytes());
+
+        assert_eq!(
+            certificate_validity(&blob),
+            Some((1_600_000_000, 1_700_000_000))
+        );
+        assert_eq!(certificate_validity(&blob[..20]), None);
+    }
+}
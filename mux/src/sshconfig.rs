@@ -0,0 +1,869 @@
+//! A parser for OpenSSH `ssh_config` files, so that ssh domains and
+//! `wezterm ssh` resolve hosts, ports, users and identities in the
+//! same way as `ssh` would.
+//! Options are resolved using the same "first obtained value wins"
+//! rule as OpenSSH, with `Host` and `Match` blocks (including
+//! `Match exec`), `Include`, `%` token expansion and hostname
+//! canonicalization.
+use anyhow::{anyhow, bail, Context};
+use std::collections::BTreeMap;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+
+/// Options that may be specified multiple times, accumulating values
+/// rather than taking the first obtained value
+const MULTI_VALUED: &[&str] = &[
+    "certificatefile",
+    "dynamicforward",
+    "identityfile",
+    "localforward",
+    "remoteforward",
+];
+
+/// Options whose values are subject to `%` token expansion
+const EXPANDED: &[&str] = &[
+    "certificatefile",
+    "controlpath",
+    "identityagent",
+    "identityfile",
+    "localcommand",
+    "proxycommand",
+    "remotecommand",
+    "userknownhostsfile",
+];
+
+/// Options whose values are paths that may start with `~`
+const PATHS: &[&str] = &[
+    "certificatefile",
+    "controlpath",
+    "identityagent",
+    "identityfile",
+    "userknownhostsfile",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    negated: bool,
+    pattern: String,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        match pattern.strip_prefix('!') {
+            Some(pattern) => Self {
+                negated: true,
+                pattern: pattern.to_lowercase(),
+            },
+            None => Self {
+                negated: false,
+                pattern: pattern.to_lowercase(),
+            },
+        }
+    }
+
+    fn matches(&self, subject: &str) -> bool {
+        wildcard_match(self.pattern.as_bytes(), subject.to_lowercase().as_bytes())
+    }
+}
+
+/// Matches `subject` against a pattern that may contain `*` and `?`
+fn wildcard_match(pattern: &[u8], subject: &[u8]) -> bool {
+    match (pattern.first(), subject.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], subject)
+                || (!subject.is_empty() && wildcard_match(pattern, &subject[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &subject[1..]),
+        (Some(p), Some(s)) if p == s => wildcard_match(&pattern[1..], &subject[1..]),
+        _ => false,
+    }
+}
+
+/// Implements the pattern-list semantics of ssh_config: the list
+/// matches if any pattern matches, unless a negated pattern matches
+fn match_pattern_list(patterns: &[Pattern], subject: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if pattern.matches(subject) {
+            if pattern.negated {
+                return false;
+            }
+            matched = true;
+        }
+    }
+    matched
+}
+
+fn pattern_list(list: &str) -> Vec<Pattern> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(Pattern::new)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MatchCriterion {
+    All,
+    Canonical,
+    Final,
+    Exec(String),
+    Host(Vec<Pattern>),
+    OriginalHost(Vec<Pattern>),
+    User(Vec<Pattern>),
+    LocalUser(Vec<Pattern>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Criteria {
+    /// Options that appear before the first `Host` or `Match`
+    Always,
+    Host(Vec<Pattern>),
+    /// Each criterion may be negated with a `!` prefix
+    Match(Vec<(bool, MatchCriterion)>),
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    criteria: Criteria,
+    options: Vec<(String, String)>,
+}
+
+/// Splits a config line into whitespace separated words,
+/// honoring double quotes
+fn tokenize(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_quotes {
+        bail!("unterminated quote in `{}`", line);
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+fn parse_match(args: &[String]) -> anyhow::Result<Vec<(bool, MatchCriterion)>> {
+    let mut criteria = vec![];
+    let mut iter = args.iter();
+    while let Some(word) = iter.next() {
+        let lower = word.to_lowercase();
+        let (negated, keyword) = match lower.strip_prefix('!') {
+            Some(keyword) => (true, keyword.to_string()),
+            None => (false, lower),
+        };
+        let mut arg = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Match {} requires an argument", keyword))
+        };
+        let criterion = match keyword.as_str() {
+            "all" => MatchCriterion::All,
+            "canonical" => MatchCriterion::Canonical,
+            "final" => MatchCriterion::Final,
+            "exec" => MatchCriterion::Exec(arg()?),
+            "host" => MatchCriterion::Host(pattern_list(&arg()?)),
+            "originalhost" => MatchCriterion::OriginalHost(pattern_list(&arg()?)),
+            "user" => MatchCriterion::User(pattern_list(&arg()?)),
+            "localuser" => MatchCriterion::LocalUser(pattern_list(&arg()?)),
+            _ => bail!("unsupported Match criteria `{}`", word),
+        };
+        criteria.push((negated, criterion));
+    }
+    Ok(criteria)
+}
+
+fn local_username() -> String {
+    config::username_from_env().unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn local_hostname() -> String {
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        if libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) != 0 {
+            0
+        } else {
+            buf.iter().position(|&b| b == 0).unwrap_or(buf.len())
+        }
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(windows)]
+fn local_hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+fn expand_tilde(value: &str) -> String {
+    match value.strip_prefix("~/") {
+        Some(rest) => config::HOME_DIR.join(rest).display().to_string(),
+        None if value == "~" => config::HOME_DIR.display().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// The values available for `%` token expansion
+struct Tokens<'a> {
+    host: &'a str,
+    original_host: &'a str,
+    port: u16,
+    remote_user: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn expand(&self, value: &str) -> anyhow::Result<String> {
+        let mut result = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => result.push('%'),
+                Some('h') => result.push_str(self.host),
+                Some('n') => result.push_str(self.original_host),
+                Some('p') => result.push_str(&self.port.to_string()),
+                Some('r') => result.push_str(self.remote_user),
+                Some('u') => result.push_str(&local_username()),
+                Some('d') => result.push_str(&config::HOME_DIR.display().to_string()),
+                Some('l') => result.push_str(&local_hostname()),
+                Some('L') => {
+                    let hostname = local_hostname();
+                    result.push_str(hostname.split('.').next().unwrap_or(""));
+                }
+                #[cfg(unix)]
+                Some('i') => result.push_str(&unsafe { libc::getuid() }.to_string()),
+                Some(t) => bail!("unsupported token %{} in `{}`", t, value),
+                None => bail!("incomplete token at end of `{}`", value),
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<Block>,
+}
+
+impl SshConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the user's `~/.ssh/config` followed by the system
+    /// `ssh_config` file.  Problems with either file are logged
+    /// rather than treated as fatal.
+    pub fn load_default() -> Self {
+        let mut config = Self::new();
+        let user_dir = config::HOME_DIR.join(".ssh");
+        if let Err(err) = config.add_config_file(&user_dir.join("config"), &user_dir) {
+            log::error!("{:#}", err);
+        }
+        let system_dir = Path::new("/etc/ssh");
+        if let Err(err) = config.add_config_file(&system_dir.join("ssh_config"), system_dir) {
+            log::error!("{:#}", err);
+        }
+        config
+    }
+
+    /// Parses the file at `path`, if it exists.  Relative `Include`
+    /// paths are resolved relative to `include_dir`.
+    pub fn add_config_file(&mut self, path: &Path, include_dir: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading ssh config {}", path.display()))?;
+        self.add_config_str(&text, include_dir)
+            .with_context(|| format!("parsing ssh config {}", path.display()))
+    }
+
+    /// Parses ssh_config formatted `text`.  Relative `Include`
+    /// paths are resolved relative to `include_dir`.
+    pub fn add_config_str(&mut self, text: &str, include_dir: &Path) -> anyhow::Result<()> {
+        // Options before the first Host or Match line are unconditional
+        self.blocks.push(Block {
+            criteria: Criteria::Always,
+            options: vec![],
+        });
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // The keyword may be separated from its arguments by
+            // whitespace or by an `=` sign
+            let split = line
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(line.len());
+            let keyword = line[..split].to_lowercase();
+            let rest = line[split..].trim_start();
+            let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+            let args = tokenize(rest).with_context(|| format!("line {}", line_number + 1))?;
+
+            match keyword.as_str() {
+                "host" => self.blocks.push(Block {
+                    criteria: Criteria::Host(args.iter().map(|p| Pattern::new(p)).collect()),
+                    options: vec![],
+                }),
+                "match" => {
+                    let criteria =
+                        parse_match(&args).with_context(|| format!("line {}", line_number + 1))?;
+                    self.blocks.push(Block {
+                        criteria: Criteria::Match(criteria),
+                        options: vec![],
+                    });
+                }
+                "include" => {
+                    for arg in &args {
+                        self.include(arg, include_dir)?;
+                    }
+                }
+                _ => {
+                    if args.is_empty() {
+                        bail!("line {}: {} requires a value", line_number + 1, keyword);
+                    }
+                    self.blocks
+                        .last_mut()
+                        .unwrap()
+                        .options
+                        .push((keyword, args.join(" ")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, pattern: &str, include_dir: &Path) -> anyhow::Result<()> {
+        let path = PathBuf::from(expand_tilde(pattern));
+        let path = if path.is_relative() {
+            include_dir.join(path)
+        } else {
+            path
+        };
+
+        let file_pattern = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("")
+            .to_string();
+        if !file_pattern.contains('*') && !file_pattern.contains('?') {
+            return self.include_file(&path, include_dir);
+        }
+
+        // Wildcards are supported in the final path component
+        let dir = path.parent().unwrap_or(include_dir);
+        let mut matches = vec![];
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            for entry in entries.filter_map(Result::ok) {
+                if let Some(name) = entry.file_name().to_str() {
+                    if wildcard_match(file_pattern.as_bytes(), name.as_bytes()) {
+                        matches.push(entry.path());
+                    }
+                }
+            }
+        }
+        matches.sort();
+        for path in matches {
+            self.include_file(&path, include_dir)?;
+        }
+        Ok(())
+    }
+
+    fn include_file(&mut self, path: &Path, include_dir: &Path) -> anyhow::Result<()> {
+        let mut included = Self::new();
+        included.add_config_file(path, include_dir)?;
+
+        // Unconditional options in the included file apply within the
+        // block that contains the Include
+        let mut blocks = included.blocks.into_iter();
+        if let Some(first) = blocks.next() {
+            self.blocks
+                .last_mut()
+                .unwrap()
+                .options
+                .extend(first.options);
+        }
+        let rest: Vec<Block> = blocks.collect();
+        if !rest.is_empty() {
+            // Any Host or Match blocks in the included file end at the end
+            // of that file, and the lines following the Include continue
+            // the block that contained it
+            let criteria = self.blocks.last().unwrap().criteria.clone();
+            self.blocks.extend(rest);
+            self.blocks.push(Block {
+                criteria,
+                options: vec![],
+            });
+        }
+        Ok(())
+    }
+
+    /// Resolves the options that apply to `host`.
+    /// `user` and `port` are those that were explicitly requested
+    /// and take precedence over the configuration.
+    pub fn resolve(
+        &self,
+        host: &str,
+        user: Option<&str>,
+        port: Option<u16>,
+    ) -> anyhow::Result<ResolvedSshConfig> {
+        let mut options = BTreeMap::new();
+        let mut target = host.to_string();
+
+        self.apply_blocks(&mut options, host, &target, user, false)?;
+        if let Some(hostname) = first(&options, "hostname") {
+            target = Tokens {
+                host,
+                original_host: host,
+                port: 22,
+                remote_user: "",
+            }
+            .expand(hostname)?;
+        }
+
+        if let Some(canonical) = canonicalize(&options, &target)? {
+            log::trace!("ssh config: canonicalized {} to {}", target, canonical);
+            target = canonical;
+            // Re-parse the configuration for the canonical name; options
+            // that were already set keep their values
+            self.apply_blocks(&mut options, host, &target, user, true)?;
+        }
+
+        let port = match port {
+            Some(port) => port,
+            None => match first(&options, "port") {
+                Some(port) => port
+                    .parse()
+                    .with_context(|| format!("invalid Port {} for {}", port, host))?,
+                None => 22,
+            },
+        };
+        let remote_user = match user {
+            Some(user) => user.to_string(),
+            None => first(&options, "user")
+                .map(str::to_string)
+                .unwrap_or_else(local_username),
+        };
+
+        let tokens = Tokens {
+            host: &target,
+            original_host: host,
+            port,
+            remote_user: &remote_user,
+        };
+        for (key, values) in options.iter_mut() {
+            if EXPANDED.contains(&key.as_str()) {
+                for value in values.iter_mut() {
+                    *value = tokens.expand(value)?;
+                }
+            }
+            if PATHS.contains(&key.as_str()) {
+                for value in values.iter_mut() {
+                    *value = expand_tilde(value);
+                }
+            }
+        }
+
+        options.insert("hostname".to_string(), vec![target]);
+        options.insert("port".to_string(), vec![port.to_string()]);
+        options.insert("user".to_string(), vec![remote_user]);
+
+        Ok(ResolvedSshConfig { options })
+    }
+
+    fn apply_blocks(
+        &self,
+        options: &mut BTreeMap<String, Vec<String>>,
+        original_host: &str,
+        host: &str,
+        user: Option<&str>,
+        canonical: bool,
+    ) -> anyhow::Result<()> {
+        for block in &self.blocks {
+            let matched = match &block.criteria {
+                Criteria::Always => true,
+                Criteria::Host(patterns) => match_pattern_list(patterns, host),
+                Criteria::Match(criteria) => {
+                    evaluate_match(criteria, options, original_host, host, user, canonical)?
+                }
+            };
+            if !matched {
+                continue;
+            }
+            for (key, value) in &block.options {
+                if MULTI_VALUED.contains(&key.as_str()) {
+                    let values = options.entry(key.clone()).or_insert_with(Vec::new);
+                    if !values.contains(value) {
+                        values.push(value.clone());
+                    }
+                } else {
+                    options
+                        .entry(key.clone())
+                        .or_insert_with(|| vec![value.clone()]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn first<'a>(options: &'a BTreeMap<String, Vec<String>>, key: &str) -> Option<&'a str> {
+    options
+        .get(key)
+        .and_then(|values| values.first())
+        .map(String::as_str)
+}
+
+fn evaluate_match(
+    criteria: &[(bool, MatchCriterion)],
+    options: &BTreeMap<String, Vec<String>>,
+    original_host: &str,
+    host: &str,
+    user: Option<&str>,
+    canonical: bool,
+) -> anyhow::Result<bool> {
+    // `Match host` applies to the HostName if one has been set so far
+    let target = match first(options, "hostname") {
+        Some(hostname) if !canonical => Tokens {
+            host: original_host,
+            original_host,
+            port: 22,
+            remote_user: "",
+        }
+        .expand(hostname)?,
+        _ => host.to_string(),
+    };
+    let remote_user = user
+        .map(str::to_string)
+        .or_else(|| first(options, "user").map(str::to_string))
+        .unwrap_or_else(local_username);
+    let port = first(options, "port")
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(22);
+
+    for (negated, criterion) in criteria {
+        let result = match criterion {
+            MatchCriterion::All => true,
+            MatchCriterion::Canonical | MatchCriterion::Final => canonical,
+            MatchCriterion::Host(patterns) => match_pattern_list(patterns, &target),
+            MatchCriterion::OriginalHost(patterns) => match_pattern_list(patterns, original_host),
+            MatchCriterion::User(patterns) => match_pattern_list(patterns, &remote_user),
+            MatchCriterion::LocalUser(patterns) => match_pattern_list(patterns, &local_username()),
+            MatchCriterion::Exec(command) => {
+                let command = Tokens {
+                    host: &target,
+                    original_host,
+                    port,
+                    remote_user: &remote_user,
+                }
+                .expand(command)?;
+                run_match_exec(&command)
+            }
+        };
+        if result == *negated {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Runs the command for a `Match exec` criterion; it matches
+/// if the command exits successfully
+fn run_match_exec(command: &str) -> bool {
+    #[cfg(unix)]
+    let status = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::null())
+        .status();
+    #[cfg(windows)]
+    let status = std::process::Command::new("cmd")
+        .arg("/c")
+        .arg(command)
+        .stdin(std::process::Stdio::null())
+        .status();
+
+    match status {
+        Ok(status) => status.success(),
+        Err(err) => {
+            log::error!("ssh config: Match exec `{}` failed: {}", command, err);
+            false
+        }
+    }
+}
+
+/// Implements `CanonicalizeHostname`, returning the canonical name
+/// for `host` if it should be replaced
+fn canonicalize(
+    options: &BTreeMap<String, Vec<String>>,
+    host: &str,
+) -> anyhow::Result<Option<String>> {
+    let mode = first(options, "canonicalizehostname")
+        .unwrap_or("no")
+        .to_lowercase();
+    let proxied = first(options, "proxyjump").map_or(false, |p| p != "none")
+        || first(options, "proxycommand").map_or(false, |p| p != "none");
+    match mode.as_str() {
+        "always" => {}
+        "yes" if !proxied => {}
+        _ => return Ok(None),
+    }
+
+    // A trailing dot marks a name that is already fully qualified
+    if let Some(host) = host.strip_suffix('.') {
+        return Ok(Some(host.to_string()));
+    }
+
+    let max_dots: usize = match first(options, "canonicalizemaxdots") {
+        Some(dots) => dots.parse().context("invalid CanonicalizeMaxDots")?,
+        None => 1,
+    };
+    if host.matches('.').count() > max_dots {
+        return Ok(None);
+    }
+
+    let domains = first(options, "canonicaldomains").unwrap_or("");
+    for domain in domains.split_whitespace() {
+        let candidate = format!("{}.{}", host, domain.trim_end_matches('.'));
+        if let Ok(mut addrs) = (candidate.as_str(), 22).to_socket_addrs() {
+            if addrs.next().is_some() {
+                return Ok(Some(candidate));
+            }
+        }
+    }
+
+    let fallback = first(options, "canonicalizefallbacklocal")
+        .unwrap_or("yes")
+        .to_lowercase();
+    if fallback == "no" {
+        bail!(
+            "unable to canonicalize host name {} using CanonicalDomains {}",
+            host,
+            domains
+        );
+    }
+    Ok(None)
+}
+
+/// The effective ssh configuration for a host
+#[derive(Debug, Clone)]
+pub struct ResolvedSshConfig {
+    options: BTreeMap<String, Vec<String>>,
+}
+
+impl ResolvedSshConfig {
+    /// Returns the first value of the option named `key`,
+    /// which must be lowercase
+    pub fn get(&self, key: &str) -> Option<&str> {
+        first(&self.options, key)
+    }
+
+    /// Returns all of the values of a multi-valued option such as
+    /// `identityfile`
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.options.get(key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The name of the host to connect to, after applying `HostName`
+    /// and canonicalization
+    pub fn hostname(&self) -> &str {
+        self.get("hostname").unwrap_or("")
+    }
+
+    pub fn port(&self) -> u16 {
+        self.get("port").and_then(|p| p.parse().ok()).unwrap_or(22)
+    }
+
+    pub fn user(&self) -> &str {
+        self.get("user").unwrap_or("")
+    }
+
+    /// The jump hosts to use, or None if none are configured
+    pub fn proxy_jump(&self) -> Option<&str> {
+        self.get("proxyjump").filter(|p| *p != "none")
+    }
+
+    /// The identity files to try, falling back to the default
+    /// ssh key locations if none are configured
+    pub fn identity_files(&self) -> Vec<PathBuf> {
+        let files = self.get_all("identityfile");
+        if files.is_empty() {
+            let ssh_dir = config::HOME_DIR.join(".ssh");
+            ["id_rsa", "id_ecdsa", "id_ed25519"]
+                .iter()
+                .map(|name| ssh_dir.join(name))
+                .collect()
+        } else {
+            files.iter().map(PathBuf::from).collect()
+        }
+    }
+
+    /// The command to run to connect to the host, if any
+    pub fn proxy_command(&self) -> Option<&str> {
+        self.get("proxycommand").filter(|p| *p != "none")
+    }
+
+    /// The OpenSSH certificates listed by `CertificateFile`
+    pub fn certificate_files(&self) -> Vec<PathBuf> {
+        self.get_all("certificatefile")
+            .iter()
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// The value of `IdentityAgent`, if any.  This is either `none`,
+    /// `SSH_AUTH_SOCK`, or the path to the agent socket.
+    pub fn identity_agent(&self) -> Option<&str> {
+        self.get("identityagent")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(text: &str) -> SshConfig {
+        let mut config = SshConfig::new();
+        config
+            .add_config_str(text, Path::new("/nonexistent"))
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn first_value_wins() {
+        let config = parse(
+            r#"
+Host foo
+    HostName foo.example.com
+    User alice
+
+Host *
+    User bob
+    Port 2222
+"#,
+        );
+        let resolved = config.resolve("foo", None, None).unwrap();
+        assert_eq!(resolved.hostname(), "foo.example.com");
+        assert_eq!(resolved.user(), "alice");
+        assert_eq!(resolved.port(), 2222);
+
+        let resolved = config.resolve("bar", None, Some(22)).unwrap();
+        assert_eq!(resolved.hostname(), "bar");
+        assert_eq!(resolved.user(), "bob");
+        assert_eq!(resolved.port(), 22);
+    }
+
+    #[test]
+    fn patterns() {
+        let config = parse(
+            r#"
+Host *.example.com !secret.example.com
+    User wildcard
+"#,
+        );
+        assert_eq!(
+            config
+                .resolve("www.example.com", None, None)
+                .unwrap()
+                .get("user"),
+            Some("wildcard")
+        );
+        assert_ne!(
+            config
+                .resolve("secret.example.com", None, None)
+                .unwrap()
+                .get("user"),
+            Some("wildcard")
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let config = parse(
+            r#"
+Host db
+    HostName %h.internal
+    User admin
+    Port 2200
+    IdentityFile /keys/%r@%h:%p
+    IdentityAgent=/agents/%n.sock
+"#,
+        );
+        let resolved = config.resolve("db", None, None).unwrap();
+        assert_eq!(resolved.hostname(), "db.internal");
+        assert_eq!(
+            resolved.identity_files(),
+            vec![PathBuf::from("/keys/admin@db.internal:2200")]
+        );
+        assert_eq!(resolved.identity_agent(), Some("/agents/db.sock"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn match_exec() {
+        let config = parse(
+            r#"
+Match host foo exec "test %h = foo"
+    User matched
+
+Match exec false
+    User never
+
+Match !exec false
+    Port 2022
+"#,
+        );
+        let resolved = config.resolve("foo", None, None).unwrap();
+        assert_eq!(resolved.user(), "matched");
+        assert_eq!(resolved.port(), 2022);
+    }
+
+    #[test]
+    fn canonicalize_respects_max_dots() {
+        let config = parse(
+            r#"
+CanonicalizeHostname yes
+CanonicalDomains invalid
+CanonicalizeFallbackLocal no
+"#,
+        );
+        assert!(config.resolve("foo", None, None).is_err());
+        assert_eq!(
+            config.resolve("a.b.c", None, None).unwrap().hostname(),
+            "a.b.c"
+        );
+        assert_eq!(
+            config.resolve("foo.", None, None).unwrap().hostname(),
+            "foo"
+        );
+    }
+}
@@ -0,0 +1,1003 @@
+//! Implements the `local_forward`, `remote_forward` and `dynamic_forward`
+//! options of an ssh domain.
+//! The forwards are multiplexed over the same ssh session that carries
+//! the mux protocol and are torn down when that session goes away.
+//! The status of each forward is recorded in a process-wide registry
+//! so that it can be reported via `wezterm cli list-clients`.
+//! The same machinery is used to tunnel through jump hosts.
+use anyhow::{anyhow, bail, Context};
+use config::{SshDomain, SshDynamicForward, SshPortForward};
+use filedescriptor::{AsRawSocketDescriptor, FileDescriptor, POLLIN, POLLOUT};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForwardKind {
+    Local,
+    Remote,
+    Dynamic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForwardState {
+    Listening,
+    Failed(String),
+}
+
+impl std::fmt::Display for ForwardState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Listening => write!(f, "listening"),
+            Self::Failed(reason) => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardStatus {
+    pub kind: ForwardKind,
+    pub description: String,
+    pub state: ForwardState,
+    pub active_connections: usize,
+    pub total_connections: usize,
+}
+
+/// Describes an ssh session held by this process on behalf of a domain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SshClientStatus {
+    pub domain_name: String,
+    pub remote_address: String,
+    pub forwards: Vec<ForwardStatus>,
+}
+
+lazy_static::lazy_static! {
+    static ref CLIENTS: Mutex<HashMap<String, SshClientStatus>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the status of the ssh sessions (and their forwards)
+/// that are currently held by this process
+pub fn ssh_client_list() -> Vec<SshClientStatus> {
+    let mut clients: Vec<SshClientStatus> = CLIENTS.lock().unwrap().values().cloned().collect();
+    clients.sort_by(|a, b| a.domain_name.cmp(&b.domain_name));
+    clients
+}
+
+fn update_status<F: FnOnce(&mut ForwardStatus)>(domain_name: &str, idx: usize, f: F) {
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some(status) = clients
+        .get_mut(domain_name)
+        .and_then(|client| client.forwards.get_mut(idx))
+    {
+        f(status);
+    }
+}
+
+/// libssh2 reports this when a non-blocking session would have blocked
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+fn would_block(err: &ssh2::Error) -> bool {
+    err.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_EAGAIN)
+}
+
+/// Repeatedly call `f` until it stops returning EAGAIN.
+/// This is used for the handful of channel setup operations that
+/// we perform while the session may be in non-blocking mode.
+pub fn retry_eagain<T, F: FnMut() -> Result<T, ssh2::Error>>(mut f: F) -> anyhow::Result<T> {
+    loop {
+        match f() {
+            Ok(t) => return Ok(t),
+            Err(err) if would_block(&err) => std::thread::sleep(Duration::from_millis(5)),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+enum Listening {
+    Local {
+        listener: TcpListener,
+        forward: SshPortForward,
+    },
+    Dynamic {
+        listener: TcpListener,
+    },
+    Remote {
+        listener: ssh2::Listener,
+        forward: SshPortForward,
+    },
+}
+
+struct Forward {
+    idx: usize,
+    listening: Listening,
+}
+
+/// Shuttles data between a local stream and an ssh channel
+struct Connection<S> {
+    idx: usize,
+    stream: S,
+    chan: ssh2::Channel,
+    to_chan: Vec<u8>,
+    to_stream: Vec<u8>,
+    stream_eof: bool,
+}
+
+/// What a call to `Connection::pump` achieved
+struct Pumped {
+    /// Some data, or an eof, was passed on
+    progress: bool,
+    /// Both sides are done with the connection
+    done: bool,
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Moves data in both directions until neither side can make
+    /// progress without blocking.
+    fn pump(&mut self) -> anyhow::Result<Pumped> {
+        let mut buf = [0u8; 8192];
+        let mut progress = false;
+        loop {
+            let to_chan = self.pump_to_chan(&mut buf)?;
+            let to_stream = self.pump_to_stream(&mut buf)?;
+            if !to_chan && !to_stream {
+                break;
+            }
+            progress = true;
+        }
+
+        let remote_done = self.chan.eof() && self.to_stream.is_empty();
+        Ok(Pumped {
+            progress,
+            done: remote_done || (self.stream_eof && self.to_chan.is_empty() && self.chan.eof()),
+        })
+    }
+
+    /// Copies from the stream to the channel until one of them would
+    /// block.  Returns true if anything was moved.
+    fn pump_to_chan(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        let mut moved = false;
+        loop {
+            if self.to_chan.is_empty() {
+                if self.stream_eof {
+                    break;
+                }
+                match self.stream.read(buf) {
+                    Ok(0) => {
+                        match self.chan.send_eof() {
+                            Ok(_) => {
+                                self.stream_eof = true;
+                                moved = true;
+                            }
+                            // The stream will report eof again next time
+                            Err(err) if would_block(&err) => {}
+                            Err(err) => return Err(err.into()),
+                        }
+                        break;
+                    }
+                    Ok(n) => {
+                        self.to_chan.extend_from_slice(&buf[..n]);
+                        moved = true;
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            match self.chan.write(&self.to_chan) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.to_chan.drain(..n);
+                    moved = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Copies from the channel to the stream until one of them would
+    /// block.  Returns true if anything was moved.
+    fn pump_to_stream(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        let mut moved = false;
+        loop {
+            if self.to_stream.is_empty() {
+                match self.chan.read(buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        self.to_stream.extend_from_slice(&buf[..n]);
+                        moved = true;
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            match self.stream.write(&self.to_stream) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.to_stream.drain(..n);
+                    moved = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(moved)
+    }
+}
+
+/// The events to wait for on the session socket: it must be polled
+/// for writing when libssh2 has output that it couldn't send, or it
+/// would not be retried until the poll times out
+fn session_events(sess: &ssh2::Session, want_write: bool) -> i16 {
+    match sess.block_directions() {
+        ssh2::BlockDirections::Outbound | ssh2::BlockDirections::Both if want_write => {
+            POLLIN | POLLOUT
+        }
+        _ => POLLIN,
+    }
+}
+
+/// The events to wait for on the local stream of a connection
+fn stream_events<S>(conn: &Connection<S>) -> i16 {
+    if conn.to_stream.is_empty() {
+        POLLIN
+    } else {
+        POLLIN | POLLOUT
+    }
+}
+
+/// How long to wait for something to happen; after making progress
+/// we only check for more without waiting, as libssh2 may already
+/// hold data that the socket won't signal again
+fn poll_timeout(progress: bool) -> Duration {
+    if progress {
+        Duration::from_millis(0)
+    } else {
+        Duration::from_millis(100)
+    }
+}
+
+/// Owns the forwards for an ssh domain.  The forwards are shut down
+/// when this is dropped.
+pub struct PortForwarder {
+    domain_name: String,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Drop for PortForwarder {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        CLIENTS.lock().unwrap().remove(&self.domain_name);
+    }
+}
+
+impl PortForwarder {
+    /// Returns true if the domain has any forwards configured
+    pub fn is_needed(ssh_dom: &SshDomain) -> bool {
+        !ssh_dom.local_forward.is_empty()
+            || !ssh_dom.remote_forward.is_empty()
+            || !ssh_dom.dynamic_forward.is_empty()
+    }
+
+    /// Establish the forwards configured for `ssh_dom` over `sess`.
+    /// `sess` will be switched into non-blocking mode so that the
+    /// forwarding thread can share it with other channels.
+    /// The forwarding thread takes over polling the session socket;
+    /// `wake` is called each time that it has serviced the session, so
+    /// that the other users of the session can check for data that
+    /// libssh2 may have buffered on their behalf.
+    pub fn start<W>(ssh_dom: &SshDomain, sess: ssh2::Session, wake: W) -> anyhow::Result<Self>
+    where
+        W: FnMut() + Send + 'static,
+    {
+        let domain_name = ssh_dom.name.clone();
+        let mut status = SshClientStatus {
+            domain_name: domain_name.clone(),
+            remote_address: ssh_dom.remote_address.clone(),
+            forwards: vec![],
+        };
+
+        let mut forwards = vec![];
+        sess.set_blocking(false);
+
+        for forward in &ssh_dom.local_forward {
+            let idx = status.forwards.len();
+            let (state, listening) = match bind_local(forward.bind_address(), forward.listen_port) {
+                Ok(listener) => (
+                    ForwardState::Listening,
+                    Some(Listening::Local {
+                        listener,
+                        forward: forward.clone(),
+                    }),
+                ),
+                Err(err) => (ForwardState::Failed(format!("{:#}", err)), None),
+            };
+            status.forwards.push(ForwardStatus {
+                kind: ForwardKind::Local,
+                description: forward.to_string(),
+                state,
+                active_connections: 0,
+                total_connections: 0,
+            });
+            if let Some(listening) = listening {
+                forwards.push(Forward { idx, listening });
+            }
+        }
+
+        for forward in &ssh_dom.dynamic_forward {
+            let idx = status.forwards.len();
+            let (state, listening) = match bind_local(forward.bind_address(), forward.listen_port) {
+                Ok(listener) => (
+                    ForwardState::Listening,
+                    Some(Listening::Dynamic { listener }),
+                ),
+                Err(err) => (ForwardState::Failed(format!("{:#}", err)), None),
+            };
+            status.forwards.push(ForwardStatus {
+                kind: ForwardKind::Dynamic,
+                description: forward.to_string(),
+                state,
+                active_connections: 0,
+                total_connections: 0,
+            });
+            if let Some(listening) = listening {
+                forwards.push(Forward { idx, listening });
+            }
+        }
+
+        for forward in &ssh_dom.remote_forward {
+            let idx = status.forwards.len();
+            let (state, listening) = match retry_eagain(|| {
+                sess.channel_forward_listen(forward.listen_port, Some(forward.bind_address()), None)
+            }) {
+                Ok((listener, _bound_port)) => (
+                    ForwardState::Listening,
+                    Some(Listening::Remote {
+                        listener,
+                        forward: forward.clone(),
+                    }),
+                ),
+                Err(err) => (ForwardState::Failed(format!("{:#}", err)), None),
+            };
+            status.forwards.push(ForwardStatus {
+                kind: ForwardKind::Remote,
+                description: forward.to_string(),
+                state,
+                active_connections: 0,
+                total_connections: 0,
+            });
+            if let Some(listening) = listening {
+                forwards.push(Forward { idx, listening });
+            }
+        }
+
+        for fwd in &status.forwards {
+            if let ForwardState::Failed(reason) = &fwd.state {
+                log::error!("ssh forward {} failed: {}", fwd.description, reason);
+            }
+        }
+
+        CLIENTS.lock().unwrap().insert(domain_name.clone(), status);
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        std::thread::spawn({
+            let shutdown = Arc::clone(&shutdown);
+            let domain_name = domain_name.clone();
+            move || {
+                if let Err(err) = forward_thread(&domain_name, sess, forwards, &shutdown, wake) {
+                    log::error!("ssh forwarding for {} stopped: {:#}", domain_name, err);
+                }
+            }
+        });
+
+        Ok(Self {
+            domain_name,
+            shutdown,
+        })
+    }
+}
+
+fn bind_local(bind_address: &str, port: u16) -> anyhow::Result<TcpListener> {
+    let listener = TcpListener::bind((bind_address, port))
+        .with_context(|| format!("binding to {}:{}", bind_address, port))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// A connection that is waiting for its ssh channel to open.
+/// libssh2 can only open one channel at a time in a session, so
+/// these are opened in turn by the forwarding thread, which retries
+/// the one at the front of the queue each time that it wakes up.
+struct PendingOpen {
+    idx: usize,
+    stream: TcpStream,
+    host: String,
+    port: u16,
+    /// Set for a SOCKS client, which is waiting for a reply
+    socks: bool,
+}
+
+/// The result of a part of setting up a connection that may block,
+/// and so runs on a thread of its own rather than on the forwarding
+/// thread, which would otherwise stall every other forward and the
+/// mux session while it waited
+enum Setup {
+    /// A SOCKS client has said where it wants to connect
+    Socks {
+        idx: usize,
+        stream: TcpStream,
+        host: String,
+        port: u16,
+    },
+    /// The connection to the destination of a remote forward
+    Connected {
+        token: usize,
+        stream: anyhow::Result<TcpStream>,
+    },
+}
+
+/// Used by the setup threads to hand their results to the
+/// forwarding thread, waking it up to take them
+struct SetupSender {
+    tx: Sender<Setup>,
+    notify: FileDescriptor,
+}
+
+impl SetupSender {
+    fn try_clone(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            tx: self.tx.clone(),
+            notify: self.notify.try_clone()?,
+        })
+    }
+
+    fn send(mut self, setup: Setup) {
+        if self.tx.send(setup).is_ok() {
+            self.notify.write(b"x").ok();
+        }
+    }
+}
+
+/// The state of the forwarding thread, which owns the listeners and
+/// the established connections and shares the session with its other
+/// users, so it must never block
+struct ForwardThread<'a> {
+    domain_name: &'a str,
+    sess: ssh2::Session,
+    forwards: Vec<Forward>,
+    connections: Vec<Connection<TcpStream>>,
+    opening: VecDeque<PendingOpen>,
+    /// The channels of remote forwards whose local connection is
+    /// still being made, by token
+    connecting: HashMap<usize, (usize, ssh2::Channel)>,
+    next_token: usize,
+    setup: SetupSender,
+    setup_rx: Receiver<Setup>,
+    setup_wakeup: FileDescriptor,
+}
+
+fn forward_thread<W: FnMut()>(
+    domain_name: &str,
+    sess: ssh2::Session,
+    forwards: Vec<Forward>,
+    shutdown: &AtomicBool,
+    mut wake: W,
+) -> anyhow::Result<()> {
+    let (mut setup_wakeup, mut notify) = filedescriptor::socketpair()?;
+    setup_wakeup.set_non_blocking(true)?;
+    notify.set_non_blocking(true)?;
+    let (tx, setup_rx) = channel();
+
+    let mut state = ForwardThread {
+        domain_name,
+        sess,
+        forwards,
+        connections: vec![],
+        opening: VecDeque::new(),
+        connecting: HashMap::new(),
+        next_token: 0,
+        setup: SetupSender { tx, notify },
+        setup_rx,
+        setup_wakeup,
+    };
+
+    let mut progress = false;
+    while !shutdown.load(Ordering::Relaxed) {
+        state.poll(poll_timeout(progress));
+        state.accept_all();
+        state.take_setups();
+        state.open_pending();
+        progress = state.pump_all();
+
+        // Data for the other channels of the session, such as the
+        // one that carries the mux protocol, may have been read into
+        // libssh2's buffers while pumping ours, so always let their
+        // readers check for it
+        wake();
+    }
+
+    Ok(())
+}
+
+impl<'a> ForwardThread<'a> {
+    /// Waits for something to do
+    fn poll(&self, timeout: Duration) {
+        let want_write = !self.opening.is_empty()
+            || self.connections.iter().any(|conn| !conn.to_chan.is_empty());
+        let mut pfd = vec![
+            filedescriptor::pollfd {
+                fd: self.sess.as_socket_descriptor(),
+                events: session_events(&self.sess, want_write),
+                revents: 0,
+            },
+            filedescriptor::pollfd {
+                fd: self.setup_wakeup.as_socket_descriptor(),
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        for fwd in &self.forwards {
+            match &fwd.listening {
+                Listening::Local { listener, .. } | Listening::Dynamic { listener } => {
+                    pfd.push(filedescriptor::pollfd {
+                        fd: listener.as_socket_descriptor(),
+                        events: POLLIN,
+                        revents: 0,
+                    })
+                }
+                Listening::Remote { .. } => {}
+            }
+        }
+        for conn in &self.connections {
+            pfd.push(filedescriptor::pollfd {
+                fd: conn.stream.as_socket_descriptor(),
+                events: stream_events(conn),
+                revents: 0,
+            });
+        }
+        filedescriptor::poll(&mut pfd, Some(timeout)).ok();
+    }
+
+    fn accept_all(&mut self) {
+        for fwd_idx in 0..self.forwards.len() {
+            if let Err(err) = self.accept(fwd_idx) {
+                log::error!("ssh forward for {}: {:#}", self.domain_name, err);
+            }
+        }
+    }
+
+    /// Accepts a connection for the forward `fwd_idx`, if one is
+    /// waiting, and starts setting it up
+    fn accept(&mut self, fwd_idx: usize) -> anyhow::Result<()> {
+        let idx = self.forwards[fwd_idx].idx;
+        match &mut self.forwards[fwd_idx].listening {
+            Listening::Local { listener, forward } => {
+                let stream = match listener.accept() {
+                    Ok((stream, _addr)) => stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                stream.set_nonblocking(true)?;
+                self.opening.push_back(PendingOpen {
+                    idx,
+                    stream,
+                    host: forward.host.clone(),
+                    port: forward.port,
+                    socks: false,
+                });
+            }
+            Listening::Dynamic { listener } => {
+                let stream = match listener.accept() {
+                    Ok((stream, _addr)) => stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                let setup = self.setup.try_clone()?;
+                std::thread::spawn(move || match socks5_accept(stream) {
+                    Ok((stream, host, port)) => setup.send(Setup::Socks {
+                        idx,
+                        stream,
+                        host,
+                        port,
+                    }),
+                    Err(err) => log::debug!("SOCKS negotiation failed: {:#}", err),
+                });
+            }
+            Listening::Remote { listener, forward } => {
+                let chan = match listener.accept() {
+                    Ok(chan) => chan,
+                    Err(err) if would_block(&err) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                let token = self.next_token;
+                self.next_token += 1;
+                self.connecting.insert(token, (idx, chan));
+
+                let setup = self.setup.try_clone()?;
+                let host = forward.host.clone();
+                let port = forward.port;
+                std::thread::spawn(move || {
+                    let stream = connect_local(&host, port);
+                    setup.send(Setup::Connected { token, stream });
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the connections that the setup threads have finished with
+    fn take_setups(&mut self) {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = self.setup_wakeup.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+
+        while let Ok(setup) = self.setup_rx.try_recv() {
+            match setup {
+                Setup::Socks {
+                    idx,
+                    stream,
+                    host,
+                    port,
+                } => match stream.set_nonblocking(true) {
+                    Ok(_) => self.opening.push_back(PendingOpen {
+                        idx,
+                        stream,
+                        host,
+                        port,
+                        socks: true,
+                    }),
+                    Err(err) => log::debug!("SOCKS connection closed: {:#}", err),
+                },
+                Setup::Connected { token, stream } => {
+                    let (idx, mut chan) = match self.connecting.remove(&token) {
+                        Some(pending) => pending,
+                        None => continue,
+                    };
+                    match stream {
+                        Ok(stream) => self.add_connection(idx, stream, chan),
+                        Err(err) => {
+                            log::error!("ssh forward for {}: {:#}", self.domain_name, err);
+                            chan.close().ok();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens the channels of the pending connections, stopping at
+    /// the first that would block
+    fn open_pending(&mut self) {
+        while let Some(pending) = self.opening.front() {
+            let result = pending
+                .stream
+                .peer_addr()
+                .map_err(anyhow::Error::from)
+                .and_then(|peer| {
+                    match self.sess.channel_direct_tcpip(
+                        &pending.host,
+                        pending.port,
+                        Some((&peer.ip().to_string(), peer.port())),
+                    ) {
+                        Ok(chan) => Ok(Some(chan)),
+                        Err(err) if would_block(&err) => Ok(None),
+                        Err(err) => Err(anyhow::Error::from(err).context(format!(
+                            "opening ssh channel to {}:{}",
+                            pending.host, pending.port
+                        ))),
+                    }
+                });
+
+            match result {
+                Ok(None) => break,
+                Ok(Some(chan)) => {
+                    let mut pending = self.opening.pop_front().unwrap();
+                    if pending.socks {
+                        // The reply is small enough to fit in the
+                        // buffer of the new socket, so this won't block
+                        if let Err(err) = socks5_reply(&mut pending.stream, SOCKS5_SUCCEEDED) {
+                            log::debug!("SOCKS connection closed: {:#}", err);
+                            continue;
+                        }
+                    }
+                    self.add_connection(pending.idx, pending.stream, chan);
+                }
+                Err(err) => {
+                    let mut pending = self.opening.pop_front().unwrap();
+                    if pending.socks {
+                        socks5_reply(&mut pending.stream, SOCKS5_HOST_UNREACHABLE).ok();
+                    }
+                    log::error!("ssh forward for {}: {:#}", self.domain_name, err);
+                }
+            }
+        }
+    }
+
+    fn add_connection(&mut self, idx: usize, stream: TcpStream, chan: ssh2::Channel) {
+        update_status(self.domain_name, idx, |status| {
+            status.active_connections += 1;
+            status.total_connections += 1;
+        });
+        self.connections.push(Connection {
+            idx,
+            stream,
+            chan,
+            to_chan: vec![],
+            to_stream: vec![],
+            stream_eof: false,
+        });
+    }
+
+    /// Pumps all of the connections, returning true if any of them
+    /// made progress
+    fn pump_all(&mut self) -> bool {
+        let mut progress = false;
+        let mut idx = 0;
+        while idx < self.connections.len() {
+            let keep = match self.connections[idx].pump() {
+                Ok(pumped) => {
+                    progress |= pumped.progress;
+                    !pumped.done
+                }
+                Err(err) => {
+                    log::debug!("ssh forward connection closed: {:#}", err);
+                    false
+                }
+            };
+            if keep {
+                idx += 1;
+            } else {
+                let mut conn = self.connections.remove(idx);
+                conn.chan.close().ok();
+                update_status(self.domain_name, conn.idx, |status| {
+                    status.active_connections = status.active_connections.saturating_sub(1);
+                });
+            }
+        }
+        progress
+    }
+}
+
+/// Connects to the destination of a remote forward
+fn connect_local(host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("unable to resolve {}", host))?;
+    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(10))
+        .with_context(|| format!("connecting to {}:{}", host, port))?;
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+/// Negotiates with a SOCKS client, giving up if it is too slow,
+/// and returns the stream together with the destination it requested
+fn socks5_accept(mut stream: TcpStream) -> anyhow::Result<(TcpStream, String, u16)> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let (host, port) = socks5_handshake(&mut stream)?;
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok((stream, host, port))
+}
+
+const SOCKS5_SUCCEEDED: u8 = 0;
+const SOCKS5_HOST_UNREACHABLE: u8 = 4;
+
+/// Performs the server side of the SOCKS5 negotiation for a CONNECT
+/// request with no authentication, returning the requested destination.
+fn socks5_handshake<S: Read + Write>(stream: &mut S) -> anyhow::Result<(String, u16)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    if header[0] != 5 {
+        bail!("unsupported SOCKS version {}", header[0]);
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods)?;
+    if !methods.contains(&0) {
+        // No acceptable methods
+        stream.write_all(&[5, 0xff])?;
+        bail!("SOCKS client requires authentication");
+    }
+    stream.write_all(&[5, 0])?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request)?;
+    if request[1] != 1 {
+        socks5_reply(stream, 7).ok();
+        bail!("unsupported SOCKS command {}", request[1]);
+    }
+
+    let host = match request[3] {
+        1 => {
+            let mut addr = [0u8; 4];
+            stream.read_exact(&mut addr)?;
+            std::net::Ipv4Addr::from(addr).to_string()
+        }
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            let mut name = vec![0u8; len[0] as usize];
+            stream.read_exact(&mut name)?;
+            String::from_utf8(name)?
+        }
+        4 => {
+            let mut addr = [0u8; 16];
+            stream.read_exact(&mut addr)?;
+            std::net::Ipv6Addr::from(addr).to_string()
+        }
+        atyp => {
+            socks5_reply(stream, 8).ok();
+            bail!("unsupported SOCKS address type {}", atyp);
+        }
+    };
+
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port)?;
+    Ok((host, u16::from_be_bytes(port)))
+}
+
+fn socks5_reply<S: Write>(stream: &mut S, status: u8) -> anyhow::Result<()> {
+    stream.write_all(&[5, status, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    Ok(())
+}
+
+/// Connects `stream` to `host:port` as seen from the host at the other
+/// end of `sess`, in the same way as `ssh -W`.  This is used to tunnel
+/// a connection to the next hop when using `proxy_jump`.
+/// `sess` is switched into non-blocking mode and is owned by the relay
+/// thread, which terminates when either side is closed.
+pub fn relay_to<S>(sess: ssh2::Session, host: &str, port: u16, stream: S) -> anyhow::Result<()>
+where
+    S: Read + Write + AsRawSocketDescriptor + Send + 'static,
+{
+    let chan = sess
+        .channel_direct_tcpip(host, port, None)
+        .with_context(|| format!("opening ssh channel to {}:{}", host, port))?;
+    sess.set_blocking(false);
+
+    let mut conn = Connection {
+        idx: 0,
+        stream,
+        chan,
+        to_chan: vec![],
+        to_stream: vec![],
+        stream_eof: false,
+    };
+    let host = host.to_string();
+
+    std::thread::spawn(move || {
+        let mut progress = false;
+        loop {
+            let mut pfd = [
+                filedescriptor::pollfd {
+                    fd: sess.as_socket_descriptor(),
+                    events: session_events(&sess, !conn.to_chan.is_empty()),
+                    revents: 0,
+                },
+                filedescriptor::pollfd {
+                    fd: conn.stream.as_socket_descriptor(),
+                    events: stream_events(&conn),
+                    revents: 0,
+                },
+            ];
+            filedescriptor::poll(&mut pfd, Some(poll_timeout(progress))).ok();
+            match conn.pump() {
+                Ok(Pumped { done: true, .. }) => break,
+                Ok(pumped) => progress = pumped.progress,
+                Err(err) => {
+                    log::debug!("ssh relay to {}:{} closed: {:#}", host, port, err);
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Stands in for the client's socket: reads come from what the
+    /// client sent and writes are collected for inspection
+    struct Client {
+        sent: std::io::Cursor<Vec<u8>>,
+        received: Vec<u8>,
+    }
+
+    impl Client {
+        fn new(sent: &[u8]) -> Self {
+            Self {
+                sent: std::io::Cursor::new(sent.to_vec()),
+                received: vec![],
+            }
+        }
+    }
+
+    impl Read for Client {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.sent.read(buf)
+        }
+    }
+
+    impl Write for Client {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.received.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Greets with the no authentication method, then sends `request`
+    fn handshake(request: &[u8]) -> (anyhow::Result<(String, u16)>, Vec<u8>) {
+        let mut sent = vec![5, 1, 0];
+        sent.extend_from_slice(request);
+        let mut client = Client::new(&sent);
+        let result = socks5_handshake(&mut client);
+        (result, client.received)
+    }
+
+    #[test]
+    fn socks_connect_ipv4() {
+        let (result, received) = handshake(&[5, 1, 0, 1, 127, 0, 0, 1, 0x1f, 0x90]);
+        assert_eq!(result.unwrap(), ("127.0.0.1".to_string(), 8080));
+        assert_eq!(received, vec![5, 0]);
+    }
+
+    #[test]
+    fn socks_connect_domain() {
+        let mut request = vec![5, 1, 0, 3, 11];
+        request.extend_from_slice(b"example.com");
+        request.extend_from_slice(&[0x01, 0xbb]);
+        let (result, received) = handshake(&request);
+        assert_eq!(result.unwrap(), ("example.com".to_string(), 443));
+        assert_eq!(received, vec![5, 0]);
+    }
+
+    #[test]
+    fn socks_connect_ipv6() {
+        let mut request = vec![5, 1, 0, 4];
+        request.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        request.extend_from_slice(&[0, 22]);
+        let (result, received) = handshake(&request);
+        assert_eq!(result.unwrap(), ("::1".to_string(), 22));
+        assert_eq!(received, vec![5, 0]);
+    }
+
+    #[test]
+    fn socks_wrong_version() {
+        let mut client = Client::new(&[4, 1, 0]);
+        assert!(socks5_handshake(&mut client).is_err());
+        assert!(client.received.is_empty());
+    }
+
+    #[test]
+    fn socks_no_acceptable_auth() {
+        // Offers only username/password authentication
+        let mut client = Client::new(&[5, 1, 2]);
+        assert!(socks5_handshake(&mut client).is_err());
+        assert_eq!(client.received, vec![5, 0xff]);
+    }
+
+    #[test]
+    fn socks_bind_is_not_supported() {
+        let (result, received) = handshake(&[5, 2, 0, 1, 127, 0, 0, 1, 0, 80]);
+        assert!(result.is_err());
+        assert_eq!(received, vec![5, 0, 5, 7, 0, 1, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,316 @@
+//! Collects the metrics recorded via the `metrics` crate so that they
+//! can be periodically logged (see `periodic_stat_logging`) and
+//! retrieved on demand by `wezterm cli stats` and `wezterm.gui.get_stats()`.
+use crate::pane::PaneId;
+use crate::Mux;
+use config::configuration;
+use hdrhistogram::Histogram;
+use luahelper::impl_lua_conversion;
+use metrics::{GaugeValue, Key, Recorder, Unit};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tabout::{tabulate_output, Alignment, Column};
+
+static ENABLE_STAT_PRINT: AtomicBool = AtomicBool::new(true);
+
+lazy_static::lazy_static! {
+    static ref INNER: Arc<Mutex<Inner>> = Arc::new(Mutex::new(Inner {
+        histograms: HashMap::new(),
+        counters: HashMap::new(),
+    }));
+}
+
+struct Inner {
+    histograms: HashMap<Key, Histogram<u64>>,
+    counters: HashMap<Key, u64>,
+}
+
+/// Summarizes the values recorded for a histogram.
+/// Durations are in nanoseconds; the values of metrics whose
+/// names end in `.size` are in bytes.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct HistogramSummary {
+    pub name: String,
+    pub count: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+impl_lua_conversion!(HistogramSummary);
+
+impl HistogramSummary {
+    /// The approximate sum of the recorded values
+    pub fn total(&self) -> f64 {
+        self.mean * self.count as f64
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct CounterValue {
+    pub name: String,
+    pub value: u64,
+}
+impl_lua_conversion!(CounterValue);
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct PaneMemory {
+    pub pane_id: PaneId,
+    pub domain: String,
+    pub title: String,
+    /// The number of lines in the scrollback, including the screen
+    pub lines: usize,
+    /// An estimate of the memory used by those lines, if the
+    /// pane is able to provide one
+    pub bytes: Option<usize>,
+}
+impl_lua_conversion!(PaneMemory);
+
+/// The state of the metrics at the time that `snapshot` was called
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    pub histograms: Vec<HistogramSummary>,
+    pub counters: Vec<CounterValue>,
+    pub panes: Vec<PaneMemory>,
+}
+impl_lua_conversion!(StatsSnapshot);
+
+impl StatsSnapshot {
+    pub fn histogram(&self, name: &str) -> Option<&HistogramSummary> {
+        self.histograms.iter().find(|h| h.name == name)
+    }
+
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        self.counters
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.value)
+    }
+
+    /// Returns the rate at which the terminal parser consumed the
+    /// output of the panes, in bytes per second, while it was busy
+    pub fn parse_throughput(&self) -> Option<f64> {
+        let bytes = self.counter("mux.parse.bytes")? as f64;
+        let nanos = self.histogram("mux.parse")?.total();
+        if nanos > 0. {
+            Some(bytes * 1_000_000_000. / nanos)
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats the key as `name{label=value,...}`
+fn key_name(key: &Key) -> String {
+    let labels: Vec<String> = key
+        .labels()
+        .map(|label| format!("{}={}", label.key(), label.value()))
+        .collect();
+    if labels.is_empty() {
+        key.name().to_string()
+    } else {
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+}
+
+/// Returns the metrics recorded so far by this process, along with
+/// the memory used by each of the panes in the mux.  The panes are
+/// only included when called on the main thread.
+pub fn snapshot() -> StatsSnapshot {
+    let mut snapshot = StatsSnapshot::default();
+    {
+        let inner = INNER.lock().unwrap();
+        for (key, histogram) in &inner.histograms {
+            snapshot.histograms.push(HistogramSummary {
+                name: key_name(key),
+                count: histogram.len(),
+                mean: histogram.mean(),
+                p50: histogram.value_at_percentile(50.),
+                p95: histogram.value_at_percentile(95.),
+                max: histogram.max(),
+            });
+        }
+        for (key, value) in &inner.counters {
+            snapshot.counters.push(CounterValue {
+                name: key_name(key),
+                value: *value,
+            });
+        }
+    }
+    snapshot.histograms.sort_by(|a, b| a.name.cmp(&b.name));
+    snapshot.counters.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if let Some(mux) = Mux::get() {
+        for pane in mux.iter_panes() {
+            let domain = mux
+                .get_domain(pane.domain_id())
+                .map(|domain| domain.domain_name().to_string())
+                .unwrap_or_default();
+            snapshot.panes.push(PaneMemory {
+                pane_id: pane.pane_id(),
+                domain,
+                title: pane.get_title(),
+                lines: pane.get_dimensions().scrollback_rows,
+                bytes: pane.approx_memory_usage(),
+            });
+        }
+        snapshot.panes.sort_by_key(|pane| pane.pane_id);
+    }
+
+    snapshot
+}
+
+fn pctile_latency(histogram: &Histogram<u64>, p: f64) -> Duration {
+    Duration::from_nanos(histogram.value_at_percentile(p))
+}
+
+impl Inner {
+    fn run(inner: Arc<Mutex<Inner>>) {
+        let mut last_print = Instant::now();
+
+        let cols = vec![
+            Column {
+                name: "STAT".to_string(),
+                alignment: Alignment::Left,
+            },
+            Column {
+                name: "p50".to_string(),
+                alignment: Alignment::Left,
+            },
+            Column {
+                name: "p75".to_string(),
+                alignment: Alignment::Left,
+            },
+            Column {
+                name: "p95".to_string(),
+                alignment: Alignment::Left,
+            },
+        ];
+
+        loop {
+            std::thread::sleep(Duration::from_secs(10));
+            if !ENABLE_STAT_PRINT.load(Ordering::Acquire) {
+                break;
+            }
+
+            let seconds = configuration().periodic_stat_logging;
+            if seconds == 0 {
+                continue;
+            }
+            if last_print.elapsed() >= Duration::from_secs(seconds) {
+                let inner = inner.lock().unwrap();
+                let mut data = vec![];
+                for (key, histogram) in &inner.histograms {
+                    if key.name().to_string().ends_with(".size") {
+                        let p50 = histogram.value_at_percentile(50.);
+                        let p75 = histogram.value_at_percentile(75.);
+                        let p95 = histogram.value_at_percentile(95.);
+                        data.push(vec![
+                            key_name(key),
+                            format!("{:.2?}", p50),
+                            format!("{:.2?}", p75),
+                            format!("{:.2?}", p95),
+                        ]);
+                    } else {
+                        let p50 = pctile_latency(histogram, 50.);
+                        let p75 = pctile_latency(histogram, 75.);
+                        let p95 = pctile_latency(histogram, 95.);
+                        data.push(vec![
+                            key_name(key),
+                            format!("{:.2?}", p50),
+                            format!("{:.2?}", p75),
+                            format!("{:.2?}", p95),
+                        ]);
+                    }
+                }
+                data.sort_by(|a, b| a[0].cmp(&b[0]));
+                eprintln!();
+                tabulate_output(&cols, &data, &mut std::io::stderr().lock()).ok();
+                last_print = Instant::now();
+            }
+        }
+    }
+}
+
+pub struct Stats {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::clone(&INNER),
+        }
+    }
+
+    pub fn init() -> anyhow::Result<()> {
+        let stats = Self::new();
+        let inner = Arc::clone(&stats.inner);
+        std::thread::spawn(move || Inner::run(inner));
+        let rec = Box::new(stats);
+        metrics::set_boxed_recorder(rec)
+            .map_err(|e| anyhow::anyhow!("Failed to set metrics recorder:{}", e))
+    }
+}
+
+impl Recorder for Stats {
+    fn register_counter(&self, _key: Key, _unit: Option<Unit>, _description: Option<&'static str>) {
+    }
+
+    fn register_gauge(&self, _key: Key, _unit: Option<Unit>, _description: Option<&'static str>) {}
+
+    fn register_histogram(
+        &self,
+        _key: Key,
+        _unit: Option<Unit>,
+        _description: Option<&'static str>,
+    ) {
+    }
+
+    fn increment_counter(&self, key: Key, value: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(key).or_insert(0) += value;
+    }
+
+    fn update_gauge(&self, key: Key, value: GaugeValue) {
+        log::trace!("gauge '{}' -> {:?}", key, value);
+    }
+
+    fn record_histogram(&self, key: Key, value: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        let histogram = inner
+            .histograms
+            .entry(key)
+            .or_insert_with(|| Histogram::new(2).expect("failed to crate new Histogram"));
+        histogram.record(value as u64).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_throughput() {
+        let mut stats = StatsSnapshot::default();
+        assert_eq!(stats.parse_throughput(), None);
+
+        stats.histograms.push(HistogramSummary {
+            name: "mux.parse".to_string(),
+            count: 4,
+            mean: 500_000_000.,
+            p50: 500_000_000,
+            p95: 500_000_000,
+            max: 500_000_000,
+        });
+        stats.counters.push(CounterValue {
+            name: "mux.parse.bytes".to_string(),
+            value: 4096,
+        });
+        assert_eq!(stats.counter("mux.parse.bytes"), Some(4096));
+        assert_eq!(stats.parse_throughput(), Some(2048.));
+    }
+}
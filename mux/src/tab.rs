@@ -7,6 +7,7 @@ use portable_pty::PtySize;
 use rangeset::range_intersection;
 use serde::{Deserialize, Serialize};
 use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 use url::Url;
@@ -17,6 +18,9 @@ pub type Cursor = bintree::Cursor<Rc<dyn Pane>, SplitDirectionAndSize>;
 static TAB_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type TabId = usize;
 
+/// Identifies a GUI client that is attached to the mux server
+pub type ClientId = usize;
+
 /// A Tab is a container of Panes
 pub struct Tab {
     id: TabId,
@@ -24,6 +28,8 @@ pub struct Tab {
     size: RefCell<PtySize>,
     active: RefCell<usize>,
     zoomed: RefCell<Option<Rc<dyn Pane>>>,
+    /// The sizes at which attached clients are displaying this tab
+    client_sizes: RefCell<HashMap<ClientId, PtySize>>,
 }
 
 #[derive(Clone)]
@@ -166,6 +172,10 @@ fn pane_tree(
         Tree::Leaf(pane) => {
             let dims = pane.get_dimensions();
             let working_dir = pane.get_current_working_dir();
+            let domain_name = Mux::get()
+                .and_then(|mux| mux.get_domain(pane.domain_id()))
+                .map(|domain| domain.domain_name().to_string())
+                .unwrap_or_default();
 
             PaneNode::Leaf(PaneEntry {
                 window_id,
@@ -181,6 +191,7 @@ fn pane_tree(
                     pixel_width: 0,
                 },
                 working_dir: working_dir.map(Into::into),
+                domain_name,
             })
         }
     }
@@ -391,6 +402,15 @@ fn apply_sizes_from_splits(tree: &Tree, size: &PtySize) {
     }
 }
 
+fn positioned_size(pos: &PositionedPane) -> PtySize {
+    PtySize {
+        rows: pos.height as u16,
+        cols: pos.width as u16,
+        pixel_width: pos.pixel_width as u16,
+        pixel_height: pos.pixel_height as u16,
+    }
+}
+
 fn cell_dimensions(size: &PtySize) -> PtySize {
     PtySize {
         rows: 1,
@@ -408,9 +428,51 @@ impl Tab {
             size: RefCell::new(*size),
             active: RefCell::new(0),
             zoomed: RefCell::new(None),
+            client_sizes: RefCell::new(HashMap::new()),
         }
     }
 
+    /// Records the size at which a client is displaying this tab, or
+    /// that it has stopped displaying it, and then resizes the tab to
+    /// fit the smallest of the clients that are displaying it.
+    pub fn set_client_size(&self, client_id: ClientId, size: Option<PtySize>) {
+        match size {
+            Some(size) => {
+                self.client_sizes.borrow_mut().insert(client_id, size);
+            }
+            None => {
+                self.client_sizes.borrow_mut().remove(&client_id);
+            }
+        }
+
+        let smallest = self
+            .client_sizes
+            .borrow()
+            .values()
+            .filter(|size| size.rows > 0 && size.cols > 0)
+            .fold(None, |smallest: Option<PtySize>, size| {
+                let dims = cell_dimensions(size);
+                let (cols, rows) = match smallest {
+                    Some(smallest) => (smallest.cols.min(size.cols), smallest.rows.min(size.rows)),
+                    None => (size.cols, size.rows),
+                };
+                Some(PtySize {
+                    rows,
+                    cols,
+                    pixel_width: cols * dims.pixel_width,
+                    pixel_height: rows * dims.pixel_height,
+                })
+            });
+        if let Some(size) = smallest {
+            self.resize(size);
+        }
+    }
+
+    /// Returns the number of clients that are displaying this tab
+    pub fn count_viewing_clients(&self) -> usize {
+        self.client_sizes.borrow().len()
+    }
+
     /// Called by the multiplexer client when building a local tab to
     /// mirror a remote tab.  The supplied `root` is the information
     /// about our counterpart in the the remote server.
@@ -1089,18 +1151,100 @@ impl Tab {
     }
 
     pub fn prune_dead_panes(&self) -> bool {
-        self.remove_pane_if(|_, pane| pane.is_dead())
+        !self
+            .remove_pane_if(|_, pane| pane.is_dead(), true)
+            .is_empty()
     }
 
     pub fn kill_pane(&self, pane_id: PaneId) -> bool {
-        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id)
+        !self
+            .remove_pane_if(|_, pane| pane.pane_id() == pane_id, true)
+            .is_empty()
     }
 
     pub fn kill_panes_in_domain(&self, domain: DomainId) -> bool {
-        self.remove_pane_if(|_, pane| pane.domain_id() == domain)
+        !self
+            .remove_pane_if(|_, pane| pane.domain_id() == domain, true)
+            .is_empty()
     }
 
-    fn remove_pane_if<F>(&self, f: F) -> bool
+    /// Removes the pane from this tab without killing it, so that
+    /// it can be placed somewhere else.
+    /// The remaining panes are resized to fill the space that it used.
+    pub fn remove_pane(&self, pane_id: PaneId) -> Option<Rc<dyn Pane>> {
+        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id, false)
+            .pop()
+    }
+
+    /// Calls `f` with each of the panes in this tab, in the order in
+    /// which `iter_panes` returns them, so that it can replace them
+    fn replace_leaves<F>(&self, mut f: F)
+    where
+        F: FnMut(&mut Rc<dyn Pane>),
+    {
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = root.take().unwrap().cursor();
+        loop {
+            if let Some(pane) = cursor.leaf_mut() {
+                f(pane);
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Puts `pane` in the place of the pane whose id is `pane_id`,
+    /// resizing it to fit, and returns the pane that it replaced.
+    /// The tab must not be zoomed.
+    pub fn replace_pane(&self, pane_id: PaneId, pane: Rc<dyn Pane>) -> Option<Rc<dyn Pane>> {
+        let pos = self
+            .iter_panes()
+            .into_iter()
+            .find(|p| p.pane.pane_id() == pane_id)?;
+        pane.resize(positioned_size(&pos)).ok();
+
+        let mut replaced = None;
+        self.replace_leaves(|leaf| {
+            if replaced.is_none() && leaf.pane_id() == pane_id {
+                replaced.replace(std::mem::replace(leaf, Rc::clone(&pane)));
+            }
+        });
+        replaced
+    }
+
+    /// Exchanges the positions of two of the panes in this tab,
+    /// resizing each of them to fit the space of the other.
+    /// The tab must not be zoomed.
+    pub fn swap_panes(&self, pane_id: PaneId, other_pane_id: PaneId) -> anyhow::Result<()> {
+        let panes = self.iter_panes();
+        let find = |id: PaneId| {
+            panes
+                .iter()
+                .find(|p| p.pane.pane_id() == id)
+                .ok_or_else(|| anyhow::anyhow!("pane {} is not in tab {}", id, self.id))
+        };
+        let pos = find(pane_id)?;
+        let other = find(other_pane_id)?;
+        pos.pane.resize(positioned_size(other)).ok();
+        other.pane.resize(positioned_size(pos)).ok();
+
+        self.replace_leaves(|leaf| {
+            let id = leaf.pane_id();
+            if id == pane_id {
+                *leaf = Rc::clone(&other.pane);
+            } else if id == other_pane_id {
+                *leaf = Rc::clone(&pos.pane);
+            }
+        });
+        Ok(())
+    }
+
+    fn remove_pane_if<F>(&self, f: F, kill: bool) -> Vec<Rc<dyn Pane>>
     where
         F: Fn(usize, &Rc<dyn Pane>) -> bool,
     {
@@ -1136,7 +1280,7 @@ impl Tab {
                         let parent;
                         match cursor.unsplit_leaf() {
                             Ok((c, dead, p)) => {
-                                dead_panes.push(dead.pane_id());
+                                dead_panes.push(dead);
                                 parent = p.unwrap();
                                 cursor = c;
                             }
@@ -1144,7 +1288,7 @@ impl Tab {
                                 // We might be the root, for example
                                 if c.is_top() && c.is_leaf() {
                                     root.replace(Tree::Empty);
-                                    dead_panes.push(pane.pane_id());
+                                    dead_panes.push(pane);
                                 } else {
                                     root.replace(c.tree());
                                 }
@@ -1186,18 +1330,17 @@ impl Tab {
             *self.active.borrow_mut() = active_idx;
         }
 
-        if !dead_panes.is_empty() {
+        if kill && !dead_panes.is_empty() {
+            let dead_pane_ids: Vec<PaneId> = dead_panes.iter().map(|p| p.pane_id()).collect();
             promise::spawn::spawn_into_main_thread(async move {
                 let mux = Mux::get().unwrap();
-                for pane_id in dead_panes.into_iter() {
+                for pane_id in dead_pane_ids.into_iter() {
                     mux.remove_pane(pane_id);
                 }
             })
             .detach();
-            true
-        } else {
-            false
         }
+        dead_panes
     }
 
     pub fn is_dead(&self) -> bool {
@@ -1314,6 +1457,34 @@ impl Tab {
         pane_index: usize,
         direction: SplitDirection,
         pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        self.split_and_insert_impl(pane_index, direction, pane, false)
+    }
+
+    /// Split the pane that has pane_index and assign the provided Pane
+    /// instance to the side of the split given by `side`.
+    /// Returns the resultant index of the newly inserted pane.
+    pub fn insert_pane_beside(
+        &self,
+        pane_index: usize,
+        side: PaneDirection,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        let (direction, before) = match side {
+            PaneDirection::Left => (SplitDirection::Horizontal, true),
+            PaneDirection::Right => (SplitDirection::Horizontal, false),
+            PaneDirection::Up => (SplitDirection::Vertical, true),
+            PaneDirection::Down => (SplitDirection::Vertical, false),
+        };
+        self.split_and_insert_impl(pane_index, direction, pane, before)
+    }
+
+    fn split_and_insert_impl(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+        before: bool,
     ) -> anyhow::Result<usize> {
         if self.zoomed.borrow().is_some() {
             anyhow::bail!("cannot split while zoomed");
@@ -1358,10 +1529,17 @@ impl Tab {
 
             let existing_pane = Rc::clone(cursor.leaf_mut().unwrap());
 
-            existing_pane.resize(split_info.first)?;
-            pane.resize(split_info.second.clone())?;
+            let inserted = if before {
+                existing_pane.resize(split_info.second)?;
+                pane.resize(split_info.first)?;
+                cursor.split_leaf_and_insert_left(pane)
+            } else {
+                existing_pane.resize(split_info.first)?;
+                pane.resize(split_info.second)?;
+                cursor.split_leaf_and_insert_right(pane)
+            };
 
-            match cursor.split_leaf_and_insert_right(pane) {
+            match inserted {
                 Ok(c) => cursor = c,
                 Err(c) => {
                     root.replace(c.tree());
@@ -1374,14 +1552,15 @@ impl Tab {
             match cursor.assign_node(Some(split_info)) {
                 Err(c) | Ok(c) => root.replace(c.tree()),
             };
-
-            *self.active.borrow_mut() = pane_index + 1;
         }
 
+        let new_index = if before { pane_index } else { pane_index + 1 };
+        *self.active.borrow_mut() = new_index;
+
         log::debug!("split info after split: {:#?}", self.iter_splits());
         log::debug!("pane info after split: {:#?}", self.iter_panes());
 
-        Ok(pane_index + 1)
+        Ok(new_index)
     }
 }
 
@@ -1443,6 +1622,10 @@ pub struct PaneEntry {
     pub working_dir: Option<SerdeUrl>,
     pub is_active_pane: bool,
     pub is_zoomed_pane: bool,
+    /// The name of the domain of the pane.  This is empty in the
+    /// session snapshots saved before it was added.
+    #[serde(default)]
+    pub domain_name: String,
 }
 
 #[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
@@ -1727,4 +1910,105 @@ mod test {
         assert_eq!(390, panes[2].pixel_width);
         assert_eq!(600, panes[2].pixel_height);
     }
+
+    #[test]
+    fn tab_moving_panes() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        tab.split_and_insert(0, SplitDirection::Horizontal, FakePane::new(2, size))
+            .unwrap();
+
+        let moved = tab.remove_pane(2).unwrap();
+        assert_eq!(2, moved.pane_id());
+        assert!(tab.remove_pane(2).is_none());
+        let panes = tab.iter_panes();
+        assert_eq!(1, panes.len());
+        assert_eq!(1, panes[0].pane.pane_id());
+        assert_eq!(80, panes[0].width);
+
+        let new_index = tab
+            .insert_pane_beside(0, PaneDirection::Left, moved)
+            .unwrap();
+        assert_eq!(0, new_index);
+
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes.len());
+        assert_eq!(2, panes[0].pane.pane_id());
+        assert_eq!(true, panes[0].is_active);
+        assert_eq!(0, panes[0].left);
+        assert_eq!(40, panes[0].width);
+        assert_eq!(1, panes[1].pane.pane_id());
+        assert_eq!(41, panes[1].left);
+        assert_eq!(39, panes[1].width);
+    }
+
+    #[test]
+    fn tab_swapping_panes() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        tab.split_and_insert(0, SplitDirection::Horizontal, FakePane::new(2, size))
+            .unwrap();
+
+        tab.swap_panes(1, 2).unwrap();
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes.len());
+        assert_eq!(2, panes[0].pane.pane_id());
+        assert_eq!(40, panes[0].width);
+        assert_eq!(1, panes[1].pane.pane_id());
+        assert_eq!(39, panes[1].width);
+        assert!(tab.swap_panes(1, 3).is_err());
+
+        let replaced = tab.replace_pane(1, FakePane::new(3, size)).unwrap();
+        assert_eq!(1, replaced.pane_id());
+        assert!(tab.replace_pane(1, replaced).is_none());
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes[0].pane.pane_id());
+        assert_eq!(3, panes[1].pane.pane_id());
+        assert_eq!(39, panes[1].width);
+    }
+
+    #[test]
+    fn tab_client_sizes() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+        let small = PtySize {
+            rows: 20,
+            cols: 100,
+            pixel_width: 1000,
+            pixel_height: 500,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        tab.set_client_size(1, Some(size));
+        tab.set_client_size(2, Some(small));
+        assert_eq!(2, tab.count_viewing_clients());
+        let combined = tab.get_size();
+        assert_eq!(80, combined.cols);
+        assert_eq!(20, combined.rows);
+
+        // Once the smaller client stops viewing, the tab grows back
+        tab.set_client_size(2, None);
+        assert_eq!(1, tab.count_viewing_clients());
+        assert_eq!(size, tab.get_size());
+    }
 }
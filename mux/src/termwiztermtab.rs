@@ -113,7 +113,7 @@ impl TermWizTerminalPane {
 
         let terminal = RefCell::new(wezterm_term::Terminal::new(
             crate::pty_size_to_terminal_size(size),
-            std::sync::Arc::new(config::TermConfig {}),
+            std::sync::Arc::new(config::TermConfig::new()),
             "WezTerm",
             config::wezterm_version(),
             Box::new(Vec::new()), // FIXME: connect to something?
@@ -245,6 +245,10 @@ impl Pane for TermWizTerminalPane {
         self.terminal.borrow().is_alt_screen_active()
     }
 
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        self.terminal.borrow().bracketed_paste_enabled()
+    }
+
     fn get_current_working_dir(&self) -> Option<Url> {
         self.terminal.borrow().get_current_dir().cloned()
     }
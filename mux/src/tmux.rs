@@ -1,14 +1,19 @@
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
-use crate::pane::{Pane, PaneId};
-use crate::tab::{SplitDirection, Tab, TabId};
+use crate::localpane::LocalPane;
+use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::tab::{PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, Tab, TabId};
+use crate::tmux_pty::{TmuxChild, TmuxPty};
 use crate::window::WindowId;
 use crate::Mux;
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
+use crossbeam::channel::{unbounded, Sender};
 use portable_pty::{CommandBuilder, PtySize};
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tmux_cc::*;
 
@@ -21,103 +26,323 @@ enum State {
 
 trait TmuxCommand {
     fn get_command(&self) -> String;
-    fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()>;
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()>;
+
+    fn process_error(&self, _domain: &TmuxDomain, result: &Guarded) {
+        log::error!(
+            "tmux command `{}` failed: {}",
+            self.get_command().trim_end(),
+            result.output.trim_end()
+        );
+    }
+}
+
+/// Quotes `s` so that tmux sees it as a single argument
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' || c == '$' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses a tmux id, skipping its sigil, such as `$`, `%` or `@`
+fn parse_id(field: Option<&str>, what: &str) -> anyhow::Result<u64> {
+    let field = field.ok_or_else(|| anyhow!("missing {}", what))?;
+    field
+        .get(1..)
+        .unwrap_or("")
+        .parse()
+        .with_context(|| format!("invalid {} {}", what, field))
+}
+
+/// Parses `#{window_id} #{window_layout}` lines and brings the
+/// corresponding tabs up to date
+fn sync_windows_from_output(domain: &TmuxDomain, output: &str) -> anyhow::Result<Vec<TabId>> {
+    let mut tabs = vec![];
+    for line in output.lines() {
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split(' ');
+        let window_id = parse_id(fields.next(), "window_id")?;
+        let layout = parse_layout(fields.next().ok_or_else(|| anyhow!("missing layout"))?)?;
+        tabs.push(domain.inner.sync_window(window_id, &layout)?);
+    }
+    Ok(tabs)
 }
 
-struct ListAllPanes;
-impl TmuxCommand for ListAllPanes {
+const WINDOW_FORMAT: &str = "'#{window_id} #{window_layout}'";
+
+struct ListWindows;
+impl TmuxCommand for ListWindows {
     fn get_command(&self) -> String {
-        "list-panes -aF '#{session_id} #{window_id} #{pane_id} \
-            #{pane_index} #{cursor_x} #{cursor_y} #{pane_width} #{pane_height} \
-            #{pane_left} #{pane_top}'\n"
-            .to_owned()
-    }
-
-    fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
-        #[derive(Debug)]
-        struct Item {
-            session_id: TmuxSessionId,
-            window_id: TmuxWindowId,
-            pane_id: TmuxPaneId,
-            pane_index: u64,
-            cursor_x: u64,
-            cursor_y: u64,
-            pane_width: u64,
-            pane_height: u64,
-            pane_left: u64,
-            pane_top: u64,
-        }
-
-        let mut items = vec![];
-
-        for line in result.output.split('\n') {
-            if line.is_empty() {
-                continue;
-            }
-            let mut fields = line.split(' ');
-            let session_id = fields.next().ok_or_else(|| anyhow!("missing session_id"))?;
-            let window_id = fields.next().ok_or_else(|| anyhow!("missing window_id"))?;
-            let pane_id = fields.next().ok_or_else(|| anyhow!("missing pane_id"))?;
-            let pane_index = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_index"))?
-                .parse()?;
-            let cursor_x = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_x"))?
-                .parse()?;
-            let cursor_y = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_y"))?
-                .parse()?;
-            let pane_width = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_width"))?
-                .parse()?;
-            let pane_height = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_height"))?
-                .parse()?;
-            let pane_left = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_left"))?
-                .parse()?;
-            let pane_top = fields
+        format!("list-windows -F {}\n", WINDOW_FORMAT)
+    }
+
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let tabs = sync_windows_from_output(domain, &result.output)?;
+
+        // Anything that isn't listed has gone away
+        let closed: Vec<TmuxWindowId> = domain
+            .inner
+            .windows
+            .borrow()
+            .iter()
+            .filter_map(|(window, tab_id)| {
+                if tabs.contains(tab_id) {
+                    None
+                } else {
+                    Some(*window)
+                }
+            })
+            .collect();
+        for window in closed {
+            domain.inner.close_window(window);
+        }
+        Ok(())
+    }
+}
+
+struct NewWindow {
+    cwd: Option<String>,
+    argv: Vec<String>,
+    tx: smol::channel::Sender<anyhow::Result<TabId>>,
+}
+impl TmuxCommand for NewWindow {
+    fn get_command(&self) -> String {
+        let mut cmd = format!("new-window -P -F {}", WINDOW_FORMAT);
+        if let Some(cwd) = &self.cwd {
+            cmd.push_str(" -c ");
+            cmd.push_str(&quote(cwd));
+        }
+        for arg in &self.argv {
+            cmd.push(' ');
+            cmd.push_str(&quote(arg));
+        }
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let tab = sync_windows_from_output(domain, &result.output).and_then(|tabs| {
+            tabs.into_iter()
                 .next()
-                .ok_or_else(|| anyhow!("missing pane_top"))?
-                .parse()?;
-
-            // These ids all have various sigils such as `$`, `%`, `@`,
-            // so skip those prior to parsing them
-            let session_id = session_id[1..].parse()?;
-            let window_id = window_id[1..].parse()?;
-            let pane_id = pane_id[1..].parse()?;
-
-            items.push(Item {
-                session_id,
-                window_id,
-                pane_id,
-                pane_index,
-                cursor_x,
-                cursor_y,
-                pane_width,
-                pane_height,
-                pane_left,
-                pane_top,
-            });
+                .ok_or_else(|| anyhow!("new-window didn't describe the new window"))
+        });
+        self.tx.try_send(tab).ok();
+        Ok(())
+    }
+
+    fn process_error(&self, _domain: &TmuxDomain, result: &Guarded) {
+        self.tx
+            .try_send(Err(anyhow!(
+                "new-window failed: {}",
+                result.output.trim_end()
+            )))
+            .ok();
+    }
+}
+
+struct SplitWindow {
+    pane: TmuxPaneId,
+    direction: SplitDirection,
+    cwd: Option<String>,
+    argv: Vec<String>,
+    tx: smol::channel::Sender<anyhow::Result<PaneId>>,
+}
+impl SplitWindow {
+    /// Parses `#{pane_id} #{window_id} #{window_layout}`
+    fn new_pane(&self, domain: &TmuxDomain, output: &str) -> anyhow::Result<PaneId> {
+        let line = output.lines().next().unwrap_or("");
+        let (pane, window) = match line.find(' ') {
+            Some(idx) => (&line[..idx], &line[idx + 1..]),
+            None => anyhow::bail!("split-window didn't describe the new pane"),
+        };
+        let pane = parse_id(Some(pane), "pane_id")?;
+        sync_windows_from_output(domain, window)?;
+        domain
+            .inner
+            .panes
+            .borrow()
+            .get(&pane)
+            .map(|p| p.local_pane_id)
+            .ok_or_else(|| anyhow!("split-window created unknown pane %{}", pane))
+    }
+}
+
+impl TmuxCommand for SplitWindow {
+    fn get_command(&self) -> String {
+        let mut cmd = format!(
+            "split-window -t %{} {} -P -F '#{{pane_id}} #{{window_id}} #{{window_layout}}'",
+            self.pane,
+            match self.direction {
+                SplitDirection::Horizontal => "-h",
+                SplitDirection::Vertical => "-v",
+            }
+        );
+        if let Some(cwd) = &self.cwd {
+            cmd.push_str(" -c ");
+            cmd.push_str(&quote(cwd));
+        }
+        for arg in &self.argv {
+            cmd.push(' ');
+            cmd.push_str(&quote(arg));
+        }
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let pane = self.new_pane(domain, &result.output);
+        self.tx.try_send(pane).ok();
+        Ok(())
+    }
+
+    fn process_error(&self, _domain: &TmuxDomain, result: &Guarded) {
+        self.tx
+            .try_send(Err(anyhow!(
+                "split-window failed: {}",
+                result.output.trim_end()
+            )))
+            .ok();
+    }
+}
+
+/// Fetches the visible content of a pane that we have only just
+/// learned about, as tmux only sends us output as it changes
+struct CapturePane(TmuxPaneId);
+impl TmuxCommand for CapturePane {
+    fn get_command(&self) -> String {
+        format!("capture-pane -p -e -t %{}\n", self.0)
+    }
+
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let content = result.output.strip_suffix('\n').unwrap_or(&result.output);
+        let mut text = String::from("\x1b[H\x1b[2J");
+        text.push_str(&content.replace('\n', "\r\n"));
+        domain.inner.pane_output(self.0, text.into_bytes());
+        Ok(())
+    }
+}
+
+struct PaneCursor(TmuxPaneId);
+impl TmuxCommand for PaneCursor {
+    fn get_command(&self) -> String {
+        format!(
+            "display-message -p -t %{} '#{{cursor_x}} #{{cursor_y}}'\n",
+            self.0
+        )
+    }
+
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let mut fields = result.output.trim_end().split(' ');
+        let x: u64 = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing cursor_x"))?
+            .parse()?;
+        let y: u64 = fields
+            .next()
+            .ok_or_else(|| anyhow!("missing cursor_y"))?
+            .parse()?;
+        domain
+            .inner
+            .pane_output(self.0, format!("\x1b[{};{}H", y + 1, x + 1).into_bytes());
+        Ok(())
+    }
+}
+
+struct SendKeys {
+    pane: TmuxPaneId,
+    data: Vec<u8>,
+}
+impl TmuxCommand for SendKeys {
+    fn get_command(&self) -> String {
+        let mut cmd = format!("send-keys -t %{} -H", self.pane);
+        for b in &self.data {
+            cmd.push_str(&format!(" {:02x}", b));
         }
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(&self, _domain: &TmuxDomain, _result: &Guarded) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct KillPane(TmuxPaneId);
+impl TmuxCommand for KillPane {
+    fn get_command(&self) -> String {
+        format!("kill-pane -t %{}\n", self.0)
+    }
+
+    fn process_result(&self, _domain: &TmuxDomain, _result: &Guarded) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct RefreshClient {
+    cols: u16,
+    rows: u16,
+}
+impl TmuxCommand for RefreshClient {
+    fn get_command(&self) -> String {
+        format!("refresh-client -C {},{}\n", self.cols, self.rows)
+    }
+
+    fn process_result(&self, _domain: &TmuxDomain, _result: &Guarded) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies a tmux paste buffer to the clipboard
+struct ShowBuffer(String);
+impl TmuxCommand for ShowBuffer {
+    fn get_command(&self) -> String {
+        format!("show-buffer -b {}\n", quote(&self.0))
+    }
 
-        log::error!("panes in domain_id {}: {:?}", domain_id, items);
+    fn process_result(&self, domain: &TmuxDomain, result: &Guarded) -> anyhow::Result<()> {
+        let text = result.output.strip_suffix('\n').unwrap_or(&result.output);
+        let mux = Mux::get().expect("to be called on main thread");
+        if let Some(clipboard) = domain
+            .inner
+            .mux_window()
+            .and_then(|window_id| mux.get_window(window_id))
+            .and_then(|window| window.get_clipboard())
+        {
+            clipboard.set_contents(Some(text.to_string()))?;
+        }
         Ok(())
     }
 }
 
+/// A pane in the tmux server, which is presented by a LocalPane
+pub(crate) struct TmuxRemotePane {
+    local_pane_id: PaneId,
+    window: TmuxWindowId,
+    output: Sender<Vec<u8>>,
+    dead: Arc<AtomicBool>,
+}
+
 pub(crate) struct TmuxDomainState {
+    /// The pane that is running `tmux -CC`
     pane_id: PaneId,
     pub domain_id: DomainId,
     parser: RefCell<Parser>,
     state: RefCell<State>,
     cmd_queue: RefCell<VecDeque<Box<dyn TmuxCommand>>>,
+    windows: RefCell<HashMap<TmuxWindowId, TabId>>,
+    panes: RefCell<HashMap<TmuxPaneId, TmuxRemotePane>>,
+    active_panes: RefCell<HashMap<TmuxWindowId, TmuxPaneId>>,
+    /// The size that tmux was last told to use for our client
+    client_size: RefCell<Option<(u16, u16)>>,
 }
 
 pub struct TmuxDomain {
@@ -126,32 +351,55 @@ pub struct TmuxDomain {
 
 impl TmuxDomainState {
     pub fn advance(&self, b: u8) {
-        let mut parser = self.parser.borrow_mut();
-        if let Some(event) = parser.advance_byte(b) {
-            let state = *self.state.borrow();
-            log::error!("tmux: {:?} in state {:?}", event, state);
-            if let Event::Guarded(response) = event {
-                match state {
-                    State::WaitForInitialGuard => {
-                        *self.state.borrow_mut() = State::Idle;
-                    }
-                    State::WaitingForResponse => {
-                        let cmd = self.cmd_queue.borrow_mut().pop_front().unwrap();
-                        let domain_id = self.domain_id;
-                        *self.state.borrow_mut() = State::Idle;
-                        promise::spawn::spawn(async move {
-                            if let Err(err) = cmd.process_result(domain_id, &response) {
-                                log::error!("error processing result: {}", err);
+        let event = match self.parser.borrow_mut().advance_byte(b) {
+            Some(event) => event,
+            None => return,
+        };
+        let state = *self.state.borrow();
+        log::trace!("tmux: {:?} in state {:?}", event, state);
+        let domain_id = self.domain_id;
+        match event {
+            Event::Output { pane, text } => {
+                self.pane_output(pane, text.into_bytes());
+            }
+            Event::Guarded(response) => match state {
+                State::WaitForInitialGuard => {
+                    *self.state.borrow_mut() = State::Idle;
+                }
+                State::WaitingForResponse => {
+                    let cmd = self.cmd_queue.borrow_mut().pop_front().unwrap();
+                    *self.state.borrow_mut() = State::Idle;
+                    promise::spawn::spawn(async move {
+                        let mux = Mux::get().expect("to be called on main thread");
+                        if let Some(domain) = mux.get_domain(domain_id) {
+                            if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                                if response.error {
+                                    cmd.process_error(tmux, &response);
+                                } else if let Err(err) = cmd.process_result(tmux, &response) {
+                                    log::error!("error processing result: {:#}", err);
+                                }
                             }
-                        })
-                        .detach();
-                    }
-                    State::Idle => {}
+                        }
+                    })
+                    .detach();
                 }
+                State::Idle => {}
+            },
+            event => {
+                // The control pane is busy parsing its output, so
+                // defer making changes to the mux until it is done
+                promise::spawn::spawn(async move {
+                    let mux = Mux::get().expect("to be called on main thread");
+                    if let Some(domain) = mux.get_domain(domain_id) {
+                        if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                            tmux.inner.process_event(event);
+                        }
+                    }
+                })
+                .detach();
             }
         }
         if *self.state.borrow() == State::Idle && !self.cmd_queue.borrow().is_empty() {
-            let domain_id = self.domain_id;
             promise::spawn::spawn(async move {
                 let mux = Mux::get().expect("to be called on main thread");
                 if let Some(domain) = mux.get_domain(domain_id) {
@@ -164,13 +412,51 @@ impl TmuxDomainState {
         }
     }
 
+    fn process_event(&self, event: Event) {
+        match event {
+            Event::WindowAdd { .. } | Event::SessionChanged { .. } => {
+                self.queue_command(Box::new(ListWindows));
+            }
+            Event::WindowClose { window } => self.close_window(window),
+            Event::LayoutChange { window, layout } => {
+                if let Err(err) = self.sync_window(window, &layout) {
+                    log::error!("failed to apply tmux layout for @{}: {:#}", window, err);
+                }
+            }
+            Event::WindowPaneChanged { window, pane } => {
+                self.active_panes.borrow_mut().insert(window, pane);
+                self.activate_local_pane(window, pane);
+            }
+            Event::PasteBufferChanged { buffer } => {
+                self.queue_command(Box::new(ShowBuffer(buffer)));
+            }
+            Event::Exit { reason } => {
+                log::info!(
+                    "tmux detached: {}",
+                    reason.as_deref().unwrap_or("no reason given")
+                );
+                // Everything is about to be torn down, and there is
+                // nobody to ask to kill the panes
+                for pane in self.panes.borrow().values() {
+                    pane.dead.store(true, Ordering::Relaxed);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn queue_command(&self, cmd: Box<dyn TmuxCommand>) {
+        self.cmd_queue.borrow_mut().push_back(cmd);
+        self.send_next_command();
+    }
+
     fn send_next_command(&self) {
         if *self.state.borrow() != State::Idle {
             return;
         }
         if let Some(first) = self.cmd_queue.borrow().front() {
             let cmd = first.get_command();
-            log::error!("sending cmd {:?}", cmd);
+            log::trace!("sending cmd {:?}", cmd);
             let mux = Mux::get().expect("to be called on main thread");
             if let Some(pane) = mux.get_pane(self.pane_id) {
                 let mut writer = pane.writer();
@@ -179,6 +465,273 @@ impl TmuxDomainState {
             *self.state.borrow_mut() = State::WaitingForResponse;
         }
     }
+
+    fn pane_output(&self, pane: TmuxPaneId, data: Vec<u8>) {
+        if let Some(pane) = self.panes.borrow().get(&pane) {
+            pane.output.send(data).ok();
+        }
+    }
+
+    /// Returns the mux window that holds the tmux windows, which is
+    /// the one that holds the pane running tmux
+    fn mux_window(&self) -> Option<WindowId> {
+        let mux = Mux::get().expect("to be called on main thread");
+        mux.resolve_pane_id(self.pane_id)
+            .map(|(_domain_id, window_id, _tab_id)| window_id)
+    }
+
+    /// Returns the pixel dimensions of a cell, taken from the
+    /// tab that holds the pane running tmux
+    fn cell_pixel_size(&self) -> (u16, u16) {
+        let mux = Mux::get().expect("to be called on main thread");
+        mux.resolve_pane_id(self.pane_id)
+            .and_then(|(_domain_id, _window_id, tab_id)| mux.get_tab(tab_id))
+            .map(|tab| {
+                let size = tab.get_size();
+                (
+                    size.pixel_width / size.cols.max(1),
+                    size.pixel_height / size.rows.max(1),
+                )
+            })
+            .unwrap_or((8, 16))
+    }
+
+    fn create_pane(
+        &self,
+        tmux_pane: TmuxPaneId,
+        window: TmuxWindowId,
+        size: PtySize,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        let dead = Arc::new(AtomicBool::new(false));
+        let pane_id = alloc_pane_id();
+
+        let pty = TmuxPty::new(self.domain_id, tmux_pane, size, rx);
+        let writer = portable_pty::MasterPty::try_clone_writer(&pty)?;
+        let terminal = wezterm_term::Terminal::new(
+            crate::pty_size_to_terminal_size(size),
+            Arc::new(config::TermConfig::new()),
+            "WezTerm",
+            config::wezterm_version(),
+            writer,
+        );
+        let child = TmuxChild::new(self.domain_id, tmux_pane, Arc::clone(&dead));
+        let pane: Rc<dyn Pane> = Rc::new(
+            LocalPane::new(
+                pane_id,
+                terminal,
+                Box::new(child),
+                Box::new(pty),
+                self.domain_id,
+            )
+            .with_local_echo(),
+        );
+
+        self.panes.borrow_mut().insert(
+            tmux_pane,
+            TmuxRemotePane {
+                local_pane_id: pane_id,
+                window,
+                output: tx,
+                dead,
+            },
+        );
+        let mux = Mux::get().expect("to be called on main thread");
+        mux.add_pane(&pane)?;
+
+        self.queue_command(Box::new(CapturePane(tmux_pane)));
+        self.queue_command(Box::new(PaneCursor(tmux_pane)));
+        Ok(())
+    }
+
+    fn pty_size(&self, cols: u64, rows: u64) -> PtySize {
+        let (cell_width, cell_height) = self.cell_pixel_size();
+        PtySize {
+            rows: rows as u16,
+            cols: cols as u16,
+            pixel_width: cols as u16 * cell_width,
+            pixel_height: rows as u16 * cell_height,
+        }
+    }
+
+    fn layout_to_pane_node(
+        &self,
+        layout: &WindowLayout,
+        window_id: WindowId,
+        tab_id: TabId,
+        active: Option<TmuxPaneId>,
+    ) -> anyhow::Result<PaneNode> {
+        let cells = match &layout.kind {
+            LayoutKind::Pane(id) => {
+                let pane_id = self
+                    .panes
+                    .borrow()
+                    .get(id)
+                    .map(|p| p.local_pane_id)
+                    .ok_or_else(|| anyhow!("no local pane for tmux pane %{}", id))?;
+                return Ok(PaneNode::Leaf(PaneEntry {
+                    window_id,
+                    tab_id,
+                    pane_id,
+                    title: String::new(),
+                    size: self.pty_size(layout.width, layout.height),
+                    working_dir: None,
+                    is_active_pane: active == Some(*id),
+                    is_zoomed_pane: false,
+                    domain_name: "tmux".to_string(),
+                }));
+            }
+            LayoutKind::LeftRight(cells) => (SplitDirection::Horizontal, cells.as_slice()),
+            LayoutKind::TopBottom(cells) => (SplitDirection::Vertical, cells.as_slice()),
+        };
+        self.cells_to_pane_node(cells.0, cells.1, window_id, tab_id, active)
+    }
+
+    /// tmux allows any number of cells in a row or column, while
+    /// our splits are binary, so nest the trailing cells
+    fn cells_to_pane_node(
+        &self,
+        direction: SplitDirection,
+        cells: &[WindowLayout],
+        window_id: WindowId,
+        tab_id: TabId,
+        active: Option<TmuxPaneId>,
+    ) -> anyhow::Result<PaneNode> {
+        let (first, rest) = cells
+            .split_first()
+            .ok_or_else(|| anyhow!("empty cell list in tmux layout"))?;
+        if rest.is_empty() {
+            return self.layout_to_pane_node(first, window_id, tab_id, active);
+        }
+
+        let left = rest.iter().map(|c| c.x).min().unwrap_or(0);
+        let top = rest.iter().map(|c| c.y).min().unwrap_or(0);
+        let right = rest.iter().map(|c| c.x + c.width).max().unwrap_or(0);
+        let bottom = rest.iter().map(|c| c.y + c.height).max().unwrap_or(0);
+
+        Ok(PaneNode::Split {
+            left: Box::new(self.layout_to_pane_node(first, window_id, tab_id, active)?),
+            right: Box::new(self.cells_to_pane_node(direction, rest, window_id, tab_id, active)?),
+            node: SplitDirectionAndSize {
+                direction,
+                first: self.pty_size(first.width, first.height),
+                second: self.pty_size(right - left, bottom - top),
+            },
+        })
+    }
+
+    /// Creates or updates the tab that represents a tmux window
+    /// so that it has the panes and splits given by `layout`
+    fn sync_window(&self, window: TmuxWindowId, layout: &WindowLayout) -> anyhow::Result<TabId> {
+        let mux = Mux::get().expect("to be called on main thread");
+        let window_id = self
+            .mux_window()
+            .ok_or_else(|| anyhow!("the pane running tmux is not in a window"))?;
+
+        let existing = self
+            .windows
+            .borrow()
+            .get(&window)
+            .and_then(|tab_id| mux.get_tab(*tab_id));
+        let size = self.pty_size(layout.width, layout.height);
+        let (tab, is_new) = match existing {
+            Some(tab) => (tab, false),
+            None => (Rc::new(Tab::new(&size)), true),
+        };
+
+        let live = layout.pane_ids();
+        let closed: Vec<TmuxPaneId> = self
+            .panes
+            .borrow()
+            .iter()
+            .filter(|(id, pane)| pane.window == window && !live.contains(*id))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &live {
+            // Panes can be moved from one window to another
+            if let Some(pane) = self.panes.borrow_mut().get_mut(id) {
+                pane.window = window;
+                continue;
+            }
+            self.create_pane(*id, window, size)?;
+        }
+
+        let active = self.active_panes.borrow().get(&window).copied();
+        let root = self.layout_to_pane_node(layout, window_id, tab.tab_id(), active)?;
+
+        // Our panes have the size that tmux gave them, so there is
+        // no need to echo that back to tmux
+        *self.client_size.borrow_mut() = Some((size.cols, size.rows));
+        tab.sync_with_pane_tree(size, root, |entry| {
+            mux.get_pane(entry.pane_id)
+                .expect("panes from tmux layouts to have been added to the mux")
+        });
+
+        if is_new {
+            mux.add_tab_no_panes(&tab);
+            mux.add_tab_to_window(&tab, window_id)?;
+            self.windows.borrow_mut().insert(window, tab.tab_id());
+        }
+
+        for id in closed {
+            self.close_pane(id);
+        }
+
+        Ok(tab.tab_id())
+    }
+
+    fn close_pane(&self, tmux_pane: TmuxPaneId) {
+        if let Some(pane) = self.panes.borrow_mut().remove(&tmux_pane) {
+            pane.dead.store(true, Ordering::Relaxed);
+            let mux = Mux::get().expect("to be called on main thread");
+            mux.remove_pane(pane.local_pane_id);
+        }
+    }
+
+    fn close_window(&self, window: TmuxWindowId) {
+        let closed: Vec<TmuxPaneId> = self
+            .panes
+            .borrow()
+            .iter()
+            .filter(|(_, pane)| pane.window == window)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in closed {
+            if let Some(pane) = self.panes.borrow_mut().remove(&id) {
+                pane.dead.store(true, Ordering::Relaxed);
+            }
+        }
+        self.active_panes.borrow_mut().remove(&window);
+        if let Some(tab_id) = self.windows.borrow_mut().remove(&window) {
+            let mux = Mux::get().expect("to be called on main thread");
+            mux.remove_tab(tab_id);
+        }
+    }
+
+    fn activate_local_pane(&self, window: TmuxWindowId, tmux_pane: TmuxPaneId) {
+        let mux = Mux::get().expect("to be called on main thread");
+        let tab = self
+            .windows
+            .borrow()
+            .get(&window)
+            .and_then(|tab_id| mux.get_tab(*tab_id));
+        let pane = self
+            .panes
+            .borrow()
+            .get(&tmux_pane)
+            .and_then(|p| mux.get_pane(p.local_pane_id));
+        if let (Some(tab), Some(pane)) = (tab, pane) {
+            tab.set_active_pane(&pane);
+        }
+    }
+
+    fn tmux_pane_for_local(&self, pane_id: PaneId) -> Option<TmuxPaneId> {
+        self.panes
+            .borrow()
+            .iter()
+            .find(|(_, pane)| pane.local_pane_id == pane_id)
+            .map(|(id, _)| *id)
+    }
 }
 
 impl TmuxDomain {
@@ -186,13 +739,17 @@ impl TmuxDomain {
         let domain_id = alloc_domain_id();
         let parser = RefCell::new(Parser::new());
         let mut cmd_queue = VecDeque::<Box<dyn TmuxCommand>>::new();
-        cmd_queue.push_back(Box::new(ListAllPanes));
+        cmd_queue.push_back(Box::new(ListWindows));
         let inner = Arc::new(TmuxDomainState {
             domain_id,
             pane_id,
             parser,
             state: RefCell::new(State::WaitForInitialGuard),
             cmd_queue: RefCell::new(cmd_queue),
+            windows: RefCell::new(HashMap::new()),
+            panes: RefCell::new(HashMap::new()),
+            active_panes: RefCell::new(HashMap::new()),
+            client_size: RefCell::new(None),
         });
         Self { inner }
     }
@@ -200,6 +757,56 @@ impl TmuxDomain {
     fn send_next_command(&self) {
         self.inner.send_next_command();
     }
+
+    pub(crate) fn send_keys(&self, pane: TmuxPaneId, data: &[u8]) {
+        // Keep the command lines to a reasonable length when
+        // something large is pasted
+        for chunk in data.chunks(1024) {
+            self.inner.queue_command(Box::new(SendKeys {
+                pane,
+                data: chunk.to_vec(),
+            }));
+        }
+    }
+
+    pub(crate) fn kill_pane(&self, pane: TmuxPaneId) {
+        self.inner.queue_command(Box::new(KillPane(pane)));
+    }
+
+    /// Tells tmux about the size of our tabs, so that it can fit
+    /// the windows to them
+    pub(crate) fn sync_client_size(&self) {
+        let mux = Mux::get().expect("to be called on main thread");
+        let size = self
+            .inner
+            .windows
+            .borrow()
+            .values()
+            .filter_map(|tab_id| mux.get_tab(*tab_id))
+            .map(|tab| tab.get_size())
+            .next();
+        if let Some(size) = size {
+            let client_size = Some((size.cols, size.rows));
+            if *self.inner.client_size.borrow() != client_size {
+                *self.inner.client_size.borrow_mut() = client_size;
+                self.inner.queue_command(Box::new(RefreshClient {
+                    cols: size.cols,
+                    rows: size.rows,
+                }));
+            }
+        }
+    }
+}
+
+fn command_argv(command: Option<CommandBuilder>) -> Vec<String> {
+    match command {
+        Some(cmd) if !cmd.is_default_prog() => cmd
+            .get_argv()
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+        _ => vec![],
+    }
 }
 
 #[async_trait(?Send)]
@@ -207,22 +814,46 @@ impl Domain for TmuxDomain {
     async fn spawn(
         &self,
         _size: PtySize,
-        _command: Option<CommandBuilder>,
-        _command_dir: Option<String>,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
         _window: WindowId,
     ) -> anyhow::Result<Rc<Tab>> {
-        anyhow::bail!("Spawn not yet implemented for TmuxDomain");
+        let (tx, rx) = smol::channel::bounded(1);
+        self.inner.queue_command(Box::new(NewWindow {
+            cwd: command_dir,
+            argv: command_argv(command),
+            tx,
+        }));
+        let tab_id = rx.recv().await??;
+        let mux = Mux::get().expect("to be called on main thread");
+        mux.get_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab for the new tmux window went away"))
     }
 
     async fn split_pane(
         &self,
-        _command: Option<CommandBuilder>,
-        _command_dir: Option<String>,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
         _tab: TabId,
-        _pane_id: PaneId,
-        _direction: SplitDirection,
+        pane_id: PaneId,
+        direction: SplitDirection,
     ) -> anyhow::Result<Rc<dyn Pane>> {
-        anyhow::bail!("split_pane not yet implemented for TmuxDomain");
+        let pane = self
+            .inner
+            .tmux_pane_for_local(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not a tmux pane", pane_id))?;
+        let (tx, rx) = smol::channel::bounded(1);
+        self.inner.queue_command(Box::new(SplitWindow {
+            pane,
+            direction,
+            cwd: command_dir,
+            argv: command_argv(command),
+            tx,
+        }));
+        let pane_id = rx.recv().await??;
+        let mux = Mux::get().expect("to be called on main thread");
+        mux.get_pane(pane_id)
+            .ok_or_else(|| anyhow!("the new tmux pane went away"))
     }
 
     fn domain_id(&self) -> DomainId {
@@ -238,7 +869,12 @@ impl Domain for TmuxDomain {
     }
 
     fn detach(&self) -> anyhow::Result<()> {
-        anyhow::bail!("detach not implemented for TmuxDomain");
+        let mux = Mux::get().expect("to be called on main thread");
+        let pane = mux
+            .get_pane(self.inner.pane_id)
+            .ok_or_else(|| anyhow!("the pane running tmux has gone away"))?;
+        writeln!(pane.writer(), "detach")?;
+        Ok(())
     }
 
     fn state(&self) -> DomainState {
@@ -0,0 +1,183 @@
+//! Presents a pane that lives in a tmux server as though it were
+//! a pty, so that it can be hosted by a `LocalPane`.
+//! Output arrives from the tmux control connection via a channel,
+//! while input, resizes and kills are turned into tmux commands.
+use crate::domain::DomainId;
+use crate::tmux::TmuxDomain;
+use crate::Mux;
+use crossbeam::channel::Receiver;
+use portable_pty::{Child, ExitStatus, MasterPty, PtySize};
+use std::cell::RefCell;
+use std::io::{Read, Result as IoResult, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tmux_cc::TmuxPaneId;
+
+/// Runs `func` against the tmux domain on the main thread.
+/// This may be called from any thread.
+fn with_tmux_domain<F>(domain_id: DomainId, func: F)
+where
+    F: FnOnce(&TmuxDomain) + Send + 'static,
+{
+    promise::spawn::spawn_into_main_thread(async move {
+        let mux = Mux::get().expect("to be called on main thread");
+        if let Some(domain) = mux.get_domain(domain_id) {
+            if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                func(tmux);
+            }
+        }
+    })
+    .detach();
+}
+
+/// Yields the output of a tmux pane, reporting EOF once the
+/// pane has been closed
+pub(crate) struct TmuxReader {
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl Read for TmuxReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv() {
+                Ok(data) => self.pending = data,
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Sends input to a tmux pane
+#[derive(Clone)]
+pub(crate) struct TmuxWriter {
+    domain_id: DomainId,
+    pane: TmuxPaneId,
+}
+
+impl Write for TmuxWriter {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let pane = self.pane;
+        let data = buf.to_vec();
+        with_tmux_domain(self.domain_id, move |tmux| tmux.send_keys(pane, &data));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+pub(crate) struct TmuxPty {
+    writer: TmuxWriter,
+    rx: Receiver<Vec<u8>>,
+    size: RefCell<PtySize>,
+}
+
+impl TmuxPty {
+    pub fn new(
+        domain_id: DomainId,
+        pane: TmuxPaneId,
+        size: PtySize,
+        rx: Receiver<Vec<u8>>,
+    ) -> Self {
+        Self {
+            writer: TmuxWriter { domain_id, pane },
+            rx,
+            size: RefCell::new(size),
+        }
+    }
+}
+
+impl Write for TmuxPty {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.flush()
+    }
+}
+
+impl MasterPty for TmuxPty {
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        *self.size.borrow_mut() = size;
+        // The size of the panes is up to tmux, which arranges them
+        // to fit the size of the client; let it know if that changed
+        with_tmux_domain(self.writer.domain_id, |tmux| tmux.sync_client_size());
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(*self.size.borrow())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(TmuxReader {
+            rx: self.rx.clone(),
+            pending: vec![],
+        }))
+    }
+
+    fn try_clone_writer(&self) -> anyhow::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(self.writer.clone()))
+    }
+
+    #[cfg(unix)]
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        None
+    }
+}
+
+/// Stands in for the process running in a tmux pane
+#[derive(Debug)]
+pub(crate) struct TmuxChild {
+    domain_id: DomainId,
+    pane: TmuxPaneId,
+    dead: Arc<AtomicBool>,
+}
+
+impl TmuxChild {
+    pub fn new(domain_id: DomainId, pane: TmuxPaneId, dead: Arc<AtomicBool>) -> Self {
+        Self {
+            domain_id,
+            pane,
+            dead,
+        }
+    }
+}
+
+impl Child for TmuxChild {
+    fn try_wait(&mut self) -> IoResult<Option<ExitStatus>> {
+        if self.dead.load(Ordering::Relaxed) {
+            Ok(Some(ExitStatus::with_exit_code(0)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn kill(&mut self) -> IoResult<()> {
+        // Only ask tmux to kill the pane if it wasn't tmux
+        // that told us that it had gone away
+        if !self.dead.swap(true, Ordering::Relaxed) {
+            let pane = self.pane;
+            with_tmux_domain(self.domain_id, move |tmux| tmux.kill_pane(pane));
+        }
+        Ok(())
+    }
+
+    fn wait(&mut self) -> IoResult<ExitStatus> {
+        if self.dead.load(Ordering::Relaxed) {
+            Ok(ExitStatus::with_exit_code(0))
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("cannot block waiting for tmux pane %{}", self.pane),
+            ))
+        }
+    }
+}
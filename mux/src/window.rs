@@ -29,6 +29,10 @@ impl Window {
         self.clipboard.replace(Arc::clone(clipboard));
     }
 
+    pub fn get_clipboard(&self) -> Option<Arc<dyn Clipboard>> {
+        self.clipboard.as_ref().map(Arc::clone)
+    }
+
     pub fn window_id(&self) -> WindowId {
         self.id
     }
@@ -90,6 +94,7 @@ impl Window {
     pub fn remove_by_id(&mut self, id: TabId) -> bool {
         if let Some(idx) = self.idx_by_id(id) {
             self.tabs.remove(idx);
+            self.invalidated = true;
             let len = self.tabs.len();
             if len > 0 && self.active == idx && idx >= len {
                 self.set_active(len - 1);
@@ -5,6 +5,9 @@
 //! `openpty` method.
 //! On most (all?) systems, attempting to open multiple instances of
 //! the same serial port will fail.
+//! If the port goes away, as happens when a USB serial adapter is
+//! unplugged, it is reopened once it comes back so that the pane
+//! that is using it can carry on.
 use crate::{Child, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, PtySystem, SlavePty};
 use anyhow::{ensure, Context};
 use filedescriptor::FileDescriptor;
@@ -19,6 +22,35 @@ use std::time::Duration;
 
 type Handle = Arc<Mutex<SystemPort>>;
 
+/// The delay before the first attempt to reopen a port that has gone
+/// away; it doubles after each failed attempt, up to the maximum
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Everything needed to open and configure a port, so that it
+/// can be reopened
+#[derive(Clone)]
+struct PortConfig {
+    port: OsString,
+    settings: PortSettings,
+}
+
+impl PortConfig {
+    fn open(&self) -> anyhow::Result<SystemPort> {
+        let mut port = serial::open(&self.port)
+            .with_context(|| format!("openpty on serial port {:?}", self.port))?;
+        port.configure(&self.settings)?;
+
+        // The timeout needs to be rather short because, at least on Windows,
+        // a read with a long timeout will block a concurrent write from
+        // happening.  In wezterm we tend to have a thread looping on read
+        // while writes happen occasionally from the gui thread, and if we
+        // make this timeout too long we can block the gui thread.
+        port.set_timeout(Duration::from_millis(50))?;
+        Ok(port)
+    }
+}
+
 pub struct SerialTty {
     port: OsString,
     baud: BaudRate,
@@ -63,33 +95,24 @@ impl SerialTty {
 
 impl PtySystem for SerialTty {
     fn openpty(&self, _size: PtySize) -> anyhow::Result<PtyPair> {
-        let mut port = serial::open(&self.port)
-            .with_context(|| format!("openpty on serial port {:?}", self.port))?;
-
-        let settings = PortSettings {
-            baud_rate: self.baud,
-            char_size: self.char_size,
-            parity: self.parity,
-            stop_bits: self.stop_bits,
-            flow_control: self.flow_control,
+        let config = PortConfig {
+            port: self.port.clone(),
+            settings: PortSettings {
+                baud_rate: self.baud,
+                char_size: self.char_size,
+                parity: self.parity,
+                stop_bits: self.stop_bits,
+                flow_control: self.flow_control,
+            },
         };
-        log::debug!("serial settings: {:#?}", settings);
-        port.configure(&settings)?;
-
-        // The timeout needs to be rather short because, at least on Windows,
-        // a read with a long timeout will block a concurrent write from
-        // happening.  In wezterm we tend to have a thread looping on read
-        // while writes happen occasionally from the gui thread, and if we
-        // make this timeout too long we can block the gui thread.
-        port.set_timeout(Duration::from_millis(50))?;
-
-        let port: Handle = Arc::new(Mutex::new(port));
+        log::debug!("serial settings: {:#?}", config.settings);
+        let port: Handle = Arc::new(Mutex::new(config.open()?));
 
         Ok(PtyPair {
             slave: Box::new(Slave {
                 port: Arc::clone(&port),
             }),
-            master: Box::new(Master { port }),
+            master: Box::new(Master { port, config }),
         })
     }
 }
@@ -142,6 +165,7 @@ impl Child for SerialChild {
 
 struct Master {
     port: Handle,
+    config: PortConfig,
 }
 
 impl Write for Master {
@@ -170,12 +194,21 @@ impl MasterPty for Master {
         // that expose the underlying file descriptor, and that direct
         // reads from that return the raw data that we want
         let fd = FileDescriptor::dup(&*self.port.lock().unwrap())?;
-        Ok(Box::new(Reader { fd }))
+        Ok(Box::new(Reader {
+            fd,
+            port: Arc::clone(&self.port),
+            config: self.config.clone(),
+            disconnected: false,
+            pending: vec![],
+        }))
     }
 
     fn try_clone_writer(&self) -> anyhow::Result<Box<dyn std::io::Write + Send>> {
         let port = Arc::clone(&self.port);
-        Ok(Box::new(Master { port }))
+        Ok(Box::new(Master {
+            port,
+            config: self.config.clone(),
+        }))
     }
 
     #[cfg(unix)]
@@ -187,11 +220,66 @@ impl MasterPty for Master {
 
 struct Reader {
     fd: FileDescriptor,
+    port: Handle,
+    config: PortConfig,
+    /// Set when the port has gone away and needs to be reopened
+    disconnected: bool,
+    /// A notice about the state of the connection, for display
+    /// in the terminal
+    pending: Vec<u8>,
+}
+
+impl Reader {
+    /// Waits for the port to come back, backing off between attempts
+    /// to reopen it.  Returns false if the pane has been closed in
+    /// the meantime, leaving nobody to use the port.
+    fn reconnect(&mut self) -> bool {
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        loop {
+            std::thread::sleep(delay);
+            if Arc::strong_count(&self.port) == 1 {
+                return false;
+            }
+
+            let reopened = self.config.open().and_then(|port| {
+                let fd = FileDescriptor::dup(&port)?;
+                Ok((port, fd))
+            });
+            match reopened {
+                Ok((port, fd)) => {
+                    *self.port.lock().unwrap() = port;
+                    self.fd = fd;
+                    return true;
+                }
+                Err(err) => {
+                    log::trace!("reopening serial port: {:#}", err);
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                }
+            }
+        }
+    }
 }
 
 impl Read for Reader {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
         loop {
+            if !self.pending.is_empty() {
+                let len = buf.len().min(self.pending.len());
+                buf[..len].copy_from_slice(&self.pending[..len]);
+                self.pending.drain(..len);
+                return Ok(len);
+            }
+
+            if self.disconnected {
+                if !self.reconnect() {
+                    return Ok(0);
+                }
+                self.disconnected = false;
+                self.pending =
+                    format!("[serial port {:?} reconnected]\r\n", self.config.port).into_bytes();
+                continue;
+            }
+
             match self.fd.read(buf) {
                 Ok(size) => {
                     if size == 0 {
@@ -204,7 +292,12 @@ impl Read for Reader {
                 }
                 Err(e) => {
                     log::error!("serial read error: {}", e);
-                    return Err(e);
+                    self.disconnected = true;
+                    self.pending = format!(
+                        "\r\n[serial port {:?} disconnected: {}; waiting for it to return]\r\n",
+                        self.config.port, e
+                    )
+                    .into_bytes();
                 }
             }
         }
@@ -21,6 +21,28 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         3500
     }
 
+    /// Returns an approximate limit on the memory used by the scrollback,
+    /// in bytes, which may further reduce the number of rows that are
+    /// retained.  0 means that there is no such limit.
+    fn scrollback_bytes(&self) -> usize {
+        0
+    }
+
+    /// Return true if identical consecutive lines that scroll into the
+    /// scrollback should be folded into a single line that records how
+    /// many times it was repeated.
+    fn scrollback_dedup(&self) -> bool {
+        false
+    }
+
+    /// Returns the number of rows that may be kept in a compressed
+    /// form on disk once they have been scrolled beyond the in-memory
+    /// scrollback.  0 disables the disk scrollback.
+    /// This requires the `use_serde` feature.
+    fn disk_scrollback_size(&self) -> usize {
+        0
+    }
+
     /// Return true if the embedding application wants to use CSI-u encoding
     /// for keys that would otherwise be ambiguous.
     /// <http://www.leonerd.org.uk/hacks/fixterms/>
@@ -0,0 +1,278 @@
+//! Holds the lines that have been scrolled out of the in-memory
+//! scrollback of a `Screen`.  Lines are accumulated into blocks that
+//! are serialized, compressed and written to a temporary file that
+//! is used as a ring: once the configured number of lines has been
+//! exceeded, the oldest blocks are forgotten and their space in the
+//! file is re-used for newer blocks.
+use crate::Line;
+use anyhow::Context;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How many lines are compressed together as a unit
+const BLOCK_LINES: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// Where the compressed data lives in the file
+    offset: u64,
+    len: u64,
+    num_lines: usize,
+}
+
+impl Block {
+    fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+
+    fn overlaps(&self, offset: u64, len: u64) -> bool {
+        offset < self.end() && self.offset < offset + len
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskScrollback {
+    file: File,
+    path: PathBuf,
+    /// The blocks in the file, oldest first
+    blocks: VecDeque<Block>,
+    /// Lines that have not yet filled a block
+    pending: Vec<Line>,
+    /// The total number of lines in `blocks` and `pending`
+    num_lines: usize,
+    /// The most recently decoded block, as scrolling and searching
+    /// tend to read the same block several times in succession
+    cache: Mutex<Option<(u64, Arc<Vec<Line>>)>>,
+}
+
+impl Drop for DiskScrollback {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(feature = "use_serde")]
+fn encode_block(lines: &[Line]) -> anyhow::Result<Vec<u8>> {
+    let data = varbincode::serialize(lines)?;
+    Ok(zstd::stream::encode_all(
+        data.as_slice(),
+        zstd::DEFAULT_COMPRESSION_LEVEL,
+    )?)
+}
+
+#[cfg(feature = "use_serde")]
+fn decode_block(data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    let data = zstd::stream::decode_all(data)?;
+    Ok(varbincode::deserialize(data.as_slice())?)
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn encode_block(_lines: &[Line]) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("disk scrollback requires the use_serde feature")
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn decode_block(_data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    anyhow::bail!("disk scrollback requires the use_serde feature")
+}
+
+fn create_file() -> anyhow::Result<(File, PathBuf)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "wezterm-scrollback-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.read(true).write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options
+        .open(&path)
+        .with_context(|| format!("creating scrollback file {}", path.display()))?;
+    Ok((file, path))
+}
+
+impl DiskScrollback {
+    /// Creates a new, empty, store
+    pub fn new() -> anyhow::Result<Self> {
+        if cfg!(not(feature = "use_serde")) {
+            anyhow::bail!("disk scrollback requires the use_serde feature");
+        }
+        let (file, path) = create_file()?;
+        Ok(Self {
+            file,
+            path,
+            blocks: VecDeque::new(),
+            pending: vec![],
+            num_lines: 0,
+            cache: Mutex::new(None),
+        })
+    }
+
+    /// Returns the number of lines that are held
+    pub fn len(&self) -> usize {
+        self.num_lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_lines == 0
+    }
+
+    /// Forgets all of the lines
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.pending.clear();
+        self.num_lines = 0;
+        self.cache.lock().unwrap().take();
+        self.file.set_len(0).ok();
+    }
+
+    /// Appends a line that was scrolled out of memory, forgetting the
+    /// oldest lines if more than `max_lines` lines are held.
+    /// Lines are forgotten a block at a time, so the pending lines
+    /// may exceed `max_lines` when it is smaller than a block.
+    pub fn push(&mut self, line: Line, max_lines: usize) -> anyhow::Result<()> {
+        self.pending.push(line);
+        self.num_lines += 1;
+        if self.pending.len() >= BLOCK_LINES {
+            self.flush_pending()?;
+        }
+
+        while self.num_lines > max_lines {
+            match self.blocks.pop_front() {
+                Some(block) => self.num_lines -= block.num_lines,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks a location for a new block of `len` bytes.  We prefer to
+    /// continue after the newest block if that is within space freed
+    /// up by older blocks, then to wrap around to the start of the file,
+    /// and otherwise grow the file.
+    fn allocate(&self, len: u64) -> u64 {
+        let file_end = self.blocks.iter().map(Block::end).max().unwrap_or(0);
+        let fits = |offset: u64| !self.blocks.iter().any(|b| b.overlaps(offset, len));
+
+        if let Some(newest) = self.blocks.back() {
+            let after_newest = newest.end();
+            if after_newest < file_end && fits(after_newest) {
+                return after_newest;
+            }
+        }
+        if fits(0) {
+            0
+        } else {
+            file_end
+        }
+    }
+
+    fn flush_pending(&mut self) -> anyhow::Result<()> {
+        let data = encode_block(&self.pending)?;
+        let len = data.len() as u64;
+        let offset = self.allocate(len);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file
+            .write_all(&data)
+            .with_context(|| format!("writing to {}", self.path.display()))?;
+        self.blocks.push_back(Block {
+            offset,
+            len,
+            num_lines: self.pending.len(),
+        });
+        self.pending.clear();
+        // The block may have re-used the space of the cached block
+        self.cache.lock().unwrap().take();
+        Ok(())
+    }
+
+    fn read_block(&self, block: &Block) -> anyhow::Result<Arc<Vec<Line>>> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((offset, lines)) = cache.as_ref() {
+            if *offset == block.offset {
+                return Ok(Arc::clone(lines));
+            }
+        }
+
+        let mut data = vec![0u8; block.len as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(block.offset))?;
+        file.read_exact(&mut data)
+            .with_context(|| format!("reading from {}", self.path.display()))?;
+        let lines = Arc::new(decode_block(&data)?);
+        cache.replace((block.offset, Arc::clone(&lines)));
+        Ok(lines)
+    }
+
+    /// Returns copies of the lines in `range`, where index 0 is the
+    /// oldest line that is held
+    pub fn get_lines(&self, range: Range<usize>) -> anyhow::Result<Vec<Line>> {
+        let end = range.end.min(self.num_lines);
+        let mut result = vec![];
+        let mut block_start = 0;
+
+        for block in &self.blocks {
+            let block_end = block_start + block.num_lines;
+            if block_end > range.start && block_start < end {
+                let lines = self.read_block(block)?;
+                let first = range.start.saturating_sub(block_start);
+                let last = (end - block_start).min(block.num_lines);
+                result.extend_from_slice(&lines[first..last]);
+            }
+            block_start = block_end;
+            if block_start >= end {
+                return Ok(result);
+            }
+        }
+
+        let first = range.start.saturating_sub(block_start);
+        let last = end - block_start;
+        if first < last {
+            result.extend_from_slice(&self.pending[first..last]);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(all(test, feature = "use_serde"))]
+mod test {
+    use super::*;
+
+    fn line(n: usize) -> Line {
+        Line::from_text(&n.to_string(), &Default::default())
+    }
+
+    #[test]
+    fn ring() {
+        let mut store = DiskScrollback::new().unwrap();
+        let total = BLOCK_LINES * 5 + 3;
+        for n in 0..total {
+            store.push(line(n), BLOCK_LINES * 2).unwrap();
+        }
+        assert!(store.len() <= BLOCK_LINES * 2);
+        let forgotten = total - store.len();
+
+        let lines = store.get_lines(0..store.len()).unwrap();
+        assert_eq!(lines.len(), store.len());
+        for (idx, l) in lines.iter().enumerate() {
+            assert_eq!(l.as_str().trim_end(), (forgotten + idx).to_string());
+        }
+
+        let lines = store.get_lines(BLOCK_LINES - 1..BLOCK_LINES + 1).unwrap();
+        assert_eq!(
+            lines[1].as_str().trim_end(),
+            (forgotten + BLOCK_LINES).to_string()
+        );
+    }
+}
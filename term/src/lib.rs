@@ -31,6 +31,7 @@ pub use termwiz::cell::{self, *};
 
 pub use termwiz::surface::line::*;
 
+pub mod diskscrollback;
 pub mod screen;
 pub use crate::screen::*;
 
@@ -124,6 +125,20 @@ pub struct SemanticZone {
     pub semantic_type: SemanticType,
 }
 
+/// Describes a shell prompt that was delimited using OSC 133 escape
+/// sequences, along with the command that was entered at it
+#[cfg_attr(feature = "use_serde", derive(Deserialize, Serialize))]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PromptMark {
+    /// Where the prompt starts
+    pub start_y: StableRowIndex,
+    pub start_x: usize,
+    /// The text that was input at the prompt
+    pub command: String,
+    /// The exit status of the command, if the shell reported it
+    pub status: Option<i32>,
+}
+
 pub mod color;
 
 #[cfg(test)]
@@ -1,14 +1,22 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::*;
+use crate::diskscrollback::DiskScrollback;
 use log::debug;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 /// Holds the model of a screen.  This can either be the primary screen
 /// which includes lines of scrollback text, or the alternate screen
 /// which holds no scrollback.  The intent is to have one instance of
 /// Screen for each of these things.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Screen {
     /// Holds the line data that comprises the screen contents.
     /// This is allocated with capacity for the entire scrollback.
@@ -25,6 +33,14 @@ pub struct Screen {
     /// PhysRowIndex and StableRowIndex.
     stable_row_index_offset: usize,
 
+    /// Lines that were scrolled out of `lines`, when the disk scrollback
+    /// is enabled.  These have the stable row indices immediately
+    /// prior to `stable_row_index_offset`.
+    disk: Option<DiskScrollback>,
+    /// Set if we failed to create the disk scrollback, so that we
+    /// don't keep trying
+    disk_failed: bool,
+
     /// config so we can access Maximum number of lines of scrollback
     config: Arc<dyn TerminalConfiguration>,
     allow_scrollback: bool,
@@ -69,11 +85,138 @@ impl Screen {
             physical_rows,
             physical_cols,
             stable_row_index_offset: 0,
+            disk: None,
+            disk_failed: false,
         }
     }
 
     fn scrollback_size(&self) -> usize {
-        scrollback_size(&self.config, self.allow_scrollback)
+        let lines = scrollback_size(&self.config, self.allow_scrollback);
+        match self.config.scrollback_bytes() {
+            0 => lines,
+            bytes => lines.min(bytes / self.line_size()),
+        }
+    }
+
+    /// The approximate memory used by a line of the current width
+    fn line_size(&self) -> usize {
+        std::mem::size_of::<Line>() + self.physical_cols * std::mem::size_of::<Cell>()
+    }
+
+    /// Folds each of the `num_rows` lines that most recently scrolled
+    /// into the scrollback into the line before it when they have the
+    /// same content, counting the repetitions.
+    /// The rows below a folded line move up by one, so they are marked
+    /// dirty in order that their new stable row indices are refreshed.
+    fn dedup_scrollback(&mut self, num_rows: usize) {
+        let mut end = self.lines.len() - self.physical_rows;
+        let first = end.saturating_sub(num_rows).max(1);
+        let mut idx = first;
+        let mut folded = false;
+        while idx < end {
+            let prior = &self.lines[idx - 1];
+            let line = &self.lines[idx];
+            if !prior.last_cell_was_wrapped()
+                && !line.last_cell_was_wrapped()
+                && prior.same_content(line)
+            {
+                let line = self.lines.remove(idx).unwrap();
+                let prior = &mut self.lines[idx - 1];
+                let count = prior
+                    .repeat_count()
+                    .saturating_add(1)
+                    .saturating_add(line.repeat_count());
+                prior.set_repeat_count(count);
+                end -= 1;
+                folded = true;
+            } else {
+                idx += 1;
+            }
+        }
+        if folded {
+            for line in self.lines.iter_mut().skip(first - 1) {
+                line.set_dirty();
+            }
+        }
+    }
+
+    /// Moves a line that no longer fits in the in-memory scrollback
+    /// to the disk scrollback, if that is enabled, otherwise the line
+    /// is discarded
+    fn spill_line(&mut self, line: Line) {
+        let max_lines = if self.allow_scrollback {
+            self.config.disk_scrollback_size()
+        } else {
+            0
+        };
+        if max_lines == 0 {
+            self.disk.take();
+            return;
+        }
+        if self.disk.is_none() && !self.disk_failed {
+            match DiskScrollback::new() {
+                Ok(disk) => self.disk = Some(disk),
+                Err(err) => {
+                    log::error!("disabling disk scrollback: {:#}", err);
+                    self.disk_failed = true;
+                }
+            }
+        }
+        if let Some(disk) = self.disk.as_mut() {
+            if let Err(err) = disk.push(line, max_lines) {
+                log::error!("disabling disk scrollback: {:#}", err);
+                self.disk.take();
+                self.disk_failed = true;
+            }
+        }
+    }
+
+    /// Returns the number of lines held in the disk scrollback
+    pub fn disk_scrollback_len(&self) -> usize {
+        self.disk.as_ref().map(DiskScrollback::len).unwrap_or(0)
+    }
+
+    /// Returns an estimate of the number of bytes used to hold the
+    /// lines of the screen and the in-memory scrollback
+    pub fn approx_memory_usage(&self) -> usize {
+        let spare = self.lines.capacity() - self.lines.len();
+        self.lines
+            .iter()
+            .map(Line::approx_memory_usage)
+            .sum::<usize>()
+            + spare * std::mem::size_of::<Line>()
+    }
+
+    /// Returns the stable row index of the earliest line that we
+    /// remember, including those in the disk scrollback
+    pub fn scrollback_top(&self) -> StableRowIndex {
+        self.phys_to_stable_row_index(0) - self.disk_scrollback_len() as StableRowIndex
+    }
+
+    /// Returns copies of the lines from the disk scrollback that are
+    /// in the specified range, along with the stable row index of the
+    /// first of them.  The portion of the range that is not held in
+    /// the disk scrollback is ignored.
+    pub fn disk_scrollback_lines(
+        &self,
+        range: Range<StableRowIndex>,
+    ) -> (StableRowIndex, Vec<Line>) {
+        let top = self.scrollback_top();
+        let start = range.start.max(top);
+        let end = range.end.min(self.phys_to_stable_row_index(0));
+        if start >= end {
+            return (start, vec![]);
+        }
+        match self.disk.as_ref() {
+            Some(disk) => match disk.get_lines((start - top) as usize..(end - top) as usize) {
+                Ok(lines) => (start, lines),
+                Err(err) => {
+                    log::error!("reading disk scrollback: {:#}", err);
+                    (start, vec![])
+                }
+            },
+            None => (start, vec![]),
+        }
     }
 
     fn rewrap_lines(
@@ -293,6 +436,9 @@ impl Screen {
         //debug!("set_cell x={} y={} phys={} {:?}", x, y, line_idx, cell);
 
         let line = self.line_mut(line_idx);
+        if line.timestamp().is_none() {
+            line.set_timestamp(Some(now_millis()));
+        }
         line.set_cell(x, cell.clone())
     }
 
@@ -517,6 +663,9 @@ impl Screen {
         let (to_remove, to_add) = {
             for _ in 0..to_move {
                 let mut line = self.lines.remove(remove_idx).unwrap();
+                if remove_idx == 0 {
+                    self.spill_line(line.clone());
+                }
                 // Make the line like a new one of the appropriate width
                 line.resize_and_clear(self.physical_cols);
                 line.set_dirty();
@@ -533,7 +682,11 @@ impl Screen {
 
         // Perform the removal
         for _ in 0..to_remove {
-            self.lines.remove(remove_idx);
+            if let Some(line) = self.lines.remove(remove_idx) {
+                if remove_idx == 0 {
+                    self.spill_line(line);
+                }
+            }
         }
 
         if remove_idx == 0 {
@@ -551,6 +704,18 @@ impl Screen {
                     .insert(phys_scroll.end, Line::with_width(self.physical_cols));
             }
         }
+
+        if scroll_region.start == 0 {
+            // Release the blank cells of the lines that just scrolled
+            // into the scrollback
+            let end = self.lines.len() - self.physical_rows;
+            for idx in end.saturating_sub(num_rows)..end {
+                self.lines[idx].compact();
+            }
+            if self.config.scrollback_dedup() {
+                self.dedup_scrollback(num_rows);
+            }
+        }
     }
 
     pub fn erase_scrollback(&mut self) {
@@ -560,6 +725,9 @@ impl Screen {
             self.lines.pop_front();
             self.stable_row_index_offset += 1;
         }
+        if let Some(disk) = self.disk.as_mut() {
+            disk.clear();
+        }
     }
 
     /// ```text
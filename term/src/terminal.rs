@@ -21,6 +21,39 @@ pub trait DeviceControlHandler {
     fn handle_device_control(&mut self, _control: termwiz::escape::DeviceControlMode);
 }
 
+/// A desktop notification requested by the application running
+/// in the terminal, via OSC 9, OSC 777 or OSC 99
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToastNotification {
+    pub title: Option<String>,
+    pub body: String,
+    /// Clicking on the notification should activate the pane
+    pub focus: bool,
+    /// Clicking on the notification should be reported back to the
+    /// application using this kitty notification identifier
+    pub report_id: Option<String>,
+    /// Only show the notification when the pane is not focused
+    pub only_when_unfocused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alert {
+    /// The application rang the bell
+    Bell,
+    ToastNotification(ToastNotification),
+    /// The application set the window or icon title
+    TitleMaybeChanged,
+    /// The application set a user variable via OSC 1337
+    SetUserVar {
+        name: String,
+        value: String,
+    },
+}
+
+pub trait AlertHandler {
+    fn alert(&mut self, alert: Alert);
+}
+
 /// Represents an instance of a terminal emulator.
 pub struct Terminal {
     /// The terminal model/state
@@ -8,7 +8,7 @@ use image::{self, GenericImageView};
 use log::{debug, error};
 use num_traits::FromPrimitive;
 use ordered_float::NotNan;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
 use std::sync::Arc;
 use termwiz::escape::csi::{
@@ -16,7 +16,8 @@ use termwiz::escape::csi::{
     EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window,
 };
 use termwiz::escape::osc::{
-    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermFileData, ITermProprietary,
+    ChangeColorPair, ColorOrQuery, FinalTermPromptKind, FinalTermSemanticPrompt, ITermFileData,
+    ITermProprietary, KittyNotification, KittyNotificationOccasion, KittyNotificationPayload,
 };
 use termwiz::escape::{
     Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, Sixel,
@@ -202,6 +203,12 @@ impl ScreenOrAlt {
     }
 }
 
+struct PendingKittyNotification {
+    id: Option<String>,
+    title: String,
+    body: String,
+}
+
 /// Manages the state for the terminal
 pub struct TerminalState {
     config: Arc<dyn TerminalConfiguration>,
@@ -279,6 +286,8 @@ pub struct TerminalState {
     title: String,
     /// The icon title string (OSC 1)
     icon_title: Option<String>,
+    /// The variables set by the application via OSC 1337 SetUserVar
+    user_vars: HashMap<String, String>,
 
     palette: Option<ColorPalette>,
 
@@ -287,6 +296,9 @@ pub struct TerminalState {
 
     clipboard: Option<Arc<dyn Clipboard>>,
     device_control_handler: Option<Box<dyn DeviceControlHandler>>,
+    alert_handler: Option<Box<dyn AlertHandler>>,
+    /// A kitty notification that is being sent in several chunks
+    pending_kitty_notification: Option<PendingKittyNotification>,
 
     current_dir: Option<Url>,
 
@@ -296,6 +308,10 @@ pub struct TerminalState {
     writer: Box<dyn std::io::Write>,
 
     image_cache: lru::LruCache<[u8; 32], Arc<ImageData>>,
+
+    /// The start of each prompt, as marked by OSC 133, and the exit
+    /// status of the command that was run from it.  Oldest first.
+    prompts: VecDeque<(StableRowIndex, usize, Option<i32>)>,
 }
 
 fn encode_modifiers(mods: KeyModifiers) -> u8 {
@@ -377,16 +393,20 @@ impl TerminalState {
             tabs: TabStop::new(size.physical_cols, 8),
             title: "wezterm".to_string(),
             icon_title: None,
+            user_vars: HashMap::new(),
             palette: None,
             pixel_height: size.pixel_height,
             pixel_width: size.pixel_width,
             clipboard: None,
             device_control_handler: None,
+            alert_handler: None,
+            pending_kitty_notification: None,
             current_dir: None,
             term_program: term_program.to_string(),
             term_version: term_version.to_string(),
             writer: Box::new(std::io::BufWriter::new(writer)),
             image_cache: lru::LruCache::new(16),
+            prompts: VecDeque::new(),
         }
     }
 
@@ -398,6 +418,56 @@ impl TerminalState {
         self.device_control_handler.replace(handler);
     }
 
+    pub fn set_alert_handler(&mut self, handler: Box<dyn AlertHandler>) {
+        self.alert_handler.replace(handler);
+    }
+
+    fn alert(&mut self, alert: Alert) {
+        match self.alert_handler.as_mut() {
+            Some(handler) => handler.alert(alert),
+            None => log::trace!("no alert handler for {:?}", alert),
+        }
+    }
+
+    fn kitty_notification(&mut self, notif: KittyNotification) {
+        let mut pending = match self.pending_kitty_notification.take() {
+            // A chunk for a different notification abandons the one
+            // that we were assembling
+            Some(pending) if pending.id == notif.id => pending,
+            _ => PendingKittyNotification {
+                id: notif.id.clone(),
+                title: String::new(),
+                body: String::new(),
+            },
+        };
+        match notif.payload_type {
+            KittyNotificationPayload::Title => pending.title.push_str(&notif.payload),
+            KittyNotificationPayload::Body => pending.body.push_str(&notif.payload),
+        }
+        if !notif.done {
+            self.pending_kitty_notification.replace(pending);
+            return;
+        }
+
+        // kitty shows the title alone when there is no body
+        let (title, body) = if pending.body.is_empty() {
+            (None, pending.title)
+        } else {
+            (Some(pending.title).filter(|t| !t.is_empty()), pending.body)
+        };
+        self.alert(Alert::ToastNotification(ToastNotification {
+            title,
+            body,
+            focus: notif.focus,
+            report_id: if notif.report {
+                Some(pending.id.unwrap_or_else(|| "0".to_string()))
+            } else {
+                None
+            },
+            only_when_unfocused: notif.occasion != KittyNotificationOccasion::Always,
+        }));
+    }
+
     /// Returns the title text associated with the terminal session.
     /// The title can be changed by the application using a number
     /// of escape sequences:
@@ -419,6 +489,12 @@ impl TerminalState {
         self.icon_title.as_ref().unwrap_or(&self.title)
     }
 
+    /// Returns the variables that the application has set via
+    /// OSC 1337 SetUserVar
+    pub fn user_vars(&self) -> &HashMap<String, String> {
+        &self.user_vars
+    }
+
     /// Returns the current working directory associated with the
     /// terminal session.  The working directory can be changed by
     /// the applicaiton using the OSC 7 escape sequence.
@@ -462,6 +538,12 @@ impl TerminalState {
         &mut self.screen
     }
 
+    /// Returns an estimate of the number of bytes used by both the
+    /// primary and alternate screens
+    pub fn approx_memory_usage(&self) -> usize {
+        self.screen.screen.approx_memory_usage() + self.screen.alt_screen.approx_memory_usage()
+    }
+
     fn set_clipboard_contents(&self, text: Option<String>) -> anyhow::Result<()> {
         if let Some(clip) = self.clipboard.as_ref() {
             clip.set_contents(text)?;
@@ -2654,6 +2736,86 @@ impl TerminalState {
 
         Ok(zones)
     }
+
+    /// Records the start of a new prompt at the cursor position.
+    /// Prompts that are made up of several OSC 133 prompt markers,
+    /// such as those with a right-side prompt, are recorded once.
+    fn record_prompt_start(&mut self) {
+        if self.pen.semantic_type() == SemanticType::Prompt || self.screen.alt_screen_is_active {
+            return;
+        }
+        let screen = &self.screen.screen;
+        let top = screen.scrollback_top();
+        while self.prompts.front().map(|p| p.0 < top).unwrap_or(false) {
+            self.prompts.pop_front();
+        }
+        let y = screen.visible_row_to_stable_row(self.cursor.y);
+        self.prompts.push_back((y, self.cursor.x, None));
+    }
+
+    /// Records the exit status of the command that was run from the
+    /// most recent prompt
+    fn record_command_status(&mut self, status: i32) {
+        if let Some(prompt) = self.prompts.back_mut() {
+            if prompt.2.is_none() {
+                prompt.2 = Some(status);
+            }
+        }
+    }
+
+    /// Returns the text that was input at the prompt that starts at
+    /// the specified position
+    fn prompt_command(&self, start_y: StableRowIndex, start_x: usize) -> String {
+        /// Commands are rarely very long; this bounds the work we do
+        /// when the input markers are absent
+        const MAX_ROWS: isize = 16;
+        let screen = &self.screen.screen;
+        let mut command = String::new();
+
+        for y in start_y..start_y + MAX_ROWS {
+            let line = match screen.stable_row_to_phys(y) {
+                Some(phys) => &screen.lines[phys],
+                None => break,
+            };
+            let skip = if y == start_y { start_x } else { 0 };
+            let mut wrapped = false;
+            for (x, cell) in line.visible_cells() {
+                if x < skip {
+                    continue;
+                }
+                match cell.attrs().semantic_type() {
+                    SemanticType::Input => command.push_str(cell.str()),
+                    SemanticType::Output if !command.is_empty() => {
+                        return command.trim().to_string();
+                    }
+                    _ => {}
+                }
+                wrapped = cell.attrs().wrapped();
+            }
+            if !wrapped && !command.is_empty() {
+                // Multi-line input is joined into a single line
+                command = command.trim_end().to_string();
+                command.push(' ');
+            }
+        }
+
+        command.trim().to_string()
+    }
+
+    /// Returns the prompts whose start is within `range`, oldest first
+    pub fn get_prompt_marks(&self, range: Range<StableRowIndex>) -> Vec<PromptMark> {
+        let top = self.screen.screen.scrollback_top();
+        self.prompts
+            .iter()
+            .filter(|(y, _, _)| *y >= top && range.contains(y))
+            .map(|&(start_y, start_x, status)| PromptMark {
+                start_y,
+                start_x,
+                command: self.prompt_command(start_y, start_x),
+                status,
+            })
+            .collect()
+    }
 }
 
 /// A helper struct for implementing `vtparse::VTActor` while compartmentalizing
@@ -2908,7 +3070,7 @@ impl<'a> Performer<'a> {
             ControlCode::HTS => self.c1_hts(),
             ControlCode::IND => self.c1_index(),
             ControlCode::NEL => self.c1_nel(),
-            ControlCode::Bell => log::info!("Ding! (this is the bell)"),
+            ControlCode::Bell => self.alert(Alert::Bell),
             ControlCode::RI => self.c1_reverse_index(),
             _ => error!("unhandled ControlCode {:?}", control),
         }
@@ -3029,15 +3191,18 @@ impl<'a> Performer<'a> {
                 } else {
                     self.icon_title = Some(title.clone());
                 }
+                self.alert(Alert::TitleMaybeChanged);
             }
             OperatingSystemCommand::SetIconNameAndWindowTitle(title) => {
                 self.icon_title.take();
                 self.title = title.clone();
+                self.alert(Alert::TitleMaybeChanged);
             }
 
             OperatingSystemCommand::SetWindowTitleSun(title)
             | OperatingSystemCommand::SetWindowTitle(title) => {
                 self.title = title.clone();
+                self.alert(Alert::TitleMaybeChanged);
             }
             OperatingSystemCommand::SetHyperlink(link) => {
                 self.set_hyperlink(link);
@@ -3063,6 +3228,10 @@ impl<'a> Performer<'a> {
             }
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
                 ITermProprietary::File(image) => self.set_image(*image),
+                ITermProprietary::SetUserVar { name, value } => {
+                    self.user_vars.insert(name.clone(), value.clone());
+                    self.alert(Alert::SetUserVar { name, value });
+                }
                 _ => error!("unhandled iterm2: {:?}", iterm),
             },
 
@@ -3073,17 +3242,22 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::FreshLineAndStartPrompt { .. },
             ) => {
                 self.fresh_line();
+                self.record_prompt_start();
                 self.pen.set_semantic_type(SemanticType::Prompt);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::StartPrompt(_),
+                FinalTermSemanticPrompt::StartPrompt(kind),
             ) => {
+                if kind == FinalTermPromptKind::Initial {
+                    self.record_prompt_start();
+                }
                 self.pen.set_semantic_type(SemanticType::Prompt);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfCommandWithFreshLine { .. },
             ) => {
                 self.fresh_line();
+                self.record_prompt_start();
                 self.pen.set_semantic_type(SemanticType::Prompt);
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
@@ -3098,15 +3272,44 @@ impl<'a> Performer<'a> {
             }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::CommandStatus { .. },
-            ) => {}
+                FinalTermSemanticPrompt::CommandStatus { status, .. },
+            ) => {
+                self.record_command_status(status);
+            }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(ft) => {
                 error!("unhandled: {:?}", ft);
             }
 
-            OperatingSystemCommand::SystemNotification(message) => {
-                error!("Application sends SystemNotification: {}", message);
+            OperatingSystemCommand::SystemNotification(body) => {
+                self.alert(Alert::ToastNotification(ToastNotification {
+                    title: None,
+                    body,
+                    focus: true,
+                    report_id: None,
+                    only_when_unfocused: false,
+                }));
+            }
+            OperatingSystemCommand::RxvtExtension(params) => {
+                if params.first().map(String::as_str) == Some("notify") && params.len() > 1 {
+                    let (title, body) = if params.len() > 2 {
+                        (Some(params[1].clone()), params[2..].join(";"))
+                    } else {
+                        (None, params[1].clone())
+                    };
+                    self.alert(Alert::ToastNotification(ToastNotification {
+                        title,
+                        body,
+                        focus: true,
+                        report_id: None,
+                        only_when_unfocused: false,
+                    }));
+                } else {
+                    log::warn!("unhandled OSC 777: {:?}", params);
+                }
+            }
+            OperatingSystemCommand::KittyNotification(notif) => {
+                self.kitty_notification(notif);
             }
             OperatingSystemCommand::CurrentWorkingDirectory(url) => {
                 self.current_dir = Url::parse(&url).ok();
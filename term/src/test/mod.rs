@@ -50,12 +50,17 @@ struct TestTerm {
 #[derive(Debug)]
 struct TestTermConfig {
     scrollback: usize,
+    dedup: bool,
 }
 impl TerminalConfiguration for TestTermConfig {
     fn scrollback_size(&self) -> usize {
         self.scrollback
     }
 
+    fn scrollback_dedup(&self) -> bool {
+        self.dedup
+    }
+
     fn color_palette(&self) -> ColorPalette {
         ColorPalette::default()
     }
@@ -63,6 +68,17 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_config(
+            height,
+            width,
+            TestTermConfig {
+                scrollback,
+                dedup: false,
+            },
+        )
+    }
+
+    fn with_config(height: usize, width: usize, config: TestTermConfig) -> Self {
         let _ = pretty_env_logger::formatted_builder()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
@@ -75,7 +91,7 @@ impl TestTerm {
                 pixel_width: width * 8,
                 pixel_height: height * 16,
             },
-            Arc::new(TestTermConfig { scrollback }),
+            Arc::new(config),
             "WezTerm",
             "O_o",
             Box::new(Vec::new()),
@@ -466,6 +482,69 @@ fn test_semantic() {
     );
 }
 
+#[test]
+fn test_prompt_marks() {
+    use termwiz::escape::osc::FinalTermSemanticPrompt;
+    let mut term = TestTerm::new(5, 20, 0);
+
+    let prompt = |term: &mut TestTerm, command: &str| {
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+                    aid: None,
+                    cl: None
+                }
+            )
+        ));
+        term.print("$ ");
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker
+            )
+        ));
+        term.print(command);
+        term.print("\r\n");
+        term.print(format!(
+            "{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None }
+            )
+        ));
+    };
+
+    prompt(&mut term, "false");
+    term.print(format!(
+        "{}",
+        OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::CommandStatus {
+            status: 1,
+            aid: None
+        })
+    ));
+    prompt(&mut term, "echo hi");
+    term.print("hi\r\n");
+
+    assert_eq!(
+        term.get_prompt_marks(0..10),
+        vec![
+            PromptMark {
+                start_y: 0,
+                start_x: 0,
+                command: "false".to_string(),
+                status: Some(1),
+            },
+            PromptMark {
+                start_y: 1,
+                start_x: 0,
+                command: "echo hi".to_string(),
+                status: None,
+            },
+        ]
+    );
+    assert_eq!(term.get_prompt_marks(1..2).len(), 1);
+}
+
 #[test]
 fn basic_output() {
     let mut term = TestTerm::new(5, 10, 0);
@@ -768,6 +847,48 @@ fn test_scrollup() {
     assert_eq!(term.screen().visible_row_to_stable_row(0), 7);
 }
 
+#[test]
+fn test_line_timestamps() {
+    let mut term = TestTerm::new(3, 5, 0);
+    term.print("1\n");
+    term.print("2\n");
+    let lines = &term.screen().lines;
+    assert!(lines[0].timestamp().is_some());
+    assert!(lines[0].timestamp() <= lines[1].timestamp());
+    assert_eq!(lines[2].timestamp(), None);
+
+    // Scrolling a line out recycles it without its timestamp
+    term.print("3\n");
+    let lines = &term.screen().lines;
+    assert!(lines[1].timestamp().is_some());
+    assert_eq!(lines[2].timestamp(), None);
+}
+
+#[test]
+fn test_scrollback_dedup() {
+    let mut term = TestTerm::with_config(
+        3,
+        5,
+        TestTermConfig {
+            scrollback: 10,
+            dedup: true,
+        },
+    );
+    term.print("a\r\na\r\na\r\na\r\nb\r\n");
+    assert_all_contents(&term, file!(), line!(), &["a", "a    ", "b    ", "     "]);
+    assert_eq!(term.screen().lines[0].repeat_count(), 2);
+    assert_eq!(term.screen().lines[1].repeat_count(), 0);
+}
+
+#[test]
+fn test_scrollback_compaction() {
+    let mut term = TestTerm::new(2, 5, 10);
+    term.print("ab\r\ncd\r\n");
+    assert_all_contents(&term, file!(), line!(), &["ab", "cd   ", "     "]);
+    assert_eq!(term.screen().lines[0].cells().len(), 2);
+    assert_eq!(term.screen().lines[1].cells().len(), 5);
+}
+
 #[test]
 fn test_scroll_margins() {
     let mut term = TestTerm::new(3, 1, 10);
@@ -901,3 +1022,92 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+struct AlertCollector {
+    alerts: Arc<std::sync::Mutex<Vec<Alert>>>,
+}
+
+impl AlertHandler for AlertCollector {
+    fn alert(&mut self, alert: Alert) {
+        self.alerts.lock().unwrap().push(alert);
+    }
+}
+
+#[test]
+fn test_bell() {
+    let mut term = TestTerm::new(3, 10, 0);
+    let alerts = Arc::new(std::sync::Mutex::new(vec![]));
+    term.set_alert_handler(Box::new(AlertCollector {
+        alerts: Arc::clone(&alerts),
+    }));
+
+    let bells = || {
+        alerts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|alert| **alert == Alert::Bell)
+            .count()
+    };
+
+    // BEL terminating an OSC is not a bell
+    term.print("\x1b]0;title\x07");
+    assert_eq!(bells(), 0);
+
+    term.print("ding\x07");
+    assert_eq!(bells(), 1);
+}
+
+#[test]
+fn test_notifications() {
+    let mut term = TestTerm::new(3, 10, 0);
+    let alerts = Arc::new(std::sync::Mutex::new(vec![]));
+    term.set_alert_handler(Box::new(AlertCollector {
+        alerts: Arc::clone(&alerts),
+    }));
+
+    term.print("\x1b]9;hello\x1b\\");
+    term.print("\x1b]777;notify;Build;done; 3 warnings\x1b\\");
+    // A kitty notification in three chunks, where the body arrives
+    // before the rest of the title
+    term.print("\x1b]99;i=1:d=0;Hel\x1b\\");
+    term.print("\x1b]99;i=1:d=0:p=body;the body\x1b\\");
+    term.print("\x1b]99;i=1:a=report:o=unfocused;lo\x1b\\");
+    // An unfinished notification is abandoned when another starts
+    term.print("\x1b]99;i=2:d=0;lost\x1b\\");
+    term.print("\x1b]99;a=-focus;just a title\x1b\\");
+
+    assert_eq!(
+        *alerts.lock().unwrap(),
+        vec![
+            Alert::ToastNotification(ToastNotification {
+                title: None,
+                body: "hello".to_string(),
+                focus: true,
+                report_id: None,
+                only_when_unfocused: false,
+            }),
+            Alert::ToastNotification(ToastNotification {
+                title: Some("Build".to_string()),
+                body: "done; 3 warnings".to_string(),
+                focus: true,
+                report_id: None,
+                only_when_unfocused: false,
+            }),
+            Alert::ToastNotification(ToastNotification {
+                title: Some("Hello".to_string()),
+                body: "the body".to_string(),
+                focus: true,
+                report_id: Some("1".to_string()),
+                only_when_unfocused: true,
+            }),
+            Alert::ToastNotification(ToastNotification {
+                title: None,
+                body: "just a title".to_string(),
+                focus: false,
+                report_id: None,
+                only_when_unfocused: false,
+            }),
+        ]
+    );
+}
@@ -5,8 +5,11 @@ use crate::image::ImageCell;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use unicode_width::UnicodeWidthStr;
 
 /// Holds the attributes for a cell.
@@ -49,6 +52,17 @@ impl std::fmt::Debug for CellAttributes {
     }
 }
 
+/// Hashes only the fields that are stored inline; attributes that
+/// have `FatAttributes` are never interned, so this is consistent
+/// with the comparisons made by the interner.
+impl Hash for CellAttributes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.attributes.hash(state);
+        self.foreground.hash(state);
+        self.background.hash(state);
+    }
+}
+
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 struct FatAttributes {
@@ -471,6 +485,75 @@ impl std::cmp::PartialEq for TeenyString {
 }
 impl std::cmp::Eq for TeenyString {}
 
+/// Cells refer to their attributes through an `Arc` so that the many
+/// cells that have the same attributes share a single copy of them.
+/// This table is used to find that copy; it is pruned of attributes
+/// that are no longer used by any cell whenever it grows past `prune_at`.
+struct AttributeInterner {
+    table: HashSet<Arc<CellAttributes>>,
+    prune_at: usize,
+}
+
+const MIN_PRUNE_AT: usize = 1024;
+
+impl AttributeInterner {
+    fn intern(&mut self, attrs: CellAttributes) -> Arc<CellAttributes> {
+        if let Some(interned) = self.table.get(&attrs) {
+            return Arc::clone(interned);
+        }
+        if self.table.len() >= self.prune_at {
+            self.table
+                .retain(|interned| Arc::strong_count(interned) > 1);
+            self.prune_at = (self.table.len() * 2).max(MIN_PRUNE_AT);
+        }
+        let interned = Arc::new(attrs);
+        self.table.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref INTERNER: Mutex<AttributeInterner> = Mutex::new(AttributeInterner {
+        table: HashSet::new(),
+        prune_at: MIN_PRUNE_AT,
+    });
+    static ref DEFAULT_ATTRIBUTES: Arc<CellAttributes> = intern_attributes(CellAttributes::default());
+}
+
+thread_local! {
+    /// Consecutive cells usually have the same attributes, so we
+    /// remember the most recently interned attributes to avoid
+    /// consulting the table for each of them.
+    static LAST_INTERNED: RefCell<Option<Arc<CellAttributes>>> = const { RefCell::new(None) };
+}
+
+fn intern_attributes(attrs: CellAttributes) -> Arc<CellAttributes> {
+    // Hyperlinks and images are not worth sharing in this way
+    if attrs.fat.is_some() {
+        return Arc::new(attrs);
+    }
+    LAST_INTERNED.with(|last| {
+        let mut last = last.borrow_mut();
+        if let Some(interned) = last.as_ref() {
+            if **interned == attrs {
+                return Arc::clone(interned);
+            }
+        }
+        let interned = INTERNER.lock().unwrap().intern(attrs);
+        last.replace(Arc::clone(&interned));
+        interned
+    })
+}
+
+#[cfg(feature = "use_serde")]
+fn deserialize_attributes<'de, D>(deserializer: D) -> Result<Arc<CellAttributes>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let attrs = CellAttributes::deserialize(deserializer)?;
+    Ok(intern_attributes(attrs))
+}
+
 /// Models the contents of a cell on the terminal display
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Eq, PartialEq)]
@@ -483,7 +566,11 @@ pub struct Cell {
         )
     )]
     text: TeenyString,
-    attrs: CellAttributes,
+    #[cfg_attr(
+        feature = "use_serde",
+        serde(deserialize_with = "deserialize_attributes")
+    )]
+    attrs: Arc<CellAttributes>,
 }
 
 impl std::fmt::Debug for Cell {
@@ -497,7 +584,10 @@ impl std::fmt::Debug for Cell {
 
 impl Default for Cell {
     fn default() -> Self {
-        Cell::new(' ', CellAttributes::default())
+        Self {
+            text: TeenyString::from_char(' '),
+            attrs: Arc::clone(&DEFAULT_ATTRIBUTES),
+        }
     }
 }
 
@@ -509,7 +599,7 @@ impl Cell {
         let storage = TeenyString::from_char(text);
         Self {
             text: storage,
-            attrs,
+            attrs: intern_attributes(attrs),
         }
     }
 
@@ -525,7 +615,7 @@ impl Cell {
 
         Self {
             text: storage,
-            attrs,
+            attrs: intern_attributes(attrs),
         }
     }
 
@@ -546,8 +636,17 @@ impl Cell {
         &self.attrs
     }
 
+    /// Returns the attributes of the cell for modification.
+    /// If the attributes are shared with other cells then this cell
+    /// is given its own copy of them; prefer `set_attrs` when replacing
+    /// the attributes wholesale so that they can remain shared.
     pub fn attrs_mut(&mut self) -> &mut CellAttributes {
-        &mut self.attrs
+        Arc::make_mut(&mut self.attrs)
+    }
+
+    /// Replaces the attributes of the cell
+    pub fn set_attrs(&mut self, attrs: CellAttributes) {
+        self.attrs = intern_attributes(attrs);
     }
 }
 
@@ -627,12 +726,36 @@ mod test {
         assert_eq!(std::mem::size_of::<crate::color::RgbColor>(), 3);
         assert_eq!(std::mem::size_of::<ColorAttribute>(), 5);
         assert_eq!(std::mem::size_of::<CellAttributes>(), 24);
-        assert_eq!(std::mem::size_of::<Cell>(), 32);
+        assert_eq!(std::mem::size_of::<Cell>(), 16);
         assert_eq!(std::mem::size_of::<Vec<u8>>(), 24);
         assert_eq!(std::mem::size_of::<char>(), 4);
         assert_eq!(std::mem::size_of::<TeenyString>(), 8);
     }
 
+    #[test]
+    fn interned_attributes() {
+        let mut attrs = CellAttributes::default();
+        attrs.set_intensity(Intensity::Bold);
+        let a = Cell::new('a', attrs.clone());
+        let b = Cell::new_grapheme("b", attrs.clone());
+        assert!(Arc::ptr_eq(&a.attrs, &b.attrs));
+
+        // Modifying one cell doesn't affect the other
+        let mut c = b.clone();
+        c.attrs_mut().set_italic(true);
+        assert!(c.attrs().italic());
+        assert!(!b.attrs().italic());
+
+        // Replacing the attributes shares them again
+        c.set_attrs(attrs);
+        assert!(Arc::ptr_eq(&a.attrs, &c.attrs));
+
+        assert!(Arc::ptr_eq(
+            &Cell::default().attrs,
+            &Cell::new(' ', CellAttributes::default()).attrs
+        ));
+    }
+
     #[test]
     fn nerf_special() {
         for c in " \n\r\t".chars() {
@@ -37,6 +37,10 @@ pub enum OperatingSystemCommand {
     QuerySelection(Selection),
     SetSelection(Selection, String),
     SystemNotification(String),
+    /// OSC 777; only the `notify` command is understood by wezterm,
+    /// but the parameters are passed through for any of them.
+    RxvtExtension(Vec<String>),
+    KittyNotification(KittyNotification),
     ITermProprietary(ITermProprietary),
     FinalTermSemanticPrompt(FinalTermSemanticPrompt),
     ChangeColorNumber(Vec<ChangeColorPair>),
@@ -296,6 +300,16 @@ impl OperatingSystemCommand {
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
             SystemNotification => single_string!(SystemNotification),
+            RxvtProprietary => {
+                let mut params = vec![];
+                for param in &osc[1..] {
+                    params.push(String::from_utf8(param.to_vec())?);
+                }
+                Ok(OperatingSystemCommand::RxvtExtension(params))
+            }
+            KittyNotification => {
+                self::KittyNotification::parse(osc).map(OperatingSystemCommand::KittyNotification)
+            }
             SetCurrentWorkingDirectory => single_string!(CurrentWorkingDirectory),
             ITermProprietary => {
                 self::ITermProprietary::parse(osc).map(OperatingSystemCommand::ITermProprietary)
@@ -396,6 +410,8 @@ osc_entries!(
     SetHighlightBackgroundColor = "17",
     SetTektronixCursorColor = "18",
     SetHighlightForegroundColor = "19",
+    /// Desktop notifications; see <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+    KittyNotification = "99",
     SetLogFileName = "46",
     SetFont = "50",
     EmacsShell = "51",
@@ -485,6 +501,8 @@ impl Display for OperatingSystemCommand {
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
             SystemNotification(s) => write!(f, "9;{}", s)?,
+            RxvtExtension(params) => write!(f, "777;{}", params.join(";"))?,
+            KittyNotification(n) => n.fmt(f)?,
             ITermProprietary(i) => i.fmt(f)?,
             FinalTermSemanticPrompt(i) => i.fmt(f)?,
             ResetColors(colors) => {
@@ -1163,6 +1181,161 @@ impl Display for ITermProprietary {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyNotificationPayload {
+    Title,
+    Body,
+}
+
+/// When a kitty notification should be shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyNotificationOccasion {
+    Always,
+    /// Only when the window doesn't have focus
+    Unfocused,
+    /// Only when the window doesn't have focus and the pane
+    /// isn't visible
+    Invisible,
+}
+
+/// A chunk of a kitty desktop notification (OSC 99).
+/// A notification may be split across several chunks which share
+/// the same `id`; the notification is complete when a chunk with
+/// `done` set is received.
+/// <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KittyNotification {
+    pub id: Option<String>,
+    pub done: bool,
+    /// Which part of the notification the payload belongs to
+    pub payload_type: KittyNotificationPayload,
+    /// Activating the notification should focus the window
+    pub focus: bool,
+    /// Activating the notification should be reported back to
+    /// the application
+    pub report: bool,
+    pub occasion: KittyNotificationOccasion,
+    /// The decoded payload text
+    pub payload: String,
+}
+
+impl Default for KittyNotification {
+    fn default() -> Self {
+        Self {
+            id: None,
+            done: true,
+            payload_type: KittyNotificationPayload::Title,
+            focus: true,
+            report: false,
+            occasion: KittyNotificationOccasion::Always,
+            payload: String::new(),
+        }
+    }
+}
+
+impl KittyNotification {
+    fn parse(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() >= 2, "no metadata");
+        let mut notif = Self::default();
+        let mut base64_encoded = false;
+
+        let metadata = str::from_utf8(osc[1])?;
+        for item in metadata.split(':').filter(|item| !item.is_empty()) {
+            let mut iter = item.splitn(2, '=');
+            let key = iter.next().unwrap_or("");
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("invalid metadata {}", item))?;
+            match key {
+                "i" => notif.id = Some(value.to_string()),
+                "d" => notif.done = value != "0",
+                "e" => base64_encoded = value == "1",
+                "p" => {
+                    notif.payload_type = match value {
+                        "title" => KittyNotificationPayload::Title,
+                        "body" => KittyNotificationPayload::Body,
+                        _ => bail!("unsupported payload type {}", value),
+                    }
+                }
+                "a" => {
+                    for action in value.split(',') {
+                        let (enable, action) = if let Some(action) = action.strip_prefix('-') {
+                            (false, action)
+                        } else if let Some(action) = action.strip_prefix('+') {
+                            (true, action)
+                        } else {
+                            (true, action)
+                        };
+                        match action {
+                            "focus" => notif.focus = enable,
+                            "report" => notif.report = enable,
+                            _ => {}
+                        }
+                    }
+                }
+                "o" => {
+                    notif.occasion = match value {
+                        "always" => KittyNotificationOccasion::Always,
+                        "unfocused" => KittyNotificationOccasion::Unfocused,
+                        "invisible" => KittyNotificationOccasion::Invisible,
+                        _ => bail!("invalid occasion {}", value),
+                    }
+                }
+                // Unknown keys are ignored so that newer applications
+                // can still produce a notification
+                _ => {}
+            }
+        }
+
+        // The payload may itself contain `;`, which our caller has
+        // split into separate parameters
+        let payload = osc[2..].join(&b';');
+        notif.payload = if base64_encoded {
+            String::from_utf8(base64::decode(&payload)?)?
+        } else {
+            String::from_utf8(payload)?
+        };
+
+        Ok(notif)
+    }
+}
+
+impl Display for KittyNotification {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "99;")?;
+        let mut metadata = vec![];
+        if let Some(id) = &self.id {
+            metadata.push(format!("i={}", id));
+        }
+        if !self.done {
+            metadata.push("d=0".to_string());
+        }
+        if self.payload_type == KittyNotificationPayload::Body {
+            metadata.push("p=body".to_string());
+        }
+        match (self.focus, self.report) {
+            (true, false) => {}
+            (true, true) => metadata.push("a=focus,report".to_string()),
+            (false, true) => metadata.push("a=-focus,report".to_string()),
+            (false, false) => metadata.push("a=-focus".to_string()),
+        }
+        match self.occasion {
+            KittyNotificationOccasion::Always => {}
+            KittyNotificationOccasion::Unfocused => metadata.push("o=unfocused".to_string()),
+            KittyNotificationOccasion::Invisible => metadata.push("o=invisible".to_string()),
+        }
+        // Always encode the payload so that it can safely contain
+        // `;` and control characters
+        metadata.push("e=1".to_string());
+        write!(
+            f,
+            "{};{}",
+            metadata.join(":"),
+            base64::encode(&self.payload)
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1235,6 +1408,68 @@ mod test {
         );
     }
 
+    #[test]
+    fn notifications() {
+        assert_eq!(
+            parse(&["9", "hello"], "\x1b]9;hello\x1b\\"),
+            OperatingSystemCommand::SystemNotification("hello".into())
+        );
+
+        assert_eq!(
+            parse(
+                &["777", "notify", "Build", "done", " 3 warnings"],
+                "\x1b]777;notify;Build;done; 3 warnings\x1b\\"
+            ),
+            OperatingSystemCommand::RxvtExtension(vec![
+                "notify".into(),
+                "Build".into(),
+                "done".into(),
+                " 3 warnings".into()
+            ])
+        );
+
+        assert_eq!(
+            parse(&["99", "", "Hello"], "\x1b]99;e=1;SGVsbG8=\x1b\\"),
+            OperatingSystemCommand::KittyNotification(KittyNotification {
+                payload: "Hello".into(),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            parse(
+                &["99", "i=1:d=0:p=body:a=-focus,report:o=unfocused", "a;b"],
+                "\x1b]99;i=1:d=0:p=body:a=-focus,report:o=unfocused:e=1;YTti\x1b\\"
+            ),
+            OperatingSystemCommand::KittyNotification(KittyNotification {
+                id: Some("1".into()),
+                done: false,
+                payload_type: KittyNotificationPayload::Body,
+                focus: false,
+                report: true,
+                occasion: KittyNotificationOccasion::Unfocused,
+                payload: "a;b".into(),
+            })
+        );
+
+        assert_eq!(
+            parse(&["99", "e=1", "SGVsbG8="], "\x1b]99;e=1;SGVsbG8=\x1b\\"),
+            OperatingSystemCommand::KittyNotification(KittyNotification {
+                payload: "Hello".into(),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            parse(&["99", "o=sometimes", "x"], "\x1b]99;o=sometimes;x\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![
+                b"99".to_vec(),
+                b"o=sometimes".to_vec(),
+                b"x".to_vec()
+            ])
+        );
+    }
+
     #[test]
     fn hyperlink() {
         assert_eq!(
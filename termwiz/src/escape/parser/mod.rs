@@ -144,6 +144,14 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         (self.callback)(Action::Print(c));
     }
 
+    /// A run of text is emitted as a batch of `Print` actions straight
+    /// from the str, rather than being decoded again a char at a time
+    /// by the state machine.
+    fn print_str(&mut self, text: &str) {
+        let callback = &mut *self.callback;
+        text.chars().for_each(|c| callback(Action::Print(c)));
+    }
+
     fn execute_c0_or_c1(&mut self, byte: u8) {
         match FromPrimitive::from_u8(byte) {
             Some(code) => (self.callback)(Action::Control(code)),
@@ -421,7 +429,7 @@ mod test {
     use crate::cell::{Intensity, Underline};
     use crate::color::ColorSpec;
     use crate::escape::csi::Sgr;
-    use crate::escape::EscCode;
+    use crate::escape::{ControlCode, EscCode};
     use std::io::Write;
 
     fn encode(seq: &Vec<Action>) -> String {
@@ -432,6 +440,30 @@ mod test {
         String::from_utf8(res).unwrap()
     }
 
+    #[test]
+    fn bulk_print_matches_bytewise() {
+        let input = "plain ascii, été 日本 🦀\x1b[1mbold\x1b[0m\r\ntail\x07".as_bytes();
+
+        let mut state = ParseState::default();
+        let mut machine = VTParser::new();
+        let mut bytewise = vec![];
+        for b in input {
+            machine.parse_byte(
+                *b,
+                &mut Performer {
+                    callback: &mut |action| bytewise.push(action),
+                    state: &mut state,
+                },
+            );
+        }
+
+        let mut p = Parser::new();
+        let bulk = p.parse_as_vec(input);
+        assert_eq!(bulk, bytewise);
+        assert!(bulk.contains(&Action::Print('🦀')));
+        assert!(bulk.contains(&Action::Control(ControlCode::Bell)));
+    }
+
     #[test]
     fn basic_parse() {
         let mut p = Parser::new();
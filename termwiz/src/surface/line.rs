@@ -30,6 +30,12 @@ bitflags! {
 pub struct Line {
     bits: LineBits,
     cells: Vec<Cell>,
+    /// When the line was first written to, in milliseconds since
+    /// the unix epoch
+    timestamp: Option<u64>,
+    /// How many identical lines that followed this one were folded
+    /// into it when they scrolled into the scrollback
+    repeat_count: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -43,7 +49,12 @@ impl Line {
         let mut cells = Vec::with_capacity(width);
         cells.resize(width, Cell::default());
         let bits = LineBits::DIRTY;
-        Self { bits, cells }
+        Self {
+            bits,
+            cells,
+            timestamp: None,
+            repeat_count: 0,
+        }
     }
 
     pub fn from_text(s: &str, attrs: &CellAttributes) -> Line {
@@ -61,14 +72,14 @@ impl Line {
         Line {
             cells,
             bits: LineBits::DIRTY,
+            timestamp: None,
+            repeat_count: 0,
         }
     }
 
     pub fn from_text_with_wrapped_last_col(s: &str, attrs: &CellAttributes) -> Line {
         let mut line = Self::from_text(s, attrs);
-        line.cells
-            .last_mut()
-            .map(|cell| cell.attrs_mut().set_wrapped(true));
+        line.set_last_cell_was_wrapped(true);
         line
     }
 
@@ -77,6 +88,8 @@ impl Line {
         self.cells.clear();
         self.cells.resize(width, blank);
         self.bits = LineBits::DIRTY;
+        self.timestamp = None;
+        self.repeat_count = 0;
     }
 
     pub fn resize(&mut self, width: usize) {
@@ -84,11 +97,31 @@ impl Line {
         self.bits |= LineBits::DIRTY;
     }
 
+    /// Releases the storage held by blank cells at the end of the line.
+    /// This is intended for lines in the scrollback, which are numerous
+    /// and rarely modified; the cells are added back as needed if the
+    /// line is subsequently changed.
+    /// At least one cell is retained so that the line has a last cell.
+    pub fn compact(&mut self) {
+        let blank = Cell::default();
+        let len = self
+            .cells
+            .iter()
+            .rposition(|cell| *cell != blank)
+            .map(|idx| idx + 1)
+            .unwrap_or(0)
+            .max(1);
+        self.cells.truncate(len);
+        self.cells.shrink_to_fit();
+    }
+
     /// Wrap the line so that it fits within the provided width.
     /// Returns the list of resultant line(s)
     pub fn wrap(mut self, width: usize) -> Vec<Self> {
         if let Some(end_idx) = self.cells.iter().rposition(|c| c.str() != " ") {
             self.cells.resize(end_idx + 1, Cell::default());
+            let timestamp = self.timestamp;
+            let repeat_count = self.repeat_count;
 
             let mut lines: Vec<_> = self
                 .cells
@@ -97,6 +130,8 @@ impl Line {
                     let mut line = Line {
                         cells: chunk.to_vec(),
                         bits: LineBits::DIRTY,
+                        timestamp,
+                        repeat_count: 0,
                     };
                     if line.cells.len() == width {
                         // Ensure that we don't forget that we wrapped
@@ -105,16 +140,52 @@ impl Line {
                     line
                 })
                 .collect();
-            // The last of the chunks wasn't actually wrapped
-            lines
-                .last_mut()
-                .map(|line| line.set_last_cell_was_wrapped(false));
+            // The last of the chunks wasn't actually wrapped, and
+            // is the one that shows the repeat count
+            if let Some(line) = lines.last_mut() {
+                line.set_last_cell_was_wrapped(false);
+                line.repeat_count = repeat_count;
+            }
             lines
         } else {
             vec![self]
         }
     }
 
+    /// Returns the time at which the line was first written to,
+    /// in milliseconds since the unix epoch
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Option<u64>) {
+        self.timestamp = timestamp;
+    }
+
+    /// Returns how many identical lines that followed this one were
+    /// folded into it
+    pub fn repeat_count(&self) -> u32 {
+        self.repeat_count
+    }
+
+    pub fn set_repeat_count(&mut self, count: u32) {
+        self.repeat_count = count;
+        self.set_dirty();
+    }
+
+    /// Returns an estimate of the number of bytes used by this line.
+    /// Cell attributes are interned and shared between cells, so they
+    /// are not included.
+    pub fn approx_memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.cells.capacity() * std::mem::size_of::<Cell>()
+    }
+
+    /// Returns true if the cells of this line are the same as those
+    /// of `other`, regardless of when they were written
+    pub fn same_content(&self, other: &Line) -> bool {
+        self.cells == other.cells
+    }
+
     /// Check whether the dirty bit is set.
     /// If it is set, then something about the line has changed since
     /// the dirty bit was last cleared.
@@ -205,13 +276,12 @@ impl Line {
         for (byte_idx, _grapheme) in line.grapheme_indices(true) {
             let cell = &mut self.cells[cell_idx];
             for m in &matches {
-                if m.range.contains(&byte_idx) {
-                    let attrs = cell.attrs_mut();
-                    // Don't replace existing links
-                    if !attrs.hyperlink().is_some() {
-                        attrs.set_hyperlink(Some(Arc::clone(&m.link)));
-                        self.bits |= LineBits::HAS_IMPLICIT_HYPERLINKS;
-                    }
+                // Don't replace existing links
+                if m.range.contains(&byte_idx) && cell.attrs().hyperlink().is_none() {
+                    let mut attrs = cell.attrs().clone();
+                    attrs.set_hyperlink(Some(Arc::clone(&m.link)));
+                    cell.set_attrs(attrs);
+                    self.bits |= LineBits::HAS_IMPLICIT_HYPERLINKS;
                 }
             }
             cell_idx += cell.width();
@@ -444,7 +514,11 @@ impl Line {
     /// line.
     pub fn set_last_cell_was_wrapped(&mut self, wrapped: bool) {
         if let Some(cell) = self.cells.last_mut() {
-            cell.attrs_mut().set_wrapped(wrapped);
+            if cell.attrs().wrapped() != wrapped {
+                let mut attrs = cell.attrs().clone();
+                attrs.set_wrapped(wrapped);
+                cell.set_attrs(attrs);
+            }
         }
     }
 
@@ -454,6 +528,12 @@ impl Line {
     /// lines back together.
     pub fn append_line(&mut self, mut other: Line) {
         self.cells.append(&mut other.cells);
+        // Keep the time that the earliest of the pair was written
+        self.timestamp = match (self.timestamp, other.timestamp) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.repeat_count += other.repeat_count;
         self.set_dirty();
     }
 
@@ -545,6 +625,23 @@ mod test {
     use crate::hyperlink::*;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn implicit_hyperlinks_keep_explicit_links() {
+        let rules = vec![Rule::new(r"\b\w+://(?:[\w.-]+)\.[a-z]{2,15}\S*\b", "$0").unwrap()];
+
+        let explicit = Arc::new(Hyperlink::new("http://example.org"));
+        let explicit_attr = CellAttributes::default()
+            .set_hyperlink(Some(Arc::clone(&explicit)))
+            .clone();
+
+        let mut line = Line::from_text("http://example.com", &explicit_attr);
+        line.scan_and_create_hyperlinks(&rules);
+        assert!(line
+            .cells()
+            .iter()
+            .all(|cell| cell.attrs().hyperlink() == Some(&explicit)));
+    }
+
     #[test]
     fn hyperlinks() {
         let text =
@@ -627,4 +724,25 @@ mod test {
         let r = line.compute_double_click_range(200, |_| true);
         assert_eq!(r, DoubleClickRange::Range(0..200));
     }
+
+    #[test]
+    fn compact() {
+        let mut line = Line::from_text("hello", &CellAttributes::default());
+        line.resize(80);
+        line.compact();
+        assert_eq!(line.cells().len(), 5);
+        assert_eq!(line.as_str(), "hello");
+
+        // Blank cells with attributes of their own are retained
+        let mut reverse = CellAttributes::default();
+        reverse.set_reverse(true);
+        line.resize(80);
+        line.set_cell(10, Cell::new(' ', reverse));
+        line.compact();
+        assert_eq!(line.cells().len(), 11);
+
+        let mut blank = Line::with_width(80);
+        blank.compact();
+        assert_eq!(blank.cells().len(), 1);
+    }
 }
@@ -48,6 +48,16 @@ pub enum Event {
     Exit {
         reason: Option<String>,
     },
+    LayoutChange {
+        window: TmuxWindowId,
+        layout: WindowLayout,
+    },
+    PasteBufferChanged {
+        buffer: String,
+    },
+    PasteBufferDeleted {
+        buffer: String,
+    },
     SessionsChanged,
     SessionChanged {
         session: TmuxSessionId,
@@ -84,6 +94,134 @@ pub enum Event {
     },
 }
 
+/// The geometry of a tmux window, or of a cell within it, as
+/// described by the `#{window_layout}` format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowLayout {
+    pub width: u64,
+    pub height: u64,
+    pub x: u64,
+    pub y: u64,
+    pub kind: LayoutKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutKind {
+    Pane(TmuxPaneId),
+    /// The cells are arranged from left to right
+    LeftRight(Vec<WindowLayout>),
+    /// The cells are arranged from top to bottom
+    TopBottom(Vec<WindowLayout>),
+}
+
+impl WindowLayout {
+    /// Returns the ids of the panes in the layout, in the
+    /// order in which they appear
+    pub fn pane_ids(&self) -> Vec<TmuxPaneId> {
+        let mut ids = vec![];
+        self.collect_pane_ids(&mut ids);
+        ids
+    }
+
+    fn collect_pane_ids(&self, ids: &mut Vec<TmuxPaneId>) {
+        match &self.kind {
+            LayoutKind::Pane(id) => ids.push(*id),
+            LayoutKind::LeftRight(cells) | LayoutKind::TopBottom(cells) => {
+                for cell in cells {
+                    cell.collect_pane_ids(ids);
+                }
+            }
+        }
+    }
+}
+
+/// Parses a tmux window layout string such as
+/// `b25d,80x24,0,0{40x24,0,0,1,39x24,41,0,2}`
+pub fn parse_layout(layout: &str) -> anyhow::Result<WindowLayout> {
+    struct LayoutParser<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> LayoutParser<'a> {
+        fn peek(&self) -> Option<u8> {
+            self.bytes.get(self.pos).copied()
+        }
+
+        fn expect(&mut self, c: u8) -> anyhow::Result<()> {
+            if self.peek() == Some(c) {
+                self.pos += 1;
+                Ok(())
+            } else {
+                anyhow::bail!("expected `{}` at offset {} of layout", c as char, self.pos)
+            }
+        }
+
+        fn number(&mut self) -> anyhow::Result<u64> {
+            let start = self.pos;
+            while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+                self.pos += 1;
+            }
+            std::str::from_utf8(&self.bytes[start..self.pos])?
+                .parse()
+                .with_context(|| format!("expected a number at offset {} of layout", start))
+        }
+
+        fn cell(&mut self) -> anyhow::Result<WindowLayout> {
+            let width = self.number()?;
+            self.expect(b'x')?;
+            let height = self.number()?;
+            self.expect(b',')?;
+            let x = self.number()?;
+            self.expect(b',')?;
+            let y = self.number()?;
+
+            let kind = match self.peek() {
+                Some(b'{') => LayoutKind::LeftRight(self.cells(b'}')?),
+                Some(b'[') => LayoutKind::TopBottom(self.cells(b']')?),
+                _ => {
+                    self.expect(b',')?;
+                    LayoutKind::Pane(self.number()?)
+                }
+            };
+
+            Ok(WindowLayout {
+                width,
+                height,
+                x,
+                y,
+                kind,
+            })
+        }
+
+        fn cells(&mut self, close: u8) -> anyhow::Result<Vec<WindowLayout>> {
+            // Skip the opening bracket
+            self.pos += 1;
+            let mut cells = vec![self.cell()?];
+            while self.peek() == Some(b',') {
+                self.pos += 1;
+                cells.push(self.cell()?);
+            }
+            self.expect(close)?;
+            Ok(cells)
+        }
+    }
+
+    // The layout starts with a checksum that we don't need to verify
+    let comma = layout
+        .find(',')
+        .ok_or_else(|| anyhow::anyhow!("layout {} has no checksum", layout))?;
+    let mut parser = LayoutParser {
+        bytes: layout[comma + 1..].as_bytes(),
+        pos: 0,
+    };
+    let result = parser.cell()?;
+    if parser.pos != parser.bytes.len() {
+        anyhow::bail!("unexpected trailing data in layout {}", layout);
+    }
+    Ok(result)
+}
+
 fn parse_pane_id(pair: Pair<Rule>) -> anyhow::Result<TmuxPaneId> {
     match pair.as_rule() {
         Rule::pane_id => {
@@ -177,6 +315,22 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
             Ok(Event::Exit { reason })
         }
         Rule::sessions_changed => Ok(Event::SessionsChanged),
+        Rule::layout_change => {
+            let mut pairs = pair.into_inner();
+            let window = parse_window_id(pairs.next().unwrap())?;
+            let layout = parse_layout(pairs.next().unwrap().as_str())?;
+            Ok(Event::LayoutChange { window, layout })
+        }
+        Rule::paste_buffer_changed => {
+            let mut pairs = pair.into_inner();
+            let buffer = unvis(pairs.next().unwrap().as_str())?;
+            Ok(Event::PasteBufferChanged { buffer })
+        }
+        Rule::paste_buffer_deleted => {
+            let mut pairs = pair.into_inner();
+            let buffer = unvis(pairs.next().unwrap().as_str())?;
+            Ok(Event::PasteBufferDeleted { buffer })
+        }
         Rule::pane_mode_changed => {
             let mut pairs = pair.into_inner();
             let pane = parse_pane_id(pairs.next().unwrap())?;
@@ -241,6 +395,7 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
         Rule::pane_id
         | Rule::word
         | Rule::client_name
+        | Rule::layout
         | Rule::window_id
         | Rule::session_id
         | Rule::any_text
@@ -584,6 +739,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_layout() {
+        assert_eq!(
+            WindowLayout {
+                width: 80,
+                height: 24,
+                x: 0,
+                y: 0,
+                kind: LayoutKind::Pane(0),
+            },
+            parse_layout("b25d,80x24,0,0,0").unwrap()
+        );
+
+        let layout =
+            parse_layout("c9f3,160x48,0,0{80x48,0,0,1,79x48,81,0[79x24,81,0,2,79x23,81,25,3]}")
+                .unwrap();
+        assert_eq!(vec![1, 2, 3], layout.pane_ids());
+        assert_eq!(
+            WindowLayout {
+                width: 160,
+                height: 48,
+                x: 0,
+                y: 0,
+                kind: LayoutKind::LeftRight(vec![
+                    WindowLayout {
+                        width: 80,
+                        height: 48,
+                        x: 0,
+                        y: 0,
+                        kind: LayoutKind::Pane(1),
+                    },
+                    WindowLayout {
+                        width: 79,
+                        height: 48,
+                        x: 81,
+                        y: 0,
+                        kind: LayoutKind::TopBottom(vec![
+                            WindowLayout {
+                                width: 79,
+                                height: 24,
+                                x: 81,
+                                y: 0,
+                                kind: LayoutKind::Pane(2),
+                            },
+                            WindowLayout {
+                                width: 79,
+                                height: 23,
+                                x: 81,
+                                y: 25,
+                                kind: LayoutKind::Pane(3),
+                            },
+                        ]),
+                    },
+                ]),
+            },
+            layout
+        );
+
+        assert!(parse_layout("b25d,80x24,0,0{40x24,0,0,1").is_err());
+        assert!(parse_layout("80x24,0,0,0").is_err());
+    }
+
     #[test]
     fn test_parse_sequence() {
         let input = b"%sessions-changed
@@ -596,6 +813,8 @@ here
 %window-add @1
 %sessions-changed
 %session-changed $1 1
+%layout-change @1 b25d,80x24,0,0,1 b25d,80x24,0,0,1 *
+%paste-buffer-changed buffer0
 %output %1 \\033[1m\\033[7m%\\033[27m\\033[1m\\033[0m    \\015 \\015
 %output %1 \\033kwez@cube-localdomain:~\\033\\134\\033]2;wez@cube-localdomain:~\\033\\134
 %output %1 \\033]7;file://cube-localdomain/home/wez\\033\\134
@@ -623,6 +842,19 @@ here
                     session: 1,
                     name: "1".to_owned(),
                 },
+                Event::LayoutChange {
+                    window: 1,
+                    layout: WindowLayout {
+                        width: 80,
+                        height: 24,
+                        x: 0,
+                        y: 0,
+                        kind: LayoutKind::Pane(1),
+                    },
+                },
+                Event::PasteBufferChanged {
+                    buffer: "buffer0".to_owned(),
+                },
                 Event::Output {
                     pane: 1,
                     text: "\x1b[1m\x1b[7m%\x1b[27m\x1b[1m\x1b[0m    \r \r".to_owned()
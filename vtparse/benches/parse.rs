@@ -0,0 +1,107 @@
+//! Compares `VTParser::parse`, which picks out runs of text in bulk,
+//! with feeding the same input through `VTParser::parse_byte`.
+//!
+//! Run with `cargo bench -p vtparse`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use vtparse::{VTActor, VTParser};
+
+/// Counts the events so that the work can't be optimized away,
+/// without the cost of recording them.
+#[derive(Default)]
+struct CountingActor {
+    chars: usize,
+    other: usize,
+}
+
+impl VTActor for CountingActor {
+    fn print(&mut self, c: char) {
+        black_box(c);
+        self.chars += 1;
+    }
+
+    fn print_str(&mut self, text: &str) {
+        black_box(text);
+        self.chars += text.len();
+    }
+
+    fn execute_c0_or_c1(&mut self, _control: u8) {
+        self.other += 1;
+    }
+
+    fn dcs_hook(&mut self, _: u8, _: &[i64], _: &[u8], _: bool) {
+        self.other += 1;
+    }
+
+    fn dcs_put(&mut self, _byte: u8) {
+        self.other += 1;
+    }
+
+    fn dcs_unhook(&mut self) {
+        self.other += 1;
+    }
+
+    fn esc_dispatch(&mut self, _: &[i64], _: &[u8], _: bool, _: u8) {
+        self.other += 1;
+    }
+
+    fn csi_dispatch(&mut self, _: &[vtparse::CsiParam], _: &[u8], _: bool, _: u8) {
+        self.other += 1;
+    }
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]]) {
+        self.other += 1;
+    }
+}
+
+/// Repeats `line` until it produces roughly 1MiB of input
+fn corpus(line: &str) -> Vec<u8> {
+    line.repeat((1024 * 1024) / line.len()).into_bytes()
+}
+
+fn inputs() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        (
+            "ascii",
+            corpus("The quick brown fox jumps over the lazy dog; 0123456789 times over and over again.\r\n"),
+        ),
+        (
+            "utf8",
+            corpus("Příliš žluťoučký kůň úpěl ďábelské ódy — 日本語のテキスト ☃ 🦀\r\n"),
+        ),
+        (
+            "sgr",
+            corpus("\x1b[1;32mok\x1b[0m  \x1b[38;2;255;128;0msrc/main.rs\x1b[0m:\x1b[33m42\x1b[0m: warning\r\n"),
+        ),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("bulk", name), &input, |b, input| {
+            b.iter(|| {
+                let mut parser = VTParser::new();
+                let mut actor = CountingActor::default();
+                parser.parse(input, &mut actor);
+                actor.chars + actor.other
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("bytewise", name), &input, |b, input| {
+            b.iter(|| {
+                let mut parser = VTParser::new();
+                let mut actor = CountingActor::default();
+                for byte in input {
+                    parser.parse_byte(*byte, &mut actor);
+                }
+                actor.chars + actor.other
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
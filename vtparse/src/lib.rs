@@ -13,6 +13,7 @@
 use utf8parse::Parser as Utf8Parser;
 mod enums;
 use crate::enums::*;
+mod scan;
 mod transitions;
 
 use transitions::{ENTRY, EXIT, TRANSITIONS};
@@ -91,6 +92,19 @@ pub trait VTActor {
     /// character.
     fn print(&mut self, b: char);
 
+    /// Invoked for a run of printable text in the ground state; the
+    /// effect must be the same as calling `print` for each of the
+    /// chars in `text`, which is what the default implementation does.
+    ///
+    /// The text is valid UTF-8 and contains no C0 or C1 controls.
+    /// Implementations that can process a run of text more efficiently
+    /// than one char at a time can override this method.
+    fn print_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.print(c);
+        }
+    }
+
     /// The C0 or C1 control function should be executed, which may have any one of a variety of
     /// effects, including changing the cursor position, suspending or resuming communications or
     /// changing the shift states in effect.
@@ -671,9 +685,21 @@ impl VTParser {
     /// Parse a sequence of bytes.  The sequence need not be complete.
     /// This may result in some number of calls to the methods on the
     /// provided `actor`.
-    pub fn parse(&mut self, bytes: &[u8], actor: &mut dyn VTActor) {
-        for b in bytes {
-            self.parse_byte(*b, actor);
+    pub fn parse(&mut self, mut bytes: &[u8], actor: &mut dyn VTActor) {
+        while let Some(&byte) = bytes.first() {
+            // Runs of text in the ground state don't change the state,
+            // so we can pick them out in bulk rather than looking up
+            // each byte in the state table
+            if self.state == State::Ground {
+                let text = scan::printable_text(bytes);
+                if !text.is_empty() {
+                    actor.print_str(text);
+                    bytes = &bytes[text.len()..];
+                    continue;
+                }
+            }
+            self.parse_byte(byte, actor);
+            bytes = &bytes[1..];
         }
     }
 }
@@ -940,6 +966,34 @@ mod test {
         );
     }
 
+    #[test]
+    fn bulk_text_matches_bytewise() {
+        let input = "plain\r\n\x1b[1mbold\x1b[0m caf\u{e9} \u{2603}\u{9b}2J\x1b]0;t\u{ef}tle\x07\
+             \x7fdel \u{1f980}"
+            .as_bytes()
+            .iter()
+            .chain(b"bad\xe9\xff utf8 \x9b1m")
+            .copied()
+            .collect::<Vec<u8>>();
+
+        let mut parser = VTParser::new();
+        let mut actor = CollectingVTActor::default();
+        for b in &input {
+            parser.parse_byte(*b, &mut actor);
+        }
+        let expected = actor.into_vec();
+
+        // Split the input at every position, so that runs of text and
+        // UTF-8 sequences are interrupted at the end of the buffer
+        for split in 0..=input.len() {
+            let mut parser = VTParser::new();
+            let mut actor = CollectingVTActor::default();
+            parser.parse(&input[..split], &mut actor);
+            parser.parse(&input[split..], &mut actor);
+            assert_eq!(actor.into_vec(), expected, "split at {}", split);
+        }
+    }
+
     #[test]
     fn tmux_control() {
         assert_eq!(
@@ -0,0 +1,148 @@
+//! Fast paths for recognizing runs of printable text in the ground
+//! state, so that the bulk of the output of eg: `cat` doesn't need to
+//! be fed through the state machine one byte at a time.
+
+/// How much non-ASCII text to validate in one go.  Keeping this small
+/// bounds the amount of work that we repeat when the input contains
+/// invalid UTF-8 that must be fed through the state machine.
+const UTF8_WINDOW: usize = 64;
+
+/// Returns the longest prefix of `bytes` that the ground state would
+/// print character by character: valid UTF-8 that contains no C0 or C1
+/// control characters.
+pub fn printable_text(bytes: &[u8]) -> &str {
+    let mut len = 0;
+    loop {
+        len += ascii_text_len(&bytes[len..]);
+        match bytes.get(len) {
+            Some(&b) if b >= 0x80 => {}
+            _ => break,
+        }
+
+        let window = &bytes[len..bytes.len().min(len + UTF8_WINDOW)];
+        let mut valid = match std::str::from_utf8(window) {
+            Ok(_) => window.len(),
+            Err(err) => err.valid_up_to(),
+        };
+        if let Some(idx) = window[..valid].iter().position(|&b| b < 0x20) {
+            valid = idx;
+        }
+        // C1 controls that were encoded as UTF-8 (U+0080..=U+009F) may
+        // begin an escape sequence, so leave those to the state machine
+        if let Some(idx) = window[..valid]
+            .windows(2)
+            .position(|pair| pair[0] == 0xc2 && pair[1] < 0xa0)
+        {
+            valid = idx;
+        }
+        if valid == 0 {
+            break;
+        }
+        len += valid;
+    }
+
+    // Safety: bytes[0..len] is made up of printable ASCII and of text
+    // that was validated by std::str::from_utf8 above, and we only ever
+    // stop at a character boundary.
+    unsafe { std::str::from_utf8_unchecked(&bytes[..len]) }
+}
+
+/// Returns the length of the leading run of printable ASCII in `bytes`;
+/// that is, the offset of the first byte that is either a C0 control
+/// or that has its high bit set.
+#[cfg(target_arch = "x86_64")]
+fn ascii_text_len(bytes: &[u8]) -> usize {
+    use std::arch::x86_64::*;
+    const LANES: usize = 16;
+
+    let mut offset = 0;
+    // SSE2 is part of the x86_64 baseline, so there is no need to
+    // detect it at runtime.
+    unsafe {
+        let space = _mm_set1_epi8(0x20);
+        while offset + LANES <= bytes.len() {
+            let chunk = _mm_loadu_si128(bytes.as_ptr().add(offset) as *const __m128i);
+            // The comparison is signed, so bytes with their high bit set
+            // compare as negative and are matched along with C0 controls
+            let mask = _mm_movemask_epi8(_mm_cmplt_epi8(chunk, space));
+            if mask != 0 {
+                return offset + mask.trailing_zeros() as usize;
+            }
+            offset += LANES;
+        }
+    }
+    offset + ascii_text_len_scalar(&bytes[offset..])
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn ascii_text_len(bytes: &[u8]) -> usize {
+    ascii_text_len_words(bytes)
+}
+
+/// A portable version of `ascii_text_len` that examines a word at a time
+#[cfg_attr(all(target_arch = "x86_64", not(test)), allow(dead_code))]
+fn ascii_text_len_words(bytes: &[u8]) -> usize {
+    const ONES: u64 = 0x0101_0101_0101_0101;
+    const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+    let mut offset = 0;
+    for chunk in bytes.chunks_exact(8) {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        let word = u64::from_le_bytes(word);
+        // The subtraction sets the high bit of any byte that is less
+        // than 0x20; or-ing in the word itself catches those bytes
+        // that already had their high bit set
+        if (word.wrapping_sub(ONES * 0x20) | word) & HIGH_BITS != 0 {
+            break;
+        }
+        offset += 8;
+    }
+    offset + ascii_text_len_scalar(&bytes[offset..])
+}
+
+fn ascii_text_len_scalar(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .position(|&b| !(0x20..0x80).contains(&b))
+        .unwrap_or(bytes.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ascii_run_length() {
+        for len in 0..80 {
+            for stop in &[0x00u8, 0x07, 0x1b, 0x1f, 0x80, 0x9b, 0xc3, 0xff] {
+                let mut bytes = vec![b'x'; len];
+                bytes.push(0x7f);
+                bytes.push(*stop);
+                bytes.extend_from_slice(b"more text");
+                assert_eq!(ascii_text_len(&bytes), len + 1);
+                assert_eq!(ascii_text_len_words(&bytes), len + 1);
+            }
+            let bytes = vec![b' '; len];
+            assert_eq!(ascii_text_len(&bytes), len);
+            assert_eq!(ascii_text_len_words(&bytes), len);
+        }
+    }
+
+    #[test]
+    fn printable() {
+        assert_eq!(printable_text(b"hello\r\nthere"), "hello");
+        assert_eq!(printable_text(b"hello\x1b[1m"), "hello");
+        assert_eq!(printable_text("café ☃ 🦀!\n".as_bytes()), "café ☃ 🦀!");
+        // Invalid UTF-8 and a truncated sequence
+        assert_eq!(printable_text(b"caf\xe9"), "caf");
+        assert_eq!(printable_text(b"\xe2\x98"), "");
+        // Raw and UTF-8 encoded C1 controls
+        assert_eq!(printable_text(b"ab\x9b1m"), "ab");
+        assert_eq!(printable_text("é\u{9b}1m".as_bytes()), "é");
+        assert_eq!(printable_text("é\u{a0}".as_bytes()), "é\u{a0}");
+
+        let long = "ü".repeat(100) + "\x07";
+        assert_eq!(printable_text(long.as_bytes()), &long[..200]);
+    }
+}
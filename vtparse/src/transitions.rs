@@ -293,7 +293,7 @@ pub(crate) static TRANSITIONS: [[u8; 256]; 14] = [
     define_table!(sos_pm_apc_string),
 ];
 
-pub(crate) static ENTRY: [Action; 14] = [
+pub(crate) static ENTRY: [Action; 16] = [
     Action::None,     // Ground
     Action::Clear,    // Escape
     Action::None,     // EscapeIntermediate
@@ -308,9 +308,11 @@ pub(crate) static ENTRY: [Action; 14] = [
     Action::None,     // DcsIgnore
     Action::OscStart, // OscString
     Action::None,     // SosPmApcString
+    Action::None,     // Anywhere
+    Action::None,     // Utf8Sequence
 ];
 
-pub(crate) static EXIT: [Action; 14] = [
+pub(crate) static EXIT: [Action; 16] = [
     Action::None,   // Ground
     Action::None,   // Escape
     Action::None,   // EscapeIntermediate
@@ -325,4 +327,6 @@ pub(crate) static EXIT: [Action; 14] = [
     Action::None,   // DcsIgnore
     Action::OscEnd, // OscString
     Action::None,   // SosPmApcString
+    Action::None,   // Anywhere
+    Action::None,   // Utf8Sequence
 ];
@@ -0,0 +1,206 @@
+//! Installs wezterm on a remote host over ssh, so that ssh domains
+//! can be used for multiplexing without manually installing a matching
+//! version of wezterm there first.
+use anyhow::{anyhow, bail, Context};
+use config::{wezterm_version, SshDomain};
+use mux::connui::ConnectionUI;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The binaries that are needed on the remote host; `wezterm cli proxy`
+/// spawns the `wezterm-mux-server` that lives alongside it.
+const BINARIES: &[&str] = &["wezterm", "wezterm-mux-server"];
+
+/// Runs `cmd` on the remote host, returning its exit status and stdout
+fn remote_output(sess: &ssh2::Session, cmd: &str) -> anyhow::Result<(i32, String)> {
+    let mut chan = sess.channel_session()?;
+    chan.exec(cmd)?;
+    let mut output = String::new();
+    chan.read_to_string(&mut output)?;
+    chan.wait_close()?;
+    Ok((chan.exit_status()?, output))
+}
+
+/// Returns the version reported by the wezterm binary at `path` on
+/// the remote host, or None if it couldn't be run
+fn remote_version(sess: &ssh2::Session, path: &str) -> Option<String> {
+    match remote_output(sess, &format!("{} --version", path)) {
+        Ok((0, output)) => output.split_whitespace().last().map(str::to_string),
+        Ok((status, _)) => {
+            log::trace!("{} --version exited with status {}", path, status);
+            None
+        }
+        Err(err) => {
+            log::trace!("running {} --version: {:#}", path, err);
+            None
+        }
+    }
+}
+
+/// Normalizes the output of `uname -sm`, or our own os and architecture,
+/// so that the two can be compared
+fn normalize_platform(os: &str, arch: &str) -> String {
+    let os = match os.to_lowercase().as_str() {
+        "darwin" | "macos" => "macos".to_string(),
+        os => os.to_string(),
+    };
+    let arch = match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        arch => arch,
+    };
+    format!("{} {}", os, arch)
+}
+
+/// The directory on the remote host, relative to the home directory,
+/// into which we install the binaries for this version of wezterm
+fn remote_install_dir() -> String {
+    format!(".local/share/wezterm/bin/{}", wezterm_version())
+}
+
+/// Locates the local directory holding the binaries to upload
+fn local_binaries_dir(
+    sess: &ssh2::Session,
+    ssh_dom: &SshDomain,
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(dir) = ssh_dom.upload_mux_server_from.as_ref() {
+        return Ok(Some(PathBuf::from(dir)));
+    }
+
+    let (_, uname) = remote_output(sess, "uname -sm")?;
+    let mut words = uname.split_whitespace();
+    let remote = normalize_platform(words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let local = normalize_platform(std::env::consts::OS, std::env::consts::ARCH);
+    if remote != local {
+        ui.output_str(&format!(
+            "The remote host is {} but this is {}; set upload_mux_server_from \
+             to a directory containing wezterm and wezterm-mux-server built \
+             for the remote host to install them automatically.\n",
+            remote, local
+        ));
+        return Ok(None);
+    }
+
+    let exe = std::env::current_exe()?;
+    Ok(exe.parent().map(Path::to_path_buf))
+}
+
+fn upload_file(sftp: &ssh2::Sftp, local: &Path, remote: &Path) -> anyhow::Result<()> {
+    let data = std::fs::read(local).with_context(|| format!("reading {}", local.display()))?;
+
+    // Upload to a temporary name and rename it into place, so that an
+    // interrupted upload doesn't leave a truncated binary behind
+    let temp = remote.with_extension("tmp");
+    let mut file = sftp
+        .open_mode(
+            &temp,
+            ssh2::OpenFlags::WRITE | ssh2::OpenFlags::TRUNCATE,
+            0o755,
+            ssh2::OpenType::File,
+        )
+        .with_context(|| format!("creating {}", temp.display()))?;
+    file.write_all(&data)
+        .with_context(|| format!("writing {}", temp.display()))?;
+    drop(file);
+
+    sftp.rename(&temp, remote, Some(ssh2::RenameFlags::OVERWRITE))
+        .with_context(|| format!("renaming {} to {}", temp.display(), remote.display()))?;
+    Ok(())
+}
+
+fn upload_binaries(
+    sess: &ssh2::Session,
+    local_dir: &Path,
+    remote_dir: &str,
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<()> {
+    let sftp = sess.sftp().context("starting sftp")?;
+
+    let mut dir = PathBuf::new();
+    for component in Path::new(remote_dir).components() {
+        dir.push(component);
+        if sftp.stat(&dir).is_err() {
+            sftp.mkdir(&dir, 0o755)
+                .with_context(|| format!("creating remote directory {}", dir.display()))?;
+        }
+    }
+
+    for name in BINARIES {
+        let local = local_dir.join(name);
+        if !local.exists() {
+            bail!("{} does not exist", local.display());
+        }
+        ui.output_str(&format!("Uploading {}\n", local.display()));
+        upload_file(&sftp, &local, &dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Ensures that a version of wezterm matching our own is available on
+/// the remote host, uploading it over sftp if needed and permitted by
+/// the `upload_mux_server` option.
+/// Returns the remote path to the wezterm binary that should be used,
+/// or None to use the configured path as-is.
+pub fn bootstrap_remote(
+    sess: &ssh2::Session,
+    ssh_dom: &SshDomain,
+    proxy_bin: &str,
+    ui: &mut ConnectionUI,
+) -> anyhow::Result<Option<String>> {
+    let ours = wezterm_version();
+    match remote_version(sess, proxy_bin) {
+        Some(version) if version == ours => return Ok(None),
+        Some(version) => ui.output_str(&format!(
+            "The remote {} is version {}, but this is version {}\n",
+            proxy_bin, version, ours
+        )),
+        None => ui.output_str(&format!("{} was not found on the remote host\n", proxy_bin)),
+    }
+
+    let remote_dir = remote_install_dir();
+    let installed = format!("{}/wezterm", remote_dir);
+    if remote_version(sess, &installed).as_deref() == Some(ours) {
+        ui.output_str(&format!("Using previously installed {}\n", installed));
+        return Ok(Some(installed));
+    }
+
+    if ssh_dom.upload_mux_server == Some(false) {
+        return Ok(None);
+    }
+
+    let local_dir = match local_binaries_dir(sess, ssh_dom, ui)? {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+
+    if ssh_dom.upload_mux_server.is_none() {
+        loop {
+            let answer = ui.input(&format!(
+                "Upload wezterm {} to ~/{} on the remote host? [y/n]> ",
+                ours, remote_dir
+            ))?;
+            match answer.trim() {
+                "y" | "Y" | "yes" => break,
+                "n" | "N" | "no" => return Ok(None),
+                _ => continue,
+            }
+        }
+    }
+
+    upload_binaries(sess, &local_dir, &remote_dir, ui)?;
+
+    match remote_version(sess, &installed) {
+        Some(version) if version == ours => Ok(Some(installed)),
+        Some(version) => Err(anyhow!(
+            "uploaded {} reports version {} rather than {}",
+            installed,
+            version,
+            ours
+        )),
+        None => Err(anyhow!(
+            "uploaded {} could not be run on the remote host",
+            installed
+        )),
+    }
+}
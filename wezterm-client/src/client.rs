@@ -1,3 +1,4 @@
+use crate::bootstrap::bootstrap_remote;
 use crate::domain::{ClientDomain, ClientDomainConfig};
 use crate::pane::ClientPane;
 use crate::UnixStream;
@@ -6,11 +7,14 @@ use async_ossl::AsyncSslStream;
 use async_trait::async_trait;
 use codec::*;
 use config::{configuration, SshDomain, TlsDomainClient, UnixDomain};
+use filedescriptor::{AsRawSocketDescriptor, FileDescriptor, SocketDescriptor};
 use futures::FutureExt;
 use mux::connui::ConnectionUI;
 use mux::domain::{alloc_domain_id, DomainId};
 use mux::pane::PaneId;
-use mux::ssh::ssh_connect_with_ui;
+use mux::sftp::SftpRegistration;
+use mux::ssh::{ssh_connect_via_with_ui, ssh_connect_with_ui};
+use mux::sshforward::{retry_eagain, PortForwarder};
 use mux::Mux;
 use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
 use openssl::x509::X509;
@@ -24,8 +28,10 @@ use std::marker::Unpin;
 use std::net::TcpStream;
 use std::path::Path;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::Poll;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 
 enum ReaderMessage {
@@ -34,13 +40,20 @@ enum ReaderMessage {
         promise: Sender<anyhow::Result<Pdu>>,
     },
     Readable,
+    Keepalive,
 }
 
 #[derive(Clone)]
 pub struct Client {
     sender: Sender<ReaderMessage>,
     local_domain_id: DomainId,
+    /// The name of the domain from the configuration; used to
+    /// label the rpc latency metrics
+    domain_name: String,
     pub is_reconnectable: bool,
+    /// Receives the events that the server reports once we have
+    /// subscribed to them
+    mux_events: Receiver<MuxEvent>,
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -65,7 +78,12 @@ macro_rules! rpc {
             let start = std::time::Instant::now();
             let result = self.send_pdu(Pdu::$request_type(pdu)).await;
             let elapsed = start.elapsed();
-            metrics::histogram!("rpc", elapsed, "method" => stringify!($method_name));
+            metrics::histogram!(
+                "rpc",
+                elapsed,
+                "method" => stringify!($method_name),
+                "domain" => self.domain_name.clone()
+            );
             match result {
                 Ok(Pdu::$response_type(res)) => Ok(res),
                 Ok(_) => bail!("unexpected response {:?}", result),
@@ -83,7 +101,12 @@ macro_rules! rpc {
             let start = std::time::Instant::now();
             let result = self.send_pdu(Pdu::$request_type($request_type{})).await;
             let elapsed = start.elapsed();
-            metrics::histogram!("rpc", elapsed, "method" => stringify!($method_name));
+            metrics::histogram!(
+                "rpc",
+                elapsed,
+                "method" => stringify!($method_name),
+                "domain" => self.domain_name.clone()
+            );
             match result {
                 Ok(Pdu::$response_type(res)) => Ok(res),
                 Ok(_) => bail!("unexpected response {:?}", result),
@@ -174,18 +197,116 @@ enum NotReconnectableError {
     ClientWasDestroyed,
 }
 
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("no response to keepalive for {0:?}")]
+struct KeepaliveTimeout(Duration);
+
+/// Tracks the keepalive pings that we send to detect a connection
+/// that has silently gone away, as can happen when the network changes
+/// or when the system resumes from sleep.
+struct Keepalive {
+    interval: Duration,
+    next_tick: Instant,
+    /// The serial and send time of the ping awaiting a response
+    pending: Option<(u64, Instant)>,
+    last_tick: (Instant, SystemTime),
+    suspended: bool,
+}
+
+impl Keepalive {
+    /// The number of intervals that a ping may go unanswered
+    const MAX_MISSED: u32 = 3;
+    /// How soon to check the connection after resuming from sleep
+    const RESUME_CHECK: Duration = Duration::from_secs(2);
+
+    fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            next_tick: now + interval,
+            pending: None,
+            last_tick: (now, SystemTime::now()),
+            suspended: false,
+        }
+    }
+
+    /// Called when the timer fires.  Returns true if a new ping should
+    /// be sent, or an error if the connection should be considered dead.
+    fn tick(&mut self) -> anyhow::Result<bool> {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+
+        // The monotonic clock doesn't advance while the system is
+        // suspended, but the wall clock does
+        let mono_elapsed = now - self.last_tick.0;
+        let wall_elapsed = wall.duration_since(self.last_tick.1).unwrap_or_default();
+        self.last_tick = (now, wall);
+        if wall_elapsed > mono_elapsed + self.interval {
+            log::warn!(
+                "system appears to have been suspended for {:?}; checking connection",
+                wall_elapsed - mono_elapsed
+            );
+            self.suspended = true;
+            // Any ping sent before the suspend is meaningless now
+            self.pending.take();
+            self.next_tick = now + Self::RESUME_CHECK.min(self.interval);
+            return Ok(true);
+        }
+
+        self.next_tick = now + self.interval;
+        match self.pending {
+            Some((_, sent)) => {
+                let limit = if self.suspended {
+                    self.interval
+                } else {
+                    self.interval * Self::MAX_MISSED
+                };
+                let waited = now - sent;
+                if waited >= limit {
+                    return Err(KeepaliveTimeout(waited).into());
+                }
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    fn sent(&mut self, serial: u64) {
+        self.pending.replace((serial, Instant::now()));
+    }
+
+    /// Returns true if `serial` is the response to our ping
+    fn received(&mut self, serial: u64) -> bool {
+        match self.pending {
+            Some((pending, _)) if pending == serial => {
+                self.pending.take();
+                self.suspended = false;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 fn client_thread(
     reconnectable: &mut Reconnectable,
     local_domain_id: DomainId,
     rx: &mut Receiver<ReaderMessage>,
+    mux_events: &Sender<MuxEvent>,
 ) -> anyhow::Result<()> {
-    block_on(client_thread_async(reconnectable, local_domain_id, rx))
+    block_on(client_thread_async(
+        reconnectable,
+        local_domain_id,
+        rx,
+        mux_events,
+    ))
 }
 
 async fn client_thread_async(
     reconnectable: &mut Reconnectable,
     local_domain_id: DomainId,
     rx: &mut Receiver<ReaderMessage>,
+    mux_events: &Sender<MuxEvent>,
 ) -> anyhow::Result<()> {
     let mut next_serial = 1u64;
 
@@ -212,14 +333,25 @@ async fn client_thread_async(
     };
 
     let mut stream = reconnectable.take_stream().unwrap();
+    let mut keepalive = reconnectable.keepalive_interval().map(Keepalive::new);
 
     loop {
         let rx_msg = rx.recv();
         let wait_for_read = stream
             .wait_for_readable()
             .map(|_| Ok(ReaderMessage::Readable));
+        let next_tick = keepalive.as_ref().map(|k| k.next_tick);
+        let wait_for_tick = async move {
+            match next_tick {
+                Some(when) => {
+                    smol::Timer::at(when).await;
+                }
+                None => futures::future::pending::<()>().await,
+            }
+            Ok(ReaderMessage::Keepalive)
+        };
 
-        match smol::future::or(rx_msg, wait_for_read).await {
+        match smol::future::or(smol::future::or(rx_msg, wait_for_read), wait_for_tick).await {
             Ok(ReaderMessage::SendPdu { pdu, promise }) => {
                 let serial = next_serial;
                 next_serial += 1;
@@ -230,10 +362,43 @@ async fn client_thread_async(
                     .context("encoding a PDU to send to the server")?;
                 stream.flush().await.context("flushing PDU to server")?;
             }
+            Ok(ReaderMessage::Keepalive) => {
+                let keepalive = keepalive.as_mut().unwrap();
+                match keepalive.tick() {
+                    Ok(true) => {
+                        let serial = next_serial;
+                        next_serial += 1;
+                        Pdu::Ping(Ping {})
+                            .encode_async(&mut stream, serial)
+                            .await
+                            .context("sending keepalive to server")?;
+                        stream
+                            .flush()
+                            .await
+                            .context("flushing keepalive to server")?;
+                        keepalive.sent(serial);
+                    }
+                    Ok(false) => {}
+                    Err(err) => {
+                        let reason = format!("{:#}", err);
+                        log::error!("{}", reason);
+                        promises.fail_all(&reason);
+                        return Err(err);
+                    }
+                }
+            }
             Ok(ReaderMessage::Readable) => match Pdu::decode_async(&mut stream).await {
                 Ok(decoded) => {
                     log::trace!("decoded serial {}", decoded.serial);
-                    if decoded.serial == 0 {
+                    if keepalive
+                        .as_mut()
+                        .map(|k| k.received(decoded.serial))
+                        .unwrap_or(false)
+                    {
+                        log::trace!("keepalive acknowledged");
+                    } else if let Pdu::MuxEventPdu(MuxEventPdu { event }) = decoded.pdu {
+                        mux_events.try_send(event).ok();
+                    } else if decoded.serial == 0 {
                         process_unilateral(local_domain_id, decoded)
                             .context("processing unilateral PDU from server")
                             .map_err(|e| {
@@ -309,11 +474,37 @@ struct Reconnectable {
     config: ClientDomainConfig,
     stream: Option<Box<dyn AsyncReadAndWrite>>,
     tls_creds: Option<GetTlsCredsResponse>,
+    ssh_session: Option<SshSessionState>,
+}
+
+/// The ssh session that backs an ssh domain.  It is retained across
+/// reconnects so that, if only the proxy channel was lost, a new
+/// channel can be opened without re-authenticating and without
+/// disturbing any port forwards.
+struct SshSessionState {
+    sess: ssh2::Session,
+    /// When port forwards are active, the forwarding thread owns
+    /// polling the session socket and signals us via this pipe
+    /// when there may be data for our channel.
+    wakeup: Option<FileDescriptor>,
+    _forwarder: Option<PortForwarder>,
+    /// The path to the wezterm binary that was installed on the
+    /// remote host by `bootstrap_remote`, if any
+    remote_wezterm_path: Option<String>,
+    /// Allows `wezterm cli scp` to use this session
+    _sftp: SftpRegistration,
+}
+
+impl std::fmt::Debug for SshSessionState {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(fmt, "SshSessionState {{...}}")
+    }
 }
 
 struct SshStream {
     chan: ssh2::Channel,
     sess: ssh2::Session,
+    wakeup: Option<FileDescriptor>,
 }
 
 impl std::fmt::Debug for SshStream {
@@ -336,7 +527,141 @@ impl std::os::windows::io::AsRawSocket for SshStream {
     }
 }
 
+/// A socket that is owned by something else, so that it can be
+/// registered with the reactor separately from its owner
+#[derive(Debug)]
+struct SocketRef(SocketDescriptor);
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for SocketRef {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.0
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsRawSocket for SocketRef {
+    fn as_raw_socket(&self) -> std::os::windows::io::RawSocket {
+        self.0 as std::os::windows::io::RawSocket
+    }
+}
+
+/// The stream for an ssh session whose socket is polled by the port
+/// forwarding thread.  That thread signals the wakeup socket when
+/// there may be data for our channel, so reads wait for that; but the
+/// wakeup socket is always writable, so writes that would block wait
+/// for the session socket instead, unless libssh2 is waiting for data
+/// from the server, such as a window adjustment.
+#[derive(Debug)]
+struct ForwardedSshStream {
+    // These are declared first so that they are deregistered
+    // before `stream` closes the wakeup socket
+    wakeup: Async<SocketRef>,
+    session: Async<SocketRef>,
+    stream: SshStream,
+}
+
+impl ForwardedSshStream {
+    fn new(stream: SshStream) -> anyhow::Result<Self> {
+        let wakeup = stream
+            .wakeup
+            .as_ref()
+            .ok_or_else(|| anyhow!("forwarded ssh stream has no wakeup socket"))?
+            .as_socket_descriptor();
+        Ok(Self {
+            wakeup: Async::new(SocketRef(wakeup))?,
+            session: Async::new(SocketRef(stream.sess.as_socket_descriptor()))?,
+            stream,
+        })
+    }
+
+    /// Waits until an operation that would have blocked may succeed
+    fn poll_unblocked(&self, cx: &mut std::task::Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.stream.sess.block_directions() {
+            ssh2::BlockDirections::Outbound | ssh2::BlockDirections::Both => {
+                self.session.poll_writable(cx)
+            }
+            ssh2::BlockDirections::Inbound | ssh2::BlockDirections::None => {
+                self.wakeup.poll_readable(cx)
+            }
+        }
+    }
+}
+
+impl AsyncRead for ForwardedSshStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.read(buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                result => return Poll::Ready(result),
+            }
+            futures::ready!(this.poll_unblocked(cx))?;
+        }
+    }
+}
+
+impl AsyncWrite for ForwardedSshStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.write(buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                result => return Poll::Ready(result),
+            }
+            futures::ready!(this.poll_unblocked(cx))?;
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.flush() {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                result => return Poll::Ready(result),
+            }
+            futures::ready!(this.poll_unblocked(cx))?;
+        }
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[async_trait(?Send)]
+impl AsyncReadAndWrite for ForwardedSshStream {
+    async fn wait_for_readable(&self) -> anyhow::Result<()> {
+        Ok(self.wakeup.readable().await?)
+    }
+}
+
 impl SshStream {
+    fn drain_wakeup(&mut self) {
+        if let Some(wakeup) = self.wakeup.as_mut() {
+            let mut buf = [0u8; 64];
+            while let Ok(n) = wakeup.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
     fn process_stderr(&mut self) {
         let blocking = self.sess.is_blocking();
         self.sess.set_blocking(false);
@@ -367,6 +692,7 @@ impl SshStream {
 
 impl Read for SshStream {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        self.drain_wakeup();
         // Take the opportunity to read and show data from stderr
         self.process_stderr();
         self.chan.read(buf)
@@ -388,6 +714,7 @@ impl Reconnectable {
             config,
             stream,
             tls_creds: None,
+            ssh_session: None,
         }
     }
 
@@ -416,15 +743,30 @@ impl Reconnectable {
             // the set of tabs and we'd have confusing and inconsistent state
             ClientDomainConfig::Unix(_) => false,
             ClientDomainConfig::Tls(_) => true,
-            // It *does* make sense to reconnect with an ssh session, but we
-            // need to grow some smarts about whether the disconnect was because
-            // we sent CTRL-D to close the last session, or whether it was a network
-            // level disconnect, because we will otherwise throw up authentication
-            // dialogs that would be annoying
-            ClientDomainConfig::Ssh(_) => false,
+            // Closing the last pane causes the server to close the connection,
+            // which we see as EOF and don't reconnect for.  Otherwise we try
+            // to open a new proxy channel in the existing ssh session, and only
+            // re-authenticate if that session is no longer usable.
+            ClientDomainConfig::Ssh(_) => true,
+        }
+    }
+
+    /// How often to check that the connection is still alive, if at all
+    fn keepalive_interval(&self) -> Option<Duration> {
+        match &self.config {
+            ClientDomainConfig::Ssh(ssh) if ssh.keepalive_interval_seconds > 0 => {
+                Some(Duration::from_secs(ssh.keepalive_interval_seconds))
+            }
+            _ => None,
         }
     }
 
+    /// Discards the underlying ssh session so that the next connect
+    /// establishes a new one, rather than trying to reuse it
+    fn discard_ssh_session(&mut self) {
+        self.ssh_session.take();
+    }
+
     fn connect(&mut self, initial: bool, ui: &mut ConnectionUI) -> anyhow::Result<()> {
         match self.config.clone() {
             ClientDomainConfig::Unix(unix_dom) => self.unix_connect(unix_dom, initial, ui),
@@ -455,12 +797,87 @@ impl Reconnectable {
         initial: bool,
         ui: &mut ConnectionUI,
     ) -> anyhow::Result<()> {
-        let sess = ssh_connect_with_ui(&ssh_dom.remote_address, &ssh_dom.username, ui)?;
-        sess.set_timeout(ssh_dom.timeout.as_secs().try_into()?);
+        if let Some(state) = self.ssh_session.as_ref() {
+            match Self::ssh_open_proxy(state, &ssh_dom, initial, ui) {
+                Ok(stream) => {
+                    ui.output_str("Reusing existing ssh session\n");
+                    self.stream.replace(stream);
+                    return Ok(());
+                }
+                Err(err) => {
+                    log::error!(
+                        "ssh session for {} is no longer usable: {:#}",
+                        ssh_dom.name,
+                        err
+                    );
+                    self.ssh_session.take();
+                }
+            }
+        }
 
-        let mut chan = sess.channel_session()?;
+        let sess = ssh_connect_via_with_ui(
+            ssh_dom.proxy_jump.as_ref().map(String::as_str),
+            ssh_dom.proxy.as_ref().map(String::as_str),
+            &ssh_dom.remote_address,
+            &ssh_dom.username,
+            ui,
+        )?;
+        sess.set_timeout(ssh_dom.timeout.as_secs().try_into()?);
 
+        // This must happen before any port forwards are started, as they
+        // switch the session to non-blocking mode
         let proxy_bin = Self::wezterm_bin_path(&ssh_dom.remote_wezterm_path);
+        let remote_wezterm_path = match bootstrap_remote(&sess, &ssh_dom, proxy_bin, ui) {
+            Ok(path) => path,
+            Err(err) => {
+                ui.output_str(&format!(
+                    "Unable to install wezterm on the remote host: {:#}\n",
+                    err
+                ));
+                None
+            }
+        };
+
+        let (wakeup, forwarder) = if PortForwarder::is_needed(&ssh_dom) {
+            let (mut wakeup, mut notify) = filedescriptor::socketpair()?;
+            wakeup.set_non_blocking(true)?;
+            notify.set_non_blocking(true)?;
+            let forwarder = PortForwarder::start(&ssh_dom, sess.clone(), move || {
+                notify.write(b"x").ok();
+            })?;
+            (Some(wakeup), Some(forwarder))
+        } else {
+            (None, None)
+        };
+
+        let sftp = SftpRegistration::new(&ssh_dom.name, sess.clone());
+        let state = SshSessionState {
+            sess,
+            wakeup,
+            _forwarder: forwarder,
+            remote_wezterm_path,
+            _sftp: sftp,
+        };
+        let stream = Self::ssh_open_proxy(&state, &ssh_dom, initial, ui)?;
+        self.ssh_session.replace(state);
+        self.stream.replace(stream);
+        Ok(())
+    }
+
+    /// Opens a channel in the ssh session and runs the mux proxy in it
+    fn ssh_open_proxy(
+        state: &SshSessionState,
+        ssh_dom: &SshDomain,
+        initial: bool,
+        ui: &mut ConnectionUI,
+    ) -> anyhow::Result<Box<dyn AsyncReadAndWrite>> {
+        // The session is non-blocking when port forwards are active
+        let mut chan = retry_eagain(|| state.sess.channel_session())?;
+
+        let proxy_bin = match state.remote_wezterm_path.as_ref() {
+            Some(path) => path.as_str(),
+            None => Self::wezterm_bin_path(&ssh_dom.remote_wezterm_path),
+        };
 
         let cmd = if initial {
             format!("{} cli proxy", proxy_bin)
@@ -469,11 +886,20 @@ impl Reconnectable {
         };
         ui.output_str(&format!("Running: {}\n", cmd));
         log::error!("going to run {}", cmd);
-        chan.exec(&cmd)?;
-
-        let stream: Box<dyn AsyncReadAndWrite> = Box::new(Async::new(SshStream { sess, chan })?);
-        self.stream.replace(stream);
-        Ok(())
+        retry_eagain(|| chan.exec(&cmd))?;
+
+        match state.wakeup.as_ref() {
+            Some(wakeup) => Ok(Box::new(ForwardedSshStream::new(SshStream {
+                sess: state.sess.clone(),
+                chan,
+                wakeup: Some(wakeup.try_clone()?),
+            })?)),
+            None => Ok(Box::new(Async::new(SshStream {
+                sess: state.sess.clone(),
+                chan,
+                wakeup: None,
+            })?)),
+        }
     }
 
     fn unix_connect(
@@ -755,7 +1181,9 @@ impl Reconnectable {
 impl Client {
     fn new(local_domain_id: DomainId, mut reconnectable: Reconnectable) -> Self {
         let is_reconnectable = reconnectable.reconnectable();
+        let domain_name = reconnectable.config.name().to_string();
         let (sender, mut receiver) = unbounded();
+        let (mux_events_tx, mux_events) = unbounded();
 
         thread::spawn(move || {
             const BASE_INTERVAL: Duration = Duration::from_secs(1);
@@ -763,7 +1191,12 @@ impl Client {
 
             let mut backoff = BASE_INTERVAL;
             loop {
-                if let Err(e) = client_thread(&mut reconnectable, local_domain_id, &mut receiver) {
+                if let Err(e) = client_thread(
+                    &mut reconnectable,
+                    local_domain_id,
+                    &mut receiver,
+                    &mux_events_tx,
+                ) {
                     if !reconnectable.reconnectable() {
                         log::debug!("client thread ended: {}", e);
                         break;
@@ -782,6 +1215,30 @@ impl Client {
                         break;
                     }
 
+                    if e.root_cause().downcast_ref::<KeepaliveTimeout>().is_some() {
+                        // The ssh session is most likely dead too, and trying
+                        // to reuse it would block until it times out
+                        reconnectable.discard_ssh_session();
+                    }
+
+                    // Try to quietly resume the session first; this succeeds
+                    // without any interaction when the network has returned
+                    // and authentication doesn't require input.
+                    let mut quiet_ui = ConnectionUI::new_headless();
+                    match reconnectable.connect(false, &mut quiet_ui) {
+                        Ok(_) => {
+                            log::error!("Reconnected after {:#}", e);
+                            promise::spawn::spawn_into_main_thread(async move {
+                                ClientDomain::reattach(local_domain_id, quiet_ui).await.ok();
+                            })
+                            .detach();
+                            continue;
+                        }
+                        Err(err) => {
+                            log::error!("unable to quietly reconnect: {:#}", err);
+                        }
+                    }
+
                     let mut ui = ConnectionUI::new();
                     ui.title("wezterm: Reconnecting...");
 
@@ -840,7 +1297,9 @@ impl Client {
         Self {
             sender,
             local_domain_id,
+            domain_name,
             is_reconnectable,
+            mux_events,
         }
     }
 
@@ -947,8 +1406,21 @@ impl Client {
         rx.recv().await?
     }
 
+    /// Asks the server to report the events that happen in its mux,
+    /// and returns the channel on which they are received
+    pub async fn subscribe_to_mux_events(&self) -> anyhow::Result<Receiver<MuxEvent>> {
+        self.request_mux_events().await?;
+        Ok(self.mux_events.clone())
+    }
+
     rpc!(ping, Ping = (), Pong);
     rpc!(list_panes, ListPanes = (), ListPanesResponse);
+    rpc!(list_clients, GetClientList = (), GetClientListResponse);
+    rpc!(sftp_stat, SftpStat, SftpStatResponse);
+    rpc!(sftp_read, SftpRead, SftpReadResponse);
+    rpc!(sftp_write, SftpWrite, UnitResponse);
+    rpc!(set_log_filter, SetLogFilter, UnitResponse);
+    rpc!(get_stats, GetStats = (), GetStatsResponse);
     rpc!(spawn, Spawn, SpawnResponse);
     rpc!(split_pane, SplitPane, SpawnResponse);
     rpc!(write_to_pane, WriteToPane, UnitResponse);
@@ -957,6 +1429,14 @@ impl Client {
     rpc!(mouse_event, SendMouseEvent, UnitResponse);
     rpc!(resize, Resize, UnitResponse);
     rpc!(set_zoomed, SetPaneZoomed, UnitResponse);
+    rpc!(set_viewed_tabs, SetViewedTabs, UnitResponse);
+    rpc!(restore_session, RestoreSession, RestoreSessionResponse);
+    rpc!(request_mux_events, SubscribeToMuxEvents = (), UnitResponse);
+    rpc!(capture_pane, CapturePane, CapturePaneResponse);
+    rpc!(wait_pane, WaitPane, WaitPaneResponse);
+    rpc!(choose_pane, ChoosePane, ChoosePaneResponse);
+    rpc!(swap_panes, SwapPanes, UnitResponse);
+    rpc!(move_pane, MovePane, UnitResponse);
     rpc!(
         get_tab_render_changes,
         GetPaneRenderChanges,
@@ -2,7 +2,7 @@ use crate::client::Client;
 use crate::pane::ClientPane;
 use anyhow::{anyhow, bail};
 use async_trait::async_trait;
-use codec::{ListPanesResponse, Spawn, SplitPane};
+use codec::{ListPanesResponse, SetViewedTabs, Spawn, SplitPane};
 use config::keyassignment::SpawnTabDomain;
 use config::{SshDomain, TlsDomainClient, UnixDomain};
 use mux::connui::ConnectionUI;
@@ -25,6 +25,8 @@ pub struct ClientInner {
     remote_to_local_window: Mutex<HashMap<WindowId, WindowId>>,
     remote_to_local_tab: Mutex<HashMap<TabId, TabId>>,
     remote_to_local_pane: Mutex<HashMap<PaneId, PaneId>>,
+    /// The tabs that we last told the server we were displaying
+    viewed_tabs: Mutex<Vec<(TabId, PtySize)>>,
 }
 
 impl ClientInner {
@@ -106,6 +108,47 @@ impl ClientInner {
         }
         None
     }
+
+    fn local_to_remote_tab_id(&self, local_tab_id: TabId) -> Option<TabId> {
+        let map = self.remote_to_local_tab.lock().unwrap();
+        for (remote, local) in map.iter() {
+            if *local == local_tab_id {
+                return Some(*remote);
+            }
+        }
+        None
+    }
+
+    /// Tells the server which of its tabs are active in our windows,
+    /// and their sizes, if that has changed since we last told it
+    pub fn update_viewed_tabs(self: &Arc<Self>) {
+        let mux = Mux::get().unwrap();
+        let mut tabs = vec![];
+        for window_id in mux.iter_windows() {
+            if let Some(window) = mux.get_window(window_id) {
+                if let Some(tab) = window.get_active() {
+                    if let Some(remote_tab_id) = self.local_to_remote_tab_id(tab.tab_id()) {
+                        tabs.push((remote_tab_id, tab.get_size()));
+                    }
+                }
+            }
+        }
+        tabs.sort_by_key(|(tab_id, _)| *tab_id);
+
+        {
+            let mut viewed = self.viewed_tabs.lock().unwrap();
+            if *viewed == tabs {
+                return;
+            }
+            *viewed = tabs.clone();
+        }
+
+        let inner = Arc::clone(self);
+        promise::spawn::spawn(
+            async move { inner.client.set_viewed_tabs(SetViewedTabs { tabs }).await },
+        )
+        .detach();
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -156,6 +199,7 @@ impl ClientInner {
             remote_domain_id,
             remote_to_local_window: Mutex::new(HashMap::new()),
             remote_to_local_tab: Mutex::new(HashMap::new()),
+            viewed_tabs: Mutex::new(vec![]),
             remote_to_local_pane: Mutex::new(HashMap::new()),
         }
     }
@@ -3,6 +3,7 @@ use std::os::unix::net::UnixStream;
 #[cfg(windows)]
 use uds_windows::UnixStream;
 
+mod bootstrap;
 pub mod client;
 pub mod domain;
 pub mod pane;
@@ -139,6 +139,9 @@ impl Pane for ClientPane {
     }
 
     fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
+        // We are asked for lines when we are being displayed, which
+        // may be because our tab was just activated
+        self.client.update_viewed_tabs();
         self.renderable.borrow().get_dirty_lines(lines)
     }
 
@@ -241,16 +244,24 @@ impl Pane for ClientPane {
             .detach();
             inner.update_last_send();
         }
+        drop(inner);
+        drop(render);
+        self.client.update_viewed_tabs();
         Ok(())
     }
 
-    async fn search(&self, pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+    async fn search(
+        &self,
+        pattern: Pattern,
+        range: Range<StableRowIndex>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
         match self
             .client
             .client
             .search_scrollback(SearchScrollbackRequest {
                 pane_id: self.remote_pane_id,
                 pattern,
+                range,
             })
             .await
         {
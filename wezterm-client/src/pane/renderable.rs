@@ -4,6 +4,9 @@ use anyhow::anyhow;
 use codec::*;
 use config::{configuration, ConfigHandle};
 use lru::LruCache;
+use mux::localecho::{
+    is_predictable_key, latency_warrants_prediction, predict_key, unconfirmed_attributes,
+};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
 use mux::Mux;
@@ -15,7 +18,7 @@ use std::ops::Range;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use termwiz::cell::{Cell, CellAttributes, Underline};
+use termwiz::cell::CellAttributes;
 use termwiz::color::AnsiColor;
 use url::Url;
 use wezterm_term::{KeyCode, KeyModifiers};
@@ -66,6 +69,7 @@ pub struct RenderableInner {
     pub dimensions: RenderableDimensions,
 
     lines: LruCache<StableRowIndex, LineEntry>,
+    images: ReceivedImages,
     pub title: String,
     pub working_dir: Option<Url>,
 
@@ -105,6 +109,7 @@ impl RenderableInner {
             cursor_position: StableCursorPosition::default(),
             dimensions,
             lines: LruCache::new(configuration().scrollback_lines),
+            images: ReceivedImages::default(),
             title: title.to_string(),
             working_dir: None,
             fetch_limiter,
@@ -131,71 +136,8 @@ impl RenderableInner {
 
     /// Predictive echo can be noisy when the link is working well,
     /// so we only employ it when it looks like the latency is high.
-    /// We pick 100ms as the threshold for this.
     fn should_predict(&self) -> bool {
-        self.last_input_rtt >= 100
-    }
-
-    /// Compute a "prediction" and apply it to the line data that we
-    /// have available, marking it as dirty so that it gets rendered.
-    /// The prediction is basically just local echo.
-    /// Open questions:
-    /// how do we tell if the intent is to suppress local echo during eg:
-    ///  * password prompt?  One option is to look back and see if the line
-    ///                      looks like a password prompt.
-    ///  * normal mode in vim: letter presses are typically movement or
-    ///                        other editor commands
-    /// There are bound to be a number of other edge cases that we should
-    /// handle.
-    fn apply_prediction(&mut self, c: KeyCode, line: &mut Line) {
-        let text = line.as_str();
-        if text.contains("sword") {
-            // This line might be a password prompt.  Don't force
-            // on local echo here, as we don't want to reveal content
-            // from their password
-            return;
-        }
-
-        match c {
-            KeyCode::Enter => {
-                self.cursor_position.x = 0;
-                self.cursor_position.y += 1;
-            }
-            KeyCode::UpArrow => {
-                self.cursor_position.y = self.cursor_position.y.saturating_sub(1);
-            }
-            KeyCode::DownArrow => {
-                self.cursor_position.y += 1;
-            }
-            KeyCode::RightArrow => {
-                self.cursor_position.x += 1;
-            }
-            KeyCode::LeftArrow => {
-                self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
-            }
-            KeyCode::Delete => {
-                line.erase_cell(self.cursor_position.x);
-            }
-            KeyCode::Backspace => {
-                if self.cursor_position.x > 0 {
-                    line.erase_cell(self.cursor_position.x - 1);
-                    self.cursor_position.x -= 1;
-                }
-            }
-            KeyCode::Char(c) => {
-                let cell = Cell::new(
-                    c,
-                    CellAttributes::default()
-                        .set_underline(Underline::Double)
-                        .clone(),
-                );
-
-                let cell = line.set_cell(self.cursor_position.x, cell);
-                // Adjust the cursor to reflect the width of this new cell
-                self.cursor_position.x += cell.width();
-            }
-            _ => {}
-        }
+        latency_warrants_prediction(Duration::from_millis(self.last_input_rtt))
     }
 
     /// Based on a keypress, apply a "prediction" of what the terminal
@@ -207,18 +149,7 @@ impl RenderableInner {
             return;
         }
 
-        let c = match key {
-            KeyCode::LeftArrow
-            | KeyCode::RightArrow
-            | KeyCode::UpArrow
-            | KeyCode::DownArrow
-            | KeyCode::Delete
-            | KeyCode::Backspace
-            | KeyCode::Enter
-            | KeyCode::Char(_) => key,
-            _ => return,
-        };
-        if mods != KeyModifiers::NONE && mods != KeyModifiers::SHIFT {
+        if !is_predictable_key(key, mods) {
             return;
         }
 
@@ -227,11 +158,11 @@ impl RenderableInner {
             Some(LineEntry::Stale(mut line))
             | Some(LineEntry::Line(mut line))
             | Some(LineEntry::Dirty(mut line)) => {
-                self.apply_prediction(c, &mut line);
+                predict_key(key, &mut self.cursor_position, &mut line);
                 self.lines.put(row, LineEntry::Dirty(line));
             }
             Some(LineEntry::DirtyAndFetching(mut line, instant)) => {
-                self.apply_prediction(c, &mut line);
+                predict_key(key, &mut self.cursor_position, &mut line);
                 self.lines
                     .put(row, LineEntry::DirtyAndFetching(line, instant));
             }
@@ -243,11 +174,7 @@ impl RenderableInner {
     }
 
     fn apply_paste_prediction(&mut self, row: usize, text: &str, line: &mut Line) {
-        let attrs = CellAttributes::default()
-            .set_underline(Underline::Double)
-            .clone();
-
-        let text_line = Line::from_text(text, &attrs);
+        let text_line = Line::from_text(text, &unconfirmed_attributes());
 
         if row == 0 {
             for cell in text_line.cells() {
@@ -342,15 +269,14 @@ impl RenderableInner {
         self.working_dir = delta.working_dir.map(Into::into);
 
         let config = configuration();
-        for (stable_row, line) in delta.bonus_lines.lines() {
+        for (stable_row, line) in delta.bonus_lines.lines_with_images(&mut self.images) {
             self.put_line(stable_row, line, &config, None);
             dirty.remove(stable_row);
         }
+        self.make_incomplete_rows_stale();
 
         if !dirty.is_empty() {
-            Mux::get()
-                .unwrap()
-                .notify(mux::MuxNotification::PaneOutput(self.local_pane_id));
+            Mux::get().unwrap().notify_pane_output(self.local_pane_id);
         }
 
         let mut to_fetch = RangeSet::new();
@@ -473,6 +399,14 @@ impl RenderableInner {
         self.lines.put(stable_row, entry);
     }
 
+    /// Lines that referenced images that we no longer have are
+    /// fetched again, along with the image data
+    fn make_incomplete_rows_stale(&mut self) {
+        for stable_row in self.images.take_incomplete_rows() {
+            self.make_stale(stable_row);
+        }
+    }
+
     fn schedule_fetch_lines(&mut self, to_fetch: RangeSet<StableRowIndex>, now: Instant) {
         if to_fetch.is_empty() {
             return;
@@ -488,6 +422,7 @@ impl RenderableInner {
 
         let client = Arc::clone(&self.client);
         let remote_pane_id = self.remote_pane_id;
+        let forget_images = self.images.take_missing();
 
         promise::spawn::spawn(async move {
             let result = client
@@ -495,6 +430,7 @@ impl RenderableInner {
                 .get_lines(GetLines {
                     pane_id: remote_pane_id,
                     lines: to_fetch.clone().into(),
+                    forget_images,
                 })
                 .await;
             Self::apply_lines(local_pane_id, result, to_fetch, now)
@@ -519,12 +455,13 @@ impl RenderableInner {
             match result {
                 Ok(result) => {
                     let config = configuration();
-                    let lines = result.lines.lines();
+                    let lines = result.lines.lines_with_images(&mut inner.images);
 
                     log::trace!("fetch complete for {:?} at {:?}", to_fetch, now);
                     for (stable_row, line) in lines.into_iter() {
                         inner.put_line(stable_row, line, &config, Some(now));
                     }
+                    inner.make_incomplete_rows_stale();
                 }
                 Err(err) => {
                     log::error!("get_lines failed: {}", err);
@@ -4,12 +4,174 @@ use crate::parser::{font_info_matches, load_built_in_fonts, parse_and_collect_fo
 use crate::FontDataHandle;
 use anyhow::{anyhow, Context};
 use config::{Config, FontAttributes};
+use lazy_static::lazy_static;
 use rangeset::RangeSet;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Instant, UNIX_EPOCH};
+
+/// Bump this when the format of the cached font information changes
+const CACHE_VERSION: u32 = 1;
+
+lazy_static! {
+    static ref PRESCAN: Mutex<Option<JoinHandle<FontDirCache>>> = Mutex::new(None);
+}
+
+/// The faces found in a font file, along with the size and
+/// modification time that tell whether the file has changed
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedFile {
+    modified: (u64, u32),
+    len: u64,
+    faces: Vec<(u32, Names)>,
+}
+
+/// Records the faces found in the files of the font_dirs, so that
+/// only new or modified files need to be parsed at startup
+#[derive(Default, Serialize, Deserialize)]
+struct FontDirCache {
+    version: u32,
+    dirs: Vec<PathBuf>,
+    /// In the order in which they were found in `dirs`
+    files: Vec<(PathBuf, CachedFile)>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl FontDirCache {
+    fn path() -> PathBuf {
+        config::CACHE_DIR.join("font-dirs.json")
+    }
+
+    fn load() -> Self {
+        std::fs::read(Self::path())
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Self>(&data).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        config::create_user_owned_dirs(&config::CACHE_DIR)?;
+        let path = Self::path();
+        std::fs::write(&path, serde_json::to_vec(self)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+fn parse_faces(path: &Path) -> Vec<(u32, Names)> {
+    let mut font_info = vec![];
+    if let Err(err) = parse_and_collect_font_info(path, &mut font_info) {
+        log::trace!("failed to read {}: {}", path.display(), err);
+    }
+    font_info
+        .into_iter()
+        .filter_map(|(names, _path, handle)| match handle {
+            FontDataHandle::OnDisk { index, .. } => Some((index, names)),
+            FontDataHandle::Memory { .. } => None,
+        })
+        .collect()
+}
+
+/// Walks `dirs`, re-using the entries of `cache` for the files that
+/// have not changed and parsing the others in parallel
+fn scan_font_dirs(dirs: &[PathBuf], cache: FontDirCache) -> FontDirCache {
+    let start = Instant::now();
+    let mut files = vec![];
+    for dir in dirs {
+        for entry in walkdir::WalkDir::new(dir)
+            .into_iter()
+            .filter_map(Result::ok)
+        {
+            let meta = match std::fs::metadata(entry.path()) {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| (d.as_secs(), d.subsec_nanos()))
+                .unwrap_or((0, 0));
+            files.push((entry.into_path(), modified, meta.len()));
+        }
+    }
+
+    let previous: HashMap<PathBuf, CachedFile> = cache.files.into_iter().collect();
+    let num_previous = previous.len();
+    let files: Vec<(PathBuf, CachedFile, bool)> = files
+        .into_par_iter()
+        .map(|(path, modified, len)| match previous.get(&path) {
+            Some(file) if file.modified == modified && file.len == len => {
+                (path, file.clone(), false)
+            }
+            _ => {
+                let faces = parse_faces(&path);
+                (
+                    path,
+                    CachedFile {
+                        modified,
+                        len,
+                        faces,
+                    },
+                    true,
+                )
+            }
+        })
+        .collect();
+
+    let num_parsed = files.iter().filter(|(_, _, parsed)| *parsed).count();
+    let changed = num_parsed > 0 || files.len() != num_previous || cache.dirs != dirs;
+    let elapsed = start.elapsed();
+    metrics::histogram!("font.scan.font_dirs", elapsed);
+    log::debug!(
+        "scanned {} font_dirs files, parsing {} of them, in {:?}",
+        files.len(),
+        num_parsed,
+        elapsed
+    );
+
+    FontDirCache {
+        version: CACHE_VERSION,
+        dirs: dirs.to_vec(),
+        files: files
+            .into_iter()
+            .map(|(path, file, _)| (path, file))
+            .collect(),
+        changed,
+    }
+}
+
+fn take_prescan() -> Option<FontDirCache> {
+    PRESCAN
+        .lock()
+        .unwrap()
+        .take()
+        .and_then(|handle| handle.join().ok())
+}
+
+/// Starts checking the font_dirs that were recorded in the cache by the
+/// previous run on a background thread, so that the scan overlaps with
+/// the evaluation of the configuration.  FontDatabase::with_font_dirs
+/// uses the result if the configured font_dirs are unchanged.
+pub fn prescan_font_dirs() {
+    let handle = std::thread::spawn(|| {
+        let cache = FontDirCache::load();
+        if cache.dirs.is_empty() {
+            return cache;
+        }
+        let dirs = cache.dirs.clone();
+        scan_font_dirs(&dirs, cache)
+    });
+    PRESCAN.lock().unwrap().replace(handle);
+}
 
 struct Entry {
     names: Names,
@@ -104,25 +266,42 @@ impl FontDatabase {
     /// Build up the database from the fonts found in the configured font dirs
     /// and from the built-in selection of fonts
     pub fn with_font_dirs(config: &Config) -> anyhow::Result<Self> {
-        let mut font_info = vec![];
-        for path in &config.font_dirs {
-            for entry in walkdir::WalkDir::new(path).into_iter() {
-                let entry = match entry {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                };
-
-                let path = entry.path();
-                parse_and_collect_font_info(path, &mut font_info)
-                    .map_err(|err| {
-                        log::trace!("failed to read {}: {}", path.display(), err);
-                        err
-                    })
-                    .ok();
+        let mut db = Self::new();
+        let prescanned = take_prescan();
+        if config.font_dirs.is_empty() {
+            // Stop the next startup from scanning the dirs that we used to have
+            if prescanned
+                .map(|cache| !cache.dirs.is_empty())
+                .unwrap_or(false)
+            {
+                FontDirCache::default().save().ok();
             }
+            return Ok(db);
         }
 
-        let mut db = Self::new();
+        let cache = match prescanned {
+            Some(cache) if cache.dirs == config.font_dirs => cache,
+            _ => scan_font_dirs(&config.font_dirs, FontDirCache::load()),
+        };
+        if cache.changed {
+            if let Err(err) = cache.save() {
+                log::warn!("failed to save font_dirs cache: {:#}", err);
+            }
+        }
+
+        let mut font_info = vec![];
+        for (path, file) in cache.files {
+            for (index, names) in file.faces {
+                font_info.push((
+                    names,
+                    path.clone(),
+                    FontDataHandle::OnDisk {
+                        path: path.clone(),
+                        index,
+                    },
+                ));
+            }
+        }
         db.load_font_info(font_info);
         Ok(db)
     }
@@ -169,6 +169,10 @@ struct FontConfigInner {
     dpi_scale: RefCell<f64>,
     font_scale: RefCell<f64>,
     config_generation: RefCell<usize>,
+    /// The configuration that fonts are resolved from; this is the
+    /// global configuration unless the owner has given one with
+    /// window specific overrides
+    config: RefCell<ConfigHandle>,
     locator: Box<dyn FontLocator>,
     font_dirs: RefCell<FontDatabase>,
     built_in: RefCell<FontDatabase>,
@@ -193,24 +197,37 @@ impl FontConfigInner {
             config_generation: RefCell::new(config.generation()),
             font_dirs: RefCell::new(FontDatabase::with_font_dirs(&config)?),
             built_in: RefCell::new(FontDatabase::with_built_in()?),
+            config: RefCell::new(config),
         })
     }
 
+    /// Switches to `config`, discarding anything that was derived
+    /// from the previous configuration
+    pub fn config_changed(&self, config: &ConfigHandle) -> anyhow::Result<()> {
+        self.fonts.borrow_mut().clear();
+        self.metrics.borrow_mut().take();
+        *self.font_dirs.borrow_mut() = FontDatabase::with_font_dirs(config)?;
+        *self.config_generation.borrow_mut() = config.generation();
+        *self.config.borrow_mut() = config.clone();
+        Ok(())
+    }
+
+    pub fn config(&self) -> ConfigHandle {
+        self.config.borrow().clone()
+    }
+
     /// Given a text style, load (with caching) the font that best
     /// matches according to the fontconfig pattern.
     fn resolve_font(&self, myself: &Rc<Self>, style: &TextStyle) -> anyhow::Result<Rc<LoadedFont>> {
-        let mut fonts = self.fonts.borrow_mut();
-
-        let config = configuration();
-        let current_generation = config.generation();
-        if current_generation != *self.config_generation.borrow() {
+        let current = configuration();
+        if current.generation() != *self.config_generation.borrow() {
             // Config was reloaded, invalidate our caches
-            fonts.clear();
-            self.metrics.borrow_mut().take();
-            *self.font_dirs.borrow_mut() = FontDatabase::with_font_dirs(&config)?;
-            *self.config_generation.borrow_mut() = current_generation;
+            self.config_changed(&current)?;
         }
 
+        let config = self.config();
+        let mut fonts = self.fonts.borrow_mut();
+
         if let Some(entry) = fonts.get(style) {
             return Ok(Rc::clone(entry));
         }
@@ -286,7 +303,6 @@ impl FontConfigInner {
 
         let shaper = new_shaper(FontShaperSelection::get_default(), &handles)?;
 
-        let config = configuration();
         let font_size = config.font_size * *self.font_scale.borrow();
         let dpi =
             *self.dpi_scale.borrow() as u32 * config.dpi.unwrap_or(::window::DEFAULT_DPI) as u32;
@@ -326,7 +342,8 @@ impl FontConfigInner {
 
     /// Returns the baseline font specified in the configuration
     pub fn default_font(&self, myself: &Rc<Self>) -> anyhow::Result<Rc<LoadedFont>> {
-        self.resolve_font(myself, &configuration().font)
+        let font = self.config().font.clone();
+        self.resolve_font(myself, &font)
     }
 
     pub fn get_font_scale(&self) -> f64 {
@@ -407,6 +424,17 @@ impl FontConfiguration {
         self.inner.change_scaling(font_scale, dpi_scale)
     }
 
+    /// Resolves fonts from `config` from now on, which allows a
+    /// window to use its own font options
+    pub fn config_changed(&self, config: &ConfigHandle) -> anyhow::Result<()> {
+        self.inner.config_changed(config)
+    }
+
+    /// Returns the configuration that fonts are resolved from
+    pub fn config(&self) -> ConfigHandle {
+        self.inner.config()
+    }
+
     /// Returns the baseline font specified in the configuration
     pub fn default_font(&self) -> anyhow::Result<Rc<LoadedFont>> {
         self.inner.default_font(&self.inner)
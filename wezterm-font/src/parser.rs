@@ -2,6 +2,7 @@ use crate::locator::FontDataHandle;
 use crate::shaper::GlyphInfo;
 use anyhow::anyhow;
 use config::FontAttributes;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use ttf_parser::{fonts_in_collection, Face, Name, PlatformId};
 
@@ -16,7 +17,7 @@ pub struct ParsedFont {
     names: Names,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Names {
     pub full_name: String,
     pub family: Option<String>,
@@ -55,6 +55,13 @@ pub struct StartCommand {
     #[structopt(long = "class")]
     pub class: Option<String>,
 
+    /// Show the window as a drop-down that is attached to the
+    /// top of the screen, using the `drop_down` settings from
+    /// the configuration, or the defaults if there are none.
+    /// This is only supported on Wayland.
+    #[structopt(long = "drop-down")]
+    pub drop_down: bool,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -79,6 +86,12 @@ pub struct SshCommand {
     /// used instead.
     pub user_at_host_and_port: SshParameters,
 
+    /// Connect by way of one or more jump hosts, given as a comma
+    /// separated list of `[username@]host[:port]`, as for `ssh -J`.
+    /// This overrides any `ProxyJump` from your ssh config.
+    #[structopt(short = "J", long = "jump")]
+    pub jump: Option<String>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm ssh user@host -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -99,6 +112,26 @@ pub struct SerialCommand {
     #[structopt(long = "baud")]
     pub baud: Option<usize>,
 
+    /// Set the parity.  The default is none.
+    #[structopt(long = "parity", possible_values = &["none", "odd", "even"])]
+    pub parity: Option<String>,
+
+    /// Set the number of data bits in each character.  The default is 8.
+    #[structopt(long = "data-bits", possible_values = &["5", "6", "7", "8"])]
+    pub data_bits: Option<usize>,
+
+    /// Set the number of stop bits.  The default is 1.
+    #[structopt(long = "stop-bits", possible_values = &["1", "2"])]
+    pub stop_bits: Option<usize>,
+
+    /// Set the flow control; software is XON/XOFF and hardware is
+    /// RTS/CTS.  The default is software.
+    #[structopt(
+        long = "flow-control",
+        possible_values = &["none", "software", "hardware"]
+    )]
+    pub flow_control: Option<String>,
+
     /// Specifies the serial device name.
     /// On Windows systems this can be a name like `COM0`.
     /// On posix systems this will be something like `/dev/ttyUSB0`
@@ -116,7 +149,9 @@ pub struct ConnectCommand {
     pub front_end: Option<FrontEndSelection>,
 
     /// Name of the multiplexer domain section from the configuration
-    /// to which you'd like to connect
+    /// to which you'd like to connect.
+    /// Use `tmux:SESSION` to attach to the named tmux session using
+    /// tmux control mode, creating it if it doesn't exist.
     pub domain_name: String,
 
     /// Instead of executing your shell, run PROG.
@@ -0,0 +1,220 @@
+//! When a previous run crashed, offers to open a pre-filled GitHub
+//! issue or to save the crash reports to a file that can be attached
+//! to one.  See env_bootstrap::crash for how the reports are written.
+use config::configuration;
+use env_bootstrap::crash::{mark_crash_reports_seen, pending_crash_reports};
+use mux::connui::ConnectionUI;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const NEW_ISSUE_URL: &str = "https://github.com/wez/wezterm/issues/new";
+/// Browsers and GitHub start to reject URLs that are longer than this
+const MAX_ISSUE_URL_LEN: usize = 8000;
+
+struct Report {
+    path: PathBuf,
+    contents: String,
+}
+
+impl Report {
+    /// Returns a one line description of the crash
+    fn summary(&self) -> String {
+        let mut lines = self.contents.lines();
+        while let Some(line) = lines.next() {
+            if line.starts_with("panic in thread") {
+                let message = lines.next().unwrap_or("");
+                return format!("panic: {}", message);
+            }
+            if line.starts_with("fatal signal") {
+                return line.to_string();
+            }
+        }
+        "unknown crash".to_string()
+    }
+
+    fn issue_url(&self) -> anyhow::Result<url::Url> {
+        let mut title = format!("Crash: {}", self.summary());
+        if let Some((idx, _)) = title.char_indices().nth(100) {
+            title.truncate(idx);
+        }
+
+        let mut limit = self.contents.len();
+        loop {
+            let mut report = self.contents.as_str();
+            let mut truncated = "";
+            if limit < report.len() {
+                let idx = (0..=limit)
+                    .rev()
+                    .find(|&idx| report.is_char_boundary(idx))
+                    .unwrap_or(0);
+                report = &report[..idx];
+                truncated = "\n(truncated; please attach the full report)";
+            }
+            let body = format!(
+                "**What were you doing when wezterm crashed?**\n\n\n\
+                 <details><summary>Crash report</summary>\n\n\
+                 ```\n{}{}\n```\n\n</details>\n",
+                report, truncated
+            );
+            let url = url::Url::parse_with_params(
+                NEW_ISSUE_URL,
+                &[("title", title.as_str()), ("body", body.as_str())],
+            )?;
+            if url.as_str().len() <= MAX_ISSUE_URL_LEN || limit == 0 {
+                return Ok(url);
+            }
+            limit = limit * 3 / 4;
+        }
+    }
+}
+
+/// Combines the reports into a single file in the home directory,
+/// returning its path
+fn save_bundle(reports: &[Report]) -> anyhow::Result<PathBuf> {
+    let path = config::HOME_DIR.join(format!(
+        "wezterm-crash-{}.txt",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let mut bundle = format!(
+        "Crash reports collected by wezterm {}\n",
+        config::wezterm_version()
+    );
+    for report in reports {
+        bundle.push_str(&format!(
+            "\n==== {} ====\n{}\n",
+            report.path.display(),
+            report.contents
+        ));
+    }
+    std::fs::write(&path, bundle)?;
+    Ok(path)
+}
+
+fn offer_crash_reports() -> anyhow::Result<()> {
+    let paths = pending_crash_reports();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let reports: Vec<Report> = paths
+        .iter()
+        .filter_map(|path| {
+            let contents = std::fs::read(path).ok()?;
+            Some(Report {
+                path: path.with_extension("seen"),
+                contents: String::from_utf8_lossy(&contents).into_owned(),
+            })
+        })
+        .collect();
+    // Mark them before showing anything, so that if we crash again
+    // while showing the window, we don't offer them in a loop
+    mark_crash_reports_seen(&paths);
+    let latest = match reports.last() {
+        Some(report) => report,
+        None => return Ok(()),
+    };
+
+    let ui = ConnectionUI::new_with_no_close_delay();
+    ui.title("WezTerm Crash Report");
+    ui.output_str(&format!(
+        "wezterm crashed the last time it ran:\n\n  {}\n\n\
+         A report was saved to {}\n\
+         Opening an issue with the report helps to get the problem fixed.\n\
+         Please review it first; it includes the command line that wezterm \
+         was started with.\n\n",
+        latest.summary(),
+        latest.path.display()
+    ));
+    if reports.len() > 1 {
+        ui.output_str(&format!(
+            "There are {} reports from earlier crashes too.\n\n",
+            reports.len() - 1
+        ));
+    }
+
+    loop {
+        let line = ui.input("Open an [i]ssue, [s]ave all reports to a file, or [d]ismiss?> ")?;
+        match line.trim() {
+            "i" => {
+                let url = latest.issue_url()?;
+                ui.run_and_log_error(|| Ok(open::that(url.as_str())?)).ok();
+            }
+            "s" => {
+                if let Ok(path) = ui.run_and_log_error(|| save_bundle(&reports)) {
+                    ui.output_str(&format!("Saved to {}\n", path.display()));
+                }
+            }
+            "d" | "" => break,
+            _ => {}
+        }
+    }
+    ui.close();
+    Ok(())
+}
+
+/// Checks for reports from crashes in previous runs and offers to help
+/// report them.  Only the first call in a process does anything.
+pub fn offer_previous_crash_reports() {
+    static OFFERED: AtomicBool = AtomicBool::new(false);
+    if configuration().offer_crash_reports {
+        if let Ok(false) =
+            OFFERED.compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            std::thread::Builder::new()
+                .name("crash_reports".into())
+                .spawn(|| {
+                    if let Err(err) = offer_crash_reports() {
+                        log::error!("while offering crash reports: {:#}", err);
+                    }
+                })
+                .expect("failed to spawn crash reports thread");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report(contents: &str) -> Report {
+        Report {
+            path: PathBuf::from("1-2.seen"),
+            contents: contents.to_string(),
+        }
+    }
+
+    #[test]
+    fn summary() {
+        assert_eq!(
+            report("wezterm crash report\n\npanic in thread 'main' at src/x.rs:1:2:\noops\n")
+                .summary(),
+            "panic: oops"
+        );
+        assert_eq!(
+            report("wezterm crash report\n\nfatal signal 11\n\nstack").summary(),
+            "fatal signal 11"
+        );
+        assert_eq!(report("").summary(), "unknown crash");
+    }
+
+    #[test]
+    fn issue_url_fits() {
+        let big = report(&format!(
+            "panic in thread 'main' at x:\nboom\n{}",
+            "ü 7f00deadbeef-7f00deadc000 r-xp /usr/lib/libfoo.so\n".repeat(2000)
+        ));
+        let url = big.issue_url().unwrap();
+        assert!(url.as_str().len() <= MAX_ISSUE_URL_LEN);
+        let body = url
+            .query_pairs()
+            .find(|(k, _)| k == "body")
+            .unwrap()
+            .1
+            .into_owned();
+        assert!(body.contains("boom"));
+        assert!(body.contains("(truncated"));
+
+        let small = report("panic in thread 'main' at x:\nboom\n");
+        let url = small.issue_url().unwrap();
+        assert!(!url.as_str().contains("truncated"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Decides how often a window should repaint in response to output
+//! from its panes.
+//!
+//! Interactive output (echoing keystrokes, a prompt appearing) is
+//! painted as soon as it arrives.  When a pane produces a continuous
+//! stream of output, such as `find /`, painting every frame wastes
+//! CPU that the producer could be using, and nobody can read the text
+//! as it flies past anyway, so we stretch the interval between paints
+//! the longer the stream continues.
+//!
+//! Where the windowing system reports when our frames are actually
+//! shown, we also keep statistics about their latency, and avoid
+//! painting more often than the display can refresh.
+use luahelper::impl_lua_conversion;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use window::FramePresentation;
+
+/// Output that arrives this soon after the previous paint waits for
+/// the next periodic maintenance tick rather than triggering a paint
+const MIN_INTERVAL: Duration = Duration::from_millis(8);
+/// Output that flows for longer than this without a pause is a firehose
+const BURST_THRESHOLD: Duration = Duration::from_millis(100);
+/// A gap in the output of at least this long ends a burst
+const QUIET_PERIOD: Duration = Duration::from_millis(50);
+/// The paint interval at the start of a firehose
+const FIREHOSE_INTERVAL: Duration = Duration::from_millis(35);
+/// The paint interval never stretches beyond this
+const MAX_INTERVAL: Duration = Duration::from_millis(250);
+/// How many presented frames the statistics are computed over
+const MAX_SAMPLES: usize = 120;
+
+#[derive(Default, Debug)]
+pub struct FramePacing {
+    /// When the current run of output began
+    burst_start: Option<Instant>,
+    /// When output was most recently seen
+    last_output: Option<Instant>,
+    /// When the most recent paint completed
+    last_paint: Option<Instant>,
+    /// How long the most recent paint took
+    paint_duration: Duration,
+    /// The refresh interval of the display, when it is known
+    refresh: Option<Duration>,
+    /// When recent frames were presented, and their latency
+    samples: VecDeque<(Instant, Duration)>,
+    frames_presented: u64,
+    frames_discarded: u64,
+}
+
+/// Statistics about recently presented frames, in milliseconds,
+/// which are returned by `window:performance_stats()`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceStats {
+    pub frames_presented: u64,
+    pub frames_discarded: u64,
+    /// How many frames were presented in the last second
+    pub fps: usize,
+    pub mean_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// The mean difference in latency between consecutive frames
+    pub jitter_ms: f64,
+    pub refresh_interval_ms: Option<f64>,
+    /// How long the most recent paint took
+    pub paint_ms: f64,
+}
+impl_lua_conversion!(PerformanceStats);
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.
+}
+
+impl FramePacing {
+    /// Records that a pane produced output.
+    /// Returns true if the window should paint right away.
+    pub fn output(&mut self, now: Instant) -> bool {
+        let quiet = self
+            .last_output
+            .map(|last| now.duration_since(last) >= QUIET_PERIOD)
+            .unwrap_or(true);
+        if quiet {
+            self.burst_start = Some(now);
+        }
+        self.last_output = Some(now);
+
+        let since_paint = self
+            .last_paint
+            .map(|last| now.duration_since(last))
+            .unwrap_or(Duration::from_secs(u64::MAX));
+        !self.is_firehose(now) && since_paint >= self.min_interval()
+    }
+
+    /// Painting more often than the display refreshes would produce
+    /// frames that nobody can see
+    fn min_interval(&self) -> Duration {
+        self.refresh
+            .map(|refresh| refresh.max(MIN_INTERVAL))
+            .unwrap_or(MIN_INTERVAL)
+    }
+
+    /// Returns true if output has been flowing continuously for long
+    /// enough that we should throttle painting
+    pub fn is_firehose(&self, now: Instant) -> bool {
+        match (self.burst_start, self.last_output) {
+            (Some(start), Some(last)) => {
+                now.duration_since(last) < QUIET_PERIOD
+                    && last.duration_since(start) >= BURST_THRESHOLD
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the minimum interval between paints that are caused by
+    /// pane output
+    pub fn interval(&self, now: Instant) -> Duration {
+        let start = match self.burst_start {
+            Some(start) if self.is_firehose(now) => start,
+            _ => return self.refresh.unwrap_or_default(),
+        };
+        // Stretch the interval the longer the firehose continues, and
+        // keep painting to no more than about a quarter of the time
+        let age = now.duration_since(start) - BURST_THRESHOLD;
+        (FIREHOSE_INTERVAL + age / 8)
+            .max(self.paint_duration * 4)
+            .max(self.min_interval())
+            .min(MAX_INTERVAL)
+    }
+
+    /// Returns true if enough time has passed since the last paint
+    /// that dirty lines in the panes should be painted now
+    pub fn should_paint(&self, now: Instant) -> bool {
+        match self.last_paint {
+            Some(last) => now.duration_since(last) >= self.interval(now),
+            None => true,
+        }
+    }
+
+    /// Records a completed paint that began at `start`
+    pub fn painted(&mut self, start: Instant, end: Instant) {
+        self.last_paint = Some(end);
+        self.paint_duration = end.duration_since(start);
+    }
+
+    /// Records that a frame was shown on the screen at `now`
+    pub fn presented(&mut self, now: Instant, presentation: FramePresentation) {
+        self.frames_presented += 1;
+        if presentation.refresh.is_some() {
+            self.refresh = presentation.refresh;
+        }
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, presentation.latency));
+    }
+
+    /// Records that a frame was never shown
+    pub fn discarded(&mut self) {
+        self.frames_discarded += 1;
+    }
+
+    pub fn stats(&self, now: Instant) -> PerformanceStats {
+        let latencies: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|(_, latency)| as_ms(*latency))
+            .collect();
+        let mean = |values: &[f64]| {
+            if values.is_empty() {
+                0.
+            } else {
+                values.iter().sum::<f64>() / values.len() as f64
+            }
+        };
+        let deltas: Vec<f64> = latencies
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .collect();
+
+        PerformanceStats {
+            frames_presented: self.frames_presented,
+            frames_discarded: self.frames_discarded,
+            fps: self
+                .samples
+                .iter()
+                .filter(|(when, _)| now.duration_since(*when) <= Duration::from_secs(1))
+                .count(),
+            mean_latency_ms: mean(&latencies),
+            max_latency_ms: latencies.iter().cloned().fold(0., f64::max),
+            jitter_ms: mean(&deltas),
+            refresh_interval_ms: self.refresh.map(as_ms),
+            paint_ms: as_ms(self.paint_duration),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn interactive_output_paints_immediately() {
+        let mut pacing = FramePacing::default();
+        let t0 = Instant::now();
+        assert!(pacing.output(t0));
+        pacing.painted(t0, t0 + ms(2));
+
+        // Too soon after the last paint; left to the periodic tick
+        assert!(!pacing.output(t0 + ms(5)));
+        assert!(pacing.should_paint(t0 + ms(5)));
+
+        // A keystroke echo after a pause
+        assert!(pacing.output(t0 + ms(300)));
+        assert!(!pacing.is_firehose(t0 + ms(300)));
+    }
+
+    #[test]
+    fn firehose_stretches_interval() {
+        let mut pacing = FramePacing::default();
+        let t0 = Instant::now();
+        for i in 0..=20 {
+            pacing.output(t0 + ms(i * 10));
+        }
+        let now = t0 + ms(200);
+        assert!(pacing.is_firehose(now));
+        assert!(!pacing.output(now));
+        pacing.painted(now, now + ms(1));
+        assert_eq!(pacing.interval(now), ms(35) + ms(100) / 8);
+        assert!(!pacing.should_paint(now + ms(20)));
+
+        // A slow paint stretches the interval further
+        pacing.painted(now, now + ms(40));
+        assert_eq!(pacing.interval(now + ms(40)), ms(160));
+
+        // The interval is capped no matter how long the output continues
+        for i in 0..1000 {
+            pacing.output(now + ms(i * 10));
+        }
+        assert_eq!(pacing.interval(now + ms(9990)), MAX_INTERVAL);
+
+        // Once the output stops, we paint the final state right away
+        let later = now + ms(10100);
+        assert!(!pacing.is_firehose(later));
+        assert!(pacing.should_paint(later));
+    }
+
+    #[test]
+    fn presentation_feedback() {
+        let mut pacing = FramePacing::default();
+        let t0 = Instant::now();
+        for (i, latency) in [10, 14, 10, 12].iter().enumerate() {
+            pacing.presented(
+                t0 + ms(i as u64 * 17),
+                FramePresentation {
+                    latency: ms(*latency),
+                    refresh: Some(ms(17)),
+                },
+            );
+        }
+        pacing.discarded();
+
+        let stats = pacing.stats(t0 + ms(1020));
+        assert_eq!(stats.frames_presented, 4);
+        assert_eq!(stats.frames_discarded, 1);
+        assert_eq!(stats.fps, 3);
+        assert_eq!(stats.mean_latency_ms, 11.5);
+        assert_eq!(stats.max_latency_ms, 14.);
+        assert_eq!(stats.jitter_ms, (4. + 4. + 2.) / 3.);
+        assert_eq!(stats.refresh_interval_ms, Some(17.));
+
+        // No point painting faster than the display refreshes
+        pacing.painted(t0, t0 + ms(1));
+        assert!(!pacing.output(t0 + ms(10)));
+        assert!(!pacing.should_paint(t0 + ms(10)));
+        assert!(pacing.should_paint(t0 + ms(18)));
+    }
+}
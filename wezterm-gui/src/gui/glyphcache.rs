@@ -5,7 +5,7 @@ use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
 use ::window::*;
 use anyhow::{anyhow, Context};
-use config::{configuration, AllowSquareGlyphOverflow, TextStyle};
+use config::{AllowSquareGlyphOverflow, TextStyle};
 use euclid::num::Zero;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -219,7 +219,7 @@ impl<T: Texture2d> GlyphCache<T> {
         let is_square = aspect >= 0.9 && aspect <= 1.1;
 
         let allow_width_overflow = if is_square {
-            match configuration().allow_square_glyphs_to_overflow_width {
+            match self.fonts.config().allow_square_glyphs_to_overflow_width {
                 AllowSquareGlyphOverflow::Never => false,
                 AllowSquareGlyphOverflow::Always => true,
                 AllowSquareGlyphOverflow::WhenFollowedBySpace => followed_by_space,
@@ -0,0 +1,204 @@
+//! Keyboard macros are named sequences of the keys that were sent to a
+//! pane while recording, as set up by StartKeyMacroRecording and
+//! StopKeyMacroRecording.  ReplayKeyMacro sends them again, encoded
+//! for the modes of the pane at that time, just as if they were typed.
+//! The macros are kept in a file in the data directory, so that they
+//! are available to later wezterm processes.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use termwiz::input::{KeyCode, Modifiers};
+
+/// One of the key presses that make up a macro
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroStep {
+    Key {
+        key: KeyCode,
+        #[serde(with = "mods_string", default)]
+        mods: Modifiers,
+    },
+    /// Text produced by the input method or a dead key composition
+    Text { text: String },
+}
+
+pub type KeyMacros = BTreeMap<String, Vec<MacroStep>>;
+
+/// Modifiers are written in the same form as the `mods` of key
+/// bindings, such as `CTRL|SHIFT`
+mod mods_string {
+    use super::Modifiers;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const NAMES: &[(Modifiers, &str)] = &[
+        (Modifiers::SHIFT, "SHIFT"),
+        (Modifiers::ALT, "ALT"),
+        (Modifiers::CTRL, "CTRL"),
+        (Modifiers::SUPER, "SUPER"),
+    ];
+
+    pub fn serialize<S: Serializer>(mods: &Modifiers, serializer: S) -> Result<S::Ok, S::Error> {
+        let names: Vec<&str> = NAMES
+            .iter()
+            .filter(|(m, _)| mods.contains(*m))
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            serializer.serialize_str("NONE")
+        } else {
+            serializer.serialize_str(&names.join("|"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Modifiers, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let mut mods = Modifiers::NONE;
+        for ele in s.split('|') {
+            let ele = ele.trim();
+            if ele.is_empty() || ele == "NONE" {
+                continue;
+            }
+            match NAMES.iter().find(|(_, name)| *name == ele) {
+                Some((m, _)) => mods |= *m,
+                None => {
+                    return Err(serde::de::Error::custom(format!(
+                        "invalid modifier name {} in {}",
+                        ele, s
+                    )))
+                }
+            }
+        }
+        Ok(mods)
+    }
+}
+
+thread_local! {
+    /// The macros, loaded from the file when they are first used
+    static MACROS: RefCell<Option<KeyMacros>> = RefCell::new(None);
+}
+
+fn macros_path() -> PathBuf {
+    config::data_dir().join("key-macros.json")
+}
+
+fn load_macros() -> KeyMacros {
+    let path = macros_path();
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return KeyMacros::new(),
+        Err(err) => {
+            log::error!(
+                "Unable to read key macros from {}: {:#}",
+                path.display(),
+                err
+            );
+            return KeyMacros::new();
+        }
+    };
+    match serde_json::from_slice(&data) {
+        Ok(macros) => macros,
+        Err(err) => {
+            log::error!(
+                "Unable to parse key macros in {}: {:#}",
+                path.display(),
+                err
+            );
+            KeyMacros::new()
+        }
+    }
+}
+
+fn save_macros(macros: &KeyMacros) -> anyhow::Result<()> {
+    let path = macros_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating directory {}", dir.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(macros)?;
+    std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))
+}
+
+fn with_macros<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut KeyMacros) -> T,
+{
+    MACROS.with(|macros| {
+        let mut macros = macros.borrow_mut();
+        f(macros.get_or_insert_with(load_macros))
+    })
+}
+
+/// Returns all of the macros, by name
+pub fn key_macros() -> KeyMacros {
+    with_macros(|macros| macros.clone())
+}
+
+pub fn get_key_macro(name: &str) -> Option<Vec<MacroStep>> {
+    with_macros(|macros| macros.get(name).cloned())
+}
+
+/// Replaces the macro `name` with `steps`, or removes it when `steps`
+/// is None, and saves the macros to their file
+pub fn set_key_macro(name: &str, steps: Option<Vec<MacroStep>>) -> anyhow::Result<()> {
+    with_macros(|macros| {
+        match steps {
+            Some(steps) => macros.insert(name.to_string(), steps),
+            None => macros.remove(name),
+        };
+        save_macros(macros)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn steps_round_trip() {
+        let steps = vec![
+            MacroStep::Key {
+                key: KeyCode::Char('x'),
+                mods: Modifiers::CTRL | Modifiers::SHIFT,
+            },
+            MacroStep::Key {
+                key: KeyCode::Enter,
+                mods: Modifiers::NONE,
+            },
+            MacroStep::Text {
+                text: "é".to_string(),
+            },
+        ];
+        let json = serde_json::to_string(&steps).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"key":{"Char":"x"},"mods":"SHIFT|CTRL"},{"key":"Enter","mods":"NONE"},{"text":"é"}]"#
+        );
+        let decoded: Vec<MacroStep> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, steps);
+    }
+
+    #[test]
+    fn parse_mods() {
+        let decoded: Vec<MacroStep> =
+            serde_json::from_str(r#"[{"key":"UpArrow"},{"key":"Tab","mods":"CTRL | ALT"}]"#)
+                .unwrap();
+        assert_eq!(
+            decoded,
+            vec![
+                MacroStep::Key {
+                    key: KeyCode::UpArrow,
+                    mods: Modifiers::NONE,
+                },
+                MacroStep::Key {
+                    key: KeyCode::Tab,
+                    mods: Modifiers::CTRL | Modifiers::ALT,
+                },
+            ]
+        );
+        assert!(
+            serde_json::from_str::<Vec<MacroStep>>(r#"[{"key":"Tab","mods":"HYPER"}]"#).is_err()
+        );
+    }
+}
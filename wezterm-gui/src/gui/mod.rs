@@ -1,11 +1,15 @@
 use ::window::*;
 use anyhow::Error;
 pub use config::FrontEndSelection;
+use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+mod framepacing;
 mod glyphcache;
+mod keymacro;
 mod overlay;
 mod quad;
 mod renderstate;
@@ -16,6 +20,8 @@ mod tabbar;
 mod termwindow;
 mod utilsprites;
 
+pub use keymacro::{key_macros, set_key_macro, MacroStep};
+pub use overlay::OverlayRequest;
 pub use selection::SelectionMode;
 pub use termwindow::set_window_class;
 pub use termwindow::TermWindow;
@@ -23,6 +29,11 @@ pub use termwindow::ICON_DATA;
 
 pub struct GuiFrontEnd {
     connection: Rc<Connection>,
+    /// Where the tab bar of each window is on the screen, so that a
+    /// tab that is dragged out of one window can be dropped onto another
+    tab_bars: RefCell<HashMap<MuxWindowId, ScreenRect>>,
+    /// The gui window that shows each mux window
+    windows: RefCell<HashMap<MuxWindowId, Window>>,
 }
 
 impl Drop for GuiFrontEnd {
@@ -49,7 +60,11 @@ impl GuiFrontEnd {
         }
 
         let connection = Connection::init()?;
-        let front_end = Rc::new(GuiFrontEnd { connection });
+        let front_end = Rc::new(GuiFrontEnd {
+            connection,
+            tab_bars: RefCell::new(HashMap::new()),
+            windows: RefCell::new(HashMap::new()),
+        });
         let mux = Mux::get().expect("mux started and running on main thread");
         let fe = Rc::downgrade(&front_end);
         mux.subscribe(move |n| {
@@ -58,7 +73,7 @@ impl GuiFrontEnd {
                     MuxNotification::WindowCreated(mux_window_id) => {
                         termwindow::TermWindow::new_window(mux_window_id).ok();
                     }
-                    MuxNotification::PaneOutput(_) => {}
+                    _ => {}
                 }
                 true
             } else {
@@ -82,6 +97,47 @@ impl GuiFrontEnd {
 
         self.connection.run_message_loop()
     }
+
+    /// Records where the tab bar of a window is, or that it has none
+    pub fn set_tab_bar_rect(&self, mux_window_id: MuxWindowId, rect: Option<ScreenRect>) {
+        let mut tab_bars = self.tab_bars.borrow_mut();
+        match rect {
+            Some(rect) => {
+                tab_bars.insert(mux_window_id, rect);
+            }
+            None => {
+                tab_bars.remove(&mux_window_id);
+            }
+        }
+    }
+
+    /// Records the gui window that shows a mux window, or that it
+    /// has been closed
+    pub fn set_window(&self, mux_window_id: MuxWindowId, window: Option<Window>) {
+        let mut windows = self.windows.borrow_mut();
+        match window {
+            Some(window) => {
+                windows.insert(mux_window_id, window);
+            }
+            None => {
+                windows.remove(&mux_window_id);
+            }
+        }
+    }
+
+    /// Returns the gui window that shows a mux window
+    pub fn get_window(&self, mux_window_id: MuxWindowId) -> Option<Window> {
+        self.windows.borrow().get(&mux_window_id).cloned()
+    }
+
+    /// Returns the window whose tab bar is at `point`, if any
+    pub fn window_with_tab_bar_at(&self, point: ScreenPoint) -> Option<MuxWindowId> {
+        self.tab_bars
+            .borrow()
+            .iter()
+            .find(|(_, rect)| rect.contains(point))
+            .map(|(mux_window_id, _)| *mux_window_id)
+    }
 }
 
 thread_local! {
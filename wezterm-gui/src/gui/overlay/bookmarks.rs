@@ -0,0 +1,175 @@
+//! The bookmarks overlay lists the rows of a pane that were marked
+//! using SetMark, together with their text.  Selecting one scrolls
+//! the pane to it, and marks can be removed from the list.
+use crate::gui::termwindow::TermWindow;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::StableRowIndex;
+
+fn scroll_to_mark(window: &::window::Window, pane_id: PaneId, row: StableRowIndex) {
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            let mux = Mux::get().unwrap();
+            if let Some(pane) = mux.get_pane(pane_id) {
+                let dims = pane.get_dimensions();
+                term_window.set_viewport(pane_id, Some(row), dims);
+            }
+        }
+        Ok(())
+    });
+}
+
+fn remove_mark(window: &::window::Window, pane_id: PaneId, row: StableRowIndex) {
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            term_window.remove_mark(pane_id, row);
+        }
+        Ok(())
+    });
+}
+
+fn render(
+    active_idx: usize,
+    top: usize,
+    marks: &[(StableRowIndex, String)],
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        Change::Text("Enter to scroll to a mark, d to remove it, Escape to cancel\r\n".to_string()),
+        Change::AllAttributes(CellAttributes::default()),
+    ];
+
+    if marks.is_empty() {
+        changes.push(Change::Text(
+            "No marks have been set; use the SetMark key assignment\r\n".to_string(),
+        ));
+    }
+
+    for (idx, (_, text)) in marks
+        .iter()
+        .enumerate()
+        .skip(top)
+        .take(size.rows.saturating_sub(1))
+    {
+        let mut text = format!(" {}", text);
+        if let Some((byte_idx, _)) = text.char_indices().nth(size.cols) {
+            text.truncate(byte_idx);
+        }
+
+        if idx == active_idx {
+            changes.push(AttributeChange::Reverse(true).into());
+        }
+        changes.push(Change::Text(text));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+        changes.push(Change::Text("\r\n".to_string()));
+    }
+
+    term.render(&changes)?;
+    term.flush()
+}
+
+/// `marks` are the bookmarked rows and their text, ordered newest first
+pub fn bookmarks(
+    pane_id: PaneId,
+    mut term: TermWizTerminal,
+    mut marks: Vec<(StableRowIndex, String)>,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let mut active_idx = 0;
+    let mut top = 0;
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Marks".to_string())])?;
+
+    loop {
+        let rows = term.get_screen_size()?.rows.saturating_sub(1).max(1);
+        if active_idx < top {
+            top = active_idx;
+        } else if active_idx >= top + rows {
+            top = active_idx + 1 - rows;
+        }
+        render(active_idx, top, &marks, &mut term)?;
+
+        let last = marks.len().saturating_sub(1);
+        match term.poll_input(None) {
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('k'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }))) => {
+                active_idx = active_idx.saturating_sub(1);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('j'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }))) => {
+                active_idx = (active_idx + 1).min(last);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('d'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Delete,
+                ..
+            }))) => {
+                if active_idx < marks.len() {
+                    let (row, _) = marks.remove(active_idx);
+                    remove_mark(&window, pane_id, row);
+                    active_idx = active_idx.min(marks.len().saturating_sub(1));
+                }
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }))) => {
+                if let Some((row, _)) = marks.get(active_idx) {
+                    scroll_to_mark(&window, pane_id, *row);
+                }
+                break;
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))) => break,
+            Ok(Some(InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }))) => {
+                if y > 0 && top + (y as usize) <= marks.len() {
+                    active_idx = top + y as usize - 1;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        scroll_to_mark(&window, pane_id, marks[active_idx].0);
+                        break;
+                    }
+                }
+                if mouse_buttons != MouseButtons::NONE && mouse_buttons != MouseButtons::LEFT {
+                    // Treat any other mouse button as cancel
+                    break;
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! The clipboard history overlay lists the text that was recently
+//! copied from wezterm, to the clipboard or the primary selection, in a
+//! list that can be fuzzy filtered by typing, and pastes the selected
+//! entry into the pane.
+use crate::gui::termwindow::TermWindow;
+use config::configuration;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use termwiz::cell::{unicode_column_width, AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+
+/// Rows at the top of the overlay that are not part of the list
+const HEADER_ROWS: usize = 2;
+
+thread_local! {
+    /// The copied text, most recent first
+    static HISTORY: RefCell<VecDeque<String>> = RefCell::new(VecDeque::new());
+}
+
+/// Remembers text that was copied, keeping clipboard_history_size
+/// entries.  Copying the same text again moves it to the front.
+pub fn record_copy(text: &str) {
+    let size = configuration().clipboard_history_size;
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        add_entry(&mut history, text, size);
+    });
+}
+
+fn add_entry(history: &mut VecDeque<String>, text: &str, size: usize) {
+    if text.is_empty() {
+        return;
+    }
+    history.retain(|entry| entry != text);
+    history.push_front(text.to_string());
+    history.truncate(size);
+}
+
+/// Returns the copied text, most recent first
+pub fn clipboard_history() -> Vec<String> {
+    HISTORY.with(|history| history.borrow().iter().cloned().collect())
+}
+
+/// Scores how well `text` matches `filter`, ignoring case, or returns
+/// None if it doesn't contain the characters of `filter` in order.
+/// Characters that match consecutively, or at the start of a word,
+/// score more than those that are scattered.
+fn fuzzy_score(filter: &str, text: &str) -> Option<i64> {
+    let mut filter = filter.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut prior: Option<char> = None;
+    let mut prior_matched = false;
+    for c in text.chars() {
+        let wanted = match filter.peek() {
+            Some(&wanted) => wanted,
+            None => break,
+        };
+        let matched = c.to_lowercase().eq(std::iter::once(wanted));
+        if matched {
+            filter.next();
+            score += 1;
+            if prior_matched {
+                score += 4;
+            }
+            if prior.map(|p| !p.is_alphanumeric()).unwrap_or(true) {
+                score += 2;
+            }
+        } else if filter.peek().is_some() && score > 0 {
+            // A gap between the matched characters
+            score -= 1;
+        }
+        prior = Some(c);
+        prior_matched = matched;
+    }
+    if filter.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+/// Shows the text of an entry on a single row
+fn entry_label(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\n' => '⏎',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+struct State {
+    entries: Vec<String>,
+    filter: String,
+    /// Indices into `entries` of those that match `filter`, best first
+    matches: Vec<usize>,
+    active_idx: usize,
+    top: usize,
+}
+
+impl State {
+    fn update_matches(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| fuzzy_score(&self.filter, entry).map(|score| (score, idx)))
+            .collect();
+        // The sort is stable, so equally good matches stay in
+        // most recent first order
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        self.matches = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.active_idx = 0;
+        self.top = 0;
+    }
+
+    fn selected(&self) -> Option<String> {
+        self.matches
+            .get(self.active_idx)
+            .map(|&idx| self.entries[idx].clone())
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1) as isize;
+        self.active_idx = (self.active_idx as isize + delta).max(0).min(last) as usize;
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let rows = size.rows.saturating_sub(HEADER_ROWS).max(1);
+        if self.active_idx < self.top {
+            self.top = self.active_idx;
+        } else if self.active_idx >= self.top + rows {
+            self.top = self.active_idx + 1 - rows;
+        }
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(format!(
+                "{} of {} copies.  Type to filter, Enter to paste, Escape to cancel\r\n",
+                self.matches.len(),
+                self.entries.len()
+            )),
+            Change::Text(format!("> {}\r\n", self.filter)),
+        ];
+
+        for (idx, &entry_idx) in self.matches.iter().enumerate().skip(self.top).take(rows) {
+            let mut text = format!(" {}", entry_label(&self.entries[entry_idx]));
+            if let Some((byte_idx, _)) = text.char_indices().nth(size.cols) {
+                text.truncate(byte_idx);
+            }
+            if idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(text));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        // Leave the cursor at the end of the filter text
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(2 + unicode_column_width(&self.filter)),
+            y: Position::Absolute(1),
+        });
+
+        term.render(&changes)?;
+        term.flush()
+    }
+}
+
+fn paste_entry(window: &::window::Window, pane_id: PaneId, text: String) {
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            term_window.paste_text(pane_id, text.clone());
+        }
+        Ok(())
+    });
+}
+
+pub fn clipboard_history_overlay(
+    pane_id: PaneId,
+    mut term: TermWizTerminal,
+    entries: Vec<String>,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let mut state = State {
+        entries,
+        filter: String::new(),
+        matches: vec![],
+        active_idx: 0,
+        top: 0,
+    };
+    state.update_matches();
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Clipboard History".to_string())])?;
+
+    loop {
+        state.render(&mut term)?;
+        let page = term
+            .get_screen_size()?
+            .rows
+            .saturating_sub(HEADER_ROWS)
+            .max(1) as isize;
+
+        match term.poll_input(None) {
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))) => break,
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }))) => {
+                if let Some(text) = state.selected() {
+                    paste_entry(&window, pane_id, text);
+                }
+                break;
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('p'),
+                modifiers: Modifiers::CTRL,
+            }))) => state.move_by(-1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('n'),
+                modifiers: Modifiers::CTRL,
+            }))) => state.move_by(1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            }))) => state.move_by(-page),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            }))) => state.move_by(page),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Backspace,
+                ..
+            }))) => {
+                state.filter.pop();
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('u'),
+                modifiers: Modifiers::CTRL,
+            }))) => {
+                state.filter.clear();
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::NONE,
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::SHIFT,
+            }))) => {
+                state.filter.push(c);
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }))) => {
+                let y = y as usize;
+                if y >= HEADER_ROWS && state.top + y - HEADER_ROWS < state.matches.len() {
+                    state.active_idx = state.top + y - HEADER_ROWS;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        if let Some(text) = state.selected() {
+                            paste_entry(&window, pane_id, text);
+                        }
+                        break;
+                    }
+                }
+                if mouse_buttons == MouseButtons::VERT_WHEEL | MouseButtons::WHEEL_POSITIVE {
+                    state.move_by(-1);
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL {
+                    state.move_by(1);
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn history_is_distinct_and_bounded() {
+        let mut history = VecDeque::new();
+        for text in &["one", "two", "", "three", "one"] {
+            add_entry(&mut history, text, 3);
+        }
+        assert_eq!(history, vec!["one", "three", "two"]);
+        add_entry(&mut history, "four", 3);
+        assert_eq!(history, vec!["four", "one", "three"]);
+        add_entry(&mut history, "five", 0);
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn fuzzy_matching() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("gco", "git log"), None);
+        assert!(fuzzy_score("gco", "git checkout").is_some());
+        assert!(fuzzy_score("GCO", "git checkout").is_some());
+        // Consecutive and word start matches are preferred
+        assert!(
+            fuzzy_score("check", "git checkout").unwrap()
+                > fuzzy_score("check", "cache kick").unwrap()
+        );
+    }
+
+    #[test]
+    fn labels_are_single_rows() {
+        assert_eq!(entry_label("ls\n\tpwd\n"), "ls⏎ pwd⏎");
+    }
+}
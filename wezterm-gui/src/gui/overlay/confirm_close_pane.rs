@@ -1,15 +1,105 @@
-use mux::pane::PaneId;
+use config::configuration;
+use config::i18n::tr;
+use mux::pane::{Pane, PaneId};
 use mux::tab::TabId;
 use mux::termwiztermtab::TermWizTerminal;
 use mux::window::WindowId;
 use mux::Mux;
-use termwiz::cell::AttributeChange;
+use std::rc::Rc;
+use termwiz::cell::{unicode_column_width, AttributeChange};
 use termwiz::color::ColorAttribute;
 use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
 use termwiz::surface::{Change, CursorVisibility, Position};
 use termwiz::terminal::Terminal;
 
-fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Result<bool> {
+/// A pane that is running a program that would be killed by
+/// closing it, and which the configuration says should be confirmed
+#[derive(Clone, Debug)]
+pub struct RunningProcess {
+    pub pane_id: PaneId,
+    pub process_name: Option<String>,
+    pub title: String,
+}
+
+impl RunningProcess {
+    fn label(&self) -> String {
+        let name = self
+            .process_name
+            .clone()
+            .unwrap_or_else(|| tr("confirm-unknown-process"));
+        if self.title.is_empty() || self.title == name {
+            name
+        } else {
+            format!("{}: {}", name, self.title)
+        }
+    }
+}
+
+/// Returns the panes from `panes` for which closing needs to be
+/// confirmed, according to skip_close_confirmation_for_processes_named
+/// and close_confirmation_rules.  If this is empty, the panes can be
+/// closed without asking.
+pub fn running_processes(panes: &[Rc<dyn Pane>]) -> Vec<RunningProcess> {
+    let config = configuration();
+    let mux = Mux::get().unwrap();
+    panes
+        .iter()
+        .filter_map(|pane| {
+            let domain = mux
+                .get_domain(pane.domain_id())
+                .map(|domain| domain.domain_name().to_string())
+                .unwrap_or_default();
+            let process_name = pane.get_foreground_process_name();
+            if config.close_needs_confirmation(&domain, process_name.as_deref()) {
+                Some(RunningProcess {
+                    pane_id: pane.pane_id(),
+                    process_name,
+                    title: pane.get_title(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Kills the panes, wherever they are, leaving their windows open
+fn kill_panes(pane_ids: Vec<PaneId>) {
+    promise::spawn::spawn_into_main_thread(async move {
+        let mux = Mux::get().unwrap();
+        for pane_id in pane_ids {
+            if let Some((_domain_id, _window_id, tab_id)) = mux.resolve_pane_id(pane_id) {
+                if let Some(tab) = mux.get_tab(tab_id) {
+                    tab.kill_pane(pane_id);
+                }
+            }
+        }
+    })
+    .detach();
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    No,
+    Yes,
+    /// Kill only the panes that were selected from the list
+    KillSelected(Vec<PaneId>),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ActiveButton {
+    None,
+    Yes,
+    No,
+    KillSelected,
+}
+
+fn run_confirmation_app(
+    message: &str,
+    processes: &[RunningProcess],
+    allow_kill_selected: bool,
+    term: &mut TermWizTerminal,
+) -> anyhow::Result<Answer> {
     term.set_raw_mode()?;
 
     let size = term.get_screen_size()?;
@@ -36,24 +126,71 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
 
     // Fit text to the width
     let wrapped = textwrap::fill(message, text_width);
-
     let message_rows = wrapped.split("\n").count();
+
+    // The list of running programs follows the message, after a
+    // blank line and some text that explains it, and is followed by
+    // another blank line.  Don't let it push the buttons off the screen.
+    let explanation = if processes.is_empty() {
+        String::new()
+    } else {
+        textwrap::fill(&tr("confirm-processes"), text_width)
+    };
+    let explanation_rows = if processes.is_empty() {
+        0
+    } else {
+        explanation.split("\n").count()
+    };
+    let max_process_rows = size
+        .rows
+        .saturating_sub(message_rows + explanation_rows + 4)
+        .max(1);
+    let process_rows = processes.len().min(max_process_rows);
+    let list_rows = if processes.is_empty() {
+        0
+    } else {
+        explanation_rows + process_rows + 1
+    };
+
     // Now we want to vertically center the prompt in the view.
     // After the prompt there will be a blank line and then the "buttons",
     // so we add two to the number of rows.
-    let top_row = (size.rows - (message_rows + 2)) / 2;
+    let top_row = size.rows.saturating_sub(message_rows + list_rows + 2) / 2;
+    let list_row = top_row + message_rows + 1;
+    let first_process_row = list_row + explanation_rows;
+    let button_row = top_row + message_rows + list_rows + 1;
 
-    let button_row = top_row + message_rows + 1;
     let mut active = ActiveButton::None;
+    let mut selected = vec![true; processes.len()];
+    let mut cursor = 0;
+    // The index of the first process that is visible in the list
+    let mut top = 0;
+    let mut prior_buttons = MouseButtons::NONE;
 
-    #[derive(Copy, Clone, PartialEq, Eq)]
-    enum ActiveButton {
-        None,
-        Yes,
-        No,
-    }
+    let yes_label = format!(" {} ", tr("confirm-yes"));
+    let no_label = format!(" {} ", tr("confirm-no"));
+    let kill_label = format!(" {} ", tr("confirm-kill-selected"));
+    const BUTTON_GAP: usize = 8;
+    let yes_width = unicode_column_width(&yes_label);
+    let no_width = unicode_column_width(&no_label);
+    let kill_width = unicode_column_width(&kill_label);
+    let no_x = x_pos + yes_width + BUTTON_GAP;
+    let kill_x = no_x + no_width + BUTTON_GAP;
 
-    let render = |term: &mut TermWizTerminal, active: ActiveButton| -> termwiz::Result<()> {
+    let selected_panes = |selected: &[bool]| -> Vec<PaneId> {
+        processes
+            .iter()
+            .zip(selected.iter())
+            .filter_map(|(p, &sel)| if sel { Some(p.pane_id) } else { None })
+            .collect()
+    };
+
+    let render = |term: &mut TermWizTerminal,
+                  active: ActiveButton,
+                  selected: &[bool],
+                  cursor: usize,
+                  top: usize|
+     -> termwiz::Result<()> {
         let mut changes = vec![
             Change::ClearScreen(ColorAttribute::Default),
             Change::CursorVisibility(CursorVisibility::Hidden),
@@ -77,6 +214,48 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
             changes.push(Change::Text(row.to_string()));
         }
 
+        if !processes.is_empty() {
+            for (y, row) in explanation.split("\n").enumerate() {
+                changes.push(Change::CursorPosition {
+                    x: Position::Absolute(x_pos),
+                    y: Position::Absolute(list_row + y),
+                });
+                changes.push(Change::Text(row.trim_end().to_string()));
+            }
+
+            for (y, (idx, process)) in processes
+                .iter()
+                .enumerate()
+                .skip(top)
+                .take(process_rows)
+                .enumerate()
+            {
+                changes.push(Change::CursorPosition {
+                    x: Position::Absolute(x_pos),
+                    y: Position::Absolute(first_process_row + y),
+                });
+                let mut text = format!(
+                    " [{}] {}",
+                    if selected[idx] { "x" } else { " " },
+                    process.label()
+                );
+                if unicode_column_width(&text) > text_width {
+                    text = textwrap::fill(&text, text_width)
+                        .split("\n")
+                        .next()
+                        .unwrap_or("")
+                        .to_string();
+                }
+                if idx == cursor {
+                    changes.push(AttributeChange::Reverse(true).into());
+                }
+                changes.push(Change::Text(text));
+                if idx == cursor {
+                    changes.push(AttributeChange::Reverse(false).into());
+                }
+            }
+        }
+
         changes.push(Change::CursorPosition {
             x: Position::Absolute(x_pos),
             y: Position::Absolute(button_row),
@@ -85,26 +264,38 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
         if active == ActiveButton::Yes {
             changes.push(AttributeChange::Reverse(true).into());
         }
-        changes.push(" [Y]es ".into());
+        changes.push(yes_label.as_str().into());
         if active == ActiveButton::Yes {
             changes.push(AttributeChange::Reverse(false).into());
         }
 
-        changes.push("        ".into());
+        changes.push(" ".repeat(BUTTON_GAP).into());
 
         if active == ActiveButton::No {
             changes.push(AttributeChange::Reverse(true).into());
         }
-        changes.push(" [N]o ".into());
+        changes.push(no_label.as_str().into());
         if active == ActiveButton::No {
             changes.push(AttributeChange::Reverse(false).into());
         }
 
+        if allow_kill_selected {
+            changes.push(" ".repeat(BUTTON_GAP).into());
+
+            if active == ActiveButton::KillSelected {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(kill_label.as_str().into());
+            if active == ActiveButton::KillSelected {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+        }
+
         term.render(&changes)?;
         term.flush()
     };
 
-    render(term, active)?;
+    render(term, active, &selected, cursor, top)?;
 
     while let Ok(Some(event)) = term.poll_input(None) {
         match event {
@@ -112,7 +303,7 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
                 key: KeyCode::Char('y'),
                 ..
             }) => {
-                return Ok(true);
+                return Ok(Answer::Yes);
             }
             InputEvent::Key(KeyEvent {
                 key: KeyCode::Char('n'),
@@ -122,7 +313,33 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
                 key: KeyCode::Escape,
                 ..
             }) => {
-                return Ok(false);
+                return Ok(Answer::No);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('k'),
+                ..
+            }) if allow_kill_selected => {
+                return Ok(Answer::KillSelected(selected_panes(&selected)));
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }) => {
+                cursor = cursor.saturating_sub(1);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }) => {
+                cursor = (cursor + 1).min(processes.len().saturating_sub(1));
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(' '),
+                ..
+            }) => {
+                if let Some(sel) = selected.get_mut(cursor) {
+                    *sel = !*sel;
+                }
             }
             InputEvent::Mouse(MouseEvent {
                 x,
@@ -132,40 +349,78 @@ fn run_confirmation_app(message: &str, term: &mut TermWizTerminal) -> anyhow::Re
             }) => {
                 let x = x as usize;
                 let y = y as usize;
-                if y == button_row && x >= x_pos && x <= x_pos + 7 {
+                let in_list = y >= first_process_row && y < first_process_row + process_rows;
+                if y == button_row && x >= x_pos && x < x_pos + yes_width {
                     active = ActiveButton::Yes;
                     if mouse_buttons == MouseButtons::LEFT {
-                        return Ok(true);
+                        return Ok(Answer::Yes);
                     }
-                } else if y == button_row && x >= x_pos + 14 && x <= x_pos + 22 {
+                } else if y == button_row && x >= no_x && x < no_x + no_width {
                     active = ActiveButton::No;
                     if mouse_buttons == MouseButtons::LEFT {
-                        return Ok(false);
+                        return Ok(Answer::No);
+                    }
+                } else if allow_kill_selected
+                    && y == button_row
+                    && x >= kill_x
+                    && x < kill_x + kill_width
+                {
+                    active = ActiveButton::KillSelected;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        return Ok(Answer::KillSelected(selected_panes(&selected)));
+                    }
+                } else if in_list {
+                    active = ActiveButton::None;
+                    let idx = top + y - first_process_row;
+                    if idx < processes.len() {
+                        cursor = idx;
+                        // Toggle once per click, rather than for each
+                        // event reported while the button is held
+                        if mouse_buttons == MouseButtons::LEFT
+                            && prior_buttons == MouseButtons::NONE
+                        {
+                            selected[idx] = !selected[idx];
+                        }
                     }
                 } else {
                     active = ActiveButton::None;
                 }
+                prior_buttons = mouse_buttons;
 
-                if mouse_buttons != MouseButtons::NONE {
+                if mouse_buttons != MouseButtons::NONE && !in_list {
                     // Treat any other mouse button as cancel
-                    return Ok(false);
+                    return Ok(Answer::No);
                 }
             }
             _ => {}
         }
 
-        render(term, active)?;
+        // Scroll the list so that the cursor is visible
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + process_rows {
+            top = cursor + 1 - process_rows;
+        }
+
+        render(term, active, &selected, cursor, top)?;
     }
 
-    Ok(false)
+    Ok(Answer::No)
+}
+
+/// Asks a yes or no question, returning true if the answer is yes
+pub fn confirm(message: &str, term: &mut TermWizTerminal) -> anyhow::Result<bool> {
+    Ok(run_confirmation_app(message, &[], false, term)? == Answer::Yes)
 }
 
 pub fn confirm_close_pane(
     pane_id: PaneId,
+    processes: Vec<RunningProcess>,
     mut term: TermWizTerminal,
     mux_window_id: WindowId,
 ) -> anyhow::Result<()> {
-    if run_confirmation_app("🛑 Really kill this pane?", &mut term)? {
+    if run_confirmation_app(&tr("confirm-close-pane"), &processes, false, &mut term)? == Answer::Yes
+    {
         promise::spawn::spawn_into_main_thread(async move {
             let mux = Mux::get().unwrap();
             let tab = match mux.get_active_tab_for_window(mux_window_id) {
@@ -182,49 +437,60 @@ pub fn confirm_close_pane(
 
 pub fn confirm_close_tab(
     tab_id: TabId,
+    processes: Vec<RunningProcess>,
     mut term: TermWizTerminal,
     _mux_window_id: WindowId,
 ) -> anyhow::Result<()> {
-    if run_confirmation_app(
-        "🛑 Really kill this tab and all contained panes?",
-        &mut term,
-    )? {
-        promise::spawn::spawn_into_main_thread(async move {
-            let mux = Mux::get().unwrap();
-            mux.remove_tab(tab_id);
-        })
-        .detach();
+    match run_confirmation_app(&tr("confirm-close-tab"), &processes, true, &mut term)? {
+        Answer::Yes => {
+            promise::spawn::spawn_into_main_thread(async move {
+                let mux = Mux::get().unwrap();
+                mux.remove_tab(tab_id);
+            })
+            .detach();
+        }
+        Answer::KillSelected(pane_ids) => kill_panes(pane_ids),
+        Answer::No => {}
     }
 
     Ok(())
 }
 
 pub fn confirm_close_window(
+    processes: Vec<RunningProcess>,
     mut term: TermWizTerminal,
     mux_window_id: WindowId,
 ) -> anyhow::Result<()> {
-    if run_confirmation_app(
-        "🛑 Really kill this window and all contained tabs and panes?",
-        &mut term,
-    )? {
-        promise::spawn::spawn_into_main_thread(async move {
-            let mux = Mux::get().unwrap();
-            mux.kill_window(mux_window_id);
-        })
-        .detach();
+    match run_confirmation_app(&tr("confirm-close-window"), &processes, true, &mut term)? {
+        Answer::Yes => {
+            promise::spawn::spawn_into_main_thread(async move {
+                let mux = Mux::get().unwrap();
+                mux.kill_window(mux_window_id);
+            })
+            .detach();
+        }
+        Answer::KillSelected(pane_ids) => kill_panes(pane_ids),
+        Answer::No => {}
     }
 
     Ok(())
 }
 
-pub fn confirm_quit_program(mut term: TermWizTerminal) -> anyhow::Result<()> {
-    if run_confirmation_app("🛑 Really Quit WezTerm?", &mut term)? {
-        promise::spawn::spawn_into_main_thread(async move {
-            use ::window::{Connection, ConnectionOps};
-            let con = Connection::get().expect("call on gui thread");
-            con.terminate_message_loop();
-        })
-        .detach();
+pub fn confirm_quit_program(
+    processes: Vec<RunningProcess>,
+    mut term: TermWizTerminal,
+) -> anyhow::Result<()> {
+    match run_confirmation_app(&tr("confirm-quit"), &processes, true, &mut term)? {
+        Answer::Yes => {
+            promise::spawn::spawn_into_main_thread(async move {
+                use ::window::{Connection, ConnectionOps};
+                let con = Connection::get().expect("call on gui thread");
+                con.terminate_message_loop();
+            })
+            .detach();
+        }
+        Answer::KillSelected(pane_ids) => kill_panes(pane_ids),
+        Answer::No => {}
     }
 
     Ok(())
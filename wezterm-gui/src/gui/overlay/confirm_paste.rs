@@ -0,0 +1,87 @@
+//! Asks before pasting text that could run commands by itself.  A
+//! program that hasn't enabled bracketed paste mode can't tell pasted
+//! text from typing, so a newline in the text acts as pressing Enter,
+//! and other control characters can act as key presses too.
+use super::confirm_close_pane::confirm;
+use config::i18n::tr;
+use mux::termwiztermtab::TermWizTerminal;
+
+/// How much of the text is shown in the prompt
+const PREVIEW_LINES: usize = 5;
+const PREVIEW_LINE_CHARS: usize = 120;
+
+fn is_unsafe_char(c: char) -> bool {
+    c != '\t' && c.is_control()
+}
+
+/// Returns true if `text` contains newlines or control characters
+pub fn paste_needs_confirmation(text: &str) -> bool {
+    text.chars().any(is_unsafe_char)
+}
+
+/// Shows control characters in caret notation, so that they are
+/// visible in the preview rather than acted upon
+fn escape_line(line: &str) -> String {
+    let mut escaped = String::new();
+    for c in line.chars() {
+        match c {
+            '\x7f' => escaped.push_str("^?"),
+            c if (c as u32) < 0x20 && c != '\t' => {
+                escaped.push('^');
+                escaped.push((c as u8 + 0x40) as char);
+            }
+            c if is_unsafe_char(c) => escaped.push_str(&c.escape_unicode().to_string()),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn preview(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut preview = lines
+        .iter()
+        .take(PREVIEW_LINES)
+        .map(|line| {
+            let line = escape_line(line);
+            if line.chars().count() > PREVIEW_LINE_CHARS {
+                let truncated: String = line.chars().take(PREVIEW_LINE_CHARS).collect();
+                format!("{}...", truncated)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>();
+    if lines.len() > PREVIEW_LINES {
+        preview.push(format!("... ({} lines)", lines.len()));
+    }
+    preview.join("\n")
+}
+
+/// Asks whether `text` should be pasted, returning true if it should
+pub fn confirm_paste(mut term: TermWizTerminal, text: &str) -> anyhow::Result<bool> {
+    let message = format!("{}\n\n{}", tr("confirm-paste"), preview(text));
+    confirm(&message, &mut term)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn needs_confirmation() {
+        assert!(!paste_needs_confirmation("ls -l\tfoo"));
+        assert!(paste_needs_confirmation("ls\nrm -rf ~"));
+        assert!(paste_needs_confirmation("ls\r"));
+        assert!(paste_needs_confirmation("ls\x1b[201~"));
+        assert!(paste_needs_confirmation("ls\u{9b}201~"));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_line("echo\x1b[201~\r"), "echo^[[201~^M");
+        assert_eq!(escape_line("a\tb\x7f"), "a\tb^?");
+        assert_eq!(escape_line("\u{9b}"), "\\u{9b}");
+        assert_eq!(preview("a\nb"), "a\nb");
+    }
+}
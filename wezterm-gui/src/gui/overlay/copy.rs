@@ -27,11 +27,59 @@ struct CopyRenderable {
     cursor: StableCursorPosition,
     delegate: Rc<dyn Pane>,
     start: Option<SelectionCoordinate>,
+    /// Whether the selection is a rectangular block
+    rectangular: bool,
     viewport: Option<StableRowIndex>,
+    /// The repeat count typed ahead of a motion, as in vim
+    count: Option<usize>,
+    /// A key that needs another key to complete it
+    pending: Option<PendingKey>,
+    /// The most recent `f`, `t`, `F` or `T` motion, for `;` and `,`
+    last_find: Option<CharSearch>,
     /// We use this to cancel ourselves later
     window: ::window::Window,
 }
 
+#[derive(Debug, Clone, Copy)]
+enum PendingKey {
+    /// `f`, `t`, `F` or `T`; waiting for the character to find
+    FindChar { forward: bool, till: bool },
+    /// `i` or `a` with a selection; waiting for the text object
+    TextObject { inner: bool },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CharSearch {
+    c: char,
+    forward: bool,
+    till: bool,
+}
+
+/// Classifies cells for the purpose of word motions and text objects.
+/// A WORD (`big_word`) is any run of non-whitespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Punctuation,
+    Word,
+}
+
+fn char_class(s: &str, big_word: bool) -> CharClass {
+    match s.chars().next() {
+        None => CharClass::Whitespace,
+        Some(c) if c.is_whitespace() => CharClass::Whitespace,
+        Some(_) if big_word => CharClass::Word,
+        Some(c) if c.is_alphanumeric() || c == '_' => CharClass::Word,
+        Some(_) => CharClass::Punctuation,
+    }
+}
+
+fn is_blank_line(line: &Line) -> bool {
+    line.cells()
+        .iter()
+        .all(|cell| char_class(cell.str(), true) == CharClass::Whitespace)
+}
+
 struct Dimensions {
     vertical_gap: isize,
     dims: RenderableDimensions,
@@ -49,7 +97,11 @@ impl CopyOverlay {
             window,
             delegate: Rc::clone(pane),
             start: None,
+            rectangular: false,
             viewport: term_window.get_viewport(pane.pane_id()),
+            count: None,
+            pending: None,
+            last_find: None,
         };
         Rc::new(CopyOverlay {
             delegate: Rc::clone(pane),
@@ -101,11 +153,13 @@ impl CopyRenderable {
 
     fn adjust_selection(&self, start: SelectionCoordinate, range: SelectionRange) {
         let pane_id = self.delegate.pane_id();
+        let rectangular = self.rectangular;
         self.window.apply(move |term_window, window| {
             if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
                 let mut selection = term_window.selection(pane_id);
                 selection.start = Some(start);
                 selection.range = Some(range);
+                selection.rectangular = rectangular;
                 window.invalidate();
             }
             Ok(())
@@ -167,6 +221,18 @@ impl CopyRenderable {
         TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
     }
 
+    /// Offers to download the selected remote path over sftp,
+    /// if this is an ssh pane
+    fn download_selection(&self) {
+        let pane_id = self.delegate.pane_id();
+        self.window.apply(move |term_window, _window| {
+            if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                term_window.show_sftp_download(pane_id);
+            }
+            Ok(())
+        });
+    }
+
     fn page_up(&mut self) {
         let dims = self.dimensions();
         self.cursor.y -= dims.dims.viewport_rows as isize;
@@ -365,7 +431,11 @@ impl CopyRenderable {
     }
 
     fn toggle_selection_by_cell(&mut self) {
-        if self.start.take().is_none() {
+        if self.start.is_some() && self.rectangular {
+            // Switch from a rectangular selection to a flowing one
+            self.rectangular = false;
+            self.select_to_cursor_pos();
+        } else if self.start.take().is_none() {
             let coord = SelectionCoordinate {
                 x: self.cursor.x,
                 y: self.cursor.y,
@@ -374,6 +444,292 @@ impl CopyRenderable {
             self.select_to_cursor_pos();
         }
     }
+
+    fn toggle_rectangular_selection(&mut self) {
+        if self.start.is_some() && self.rectangular {
+            self.start.take();
+            self.rectangular = false;
+        } else {
+            if self.start.is_none() {
+                self.start.replace(SelectionCoordinate {
+                    x: self.cursor.x,
+                    y: self.cursor.y,
+                });
+            }
+            self.rectangular = true;
+            self.select_to_cursor_pos();
+        }
+    }
+
+    /// Waits for the next key to complete `pending`, preserving the count
+    fn begin_pending(&mut self, count: usize, pending: PendingKey) {
+        self.count = Some(count);
+        self.pending = Some(pending);
+    }
+
+    /// Returns the line at the stable row `y`, if it exists
+    fn get_line(&self, y: StableRowIndex) -> Option<Line> {
+        let (top, mut lines) = self.delegate.get_lines(y..y + 1);
+        if top == y && !lines.is_empty() {
+            Some(lines.remove(0))
+        } else {
+            None
+        }
+    }
+
+    fn max_row(&self) -> StableRowIndex {
+        let dims = self.delegate.get_dimensions();
+        dims.scrollback_top + dims.scrollback_rows as isize
+    }
+
+    fn line_classes(&self, y: StableRowIndex, big_word: bool) -> Vec<CharClass> {
+        self.get_line(y)
+            .map(|line| {
+                line.cells()
+                    .iter()
+                    .map(|cell| char_class(cell.str(), big_word))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Moves to the start of the next WORD, like `W` in vim
+    fn move_forward_one_big_word(&mut self) {
+        let classes = self.line_classes(self.cursor.y, true);
+        let mut x = self.cursor.x;
+        while x < classes.len() && classes[x] != CharClass::Whitespace {
+            x += 1;
+        }
+        while x < classes.len() && classes[x] == CharClass::Whitespace {
+            x += 1;
+        }
+        if x >= classes.len() {
+            if self.cursor.y + 1 < self.max_row() {
+                self.cursor.y += 1;
+                return self.move_to_start_of_line_content();
+            }
+        } else {
+            self.cursor.x = x;
+        }
+        self.select_to_cursor_pos();
+    }
+
+    /// Moves to the start of the current or prior WORD, like `B` in vim
+    fn move_backward_one_big_word(&mut self) {
+        let dims = self.delegate.get_dimensions();
+        let mut y = self.cursor.y;
+        let mut x = Some(self.cursor.x);
+        loop {
+            let classes = self.line_classes(y, true);
+            let mut pos = x.unwrap_or(classes.len()).min(classes.len());
+            while pos > 0 && classes[pos - 1] == CharClass::Whitespace {
+                pos -= 1;
+            }
+            if pos == 0 && y > dims.scrollback_top {
+                // Continue into the end of the prior line
+                y -= 1;
+                x = None;
+                continue;
+            }
+            while pos > 0 && classes[pos - 1] != CharClass::Whitespace {
+                pos -= 1;
+            }
+            self.cursor.x = pos;
+            self.cursor.y = y;
+            break;
+        }
+        self.select_to_cursor_pos();
+    }
+
+    /// Moves to the next (`forward`) or prior blank line beyond the
+    /// current paragraph, like `}` and `{` in vim
+    fn move_by_paragraph(&mut self, forward: bool) {
+        let dims = self.delegate.get_dimensions();
+        let max_row = self.max_row();
+        let is_blank = |y| self.get_line(y).map(|l| is_blank_line(&l)).unwrap_or(true);
+        let step = |y: StableRowIndex| {
+            if forward {
+                if y + 1 < max_row {
+                    Some(y + 1)
+                } else {
+                    None
+                }
+            } else if y > dims.scrollback_top {
+                Some(y - 1)
+            } else {
+                None
+            }
+        };
+
+        let mut y = self.cursor.y;
+        // Skip any blank lines, then the paragraph itself
+        while let Some(next) = step(y) {
+            y = next;
+            if !is_blank(y) {
+                break;
+            }
+        }
+        while let Some(next) = step(y) {
+            y = next;
+            if is_blank(y) {
+                break;
+            }
+        }
+        self.cursor.y = y;
+        self.cursor.x = 0;
+        self.select_to_cursor_pos();
+    }
+
+    /// Moves to the next occurrence of `search.c` on the current line,
+    /// like `f`, `t`, `F` and `T` in vim.  `repeat` is true for `;` and
+    /// `,`, which skip over an adjacent match for `t` and `T`.
+    fn find_char(&mut self, search: CharSearch, repeat: bool) {
+        let line = match self.get_line(self.cursor.y) {
+            Some(line) => line,
+            None => return,
+        };
+        let target = search.c.to_string();
+        let cells = line.cells();
+        let skip = if search.till && repeat { 2 } else { 1 };
+
+        let found = if search.forward {
+            (self.cursor.x + skip..cells.len())
+                .find(|&x| cells[x].str() == target)
+                .map(|x| if search.till { x - 1 } else { x })
+        } else {
+            (0..self.cursor.x.saturating_sub(skip - 1))
+                .rev()
+                .find(|&x| cells[x].str() == target)
+                .map(|x| if search.till { x + 1 } else { x })
+        };
+        if let Some(x) = found {
+            self.cursor.x = x;
+            self.select_to_cursor_pos();
+        }
+    }
+
+    fn repeat_find_char(&mut self, reverse: bool) {
+        if let Some(mut search) = self.last_find {
+            if reverse {
+                search.forward = !search.forward;
+            }
+            self.find_char(search, true);
+        }
+    }
+
+    /// Selects the word or WORD around the cursor, like `iw`, `aw`,
+    /// `iW` and `aW` in vim
+    fn select_word_object(&mut self, big_word: bool, inner: bool) {
+        let classes = self.line_classes(self.cursor.y, big_word);
+        if classes.is_empty() {
+            return;
+        }
+        let len = classes.len();
+        let x = self.cursor.x.min(len - 1);
+        let class = classes[x];
+
+        let mut start = x;
+        while start > 0 && classes[start - 1] == class {
+            start -= 1;
+        }
+        let mut end = x;
+        while end + 1 < len && classes[end + 1] == class {
+            end += 1;
+        }
+
+        if !inner {
+            if class == CharClass::Whitespace {
+                // The whitespace along with the word that follows it
+                if end + 1 < len {
+                    let next = classes[end + 1];
+                    while end + 1 < len && classes[end + 1] == next {
+                        end += 1;
+                    }
+                }
+            } else if end + 1 < len && classes[end + 1] == CharClass::Whitespace {
+                // The word along with its trailing whitespace
+                while end + 1 < len && classes[end + 1] == CharClass::Whitespace {
+                    end += 1;
+                }
+            } else {
+                // There is no trailing whitespace, so take the leading
+                while start > 0 && classes[start - 1] == CharClass::Whitespace {
+                    start -= 1;
+                }
+            }
+        }
+
+        self.start.replace(SelectionCoordinate {
+            x: start,
+            y: self.cursor.y,
+        });
+        self.cursor.x = end;
+        self.select_to_cursor_pos();
+    }
+
+    /// Selects the paragraph around the cursor, like `ip` and `ap` in vim
+    fn select_paragraph_object(&mut self, inner: bool) {
+        let dims = self.delegate.get_dimensions();
+        let max_row = self.max_row();
+        let is_blank = |y| self.get_line(y).map(|l| is_blank_line(&l)).unwrap_or(true);
+
+        let y = self.cursor.y;
+        let blank = is_blank(y);
+        let mut top = y;
+        while top > dims.scrollback_top && is_blank(top - 1) == blank {
+            top -= 1;
+        }
+        let mut bottom = y;
+        while bottom + 1 < max_row && is_blank(bottom + 1) == blank {
+            bottom += 1;
+        }
+        if !inner {
+            // Include the run of lines of the other kind that follows
+            while bottom + 1 < max_row && is_blank(bottom + 1) != blank {
+                bottom += 1;
+            }
+        }
+
+        self.start.replace(SelectionCoordinate { x: 0, y: top });
+        self.cursor.y = bottom;
+        self.cursor.x = dims.cols.saturating_sub(1);
+        self.select_to_cursor_pos();
+    }
+
+    fn select_text_object(&mut self, object: char, inner: bool) {
+        match object {
+            'w' => self.select_word_object(false, inner),
+            'W' => self.select_word_object(true, inner),
+            'p' => self.select_paragraph_object(inner),
+            _ => {}
+        }
+    }
+
+    /// Handles the second key of `f`/`t`/`F`/`T` and of text objects.
+    /// Returns true if the key was consumed.
+    fn complete_pending_key(&mut self, key: KeyCode, mods: KeyModifiers) -> bool {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return false,
+        };
+        let c = match (key, mods) {
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => c,
+            // Anything else, such as Escape, cancels the pending key
+            _ => return true,
+        };
+        let count = self.count.take().unwrap_or(1);
+        match pending {
+            PendingKey::FindChar { forward, till } => {
+                let search = CharSearch { c, forward, till };
+                self.last_find = Some(search);
+                for i in 0..count {
+                    self.find_char(search, i > 0);
+                }
+            }
+            PendingKey::TextObject { inner } => self.select_text_object(c, inner),
+        }
+        true
+    }
 }
 
 impl Pane for CopyOverlay {
@@ -402,37 +758,79 @@ impl Pane for CopyOverlay {
     }
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        let count = {
+            let mut render = self.render.borrow_mut();
+            if render.complete_pending_key(key, mods) {
+                return Ok(());
+            }
+            match (key, mods) {
+                (KeyCode::Char(c), KeyModifiers::NONE)
+                    if c.is_ascii_digit() && (c != '0' || render.count.is_some()) =>
+                {
+                    // Accumulate a repeat count for the next motion
+                    let digit = c as usize - '0' as usize;
+                    render.count = Some(
+                        render
+                            .count
+                            .unwrap_or(0)
+                            .saturating_mul(10)
+                            .saturating_add(digit),
+                    );
+                    return Ok(());
+                }
+                _ => render.count.take().unwrap_or(1),
+            }
+        };
+
         match (key, mods) {
             (KeyCode::Char('c'), KeyModifiers::CTRL)
             | (KeyCode::Char('g'), KeyModifiers::CTRL)
             | (KeyCode::Char('q'), KeyModifiers::NONE)
             | (KeyCode::Escape, KeyModifiers::NONE) => self.render.borrow().close(),
             (KeyCode::Char('h'), KeyModifiers::NONE) | (KeyCode::LeftArrow, KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_left_single_cell();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_left_single_cell();
+                }
             }
             (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::DownArrow, KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_down_single_row();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_down_single_row();
+                }
             }
             (KeyCode::Char('k'), KeyModifiers::NONE) | (KeyCode::UpArrow, KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_up_single_row();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_up_single_row();
+                }
             }
             (KeyCode::Char('l'), KeyModifiers::NONE)
             | (KeyCode::RightArrow, KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_right_single_cell();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_right_single_cell();
+                }
             }
 
             (KeyCode::RightArrow, KeyModifiers::ALT) |
             (KeyCode::Char('f'), KeyModifiers::ALT)|
             (KeyCode::Tab, KeyModifiers::NONE) |
             (KeyCode::Char('w'), KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_forward_one_word();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_forward_one_word();
+                }
             }
 
             (KeyCode::LeftArrow, KeyModifiers::ALT) |
             (KeyCode::Char('b'), KeyModifiers::ALT) |
             (KeyCode::Tab, KeyModifiers::SHIFT) |
             (KeyCode::Char('b'), KeyModifiers::NONE) => {
-                self.render.borrow_mut().move_backward_one_word();
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_backward_one_word();
+                }
             }
             (KeyCode::Char('0'), KeyModifiers::NONE) => {
                 self.render.borrow_mut().move_to_start_of_line();
@@ -452,6 +850,71 @@ impl Pane for CopyOverlay {
             (KeyCode::Char(' '), KeyModifiers::NONE) | (KeyCode::Char('v'), KeyModifiers::NONE) => {
                 self.render.borrow_mut().toggle_selection_by_cell();
             }
+            (KeyCode::Char('v'), KeyModifiers::CTRL) => {
+                self.render.borrow_mut().toggle_rectangular_selection();
+            }
+            (KeyCode::Char('W'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('W'), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_forward_one_big_word();
+                }
+            }
+            (KeyCode::Char('B'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('B'), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_backward_one_big_word();
+                }
+            }
+            (KeyCode::Char('}'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('}'), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_by_paragraph(true);
+                }
+            }
+            (KeyCode::Char('{'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('{'), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.move_by_paragraph(false);
+                }
+            }
+            (KeyCode::Char('f'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().begin_pending(count, PendingKey::FindChar { forward: true, till: false });
+            }
+            (KeyCode::Char('t'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().begin_pending(count, PendingKey::FindChar { forward: true, till: true });
+            }
+            (KeyCode::Char('F'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('F'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().begin_pending(count, PendingKey::FindChar { forward: false, till: false });
+            }
+            (KeyCode::Char('T'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('T'), KeyModifiers::NONE) => {
+                self.render.borrow_mut().begin_pending(count, PendingKey::FindChar { forward: false, till: true });
+            }
+            (KeyCode::Char(';'), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.repeat_find_char(false);
+                }
+            }
+            (KeyCode::Char(','), KeyModifiers::NONE) => {
+                let mut render = self.render.borrow_mut();
+                for _ in 0..count {
+                    render.repeat_find_char(true);
+                }
+            }
+            (KeyCode::Char('i'), KeyModifiers::NONE) | (KeyCode::Char('a'), KeyModifiers::NONE) => {
+                // Text objects apply only while selecting, as in vim's visual mode
+                let mut render = self.render.borrow_mut();
+                if render.start.is_some() {
+                    let inner = key == KeyCode::Char('i');
+                    render.begin_pending(count, PendingKey::TextObject { inner });
+                }
+            }
             (KeyCode::Char('G'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
             (KeyCode::Char('G'), KeyModifiers::NONE) => {
                 self.render.borrow_mut().move_to_bottom();
@@ -471,6 +934,10 @@ impl Pane for CopyOverlay {
             (KeyCode::Char('L'), KeyModifiers::NONE) => {
                 self.render.borrow_mut().move_to_viewport_bottom();
             }
+            (KeyCode::Char('D'), KeyModifiers::SHIFT) | // FIXME: normalize the shift away!
+            (KeyCode::Char('D'), KeyModifiers::NONE) => {
+                self.render.borrow().download_selection();
+            }
             (KeyCode::PageUp, KeyModifiers::NONE) | (KeyCode::Char('b'), KeyModifiers::CTRL) => self.render.borrow_mut().page_up(),
             (KeyCode::PageDown, KeyModifiers::NONE) | (KeyCode::Char('f'), KeyModifiers::CTRL) => self.render.borrow_mut().page_down(),
             _ => {}
@@ -0,0 +1,168 @@
+//! The debug log overlay shows the most recent log messages of the gui
+//! process, and keeps updating as more are logged.  Together with
+//! `wezterm cli set-log-level` this allows capturing the logs around an
+//! intermittent problem without restarting wezterm.
+use env_bootstrap::logging::{log_filter, recent_log_entries, LogEntry};
+use mux::termwiztermtab::TermWizTerminal;
+use std::time::Duration;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+
+/// How often to check for newly logged messages
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+fn format_entry(entry: &LogEntry, cols: usize) -> String {
+    let when: chrono::DateTime<chrono::Local> = entry.when.into();
+    let message = entry.message.lines().next().unwrap_or("");
+    let mut text = format!(
+        "{} {:<5} {} > {}",
+        when.format("%H:%M:%S%.3f"),
+        entry.level,
+        entry.target,
+        message
+    );
+    if let Some((idx, _)) = text.char_indices().nth(cols) {
+        text.truncate(idx);
+    }
+    text
+}
+
+fn render(
+    entries: &[LogEntry],
+    bottom: Option<usize>,
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    let rows = size.rows.saturating_sub(1);
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        AttributeChange::Reverse(true).into(),
+        Change::Text(format!(
+            "Log filter: {}  (change it with `wezterm cli set-log-level`)  {}  Escape to close",
+            log_filter(),
+            if bottom.is_some() {
+                "End to follow"
+            } else {
+                "Following"
+            }
+        )),
+        Change::AllAttributes(CellAttributes::default()),
+    ];
+
+    let end = bottom.unwrap_or(entries.len()).min(entries.len());
+    let start = end.saturating_sub(rows);
+    for entry in &entries[start..end] {
+        let color = match entry.level {
+            log::Level::Error => Some(AnsiColor::Maroon),
+            log::Level::Warn => Some(AnsiColor::Olive),
+            log::Level::Info => None,
+            log::Level::Debug | log::Level::Trace => Some(AnsiColor::Grey),
+        };
+        changes.push(Change::Text("\r\n".to_string()));
+        if let Some(color) = color {
+            changes.push(AttributeChange::Foreground(color.into()).into());
+        }
+        changes.push(Change::Text(format_entry(entry, size.cols)));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    term.render(&changes)?;
+    term.flush()
+}
+
+pub fn debug_log(mut term: TermWizTerminal) -> anyhow::Result<()> {
+    // When None, we show the newest messages as they arrive.
+    // Otherwise, this is the index of the entry after the last
+    // one that is displayed.
+    let mut bottom: Option<usize> = None;
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Debug Log".to_string())])?;
+
+    loop {
+        let entries = recent_log_entries();
+        render(&entries, bottom, &mut term)?;
+
+        let rows = term.get_screen_size()?.rows.saturating_sub(1).max(1);
+        let len = entries.len();
+        let current = bottom.unwrap_or(len).min(len);
+        let scroll_up = |amount: usize| Some(current.saturating_sub(amount).max(rows.min(len)));
+        let scroll_down = |amount: usize| {
+            let next = current + amount;
+            if next >= len {
+                None
+            } else {
+                Some(next)
+            }
+        };
+
+        match term.poll_input(Some(REFRESH_INTERVAL)) {
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('k'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }))) => bottom = scroll_up(1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('j'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }))) => bottom = scroll_down(1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            }))) => bottom = scroll_up(rows),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            }))) => bottom = scroll_down(rows),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('g'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Home, ..
+            }))) => bottom = Some(rows.min(len)),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('G'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::End, ..
+            }))) => bottom = None,
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('q'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))) => break,
+            Ok(Some(InputEvent::Mouse(MouseEvent { mouse_buttons, .. }))) => {
+                if mouse_buttons == MouseButtons::VERT_WHEEL | MouseButtons::WHEEL_POSITIVE {
+                    bottom = scroll_up(3);
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL {
+                    bottom = scroll_down(3);
+                }
+            }
+            // A timeout; loop around to pick up new messages
+            Ok(None) => {}
+            Ok(Some(_)) => {}
+            Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,464 @@
+//! The global search overlay searches the scrollback of all of the panes
+//! in all of the tabs of the window and presents the matches grouped by
+//! pane, allowing you to jump to any of them.
+use crate::gui::termwindow::TermWindow;
+use config::keyassignment::Pattern;
+use mux::pane::{PaneId, SearchResult};
+use mux::tab::TabId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::window::WindowId;
+use mux::Mux;
+use std::rc::Rc;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::StableRowIndex;
+
+/// The number of matches per pane that we will show; very common
+/// search terms would otherwise produce an unwieldy list
+const MAX_MATCHES_PER_PANE: usize = 1000;
+
+/// Rows at the top of the overlay that are not part of the result list
+const HEADER_ROWS: usize = 2;
+
+struct Match {
+    pane_id: PaneId,
+    result: SearchResult,
+    /// 1-based line number relative to the top of the scrollback
+    line_number: usize,
+    text: String,
+}
+
+struct PaneMatches {
+    label: String,
+    matches: Vec<Match>,
+    /// The number of matches that were not included in `matches`
+    omitted: usize,
+}
+
+/// Searches all of the panes in the window concurrently; for remote
+/// panes the search is performed by the server.
+async fn search_panes(mux_window_id: WindowId, pattern: Pattern) -> Vec<PaneMatches> {
+    let mux = Mux::get().unwrap();
+    let tabs: Vec<_> = match mux.get_window(mux_window_id) {
+        Some(window) => window.iter().map(Rc::clone).collect(),
+        None => return vec![],
+    };
+
+    let mut searches = vec![];
+    for (tab_idx, tab) in tabs.iter().enumerate() {
+        for pos in tab.iter_panes() {
+            let pane = pos.pane;
+            let label = format!(
+                "Tab {} pane {}: {}",
+                tab_idx + 1,
+                pos.index + 1,
+                pane.get_title()
+            );
+            let pattern = pattern.clone();
+            let dims = pane.get_dimensions();
+            let range =
+                dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex;
+            // Start all of the searches before awaiting any of them,
+            // so that they run in parallel
+            let search = promise::spawn::spawn(async move {
+                let results = pane.search(pattern, range).await;
+                (pane, results)
+            });
+            searches.push((label, search));
+        }
+    }
+
+    let mut panes = vec![];
+    for (label, search) in searches {
+        let (pane, results) = search.await;
+        let mut results = match results {
+            Ok(results) => results,
+            Err(err) => {
+                log::error!("searching pane {}: {:#}", pane.pane_id(), err);
+                continue;
+            }
+        };
+        if results.is_empty() {
+            continue;
+        }
+        results.sort();
+        let omitted = results.len().saturating_sub(MAX_MATCHES_PER_PANE);
+        results.truncate(MAX_MATCHES_PER_PANE);
+
+        let dims = pane.get_dimensions();
+        let matches = results
+            .into_iter()
+            .map(|result| {
+                let (_, lines) = pane.get_lines(result.start_y..result.start_y + 1);
+                Match {
+                    pane_id: pane.pane_id(),
+                    result,
+                    line_number: (result.start_y - dims.scrollback_top).max(0) as usize + 1,
+                    text: lines
+                        .get(0)
+                        .map(|line| line.as_str().trim().to_string())
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+        panes.push(PaneMatches {
+            label,
+            matches,
+            omitted,
+        });
+    }
+    panes
+}
+
+/// Runs the search on the main thread, where the panes live, and
+/// waits for the results
+fn run_search(mux_window_id: WindowId, pattern: Pattern) -> Vec<PaneMatches> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    promise::spawn::spawn_into_main_thread(async move {
+        promise::spawn::spawn(async move {
+            tx.send(search_panes(mux_window_id, pattern).await).ok();
+        })
+        .detach();
+    })
+    .detach();
+    rx.recv().unwrap_or_else(|_| vec![])
+}
+
+/// A row in the result list
+enum Row {
+    Pane(usize),
+    Match(usize, usize),
+    Omitted(usize),
+}
+
+struct State {
+    pattern: Pattern,
+    /// The pattern that produced `panes`, if any
+    searched: Option<Pattern>,
+    panes: Vec<PaneMatches>,
+    rows: Vec<Row>,
+    /// Index into `rows` of the selected match
+    selected: Option<usize>,
+    top: usize,
+}
+
+impl State {
+    fn num_matches(&self) -> usize {
+        self.panes.iter().map(|p| p.matches.len() + p.omitted).sum()
+    }
+
+    fn set_results(&mut self, panes: Vec<PaneMatches>) {
+        self.rows.clear();
+        for (pane_idx, pane) in panes.iter().enumerate() {
+            self.rows.push(Row::Pane(pane_idx));
+            for match_idx in 0..pane.matches.len() {
+                self.rows.push(Row::Match(pane_idx, match_idx));
+            }
+            if pane.omitted > 0 {
+                self.rows.push(Row::Omitted(pane_idx));
+            }
+        }
+        self.panes = panes;
+        self.searched.replace(self.pattern.clone());
+        self.top = 0;
+        self.selected = self.rows.iter().position(|r| matches!(r, Row::Match(..)));
+    }
+
+    fn selected_match(&self) -> Option<&Match> {
+        match self.rows.get(self.selected?)? {
+            Row::Match(pane_idx, match_idx) => Some(&self.panes[*pane_idx].matches[*match_idx]),
+            _ => None,
+        }
+    }
+
+    /// Moves the selection by `delta` matches, skipping over the
+    /// pane headings
+    fn move_selection(&mut self, delta: isize) {
+        let mut idx = match self.selected {
+            Some(idx) => idx,
+            None => return,
+        };
+        let step: isize = if delta < 0 { -1 } else { 1 };
+        for _ in 0..delta.abs() {
+            let mut candidate = idx as isize;
+            loop {
+                candidate += step;
+                if candidate < 0 || candidate as usize >= self.rows.len() {
+                    break;
+                }
+                if let Row::Match(..) = self.rows[candidate as usize] {
+                    idx = candidate as usize;
+                    break;
+                }
+            }
+        }
+        self.selected.replace(idx);
+    }
+
+    fn select_row(&mut self, row: usize) -> bool {
+        match self.rows.get(row) {
+            Some(Row::Match(..)) => {
+                self.selected.replace(row);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn cycle_mode(&mut self) {
+        self.pattern = match &self.pattern {
+            Pattern::CaseSensitiveString(s) => Pattern::CaseInSensitiveString(s.clone()),
+            Pattern::CaseInSensitiveString(s) => Pattern::Regex(s.clone()),
+            Pattern::Regex(s) => Pattern::CaseSensitiveString(s.clone()),
+        };
+    }
+
+    fn edit_pattern<F: FnOnce(&mut String)>(&mut self, f: F) {
+        match &mut self.pattern {
+            Pattern::CaseSensitiveString(s)
+            | Pattern::CaseInSensitiveString(s)
+            | Pattern::Regex(s) => f(s),
+        }
+    }
+
+    fn row_text(&self, row: &Row) -> String {
+        match row {
+            Row::Pane(pane_idx) => {
+                let pane = &self.panes[*pane_idx];
+                let n = pane.matches.len() + pane.omitted;
+                format!(
+                    "{} ({} match{})",
+                    pane.label,
+                    n,
+                    if n == 1 { "" } else { "es" }
+                )
+            }
+            Row::Match(pane_idx, match_idx) => {
+                let m = &self.panes[*pane_idx].matches[*match_idx];
+                format!("  {:>6}: {}", m.line_number, m.text)
+            }
+            Row::Omitted(pane_idx) => {
+                format!("  ... {} more", self.panes[*pane_idx].omitted)
+            }
+        }
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let list_rows = size.rows.saturating_sub(HEADER_ROWS).max(1);
+
+        if let Some(selected) = self.selected {
+            if selected < self.top {
+                // Keep the heading of the pane visible where possible
+                self.top = selected.saturating_sub(1);
+            } else if selected >= self.top + list_rows {
+                self.top = selected + 1 - list_rows;
+            }
+        }
+
+        let mode = match &self.pattern {
+            Pattern::CaseSensitiveString(_) => "case-sensitive",
+            Pattern::CaseInSensitiveString(_) => "ignore-case",
+            Pattern::Regex(_) => "regex",
+        };
+        let status = if self.searched.as_ref() != Some(&self.pattern) {
+            "Enter: search, CTRL-R: change mode, Escape: cancel".to_string()
+        } else if self.panes.is_empty() {
+            "No matches".to_string()
+        } else {
+            format!(
+                "{} matches in {} panes.  Enter: jump to match, Escape: cancel",
+                self.num_matches(),
+                self.panes.len()
+            )
+        };
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(format!(
+                "Search all panes ({}): {}\r\n",
+                mode, &*self.pattern
+            )),
+            Change::Text(format!("{}\r\n", status)),
+            Change::AllAttributes(CellAttributes::default()),
+        ];
+
+        for (idx, row) in self.rows.iter().enumerate().skip(self.top).take(list_rows) {
+            let mut text = self.row_text(row);
+            if let Some((byte_idx, _)) = text.char_indices().nth(size.cols) {
+                text.truncate(byte_idx);
+            }
+            let selected = Some(idx) == self.selected;
+            let heading = matches!(row, Row::Pane(_));
+            if selected {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            if heading {
+                changes.push(AttributeChange::Intensity(Intensity::Bold).into());
+            }
+            changes.push(Change::Text(text));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        // Leave the cursor at the end of the pattern
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(
+                format!("Search all panes ({}): {}", mode, &*self.pattern)
+                    .chars()
+                    .count(),
+            ),
+            y: Position::Absolute(0),
+        });
+
+        term.render(&changes)?;
+        term.flush()
+    }
+}
+
+fn jump_to_match(window: &::window::Window, m: &Match) {
+    let pane_id = m.pane_id;
+    let result = m.result;
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            term_window.jump_to_search_result(pane_id, result);
+        }
+        Ok(())
+    });
+}
+
+pub fn global_search(
+    _tab_id: TabId,
+    mut term: TermWizTerminal,
+    mux_window_id: WindowId,
+    window: ::window::Window,
+    pattern: Pattern,
+) -> anyhow::Result<()> {
+    let mut state = State {
+        pattern,
+        searched: None,
+        panes: vec![],
+        rows: vec![],
+        selected: None,
+        top: 0,
+    };
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Search All Panes".to_string())])?;
+
+    let search = |state: &mut State, term: &mut TermWizTerminal| -> termwiz::Result<()> {
+        term.render(&[Change::Text("\r\nSearching...".to_string())])?;
+        term.flush()?;
+        let results = run_search(mux_window_id, state.pattern.clone());
+        state.set_results(results);
+        Ok(())
+    };
+
+    if !state.pattern.is_empty() {
+        search(&mut state, &mut term)?;
+    }
+    state.render(&mut term)?;
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => break,
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }) => {
+                if state.searched.as_ref() != Some(&state.pattern) {
+                    if !state.pattern.is_empty() {
+                        search(&mut state, &mut term)?;
+                    }
+                } else if let Some(m) = state.selected_match() {
+                    jump_to_match(&window, m);
+                    break;
+                }
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('r'),
+                modifiers: Modifiers::CTRL,
+            }) => {
+                state.cycle_mode();
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }) => state.move_selection(-1),
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }) => state.move_selection(1),
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            }) => {
+                let rows = term.get_screen_size()?.rows.saturating_sub(HEADER_ROWS);
+                state.move_selection(-(rows.max(1) as isize));
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            }) => {
+                let rows = term.get_screen_size()?.rows.saturating_sub(HEADER_ROWS);
+                state.move_selection(rows.max(1) as isize);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Backspace,
+                ..
+            }) => {
+                state.edit_pattern(|s| {
+                    s.pop();
+                });
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::NONE,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::SHIFT,
+            }) => {
+                state.edit_pattern(|s| s.push(c));
+            }
+            InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }) => {
+                let row = (y as usize)
+                    .checked_sub(HEADER_ROWS)
+                    .map(|row| row + state.top);
+                if mouse_buttons == MouseButtons::LEFT {
+                    if let Some(row) = row {
+                        if state.select_row(row) {
+                            if let Some(m) = state.selected_match() {
+                                jump_to_match(&window, m);
+                                break;
+                            }
+                        }
+                    }
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL | MouseButtons::WHEEL_POSITIVE {
+                    state.move_selection(-1);
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL {
+                    state.move_selection(1);
+                } else if mouse_buttons != MouseButtons::NONE {
+                    // Treat any other mouse button as cancel
+                    break;
+                }
+            }
+            _ => {}
+        }
+        state.render(&mut term)?;
+    }
+
+    Ok(())
+}
@@ -8,6 +8,7 @@
 use crate::gui::termwindow::{ClipboardHelper, SpawnWhere, TermWindow};
 use anyhow::anyhow;
 use config::configuration;
+use config::i18n::{tr, tr_args};
 use config::keyassignment::{SpawnCommand, SpawnTabDomain};
 use mux::domain::{DomainId, DomainState};
 use mux::tab::TabId;
@@ -96,6 +97,90 @@ fn enumerate_wsl_entries(entries: &mut Vec<Entry>) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Adds an entry for each of the running containers.
+/// A domain is registered for each container so that the entries
+/// can refer to it by name.
+fn enumerate_container_entries(runtimes: &[String], entries: &mut Vec<Entry>) {
+    let mut containers = vec![];
+    for runtime in runtimes {
+        match mux::container::enumerate_containers(runtime) {
+            Ok(names) => {
+                containers.extend(names.into_iter().map(|name| (runtime.clone(), name)));
+            }
+            Err(err) => log::debug!("{:#}", err),
+        }
+    }
+    if containers.is_empty() {
+        return;
+    }
+
+    for (runtime, container) in &containers {
+        entries.push(Entry::Spawn {
+            label: tr_args(
+                "launcher-attach-container",
+                &[
+                    ("runtime", runtime.as_str()),
+                    ("container", container.as_str()),
+                ],
+            ),
+            command: SpawnCommand {
+                domain: SpawnTabDomain::DomainName(mux::container::container_domain_name(
+                    runtime, container,
+                )),
+                ..SpawnCommand::default()
+            },
+            spawn_where: SpawnWhere::NewTab,
+        });
+    }
+
+    // We're running on the launcher's own thread, but domains
+    // can only be registered on the main thread
+    promise::spawn::block_on(promise::spawn::spawn_into_main_thread(async move {
+        for (runtime, container) in containers {
+            if let Err(err) = mux::container::get_or_create_container_domain(&runtime, &container) {
+                log::error!("{:#}", err);
+            }
+        }
+    }));
+}
+
+/// Adds an entry for each of the serial ports that are present.
+/// A domain is registered for each port so that the entries
+/// can refer to it by name.
+fn enumerate_serial_entries(entries: &mut Vec<Entry>) {
+    let ports = match mux::serial::enumerate_serial_ports() {
+        Ok(ports) => ports,
+        Err(err) => {
+            log::debug!("enumerating serial ports: {:#}", err);
+            return;
+        }
+    };
+    if ports.is_empty() {
+        return;
+    }
+
+    for port in &ports {
+        entries.push(Entry::Spawn {
+            label: tr_args("launcher-serial-port", &[("port", port.label().as_str())]),
+            command: SpawnCommand {
+                domain: SpawnTabDomain::DomainName(mux::serial::serial_domain_name(&port.port)),
+                ..SpawnCommand::default()
+            },
+            spawn_where: SpawnWhere::NewTab,
+        });
+    }
+
+    // We're running on the launcher's own thread, but domains
+    // can only be registered on the main thread
+    promise::spawn::block_on(promise::spawn::spawn_into_main_thread(async move {
+        for port in ports {
+            if let Err(err) = mux::serial::get_or_create_serial_domain(&port) {
+                log::error!("{:#}", err);
+            }
+        }
+    }));
+}
+
 pub fn launcher(
     _tab_id: TabId,
     domain_id_of_current_tab: DomainId,
@@ -120,7 +205,7 @@ pub fn launcher(
                 Some(label) => label.to_string(),
                 None => match item.args.as_ref() {
                     Some(args) => args.join(" "),
-                    None => "(default shell)".to_string(),
+                    None => tr("launcher-default-shell"),
                 },
             },
             command: item.clone(),
@@ -135,10 +220,18 @@ pub fn launcher(
         }
     }
 
+    if config.add_containers_to_launch_menu {
+        enumerate_container_entries(&config.container_runtimes, &mut entries);
+    }
+
+    if config.add_serial_ports_to_launch_menu {
+        enumerate_serial_entries(&mut entries);
+    }
+
     for (domain_id, domain_state, domain_name) in &domains {
         let entry = if *domain_state == DomainState::Attached {
             Entry::Spawn {
-                label: format!("New Tab ({})", domain_name),
+                label: tr_args("launcher-new-tab", &[("domain", domain_name.as_str())]),
                 command: SpawnCommand {
                     domain: SpawnTabDomain::DomainName(domain_name.to_string()),
                     ..SpawnCommand::default()
@@ -147,7 +240,7 @@ pub fn launcher(
             }
         } else {
             Entry::Attach {
-                label: format!("Attach {}", domain_name),
+                label: tr_args("launcher-attach", &[("domain", domain_name.as_str())]),
                 domain: *domain_id,
             }
         };
@@ -172,11 +265,7 @@ pub fn launcher(
                 x: Position::Absolute(0),
                 y: Position::Absolute(0),
             },
-            Change::Text(
-                "Select an item and press Enter to launch it.  \
-                Press Escape to cancel\r\n"
-                    .to_string(),
-            ),
+            Change::Text(format!("{}\r\n", tr("launcher-help"))),
             Change::AllAttributes(CellAttributes::default()),
         ];
 
@@ -194,7 +283,7 @@ pub fn launcher(
         term.render(&changes)
     }
 
-    term.render(&[Change::Title("Launcher".to_string())])?;
+    term.render(&[Change::Title(tr("launcher-title"))])?;
     render(active_idx, &entries, &mut term)?;
 
     fn launch(
@@ -6,20 +6,45 @@ use portable_pty::PtySize;
 use std::pin::Pin;
 use std::rc::Rc;
 
+mod bookmarks;
+mod clipboardhistory;
 mod confirm_close_pane;
+mod confirm_paste;
 mod copy;
+mod debuglog;
+mod globalsearch;
 mod launcher;
+mod panechooser;
+mod promptmarks;
+mod quickselect;
+mod scripted;
 mod search;
+mod sftp;
 mod tabnavigator;
+mod updateinfo;
+mod urllist;
 
+pub use bookmarks::bookmarks;
+pub use clipboardhistory::{clipboard_history, clipboard_history_overlay, record_copy};
 pub use confirm_close_pane::confirm_close_pane;
 pub use confirm_close_pane::confirm_close_tab;
 pub use confirm_close_pane::confirm_close_window;
 pub use confirm_close_pane::confirm_quit_program;
+pub use confirm_close_pane::running_processes;
+pub use confirm_paste::{confirm_paste, paste_needs_confirmation};
 pub use copy::CopyOverlay;
+pub use debuglog::debug_log;
+pub use globalsearch::global_search;
 pub use launcher::launcher;
+pub use panechooser::{pane_chooser, pane_label, PaneChoice};
+pub use promptmarks::prompt_marks;
+pub use quickselect::QuickSelectOverlay;
+pub use scripted::{scripted_overlay, OverlayRequest};
 pub use search::SearchOverlay;
+pub use sftp::{remote_cwd, resolve_remote_path, sftp_domain_name, sftp_download, sftp_upload};
 pub use tabnavigator::tab_navigator;
+pub use updateinfo::update_info;
+pub use urllist::{collect_links, url_list};
 
 pub fn start_overlay<T, F>(
     term_window: &TermWindow,
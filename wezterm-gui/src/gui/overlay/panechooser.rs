@@ -0,0 +1,101 @@
+//! Labels each of the panes of a tab so that one of them can be picked,
+//! either by typing its label or by clicking on it, for the commands
+//! such as `wezterm cli swap-pane --interactive` that need a target.
+//! Each pane shows its label in an overlay of its own; whichever of
+//! them receives the input records the outcome for all of them.
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+
+const LABELS: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Holds the outcome once a pane has been picked, or the chooser
+/// has been dismissed
+pub type PaneChoice = Arc<Mutex<Option<Option<PaneId>>>>;
+
+/// Returns the label for the pane at `pane_index` in its tab
+pub fn pane_label(pane_index: usize) -> Option<char> {
+    LABELS.chars().nth(pane_index)
+}
+
+fn render(term: &mut TermWizTerminal, label: char) -> anyhow::Result<()> {
+    let size = term.get_screen_size()?;
+    let left = size.cols.saturating_sub(5) / 2;
+    let top = size.rows.saturating_sub(3) / 2;
+
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+        AttributeChange::Reverse(true).into(),
+    ];
+    let middle = format!("  {}  ", label);
+    for (row, text) in ["     ", middle.as_str(), "     "].iter().enumerate() {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(left),
+            y: Position::Absolute(top + row),
+        });
+        changes.push(Change::Text(text.to_string()));
+    }
+    changes.push(Change::AllAttributes(CellAttributes::default()));
+
+    term.render(&changes)?;
+    term.flush()?;
+    Ok(())
+}
+
+/// Shows `label` in the overlay of the pane `pane_id` until a pane is
+/// picked from `labels`, the chooser is dismissed, or it times out
+pub fn pane_chooser(
+    mut term: TermWizTerminal,
+    pane_id: PaneId,
+    label: char,
+    labels: Vec<(char, PaneId)>,
+    choice: PaneChoice,
+) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+    render(&mut term, label)?;
+
+    let deadline = Instant::now() + mux::PANE_CHOOSER_TIMEOUT;
+    loop {
+        if choice.lock().unwrap().is_some() {
+            break;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            choice.lock().unwrap().get_or_insert(None);
+            break;
+        }
+
+        let wait = (deadline - now).min(Duration::from_millis(100));
+        let picked = match term.poll_input(Some(wait))? {
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                ..
+            })) => Some(
+                labels
+                    .iter()
+                    .find(|(label, _)| *label == c.to_ascii_lowercase())
+                    .map(|(_, pane_id)| *pane_id),
+            ),
+            // Any other key dismisses the chooser
+            Some(InputEvent::Key(_)) => Some(None),
+            Some(InputEvent::Mouse(MouseEvent { mouse_buttons, .. }))
+                if mouse_buttons == MouseButtons::LEFT =>
+            {
+                Some(Some(pane_id))
+            }
+            _ => None,
+        };
+        if let Some(picked) = picked {
+            choice.lock().unwrap().get_or_insert(picked);
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! The prompt marks overlay lists the recent shell prompts of a pane,
+//! as marked up using OSC 133, together with the commands that were
+//! run from them and their exit status.  Selecting one scrolls the
+//! pane to that prompt.
+use crate::gui::termwindow::TermWindow;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::{PromptMark, StableRowIndex};
+
+fn scroll_to_prompt(window: &::window::Window, pane_id: PaneId, row: StableRowIndex) {
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            let mux = Mux::get().unwrap();
+            if let Some(pane) = mux.get_pane(pane_id) {
+                let dims = pane.get_dimensions();
+                term_window.set_viewport(pane_id, Some(row), dims);
+            }
+        }
+        Ok(())
+    });
+}
+
+fn render(
+    active_idx: usize,
+    top: usize,
+    marks: &[PromptMark],
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        Change::Text(
+            "Select a prompt and press Enter to scroll to it.  Press Escape to cancel\r\n"
+                .to_string(),
+        ),
+        Change::AllAttributes(CellAttributes::default()),
+    ];
+
+    if marks.is_empty() {
+        changes.push(Change::Text(
+            "No prompts have been marked; see the shell integration docs\r\n".to_string(),
+        ));
+    }
+
+    for (idx, mark) in marks
+        .iter()
+        .enumerate()
+        .skip(top)
+        .take(size.rows.saturating_sub(1))
+    {
+        let status = match mark.status {
+            Some(0) => "ok".to_string(),
+            Some(status) => status.to_string(),
+            None => "".to_string(),
+        };
+        let command = if mark.command.is_empty() {
+            "(no command)"
+        } else {
+            &mark.command
+        };
+        let mut text = format!(" {:>5}  {}", status, command);
+        if let Some((byte_idx, _)) = text.char_indices().nth(size.cols) {
+            text.truncate(byte_idx);
+        }
+
+        if idx == active_idx {
+            changes.push(AttributeChange::Reverse(true).into());
+        }
+        if mark.status.map(|status| status != 0).unwrap_or(false) {
+            changes.push(AttributeChange::Foreground(AnsiColor::Maroon.into()).into());
+        }
+        changes.push(Change::Text(text));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+        changes.push(Change::Text("\r\n".to_string()));
+    }
+
+    term.render(&changes)?;
+    term.flush()
+}
+
+/// `marks` are expected to be ordered newest first
+pub fn prompt_marks(
+    pane_id: PaneId,
+    mut term: TermWizTerminal,
+    marks: Vec<PromptMark>,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let mut active_idx = 0;
+    let mut top = 0;
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Prompts".to_string())])?;
+
+    loop {
+        let rows = term.get_screen_size()?.rows.saturating_sub(1).max(1);
+        if active_idx < top {
+            top = active_idx;
+        } else if active_idx >= top + rows {
+            top = active_idx + 1 - rows;
+        }
+        render(active_idx, top, &marks, &mut term)?;
+
+        let last = marks.len().saturating_sub(1);
+        match term.poll_input(None) {
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('k'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            }))) => {
+                active_idx = active_idx.saturating_sub(1);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('j'),
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            }))) => {
+                active_idx = (active_idx + 1).min(last);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            }))) => {
+                active_idx = active_idx.saturating_sub(rows);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            }))) => {
+                active_idx = (active_idx + rows).min(last);
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }))) => {
+                if let Some(mark) = marks.get(active_idx) {
+                    scroll_to_prompt(&window, pane_id, mark.start_y);
+                }
+                break;
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))) => break,
+            Ok(Some(InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }))) => {
+                if y > 0 && top + (y as usize) <= marks.len() {
+                    active_idx = top + y as usize - 1;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        scroll_to_prompt(&window, pane_id, marks[active_idx].start_y);
+                        break;
+                    }
+                }
+                if mouse_buttons != MouseButtons::NONE && mouse_buttons != MouseButtons::LEFT {
+                    // Treat any other mouse button as cancel
+                    break;
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,488 @@
+use crate::gui::termwindow::TermWindow;
+use config::keyassignment::{
+    CaptureGroup, KeyAssignment, QuickSelectAction, QuickSelectPattern, ScrollbackEraseMode,
+};
+use mux::domain::DomainId;
+use mux::pane::{Pane, PaneId};
+use mux::renderable::*;
+use mux::Mux;
+use portable_pty::PtySize;
+use rangeset::RangeSet;
+use regex::Regex;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+use termwiz::cell::CellAttributes;
+use termwiz::color::AnsiColor;
+use url::Url;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
+use window::WindowOps;
+
+/// The patterns that are recognized unless
+/// `disable_default_quick_select_patterns` is set
+const DEFAULT_PATTERNS: &[&str] = &[
+    // URLs
+    r#"\b\w+://[^\s<>"'`]*[^\s<>"'`.,;:!?)\]]"#,
+    // Paths
+    r"(?:[.\w\-@~]+)?(?:/[.\w\-@]+)+",
+    // Hashes, such as git commit ids
+    r"\b[0-9a-f]{7,40}\b",
+    // IPv4 addresses
+    r"\b\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}\b",
+    // Numbers of four or more digits
+    r"\b\d{4,}\b",
+];
+
+pub struct QuickSelectOverlay {
+    renderer: RefCell<QuickSelectRenderable>,
+    delegate: Rc<dyn Pane>,
+}
+
+#[derive(Debug)]
+struct QuickSelectMatch {
+    row: StableRowIndex,
+    /// The cells that hold the text
+    range: Range<usize>,
+    label: String,
+    text: String,
+    action: QuickSelectAction,
+}
+
+struct CompiledPattern {
+    regex: Regex,
+    group: Option<CaptureGroup>,
+    action: QuickSelectAction,
+}
+
+struct QuickSelectRenderable {
+    delegate: Rc<dyn Pane>,
+    patterns: Vec<CompiledPattern>,
+    alphabet: Vec<char>,
+    matches: Vec<QuickSelectMatch>,
+    /// The portion of a label that has been typed so far
+    typed: String,
+    viewport: Option<StableRowIndex>,
+    dirty: RangeSet<StableRowIndex>,
+    /// We use this to cancel ourselves later
+    window: ::window::Window,
+}
+
+impl QuickSelectOverlay {
+    pub fn with_pane(term_window: &TermWindow, pane: &Rc<dyn Pane>) -> Rc<dyn Pane> {
+        let config = config::configuration();
+
+        let mut patterns = vec![];
+        let defaults = DEFAULT_PATTERNS.iter().map(|regex| QuickSelectPattern {
+            regex: regex.to_string(),
+            group: None,
+            action: QuickSelectAction::default(),
+        });
+        let all_patterns: Vec<QuickSelectPattern> = if config.disable_default_quick_select_patterns
+        {
+            config.quick_select_patterns.clone()
+        } else {
+            config
+                .quick_select_patterns
+                .iter()
+                .cloned()
+                .chain(defaults)
+                .collect()
+        };
+        for pattern in all_patterns {
+            match Regex::new(&pattern.regex) {
+                Ok(regex) => patterns.push(CompiledPattern {
+                    regex,
+                    group: pattern.group,
+                    action: pattern.action,
+                }),
+                Err(err) => log::error!("invalid quick select pattern {}: {}", pattern.regex, err),
+            }
+        }
+
+        let mut alphabet = vec![];
+        for c in config.quick_select_alphabet.chars() {
+            if !alphabet.contains(&c) {
+                alphabet.push(c);
+            }
+        }
+        if alphabet.len() < 2 {
+            log::error!("quick_select_alphabet must have at least two distinct characters");
+            alphabet = "asdfqwerzxcvjklmiuopghtybn".chars().collect();
+        }
+
+        let mut renderer = QuickSelectRenderable {
+            delegate: Rc::clone(pane),
+            patterns,
+            alphabet,
+            matches: vec![],
+            typed: String::new(),
+            viewport: term_window.get_viewport(pane.pane_id()),
+            dirty: RangeSet::default(),
+            window: term_window.window.clone().unwrap(),
+        };
+        renderer.compute_matches();
+
+        Rc::new(QuickSelectOverlay {
+            renderer: RefCell::new(renderer),
+            delegate: Rc::clone(pane),
+        })
+    }
+
+    pub fn viewport_changed(&self, viewport: Option<StableRowIndex>) {
+        let mut r = self.renderer.borrow_mut();
+        if r.viewport != viewport {
+            r.viewport = viewport;
+            r.compute_matches();
+        }
+    }
+}
+
+/// Returns the labels for `count` items; they all have the same length
+/// so that no label is a prefix of another
+fn compute_labels(alphabet: &[char], count: usize) -> Vec<String> {
+    let mut len = 1;
+    let mut capacity = alphabet.len();
+    while capacity < count {
+        len += 1;
+        capacity = capacity.saturating_mul(alphabet.len());
+    }
+
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![alphabet[0]; len];
+            for slot in label.iter_mut().rev() {
+                *slot = alphabet[n % alphabet.len()];
+                n /= alphabet.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+impl QuickSelectRenderable {
+    fn viewport_rows(&self) -> Range<StableRowIndex> {
+        let dims = self.delegate.get_dimensions();
+        let top = self.viewport.unwrap_or(dims.physical_top);
+        top..top + dims.viewport_rows as StableRowIndex
+    }
+
+    fn compute_matches(&mut self) {
+        let rows = self.viewport_rows();
+        for m in &self.matches {
+            self.dirty.add(m.row);
+        }
+        self.matches.clear();
+        self.typed.clear();
+
+        struct Found {
+            row: StableRowIndex,
+            range: Range<usize>,
+            text: String,
+            action: QuickSelectAction,
+        }
+        let mut found = vec![];
+
+        let (top, lines) = self.delegate.get_lines(rows);
+        for (idx, line) in lines.iter().enumerate() {
+            let row = top + idx as StableRowIndex;
+
+            // The text of the line, along with the byte offset and cell
+            // index of each cell that it is composed of
+            let mut text = String::new();
+            let mut cells = vec![];
+            for (cell_idx, cell) in line.visible_cells() {
+                cells.push((text.len(), cell_idx));
+                text.push_str(cell.str());
+            }
+            cells.push((text.len(), line.cells().len()));
+            let cell_at = |byte: usize| match cells.binary_search_by(|(b, _)| b.cmp(&byte)) {
+                Ok(i) => cells[i].1,
+                Err(i) => cells[i.saturating_sub(1)].1,
+            };
+
+            // Earlier patterns take precedence where matches overlap
+            let mut claimed: Vec<Range<usize>> = vec![];
+            for pattern in &self.patterns {
+                for caps in pattern.regex.captures_iter(&text) {
+                    let m = match &pattern.group {
+                        None => caps.get(0),
+                        Some(CaptureGroup::Index(n)) => caps.get(*n),
+                        Some(CaptureGroup::Name(name)) => caps.name(name),
+                    };
+                    let m = match m {
+                        Some(m) if !m.as_str().is_empty() => m,
+                        _ => continue,
+                    };
+                    if claimed
+                        .iter()
+                        .any(|r| r.start < m.end() && m.start() < r.end)
+                    {
+                        continue;
+                    }
+                    claimed.push(m.start()..m.end());
+                    found.push(Found {
+                        row,
+                        range: cell_at(m.start())..cell_at(m.end()),
+                        text: m.as_str().to_string(),
+                        action: pattern.action.clone(),
+                    });
+                }
+            }
+        }
+
+        // Label from the bottom up, as the most recent output is usually
+        // the most interesting; the same text gets the same label
+        let mut unique: Vec<&str> = vec![];
+        for f in found.iter().rev() {
+            if !unique.contains(&f.text.as_str()) {
+                unique.push(&f.text);
+            }
+        }
+        let labels: HashMap<&str, String> = unique
+            .iter()
+            .cloned()
+            .zip(compute_labels(&self.alphabet, unique.len()))
+            .collect();
+
+        let matches: Vec<QuickSelectMatch> = found
+            .iter()
+            .map(|f| QuickSelectMatch {
+                row: f.row,
+                range: f.range.clone(),
+                label: labels[f.text.as_str()].clone(),
+                text: f.text.clone(),
+                action: f.action.clone(),
+            })
+            .collect();
+        self.matches = matches;
+
+        for m in &self.matches {
+            self.dirty.add(m.row);
+        }
+        self.window.invalidate();
+    }
+
+    fn close(&self) {
+        TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
+    }
+
+    /// Adds `c` to the typed label, acting on the match with that label
+    /// once it has been typed in full
+    fn type_char(&mut self, c: char) {
+        self.typed.push(c);
+        for m in &self.matches {
+            self.dirty.add(m.row);
+        }
+
+        if let Some(m) = self.matches.iter().find(|m| m.label == self.typed) {
+            self.perform_action(m.text.clone(), m.action.clone());
+            self.close();
+        } else if !self
+            .matches
+            .iter()
+            .any(|m| m.label.starts_with(&self.typed))
+        {
+            // Not a valid label; start over
+            self.typed.clear();
+        }
+    }
+
+    fn backspace(&mut self) {
+        self.typed.pop();
+        for m in &self.matches {
+            self.dirty.add(m.row);
+        }
+    }
+
+    fn perform_action(&self, text: String, action: QuickSelectAction) {
+        let pane_id = self.delegate.pane_id();
+        match action {
+            QuickSelectAction::CopyTo(dest) => {
+                self.window.apply(move |term_window, _window| {
+                    if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                        if let Some(pane) = Mux::get().unwrap().get_pane(pane_id) {
+                            term_window.copy_from_pane(&pane, "quickselect", dest, text.clone());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+            QuickSelectAction::Paste => {
+                if let Err(err) = self.delegate.trickle_paste(text) {
+                    log::error!("quick select paste: {:#}", err);
+                }
+            }
+            QuickSelectAction::OpenUrl => {
+                // Spawn the `open` call outside of the context of our
+                // window loop, as for OpenLinkAtMouseCursor
+                promise::spawn::spawn(async move {
+                    log::info!("opening {}", text);
+                    if let Err(err) = open::that(&text) {
+                        log::error!("failed to open {}: {:?}", text, err);
+                    }
+                })
+                .detach();
+            }
+            QuickSelectAction::SpawnCommandInNewTab(mut cmd) => {
+                cmd.args.get_or_insert_with(Vec::new).push(text);
+                let assignment = KeyAssignment::SpawnCommandInNewTab(cmd);
+                self.window.apply(move |term_window, _window| {
+                    if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                        let mux = Mux::get().unwrap();
+                        if let Some(pane) = mux.get_pane(pane_id) {
+                            term_window.perform_key_assignment(&pane, &assignment)?;
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+impl Pane for QuickSelectOverlay {
+    fn pane_id(&self) -> PaneId {
+        self.delegate.pane_id()
+    }
+
+    fn get_title(&self) -> String {
+        format!("Quick select: {}", self.delegate.get_title())
+    }
+
+    fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
+        anyhow::bail!("ignoring paste while quick selecting");
+    }
+
+    fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+        panic!("do not call reader on QuickSelectOverlay bar tab instance");
+    }
+
+    fn writer(&self) -> RefMut<dyn std::io::Write> {
+        self.delegate.writer()
+    }
+
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        self.delegate.resize(size)
+    }
+
+    fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        match (key, mods) {
+            (KeyCode::Escape, KeyModifiers::NONE)
+            | (KeyCode::Char('c'), KeyModifiers::CTRL)
+            | (KeyCode::Char('g'), KeyModifiers::CTRL) => self.renderer.borrow().close(),
+            (KeyCode::Backspace, KeyModifiers::NONE) => self.renderer.borrow_mut().backspace(),
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                self.renderer.borrow_mut().type_char(c.to_ascii_lowercase())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn mouse_event(&self, _event: MouseEvent) -> anyhow::Result<()> {
+        anyhow::bail!("ignoring mouse while quick selecting");
+    }
+
+    fn advance_bytes(&self, buf: &[u8]) {
+        self.delegate.advance_bytes(buf)
+    }
+
+    fn is_dead(&self) -> bool {
+        self.delegate.is_dead()
+    }
+
+    fn palette(&self) -> ColorPalette {
+        self.delegate.palette()
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.delegate.domain_id()
+    }
+
+    fn erase_scrollback(&self, erase_mode: ScrollbackEraseMode) {
+        self.delegate.erase_scrollback(erase_mode)
+    }
+
+    fn is_mouse_grabbed(&self) -> bool {
+        false
+    }
+
+    fn is_alt_screen_active(&self) -> bool {
+        false
+    }
+
+    fn set_clipboard(&self, clipboard: &Arc<dyn Clipboard>) {
+        self.delegate.set_clipboard(clipboard)
+    }
+
+    fn get_current_working_dir(&self) -> Option<Url> {
+        self.delegate.get_current_working_dir()
+    }
+
+    fn get_cursor_position(&self) -> StableCursorPosition {
+        let mut cursor = self.delegate.get_cursor_position();
+        cursor.visibility = termwiz::surface::CursorVisibility::Hidden;
+        cursor
+    }
+
+    fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
+        let mut dirty = self.delegate.get_dirty_lines(lines.clone());
+        dirty.add_set(&self.renderer.borrow().dirty);
+        dirty.intersection_with_range(lines)
+    }
+
+    fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let mut renderer = self.renderer.borrow_mut();
+        let (top, mut lines) = self.delegate.get_lines(lines);
+
+        let highlight = CellAttributes::default()
+            .set_background(AnsiColor::Navy)
+            .set_foreground(AnsiColor::White)
+            .clone();
+        let label_attrs = CellAttributes::default()
+            .set_background(AnsiColor::Yellow)
+            .set_foreground(AnsiColor::Black)
+            .set_intensity(termwiz::cell::Intensity::Bold)
+            .clone();
+
+        for (idx, line) in lines.iter_mut().enumerate() {
+            let stable_idx = idx as StableRowIndex + top;
+            renderer.dirty.remove(stable_idx);
+
+            for m in renderer.matches.iter().filter(|m| m.row == stable_idx) {
+                for cell_idx in m.range.clone() {
+                    if let Some(cell) = line.cells_mut_for_attr_changes_only().get_mut(cell_idx) {
+                        cell.set_attrs(highlight.clone());
+                    }
+                }
+                // Show the remainder of the label while it is being typed
+                if let Some(rest) = m.label.strip_prefix(renderer.typed.as_str()) {
+                    line.overlay_text_with_attribute(m.range.start, rest, label_attrs.clone());
+                }
+            }
+        }
+
+        (top, lines)
+    }
+
+    fn get_dimensions(&self) -> RenderableDimensions {
+        self.delegate.get_dimensions()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        let alphabet = ['a', 'b', 'c'];
+        assert_eq!(compute_labels(&alphabet, 2), vec!["a", "b"]);
+        assert_eq!(compute_labels(&alphabet, 4), vec!["aa", "ab", "ac", "ba"]);
+        assert_eq!(compute_labels(&alphabet, 10).len(), 10);
+        assert_eq!(compute_labels(&alphabet, 10)[9], "baa");
+    }
+}
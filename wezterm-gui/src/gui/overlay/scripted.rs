@@ -0,0 +1,42 @@
+//! The overlays that lua code makes with `wezterm.gui.make_overlay`.
+//! The lua callback runs on the main thread, while the overlay runs in
+//! a thread of its own like the other overlays, so the callback sends
+//! it the changes to draw and it sends back the input that it receives.
+use mux::termwiztermtab::TermWizTerminal;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+use termwiz::input::InputEvent;
+use termwiz::surface::Change;
+use termwiz::terminal::Terminal;
+
+pub enum OverlayRequest {
+    Render(Vec<Change>),
+    Close,
+}
+
+pub fn scripted_overlay(
+    mut term: TermWizTerminal,
+    requests: Receiver<OverlayRequest>,
+    events: smol::channel::Sender<InputEvent>,
+) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+
+    loop {
+        loop {
+            match requests.try_recv() {
+                Ok(OverlayRequest::Render(changes)) => {
+                    term.render(&changes)?;
+                    term.flush()?;
+                }
+                Ok(OverlayRequest::Close) | Err(TryRecvError::Disconnected) => return Ok(()),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        if let Some(event) = term.poll_input(Some(Duration::from_millis(20)))? {
+            // The callback may have stopped reading events; the
+            // overlay stays up until it is asked to close
+            events.try_send(event).ok();
+        }
+    }
+}
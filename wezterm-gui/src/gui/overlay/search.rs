@@ -32,7 +32,13 @@ struct MatchResult {
 struct SearchRenderable {
     delegate: Rc<dyn Pane>,
     /// The text that the user entered
-    pattern: Pattern,
+    pattern: String,
+    /// Whether the pattern matches case sensitively
+    case_sensitive: bool,
+    /// Whether the pattern is a regular expression
+    regex: bool,
+    /// Whether the pattern only matches whole words
+    whole_word: bool,
     /// The most recently queried set of matches
     results: Vec<SearchResult>,
     by_line: HashMap<StableRowIndex, Vec<MatchResult>>,
@@ -45,6 +51,21 @@ struct SearchRenderable {
     width: usize,
     height: usize,
 
+    /// Incremented for each search, so that the results of a search
+    /// that has been superseded can be ignored
+    generation: usize,
+    search_in_flight: bool,
+    /// The rows that changed while a search was in flight, which
+    /// are to be searched again once it completes
+    pending_refresh: Option<Range<StableRowIndex>>,
+    /// The physical_top and scrollback_top of the pane when we last
+    /// searched, used to notice when new output arrives
+    searched_tops: (StableRowIndex, StableRowIndex),
+
+    /// When true, Enter sends the selected match to the pane
+    /// rather than moving to the prior match
+    send_on_enter: bool,
+
     /// We use this to cancel ourselves later
     window: ::window::Window,
 }
@@ -54,14 +75,42 @@ impl SearchOverlay {
         term_window: &TermWindow,
         pane: &Rc<dyn Pane>,
         pattern: Pattern,
+    ) -> Rc<dyn Pane> {
+        Self::new(term_window, pane, pattern, false)
+    }
+
+    /// Creates a search overlay in which Enter sends the text of the
+    /// selected match to the pane, for example to pick a commit hash
+    /// from earlier output and have it typed at the prompt
+    pub fn with_pane_for_send(
+        term_window: &TermWindow,
+        pane: &Rc<dyn Pane>,
+        pattern: Pattern,
+    ) -> Rc<dyn Pane> {
+        Self::new(term_window, pane, pattern, true)
+    }
+
+    fn new(
+        term_window: &TermWindow,
+        pane: &Rc<dyn Pane>,
+        pattern: Pattern,
+        send_on_enter: bool,
     ) -> Rc<dyn Pane> {
         let viewport = term_window.get_viewport(pane.pane_id());
         let dims = pane.get_dimensions();
 
         let window = term_window.window.clone().unwrap();
+        let (case_sensitive, regex) = match &pattern {
+            Pattern::CaseSensitiveString(_) => (true, false),
+            Pattern::CaseInSensitiveString(_) => (false, false),
+            Pattern::Regex(_) => (true, true),
+        };
         let mut renderer = SearchRenderable {
             delegate: Rc::clone(pane),
-            pattern,
+            pattern: pattern.to_string(),
+            case_sensitive,
+            regex,
+            whole_word: false,
             results: vec![],
             by_line: HashMap::new(),
             dirty_results: RangeSet::default(),
@@ -71,6 +120,11 @@ impl SearchOverlay {
             result_pos: None,
             width: dims.cols,
             height: dims.viewport_rows,
+            generation: 0,
+            search_in_flight: false,
+            pending_refresh: None,
+            searched_tops: (dims.physical_top, dims.scrollback_top),
+            send_on_enter,
         };
 
         let search_row = renderer.compute_search_row();
@@ -83,6 +137,21 @@ impl SearchOverlay {
         })
     }
 
+    /// Closes the overlay and sends the text of the selected match,
+    /// or of its first capture group when the pattern has one, to
+    /// the pane as though it had been pasted
+    fn send_selected_match(&self) -> anyhow::Result<()> {
+        let text = {
+            let r = self.renderer.borrow();
+            match r.result_pos.and_then(|pos| r.results.get(pos)) {
+                Some(result) => r.match_text(result),
+                None => return Ok(()),
+            }
+        };
+        self.renderer.borrow().close();
+        self.delegate.trickle_paste(text)
+    }
+
     pub fn viewport_changed(&self, viewport: Option<StableRowIndex>) {
         let mut render = self.renderer.borrow_mut();
         if render.viewport != viewport {
@@ -129,6 +198,10 @@ impl Pane for SearchOverlay {
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
         match (key, mods) {
             (KeyCode::Escape, KeyModifiers::NONE) => self.renderer.borrow().close(),
+            (KeyCode::Enter, KeyModifiers::ALT) => self.send_selected_match()?,
+            (KeyCode::Enter, KeyModifiers::NONE) if self.renderer.borrow().send_on_enter => {
+                self.send_selected_match()?
+            }
             (KeyCode::UpArrow, KeyModifiers::NONE)
             | (KeyCode::Enter, KeyModifiers::NONE)
             | (KeyCode::Char('p'), KeyModifiers::CTRL) => {
@@ -193,12 +266,31 @@ impl Pane for SearchOverlay {
             (KeyCode::Char('r'), KeyModifiers::CTRL) => {
                 // CTRL-r cycles through pattern match types
                 let mut r = self.renderer.borrow_mut();
-                let pattern = match &r.pattern {
-                    Pattern::CaseSensitiveString(s) => Pattern::CaseInSensitiveString(s.clone()),
-                    Pattern::CaseInSensitiveString(s) => Pattern::Regex(s.clone()),
-                    Pattern::Regex(s) => Pattern::CaseSensitiveString(s.clone()),
+                let (case_sensitive, regex) = match (r.case_sensitive, r.regex) {
+                    (true, false) => (false, false),
+                    (false, false) => (true, true),
+                    _ => (true, false),
                 };
-                r.pattern = pattern;
+                r.case_sensitive = case_sensitive;
+                r.regex = regex;
+                r.update_search();
+            }
+            (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                // ALT-c toggles case sensitivity
+                let mut r = self.renderer.borrow_mut();
+                r.case_sensitive = !r.case_sensitive;
+                r.update_search();
+            }
+            (KeyCode::Char('r'), KeyModifiers::ALT) => {
+                // ALT-r toggles regex matching
+                let mut r = self.renderer.borrow_mut();
+                r.regex = !r.regex;
+                r.update_search();
+            }
+            (KeyCode::Char('w'), KeyModifiers::ALT) => {
+                // ALT-w toggles whole word matching
+                let mut r = self.renderer.borrow_mut();
+                r.whole_word = !r.whole_word;
                 r.update_search();
             }
             (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
@@ -270,7 +362,9 @@ impl Pane for SearchOverlay {
 
     fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
         let mut dirty = self.delegate.get_dirty_lines(lines.clone());
-        dirty.add_set(&self.renderer.borrow().dirty_results);
+        let mut renderer = self.renderer.borrow_mut();
+        renderer.check_for_new_output(!dirty.is_empty());
+        dirty.add_set(&renderer.dirty_results);
         dirty.intersection_with_range(lines)
     }
 
@@ -292,19 +386,34 @@ impl Pane for SearchOverlay {
                 // Replace with search UI
                 let rev = CellAttributes::default().set_reverse(true).clone();
                 line.fill_range(0..dims.cols, &Cell::new(' ', rev.clone()));
-                let mode = &match renderer.pattern {
-                    Pattern::CaseSensitiveString(_) => "case-sensitive",
-                    Pattern::CaseInSensitiveString(_) => "ignore-case",
-                    Pattern::Regex(_) => "regex",
+                let mut modes = vec![if renderer.case_sensitive {
+                    "case-sensitive"
+                } else {
+                    "ignore-case"
+                }];
+                if renderer.regex {
+                    modes.push("regex");
+                }
+                if renderer.whole_word {
+                    modes.push("whole-word");
+                }
+                let status = match (renderer.result_pos, renderer.results.len()) {
+                    (_, 0) => "no matches".to_string(),
+                    (Some(pos), total) => format!("match {} of {}", pos + 1, total),
+                    (None, total) => format!("{} matches", total),
                 };
                 line.overlay_text_with_attribute(
                     0,
                     &format!(
-                        "Search: {} ({}/{} matches. {})",
-                        *renderer.pattern,
-                        renderer.result_pos.map(|x| x + 1).unwrap_or(0),
-                        renderer.results.len(),
-                        mode
+                        "{}: {} ({}. {})",
+                        if renderer.send_on_enter {
+                            "Send"
+                        } else {
+                            "Search"
+                        },
+                        renderer.pattern,
+                        status,
+                        modes.join(", ")
                     ),
                     rev,
                 );
@@ -348,6 +457,38 @@ impl SearchRenderable {
         bottom
     }
 
+    /// Returns the text of a match; when the pattern is a regex that
+    /// has capture groups, only the text of the first group is returned
+    fn match_text(&self, result: &SearchResult) -> String {
+        let (top, lines) = self.delegate.get_lines(result.start_y..result.end_y + 1);
+        let mut text = String::new();
+        for (idx, line) in lines.iter().enumerate() {
+            let row = top + idx as StableRowIndex;
+            let start = if row == result.start_y {
+                result.start_x
+            } else {
+                0
+            };
+            let end = if row == result.end_y {
+                result.end_x
+            } else {
+                usize::max_value()
+            };
+            // Matches only span rows that were wrapped, so the rows are
+            // simply concatenated
+            text.push_str(&line.columns_as_str(start..end));
+        }
+
+        if let Pattern::Regex(pattern) = self.effective_pattern() {
+            if let Ok(re) = regex::Regex::new(&pattern) {
+                if let Some(group) = re.captures(&text).and_then(|c| c.get(1)) {
+                    return group.as_str().to_string();
+                }
+            }
+        }
+        text
+    }
+
     fn close(&self) {
         TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
     }
@@ -407,6 +548,36 @@ impl SearchRenderable {
         }
     }
 
+    /// Computes the pattern to search for from the text and the
+    /// matching modes.  Whole word matching, and case insensitive
+    /// regex matching, are expressed as a regex.
+    fn effective_pattern(&self) -> Pattern {
+        if !self.regex && !self.whole_word {
+            return if self.case_sensitive {
+                Pattern::CaseSensitiveString(self.pattern.clone())
+            } else {
+                Pattern::CaseInSensitiveString(self.pattern.clone())
+            };
+        }
+
+        let body = if self.regex {
+            format!("(?:{})", self.pattern)
+        } else {
+            regex::escape(&self.pattern)
+        };
+        Pattern::Regex(format!(
+            "{}{}",
+            if self.case_sensitive { "" } else { "(?i)" },
+            if self.whole_word {
+                format!(r"\b{}\b", body)
+            } else {
+                body
+            }
+        ))
+    }
+
+    /// Called when the pattern or matching mode changes; searches
+    /// afresh and selects the bottom-most match
     fn update_search(&mut self) {
         for idx in self.by_line.keys() {
             self.dirty_results.add(*idx);
@@ -418,46 +589,159 @@ impl SearchRenderable {
         self.results.clear();
         self.by_line.clear();
         self.result_pos.take();
+        self.pending_refresh = None;
 
         let bar_pos = self.compute_search_row();
         self.dirty_results.add(bar_pos);
 
         if !self.pattern.is_empty() {
-            let pane: Rc<dyn Pane> = self.delegate.clone();
-            let window = self.window.clone();
-            let pattern = self.pattern.clone();
-            promise::spawn::spawn(async move {
-                let mut results = pane.search(pattern).await?;
-                results.sort();
-
-                let pane_id = pane.pane_id();
-                let mut results = Some(results);
-                window.apply(move |term_window, _window| {
-                    let term_window = term_window
-                        .downcast_mut::<TermWindow>()
-                        .expect("to be TermWindow");
-                    let state = term_window.pane_state(pane_id);
-                    if let Some(overlay) = state.overlay.as_ref() {
-                        if let Some(search_overlay) = overlay.downcast_ref::<SearchOverlay>() {
-                            let mut r = search_overlay.renderer.borrow_mut();
-                            r.results = results.take().unwrap();
-                            r.recompute_results();
-                            let num_results = r.results.len();
-
-                            if !r.results.is_empty() {
-                                r.activate_match_number(num_results - 1);
-                            } else {
-                                r.set_viewport(None);
-                                r.clear_selection();
-                            }
+            self.start_search(None);
+        } else {
+            // Ignore the results of any search that is still in flight
+            self.generation += 1;
+            self.search_in_flight = false;
+            self.set_viewport(None);
+            self.clear_selection();
+        }
+    }
+
+    /// Called when the pane may have changed; searches the rows that
+    /// may have changed again, so that the matches reflect any newly
+    /// arrived output.
+    fn check_for_new_output(&mut self, lines_changed: bool) {
+        let dims = self.delegate.get_dimensions();
+        if dims.cols != self.width || dims.viewport_rows != self.height {
+            // check_for_resize will search everything again
+            return;
+        }
+        let tops = (dims.physical_top, dims.scrollback_top);
+        if !lines_changed && tops == self.searched_tops {
+            return;
+        }
+        let (physical_top, scrollback_top) = std::mem::replace(&mut self.searched_tops, tops);
+        if self.pattern.is_empty() {
+            return;
+        }
+
+        // Output can only change the rows of the screen; the rows above
+        // the physical_top that we last saw are in the scrollback, and
+        // can only scroll out of it.  If the screen moved up then the
+        // scrollback was replaced, so everything is searched again.
+        let start = if dims.physical_top < physical_top || dims.scrollback_top < scrollback_top {
+            dims.scrollback_top
+        } else {
+            physical_top
+        };
+        let changed = start..dims.physical_top + dims.viewport_rows as StableRowIndex;
+
+        if self.search_in_flight {
+            self.pending_refresh = Some(match self.pending_refresh.take() {
+                Some(pending) => pending.start.min(changed.start)..pending.end.max(changed.end),
+                None => changed,
+            });
+        } else {
+            self.start_search(Some(changed));
+        }
+    }
+
+    /// Starts an asynchronous search.  When `refresh` is None, all of
+    /// the rows are searched and the bottom-most match is selected.
+    /// Otherwise only the rows in `refresh` are searched, and their
+    /// matches replace the ones that we had for those rows; the
+    /// currently selected match remains selected if it is still present.
+    fn start_search(&mut self, refresh: Option<Range<StableRowIndex>>) {
+        self.generation += 1;
+        self.search_in_flight = true;
+        let generation = self.generation;
+
+        let range = match &refresh {
+            Some(range) => range.clone(),
+            None => {
+                let dims = self.delegate.get_dimensions();
+                dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex
+            }
+        };
+        let pane: Rc<dyn Pane> = self.delegate.clone();
+        let window = self.window.clone();
+        let pattern = self.effective_pattern();
+        promise::spawn::spawn(async move {
+            let mut results = pane.search(pattern, range).await?;
+            results.sort();
+
+            let pane_id = pane.pane_id();
+            let mut results = Some(results);
+            window.apply(move |term_window, _window| {
+                let term_window = term_window
+                    .downcast_mut::<TermWindow>()
+                    .expect("to be TermWindow");
+                let state = term_window.pane_state(pane_id);
+                if let Some(overlay) = state.overlay.as_ref() {
+                    if let Some(search_overlay) = overlay.downcast_ref::<SearchOverlay>() {
+                        let mut r = search_overlay.renderer.borrow_mut();
+                        if r.generation != generation {
+                            // Superseded by a later search
+                            return Ok(());
+                        }
+                        r.search_in_flight = false;
+                        r.apply_results(results.take().unwrap(), refresh.clone());
+                        if let Some(pending) = r.pending_refresh.take() {
+                            r.start_search(Some(pending));
                         }
                     }
-                    Ok(())
-                });
-                anyhow::Result::<()>::Ok(())
-            })
-            .detach();
+                }
+                Ok(())
+            });
+            anyhow::Result::<()>::Ok(())
+        })
+        .detach();
+    }
+
+    fn apply_results(
+        &mut self,
+        results: Vec<SearchResult>,
+        refresh: Option<Range<StableRowIndex>>,
+    ) {
+        let prior = self
+            .result_pos
+            .and_then(|pos| self.results.get(pos).cloned());
+
+        for idx in self.by_line.keys() {
+            self.dirty_results.add(*idx);
+        }
+        self.by_line.clear();
+        let bar_pos = self.compute_search_row();
+        self.dirty_results.add(bar_pos);
+
+        match &refresh {
+            None => self.results = results,
+            Some(changed) => {
+                // Keep the matches from the rows that were not searched
+                // again, unless they have scrolled out of the scrollback
+                let scrollback_top = self.delegate.get_dimensions().scrollback_top;
+                let searched =
+                    |r: &SearchResult| r.end_y >= changed.start && r.start_y < changed.end;
+                self.results
+                    .retain(|r| r.start_y >= scrollback_top && !searched(r));
+                self.results.extend(results.into_iter().filter(searched));
+                self.results.sort();
+            }
+        }
+        self.recompute_results();
+
+        if refresh.is_some() {
+            // Keep the current match selected, without moving the viewport
+            if let Some(pos) = prior.and_then(|prior| self.results.iter().position(|r| *r == prior))
+            {
+                self.result_pos.replace(pos);
+                return;
+            }
+        }
+
+        let num_results = self.results.len();
+        if num_results > 0 {
+            self.activate_match_number(num_results - 1);
         } else {
+            self.result_pos.take();
             self.set_viewport(None);
             self.clear_selection();
         }
@@ -488,6 +772,7 @@ impl SearchRenderable {
                     y: result.start_y,
                 };
                 selection.start = Some(start);
+                selection.rectangular = false;
                 selection.range = Some(SelectionRange {
                     start,
                     end: SelectionCoordinate {
@@ -0,0 +1,232 @@
+//! Transfers files to and from the remote host of an ssh pane using
+//! the sftp subsystem of its ssh session.  The transfers block, so
+//! they run in the thread of an overlay that shows their progress.
+use super::confirm_close_pane::confirm;
+use anyhow::{anyhow, bail, Context};
+use config::i18n::{tr, tr_args};
+use mux::pane::Pane;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use termwiz::color::ColorAttribute;
+use termwiz::input::InputEvent;
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+
+const CHUNK_SIZE: usize = 128 * 1024;
+
+/// Returns the name of the domain of `pane` if it is an ssh domain
+/// whose session can be used to transfer files
+pub fn sftp_domain_name(pane: &Rc<dyn Pane>) -> Option<String> {
+    let mux = Mux::get().unwrap();
+    let domain = mux.get_domain(pane.domain_id())?;
+    let name = domain.domain_name();
+    if mux::sftp::is_available(name) {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the remote directory that the shell in `pane` last reported
+/// via OSC 7, or the home directory if it hasn't reported one
+pub fn remote_cwd(pane: &Rc<dyn Pane>) -> String {
+    match pane.get_current_working_dir() {
+        Some(url) if url.scheme() == "file" => url.path().to_string(),
+        Some(_) | None => "~".to_string(),
+    }
+}
+
+/// Returns `path` relative to `cwd`, unless it is already absolute
+pub fn resolve_remote_path(cwd: &str, path: &str) -> String {
+    if path.starts_with('/') || path.starts_with('~') {
+        path.to_string()
+    } else {
+        format!("{}/{}", cwd.trim_end_matches('/'), path)
+    }
+}
+
+/// Downloads go to the Downloads directory, if there is one
+fn download_dir() -> PathBuf {
+    let dir = config::HOME_DIR.join("Downloads");
+    if dir.is_dir() {
+        dir
+    } else {
+        config::HOME_DIR.clone()
+    }
+}
+
+fn show_progress(
+    term: &mut TermWizTerminal,
+    name: &str,
+    done: u64,
+    total: u64,
+) -> anyhow::Result<()> {
+    let percent = if total == 0 { 100 } else { done * 100 / total };
+    term.render(&[
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        Change::Text(tr_args(
+            "sftp-progress",
+            &[("file", name), ("percent", &percent.to_string())],
+        )),
+    ])?;
+    term.flush()?;
+    Ok(())
+}
+
+/// Leaves an error on screen until a key is pressed; a successful
+/// transfer simply closes the overlay
+fn finish(mut term: TermWizTerminal, result: anyhow::Result<()>) -> anyhow::Result<()> {
+    if let Err(err) = result {
+        log::error!("sftp transfer failed: {:#}", err);
+        term.render(&[
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(tr_args("error-failed", &[("error", &format!("{:#}", err))])),
+            Change::Text("\r\n\r\n".to_string()),
+            Change::Text(tr("sftp-press-any-key")),
+        ])?;
+        term.flush()?;
+        while let Ok(Some(event)) = term.poll_input(None) {
+            if let InputEvent::Key(_) = event {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn upload_file(
+    term: &mut TermWizTerminal,
+    domain: &str,
+    local: &Path,
+    remote_dir: &str,
+) -> anyhow::Result<()> {
+    let name = local
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("{} is not a file", local.display()))?;
+    let mut file =
+        std::fs::File::open(local).with_context(|| format!("opening {}", local.display()))?;
+    let meta = file.metadata()?;
+    if meta.is_dir() {
+        bail!(
+            "{} is a directory, which cannot be uploaded",
+            local.display()
+        );
+    }
+    let total = meta.len();
+    let remote = format!("{}/{}", remote_dir.trim_end_matches('/'), name);
+
+    let mut offset = 0u64;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        show_progress(term, name, offset, total)?;
+        let len = file.read(&mut buf)?;
+        // An empty file still needs to be created
+        if len == 0 && offset > 0 {
+            break;
+        }
+        mux::sftp::write(domain, &remote, offset, &buf[..len])?;
+        offset += len as u64;
+        if len == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Asks whether to upload `paths` into `remote_dir` on the host of
+/// the ssh domain named `domain`, and uploads them if so
+pub fn sftp_upload(
+    mut term: TermWizTerminal,
+    domain: String,
+    paths: Vec<PathBuf>,
+    remote_dir: String,
+) -> anyhow::Result<()> {
+    let files = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let message = tr_args(
+        "sftp-confirm-upload",
+        &[("files", &files), ("destination", &remote_dir)],
+    );
+    if !confirm(&message, &mut term)? {
+        return Ok(());
+    }
+
+    let result = paths
+        .iter()
+        .try_for_each(|path| upload_file(&mut term, &domain, path, &remote_dir));
+    finish(term, result)
+}
+
+fn download_file(
+    term: &mut TermWizTerminal,
+    domain: &str,
+    remote: &str,
+    local: &Path,
+    name: &str,
+) -> anyhow::Result<()> {
+    let info =
+        mux::sftp::stat(domain, remote)?.ok_or_else(|| anyhow!("{} does not exist", remote))?;
+    if info.is_dir {
+        bail!("{} is a directory, which cannot be downloaded", remote);
+    }
+    let mut file =
+        std::fs::File::create(local).with_context(|| format!("creating {}", local.display()))?;
+
+    let mut offset = 0u64;
+    loop {
+        show_progress(term, name, offset, info.size)?;
+        let data = mux::sftp::read(domain, remote, offset, CHUNK_SIZE)?;
+        if data.is_empty() {
+            break;
+        }
+        file.write_all(&data)?;
+        offset += data.len() as u64;
+    }
+    Ok(())
+}
+
+/// Asks whether to download the file at `remote` from the host of the
+/// ssh domain named `domain`, and downloads it if so
+pub fn sftp_download(
+    mut term: TermWizTerminal,
+    domain: String,
+    remote: String,
+) -> anyhow::Result<()> {
+    let name = match Path::new(&remote)
+        .file_name()
+        .and_then(|name| name.to_str())
+    {
+        Some(name) => name.to_string(),
+        None => return finish(term, Err(anyhow!("{} is not a file", remote))),
+    };
+    let local = download_dir().join(&name);
+    let message = tr_args(
+        "sftp-confirm-download",
+        &[
+            ("file", &remote),
+            ("destination", &local.display().to_string()),
+        ],
+    );
+    if !confirm(&message, &mut term)? {
+        return Ok(());
+    }
+
+    let result = download_file(&mut term, &domain, &remote, &local, &name);
+    finish(term, result)
+}
@@ -0,0 +1,198 @@
+//! Shows what has changed in the most recent release of the configured
+//! update channel, compared with the running version, and offers to
+//! download the package for this system.
+use crate::update::{
+    asset_for_this_system, download_asset, get_changes_since_current, get_release_info,
+};
+use config::{configuration, wezterm_version};
+use mux::termwiztermtab::TermWizTerminal;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Line, Position, Surface};
+use termwiz::terminal::{ScreenSize, Terminal};
+
+/// Renders the markdown into lines that are `cols` wide
+fn render_markdown(markdown: &str, size: ScreenSize) -> Vec<Line> {
+    let mut render = crate::markdown::RenderState::new(size.cols.saturating_sub(2).max(20), size);
+    render.parse_str(markdown);
+    let changes = render.into_changes();
+
+    let rows = 1 + changes
+        .iter()
+        .map(|change| match change {
+            Change::Text(text) => text.matches('\n').count(),
+            _ => 0,
+        })
+        .sum::<usize>();
+    let mut surface = Surface::new(size.cols, rows);
+    surface.add_changes(changes);
+    surface
+        .screen_lines()
+        .into_iter()
+        .map(|line| line.into_owned())
+        .collect()
+}
+
+fn render(
+    header: &str,
+    lines: &[Line],
+    top: usize,
+    footer: &str,
+    term: &mut TermWizTerminal,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    let rows = size.rows.saturating_sub(2);
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        AttributeChange::Reverse(true).into(),
+        Change::Text(header.to_string()),
+        Change::AllAttributes(CellAttributes::default()),
+    ];
+
+    for (idx, line) in lines.iter().skip(top).take(rows).enumerate() {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(idx + 1),
+        });
+        changes.append(&mut line.changes(&CellAttributes::default()));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    }
+
+    changes.push(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Absolute(size.rows.saturating_sub(1)),
+    });
+    changes.push(AttributeChange::Reverse(true).into());
+    changes.push(Change::Text(footer.to_string()));
+    changes.push(Change::AllAttributes(CellAttributes::default()));
+
+    term.render(&changes)?;
+    term.flush()
+}
+
+fn wait_for_key(term: &mut TermWizTerminal) -> anyhow::Result<()> {
+    while let Some(event) = term.poll_input(None)? {
+        if let InputEvent::Key(_) = event {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn update_info(mut term: TermWizTerminal) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+    term.render(&[
+        Change::Title("Update".to_string()),
+        Change::Text("Checking for updates...".to_string()),
+    ])?;
+    term.flush()?;
+
+    let config = configuration();
+    let channel = config.update_channel;
+    let settings = config.current_update_channel_settings();
+
+    let release = match get_release_info(channel) {
+        Ok(release) => release,
+        Err(err) => {
+            term.render(&[Change::Text(format!(
+                "\r\n{:#}\r\nPress any key to close",
+                err
+            ))])?;
+            term.flush()?;
+            return wait_for_key(&mut term);
+        }
+    };
+
+    let newer = release.is_newer(channel);
+    let header = if newer {
+        format!(
+            "{} is available from the {:?} channel; this is {}",
+            release.tag_name,
+            channel,
+            wezterm_version()
+        )
+    } else {
+        format!(
+            "{} is up to date with the {:?} channel",
+            wezterm_version(),
+            channel
+        )
+    };
+    let markdown = if newer {
+        get_changes_since_current(&release, channel)
+            .unwrap_or_else(|err| format!("Unable to fetch the changelog: {:#}", err))
+    } else {
+        String::new()
+    };
+    let lines = render_markdown(&markdown, term.get_screen_size()?);
+    let asset = if newer {
+        asset_for_this_system(&release)
+    } else {
+        None
+    };
+
+    let mut top = 0;
+    let mut status = String::new();
+    loop {
+        let footer = match (&asset, status.is_empty()) {
+            (_, false) => status.clone(),
+            (Some(asset), true) => format!(
+                "[d] Download {}  [o] Open release page  [q] Close",
+                asset.name
+            ),
+            (None, true) => "[o] Open release page  [q] Close".to_string(),
+        };
+        render(&header, &lines, top, &footer, &mut term)?;
+
+        let rows = term.get_screen_size()?.rows.saturating_sub(2).max(1);
+        let max_top = lines.len().saturating_sub(rows);
+        match term.poll_input(None) {
+            Ok(Some(InputEvent::Key(KeyEvent { key, .. }))) => match key {
+                KeyCode::Char('k') | KeyCode::UpArrow => top = top.saturating_sub(1),
+                KeyCode::Char('j') | KeyCode::DownArrow => top = (top + 1).min(max_top),
+                KeyCode::PageUp => top = top.saturating_sub(rows),
+                KeyCode::PageDown => top = (top + rows).min(max_top),
+                KeyCode::Char('g') | KeyCode::Home => top = 0,
+                KeyCode::Char('G') | KeyCode::End => top = max_top,
+                KeyCode::Char('o') => {
+                    if let Err(err) = open::that(&release.html_url) {
+                        status = format!("Failed to open {}: {:#}", release.html_url, err);
+                    }
+                }
+                KeyCode::Char('d') => {
+                    if let Some(asset) = &asset {
+                        render(
+                            &header,
+                            &lines,
+                            top,
+                            &format!("Downloading {}...", asset.name),
+                            &mut term,
+                        )?;
+                        status = match download_asset(&release, asset, &settings) {
+                            Ok(path) => format!("Downloaded to {}", path.display()),
+                            Err(err) => format!("Download failed: {:#}", err),
+                        };
+                    }
+                }
+                KeyCode::Char('q') | KeyCode::Escape => break,
+                _ => {}
+            },
+            Ok(Some(InputEvent::Mouse(MouseEvent { mouse_buttons, .. }))) => {
+                if mouse_buttons == MouseButtons::VERT_WHEEL | MouseButtons::WHEEL_POSITIVE {
+                    top = top.saturating_sub(3);
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL {
+                    top = (top + 3).min(max_top);
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! The URL list overlay collects the hyperlinks in the scrollback of a
+//! pane, both explicit OSC 8 links and those produced by the hyperlink
+//! rules, into a list that can be filtered by typing, and that allows
+//! opening or copying the selected link.
+use crate::gui::termwindow::TermWindow;
+use config::keyassignment::ClipboardCopyDestination;
+use mux::termwiztermtab::TermWizTerminal;
+use std::collections::HashSet;
+use termwiz::cell::{unicode_column_width, AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use wezterm_term::Line;
+
+/// Rows at the top of the overlay that are not part of the list
+const HEADER_ROWS: usize = 2;
+
+/// Returns the distinct links in `lines`, most recent first
+pub fn collect_links(lines: &[Line]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut links = vec![];
+    for line in lines.iter().rev() {
+        let mut line_links: Vec<String> = vec![];
+        for cell in line.cells() {
+            if let Some(link) = cell.attrs().hyperlink() {
+                if line_links.last().map(String::as_str) != Some(link.uri()) {
+                    line_links.push(link.uri().to_string());
+                }
+            }
+        }
+        // Preserve the left-to-right order of the links within a line
+        for link in line_links {
+            if seen.insert(link.clone()) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+struct State {
+    links: Vec<String>,
+    filter: String,
+    /// Indices into `links` of those that match `filter`
+    matches: Vec<usize>,
+    active_idx: usize,
+    top: usize,
+}
+
+impl State {
+    fn update_matches(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.matches = self
+            .links
+            .iter()
+            .enumerate()
+            .filter(|(_, link)| link.to_lowercase().contains(&filter))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.active_idx = 0;
+        self.top = 0;
+    }
+
+    fn selected(&self) -> Option<String> {
+        self.matches
+            .get(self.active_idx)
+            .map(|&idx| self.links[idx].clone())
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1) as isize;
+        self.active_idx = (self.active_idx as isize + delta).max(0).min(last) as usize;
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let rows = size.rows.saturating_sub(HEADER_ROWS).max(1);
+        if self.active_idx < self.top {
+            self.top = self.active_idx;
+        } else if self.active_idx >= self.top + rows {
+            self.top = self.active_idx + 1 - rows;
+        }
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(format!(
+                "{} of {} links.  Type to filter, Enter to open, \
+                 CTRL-Y to copy, Escape to cancel\r\n",
+                self.matches.len(),
+                self.links.len()
+            )),
+            Change::Text(format!("> {}\r\n", self.filter)),
+        ];
+
+        for (idx, &link_idx) in self.matches.iter().enumerate().skip(self.top).take(rows) {
+            let mut text = format!(" {}", self.links[link_idx]);
+            if let Some((byte_idx, _)) = text.char_indices().nth(size.cols) {
+                text.truncate(byte_idx);
+            }
+            if idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(text));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        // Leave the cursor at the end of the filter text
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(2 + unicode_column_width(&self.filter)),
+            y: Position::Absolute(1),
+        });
+
+        term.render(&changes)?;
+        term.flush()
+    }
+}
+
+fn open_link(link: String) {
+    log::info!("opening {}", link);
+    if let Err(err) = open::that(&link) {
+        log::error!("failed to open {}: {:?}", link, err);
+    }
+}
+
+fn copy_link(window: &::window::Window, link: String) {
+    window.apply(move |term_window, _window| {
+        if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+            term_window.copy_to_clipboard(
+                ClipboardCopyDestination::ClipboardAndPrimarySelection,
+                link.clone(),
+            );
+        }
+        Ok(())
+    });
+}
+
+pub fn url_list(
+    mut term: TermWizTerminal,
+    links: Vec<String>,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let mut state = State {
+        links,
+        filter: String::new(),
+        matches: vec![],
+        active_idx: 0,
+        top: 0,
+    };
+    state.update_matches();
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Links".to_string())])?;
+
+    loop {
+        state.render(&mut term)?;
+        let page = term
+            .get_screen_size()?
+            .rows
+            .saturating_sub(HEADER_ROWS)
+            .max(1) as isize;
+
+        match term.poll_input(None) {
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }))) => break,
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }))) => {
+                if let Some(link) = state.selected() {
+                    open_link(link);
+                }
+                break;
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('y'),
+                modifiers: Modifiers::CTRL,
+            }))) => {
+                if let Some(link) = state.selected() {
+                    copy_link(&window, link);
+                }
+                break;
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('p'),
+                modifiers: Modifiers::CTRL,
+            }))) => state.move_by(-1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('n'),
+                modifiers: Modifiers::CTRL,
+            }))) => state.move_by(1),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageUp,
+                ..
+            }))) => state.move_by(-page),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::PageDown,
+                ..
+            }))) => state.move_by(page),
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Backspace,
+                ..
+            }))) => {
+                state.filter.pop();
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('u'),
+                modifiers: Modifiers::CTRL,
+            }))) => {
+                state.filter.clear();
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::NONE,
+            })))
+            | Ok(Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::SHIFT,
+            }))) => {
+                state.filter.push(c);
+                state.update_matches();
+            }
+            Ok(Some(InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }))) => {
+                let y = y as usize;
+                if y >= HEADER_ROWS && state.top + y - HEADER_ROWS < state.matches.len() {
+                    state.active_idx = state.top + y - HEADER_ROWS;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        if let Some(link) = state.selected() {
+                            open_link(link);
+                        }
+                        break;
+                    }
+                }
+                if mouse_buttons == MouseButtons::VERT_WHEEL | MouseButtons::WHEEL_POSITIVE {
+                    state.move_by(-1);
+                } else if mouse_buttons == MouseButtons::VERT_WHEEL {
+                    state.move_by(1);
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use termwiz::hyperlink::Hyperlink;
+
+    fn line_with_link(text: &str, uri: &str) -> Line {
+        let mut attrs = CellAttributes::default();
+        attrs.set_hyperlink(Some(Arc::new(Hyperlink::new(uri))));
+        Line::from_text(text, &attrs)
+    }
+
+    #[test]
+    fn links_are_distinct_and_most_recent_first() {
+        let lines = vec![
+            line_with_link("one", "https://one.example.com"),
+            Line::from_text("no link here", &CellAttributes::default()),
+            line_with_link("two", "https://two.example.com"),
+            line_with_link("one again", "https://one.example.com"),
+        ];
+        assert_eq!(
+            collect_links(&lines),
+            vec![
+                "https://one.example.com".to_string(),
+                "https://two.example.com".to_string()
+            ]
+        );
+    }
+}
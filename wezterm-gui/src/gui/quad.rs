@@ -3,7 +3,6 @@
 #![allow(clippy::unneeded_field_pattern)]
 
 use ::window::bitmaps::TextureRect;
-use ::window::glium::VertexBuffer;
 use ::window::*;
 use std::cell::RefMut;
 
@@ -14,8 +13,10 @@ pub const V_TOP_LEFT: usize = 0;
 pub const V_TOP_RIGHT: usize = 1;
 pub const V_BOT_LEFT: usize = 2;
 pub const V_BOT_RIGHT: usize = 3;
+/// How many bookmarks can be shown in the scroll bar
+pub const MAX_SCROLL_MARKS: usize = 32;
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq)]
 pub struct Vertex {
     // Physical position of the corner of the character cell
     pub position: (f32, f32),
@@ -72,10 +73,13 @@ pub struct Quads {
     /// The vertex index for the first vertex of the scroll bar thumb
     pub scroll_thumb: usize,
     pub background_image: usize,
+    /// The vertex index for the first of the MAX_SCROLL_MARKS
+    /// scroll bar bookmark quads
+    pub scroll_marks: usize,
 }
 
 pub struct MappedQuads<'a> {
-    mapping: glium::buffer::Mapping<'a, [Vertex]>,
+    mapping: &'a mut [Vertex],
     quads: Quads,
 }
 
@@ -104,6 +108,16 @@ impl<'a> MappedQuads<'a> {
         }
     }
 
+    pub fn scroll_mark<'b>(&'b mut self, idx: usize) -> anyhow::Result<Quad<'b>> {
+        if idx >= MAX_SCROLL_MARKS {
+            anyhow::bail!("scroll mark {} is outside of the vertex buffer range", idx);
+        }
+        let start = self.quads.scroll_marks + idx * VERTICES_PER_CELL;
+        Ok(Quad {
+            vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+        })
+    }
+
     pub fn background_image<'b>(&'b mut self) -> Quad<'b> {
         let start = self.quads.background_image;
         Quad {
@@ -113,13 +127,45 @@ impl<'a> MappedQuads<'a> {
 }
 
 impl Quads {
-    pub fn map<'a>(&self, vb: &'a mut RefMut<VertexBuffer<Vertex>>) -> MappedQuads<'a> {
-        let mapping = vb.slice_mut(..).expect("to map vertex buffer").map();
+    pub fn map<'a>(&self, vertices: &'a mut RefMut<Vec<Vertex>>) -> MappedQuads<'a> {
         MappedQuads {
-            mapping,
+            mapping: vertices.as_mut_slice(),
             quads: self.clone(),
         }
     }
+
+    /// Compares two generations of the vertices and returns the rows
+    /// whose quads differ, or None if anything other than the rows
+    /// changed, such as the background or the scroll bar, in which
+    /// case the whole window has to be considered to have changed.
+    pub fn damaged_rows(&self, prev: &[Vertex], current: &[Vertex]) -> Option<Vec<usize>> {
+        if prev.len() != current.len() {
+            return None;
+        }
+        let row_len = self.cols * VERTICES_PER_CELL;
+        let first_row = self.row_starts.first().copied().unwrap_or(0);
+        let end_of_rows = self
+            .row_starts
+            .last()
+            .map(|start| start + row_len)
+            .unwrap_or(0);
+        if prev[..first_row] != current[..first_row]
+            || prev[end_of_rows..] != current[end_of_rows..]
+        {
+            return None;
+        }
+
+        Some(
+            self.row_starts
+                .iter()
+                .enumerate()
+                .filter(|(_, &start)| {
+                    prev[start..start + row_len] != current[start..start + row_len]
+                })
+                .map(|(row, _)| row)
+                .collect(),
+        )
+    }
 }
 
 /// A helper for updating the 4 vertices that compose a glyph cell
@@ -232,3 +278,34 @@ impl<'a> Quad<'a> {
         self.vert[V_BOT_RIGHT].position = (right, bottom);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn damaged_rows() {
+        let quads = Quads {
+            cols: 2,
+            row_starts: vec![4, 12, 20],
+            scroll_thumb: 28,
+            background_image: 0,
+            scroll_marks: 32,
+        };
+        let prev = vec![Vertex::default(); 36];
+
+        let mut current = prev.clone();
+        assert_eq!(quads.damaged_rows(&prev, &current), Some(vec![]));
+
+        current[13].has_color = 1.0;
+        current[27].has_color = 1.0;
+        assert_eq!(quads.damaged_rows(&prev, &current), Some(vec![1, 2]));
+
+        // The scroll thumb moved
+        current[28].position = (1.0, 1.0);
+        assert_eq!(quads.damaged_rows(&prev, &current), None);
+
+        // The first paint after a resize
+        assert_eq!(quads.damaged_rows(&[], &current), None);
+    }
+}
@@ -7,7 +7,7 @@ use ::window::glium::texture::SrgbTexture2d;
 use ::window::glium::{IndexBuffer, VertexBuffer};
 use ::window::*;
 use anyhow::anyhow;
-use config::configuration;
+use config::ConfigHandle;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wezterm_font::FontConfiguration;
@@ -20,6 +20,11 @@ pub struct RenderState {
     pub glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     pub glyph_index_buffer: IndexBuffer<u32>,
     pub quads: Quads,
+    /// The vertices are painted here and then uploaded to the
+    /// glyph_vertex_buffer, so that we can tell what changed
+    pub vertices: RefCell<Vec<Vertex>>,
+    /// The vertices as of the previous upload
+    prev_vertices: RefCell<Vec<Vertex>>,
 }
 
 impl RenderState {
@@ -64,12 +69,14 @@ impl RenderState {
                         anyhow!("Failed to compile shaders: {}", errors.join("\n"))
                     })?;
 
-                    let (glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_vertices(
-                        &context,
-                        metrics,
-                        pixel_width as f32,
-                        pixel_height as f32,
-                    )?;
+                    let (vertices, glyph_vertex_buffer, glyph_index_buffer, quads) =
+                        Self::compute_vertices(
+                            &context,
+                            &fonts.config(),
+                            metrics,
+                            pixel_width as f32,
+                            pixel_height as f32,
+                        )?;
 
                     return Ok(Self {
                         context,
@@ -79,6 +86,8 @@ impl RenderState {
                         glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
                         glyph_index_buffer,
                         quads,
+                        vertices: RefCell::new(vertices),
+                        prev_vertices: RefCell::new(vec![]),
                     });
                 }
                 Err(OutOfTextureSpace { size: Some(size) }) => {
@@ -93,12 +102,14 @@ impl RenderState {
 
     pub fn advise_of_window_size_change(
         &mut self,
+        config: &ConfigHandle,
         metrics: &RenderMetrics,
         pixel_width: usize,
         pixel_height: usize,
     ) -> anyhow::Result<()> {
-        let (glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_vertices(
+        let (vertices, glyph_vertex_buffer, glyph_index_buffer, quads) = Self::compute_vertices(
             &self.context,
+            config,
             metrics,
             pixel_width as f32,
             pixel_height as f32,
@@ -107,9 +118,30 @@ impl RenderState {
         *self.glyph_vertex_buffer.borrow_mut() = glyph_vertex_buffer;
         self.glyph_index_buffer = glyph_index_buffer;
         self.quads = quads;
+        *self.vertices.borrow_mut() = vertices;
+        self.invalidate_damage();
         Ok(())
     }
 
+    /// Uploads the painted vertices to the GPU, and returns the rows
+    /// whose quads changed since the previous upload, or None if the
+    /// whole window should be considered to have changed
+    pub fn upload_vertices(&self) -> Option<Vec<usize>> {
+        let vertices = self.vertices.borrow();
+        self.glyph_vertex_buffer.borrow_mut().write(&vertices);
+        let mut prev = self.prev_vertices.borrow_mut();
+        let damaged_rows = self.quads.damaged_rows(&prev, &vertices);
+        prev.clone_from(&vertices);
+        damaged_rows
+    }
+
+    /// Causes the next upload to report that the whole window changed,
+    /// for changes that aren't reflected in the vertices, such as to
+    /// the contents of the glyph atlas
+    pub fn invalidate_damage(&self) {
+        self.prev_vertices.borrow_mut().clear();
+    }
+
     fn vertex_shader(version: &str) -> String {
         format!("#version {}\n{}", version, include_str!("vertex.glsl"))
     }
@@ -126,17 +158,17 @@ impl RenderState {
     /// let the GPU figure out the rest.
     fn compute_vertices(
         context: &Rc<GliumContext>,
+        config: &ConfigHandle,
         metrics: &RenderMetrics,
         width: f32,
         height: f32,
-    ) -> anyhow::Result<(VertexBuffer<Vertex>, IndexBuffer<u32>, Quads)> {
+    ) -> anyhow::Result<(Vec<Vertex>, VertexBuffer<Vertex>, IndexBuffer<u32>, Quads)> {
         let cell_width = metrics.cell_size.width as f32;
         let cell_height = metrics.cell_size.height as f32;
         let mut verts = Vec::new();
         let mut indices = Vec::new();
 
-        let config = configuration();
-        let padding_right = super::termwindow::effective_right_padding(&config, metrics);
+        let padding_right = super::termwindow::effective_right_padding(config, metrics);
         let avail_width =
             (width as usize).saturating_sub((config.window_padding.left + padding_right) as usize);
         let avail_height = (height as usize)
@@ -219,8 +251,16 @@ impl RenderState {
         // And a quad for the scrollbar thumb
         quads.scroll_thumb = define_quad(0.0, 0.0, 0.0, 0.0) as usize;
 
+        // And the bookmarks that are shown over the top of the scrollbar
+        quads.scroll_marks = define_quad(0.0, 0.0, 0.0, 0.0) as usize;
+        for _ in 1..MAX_SCROLL_MARKS {
+            define_quad(0.0, 0.0, 0.0, 0.0);
+        }
+
+        let vertex_buffer = VertexBuffer::dynamic(context, &verts)?;
         Ok((
-            VertexBuffer::dynamic(context, &verts)?,
+            verts,
+            vertex_buffer,
             IndexBuffer::new(
                 context,
                 glium::index::PrimitiveType::TrianglesList,
@@ -234,6 +274,7 @@ impl RenderState {
         let mut glyph_cache = self.glyph_cache.borrow_mut();
         glyph_cache.clear();
         self.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+        self.invalidate_damage();
         Ok(())
     }
 
@@ -284,6 +325,7 @@ impl RenderState {
         let mut glyph_cache = GlyphCache::new_gl(&self.context, fonts, size, metrics)?;
         self.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
         *self.glyph_cache.borrow_mut() = glyph_cache;
+        self.invalidate_damage();
         Ok(())
     }
 }
@@ -83,6 +83,15 @@ impl ScrollHit {
         }
     }
 
+    /// Compute the y-coordinate in the scrollbar that represents the
+    /// stable row `row`, such as for drawing a bookmark
+    pub fn row_to_top(pane: &dyn Pane, row: StableRowIndex, size: PtySize) -> usize {
+        let render_dims = pane.get_dimensions();
+        let offset = row.saturating_sub(render_dims.scrollback_top).max(0) as f32;
+        let scroll_size = render_dims.scrollback_rows.max(1) as f32;
+        ((offset / scroll_size) * size.pixel_height as f32) as usize
+    }
+
     /// Given a new thumb top coordinate (produced by dragging the thumb),
     /// compute the equivalent viewport offset.
     pub fn thumb_top_to_scroll_top(
@@ -5,7 +5,7 @@ use mux::pane::Pane;
 use std::cmp::Ordering;
 use std::ops::Range;
 use termwiz::surface::line::DoubleClickRange;
-use wezterm_term::{SemanticZone, StableRowIndex};
+use wezterm_term::{SemanticType, SemanticZone, StableRowIndex};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub struct Selection {
@@ -14,6 +14,10 @@ pub struct Selection {
     pub start: Option<SelectionCoordinate>,
     /// Holds the not-normalized selection range.
     pub range: Option<SelectionRange>,
+    /// When true, the selection is the rectangular block of cells
+    /// between the start and end coordinates rather than the flow
+    /// of text between them.
+    pub rectangular: bool,
 }
 
 pub use config::keyassignment::SelectionMode;
@@ -23,17 +27,29 @@ impl Selection {
     pub fn clear(&mut self) {
         self.range = None;
         self.start = None;
+        self.rectangular = false;
     }
 
     pub fn begin(&mut self, start: SelectionCoordinate) {
         self.range = None;
         self.start = Some(start);
+        self.rectangular = false;
     }
 
     #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.range.is_none()
     }
+
+    /// Yields a range representing the selected columns for the
+    /// specified row, taking rectangular selection into account.
+    pub fn cols_for_row(&self, row: StableRowIndex) -> Range<usize> {
+        match self.range {
+            Some(range) if self.rectangular => range.rectangular_cols_for_row(row),
+            Some(range) => range.cols_for_row(row),
+            None => 0..0,
+        }
+    }
 }
 
 /// The x,y coordinates of either the start or end of a selection region
@@ -51,6 +67,34 @@ pub struct SelectionRange {
     pub end: SelectionCoordinate,
 }
 
+fn find_zone(start: &SelectionCoordinate, zone: &SemanticZone) -> Ordering {
+    match zone.start_y.cmp(&start.y) {
+        Ordering::Greater => return Ordering::Greater,
+        // If the zone starts on the same line then check that the
+        // x position is within bounds
+        Ordering::Equal => match zone.start_x.cmp(&start.x) {
+            Ordering::Greater => return Ordering::Greater,
+            Ordering::Equal | Ordering::Less => {}
+        },
+        Ordering::Less => {}
+    }
+    match zone.end_y.cmp(&start.y) {
+        Ordering::Less => Ordering::Less,
+        // If the zone ends on the same line then check that the
+        // x position is within bounds
+        Ordering::Equal => match zone.end_x.cmp(&start.x) {
+            Ordering::Less => Ordering::Less,
+            Ordering::Equal | Ordering::Greater => Ordering::Equal,
+        },
+        Ordering::Greater => Ordering::Equal,
+    }
+}
+
+/// Returns the index of the zone that contains the specified coords
+fn zone_index(zones: &[SemanticZone], coord: &SelectionCoordinate) -> Option<usize> {
+    zones.binary_search_by(|zone| find_zone(coord, zone)).ok()
+}
+
 fn is_double_click_word(s: &str) -> bool {
     match s.len() {
         1 => !config::configuration().selection_word_boundary.contains(s),
@@ -83,44 +127,58 @@ impl SelectionRange {
             Err(_) => return Self { start, end: start },
         };
 
-        fn find_zone(start: &SelectionCoordinate, zone: &SemanticZone) -> Ordering {
-            match zone.start_y.cmp(&start.y) {
-                Ordering::Greater => return Ordering::Greater,
-                // If the zone starts on the same line then check that the
-                // x position is within bounds
-                Ordering::Equal => match zone.start_x.cmp(&start.x) {
-                    Ordering::Greater => return Ordering::Greater,
-                    Ordering::Equal | Ordering::Less => {}
-                },
-                Ordering::Less => {}
-            }
-            match zone.end_y.cmp(&start.y) {
-                Ordering::Less => Ordering::Less,
-                // If the zone ends on the same line then check that the
-                // x position is within bounds
-                Ordering::Equal => match zone.end_x.cmp(&start.x) {
-                    Ordering::Less => Ordering::Less,
-                    Ordering::Equal | Ordering::Greater => Ordering::Equal,
-                },
-                Ordering::Greater => Ordering::Equal,
-            }
+        match zone_index(&zones, &start) {
+            Some(idx) => Self::from_zone(&zones[idx]),
+            None => Self { start, end: start },
         }
+    }
 
-        if let Ok(idx) = zones.binary_search_by(|zone| find_zone(&start, zone)) {
-            let zone = &zones[idx];
-            Self {
-                start: SelectionCoordinate {
-                    x: zone.start_x,
-                    y: zone.start_y,
-                },
-                end: SelectionCoordinate {
-                    x: zone.end_x,
-                    y: zone.end_y,
-                },
-            }
+    /// Computes the selection range that covers the specified zone
+    pub fn from_zone(zone: &SemanticZone) -> Self {
+        Self {
+            start: SelectionCoordinate {
+                x: zone.start_x,
+                y: zone.start_y,
+            },
+            end: SelectionCoordinate {
+                x: zone.end_x,
+                y: zone.end_y,
+            },
+        }
+    }
+
+    /// Computes the range that this range expands to; first to cover
+    /// the semantic zones that it touches, and when it already covers
+    /// exactly those, to the whole of the command around them: from
+    /// its prompt through to the end of its output.
+    pub fn expand_to_zones(&self, zones: &[SemanticZone]) -> Self {
+        let norm = self.normalize();
+        let (first, last) = if norm.start.y == norm.end.y && norm.start.x > norm.end.x {
+            (norm.end, norm.start)
         } else {
-            Self { start, end: start }
+            (norm.start, norm.end)
+        };
+        let (first_idx, last_idx) = match (zone_index(zones, &first), zone_index(zones, &last)) {
+            (Some(first_idx), Some(last_idx)) => (first_idx, last_idx),
+            _ => return *self,
+        };
+
+        let expanded =
+            Self::from_zone(&zones[first_idx]).extend_with(Self::from_zone(&zones[last_idx]));
+        if expanded.start != first || expanded.end != last {
+            return expanded;
+        }
+
+        let mut start_idx = first_idx;
+        while start_idx > 0 && zones[start_idx].semantic_type != SemanticType::Prompt {
+            start_idx -= 1;
+        }
+        let mut end_idx = last_idx;
+        while end_idx + 1 < zones.len() && zones[end_idx + 1].semantic_type != SemanticType::Prompt
+        {
+            end_idx += 1;
         }
+        Self::from_zone(&zones[start_idx]).extend_with(Self::from_zone(&zones[end_idx]))
     }
 
     /// Computes the selection range for the word around the specified coords
@@ -257,4 +315,90 @@ impl SelectionRange {
             0..usize::max_value()
         }
     }
+
+    /// Yields a range representing the selected columns for the specified
+    /// row when the range is treated as a rectangular block.
+    pub fn rectangular_cols_for_row(&self, row: StableRowIndex) -> Range<usize> {
+        let norm = self.normalize();
+        if row < norm.start.y || row > norm.end.y {
+            0..0
+        } else {
+            let left = norm.start.x.min(norm.end.x);
+            let right = norm.start.x.max(norm.end.x);
+            left..right.saturating_add(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rectangular_cols() {
+        let range = SelectionRange {
+            start: SelectionCoordinate { x: 7, y: 2 },
+            end: SelectionCoordinate { x: 3, y: 4 },
+        };
+        assert_eq!(range.rectangular_cols_for_row(1), 0..0);
+        assert_eq!(range.rectangular_cols_for_row(2), 3..8);
+        assert_eq!(range.rectangular_cols_for_row(3), 3..8);
+        assert_eq!(range.rectangular_cols_for_row(4), 3..8);
+        assert_eq!(range.rectangular_cols_for_row(5), 0..0);
+
+        // The flowing selection differs on the middle and end rows
+        assert_eq!(range.cols_for_row(3), 0..usize::max_value());
+        assert_eq!(range.cols_for_row(4), 0..4);
+    }
+
+    #[test]
+    fn expand_zones() {
+        fn zone(
+            semantic_type: SemanticType,
+            start: (usize, StableRowIndex),
+            end: (usize, StableRowIndex),
+        ) -> SemanticZone {
+            SemanticZone {
+                start_x: start.0,
+                start_y: start.1,
+                end_x: end.0,
+                end_y: end.1,
+                semantic_type,
+            }
+        }
+        fn range(start: (usize, StableRowIndex), end: (usize, StableRowIndex)) -> SelectionRange {
+            SelectionRange {
+                start: SelectionCoordinate {
+                    x: start.0,
+                    y: start.1,
+                },
+                end: SelectionCoordinate { x: end.0, y: end.1 },
+            }
+        }
+
+        let zones = vec![
+            zone(SemanticType::Prompt, (0, 0), (1, 0)),
+            zone(SemanticType::Input, (2, 0), (10, 0)),
+            zone(SemanticType::Output, (0, 1), (5, 3)),
+            zone(SemanticType::Prompt, (0, 4), (1, 4)),
+            zone(SemanticType::Input, (2, 4), (8, 4)),
+        ];
+
+        // A selection within the output grows to the whole output
+        let output = range((3, 2), (4, 2)).expand_to_zones(&zones);
+        assert_eq!(output, range((0, 1), (5, 3)));
+
+        // and then to the whole of the command that produced it
+        let command = output.expand_to_zones(&zones);
+        assert_eq!(command, range((0, 0), (5, 3)));
+
+        // which is as far as it goes
+        assert_eq!(command.expand_to_zones(&zones), command);
+
+        // A selection that spans zones grows to cover both of them
+        assert_eq!(
+            range((5, 4), (3, 3)).expand_to_zones(&zones),
+            range((0, 1), (8, 4))
+        );
+    }
 }
@@ -1,9 +1,13 @@
 use config::{ConfigHandle, TabBarColors};
+use mux::recording::RecordingState;
+use mux::tab::Tab;
 use mux::window::Window as MuxWindow;
+use mux::Mux;
 use std::cell::Ref;
+use std::rc::Rc;
 use termwiz::cell::unicode_column_width;
 use termwiz::cell::{Cell, CellAttributes};
-use termwiz::color::ColorSpec;
+use termwiz::color::{AnsiColor, ColorSpec};
 use unicode_segmentation::UnicodeSegmentation;
 use wezterm_term::Line;
 
@@ -18,6 +22,8 @@ pub enum TabBarItem {
     None,
     Tab(usize),
     NewTabButton,
+    /// One of the `tab_bar_buttons` from the configuration
+    Button(usize),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -27,6 +33,21 @@ struct TabEntry {
     width: usize,
 }
 
+/// Returns the symbol that marks a tab that has a pane that is being
+/// recorded; a recording in progress takes precedence over a paused one
+fn recording_indicator(tab: &Rc<Tab>) -> Option<&'static str> {
+    let mux = Mux::get()?;
+    let mut indicator = None;
+    for pos in tab.iter_panes() {
+        match mux.recording_state(pos.pane.pane_id()) {
+            Some(RecordingState::Recording) => return Some("\u{25cf}"),
+            Some(RecordingState::Paused) => indicator = Some("\u{23f8}"),
+            None => {}
+        }
+    }
+    indicator
+}
+
 impl TabBarState {
     pub fn default() -> Self {
         Self {
@@ -56,9 +77,17 @@ impl TabBarState {
         // menu with tab creation options) and the other three chars
         // are symbols representing minimize, maximize and close.
         let per_tab_overhead = 2;
-        let system_overhead = 3;
+        // The custom buttons follow the new tab button, each padded
+        // with a space either side
+        let buttons_width: usize = config
+            .tab_bar_buttons
+            .iter()
+            .map(|button| unicode_column_width(&button.text) + 2)
+            .sum();
+        let system_overhead = 3 + buttons_width;
 
-        let tab_titles: Vec<String> = window
+        // Each title, and whether it begins with a recording indicator
+        let (tab_titles, recording): (Vec<String>, Vec<bool>) = window
             .iter()
             .enumerate()
             .map(|(idx, tab)| {
@@ -75,6 +104,10 @@ impl TabBarState {
                             title
                         );
                     }
+                    let indicator = recording_indicator(tab);
+                    if let Some(indicator) = indicator {
+                        title = format!("{} {}", indicator, title);
+                    }
                     // We have a preferred soft minimum on tab width to make it
                     // easier to click on tab titles, but we'll still go below
                     // this if there are too many tabs to fit the window at
@@ -82,12 +115,12 @@ impl TabBarState {
                     while title.len() < 5 {
                         title.push(' ');
                     }
-                    title
+                    (title, indicator.is_some())
                 } else {
-                    "no pane".to_string()
+                    ("no pane".to_string(), false)
                 }
             })
-            .collect();
+            .unzip();
         let titles_len: usize = tab_titles.iter().map(|s| unicode_column_width(s)).sum();
         let number_of_tabs = tab_titles.len();
 
@@ -136,7 +169,13 @@ impl TabBarState {
                     break;
                 }
 
-                line.set_cell(x, Cell::new_grapheme(sub, cell_attrs.clone()));
+                if idx == 0 && recording[tab_idx] {
+                    let mut attrs = cell_attrs.clone();
+                    attrs.set_foreground(AnsiColor::Red);
+                    line.set_cell(x, Cell::new_grapheme(sub, attrs));
+                } else {
+                    line.set_cell(x, Cell::new_grapheme(sub, cell_attrs.clone()));
+                }
                 x += 1;
             }
 
@@ -174,6 +213,39 @@ impl TabBarState {
             x += 3;
         }
 
+        for (button_idx, button) in config.tab_bar_buttons.iter().enumerate() {
+            let width = unicode_column_width(&button.text) + 2;
+            if x + width > title_width {
+                break;
+            }
+            let hover = mouse_x
+                .map(|mouse_x| mouse_x >= x && mouse_x < x + width)
+                .unwrap_or(false);
+
+            let cell_attrs = if hover {
+                colors.inactive_tab_hover.as_cell_attributes()
+            } else {
+                colors.inactive_tab.as_cell_attributes()
+            };
+
+            items.push(TabEntry {
+                item: TabBarItem::Button(button_idx),
+                x,
+                width,
+            });
+
+            line.set_cell(x, Cell::new(' ', cell_attrs.clone()));
+            x += 1;
+            for sub in button.text.graphemes(true) {
+                let cell = Cell::new_grapheme(sub, cell_attrs.clone());
+                let cell_width = cell.width();
+                line.set_cell(x, cell);
+                x += cell_width;
+            }
+            line.set_cell(x, Cell::new(' ', cell_attrs));
+            x += 1;
+        }
+
         let black_cell = Cell::new(
             ' ',
             CellAttributes::default()
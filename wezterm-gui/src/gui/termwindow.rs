@@ -1,10 +1,17 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
+use super::framepacing::{FramePacing, PerformanceStats};
 use super::quad::*;
 use super::renderstate::*;
 use super::utilsprites::RenderMetrics;
+use crate::gui::keymacro::{get_key_macro, set_key_macro, MacroStep};
 use crate::gui::overlay::{
-    confirm_close_pane, confirm_close_tab, confirm_close_window, confirm_quit_program, launcher,
-    start_overlay, start_overlay_pane, tab_navigator, CopyOverlay, SearchOverlay,
+    bookmarks, clipboard_history, clipboard_history_overlay, collect_links, confirm_close_pane,
+    confirm_close_tab, confirm_close_window, confirm_paste, confirm_quit_program, debug_log,
+    global_search, launcher, pane_chooser, pane_label, paste_needs_confirmation, prompt_marks,
+    record_copy, remote_cwd, resolve_remote_path, running_processes, scripted_overlay,
+    sftp_domain_name, sftp_download, sftp_upload, start_overlay, start_overlay_pane, tab_navigator,
+    update_info, url_list, CopyOverlay, OverlayRequest, PaneChoice, QuickSelectOverlay,
+    SearchOverlay,
 };
 use crate::gui::scrollbar::*;
 use crate::gui::selection::*;
@@ -25,18 +32,19 @@ use ::window::MouseEventKind as WMEK;
 use ::window::*;
 use anyhow::{anyhow, bail, ensure};
 use config::keyassignment::{
-    ClipboardCopyDestination, ClipboardPasteSource, InputMap, KeyAssignment, MouseEventTrigger,
+    ClipboardCopyDestination, ClipboardPasteSource, EditScrollback, EditScrollbackContent,
+    InputMap, KeyAssignment, MouseEventTrigger, PaneDirection, Pattern, ScrollbackExportFormat,
     SpawnCommand, SpawnTabDomain,
 };
 use config::{configuration, ConfigHandle, WindowCloseConfirmation};
 use lru::LruCache;
 use mux::activity::Activity;
 use mux::domain::{DomainId, DomainState};
-use mux::pane::{Pane, PaneId};
+use mux::pane::{Pane, PaneId, SearchResult};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, TabId};
 use mux::window::WindowId as MuxWindowId;
-use mux::Mux;
+use mux::{Mux, MuxNotification};
 use portable_pty::{CommandBuilder, PtySize};
 use std::any::Any;
 use std::cell::{RefCell, RefMut};
@@ -51,14 +59,20 @@ use termwiz::color::{ColorAttribute, RgbColor};
 use termwiz::hyperlink::Hyperlink;
 use termwiz::image::ImageData;
 use termwiz::surface::{CursorShape, CursorVisibility};
+use wezterm_client::domain::ClientDomain;
 use wezterm_font::shaper::GlyphInfo;
 use wezterm_font::units::*;
 use wezterm_font::FontConfiguration;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::input::LastMouseClick;
-use wezterm_term::{CellAttributes, Line, StableRowIndex, TerminalConfiguration};
+use wezterm_term::{
+    Alert, CellAttributes, Line, SemanticType, StableRowIndex, TerminalConfiguration,
+};
 
 const ATLAS_SIZE: usize = 128;
+/// How often to look at the foreground processes of the panes
+/// to decide whether idle should be inhibited
+const IDLE_INHIBIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 lazy_static::lazy_static! {
     static ref WINDOW_CLASS: Mutex<String> = Mutex::new("org.wezfurlong.wezterm".to_owned());
@@ -91,6 +105,8 @@ struct RenderScreenLineOpenGLParams<'a> {
     cursor_border_color: Color,
     foreground: Color,
     is_active: bool,
+    /// The line is the prompt of a command that failed
+    failed_command: bool,
 }
 
 struct ComputeCellFgBgParams<'a> {
@@ -103,6 +119,7 @@ struct ComputeCellFgBgParams<'a> {
     palette: &'a ColorPalette,
     is_active_pane: bool,
     config: &'a ConfigHandle,
+    failed_command: bool,
 }
 
 struct ComputeCellFgBgResult {
@@ -144,12 +161,69 @@ impl wezterm_term::Clipboard for ClipboardHelper {
     }
 
     fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
-        self.window
-            .set_clipboard(Clipboard::Clipboard, data.unwrap_or_else(String::new));
+        // This is how an application copies text, using OSC 52.
+        // We don't know which of the panes of the window it came from,
+        // so the handler of copy-intercept is given the active one.
+        let text = data.unwrap_or_else(String::new);
+        self.window.apply(move |term_window, window| {
+            if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                match term_window.get_active_pane_no_overlay() {
+                    Some(pane) => term_window.copy_from_pane(
+                        &pane,
+                        "osc52",
+                        ClipboardCopyDestination::Clipboard,
+                        text.clone(),
+                    ),
+                    None => window.set_clipboard(Clipboard::Clipboard, text.clone()),
+                }
+            }
+            Ok(())
+        });
         Ok(())
     }
 }
 
+fn set_clipboard(window: &Window, clipboard: ClipboardCopyDestination, text: String) {
+    record_copy(&text);
+    let clipboard = match clipboard {
+        ClipboardCopyDestination::Clipboard => [Some(Clipboard::Clipboard), None],
+        ClipboardCopyDestination::PrimarySelection => [Some(Clipboard::PrimarySelection), None],
+        ClipboardCopyDestination::ClipboardAndPrimarySelection => [
+            Some(Clipboard::Clipboard),
+            Some(Clipboard::PrimarySelection),
+        ],
+    };
+    for &c in &clipboard {
+        if let Some(c) = c {
+            window.set_clipboard(c, text.clone());
+        }
+    }
+}
+
+/// A tab that is being dragged by its entry in the tab bar
+#[derive(Debug, Clone, Copy)]
+struct TabDrag {
+    tab_idx: usize,
+    /// Set while the pointer is well away from the tab bar, where
+    /// releasing the button tears the tab out of this window
+    torn: bool,
+}
+
+/// Where a dragged pane will be placed if it is dropped now
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PaneDropTarget {
+    pane_id: PaneId,
+    pane_index: usize,
+    side: PaneDirection,
+}
+
+/// A pane that is being dragged to a new position
+#[derive(Debug, Clone, Copy)]
+struct PaneDrag {
+    pane_id: PaneId,
+    target: Option<PaneDropTarget>,
+}
+
 #[derive(Clone)]
 struct PrevCursorPos {
     pos: StableCursorPosition,
@@ -190,6 +264,8 @@ pub struct PaneState {
     /// scrollback.
     viewport: Option<StableRowIndex>,
     selection: Selection,
+    /// The rows bookmarked using SetMark, in ascending order
+    marks: Vec<StableRowIndex>,
     /// If is_some(), rather than display the actual tab
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
@@ -219,14 +295,25 @@ pub struct TermWindow {
     input_map: InputMap,
     /// If is_some, the LEADER modifier is active until the specified instant.
     leader_is_down: Option<std::time::Instant>,
+    /// The name of the keyboard macro that is being recorded, and the
+    /// keys that have been recorded for it so far
+    key_macro_recording: Option<(String, Vec<MacroStep>)>,
     show_tab_bar: bool,
     show_scroll_bar: bool,
+    /// Toggled by ToggleLineTimestamps
+    show_line_timestamps: bool,
     tab_bar: TabBarState,
     last_mouse_coords: (usize, i64),
     last_mouse_terminal_coords: (usize, StableRowIndex),
     scroll_drag_start: Option<isize>,
     split_drag_start: Option<PositionedSplit>,
+    tab_drag: Option<TabDrag>,
+    pane_drag: Option<PaneDrag>,
     config_generation: usize,
+    /// The configuration with this window's overrides applied
+    config: ConfigHandle,
+    /// Set by `window:set_config_overrides`
+    config_overrides: serde_json::Map<String, serde_json::Value>,
     prev_cursor: PrevCursorPos,
     last_scroll_info: RenderableDimensions,
 
@@ -250,6 +337,13 @@ pub struct TermWindow {
     shape_cache: RefCell<LruCache<ShapeCacheKey, anyhow::Result<Rc<Vec<GlyphInfo>>>>>,
 
     last_blink_paint: Instant,
+    frame_pacing: FramePacing,
+
+    /// Whether we have asked the window to inhibit idle
+    idle_inhibited: bool,
+    /// Set from lua via window:set_idle_inhibited
+    lua_idle_inhibit: bool,
+    last_idle_inhibit_check: Instant,
 
     palette: Option<ColorPalette>,
 }
@@ -270,9 +364,16 @@ enum Key {
 }
 
 impl WindowCallbacks for TermWindow {
+    fn destroy(&mut self) {
+        if let Some(fe) = super::front_end() {
+            fe.set_tab_bar_rect(self.mux_window_id, None);
+            fe.set_window(self.mux_window_id, None);
+        }
+    }
+
     fn can_close(&mut self) -> bool {
         let mux = Mux::get().unwrap();
-        let config = configuration();
+        let config = self.config.clone();
         match config.window_close_confirmation {
             WindowCloseConfirmation::NeverPrompt => {
                 // Immediately kill the tabs and allow the window to close
@@ -280,6 +381,20 @@ impl WindowCallbacks for TermWindow {
                 true
             }
             WindowCloseConfirmation::AlwaysPrompt => {
+                let panes: Vec<Rc<dyn Pane>> = match mux.get_window(self.mux_window_id) {
+                    Some(win) => win
+                        .iter()
+                        .flat_map(|tab| tab.iter_panes())
+                        .map(|pos| pos.pane)
+                        .collect(),
+                    None => vec![],
+                };
+                let processes = running_processes(&panes);
+                if processes.is_empty() {
+                    mux.kill_window(self.mux_window_id);
+                    return true;
+                }
+
                 let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
                     Some(tab) => tab,
                     None => return true,
@@ -287,7 +402,7 @@ impl WindowCallbacks for TermWindow {
 
                 let mux_window_id = self.mux_window_id;
                 let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
-                    confirm_close_window(term, mux_window_id)
+                    confirm_close_window(processes, term, mux_window_id)
                 });
                 self.assign_overlay(tab.tab_id(), overlay);
                 promise::spawn::spawn(future).detach();
@@ -323,13 +438,47 @@ impl WindowCallbacks for TermWindow {
         }
     }
 
+    fn dropped_file(&mut self, paths: Vec<std::path::PathBuf>) {
+        // Files dropped on an ssh pane are offered for upload to the
+        // remote host, as their local paths would mean nothing there
+        if let Some(pane) = self.get_active_pane_no_overlay() {
+            if let Some(domain) = sftp_domain_name(&pane) {
+                let remote_dir = remote_cwd(&pane);
+                let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+                    sftp_upload(term, domain, paths, remote_dir)
+                });
+                self.assign_overlay_for_pane(pane.pane_id(), overlay);
+                promise::spawn::spawn(future).detach();
+                return;
+            }
+        }
+
+        if let Some(pane) = self.get_active_pane_or_overlay() {
+            // Quote the paths so that they can be used as arguments
+            // to a shell command, leaving the cursor after them
+            let mut text = paths
+                .iter()
+                .map(|path| shell_words::quote(&path.to_string_lossy()).into_owned())
+                .collect::<Vec<_>>()
+                .join(" ");
+            text.push(' ');
+            pane.trickle_paste(text).ok();
+        }
+    }
+
+    fn dropped_string(&mut self, text: String) {
+        if let Some(pane) = self.get_active_pane_no_overlay() {
+            self.paste_text(pane.pane_id(), text);
+        }
+    }
+
     fn mouse_event(&mut self, event: &MouseEvent, context: &dyn WindowOps) {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
             None => return,
         };
 
-        let config = configuration();
+        let config = self.config.clone();
         // Round the x coordinate so that we're a bit more forgiving of
         // the horizontal position when selecting cells
         let x = ((event
@@ -350,6 +499,7 @@ impl WindowCallbacks for TermWindow {
 
         let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
         self.last_mouse_coords = (x, y);
+        self.update_tab_bar_rect(event);
 
         let in_tab_bar = self.show_tab_bar && y == 0 && event.coords.y >= 0;
         let in_scroll_bar = self.show_scroll_bar && x >= self.terminal_size.cols as usize;
@@ -367,6 +517,21 @@ impl WindowCallbacks for TermWindow {
                     // Completed a split drag
                     return;
                 }
+                if press == &MousePress::Left {
+                    if let Some(drag) = self.pane_drag.take() {
+                        self.drop_pane(drag);
+                        context.set_cursor(Some(MouseCursor::Arrow));
+                        context.invalidate();
+                        return;
+                    }
+                    if let Some(drag) = self.tab_drag.take() {
+                        if drag.torn {
+                            self.drop_torn_tab(drag.tab_idx, event);
+                            context.set_cursor(Some(MouseCursor::Arrow));
+                            return;
+                        }
+                    }
+                }
             }
 
             WMEK::Press(ref press) => {
@@ -454,6 +619,40 @@ impl WindowCallbacks for TermWindow {
 
                     return;
                 }
+
+                if self.pane_drag.is_some() {
+                    if !event.mouse_buttons.contains(WMB::LEFT) {
+                        self.pane_drag = None;
+                    } else {
+                        self.update_pane_drag(x, term_y, in_tab_bar);
+                        context.set_cursor(Some(MouseCursor::Hand));
+                    }
+                    context.invalidate();
+                    return;
+                }
+
+                if let Some(drag) = self.tab_drag.as_mut() {
+                    if !event.mouse_buttons.contains(WMB::LEFT) {
+                        // We missed the release, perhaps because it
+                        // happened while another window had the pointer
+                        self.tab_drag = None;
+                    } else {
+                        // Dragging a tab more than a cell away from
+                        // the tab bar tears it off
+                        let cell_height = self.render_metrics.cell_size.height;
+                        let tab_bar_bottom = config.window_padding.top as isize + cell_height;
+                        drag.torn = event.coords.y < -cell_height
+                            || event.coords.y >= tab_bar_bottom + cell_height
+                            || event.coords.x < 0
+                            || event.coords.x >= self.dimensions.pixel_width as isize;
+                        context.set_cursor(Some(if drag.torn {
+                            MouseCursor::Hand
+                        } else {
+                            MouseCursor::Arrow
+                        }));
+                        return;
+                    }
+                }
             }
             _ => {}
         }
@@ -486,7 +685,7 @@ impl WindowCallbacks for TermWindow {
             return false;
         }
 
-        if configuration().debug_key_events {
+        if self.config.debug_key_events {
             log::info!("key_event {:?}", window_key);
         } else {
             log::trace!("key_event {:?}", window_key);
@@ -585,7 +784,7 @@ impl WindowCallbacks for TermWindow {
             // While the leader modifier is active, only registered
             // keybindings are recognized.
             if !leader_active {
-                let config = configuration();
+                let config = self.config.clone();
 
                 // This is a bit ugly.
                 // Not all of our platforms report LEFT|RIGHT ALT; most report just ALT.
@@ -608,6 +807,12 @@ impl WindowCallbacks for TermWindow {
 
                 if let Key::Code(term_key) = self.win_key_code_to_termwiz_key_code(&key) {
                     if bypass_compose && pane.key_down(term_key, raw_modifiers).is_ok() {
+                        if !key.is_modifier() {
+                            self.record_key_macro_step(MacroStep::Key {
+                                key: term_key,
+                                mods: raw_modifiers,
+                            });
+                        }
                         if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
                             self.maybe_scroll_to_bottom_for_input(&pane);
                         }
@@ -656,6 +861,12 @@ impl WindowCallbacks for TermWindow {
             match key {
                 Key::Code(key) => {
                     if pane.key_down(key, modifiers).is_ok() {
+                        if !key.is_modifier() {
+                            self.record_key_macro_step(MacroStep::Key {
+                                key,
+                                mods: modifiers,
+                            });
+                        }
                         if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
                             self.maybe_scroll_to_bottom_for_input(&pane);
                         }
@@ -673,6 +884,7 @@ impl WindowCallbacks for TermWindow {
                         self.leader_is_down.take();
                     } else {
                         pane.writer().write_all(s.as_bytes()).ok();
+                        self.record_key_macro_step(MacroStep::Text { text: s });
                         self.maybe_scroll_to_bottom_for_input(&pane);
                         context.invalidate();
                     }
@@ -704,16 +916,22 @@ impl WindowCallbacks for TermWindow {
             dimensions,
             terminal_size: self.terminal_size.clone(),
             render_state,
-            input_map: InputMap::new(),
+            input_map: InputMap::new(&self.config),
             leader_is_down: None,
+            key_macro_recording: self.key_macro_recording.take(),
             show_tab_bar: self.show_tab_bar,
             show_scroll_bar: self.show_scroll_bar,
+            show_line_timestamps: self.show_line_timestamps,
             tab_bar: self.tab_bar.clone(),
             last_mouse_coords: self.last_mouse_coords.clone(),
             last_mouse_terminal_coords: self.last_mouse_terminal_coords.clone(),
             scroll_drag_start: self.scroll_drag_start.clone(),
             split_drag_start: self.split_drag_start.clone(),
+            tab_drag: None,
+            pane_drag: None,
             config_generation: self.config_generation,
+            config: self.config.clone(),
+            config_overrides: self.config_overrides.clone(),
             prev_cursor: self.prev_cursor.clone(),
             last_scroll_info: self.last_scroll_info.clone(),
             clipboard_contents: Arc::clone(&clipboard_contents),
@@ -724,6 +942,11 @@ impl WindowCallbacks for TermWindow {
             current_highlight: self.current_highlight.clone(),
             shape_cache: RefCell::new(LruCache::new(65536)),
             last_blink_paint: Instant::now(),
+            frame_pacing: FramePacing::default(),
+            idle_inhibited: false,
+            lua_idle_inhibit: self.lua_idle_inhibit,
+            last_idle_inhibit_check: Instant::now() - IDLE_INHIBIT_CHECK_INTERVAL,
+            damage: None,
         });
         prior_window.close();
 
@@ -740,6 +963,7 @@ impl WindowCallbacks for TermWindow {
 
             Self::apply_icon(&window)?;
             Self::start_periodic_maintenance(window.clone());
+            Self::subscribe_to_pane_updates(window.clone(), mux_window_id);
             Self::setup_clipboard(&window, mux_window_id, clipboard_contents);
 
             drop(activity); // Keep the activity outstanding until we get here
@@ -756,6 +980,10 @@ impl WindowCallbacks for TermWindow {
         ctx: std::rc::Rc<glium::backend::Context>,
     ) -> anyhow::Result<()> {
         self.window.replace(window.clone());
+        if let Some(fe) = super::front_end() {
+            fe.set_window(self.mux_window_id, Some(window.clone()));
+        }
+        self.update_resize_increments();
 
         self.render_state = None;
 
@@ -793,7 +1021,7 @@ impl WindowCallbacks for TermWindow {
 
     fn paint(&mut self, frame: &mut glium::Frame) {
         self.check_for_config_reload();
-        let config = configuration();
+        let config = self.config.clone();
         let start = std::time::Instant::now();
 
         {
@@ -840,10 +1068,24 @@ impl WindowCallbacks for TermWindow {
         }
 
         self.call_draw(frame).ok();
+        self.frame_pacing.painted(start, Instant::now());
         log::debug!("paint_pane_opengl elapsed={:?}", start.elapsed());
         metrics::histogram!("gui.paint.opengl", start.elapsed());
         self.update_title();
     }
+
+    fn frame_presented(&mut self, presentation: FramePresentation) {
+        metrics::histogram!("gui.present.latency", presentation.latency);
+        self.frame_pacing.presented(Instant::now(), presentation);
+    }
+
+    fn frame_discarded(&mut self) {
+        self.frame_pacing.discarded();
+    }
+
+    fn damaged_region(&mut self) -> Option<Vec<Rect>> {
+        self.damage.take()
+    }
 }
 
 /// Computes the effective padding for the RHS.
@@ -977,16 +1219,22 @@ impl TermWindow {
                 dimensions,
                 terminal_size,
                 render_state,
-                input_map: InputMap::new(),
+                input_map: InputMap::new(&config),
                 leader_is_down: None,
+                key_macro_recording: None,
                 show_tab_bar,
                 show_scroll_bar: config.enable_scroll_bar,
+                show_line_timestamps: false,
                 tab_bar: TabBarState::default(),
                 last_mouse_coords: (0, -1),
                 last_mouse_terminal_coords: (0, 0),
                 scroll_drag_start: None,
                 split_drag_start: None,
+                tab_drag: None,
+                pane_drag: None,
                 config_generation: config.generation(),
+                config: config.clone(),
+                config_overrides: serde_json::Map::new(),
                 prev_cursor: PrevCursorPos::new(),
                 last_scroll_info: RenderableDimensions::default(),
                 clipboard_contents: Arc::clone(&clipboard_contents),
@@ -997,14 +1245,21 @@ impl TermWindow {
                 current_highlight: None,
                 shape_cache: RefCell::new(LruCache::new(65536)),
                 last_blink_paint: Instant::now(),
+                frame_pacing: FramePacing::default(),
+                idle_inhibited: false,
+                lua_idle_inhibit: false,
+                last_idle_inhibit_check: Instant::now() - IDLE_INHIBIT_CHECK_INTERVAL,
+                damage: None,
             }),
         )?;
 
         Self::apply_icon(&window)?;
         Self::start_periodic_maintenance(window.clone());
+        Self::subscribe_to_pane_updates(window.clone(), mux_window_id);
         Self::setup_clipboard(&window, mux_window_id, clipboard_contents);
 
         crate::update::start_update_checker();
+        crate::crashreport::offer_previous_crash_reports();
         Ok(())
     }
 
@@ -1055,6 +1310,155 @@ impl TermWindow {
         );
     }
 
+    fn subscribe_to_pane_updates(window: Window, mux_window_id: MuxWindowId) {
+        let mux = Mux::get().unwrap();
+        mux.subscribe(move |n| {
+            let mux = match Mux::get() {
+                Some(mux) => mux,
+                None => return false,
+            };
+            match n {
+                MuxNotification::PaneOutput(pane_id) => {
+                    if let Some((_domain, window_id, _tab)) = mux.resolve_pane_id(pane_id) {
+                        if window_id == mux_window_id {
+                            window.apply(move |myself, window| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.pane_output(window);
+                                }
+                                Ok(())
+                            });
+                        }
+                    }
+                }
+                MuxNotification::Alert { pane_id, alert } => {
+                    if let Some((_domain, window_id, _tab)) = mux.resolve_pane_id(pane_id) {
+                        if window_id == mux_window_id {
+                            window.apply(move |myself, _window| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.pane_alert(pane_id, alert.clone());
+                                }
+                                Ok(())
+                            });
+                        }
+                    }
+                }
+                MuxNotification::ChoosePane {
+                    pane_id,
+                    request_id,
+                } => {
+                    if let Some((_domain, window_id, _tab)) = mux.resolve_pane_id(pane_id) {
+                        if window_id == mux_window_id {
+                            window.apply(move |myself, _window| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.show_pane_chooser(pane_id, request_id);
+                                }
+                                Ok(())
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+            mux.get_window(mux_window_id).is_some()
+        });
+    }
+
+    /// Labels the panes of the tab that holds `pane_id` and reports
+    /// the one that the user picks to the mux
+    fn show_pane_chooser(&mut self, pane_id: PaneId, request_id: usize) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux
+            .resolve_pane_id(pane_id)
+            .and_then(|(_, _, tab_id)| mux.get_tab(tab_id))
+        {
+            Some(tab) => tab,
+            None => {
+                mux.pane_chosen(request_id, None);
+                return;
+            }
+        };
+
+        // The labels need to be seen, so bring the tab to the front
+        let tab_idx = mux
+            .get_window(self.mux_window_id)
+            .and_then(|window| window.idx_by_id(tab.tab_id()));
+        if let Some(tab_idx) = tab_idx {
+            self.activate_tab(tab_idx as isize).ok();
+        }
+        tab.set_zoomed(false);
+
+        let panes: Vec<(char, Rc<dyn Pane>)> = tab
+            .iter_panes()
+            .into_iter()
+            .filter_map(|pos| pane_label(pos.index).map(|label| (label, pos.pane)))
+            .collect();
+        let labels: Vec<(char, PaneId)> = panes
+            .iter()
+            .map(|(label, pane)| (*label, pane.pane_id()))
+            .collect();
+        let choice = PaneChoice::default();
+
+        let mut futures = vec![];
+        for (label, pane) in panes {
+            let labels = labels.clone();
+            let choice = Arc::clone(&choice);
+            let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+                pane_chooser(term, pane_id, label, labels, choice)
+            });
+            self.assign_overlay_for_pane(pane.pane_id(), overlay);
+            futures.push(future);
+        }
+
+        promise::spawn::spawn(async move {
+            for future in futures {
+                future.await.ok();
+            }
+            let chosen = choice.lock().unwrap().take().flatten();
+            Mux::get().unwrap().pane_chosen(request_id, chosen);
+        })
+        .detach();
+    }
+
+    /// Called when the application in one of the panes in this
+    /// window wants to get the attention of the user
+    fn pane_alert(&mut self, pane_id: PaneId, alert: Alert) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+        match alert {
+            Alert::Bell => {
+                if self.focused.is_none() {
+                    window.request_attention();
+                }
+            }
+            Alert::ToastNotification(toast) => {
+                let pane_focused = self.focused.is_some()
+                    && self.get_active_pane_no_overlay().map(|p| p.pane_id()) == Some(pane_id);
+                crate::notifications::show_toast(window, &pane, toast, pane_focused);
+            }
+            Alert::TitleMaybeChanged => {
+                self.update_title();
+            }
+            Alert::SetUserVar { .. } => {}
+        }
+    }
+
+    /// Called when one of the panes in this window has produced output.
+    /// Paint right away if the output looks interactive; otherwise the
+    /// periodic maintenance will pick up the changes at a pace that
+    /// suits the rate of output.
+    fn pane_output(&mut self, window: &dyn WindowOps) {
+        if self.frame_pacing.output(Instant::now()) {
+            window.invalidate();
+        }
+    }
+
     fn periodic_window_maintenance(&mut self, _window: &dyn WindowOps) -> anyhow::Result<()> {
         let mux = Mux::get().unwrap();
 
@@ -1063,7 +1467,7 @@ impl TermWindow {
         // and render any changes
         self.check_for_config_reload();
 
-        let config = configuration();
+        let config = self.config.clone();
 
         let panes = self.get_panes_to_render();
         if panes.is_empty() {
@@ -1071,6 +1475,10 @@ impl TermWindow {
             return Ok(());
         }
 
+        if self.last_idle_inhibit_check.elapsed() >= IDLE_INHIBIT_CHECK_INTERVAL {
+            self.update_idle_inhibit();
+        }
+
         for pos in panes {
             // If blinking is permitted, and the cursor shape is set
             // to a blinking variant, and it's been longer than the
@@ -1128,7 +1536,9 @@ impl TermWindow {
                     }
                 }
 
-                needs_invalidate = true;
+                if self.frame_pacing.should_paint(Instant::now()) {
+                    needs_invalidate = true;
+                }
             }
         }
 
@@ -1154,14 +1564,14 @@ impl TermWindow {
             WK::Char('\r') => KC::Enter,
             WK::Char('\t') => KC::Tab,
             WK::Char('\u{08}') => {
-                if configuration().swap_backspace_and_delete {
+                if self.config.swap_backspace_and_delete {
                     KC::Delete
                 } else {
                     KC::Backspace
                 }
             }
             WK::Char('\u{7f}') => {
-                if configuration().swap_backspace_and_delete {
+                if self.config.swap_backspace_and_delete {
                     KC::Backspace
                 } else {
                     KC::Delete
@@ -1273,17 +1683,81 @@ impl TermWindow {
 
     fn palette(&mut self) -> &ColorPalette {
         if self.palette.is_none() {
-            self.palette.replace(config::TermConfig.color_palette());
+            self.palette
+                .replace(self.config.resolved_palette.clone().into());
         }
         self.palette.as_ref().unwrap()
     }
 
+    /// Returns true if this window's overrides change the colors,
+    /// in which case they are used in place of the palettes of
+    /// the panes
+    fn colors_are_overridden(&self) -> bool {
+        [
+            "colors",
+            "color_scheme",
+            "color_schemes",
+            "color_scheme_dirs",
+        ]
+        .iter()
+        .any(|key| self.config_overrides.contains_key(*key))
+    }
+
+    /// Returns the palette to paint `pane` with
+    fn pane_palette(&mut self, pane: &Rc<dyn Pane>) -> ColorPalette {
+        if self.colors_are_overridden() {
+            self.palette().clone()
+        } else {
+            pane.palette()
+        }
+    }
+
+    /// Replaces the configuration overrides of this window; see
+    /// `window:set_config_overrides`.  The options that can't be
+    /// changed for a single window are ignored, with a warning.
+    pub fn set_config_overrides(
+        &mut self,
+        overrides: serde_json::Map<String, serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        // Raise any problems with the values before adopting them
+        config::configuration_with_overrides(&overrides)?;
+
+        let unsupported = config::check_overrides(&overrides);
+        if !unsupported.is_empty() {
+            log::warn!("window:set_config_overrides: {}", unsupported);
+        }
+
+        self.config_overrides = overrides;
+        self.config_was_reloaded();
+        Ok(())
+    }
+
+    pub fn get_config_overrides(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.config_overrides
+    }
+
     fn config_was_reloaded(&mut self) {
-        let config = configuration();
+        let config = match config::configuration_with_overrides(&self.config_overrides) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!(
+                    "while applying the configuration overrides of this window: {:#}",
+                    err
+                );
+                configuration()
+            }
+        };
         self.config_generation = config.generation();
+        self.config = config.clone();
         self.palette.take();
+        if let Err(err) = self.fonts.config_changed(&config) {
+            log::error!("while changing the fonts of this window: {:#}", err);
+        }
 
         self.window_background = reload_background_image(&config, &self.window_background);
+        if let Some(render_state) = self.render_state.as_ref() {
+            render_state.invalidate_damage();
+        }
 
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
@@ -1298,7 +1772,7 @@ impl TermWindow {
 
         self.show_scroll_bar = config.enable_scroll_bar;
         self.shape_cache.borrow_mut().clear();
-        self.input_map = InputMap::new();
+        self.input_map = InputMap::new(&config);
         self.leader_is_down = None;
         let dimensions = self.dimensions;
         let cell_dims = self.current_cell_dimensions();
@@ -1338,7 +1812,7 @@ impl TermWindow {
             Some(window) => window,
             _ => return,
         };
-        let config = configuration();
+        let config = self.config.clone();
 
         let new_tab_bar = TabBarState::new(
             self.terminal_size.cols as usize,
@@ -1370,20 +1844,26 @@ impl TermWindow {
         let panes = self.get_panes_to_render();
         if let Some(pos) = panes.iter().find(|p| p.is_active) {
             let title = pos.pane.get_title();
+            let macro_indicator = match self.recording_key_macro() {
+                Some(name) => format!("[REC {}] ", name),
+                None => String::new(),
+            };
 
             if let Some(window) = self.window.as_ref() {
                 let show_tab_bar;
                 if num_tabs == 1 {
                     window.set_title(&format!(
-                        "{}{}",
+                        "{}{}{}",
                         if pos.is_zoomed { "[Z] " } else { "" },
+                        macro_indicator,
                         title
                     ));
                     show_tab_bar = config.enable_tab_bar && !config.hide_tab_bar_if_only_one_tab;
                 } else {
                     window.set_title(&format!(
-                        "{}[{}/{}] {}",
+                        "{}{}[{}/{}] {}",
                         if pos.is_zoomed { "[Z] " } else { "" },
+                        macro_indicator,
                         tab_no + 1,
                         num_tabs,
                         title
@@ -1405,7 +1885,7 @@ impl TermWindow {
     fn update_text_cursor(&mut self, pane: &Rc<dyn Pane>) {
         let cursor = pane.get_cursor_position();
         if let Some(win) = self.window.as_ref() {
-            let config = configuration();
+            let config = self.config.clone();
             let top = pane.get_dimensions().physical_top + if self.show_tab_bar { -1 } else { 0 };
             let r = Rect::new(
                 Point::new(
@@ -1529,6 +2009,75 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_global_search(&mut self, pattern: Pattern) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let mux_window_id = self.mux_window_id;
+        let window = self.window.as_ref().unwrap().clone();
+        let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            global_search(tab_id, term, mux_window_id, window, pattern)
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    /// Activates the tab and pane that contain a search result, then
+    /// scrolls to and selects the matching text
+    pub fn jump_to_search_result(&mut self, pane_id: PaneId, result: SearchResult) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let tab = match mux.resolve_pane_id(pane_id) {
+            Some((_domain_id, window_id, tab_id)) if window_id == self.mux_window_id => {
+                match mux.get_tab(tab_id) {
+                    Some(tab) => tab,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+
+        if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+            if let Some(idx) = window.idx_by_id(tab.tab_id()) {
+                window.set_active(idx);
+            }
+        }
+        tab.set_active_pane(&pane);
+
+        {
+            let mut selection = self.selection(pane_id);
+            let start = SelectionCoordinate {
+                x: result.start_x,
+                y: result.start_y,
+            };
+            selection.start = Some(start);
+            selection.rectangular = false;
+            selection.range = Some(SelectionRange {
+                start,
+                end: SelectionCoordinate {
+                    // inclusive range for selection, but the result
+                    // range is exclusive
+                    x: result.end_x.saturating_sub(1),
+                    y: result.end_y,
+                },
+            });
+        }
+
+        let dims = pane.get_dimensions();
+        let top = result.start_y - dims.viewport_rows as StableRowIndex / 2;
+        self.set_viewport(pane_id, Some(top), dims);
+        self.update_title();
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
     fn show_launcher(&mut self) {
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -1598,23 +2147,250 @@ impl TermWindow {
             Some(pane) => pane,
             None => return Ok(()),
         };
+        let dims = pane.get_dimensions();
+        let rows: Vec<StableRowIndex> = pane
+            .get_prompt_marks(
+                dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+            )?
+            .into_iter()
+            .map(|mark| mark.start_y)
+            .collect();
+        self.scroll_to_nth_row(&pane, &rows, amount);
+        Ok(())
+    }
+
+    /// Scrolls the viewport so that the row `amount` steps away from
+    /// the top of the viewport, as counted through the sorted `rows`,
+    /// is at the top.  Moving beyond the last row returns to the bottom.
+    fn scroll_to_nth_row(&mut self, pane: &Rc<dyn Pane>, rows: &[StableRowIndex], amount: isize) {
         let dims = pane.get_dimensions();
         let position = self
             .get_viewport(pane.pane_id())
             .unwrap_or(dims.physical_top);
-        let mut zones = pane.get_semantic_zones()?;
-        zones.retain(|zone| zone.semantic_type == wezterm_term::SemanticType::Prompt);
-        let idx = match zones.binary_search_by(|zone| zone.start_y.cmp(&position)) {
-            Ok(idx) | Err(idx) => idx,
+        let idx = match rows.binary_search(&position) {
+            Ok(idx) => idx as isize + amount,
+            // idx is the first row below the top of the viewport,
+            // so it is one step forward rather than zero steps
+            Err(idx) if amount > 0 => idx as isize + amount - 1,
+            Err(idx) => idx as isize + amount,
         };
-        let idx = ((idx as isize) + amount).max(0) as usize;
-        if let Some(zone) = zones.get(idx) {
-            self.set_viewport(pane.pane_id(), Some(zone.start_y), dims);
+        if idx >= rows.len() as isize {
+            self.set_viewport(pane.pane_id(), None, dims);
+        } else if let Some(row) = rows.get(idx.max(0) as usize) {
+            self.set_viewport(pane.pane_id(), Some(*row), dims);
+        }
+
+        if let Some(win) = self.window.as_ref() {
+            win.invalidate();
+        }
+    }
+
+    /// Returns the bookmarked rows of the pane, forgetting those that
+    /// have been trimmed from the scrollback
+    pub fn marks(&self, pane: &Rc<dyn Pane>) -> Vec<StableRowIndex> {
+        let top = pane.get_dimensions().scrollback_top;
+        let mut state = self.pane_state(pane.pane_id());
+        state.marks.retain(|row| *row >= top);
+        state.marks.clone()
+    }
+
+    pub fn remove_mark(&self, pane_id: PaneId, row: StableRowIndex) {
+        self.pane_state(pane_id).marks.retain(|r| *r != row);
+        if let Some(win) = self.window.as_ref() {
+            win.invalidate();
         }
+    }
 
+    /// Bookmarks the top row of the viewport, or the row of the cursor
+    /// when the viewport is at the bottom of the scrollback
+    fn set_mark(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let row = match self.get_viewport(pane.pane_id()) {
+            Some(top) => top,
+            None => {
+                let dims = pane.get_dimensions();
+                dims.physical_top + pane.get_cursor_position().y as StableRowIndex
+            }
+        };
+        let mut state = self.pane_state(pane.pane_id());
+        if let Err(idx) = state.marks.binary_search(&row) {
+            state.marks.insert(idx, row);
+        }
+        drop(state);
         if let Some(win) = self.window.as_ref() {
             win.invalidate();
         }
+    }
+
+    fn jump_to_mark(&mut self, amount: isize) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let marks = self.marks(&pane);
+        self.scroll_to_nth_row(&pane, &marks, amount);
+    }
+
+    fn list_marks(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let marks: Vec<(StableRowIndex, String)> = self
+            .marks(&pane)
+            .into_iter()
+            .rev()
+            .map(|row| {
+                let (_, lines) = pane.get_lines(row..row + 1);
+                let text = lines
+                    .get(0)
+                    .map(|line| line.as_str().trim().to_string())
+                    .unwrap_or_else(String::new);
+                (row, text)
+            })
+            .collect();
+
+        let window = self.window.as_ref().unwrap().clone();
+        let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+            bookmarks(pane_id, term, marks, window)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    /// Emits the named event, passing the window and pane to the
+    /// handlers that were registered using `wezterm.on`
+    fn emit_window_event(&self, name: &str, pane: &Rc<dyn Pane>) {
+        let window = GuiWin::new(self);
+        let pane = PaneObject::new(pane);
+
+        async fn emit_event(
+            lua: Option<Rc<mlua::Lua>>,
+            name: String,
+            window: GuiWin,
+            pane: PaneObject,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let args = lua.pack_multi((window, pane))?;
+                config::lua::emit_event(&lua, (name.clone(), args))
+                    .await
+                    .map_err(|e| {
+                        log::error!("while processing {} event: {:#}", name, e);
+                        e
+                    })?;
+            }
+            Ok(())
+        }
+
+        let name = name.to_string();
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            emit_event(lua, name, window, pane)
+        }))
+        .detach();
+    }
+
+    fn show_url_list(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let dims = pane.get_dimensions();
+        let (_, lines) = pane.get_lines(
+            dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+        );
+        let links = collect_links(&lines);
+
+        let window = self.window.as_ref().unwrap().clone();
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+            url_list(term, links, window)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_clipboard_history(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let entries = clipboard_history();
+
+        let window = self.window.as_ref().unwrap().clone();
+        let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+            clipboard_history_overlay(pane_id, term, entries, window)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    /// Offers to download the remote file whose path is selected in
+    /// the ssh pane `pane_id`; this is used by copy mode
+    pub fn show_sftp_download(&mut self, pane_id: PaneId) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let domain = match sftp_domain_name(&pane) {
+            Some(domain) => domain,
+            None => return,
+        };
+        let path = self.selection_text(&pane);
+        let path = path.trim();
+        if path.is_empty() {
+            return;
+        }
+        let remote = resolve_remote_path(&remote_cwd(&pane), path);
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+            sftp_download(term, domain, remote)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_debug_log(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let (overlay, future) =
+            start_overlay_pane(self, &pane, move |_pane_id, term| debug_log(term));
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_update_info(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let (overlay, future) =
+            start_overlay_pane(self, &pane, move |_pane_id, term| update_info(term));
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_prompt_marks(&mut self) -> anyhow::Result<()> {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return Ok(()),
+        };
+        let dims = pane.get_dimensions();
+        let mut marks = pane.get_prompt_marks(
+            dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+        )?;
+        marks.reverse();
+
+        let window = self.window.as_ref().unwrap().clone();
+        let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+            prompt_marks(pane_id, term, marks, window)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
         Ok(())
     }
 
@@ -1821,6 +2597,52 @@ impl TermWindow {
         .detach();
     }
 
+    /// Writes the scrollback, or the output of the last command, to a
+    /// temporary file and opens it in an editor in a new split.
+    /// The editor runs in the local domain as that is where the file is.
+    fn edit_scrollback_in_editor(&mut self, edit: &EditScrollback) -> anyhow::Result<()> {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return Ok(()),
+        };
+        let text = match edit.content {
+            EditScrollbackContent::Scrollback => {
+                mux::export::export_scrollback(&*pane, ScrollbackExportFormat::Text, false)
+            }
+            EditScrollbackContent::LastCommandOutput => {
+                match mux::export::last_command_output(&*pane) {
+                    Some(text) => text,
+                    None => {
+                        log::error!(
+                            "EditScrollbackInEditor: no command output has been \
+                             marked using OSC 133 in pane {}",
+                            pane.pane_id()
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        let path = mux::export::write_temp_file(pane.pane_id(), &text)?;
+
+        let mut args = mux::export::editor_command();
+        args.push(path.to_string_lossy().into_owned());
+        let direction = if edit.vertical {
+            SplitDirection::Vertical
+        } else {
+            SplitDirection::Horizontal
+        };
+        self.spawn_command(
+            &SpawnCommand {
+                args: Some(args),
+                domain: SpawnTabDomain::DomainName("local".to_string()),
+                ..Default::default()
+            },
+            SpawnWhere::SplitPane(direction),
+        );
+        Ok(())
+    }
+
     fn spawn_tab(&mut self, domain: &SpawnTabDomain) {
         self.spawn_command(
             &SpawnCommand {
@@ -1831,20 +2653,23 @@ impl TermWindow {
         );
     }
 
-    fn selection_text(&self, pane: &Rc<dyn Pane>) -> String {
+    pub fn selection_text(&self, pane: &Rc<dyn Pane>) -> String {
         let mut s = String::new();
-        if let Some(sel) = self
-            .selection(pane.pane_id())
-            .range
-            .as_ref()
-            .map(|r| r.normalize())
-        {
+        let selection = self.selection(pane.pane_id()).clone();
+        if let Some(sel) = selection.range.as_ref().map(|r| r.normalize()) {
             let mut last_was_wrapped = false;
             let (first_row, lines) = pane.get_lines(sel.rows());
             for (idx, line) in lines.iter().enumerate() {
-                let cols = sel.cols_for_row(first_row + idx as StableRowIndex);
+                let cols = selection.cols_for_row(first_row + idx as StableRowIndex);
                 let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
-                if !s.is_empty() && !last_was_wrapped {
+                // Each row of a rectangular selection is its own line,
+                // regardless of wrapping
+                let need_newline = if selection.rectangular {
+                    idx > 0
+                } else {
+                    !s.is_empty() && !last_was_wrapped
+                };
+                if need_newline {
                     s.push('\n');
                 }
                 s.push_str(line.columns_as_str(cols).trim_end());
@@ -1855,23 +2680,69 @@ impl TermWindow {
             }
         }
 
-        s
+        s
+    }
+
+    /// Copies `text` from `pane` to the clipboard, once the handler of
+    /// the `copy-intercept` event, if any, has had the opportunity to
+    /// change it or to prevent it from being copied.
+    /// `source` tells the handler how the text was chosen.
+    pub fn copy_from_pane(
+        &self,
+        pane: &Rc<dyn Pane>,
+        source: &'static str,
+        clipboard: ClipboardCopyDestination,
+        text: String,
+    ) {
+        let window = GuiWin::new(self);
+        let pane = PaneObject::new(pane);
+        let gui_window = self.window.as_ref().unwrap().clone();
+
+        async fn intercept(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: PaneObject,
+            source: &'static str,
+            text: String,
+        ) -> anyhow::Result<Option<String>> {
+            let lua = match lua {
+                Some(lua) => lua,
+                None => return Ok(Some(text)),
+            };
+            let args = lua.pack_multi((window, pane, text.clone(), source))?;
+            let result =
+                config::lua::emit_async_callback(&lua, ("copy-intercept".to_string(), args))
+                    .await?;
+            match result {
+                mlua::Value::Nil | mlua::Value::Boolean(true) => Ok(Some(text)),
+                mlua::Value::Boolean(false) => Ok(None),
+                mlua::Value::String(s) => Ok(Some(s.to_str()?.to_string())),
+                other => bail!(
+                    "copy-intercept handler returned a {}, \
+                     but a string, false or nil was expected",
+                    other.type_name()
+                ),
+            }
+        }
+
+        promise::spawn::spawn(async move {
+            let result = config::with_lua_config_on_main_thread(move |lua| {
+                intercept(lua, window, pane, source, text)
+            })
+            .await;
+            match result {
+                Ok(Some(text)) => set_clipboard(&gui_window, clipboard, text),
+                Ok(None) => log::debug!("copy-intercept prevented copying the text"),
+                // Don't copy text that the handler may have been
+                // meant to redact
+                Err(err) => log::error!("while processing copy-intercept event: {:#}", err),
+            }
+        })
+        .detach();
     }
 
-    fn copy_to_clipboard(&self, clipboard: ClipboardCopyDestination, text: String) {
-        let clipboard = match clipboard {
-            ClipboardCopyDestination::Clipboard => [Some(Clipboard::Clipboard), None],
-            ClipboardCopyDestination::PrimarySelection => [Some(Clipboard::PrimarySelection), None],
-            ClipboardCopyDestination::ClipboardAndPrimarySelection => [
-                Some(Clipboard::Clipboard),
-                Some(Clipboard::PrimarySelection),
-            ],
-        };
-        for &c in &clipboard {
-            if let Some(c) = c {
-                self.window.as_ref().unwrap().set_clipboard(c, text.clone());
-            }
-        }
+    pub fn copy_to_clipboard(&self, clipboard: ClipboardCopyDestination, text: String) {
+        set_clipboard(self.window.as_ref().unwrap(), clipboard, text);
     }
 
     fn paste_from_clipboard(&mut self, pane: &Rc<dyn Pane>, clipboard: ClipboardPasteSource) {
@@ -1887,16 +2758,8 @@ impl TermWindow {
             if let Ok(clip) = future.await {
                 window
                     .apply(move |term_window, _window| {
-                        let clip = clip.clone();
                         if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
-                            if let Some(pane) =
-                                term_window.pane_state(pane_id).overlay.clone().or_else(|| {
-                                    let mux = Mux::get().unwrap();
-                                    mux.get_pane(pane_id)
-                                })
-                            {
-                                pane.trickle_paste(clip).ok();
-                            }
+                            term_window.paste_text(pane_id, clip.clone());
                         }
                         Ok(())
                     })
@@ -1907,6 +2770,108 @@ impl TermWindow {
         .detach();
     }
 
+    /// Pastes `text` into the pane `pane_id`, or the overlay that covers
+    /// it, once the will-paste event has had the chance to change or
+    /// prevent the paste
+    pub fn paste_text(&self, pane_id: PaneId, text: String) {
+        let pane = match Mux::get().unwrap().get_pane(pane_id) {
+            Some(pane) => PaneObject::new(&pane),
+            None => return,
+        };
+        let window = GuiWin::new(self);
+        let gui_window = self.window.as_ref().unwrap().clone();
+
+        async fn intercept(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: PaneObject,
+            text: String,
+        ) -> anyhow::Result<Option<String>> {
+            let lua = match lua {
+                Some(lua) => lua,
+                None => return Ok(Some(text)),
+            };
+            let args = lua.pack_multi((window, pane, text.clone()))?;
+            let result =
+                config::lua::emit_async_callback(&lua, ("will-paste".to_string(), args)).await?;
+            match result {
+                mlua::Value::Nil | mlua::Value::Boolean(true) => Ok(Some(text)),
+                mlua::Value::Boolean(false) => Ok(None),
+                mlua::Value::String(s) => Ok(Some(s.to_str()?.to_string())),
+                other => bail!(
+                    "will-paste handler returned a {}, \
+                     but a string, false or nil was expected",
+                    other.type_name()
+                ),
+            }
+        }
+
+        promise::spawn::spawn(async move {
+            let result = config::with_lua_config_on_main_thread(move |lua| {
+                intercept(lua, window, pane, text)
+            })
+            .await;
+            match result {
+                Ok(Some(text)) => {
+                    gui_window
+                        .apply(move |term_window, _window| {
+                            if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                                term_window.paste_into_pane(pane_id, text.clone());
+                            }
+                            Ok(())
+                        })
+                        .await
+                        .ok();
+                }
+                Ok(None) => log::debug!("will-paste prevented pasting the text"),
+                // Don't paste text that the handler may have been
+                // meant to reject
+                Err(err) => log::error!("while processing will-paste event: {:#}", err),
+            }
+        })
+        .detach();
+    }
+
+    /// Sends `text` to the overlay that covers the pane `pane_id`, or
+    /// else to the pane itself.  A pane whose program hasn't enabled
+    /// bracketed paste mode can't tell pasted newlines from the user
+    /// pressing Enter, so text that contains them or other control
+    /// characters is only sent to it once the user confirms it.
+    fn paste_into_pane(&mut self, pane_id: PaneId, text: String) {
+        let overlay = self.pane_state(pane_id).overlay.clone();
+        if let Some(overlay) = overlay {
+            overlay.trickle_paste(text).ok();
+            return;
+        }
+        let pane = match Mux::get().unwrap().get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+
+        if !self.config.confirm_unsafe_paste
+            || pane.is_bracketed_paste_enabled()
+            || !paste_needs_confirmation(&text)
+        {
+            pane.trickle_paste(text).ok();
+            return;
+        }
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+            let confirmed = confirm_paste(term, &text)?;
+            Ok(if confirmed { Some(text) } else { None })
+        });
+        self.assign_overlay_for_pane(pane_id, overlay);
+        promise::spawn::spawn(async move {
+            if let Some(text) = future.await? {
+                if let Some(pane) = Mux::get().unwrap().get_pane(pane_id) {
+                    pane.trickle_paste(text)?;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        })
+        .detach();
+    }
+
     pub fn perform_key_assignment(
         &mut self,
         pane: &Rc<dyn Pane>,
@@ -1937,16 +2902,27 @@ impl TermWindow {
             ToggleFullScreen => {
                 self.window.as_ref().unwrap().toggle_fullscreen();
             }
+            ToggleDropDown => {
+                self.window.as_ref().unwrap().toggle_drop_down();
+            }
+            ToggleKeyboardShortcutsInhibit => {
+                self.window
+                    .as_ref()
+                    .unwrap()
+                    .toggle_keyboard_shortcuts_inhibit();
+            }
             Copy => {
                 let text = self.selection_text(pane);
-                self.copy_to_clipboard(
+                self.copy_from_pane(
+                    pane,
+                    "copy",
                     ClipboardCopyDestination::ClipboardAndPrimarySelection,
                     text,
                 );
             }
             CopyTo(dest) => {
                 let text = self.selection_text(pane);
-                self.copy_to_clipboard(*dest, text);
+                self.copy_from_pane(pane, "copy", *dest, text);
             }
             Paste => {
                 self.paste_from_clipboard(pane, ClipboardPasteSource::Clipboard);
@@ -1985,6 +2961,10 @@ impl TermWindow {
             MoveTabRelative(n) => self.move_tab_relative(*n)?,
             ScrollByPage(n) => self.scroll_by_page(*n)?,
             ScrollToPrompt(n) => self.scroll_to_prompt(*n)?,
+            ShowPromptMarks => self.show_prompt_marks()?,
+            SetMark => self.set_mark(),
+            JumpToMark(n) => self.jump_to_mark(*n),
+            ListMarks => self.list_marks(),
             ShowTabNavigator => self.show_tab_navigator(),
             ShowLauncher => self.show_launcher(),
             HideApplication => {
@@ -1993,7 +2973,7 @@ impl TermWindow {
             }
             QuitApplication => {
                 let mux = Mux::get().unwrap();
-                let config = configuration();
+                let config = self.config.clone();
 
                 match config.window_close_confirmation {
                     WindowCloseConfirmation::NeverPrompt => {
@@ -2001,13 +2981,20 @@ impl TermWindow {
                         con.terminate_message_loop();
                     }
                     WindowCloseConfirmation::AlwaysPrompt => {
+                        let processes = running_processes(&mux.iter_panes());
+                        if processes.is_empty() {
+                            let con = Connection::get().expect("call on gui thread");
+                            con.terminate_message_loop();
+                            return Ok(());
+                        }
+
                         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
                             Some(tab) => tab,
                             None => anyhow::bail!("no active tab!?"),
                         };
 
                         let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
-                            confirm_quit_program(term)
+                            confirm_quit_program(processes, term)
                         });
                         self.assign_overlay(tab.tab_id(), overlay);
                         promise::spawn::spawn(future).detach();
@@ -2018,6 +3005,7 @@ impl TermWindow {
             ExtendSelectionToMouseCursor(mode) => {
                 self.extend_selection_at_mouse_cursor(*mode, pane)
             }
+            DragPane => self.start_pane_drag(pane),
             OpenLinkAtMouseCursor => {
                 // They clicked on a link, so let's open it!
                 // We need to ensure that we spawn the `open` call outside of the context
@@ -2063,40 +3051,15 @@ impl TermWindow {
                     .detach();
                 }
             }
-            EmitEvent(name) => {
-                let window = GuiWin::new(self);
-                let pane = PaneObject::new(pane);
-
-                async fn emit_event(
-                    lua: Option<Rc<mlua::Lua>>,
-                    name: String,
-                    window: GuiWin,
-                    pane: PaneObject,
-                ) -> anyhow::Result<()> {
-                    if let Some(lua) = lua {
-                        let args = lua.pack_multi((window, pane))?;
-                        config::lua::emit_event(&lua, (name.clone(), args))
-                            .await
-                            .map_err(|e| {
-                                log::error!("while processing EmitEvent({}): {:#}", name, e);
-                                e
-                            })?;
-                    }
-                    Ok(())
-                }
-
-                let name = name.to_string();
-                promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
-                    emit_event(lua, name, window, pane)
-                }))
-                .detach();
-            }
+            EmitEvent(name) => self.emit_window_event(name, pane),
+            ExpandSelectionToSemanticZone => self.expand_selection_to_semantic_zone(pane),
             CompleteSelectionOrOpenLinkAtMouseCursor(dest) => {
                 let text = self.selection_text(pane);
                 if !text.is_empty() {
-                    self.copy_to_clipboard(*dest, text);
+                    self.copy_from_pane(pane, "selection", *dest, text);
                     let window = self.window.as_ref().unwrap();
                     window.invalidate();
+                    self.emit_window_event("selection-changed", pane);
                 } else {
                     return self
                         .perform_key_assignment(pane, &KeyAssignment::OpenLinkAtMouseCursor);
@@ -2105,9 +3068,10 @@ impl TermWindow {
             CompleteSelection(dest) => {
                 let text = self.selection_text(pane);
                 if !text.is_empty() {
-                    self.copy_to_clipboard(*dest, text);
+                    self.copy_from_pane(pane, "selection", *dest, text);
                     let window = self.window.as_ref().unwrap();
                     window.invalidate();
+                    self.emit_window_event("selection-changed", pane);
                 }
             }
             ClearScrollback(erase_mode) => {
@@ -2115,18 +3079,81 @@ impl TermWindow {
                 let window = self.window.as_ref().unwrap();
                 window.invalidate();
             }
+            ExportScrollback(export) => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let text =
+                        mux::export::export_scrollback(&*pane, export.format, export.timestamps);
+                    let path = export.path.clone().unwrap_or_else(|| {
+                        mux::export::default_path(pane.pane_id(), export.format)
+                    });
+                    match std::fs::write(&path, text) {
+                        Ok(()) => log::info!("exported scrollback to {}", path.display()),
+                        Err(err) => {
+                            log::error!("exporting scrollback to {}: {}", path.display(), err)
+                        }
+                    }
+                }
+            }
+            EditScrollbackInEditor(edit) => self.edit_scrollback_in_editor(edit)?,
             Search(pattern) => {
                 if let Some(pane) = self.get_active_pane_no_overlay() {
                     let search = SearchOverlay::with_pane(self, &pane, pattern.clone());
                     self.assign_overlay_for_pane(pane.pane_id(), search);
                 }
             }
+            SearchAllPanes(pattern) => self.show_global_search(pattern.clone()),
+            SearchAndSend(pattern) => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let search = SearchOverlay::with_pane_for_send(self, &pane, pattern.clone());
+                    self.assign_overlay_for_pane(pane.pane_id(), search);
+                }
+            }
             ActivateCopyMode => {
                 if let Some(pane) = self.get_active_pane_no_overlay() {
                     let copy = CopyOverlay::with_pane(self, &pane);
                     self.assign_overlay_for_pane(pane.pane_id(), copy);
                 }
             }
+            QuickSelect => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let qs = QuickSelectOverlay::with_pane(self, &pane);
+                    self.assign_overlay_for_pane(pane.pane_id(), qs);
+                }
+            }
+            ShowUrlList => self.show_url_list(),
+            ClipboardHistory => self.show_clipboard_history(),
+            ShowDebugLog => self.show_debug_log(),
+            ShowUpdateInfo => self.show_update_info(),
+            ToggleRecording => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let mux = Mux::get().unwrap();
+                    mux.toggle_recording(pane.pane_id())?;
+                    self.update_title();
+                }
+            }
+            ToggleRecordingPaused => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let mux = Mux::get().unwrap();
+                    mux.toggle_recording_paused(pane.pane_id());
+                    self.update_title();
+                }
+            }
+            StartKeyMacroRecording(name) => {
+                self.stop_key_macro_recording();
+                self.key_macro_recording = Some((name.clone(), vec![]));
+                self.update_title();
+            }
+            StopKeyMacroRecording => {
+                self.stop_key_macro_recording();
+                self.update_title();
+            }
+            ReplayKeyMacro(name) => self.replay_key_macro(pane, name),
+            ToggleLineTimestamps => {
+                self.show_line_timestamps = !self.show_line_timestamps;
+                if let Some(window) = self.window.as_ref() {
+                    window.invalidate();
+                }
+            }
             AdjustPaneSize(direction, amount) => {
                 let mux = Mux::get().unwrap();
                 let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -2166,7 +3193,7 @@ impl TermWindow {
     }
 
     fn apply_scale_change(&mut self, dimensions: &Dimensions, font_scale: f64) {
-        let config = configuration();
+        let config = self.config.clone();
         let font_size = config.font_size * font_scale;
         let theoretical_height = font_size * dimensions.dpi as f64 / 72.0;
 
@@ -2207,6 +3234,70 @@ impl TermWindow {
                 .recreate_texture_atlas(&self.fonts, &self.render_metrics, None)
                 .unwrap();
         }
+        self.update_resize_increments();
+    }
+
+    /// Returns true if any pane in this window, including those in
+    /// background tabs, is running one of idle_inhibit_processes
+    fn panes_want_idle_inhibit(&self) -> bool {
+        let config = self.config.clone();
+        if config.idle_inhibit_processes.is_empty() {
+            return false;
+        }
+        let mux = Mux::get().unwrap();
+        let window = match mux.get_window(self.mux_window_id) {
+            Some(window) => window,
+            None => return false,
+        };
+        window
+            .iter()
+            .flat_map(|tab| tab.iter_panes())
+            .filter_map(|pos| pos.pane.get_foreground_process_name())
+            .any(|name| config.idle_inhibit_processes.iter().any(|p| *p == name))
+    }
+
+    /// Asks the window to inhibit idle if either lua or one of our
+    /// panes wants it, and to release the inhibition otherwise
+    pub fn update_idle_inhibit(&mut self) {
+        self.last_idle_inhibit_check = Instant::now();
+        let inhibit = self.lua_idle_inhibit || self.panes_want_idle_inhibit();
+        if inhibit == self.idle_inhibited {
+            return;
+        }
+        if let Some(window) = self.window.as_ref() {
+            log::debug!("idle inhibit is now {}", inhibit);
+            window.set_idle_inhibited(inhibit);
+            self.idle_inhibited = inhibit;
+        }
+    }
+
+    pub fn performance_stats(&self) -> PerformanceStats {
+        self.frame_pacing.stats(Instant::now())
+    }
+
+    pub fn set_lua_idle_inhibit(&mut self, inhibit: bool) {
+        self.lua_idle_inhibit = inhibit;
+        self.update_idle_inhibit();
+    }
+
+    /// Tells the window about the cell size, so that resizing it
+    /// leaves no partial rows or columns, when that is enabled
+    fn update_resize_increments(&self) {
+        let config = self.config.clone();
+        if !config.use_resize_increments {
+            return;
+        }
+        if let Some(window) = self.window.as_ref() {
+            let cell_height = self.render_metrics.cell_size.height as u16;
+            window.set_resize_increments(ResizeIncrement {
+                x: self.render_metrics.cell_size.width as u16,
+                y: cell_height,
+                base_width: config.window_padding.left + self.effective_right_padding(&config),
+                base_height: config.window_padding.top
+                    + config.window_padding.bottom
+                    + if self.show_tab_bar { cell_height } else { 0 },
+            });
+        }
     }
 
     fn apply_dimensions(
@@ -2226,7 +3317,7 @@ impl TermWindow {
         // final size, which in that case should result in a NOP
         // change to the tab size.
 
-        let config = configuration();
+        let config = self.config.clone();
 
         let (size, dims) = if let Some(cell_dims) = scale_changed_cells {
             // Scaling preserves existing terminal dimensions, yielding a new
@@ -2279,6 +3370,7 @@ impl TermWindow {
 
         if let Some(render_state) = self.render_state.as_mut() {
             if let Err(err) = render_state.advise_of_window_size_change(
+                &self.config,
                 &self.render_metrics,
                 dimensions.pixel_width,
                 dimensions.pixel_height,
@@ -2342,7 +3434,7 @@ impl TermWindow {
     // the `adjust_window_size_when_changing_font_size` configuration and
     // revises the scaling/resize change accordingly
     fn adjust_font_scale(&mut self, font_scale: f64) {
-        if configuration().adjust_window_size_when_changing_font_size {
+        if self.config.adjust_window_size_when_changing_font_size {
             self.scaling_changed(self.dimensions, font_scale);
         } else {
             let dimensions = self.dimensions;
@@ -2376,9 +3468,14 @@ impl TermWindow {
         };
 
         let pane_id = pane.pane_id();
-        if confirm {
+        let processes = if confirm {
+            running_processes(&[Rc::clone(&pane)])
+        } else {
+            vec![]
+        };
+        if !processes.is_empty() {
             let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
-                confirm_close_pane(pane_id, term, mux_window_id)
+                confirm_close_pane(pane_id, processes, term, mux_window_id)
             });
             self.assign_overlay_for_pane(pane_id, overlay);
             promise::spawn::spawn(future).detach();
@@ -2395,9 +3492,16 @@ impl TermWindow {
         };
         let tab_id = tab.tab_id();
         let mux_window_id = self.mux_window_id;
-        if confirm {
+        let processes = if confirm {
+            let panes: Vec<Rc<dyn Pane>> =
+                tab.iter_panes().into_iter().map(|pos| pos.pane).collect();
+            running_processes(&panes)
+        } else {
+            vec![]
+        };
+        if !processes.is_empty() {
             let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
-                confirm_close_tab(tab_id, term, mux_window_id)
+                confirm_close_tab(tab_id, processes, term, mux_window_id)
             });
             self.assign_overlay(tab_id, overlay);
             promise::spawn::spawn(future).detach();
@@ -2425,16 +3529,16 @@ impl TermWindow {
         split: &PositionedSplit,
         pane: &Rc<dyn Pane>,
     ) -> anyhow::Result<()> {
+        let palette = self.pane_palette(pane);
         let gl_state = self.render_state.as_ref().unwrap();
-        let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
-        let mut quads = gl_state.quads.map(&mut vb);
-        let config = configuration();
+        let mut vertices = gl_state.vertices.borrow_mut();
+        let mut quads = gl_state.quads.map(&mut vertices);
+        let config = self.config.clone();
         let text = if split.direction == SplitDirection::Horizontal {
             "│"
         } else {
             "─"
         };
-        let palette = pane.palette();
         let foreground = rgbcolor_to_window_color(palette.split);
         let background = rgbcolor_alpha_to_window_color(
             palette.background,
@@ -2556,8 +3660,8 @@ impl TermWindow {
     }
 
     fn paint_pane_opengl(&mut self, pos: &PositionedPane) -> anyhow::Result<()> {
-        let config = configuration();
-        let palette = pos.pane.palette();
+        let config = self.config.clone();
+        let palette = self.pane_palette(&pos.pane);
 
         let background_color = palette.resolve_bg(wezterm_term::color::ColorAttribute::Default);
         let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
@@ -2583,8 +3687,8 @@ impl TermWindow {
         }
 
         let gl_state = self.render_state.as_ref().unwrap();
-        let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
-        let mut quads = gl_state.quads.map(&mut vb);
+        let mut vertices = gl_state.vertices.borrow_mut();
+        let mut quads = gl_state.quads.map(&mut vertices);
 
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
         let foreground = rgbcolor_to_window_color(palette.foreground);
@@ -2608,6 +3712,7 @@ impl TermWindow {
                     foreground,
                     pos,
                     is_active: true,
+                    failed_command: false,
                 },
                 &mut quads,
             )?;
@@ -2641,7 +3746,7 @@ impl TermWindow {
             let top = (self.dimensions.pixel_height as f32 / -2.0) + thumb_top;
             let bottom = top + thumb_size;
 
-            let config = configuration();
+            let config = self.config.clone();
             let padding = self.effective_right_padding(&config) as f32;
 
             let right = self.dimensions.pixel_width as f32 / 2.;
@@ -2660,6 +3765,36 @@ impl TermWindow {
             quad.set_has_color(false);
             quad.set_cursor(white_space);
             quad.set_cursor_color(rgbcolor_to_window_color(background_color));
+
+            // Draw the bookmarks as short ticks over the scrollbar;
+            // any quads that are left over are collapsed out of sight
+            let marks = if self.show_scroll_bar {
+                self.marks(&pos.pane)
+            } else {
+                vec![]
+            };
+            let mark_color = rgbcolor_to_window_color(palette.cursor_bg);
+            for idx in 0..MAX_SCROLL_MARKS {
+                let mut quad = quads.scroll_mark(idx)?;
+                match marks.get(idx) {
+                    Some(row) => {
+                        let top = (self.dimensions.pixel_height as f32 / -2.0)
+                            + ScrollHit::row_to_top(&*pos.pane, *row, self.terminal_size) as f32;
+                        quad.set_position(left, top, right, top + 2.);
+                    }
+                    None => quad.set_position(0., 0., 0., 0.),
+                }
+                quad.set_bg_color(mark_color);
+                quad.set_fg_color(mark_color);
+                quad.set_underline_color(mark_color);
+                quad.set_texture(white_space);
+                quad.set_texture_adjust(0., 0., 0., 0.);
+                quad.set_hsv(None);
+                quad.set_underline(white_space);
+                quad.set_has_color(false);
+                quad.set_cursor(white_space);
+                quad.set_cursor_color(rgbcolor_to_window_color(background_color));
+            }
         }
 
         {
@@ -2687,13 +3822,69 @@ impl TermWindow {
             quad.set_bg_color(color);
         }
 
-        let selrange = self.selection(pos.pane.pane_id()).range.clone();
+        let selection = self.selection(pos.pane.pane_id()).clone();
+        let drop_highlight = self.pane_drop_highlight(pos);
+
+        let failed_prompts: Vec<StableRowIndex> = if config.mark_failed_commands {
+            pos.pane
+                .get_prompt_marks(stable_top..stable_top + lines.len() as StableRowIndex)
+                .unwrap_or_else(|_| vec![])
+                .into_iter()
+                .filter(|mark| mark.status.map(|status| status != 0).unwrap_or(false))
+                .map(|mark| mark.start_y)
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let now = mux::export::now_millis();
+        let decoration_attrs = CellAttributes::default()
+            .set_intensity(termwiz::cell::Intensity::Half)
+            .clone();
 
         for (line_idx, line) in lines.iter().enumerate() {
             let stable_row = stable_top + line_idx as StableRowIndex;
-            let selrange = selrange
-                .map(|sel| sel.cols_for_row(stable_row))
-                .unwrap_or(0..0);
+            let selrange = match &drop_highlight {
+                Some((cols, rows)) if rows.contains(&line_idx) => cols.clone(),
+                _ => selection.cols_for_row(stable_row),
+            };
+
+            // The repeat count of a folded line follows its text, while
+            // the timestamp gutter is drawn over the right hand end of
+            // the line, as the cell grid leaves no room for a margin
+            let timestamp = line.timestamp().filter(|_| self.show_line_timestamps);
+            let mut decorated_line;
+            let line = if line.repeat_count() > 0 || timestamp.is_some() {
+                decorated_line = line.clone();
+                decorated_line.resize(dims.cols);
+                if line.repeat_count() > 0 {
+                    let text = mux::export::repeat_marker(line.repeat_count());
+                    let width = termwiz::cell::unicode_column_width(&text);
+                    decorated_line.overlay_text_with_attribute(
+                        mux::export::text_width(line).min(dims.cols.saturating_sub(width)),
+                        &text,
+                        decoration_attrs.clone(),
+                    );
+                }
+                if let Some(timestamp) = timestamp {
+                    let text = format!(
+                        " {:>8}",
+                        mux::export::format_line_timestamp(
+                            timestamp,
+                            config.line_timestamp_format,
+                            now
+                        )
+                    );
+                    decorated_line.overlay_text_with_attribute(
+                        dims.cols.saturating_sub(text.len()),
+                        &text,
+                        decoration_attrs.clone(),
+                    );
+                }
+                &decorated_line
+            } else {
+                line
+            };
 
             self.render_screen_line_opengl(
                 RenderScreenLineOpenGLParams {
@@ -2709,6 +3900,7 @@ impl TermWindow {
                     foreground,
                     pos,
                     is_active: pos.is_active,
+                    failed_command: failed_prompts.contains(&stable_row),
                 },
                 &mut quads,
             )?;
@@ -2719,6 +3911,7 @@ impl TermWindow {
 
     fn call_draw(&mut self, frame: &mut glium::Frame) -> anyhow::Result<()> {
         let gl_state = self.render_state.as_ref().unwrap();
+        self.damage = self.damage_rects(gl_state.upload_vertices());
         let vb = gl_state.glyph_vertex_buffer.borrow_mut();
 
         let tex = gl_state.glyph_cache.borrow().atlas.texture();
@@ -2833,6 +4026,31 @@ impl TermWindow {
         Ok(())
     }
 
+    /// Converts the rows whose quads changed into the parts of the
+    /// window that they occupy.  Glyphs can spill over into the
+    /// neighboring rows, so those are included too.
+    fn damage_rects(&self, rows: Option<Vec<usize>>) -> Option<Vec<Rect>> {
+        let config = self.config.clone();
+        let cell_height = self.render_metrics.cell_size.height;
+        let pixel_height = self.dimensions.pixel_height as isize;
+        let mut rects: Vec<Rect> = vec![];
+        for row in rows? {
+            let row_top = config.window_padding.top as isize + row as isize * cell_height;
+            let top = (row_top - cell_height).max(0);
+            let bottom = (row_top + 2 * cell_height).min(pixel_height);
+            match rects.last_mut() {
+                Some(prior) if prior.max_y() >= top => {
+                    prior.size.height = bottom - prior.origin.y;
+                }
+                _ => rects.push(Rect::new(
+                    Point::new(0, top),
+                    Size::new(self.dimensions.pixel_width as isize, bottom - top),
+                )),
+            }
+        }
+        Some(rects)
+    }
+
     fn lookup_cached_shape(
         &self,
         key: &dyn ShapeCacheKeyTrait,
@@ -3070,6 +4288,7 @@ impl TermWindow {
                         palette: params.palette,
                         is_active_pane: params.pos.is_active,
                         config: params.config,
+                        failed_command: params.failed_command,
                     });
 
                     if let Some(image) = attrs.image() {
@@ -3225,6 +4444,7 @@ impl TermWindow {
                 palette: params.palette,
                 is_active_pane: params.pos.is_active,
                 config: params.config,
+                failed_command: params.failed_command,
             });
 
             let mut quad =
@@ -3317,6 +4537,11 @@ impl TermWindow {
                 rgbcolor_to_window_color(params.palette.cursor_fg),
                 rgbcolor_to_window_color(params.palette.cursor_bg),
             ),
+            // Mark the prompt of a failed command using the ANSI red color
+            _ if params.failed_command => (
+                params.fg_color,
+                rgbcolor_to_window_color(params.palette.colors.0[1]),
+            ),
             // Normally, render the cell as configured (or if the window is unfocused)
             _ => (params.fg_color, params.bg_color),
         };
@@ -3381,6 +4606,8 @@ impl TermWindow {
                     search_overlay.viewport_changed(pos);
                 } else if let Some(copy) = overlay.downcast_ref::<CopyOverlay>() {
                     copy.viewport_changed(pos);
+                } else if let Some(qs) = overlay.downcast_ref::<QuickSelectOverlay>() {
+                    qs.viewport_changed(pos);
                 }
             }
             self.window.as_ref().unwrap().invalidate();
@@ -3392,17 +4619,34 @@ impl TermWindow {
             WMEK::Press(MousePress::Left) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(tab_idx) => {
                     self.activate_tab(tab_idx as isize).ok();
+                    self.tab_drag = Some(TabDrag {
+                        tab_idx,
+                        torn: false,
+                    });
                 }
                 TabBarItem::NewTabButton => {
                     self.spawn_tab(&SpawnTabDomain::CurrentPaneDomain);
                 }
+                TabBarItem::Button(button_idx) => {
+                    let action = self
+                        .config
+                        .tab_bar_buttons
+                        .get(button_idx)
+                        .map(|button| button.action.clone());
+                    if let (Some(action), Some(pane)) = (action, self.get_active_pane_or_overlay())
+                    {
+                        if let Err(err) = self.perform_key_assignment(&pane, &action) {
+                            log::error!("tab bar button: {:#}", err);
+                        }
+                    }
+                }
                 TabBarItem::None => {}
             },
             WMEK::Press(MousePress::Middle) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(tab_idx) => {
                     self.close_tab_idx(tab_idx).ok();
                 }
-                TabBarItem::NewTabButton | TabBarItem::None => {}
+                TabBarItem::NewTabButton | TabBarItem::Button(_) | TabBarItem::None => {}
             },
             WMEK::Press(MousePress::Right) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(_) => {
@@ -3411,7 +4655,7 @@ impl TermWindow {
                 TabBarItem::NewTabButton => {
                     self.show_launcher();
                 }
-                TabBarItem::None => {}
+                TabBarItem::Button(_) | TabBarItem::None => {}
             },
             _ => {}
         }
@@ -3419,6 +4663,162 @@ impl TermWindow {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
 
+    /// Tells the front end where our tab bar is on the screen, so that
+    /// tabs that are dragged out of other windows can be dropped onto it
+    fn update_tab_bar_rect(&self, event: &MouseEvent) {
+        let fe = match super::front_end() {
+            Some(fe) => fe,
+            None => return,
+        };
+        let rect = if self.show_tab_bar && Connection::get().unwrap().has_screen_coordinates() {
+            let config = self.config.clone();
+            Some(ScreenRect::new(
+                ScreenPoint::new(
+                    event.screen_coords.x - event.coords.x,
+                    event.screen_coords.y - event.coords.y,
+                ),
+                euclid::size2(
+                    self.dimensions.pixel_width as isize,
+                    config.window_padding.top as isize + self.render_metrics.cell_size.height,
+                ),
+            ))
+        } else {
+            None
+        };
+        fe.set_tab_bar_rect(self.mux_window_id, rect);
+    }
+
+    /// Moves a tab that was dragged out of the tab bar into the window
+    /// whose tab bar it was dropped onto, or otherwise into a new window
+    fn drop_torn_tab(&mut self, tab_idx: usize, event: &MouseEvent) {
+        let mux = Mux::get().unwrap();
+        let (tab_id, num_tabs) = match mux.get_window(self.mux_window_id) {
+            Some(window) => match window.get_by_idx(tab_idx) {
+                Some(tab) => (tab.tab_id(), window.len()),
+                None => return,
+            },
+            None => return,
+        };
+
+        let target = if Connection::get().unwrap().has_screen_coordinates() {
+            super::front_end()
+                .and_then(|fe| fe.window_with_tab_bar_at(event.screen_coords))
+                .filter(|&mux_window_id| mux_window_id != self.mux_window_id)
+        } else {
+            None
+        };
+        if target.is_none() && num_tabs == 1 {
+            // It is already on its own
+            return;
+        }
+
+        if let Err(err) = mux.move_tab_to_window(tab_id, target) {
+            log::error!("Failed to move tab {} to another window: {:#}", tab_id, err);
+        }
+    }
+
+    fn start_pane_drag(&mut self, pane: &Rc<dyn Pane>) {
+        if self.pane_drag.is_some() {
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        // Overlays aren't part of the pane tree, and the layout of
+        // panes in a remote mux belongs to the server
+        let is_movable = mux.resolve_pane_id(pane.pane_id()).is_some()
+            && mux
+                .get_domain(pane.domain_id())
+                .map(|domain| domain.downcast_ref::<ClientDomain>().is_none())
+                .unwrap_or(false);
+        if is_movable {
+            self.pane_drag = Some(PaneDrag {
+                pane_id: pane.pane_id(),
+                target: None,
+            });
+        }
+    }
+
+    /// Updates the drop target of the pane drag for the pointer
+    /// being over cell `x`, `y` of the terminal area
+    fn update_pane_drag(&mut self, x: usize, y: i64, in_tab_bar: bool) {
+        let target = if in_tab_bar {
+            // Hovering over a tab switches to it, so that the pane
+            // can be dropped into a different tab
+            if let TabBarItem::Tab(tab_idx) = self.tab_bar.hit_test(x) {
+                self.activate_tab(tab_idx as isize).ok();
+            }
+            None
+        } else {
+            self.pane_drop_target(x, y)
+        };
+        if let Some(drag) = self.pane_drag.as_mut() {
+            drag.target = target;
+        }
+    }
+
+    fn pane_drop_target(&self, x: usize, y: i64) -> Option<PaneDropTarget> {
+        let drag_pane_id = self.pane_drag.as_ref()?.pane_id;
+        if y < 0 {
+            return None;
+        }
+        let y = y as usize;
+
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_active_tab_for_window(self.mux_window_id)?;
+        if self.tab_state(tab.tab_id()).overlay.is_some() {
+            return None;
+        }
+        let pos = tab.iter_panes().into_iter().find(|pos| {
+            x >= pos.left && x < pos.left + pos.width && y >= pos.top && y < pos.top + pos.height
+        })?;
+        if pos.pane.pane_id() == drag_pane_id {
+            return None;
+        }
+
+        // Land on whichever side of the pane the pointer is closest to
+        let fx = (x - pos.left) as f32 / pos.width.max(1) as f32;
+        let fy = (y - pos.top) as f32 / pos.height.max(1) as f32;
+        let side = [
+            (fx, PaneDirection::Left),
+            (1. - fx, PaneDirection::Right),
+            (fy, PaneDirection::Up),
+            (1. - fy, PaneDirection::Down),
+        ]
+        .iter()
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, side)| *side)?;
+
+        Some(PaneDropTarget {
+            pane_id: pos.pane.pane_id(),
+            pane_index: pos.index,
+            side,
+        })
+    }
+
+    /// Returns the columns and rows of the pane at `pos` that are
+    /// highlighted to show where a dragged pane will land
+    fn pane_drop_highlight(&self, pos: &PositionedPane) -> Option<(Range<usize>, Range<usize>)> {
+        let target = self.pane_drag.as_ref()?.target?;
+        if target.pane_index != pos.index {
+            return None;
+        }
+        let (width, height) = (pos.width, pos.height);
+        Some(match target.side {
+            PaneDirection::Left => (0..width / 2, 0..height),
+            PaneDirection::Right => (width / 2..width, 0..height),
+            PaneDirection::Up => (0..width, 0..height / 2),
+            PaneDirection::Down => (0..width, height / 2..height),
+        })
+    }
+
+    fn drop_pane(&mut self, drag: PaneDrag) {
+        if let Some(target) = drag.target {
+            let mux = Mux::get().unwrap();
+            if let Err(err) = mux.move_pane_beside(drag.pane_id, target.pane_id, target.side) {
+                log::error!("Failed to move pane {}: {:#}", drag.pane_id, err);
+            }
+        }
+    }
+
     fn mouse_event_scroll_bar(
         &mut self,
         pane: Rc<dyn Pane>,
@@ -3565,8 +4965,53 @@ impl TermWindow {
         self.window.as_ref().unwrap().invalidate();
     }
 
+    /// Grows the selection to the semantic zones that it touches, or
+    /// to the command around them if it already covers them, and copies
+    /// the result as for copy-on-select.  Without a selection, selects
+    /// the output of the most recent command.
+    fn expand_selection_to_semantic_zone(&mut self, pane: &Rc<dyn Pane>) {
+        let zones = match pane.get_semantic_zones() {
+            Ok(zones) => zones,
+            Err(err) => {
+                log::error!("ExpandSelectionToSemanticZone: {:#}", err);
+                return;
+            }
+        };
+        let selection_range = match self.selection(pane.pane_id()).range {
+            Some(range) => range.expand_to_zones(&zones),
+            None => match zones
+                .iter()
+                .rev()
+                .find(|zone| zone.semantic_type == SemanticType::Output)
+            {
+                Some(zone) => SelectionRange::from_zone(zone),
+                None => return,
+            },
+        };
+
+        {
+            let mut selection = self.selection(pane.pane_id());
+            selection.rectangular = false;
+            selection.start = Some(selection_range.start);
+            selection.range = Some(selection_range);
+        }
+
+        let text = self.selection_text(pane);
+        if !text.is_empty() {
+            self.copy_from_pane(
+                pane,
+                "selection",
+                self.config.copy_on_select_destination(),
+                text,
+            );
+            self.emit_window_event("selection-changed", pane);
+        }
+        self.window.as_ref().unwrap().invalidate();
+    }
+
     fn select_text_at_mouse_cursor(&mut self, mode: SelectionMode, pane: &Rc<dyn Pane>) {
         let (x, y) = self.last_mouse_terminal_coords;
+        self.selection(pane.pane_id()).rectangular = false;
         match mode {
             SelectionMode::Line => {
                 let start = SelectionCoordinate { x, y };
@@ -3827,8 +5272,61 @@ impl TermWindow {
         }
     }
 
+    fn record_key_macro_step(&mut self, step: MacroStep) {
+        if let Some((_, steps)) = self.key_macro_recording.as_mut() {
+            steps.push(step);
+        }
+    }
+
+    /// Saves the macro that is being recorded, if any.  A recording
+    /// without any keys leaves the macro of that name unchanged.
+    fn stop_key_macro_recording(&mut self) {
+        if let Some((name, steps)) = self.key_macro_recording.take() {
+            if steps.is_empty() {
+                log::warn!("No keys were recorded for key macro {}", name);
+            } else if let Err(err) = set_key_macro(&name, Some(steps)) {
+                log::error!("Unable to save key macro {}: {:#}", name, err);
+            }
+        }
+    }
+
+    pub fn recording_key_macro(&self) -> Option<&str> {
+        self.key_macro_recording
+            .as_ref()
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Sends the keys of the macro `name` to `pane`.  While another
+    /// macro is being recorded, they are recorded as part of it.
+    fn replay_key_macro(&mut self, pane: &Rc<dyn Pane>, name: &str) {
+        let steps = match get_key_macro(name) {
+            Some(steps) => steps,
+            None => {
+                log::error!("There is no key macro named {}", name);
+                return;
+            }
+        };
+        for step in steps {
+            let result = match &step {
+                MacroStep::Key { key, mods } => pane.key_down(*key, *mods),
+                MacroStep::Text { text } => pane
+                    .writer()
+                    .write_all(text.as_bytes())
+                    .map_err(anyhow::Error::from),
+            };
+            if let Err(err) = result {
+                log::error!("While replaying key macro {}: {:#}", name, err);
+                return;
+            }
+            self.record_key_macro_step(step);
+        }
+        if self.pane_state(pane.pane_id()).overlay.is_none() {
+            self.maybe_scroll_to_bottom_for_input(pane);
+        }
+    }
+
     fn maybe_scroll_to_bottom_for_input(&mut self, pane: &Rc<dyn Pane>) {
-        if configuration().scroll_to_bottom_on_input {
+        if self.config.scroll_to_bottom_on_input {
             self.scroll_to_bottom(pane);
         }
     }
@@ -3953,6 +5451,21 @@ impl TermWindow {
         });
     }
 
+    /// Shows an overlay on `pane` that is drawn by lua code, which
+    /// sends it `requests` and receives its input from `events`
+    pub fn show_scripted_overlay(
+        &mut self,
+        pane: &Rc<dyn Pane>,
+        requests: std::sync::mpsc::Receiver<OverlayRequest>,
+        events: smol::channel::Sender<termwiz::input::InputEvent>,
+    ) {
+        let (overlay, future) = start_overlay_pane(self, pane, move |_pane_id, term| {
+            scripted_overlay(term, requests, events)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     pub fn assign_overlay_for_pane(&mut self, pane_id: PaneId, overlay: Rc<dyn Pane>) {
         self.pane_state(pane_id).overlay.replace(overlay);
         self.update_title();
@@ -3,7 +3,6 @@ use ::window::bitmaps::atlas::{OutOfTextureSpace, Sprite};
 use ::window::bitmaps::{BitmapImage, Image, Texture2d};
 use ::window::*;
 use anyhow::Context;
-use config::configuration;
 use std::rc::Rc;
 use termwiz::surface::CursorShape;
 use wezterm_font::units::*;
@@ -25,7 +24,7 @@ impl RenderMetrics {
             .default_font_metrics()
             .context("failed to get font metrics!?")?;
 
-        let line_height = configuration().line_height;
+        let line_height = fonts.config().line_height;
 
         let (cell_height, cell_width) = (
             (metrics.cell_height.get() * line_height).ceil() as usize,
@@ -15,10 +15,11 @@ use wezterm_client::domain::{ClientDomain, ClientDomainConfig};
 use wezterm_gui_subcommands::*;
 use wezterm_toast_notification::*;
 
+mod crashreport;
 mod gui;
 mod markdown;
+mod notifications;
 mod scripting;
-mod stats;
 mod update;
 mod window_config;
 
@@ -55,7 +56,12 @@ enum SubCommand {
 async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
     // Establish the connection; it may show UI for authentication
     let params = &opts.user_at_host_and_port;
-    let sess = mux::ssh::async_ssh_connect(&params.host_and_port, &params.username).await?;
+    let sess = mux::ssh::async_ssh_connect(
+        opts.jump.as_deref(),
+        &params.host_and_port,
+        &params.username,
+    )
+    .await?;
     // Now we have a connected session, set up the ssh domain and make it
     // the default domain
     let _gui = front_end().unwrap();
@@ -68,11 +74,17 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
     };
 
     let config = config::configuration();
-    let pty_system = Box::new(portable_pty::ssh::SshSession::new(sess, &config.term));
-    let domain: Arc<dyn Domain> = Arc::new(mux::ssh::RemoteSshDomain::with_pty_system(
-        &opts.user_at_host_and_port.to_string(),
-        pty_system,
+    let pty_system = Box::new(portable_pty::ssh::SshSession::new(
+        sess.clone(),
+        &config.term,
     ));
+    let domain: Arc<dyn Domain> = Arc::new(
+        mux::ssh::RemoteSshDomain::with_pty_system(
+            &opts.user_at_host_and_port.to_string(),
+            pty_system,
+        )
+        .with_sftp_session(sess),
+    );
 
     let mux = Mux::get().unwrap();
     mux.add_domain(&domain);
@@ -124,6 +136,34 @@ fn run_serial(config: config::ConfigHandle, opts: &SerialCommand) -> anyhow::Res
     if let Some(baud) = opts.baud {
         serial.set_baud_rate(serial::BaudRate::from_speed(baud));
     }
+    if let Some(parity) = opts.parity.as_deref() {
+        serial.set_parity(match parity {
+            "odd" => serial::Parity::ParityOdd,
+            "even" => serial::Parity::ParityEven,
+            _ => serial::Parity::ParityNone,
+        });
+    }
+    if let Some(bits) = opts.data_bits {
+        serial.set_char_size(match bits {
+            5 => serial::CharSize::Bits5,
+            6 => serial::CharSize::Bits6,
+            7 => serial::CharSize::Bits7,
+            _ => serial::CharSize::Bits8,
+        });
+    }
+    if let Some(bits) = opts.stop_bits {
+        serial.set_stop_bits(match bits {
+            2 => serial::StopBits::Stop2,
+            _ => serial::StopBits::Stop1,
+        });
+    }
+    if let Some(flow) = opts.flow_control.as_deref() {
+        serial.set_flow_control(match flow {
+            "none" => serial::FlowControl::FlowNone,
+            "hardware" => serial::FlowControl::FlowHardware,
+            _ => serial::FlowControl::FlowSoftware,
+        });
+    }
 
     let pty_system = Box::new(serial);
     let domain: Arc<dyn Domain> = Arc::new(LocalDomain::with_pty_system("local", pty_system));
@@ -162,17 +202,26 @@ fn client_domains(config: &config::ConfigHandle) -> Vec<ClientDomainConfig> {
 }
 
 fn run_mux_client(config: config::ConfigHandle, opts: &ConnectCommand) -> anyhow::Result<()> {
-    let client_config = client_domains(&config)
-        .into_iter()
-        .find(|c| c.name() == opts.domain_name)
-        .ok_or_else(|| {
-            anyhow!(
-                "no multiplexer domain with name `{}` was found in the configuration",
-                opts.domain_name
-            )
-        })?;
-
-    let domain: Arc<dyn Domain> = Arc::new(ClientDomain::new(client_config));
+    // `tmux:SESSION` runs tmux in control mode in a local pane; its
+    // windows are then presented as tabs by the TmuxDomain that
+    // gets created when tmux announces control mode
+    let tmux_session = opts.domain_name.strip_prefix("tmux:");
+
+    let domain: Arc<dyn Domain> = match tmux_session {
+        Some(_) => Arc::new(LocalDomain::new("local")?),
+        None => {
+            let client_config = client_domains(&config)
+                .into_iter()
+                .find(|c| c.name() == opts.domain_name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "no multiplexer domain with name `{}` was found in the configuration",
+                        opts.domain_name
+                    )
+                })?;
+            Arc::new(ClientDomain::new(client_config))
+        }
+    };
     let mux = Rc::new(mux::Mux::new(Some(domain.clone())));
     Mux::set_mux(&mux);
     crate::update::load_last_release_info_and_set_banner();
@@ -181,7 +230,15 @@ fn run_mux_client(config: config::ConfigHandle, opts: &ConnectCommand) -> anyhow
     let gui = crate::gui::try_new(front_end_selection)?;
     let opts = opts.clone();
 
-    let cmd = if !opts.prog.is_empty() {
+    let cmd = if let Some(session) = tmux_session {
+        let mut builder = CommandBuilder::new("tmux");
+        builder.args(&["-CC", "new-session", "-A"]);
+        if !session.is_empty() {
+            builder.args(&["-s", session]);
+        }
+        builder.args(&opts.prog);
+        Some(builder)
+    } else if !opts.prog.is_empty() {
         let builder = CommandBuilder::from_argv(opts.prog);
         Some(builder)
     } else {
@@ -247,6 +304,15 @@ async fn async_run_terminal_gui(
         }
     }
 
+    let snapshot_path = mux::snapshot::default_snapshot_path("gui");
+    mux::snapshot::start_snapshots(snapshot_path.clone());
+    if cmd.is_none()
+        && mux.is_empty()
+        && mux::snapshot::restore_on_startup(&snapshot_path, &mux.default_domain()).await
+    {
+        return Ok(());
+    }
+
     spawn_tab_in_default_domain_if_mux_is_empty(cmd).await
 }
 
@@ -254,6 +320,9 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow:
     if let Some(cls) = opts.class.as_ref() {
         crate::gui::set_window_class(cls);
     }
+    if opts.drop_down {
+        crate::window_config::force_drop_down();
+    }
 
     let unix_socket_path =
         config::RUNTIME_DIR.join(format!("gui-sock-{}", unsafe { libc::getpid() }));
@@ -344,7 +413,7 @@ fn notify_on_panic() {
 
 fn terminate_with_error_message(err: &str) -> ! {
     log::error!("{}; terminating", err);
-    fatal_toast_notification("Wezterm Error", &err);
+    fatal_toast_notification(&config::i18n::tr("error-notification-title"), &err);
     std::process::exit(1);
 }
 
@@ -404,11 +473,15 @@ fn run() -> anyhow::Result<()> {
 
     env_bootstrap::bootstrap();
 
-    stats::Stats::init()?;
+    mux::stats::Stats::init()?;
+    config::assign_lua_factory(scripting::make_lua_context);
     let _saver = umask::UmaskSaver::new();
 
     let opts = Opt::from_args();
     if !opts.skip_config {
+        // Check the fonts that the previous run found in font_dirs
+        // while the configuration is being evaluated
+        wezterm_font::db::prescan_font_dirs();
         config::reload();
     }
     let config = config::configuration();
@@ -0,0 +1,165 @@
+//! Shows the desktop notifications that applications request using
+//! OSC 9, OSC 777 and OSC 99.  The request is checked against the
+//! focus of the pane, the do-not-disturb schedule and the
+//! notification_rules from the configuration before being passed to
+//! the native notification system.
+use ::window::{Window, WindowOps};
+use chrono::NaiveTime;
+use config::{configuration, DoNotDisturbSchedule, NotificationAction};
+use mux::pane::{Pane, PaneId};
+use mux::Mux;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::Duration;
+use wezterm_term::ToastNotification;
+
+/// How long a notification is shown for
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn parse_time(time: &str) -> anyhow::Result<NaiveTime> {
+    Ok(NaiveTime::parse_from_str(time.trim(), "%H:%M")?)
+}
+
+/// Returns true if `now` falls within the do-not-disturb schedule
+fn in_do_not_disturb(schedule: &DoNotDisturbSchedule, now: NaiveTime) -> anyhow::Result<bool> {
+    let start = parse_time(&schedule.start)?;
+    let end = parse_time(&schedule.end)?;
+    Ok(if start <= end {
+        now >= start && now < end
+    } else {
+        // The period spans midnight
+        now >= start || now < end
+    })
+}
+
+/// Decides whether a notification from `pane` should be shown
+fn should_show(pane: &Rc<dyn Pane>, toast: &ToastNotification, pane_focused: bool) -> bool {
+    if toast.only_when_unfocused && pane_focused {
+        return false;
+    }
+
+    let config = configuration();
+    if let Some(schedule) = &config.notification_do_not_disturb {
+        match in_do_not_disturb(schedule, chrono::Local::now().time()) {
+            Ok(true) => {
+                log::debug!("not showing notification during do-not-disturb");
+                return false;
+            }
+            Ok(false) => {}
+            Err(err) => log::error!("invalid notification_do_not_disturb: {:#}", err),
+        }
+    }
+
+    let mux = Mux::get().unwrap();
+    let domain = mux
+        .get_domain(pane.domain_id())
+        .map(|domain| domain.domain_name().to_string())
+        .unwrap_or_default();
+    let pane_title = pane.get_title();
+    let title = toast.title.as_deref().unwrap_or("");
+    match config
+        .notification_rules
+        .iter()
+        .find(|rule| rule.matches(&domain, &pane_title, title, &toast.body))
+    {
+        Some(rule) => rule.action == NotificationAction::Show,
+        None => true,
+    }
+}
+
+/// Called when the notification is clicked.  Activates the pane
+/// and/or reports the click back to the application, as it requested.
+fn activate(window: Window, pane_id: PaneId, focus: bool, report_id: Option<String>) {
+    let mux = Mux::get().unwrap();
+    let pane = match mux.get_pane(pane_id) {
+        Some(pane) => pane,
+        None => return,
+    };
+
+    if focus {
+        if let Some((_domain_id, window_id, tab_id)) = mux.resolve_pane_id(pane_id) {
+            if let Some(tab) = mux.get_tab(tab_id) {
+                if let Some(mut mux_window) = mux.get_window_mut(window_id) {
+                    if let Some(idx) = mux_window.idx_by_id(tab_id) {
+                        mux_window.set_active(idx);
+                    }
+                }
+                tab.set_active_pane(&pane);
+            }
+        }
+        window.show();
+        window.focus();
+        window.invalidate();
+    }
+
+    if let Some(id) = report_id {
+        if let Err(err) = write!(pane.writer(), "\x1b]99;i={};\x1b\\", id) {
+            log::error!("failed to report notification activation: {:#}", err);
+        }
+    }
+}
+
+/// Shows `toast`, which was requested by `pane`, unless the
+/// configuration says otherwise.  `window` is the gui window that
+/// contains the pane, and `pane_focused` indicates whether it is
+/// the active pane of that window and the window has focus.
+pub fn show_toast(
+    window: Window,
+    pane: &Rc<dyn Pane>,
+    toast: ToastNotification,
+    pane_focused: bool,
+) {
+    if !should_show(pane, &toast, pane_focused) {
+        return;
+    }
+
+    let title = toast.title.clone().unwrap_or_else(|| pane.get_title());
+    let mut notif = wezterm_toast_notification::ToastNotification::new(&title, &toast.body);
+    notif.timeout = Some(NOTIFICATION_TIMEOUT);
+    if toast.focus || toast.report_id.is_some() {
+        let pane_id = pane.pane_id();
+        let focus = toast.focus;
+        let report_id = toast.report_id;
+        notif.on_click = Some(Box::new(move || {
+            promise::spawn::spawn_into_main_thread(async move {
+                activate(window, pane_id, focus, report_id);
+            })
+            .detach();
+        }));
+    }
+    wezterm_toast_notification::show(notif);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schedule(start: &str, end: &str) -> DoNotDisturbSchedule {
+        DoNotDisturbSchedule {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    fn at(time: &str) -> NaiveTime {
+        parse_time(time).unwrap()
+    }
+
+    #[test]
+    fn do_not_disturb() {
+        let day = schedule("09:00", "17:30");
+        assert!(!in_do_not_disturb(&day, at("08:59")).unwrap());
+        assert!(in_do_not_disturb(&day, at("09:00")).unwrap());
+        assert!(in_do_not_disturb(&day, at("17:29")).unwrap());
+        assert!(!in_do_not_disturb(&day, at("17:30")).unwrap());
+
+        let night = schedule("22:00", "07:00");
+        assert!(in_do_not_disturb(&night, at("23:15")).unwrap());
+        assert!(in_do_not_disturb(&night, at("00:00")).unwrap());
+        assert!(in_do_not_disturb(&night, at("06:59")).unwrap());
+        assert!(!in_do_not_disturb(&night, at("07:00")).unwrap());
+        assert!(!in_do_not_disturb(&night, at("12:00")).unwrap());
+
+        assert!(in_do_not_disturb(&schedule("25:00", "07:00"), at("00:00")).is_err());
+    }
+}
@@ -1,10 +1,10 @@
 //! GuiWin represents a Gui TermWindow (as opposed to a Mux window) in lua code
 use super::luaerr;
 use super::pane::PaneObject;
-use crate::gui::TermWindow;
+use crate::gui::{key_macros, set_key_macro, MacroStep, TermWindow};
 use anyhow::anyhow;
 use config::keyassignment::KeyAssignment;
-use mlua::{UserData, UserDataMethods};
+use mlua::{UserData, UserDataMethods, Value};
 use mux::window::WindowId as MuxWindowId;
 use window::WindowOps;
 
@@ -55,5 +55,84 @@ impl UserData for GuiWin {
                 .await
             },
         );
+        methods.add_async_method(
+            "get_selection_text_for_pane",
+            |_, this, pane: PaneObject| async move {
+                this.with_term_window(move |term_window, _ops| {
+                    Ok(term_window.selection_text(&pane.pane()?))
+                })
+                .await
+            },
+        );
+        methods.add_async_method(
+            "set_keyboard_shortcuts_inhibited",
+            |_, this, inhibit: bool| async move {
+                this.window
+                    .set_keyboard_shortcuts_inhibited(inhibit)
+                    .await
+                    .map_err(luaerr)
+            },
+        );
+        methods.add_async_method("performance_stats", |_, this, _: ()| async move {
+            this.with_term_window(move |term_window, _ops| Ok(term_window.performance_stats()))
+                .await
+        });
+        methods.add_async_method("set_config_overrides", |_, this, value: Value| async move {
+            let overrides = match luahelper::from_lua_value(value).map_err(mlua::Error::external)? {
+                serde_json::Value::Null => serde_json::Map::new(),
+                serde_json::Value::Object(overrides) => overrides,
+                // An empty lua table looks like an empty array
+                serde_json::Value::Array(a) if a.is_empty() => serde_json::Map::new(),
+                _ => {
+                    return Err(luaerr(anyhow!(
+                        "set_config_overrides expects a table of configuration options or nil"
+                    )))
+                }
+            };
+            this.with_term_window(move |term_window, _ops| {
+                term_window.set_config_overrides(overrides.clone())
+            })
+            .await
+        });
+        methods.add_async_method("get_config_overrides", |lua, this, _: ()| async move {
+            let overrides = this
+                .with_term_window(move |term_window, _ops| {
+                    Ok(serde_json::Value::Object(
+                        term_window.get_config_overrides().clone(),
+                    ))
+                })
+                .await?;
+            luahelper::to_lua_value(lua, overrides)
+        });
+        methods.add_async_method("get_key_macros", |lua, this, _: ()| async move {
+            let macros = this
+                .with_term_window(move |_term_window, _ops| Ok(serde_json::to_value(key_macros())?))
+                .await?;
+            luahelper::to_lua_value(lua, macros)
+        });
+        methods.add_async_method(
+            "set_key_macro",
+            |_, this, (name, steps): (String, Value)| async move {
+                let steps: Option<Vec<MacroStep>> =
+                    luahelper::from_lua_value(steps).map_err(mlua::Error::external)?;
+                this.with_term_window(move |_term_window, _ops| set_key_macro(&name, steps.clone()))
+                    .await
+            },
+        );
+        methods.add_async_method("recording_key_macro", |_, this, _: ()| async move {
+            this.with_term_window(move |term_window, _ops| {
+                Ok(term_window
+                    .recording_key_macro()
+                    .map(|name| name.to_string()))
+            })
+            .await
+        });
+        methods.add_async_method("set_idle_inhibited", |_, this, inhibit: bool| async move {
+            this.with_term_window(move |term_window, _ops| {
+                term_window.set_lua_idle_inhibit(inhibit);
+                Ok(())
+            })
+            .await
+        });
     }
 }
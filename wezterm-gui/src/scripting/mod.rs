@@ -1,6 +1,61 @@
+use ::window::{Connection, ConnectionOps};
+use anyhow::anyhow;
+use mlua::{Lua, Table};
+use std::path::Path;
+
 pub mod guiwin;
+pub mod overlay;
 pub mod pane;
 
 fn luaerr(err: anyhow::Error) -> mlua::Error {
     mlua::Error::external(err)
 }
+
+/// Extends the lua context set up by the config crate with the
+/// `wezterm.gui` module, whose functions are only meaningful
+/// in the gui process.
+pub fn make_lua_context(config_dir: &Path) -> anyhow::Result<Lua> {
+    let lua = config::lua::make_lua_context(config_dir)?;
+
+    {
+        let package: Table = lua.globals().get("package")?;
+        let loaded: Table = package.get("loaded")?;
+        let wezterm_mod: Table = loaded.get("wezterm")?;
+
+        let gui_mod = lua.create_table()?;
+        gui_mod.set(
+            "get_stats",
+            lua.create_function(|_, ()| Ok(mux::stats::snapshot()))?,
+        )?;
+        gui_mod.set("screens", lua.create_function(screens)?)?;
+        gui_mod.set(
+            "make_overlay",
+            lua.create_async_function(overlay::make_overlay)?,
+        )?;
+        wezterm_mod.set("gui", gui_mod)?;
+    }
+
+    Ok(lua)
+}
+
+/// Returns a table describing each of the monitors.
+/// This requires the gui, so it is only available to event
+/// handlers; the config file itself is evaluated before the
+/// gui has started.
+fn screens<'lua>(lua: &'lua Lua, _: ()) -> mlua::Result<Table<'lua>> {
+    let conn = Connection::get()
+        .ok_or_else(|| anyhow!("screens can only be called from the gui thread"))
+        .map_err(luaerr)?;
+    let result = lua.create_table()?;
+    for (idx, screen) in conn.screens().map_err(luaerr)?.into_iter().enumerate() {
+        let info = lua.create_table()?;
+        info.set("name", screen.name)?;
+        info.set("x", screen.rect.origin.x)?;
+        info.set("y", screen.rect.origin.y)?;
+        info.set("width", screen.rect.size.width)?;
+        info.set("height", screen.rect.size.height)?;
+        info.set("scale", screen.scale)?;
+        result.set(idx + 1, info)?;
+    }
+    Ok(result)
+}
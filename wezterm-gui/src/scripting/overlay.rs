@@ -0,0 +1,210 @@
+//! Implements `wezterm.gui.make_overlay`, which covers a pane with an
+//! overlay that lua code draws in and reads the input of, so that
+//! plugins can make pickers and prompts in the same way as the launcher.
+use super::luaerr;
+use super::pane::PaneObject;
+use crate::gui::{OverlayRequest, TermWindow};
+use anyhow::anyhow;
+use mlua::{Function, Lua, Table, UserData, UserDataMethods, Value};
+use mux::Mux;
+use std::sync::mpsc::Sender;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity, Underline};
+use termwiz::color::{ColorAttribute, RgbColor};
+use termwiz::input::InputEvent;
+use termwiz::surface::{Change, CursorVisibility, Position};
+use window::WindowOps;
+
+#[derive(Clone)]
+pub struct OverlayObject {
+    requests: Sender<OverlayRequest>,
+    events: smol::channel::Receiver<InputEvent>,
+    cols: usize,
+    rows: usize,
+}
+
+impl OverlayObject {
+    fn render(&self, changes: Vec<Change>) -> mlua::Result<()> {
+        self.requests
+            .send(OverlayRequest::Render(changes))
+            .map_err(|_| luaerr(anyhow!("the overlay has been closed")))
+    }
+}
+
+fn color(spec: &str) -> mlua::Result<ColorAttribute> {
+    RgbColor::from_named_or_rgb_string(spec)
+        .map(ColorAttribute::TrueColorWithDefaultFallback)
+        .ok_or_else(|| luaerr(anyhow!("`{}` is not a color", spec)))
+}
+
+/// Makes the changes for a table of attributes such as
+/// `{bold=true, foreground="#ff0000"}`
+fn attribute_changes(attrs: Table) -> mlua::Result<Vec<Change>> {
+    let mut changes = vec![];
+    if let Some(bold) = attrs.get::<_, Option<bool>>("bold")? {
+        changes.push(
+            AttributeChange::Intensity(if bold {
+                Intensity::Bold
+            } else {
+                Intensity::Normal
+            })
+            .into(),
+        );
+    }
+    if let Some(italic) = attrs.get::<_, Option<bool>>("italic")? {
+        changes.push(AttributeChange::Italic(italic).into());
+    }
+    if let Some(underline) = attrs.get::<_, Option<bool>>("underline")? {
+        changes.push(
+            AttributeChange::Underline(if underline {
+                Underline::Single
+            } else {
+                Underline::None
+            })
+            .into(),
+        );
+    }
+    if let Some(reverse) = attrs.get::<_, Option<bool>>("reverse")? {
+        changes.push(AttributeChange::Reverse(reverse).into());
+    }
+    if let Some(fg) = attrs.get::<_, Option<String>>("foreground")? {
+        changes.push(AttributeChange::Foreground(color(&fg)?).into());
+    }
+    if let Some(bg) = attrs.get::<_, Option<String>>("background")? {
+        changes.push(AttributeChange::Background(color(&bg)?).into());
+    }
+    Ok(changes)
+}
+
+/// Describes an input event as a table, or returns None for
+/// the kinds of event that aren't passed on to lua
+fn event_table<'lua>(lua: &'lua Lua, event: InputEvent) -> mlua::Result<Option<Table<'lua>>> {
+    let table = lua.create_table()?;
+    match event {
+        InputEvent::Key(key) => {
+            table.set("type", "key")?;
+            let name = match key.key {
+                termwiz::input::KeyCode::Char(c) => c.to_string(),
+                code => format!("{:?}", code),
+            };
+            table.set("key", name)?;
+            table.set("mods", format!("{:?}", key.modifiers))?;
+        }
+        InputEvent::Mouse(mouse) => {
+            table.set("type", "mouse")?;
+            table.set("x", mouse.x)?;
+            table.set("y", mouse.y)?;
+            table.set("buttons", format!("{:?}", mouse.mouse_buttons))?;
+            table.set("mods", format!("{:?}", mouse.modifiers))?;
+        }
+        InputEvent::Resized { cols, rows } => {
+            table.set("type", "resized")?;
+            table.set("cols", cols)?;
+            table.set("rows", rows)?;
+        }
+        InputEvent::Paste(text) => {
+            table.set("type", "paste")?;
+            table.set("text", text)?;
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(table))
+}
+
+impl UserData for OverlayObject {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_size", |lua, this, _: ()| {
+            let size = lua.create_table()?;
+            size.set("cols", this.cols)?;
+            size.set("rows", this.rows)?;
+            Ok(size)
+        });
+        methods.add_method("clear", |_, this, _: ()| {
+            this.render(vec![
+                Change::ClearScreen(ColorAttribute::Default),
+                Change::CursorVisibility(CursorVisibility::Hidden),
+            ])
+        });
+        methods.add_method("set_cursor_position", |_, this, (x, y): (usize, usize)| {
+            this.render(vec![Change::CursorPosition {
+                x: Position::Absolute(x),
+                y: Position::Absolute(y),
+            }])
+        });
+        methods.add_method("set_attributes", |_, this, attrs: Table| {
+            this.render(attribute_changes(attrs)?)
+        });
+        methods.add_method("reset_attributes", |_, this, _: ()| {
+            this.render(vec![Change::AllAttributes(CellAttributes::default())])
+        });
+        methods.add_method("write", |_, this, text: String| {
+            this.render(vec![Change::Text(text)])
+        });
+        methods.add_async_method("next_event", |lua, this, _: ()| async move {
+            while let Ok(event) = this.events.recv().await {
+                if let Some(table) = event_table(lua, event)? {
+                    return Ok(Some(table));
+                }
+            }
+            Ok(None)
+        });
+        methods.add_method("close", |_, this, _: ()| {
+            this.requests.send(OverlayRequest::Close).ok();
+            Ok(())
+        });
+    }
+}
+
+/// Covers `pane` with an overlay and calls `callback` with an object
+/// for drawing in it and reading its input.  The overlay is closed
+/// when the callback returns, and its return value is returned.
+pub async fn make_overlay<'lua>(
+    _: &'lua Lua,
+    (pane, callback): (PaneObject, Function<'lua>),
+) -> mlua::Result<Value<'lua>> {
+    let pane = pane.pane()?;
+    let pane_id = pane.pane_id();
+    let mux = Mux::get()
+        .ok_or_else(|| anyhow!("must be called on main thread"))
+        .map_err(luaerr)?;
+    let (_domain, mux_window_id, _tab) = mux
+        .resolve_pane_id(pane_id)
+        .ok_or_else(|| anyhow!("pane {} is not in a window", pane_id))
+        .map_err(luaerr)?;
+    let window = crate::gui::front_end()
+        .and_then(|fe| fe.get_window(mux_window_id))
+        .ok_or_else(|| anyhow!("pane {} is not shown in a gui window", pane_id))
+        .map_err(luaerr)?;
+    let dims = pane.get_dimensions();
+    drop(pane);
+    drop(mux);
+
+    let (requests, requests_rx) = std::sync::mpsc::channel();
+    let (events_tx, events) = smol::channel::unbounded();
+    let mut channels = Some((requests_rx, events_tx));
+    window
+        .apply(move |myself, _| {
+            let term_window = myself
+                .downcast_mut::<TermWindow>()
+                .ok_or_else(|| anyhow!("Window is not TermWindow!?"))?;
+            let pane = Mux::get()
+                .unwrap()
+                .get_pane(pane_id)
+                .ok_or_else(|| anyhow!("pane {} has gone away", pane_id))?;
+            if let Some((requests_rx, events_tx)) = channels.take() {
+                term_window.show_scripted_overlay(&pane, requests_rx, events_tx);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(luaerr)?;
+
+    let overlay = OverlayObject {
+        requests: requests.clone(),
+        events,
+        cols: dims.cols,
+        rows: dims.viewport_rows,
+    };
+    let result = callback.call_async(overlay).await;
+    requests.send(OverlayRequest::Close).ok();
+    result
+}
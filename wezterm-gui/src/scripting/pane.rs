@@ -1,10 +1,15 @@
 //! PaneObject represents a Mux Pane instance in lua code
 use super::luaerr;
-use anyhow::anyhow;
-use mlua::{UserData, UserDataMethods};
+use anyhow::{anyhow, Context};
+use config::keyassignment::ExportScrollback;
+use mlua::{Lua, Table, UserData, UserDataMethods};
 use mux::pane::{Pane, PaneId};
+use mux::renderable::StableRowIndex;
 use mux::Mux;
+use std::ops::Range;
 use std::rc::Rc;
+use termwiz::cell::Cell;
+use wezterm_term::color::ColorPalette;
 
 #[derive(Clone)]
 pub struct PaneObject {
@@ -28,6 +33,75 @@ impl PaneObject {
     }
 }
 
+/// Returns the rows and columns of the region described by a table such
+/// as `{top=10, bottom=20, left=0, right=40}`.  The rows are stable row
+/// indices, and the bottom and right edges are excluded.  Any edge that
+/// isn't given defaults to that of the viewport.
+fn region(
+    pane: &Rc<dyn Pane>,
+    region: Option<Table>,
+) -> mlua::Result<(Range<StableRowIndex>, Range<usize>)> {
+    let dims = pane.get_dimensions();
+    let mut rows = dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex;
+    let mut cols = 0..dims.cols;
+    if let Some(region) = region {
+        if let Some(top) = region.get::<_, Option<StableRowIndex>>("top")? {
+            rows.start = top;
+        }
+        if let Some(bottom) = region.get::<_, Option<StableRowIndex>>("bottom")? {
+            rows.end = bottom;
+        }
+        if let Some(left) = region.get::<_, Option<usize>>("left")? {
+            cols.start = left;
+        }
+        if let Some(right) = region.get::<_, Option<usize>>("right")? {
+            cols.end = right;
+        }
+    }
+    if rows.start > rows.end || cols.start > cols.end {
+        return Err(luaerr(anyhow!(
+            "region rows {:?} and columns {:?} are empty",
+            rows,
+            cols
+        )));
+    }
+    Ok((rows, cols))
+}
+
+/// Describes a cell, along with its attributes and hyperlink, as a table.
+/// Colors are resolved through the palette of the pane.
+fn cell_table<'lua>(
+    lua: &'lua Lua,
+    x: usize,
+    cell: &Cell,
+    palette: &ColorPalette,
+) -> mlua::Result<Table<'lua>> {
+    let attrs = cell.attrs();
+    let table = lua.create_table()?;
+    table.set("x", x)?;
+    table.set("text", cell.str())?;
+    table.set("width", cell.width())?;
+    table.set("intensity", format!("{:?}", attrs.intensity()))?;
+    table.set("underline", format!("{:?}", attrs.underline()))?;
+    table.set("blink", format!("{:?}", attrs.blink()))?;
+    table.set("italic", attrs.italic())?;
+    table.set("reverse", attrs.reverse())?;
+    table.set("strikethrough", attrs.strikethrough())?;
+    table.set("invisible", attrs.invisible())?;
+    table.set(
+        "foreground",
+        palette.resolve_fg(attrs.foreground).to_rgb_string(),
+    )?;
+    table.set(
+        "background",
+        palette.resolve_bg(attrs.background).to_rgb_string(),
+    )?;
+    if let Some(link) = attrs.hyperlink() {
+        table.set("hyperlink", link.uri())?;
+    }
+    Ok(table)
+}
+
 impl UserData for PaneObject {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method("pane_id", |_, this, _: ()| Ok(this.pane()?.pane_id()));
@@ -74,5 +148,70 @@ impl UserData for PaneObject {
             text.truncate(trimmed);
             Ok(text)
         });
+
+        // Returns the text in a rectangular region of the screen or
+        // scrollback, with trailing whitespace trimmed from each row.
+        methods.add_method("get_text_region", |_, this, r: Option<Table>| {
+            let pane = this.pane()?;
+            let (rows, cols) = region(&pane, r)?;
+            let (_first_row, lines) = pane.get_lines(rows);
+            let mut text = String::new();
+            for (idx, line) in lines.iter().enumerate() {
+                if idx > 0 {
+                    text.push('\n');
+                }
+                for (x, cell) in line.visible_cells() {
+                    if cols.contains(&x) {
+                        text.push_str(cell.str());
+                    }
+                }
+                let trimmed = text.trim_end_matches(' ').len();
+                text.truncate(trimmed);
+            }
+            Ok(text)
+        });
+
+        // Returns the cells in a rectangular region of the screen or
+        // scrollback as an array of rows, each of which holds the
+        // stable index of the row and its cells with their attributes.
+        methods.add_method("get_cells", |lua, this, r: Option<Table>| {
+            let pane = this.pane()?;
+            let (rows, cols) = region(&pane, r)?;
+            let palette = pane.palette();
+            let (first_row, lines) = pane.get_lines(rows);
+            let result = lua.create_table()?;
+            for (idx, line) in lines.iter().enumerate() {
+                let cells = lua.create_table()?;
+                for (x, cell) in line.visible_cells().filter(|(x, _)| cols.contains(x)) {
+                    cells.set(cells.len()? + 1, cell_table(lua, x, cell, &palette)?)?;
+                }
+                let row = lua.create_table()?;
+                row.set("row", first_row + idx as StableRowIndex)?;
+                row.set("wrapped", line.last_cell_was_wrapped())?;
+                row.set("cells", cells)?;
+                result.set(idx + 1, row)?;
+            }
+            Ok(result)
+        });
+
+        // Returns the scrollback in the requested format, or writes it
+        // to the file named by `path` and returns nil.
+        methods.add_method(
+            "export_scrollback",
+            |_, this, export: Option<ExportScrollback>| {
+                let pane = this.pane()?;
+                let export = export.unwrap_or_default();
+                let text = mux::export::export_scrollback(&*pane, export.format, export.timestamps);
+                match export.path {
+                    Some(path) => {
+                        std::fs::write(&path, text)
+                            .with_context(|| format!("writing {}", path.display()))
+                            .map_err(luaerr)?;
+                        Ok(None)
+                    }
+                    None => Ok(Some(text)),
+                }
+            },
+        );
     }
 }
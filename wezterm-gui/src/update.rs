@@ -1,14 +1,18 @@
 use crate::gui::ICON_DATA;
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, ensure, Context};
 use config::configuration;
-use config::wezterm_version;
+use config::{wezterm_version, UpdateChannel, UpdateChannelSettings};
 use http_req::request::{HttpVersion, Request};
 use http_req::uri::Uri;
 use mux::connui::ConnectionUI;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
 use portable_pty::PtySize;
 use regex::Regex;
 use serde::*;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -27,6 +31,9 @@ pub struct Release {
     pub html_url: String,
     pub tag_name: String,
     pub assets: Vec<Asset>,
+    /// eg: `2021-02-03T09:56:43Z`
+    #[serde(default)]
+    pub published_at: Option<String>,
 }
 
 impl Release {
@@ -38,6 +45,85 @@ impl Release {
         }
         map
     }
+
+    fn asset_by_name(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+
+    /// Returns the version of the release in the same form as
+    /// wezterm_version(), so that the two can be compared.
+    /// The nightly release is continually replaced, so its version
+    /// is derived from the time at which it was published.
+    fn version(&self, channel: UpdateChannel) -> String {
+        match (channel, &self.published_at) {
+            (UpdateChannel::Nightly, Some(published)) => published
+                .chars()
+                .filter(|c| c.is_ascii_digit() || *c == 'T')
+                .take(15)
+                .collect::<String>()
+                .replace('T', "-"),
+            _ => self.tag_name.clone(),
+        }
+    }
+
+    /// Returns true if this release is newer than the running build.
+    /// Nightly builds are only compared by date, as the nightly release
+    /// is published some time after the commit that it was built from.
+    pub fn is_newer(&self, channel: UpdateChannel) -> bool {
+        let version = self.version(channel);
+        let current = wezterm_version();
+        match channel {
+            UpdateChannel::Stable => version.as_str() > current,
+            UpdateChannel::Nightly => version.get(..8) > current.get(..8),
+        }
+    }
+
+    /// Stable releases can be rolled out in stages by including a
+    /// `<!-- rollout: 25% -->` comment in the release notes; only that
+    /// percentage of installations are told about the release until
+    /// the comment is changed or removed.
+    fn rollout_percent(&self) -> u8 {
+        let re = Regex::new(r"<!--\s*rollout:\s*(\d+)%\s*-->").unwrap();
+        re.captures(&self.body)
+            .and_then(|cap| cap[1].parse::<u8>().ok())
+            .map(|percent| percent.min(100))
+            .unwrap_or(100)
+    }
+
+    /// Returns true if this installation should be offered the release
+    pub fn is_rolled_out(&self, channel: UpdateChannel) -> bool {
+        match channel {
+            UpdateChannel::Nightly => true,
+            UpdateChannel::Stable => rollout_bucket() < self.rollout_percent(),
+        }
+    }
+}
+
+/// Returns a number in the range 0-99 that is chosen at random the
+/// first time that it is needed, and then remembered, so that staged
+/// rollouts consistently include or exclude this installation
+fn rollout_bucket() -> u8 {
+    let file_name = config::CACHE_DIR.join("update-rollout-bucket");
+    if let Some(bucket) = std::fs::read_to_string(&file_name)
+        .ok()
+        .and_then(|s| s.trim().parse::<u8>().ok())
+    {
+        return bucket.min(99);
+    }
+
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    );
+    let bucket = (hasher.finish() % 100) as u8;
+    config::create_user_owned_dirs(&config::CACHE_DIR).ok();
+    std::fs::write(&file_name, bucket.to_string()).ok();
+    bucket
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -117,21 +203,43 @@ fn classify_asset_name(name: &str) -> AssetKind {
     }
 }
 
-fn get_github_release_info(uri: &str) -> anyhow::Result<Release> {
-    let uri = uri.parse::<Uri>().expect("URL to be valid!?");
-
-    let mut latest = Vec::new();
-    let _res = Request::new(&uri)
-        .version(HttpVersion::Http10)
-        .header("User-Agent", &format!("wez/wezterm-{}", wezterm_version()))
-        .send(&mut latest)
-        .map_err(|e| anyhow!("failed to query github releases: {}", e))?;
-
-    /*
-    println!("Status: {} {}", _res.status_code(), _res.reason());
-    println!("{}", String::from_utf8_lossy(&latest));
-    */
+/// Fetches `uri`, following redirects, which github uses for the
+/// release assets
+fn http_get(uri: &str) -> anyhow::Result<Vec<u8>> {
+    let mut uri = uri.to_string();
+    for _ in 0..5 {
+        let parsed = uri
+            .parse::<Uri>()
+            .map_err(|e| anyhow!("invalid url {}: {}", uri, e))?;
+        let mut body = Vec::new();
+        let res = Request::new(&parsed)
+            .version(HttpVersion::Http10)
+            .header("User-Agent", &format!("wez/wezterm-{}", wezterm_version()))
+            .send(&mut body)
+            .map_err(|e| anyhow!("failed to fetch {}: {}", uri, e))?;
+
+        if res.status_code().is_redirect() {
+            uri = res
+                .headers()
+                .get("Location")
+                .ok_or_else(|| anyhow!("redirect from {} has no location", uri))?
+                .to_string();
+            continue;
+        }
+        ensure!(
+            res.status_code().is_success(),
+            "failed to fetch {}: {} {}",
+            uri,
+            res.status_code(),
+            res.reason()
+        );
+        return Ok(body);
+    }
+    bail!("too many redirects while fetching {}", uri);
+}
 
+fn get_github_release_info(uri: &str) -> anyhow::Result<Release> {
+    let latest = http_get(uri).context("failed to query github releases")?;
     let latest: Release = serde_json::from_slice(&latest)?;
     Ok(latest)
 }
@@ -140,16 +248,174 @@ pub fn get_latest_release_info() -> anyhow::Result<Release> {
     get_github_release_info("https://api.github.com/repos/wez/wezterm/releases/latest")
 }
 
-#[allow(unused)]
 pub fn get_nightly_release_info() -> anyhow::Result<Release> {
     get_github_release_info("https://api.github.com/repos/wez/wezterm/releases/tags/nightly")
 }
 
+pub fn get_release_info(channel: UpdateChannel) -> anyhow::Result<Release> {
+    match channel {
+        UpdateChannel::Stable => get_latest_release_info(),
+        UpdateChannel::Nightly => get_nightly_release_info(),
+    }
+}
+
+/// Returns the sections of the changelog that describe changes made
+/// after the `current` version.  The changelog has a `### VERSION`
+/// heading for each release, newest first, preceded by a `### Nightly`
+/// section for the changes that haven't been released yet.
+fn changelog_since(changelog: &str, current: &str) -> String {
+    let mut result = String::new();
+    let mut in_section = false;
+    for line in changelog.lines() {
+        if line.starts_with("### ") {
+            let heading = line[4..].trim();
+            if heading != "Nightly" && heading <= current {
+                break;
+            }
+            in_section = true;
+        }
+        if in_section {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    result
+}
+
+/// Fetches the changelog as of `release` and returns the changes that
+/// it has over the running version
+pub fn get_changes_since_current(
+    release: &Release,
+    channel: UpdateChannel,
+) -> anyhow::Result<String> {
+    let git_ref = match channel {
+        UpdateChannel::Stable => release.tag_name.as_str(),
+        UpdateChannel::Nightly => "main",
+    };
+    let changelog = http_get(&format!(
+        "https://raw.githubusercontent.com/wez/wezterm/{}/docs/changelog.md",
+        git_ref
+    ))?;
+    Ok(changelog_since(
+        &String::from_utf8_lossy(&changelog).replace("\r\n", "\n"),
+        wezterm_version(),
+    ))
+}
+
+/// Returns the package in the release that is suitable for this system
+pub fn asset_for_this_system(release: &Release) -> Option<Asset> {
+    let assets = release.classify_assets();
+    let kind = if cfg!(windows) {
+        AssetKind::WindowsSetupExe
+    } else if cfg!(target_os = "macos") {
+        AssetKind::MacOSZip
+    } else if std::env::var_os("APPIMAGE").is_some() {
+        AssetKind::AppImage
+    } else {
+        let os_release = std::fs::read_to_string("/etc/os-release").ok()?;
+        let field = |name: &str| {
+            os_release.lines().find_map(|line| {
+                let value = line.strip_prefix(name)?.strip_prefix('=')?;
+                Some(value.trim_matches('"').to_string())
+            })
+        };
+        let version = field("VERSION_ID")?;
+        match field("ID")?.as_str() {
+            "ubuntu" => AssetKind::UbuntuDeb(version),
+            "debian" => AssetKind::DebianDeb(version),
+            "fedora" => AssetKind::FedoraRpm(version),
+            "centos" => AssetKind::CentOSRpm(version),
+            _ => return None,
+        }
+    };
+    assets.get(&kind).cloned()
+}
+
+/// Checks that `signature` is a valid signature of `data` made with
+/// the private key corresponding to the PEM encoded `public_key`
+fn verify_signature(data: &[u8], signature: &[u8], public_key: &[u8]) -> anyhow::Result<()> {
+    let key = PKey::public_key_from_pem(public_key).context("parsing public key")?;
+    let valid = if key.id() == Id::ED25519 {
+        let mut verifier = Verifier::new_without_digest(&key)?;
+        verifier.verify_oneshot(signature, data)?
+    } else {
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+        verifier.update(data)?;
+        verifier.verify(signature)?
+    };
+    ensure!(valid, "the signature is not valid");
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Downloads `asset` from `release` into the download directory
+/// configured for the channel, returning the path to the file.
+/// If the release has a `.sha256` file for the asset, the download
+/// must match it, and if a signing key is configured, the download
+/// must have a valid signature in a `.sig` file.
+pub fn download_asset(
+    release: &Release,
+    asset: &Asset,
+    settings: &UpdateChannelSettings,
+) -> anyhow::Result<PathBuf> {
+    let data = http_get(&asset.browser_download_url)?;
+
+    if let Some(sum) = release.asset_by_name(&format!("{}.sha256", asset.name)) {
+        let expected = String::from_utf8(http_get(&sum.browser_download_url)?)?;
+        let expected = expected.split_whitespace().next().unwrap_or("");
+        let actual = hex(&openssl::sha::sha256(&data));
+        ensure!(
+            expected.eq_ignore_ascii_case(&actual),
+            "{} has checksum {} but {} was expected",
+            asset.name,
+            actual,
+            expected
+        );
+    }
+
+    if let Some(key_file) = &settings.signing_public_key {
+        let sig = release
+            .asset_by_name(&format!("{}.sig", asset.name))
+            .ok_or_else(|| anyhow!("{} has no signature", asset.name))?;
+        let signature = http_get(&sig.browser_download_url)?;
+        let public_key =
+            std::fs::read(key_file).with_context(|| format!("reading {}", key_file.display()))?;
+        verify_signature(&data, &signature, &public_key)
+            .with_context(|| format!("verifying the signature of {}", asset.name))?;
+    }
+
+    let dir = settings
+        .download_dir
+        .clone()
+        .unwrap_or_else(default_download_dir);
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    // The name comes from the server, so don't let it pick a directory
+    let file_name = Path::new(&asset.name)
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid asset name {}", asset.name))?;
+    let path = dir.join(file_name);
+    std::fs::write(&path, &data).with_context(|| format!("writing {}", path.display()))?;
+    log::info!("downloaded {} to {}", asset.name, path.display());
+    Ok(path)
+}
+
+fn default_download_dir() -> PathBuf {
+    let downloads = config::HOME_DIR.join("Downloads");
+    if downloads.is_dir() {
+        downloads
+    } else {
+        config::HOME_DIR.clone()
+    }
+}
+
 lazy_static::lazy_static! {
     static ref UPDATER_WINDOW: Mutex<Option<ConnectionUI>> = Mutex::new(None);
 }
 
-fn show_update_available(release: Release) {
+fn show_update_available(release: Release, channel: UpdateChannel, downloaded: Option<PathBuf>) {
     if !configuration().show_update_window {
         return;
     }
@@ -180,8 +446,13 @@ fn show_update_available(release: Release) {
         release.tag_name
     );
 
-    let brief_blurb = release
-        .body
+    // Prefer to show everything that changed since the running
+    // version, falling back to the notes for the release
+    let brief_blurb = match get_changes_since_current(&release, channel) {
+        Ok(changes) if !changes.trim().is_empty() => changes,
+        _ => release.body.clone(),
+    };
+    let brief_blurb = brief_blurb
         // The default for the release body is a series of newlines.
         // Trim that so that it doesn't make the window look weird
         .trim_end()
@@ -223,6 +494,10 @@ fn show_update_available(release: Release) {
     ]);
     ui.output(output);
 
+    if let Some(path) = downloaded {
+        ui.output_str(&format!("\nDownloaded to {}\n", path.display()));
+    }
+
     let assets = release.classify_assets();
     let appimage = assets.get(&AssetKind::AppImage);
     let setupexe = assets.get(&AssetKind::WindowsSetupExe);
@@ -268,9 +543,9 @@ pub fn load_last_release_info_and_set_banner() {
             Err(_) => return,
         };
 
-        let current = wezterm_version();
+        let channel = configuration().update_channel;
         let force_ui = std::env::var_os("WEZTERM_ALWAYS_SHOW_UPDATE_UI").is_some();
-        if latest.tag_name.as_str() <= current && !force_ui {
+        if !(latest.is_newer(channel) && latest.is_rolled_out(channel)) && !force_ui {
             return;
         }
 
@@ -322,10 +597,6 @@ fn set_banner_from_release_info(latest: &Release) {
 }
 
 fn schedule_set_banner_from_release_info(latest: &Release) {
-    let current = wezterm_version();
-    if latest.tag_name.as_str() <= current {
-        return;
-    }
     promise::spawn::spawn_into_main_thread({
         let latest = latest.clone();
         async move {
@@ -341,7 +612,14 @@ fn update_checker() {
     // launch, otherwise compute the interval based on the time of
     // the last check.
     let config = configuration();
-    let update_interval = Duration::new(config.check_for_updates_interval_seconds, 0);
+    let channel = config.update_channel;
+    let settings = config.current_update_channel_settings();
+    let update_interval = Duration::new(
+        settings
+            .check_interval_seconds
+            .unwrap_or(config.check_for_updates_interval_seconds),
+        0,
+    );
     let initial_interval = Duration::new(10, 0);
 
     let force_ui = std::env::var_os("WEZTERM_ALWAYS_SHOW_UPDATE_UI").is_some();
@@ -360,15 +638,31 @@ fn update_checker() {
     std::thread::sleep(if force_ui { initial_interval } else { delay });
 
     loop {
-        if let Ok(latest) = get_latest_release_info() {
-            schedule_set_banner_from_release_info(&latest);
+        if let Ok(latest) = get_release_info(channel) {
             let current = wezterm_version();
-            if latest.tag_name.as_str() > current || force_ui {
+            if (latest.is_newer(channel) && latest.is_rolled_out(channel)) || force_ui {
                 log::info!(
-                    "latest release {} is newer than current build {}",
-                    latest.tag_name,
+                    "latest {:?} release {} is newer than current build {}",
+                    channel,
+                    latest.version(channel),
                     current
                 );
+                schedule_set_banner_from_release_info(&latest);
+
+                let downloaded = if settings.download {
+                    match asset_for_this_system(&latest)
+                        .ok_or_else(|| anyhow!("no package for this system"))
+                        .and_then(|asset| download_asset(&latest, &asset, &settings))
+                    {
+                        Ok(path) => Some(path),
+                        Err(err) => {
+                            log::error!("failed to download update: {:#}", err);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
 
                 let url = format!(
                     "https://wezfurlong.org/wezterm/changelog.html#{}",
@@ -377,11 +671,14 @@ fn update_checker() {
 
                 persistent_toast_notification_with_click_to_open_url(
                     "WezTerm Update Available",
-                    "Click to see what's new",
+                    &match &downloaded {
+                        Some(path) => format!("Downloaded to {}", path.display()),
+                        None => "Click to see what's new".to_string(),
+                    },
                     &url,
                 );
 
-                show_update_available(latest.clone());
+                show_update_available(latest.clone(), channel, downloaded);
             }
 
             config::create_user_owned_dirs(update_file_name.parent().unwrap()).ok();
@@ -419,6 +716,81 @@ pub fn start_update_checker() {
 mod test {
     use super::*;
 
+    fn release(body: &str, published_at: Option<&str>) -> Release {
+        Release {
+            url: String::new(),
+            body: body.to_string(),
+            html_url: String::new(),
+            tag_name: "nightly".to_string(),
+            assets: vec![],
+            published_at: published_at.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn changelog() {
+        let changelog = "## Changes\n\
+                         \n\
+                         ### Nightly\n\
+                         * nightly thing\n\
+                         \n\
+                         ### 20210314-114017-04b7cedd\n\
+                         * newer thing\n\
+                         \n\
+                         ### 20210203-095643-70a364eb\n\
+                         * older thing\n";
+        assert_eq!(
+            changelog_since(changelog, "20210203-095643-70a364eb"),
+            "### Nightly\n* nightly thing\n\n### 20210314-114017-04b7cedd\n* newer thing\n\n"
+        );
+        // A nightly build made between the two releases
+        assert_eq!(
+            changelog_since(changelog, "20210220-080000-deadbeef"),
+            "### Nightly\n* nightly thing\n\n### 20210314-114017-04b7cedd\n* newer thing\n\n"
+        );
+        assert_eq!(
+            changelog_since(changelog, "20210314-114017-04b7cedd"),
+            "### Nightly\n* nightly thing\n\n"
+        );
+    }
+
+    #[test]
+    fn nightly_version() {
+        let nightly = release("", Some("2021-02-03T09:56:43Z"));
+        assert_eq!(nightly.version(UpdateChannel::Nightly), "20210203-095643");
+        assert_eq!(nightly.version(UpdateChannel::Stable), "nightly");
+    }
+
+    #[test]
+    fn rollout() {
+        assert_eq!(release("notes", None).rollout_percent(), 100);
+        assert_eq!(
+            release("notes\n<!-- rollout: 25% -->\n", None).rollout_percent(),
+            25
+        );
+        assert_eq!(release("<!--rollout:250%-->", None).rollout_percent(), 100);
+    }
+
+    #[test]
+    fn signature() {
+        use openssl::sign::Signer;
+
+        let key = PKey::generate_ed25519().unwrap();
+        let public_key = key.public_key_to_pem().unwrap();
+        let data = b"WezTerm-nightly-setup.exe contents";
+        let signature = Signer::new_without_digest(&key)
+            .unwrap()
+            .sign_oneshot_to_vec(data)
+            .unwrap();
+
+        assert!(verify_signature(data, &signature, &public_key).is_ok());
+        assert!(verify_signature(b"tampered", &signature, &public_key).is_err());
+
+        let other = PKey::generate_ed25519().unwrap();
+        let other_public_key = other.public_key_to_pem().unwrap();
+        assert!(verify_signature(data, &signature, &other_public_key).is_err());
+    }
+
     #[test]
     fn classify_names() {
         assert_eq!(
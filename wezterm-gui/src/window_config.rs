@@ -1,5 +1,17 @@
-use ::window::configuration::WindowConfiguration;
-use config::configuration;
+use ::window::configuration::{DropDownSettings, WindowConfiguration};
+use config::{
+    configuration, DropDown, DropDownKeyboardInteractivity, EnableWayland, WindowDecorations,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `wezterm start --drop-down`
+static FORCE_DROP_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Show the first window as a drop-down, even if `drop_down`
+/// is not set in the configuration
+pub fn force_drop_down() {
+    FORCE_DROP_DOWN.store(true, Ordering::Relaxed);
+}
 
 pub struct ConfigBridge;
 
@@ -21,13 +33,45 @@ impl WindowConfiguration for ConfigBridge {
     }
 
     fn enable_wayland(&self) -> bool {
-        configuration().enable_wayland
+        configuration().enable_wayland != EnableWayland::Disabled
+    }
+
+    fn wayland_fallback_to_x11(&self) -> bool {
+        configuration().enable_wayland == EnableWayland::Auto
     }
 
     fn prefer_egl(&self) -> bool {
         configuration().prefer_egl
     }
 
+    fn prefer_egl_device(&self) -> Option<String> {
+        configuration().prefer_egl_device.clone()
+    }
+
+    fn window_decorations(&self) -> bool {
+        configuration().window_decorations == WindowDecorations::Full
+    }
+
+    fn drop_down(&self) -> Option<DropDownSettings> {
+        let drop_down = match configuration().drop_down {
+            Some(drop_down) => drop_down,
+            None if FORCE_DROP_DOWN.load(Ordering::Relaxed) => DropDown::default(),
+            None => return None,
+        };
+        Some(DropDownSettings {
+            height_percent: drop_down.height.max(1).min(100),
+            margin_top: drop_down.margin_top.into(),
+            margin_left: drop_down.margin_left.into(),
+            margin_right: drop_down.margin_right.into(),
+            exclusive_keyboard: drop_down.keyboard_interactivity
+                == DropDownKeyboardInteractivity::Exclusive,
+        })
+    }
+
+    fn dpi_by_screen(&self, screen: &str) -> Option<f64> {
+        configuration().dpi_by_screen.get(screen).copied()
+    }
+
     fn native_macos_fullscreen_mode(&self) -> bool {
         configuration().native_macos_fullscreen_mode
     }
@@ -35,4 +79,15 @@ impl WindowConfiguration for ConfigBridge {
     fn window_background_opacity(&self) -> f32 {
         configuration().window_background_opacity
     }
+
+    fn window_opacity(&self) -> f32 {
+        configuration().window_opacity
+    }
+
+    fn wayland_background_blur(&self) -> bool {
+        let config = configuration();
+        // Blurring the background of an opaque window is wasted effort
+        config.wayland_background_blur
+            && (config.window_background_opacity < 1.0 || config.window_opacity < 1.0)
+    }
 }
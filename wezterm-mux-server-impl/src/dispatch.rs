@@ -23,7 +23,9 @@ enum Item {
     Readable,
 }
 
-pub async fn process<T>(stream: T) -> anyhow::Result<()>
+/// Services a client connection.  If `read_only` is true, the client
+/// can observe the session but any input that it sends is discarded.
+pub async fn process<T>(stream: T, read_only: bool) -> anyhow::Result<()>
 where
     T: 'static,
     T: std::io::Read,
@@ -32,10 +34,10 @@ where
     T: std::fmt::Debug,
 {
     let stream = smol::Async::new(stream)?;
-    process_async(stream).await
+    process_async(stream, read_only).await
 }
 
-pub async fn process_async<T>(mut stream: Async<T>) -> anyhow::Result<()>
+pub async fn process_async<T>(mut stream: Async<T>, read_only: bool) -> anyhow::Result<()>
 where
     T: 'static,
     T: std::io::Read,
@@ -54,7 +56,7 @@ where
                 .map_err(|e| anyhow::anyhow!("{:?}", e))
         }
     });
-    let mut handler = SessionHandler::new(pdu_sender);
+    let mut handler = SessionHandler::new(pdu_sender, read_only);
 
     {
         let mux = Mux::get().expect("to be running on gui thread");
@@ -78,10 +80,12 @@ where
                     .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
-            Ok(Item::Notif(MuxNotification::PaneOutput(pane_id))) => {
-                handler.schedule_pane_push(pane_id);
+            Ok(Item::Notif(notification)) => {
+                handler.forward_mux_notification(&notification);
+                if let MuxNotification::PaneOutput(pane_id) = notification {
+                    handler.schedule_pane_push(pane_id);
+                }
             }
-            Ok(Item::Notif(MuxNotification::WindowCreated(_window_id))) => {}
             Err(err) => {
                 log::error!("process_async Err {}", err);
                 return Ok(());
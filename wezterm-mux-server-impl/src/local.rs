@@ -2,14 +2,19 @@ use crate::UnixListener;
 use anyhow::{anyhow, Context as _};
 use config::{create_user_owned_dirs, UnixDomain};
 use promise::spawn::spawn_into_main_thread;
+use std::path::Path;
 
 pub struct LocalListener {
     listener: UnixListener,
+    read_only: bool,
 }
 
 impl LocalListener {
     pub fn new(listener: UnixListener) -> Self {
-        Self { listener }
+        Self {
+            listener,
+            read_only: false,
+        }
     }
 
     pub fn with_domain(unix_dom: &UnixDomain) -> anyhow::Result<Self> {
@@ -17,15 +22,45 @@ impl LocalListener {
         Ok(Self::new(listener))
     }
 
+    /// Sets up a listener on the `read_only_socket_path` of `unix_dom`,
+    /// if it has one.  Clients that connect to it can observe the
+    /// session but cannot interact with it.
+    /// Unlike the main socket, this one is intended to be reachable by
+    /// other users, so who may connect to it is left up to the
+    /// permissions that the user chooses for it and its directory.
+    pub fn read_only_with_domain(unix_dom: &UnixDomain) -> anyhow::Result<Option<Self>> {
+        let sock_path = match unix_dom.read_only_socket_path.as_ref() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        log::info!("setting up read-only {}", sock_path.display());
+
+        if let Some(sock_dir) = sock_path.parent() {
+            std::fs::create_dir_all(sock_dir)
+                .with_context(|| format!("Unable to create {}", sock_dir.display()))?;
+        }
+        remove_stale_sock(sock_path)?;
+
+        let listener = UnixListener::bind(sock_path)
+            .with_context(|| format!("Failed to bind to {}", sock_path.display()))?;
+        Ok(Some(Self {
+            listener,
+            read_only: true,
+        }))
+    }
+
     pub fn run(&mut self) {
         for stream in self.listener.incoming() {
             match stream {
                 Ok(stream) => {
+                    let read_only = self.read_only;
                     spawn_into_main_thread(async move {
-                        crate::dispatch::process(stream).await.map_err(|e| {
-                            log::error!("{:#}", e);
-                            e
-                        })
+                        crate::dispatch::process(stream, read_only)
+                            .await
+                            .map_err(|e| {
+                                log::error!("{:#}", e);
+                                e
+                            })
                     })
                     .detach();
                 }
@@ -73,18 +108,22 @@ fn safely_create_sock_path(unix_dom: &UnixDomain) -> anyhow::Result<UnixListener
         }
     }
 
-    // We want to remove the socket if it exists.
-    // However, on windows, we can't tell if the unix domain socket
-    // exists using the methods on Path, so instead we just unconditionally
-    // remove it and see what error occurs.
+    remove_stale_sock(sock_path)?;
+
+    UnixListener::bind(sock_path)
+        .with_context(|| format!("Failed to bind to {}", sock_path.display()))
+}
+
+/// We want to remove the socket if it exists.
+/// However, on windows, we can't tell if the unix domain socket
+/// exists using the methods on Path, so instead we just unconditionally
+/// remove it and see what error occurs.
+fn remove_stale_sock(sock_path: &Path) -> anyhow::Result<()> {
     match std::fs::remove_file(sock_path) {
-        Ok(_) => {}
+        Ok(_) => Ok(()),
         Err(err) => match err.kind() {
-            std::io::ErrorKind::NotFound => {}
-            _ => return Err(err).context(format!("Unable to remove {}", sock_path.display())),
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err).context(format!("Unable to remove {}", sock_path.display())),
         },
     }
-
-    UnixListener::bind(sock_path)
-        .with_context(|| format!("Failed to bind to {}", sock_path.display()))
 }
@@ -1,20 +1,24 @@
 use crate::PKI;
 use anyhow::anyhow;
 use codec::*;
+use config::configuration;
 use config::keyassignment::SpawnTabDomain;
 use mux::pane::{Pane, PaneId};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
-use mux::tab::TabId;
-use mux::Mux;
+use mux::tab::{ClientId, TabId};
+use mux::{Mux, MuxNotification};
 use portable_pty::PtySize;
 use promise::spawn::spawn_into_main_thread;
 use rangeset::RangeSet;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use url::Url;
 use wezterm_term::terminal::Clipboard;
+use wezterm_term::Alert;
 use wezterm_term::StableRowIndex;
 
 #[derive(Clone)]
@@ -43,6 +47,10 @@ struct PerPane {
     dimensions: RenderableDimensions,
     dirty_lines: RangeSet<StableRowIndex>,
     mouse_grabbed: bool,
+    sent_images: SentImages,
+    /// Whether we have sent the client the scrollback that precedes
+    /// the viewport when it first looked at this pane
+    sent_scrollback: bool,
 }
 
 impl PerPane {
@@ -108,6 +116,26 @@ impl PerPane {
         let (cursor_line, lines) = pane.get_lines(cursor_position.y..cursor_position.y + 1);
         bonus_lines.push((cursor_line, lines[0].clone()));
 
+        // The first time that the client sees this pane, send along
+        // some history so that it can scroll back without having to
+        // fetch each page of it on demand
+        if !self.sent_scrollback {
+            self.sent_scrollback = true;
+            let history_lines = configuration().mux_scrollback_lines_on_attach;
+            if history_lines > 0 {
+                let history_top = dims
+                    .physical_top
+                    .saturating_sub(history_lines as StableRowIndex)
+                    .max(dims.scrollback_top);
+                let (first_line, lines) = pane.get_lines(history_top..dims.physical_top);
+                for (idx, line) in lines.into_iter().enumerate() {
+                    let stable_row = first_line + idx as StableRowIndex;
+                    all_dirty_lines.remove(stable_row);
+                    bonus_lines.push((stable_row, line));
+                }
+            }
+        }
+
         self.cursor_position = cursor_position;
         self.title = title.clone();
         self.working_dir = working_dir.clone();
@@ -116,7 +144,7 @@ impl PerPane {
         self.mouse_grabbed = mouse_grabbed;
 
         let dirty_lines = dirty_delta.iter().cloned().collect();
-        let bonus_lines = bonus_lines.into();
+        let bonus_lines = SerializedLines::with_images(bonus_lines, &mut self.sent_images);
         Some(GetPaneRenderChangesResponse {
             pane_id: pane.pane_id(),
             mouse_grabbed,
@@ -150,16 +178,48 @@ fn maybe_push_pane_changes(
     Ok(())
 }
 
+static CLIENT_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub struct SessionHandler {
     to_write_tx: PduSender,
     per_pane: HashMap<TabId, Arc<Mutex<PerPane>>>,
+    client_id: ClientId,
+    /// The tabs that the client has told us that it is displaying
+    viewed_tabs: Arc<Mutex<Vec<TabId>>>,
+    /// If true, the client may only observe the session; any input
+    /// that it sends is discarded
+    read_only: bool,
+    /// Whether the client has asked to be told about mux events
+    subscribed_to_mux_events: bool,
+}
+
+impl Drop for SessionHandler {
+    fn drop(&mut self) {
+        // The client is gone, so it no longer constrains the size
+        // of the tabs that it was displaying
+        let client_id = self.client_id;
+        let viewed_tabs = Arc::clone(&self.viewed_tabs);
+        spawn_into_main_thread(async move {
+            let mux = Mux::get().unwrap();
+            for tab_id in viewed_tabs.lock().unwrap().drain(..) {
+                if let Some(tab) = mux.get_tab(tab_id) {
+                    tab.set_client_size(client_id, None);
+                }
+            }
+        })
+        .detach();
+    }
 }
 
 impl SessionHandler {
-    pub fn new(to_write_tx: PduSender) -> Self {
+    pub fn new(to_write_tx: PduSender, read_only: bool) -> Self {
         Self {
             to_write_tx,
             per_pane: HashMap::new(),
+            client_id: CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            viewed_tabs: Arc::new(Mutex::new(vec![])),
+            read_only,
+            subscribed_to_mux_events: false,
         }
     }
     fn per_pane(&mut self, pane_id: PaneId) -> Arc<Mutex<PerPane>> {
@@ -184,6 +244,56 @@ impl SessionHandler {
         .detach();
     }
 
+    /// Reports `notification` to the client, if it has subscribed
+    /// to mux events.  Must be called on the main thread.
+    pub fn forward_mux_notification(&mut self, notification: &MuxNotification) {
+        if !self.subscribed_to_mux_events {
+            return;
+        }
+        let event = match notification {
+            MuxNotification::PaneOutput(pane_id) => MuxEvent::PaneOutput { pane_id: *pane_id },
+            MuxNotification::PaneAdded(pane_id) => MuxEvent::PaneAdded { pane_id: *pane_id },
+            MuxNotification::PaneRemoved(pane_id) => MuxEvent::PaneRemoved { pane_id: *pane_id },
+            MuxNotification::TabAddedToWindow { tab_id, window_id } => MuxEvent::TabAdded {
+                tab_id: *tab_id,
+                window_id: *window_id,
+            },
+            MuxNotification::TabRemoved(tab_id) => MuxEvent::TabRemoved { tab_id: *tab_id },
+            MuxNotification::WindowCreated(window_id) => MuxEvent::WindowCreated {
+                window_id: *window_id,
+            },
+            MuxNotification::WindowRemoved(window_id) => MuxEvent::WindowRemoved {
+                window_id: *window_id,
+            },
+            MuxNotification::Alert { pane_id, alert } => match alert {
+                Alert::Bell => MuxEvent::Bell { pane_id: *pane_id },
+                Alert::TitleMaybeChanged => {
+                    let title = match Mux::get().and_then(|mux| mux.get_pane(*pane_id)) {
+                        Some(pane) => pane.get_title(),
+                        None => return,
+                    };
+                    MuxEvent::TitleChanged {
+                        pane_id: *pane_id,
+                        title,
+                    }
+                }
+                Alert::SetUserVar { name, value } => MuxEvent::UserVarChanged {
+                    pane_id: *pane_id,
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                Alert::ToastNotification(_) => return,
+            },
+            MuxNotification::ChoosePane { .. } => return,
+        };
+        if let Err(err) = self.to_write_tx.send(DecodedPdu {
+            pdu: Pdu::MuxEventPdu(MuxEventPdu { event }),
+            serial: 0,
+        }) {
+            log::error!("failed to send mux event: {:#}", err);
+        }
+    }
+
     pub fn process_one(&mut self, decoded: DecodedPdu) {
         let start = Instant::now();
         let sender = self.to_write_tx.clone();
@@ -208,6 +318,41 @@ impl SessionHandler {
             send_response(f());
         }
 
+        if self.read_only {
+            match &decoded.pdu {
+                // Input from a read-only client is quietly discarded,
+                // as is any attempt to influence the size of the panes
+                Pdu::WriteToPane(_)
+                | Pdu::SendPaste(_)
+                | Pdu::SendKeyDown(_)
+                | Pdu::SendMouseEvent(_)
+                | Pdu::Resize(_)
+                | Pdu::SetPaneZoomed(_)
+                | Pdu::SetViewedTabs(_) => {
+                    send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+                    return;
+                }
+                // These would allow the client to act as the user
+                // that owns the session, or to rearrange their panes,
+                // so refuse them outright
+                Pdu::Spawn(_)
+                | Pdu::SplitPane(_)
+                | Pdu::GetTlsCreds(_)
+                | Pdu::SftpStat(_)
+                | Pdu::SftpRead(_)
+                | Pdu::SftpWrite(_)
+                | Pdu::SetLogFilter(_)
+                | Pdu::RestoreSession(_)
+                | Pdu::ChoosePane(_)
+                | Pdu::SwapPanes(_)
+                | Pdu::MovePane(_) => {
+                    send_response(Err(anyhow!("this session is read-only")));
+                    return;
+                }
+                _ => {}
+            }
+        }
+
         match decoded.pdu {
             Pdu::Ping(Ping {}) => send_response(Ok(Pdu::Pong(Pong {}))),
             Pdu::ListPanes(ListPanes {}) => {
@@ -270,23 +415,31 @@ impl SessionHandler {
                 .detach();
             }
 
-            Pdu::SearchScrollbackRequest(SearchScrollbackRequest { pane_id, pattern }) => {
+            Pdu::SearchScrollbackRequest(SearchScrollbackRequest {
+                pane_id,
+                pattern,
+                range,
+            }) => {
                 use mux::pane::Pattern;
 
-                async fn do_search(pane_id: TabId, pattern: Pattern) -> anyhow::Result<Pdu> {
+                async fn do_search(
+                    pane_id: TabId,
+                    pattern: Pattern,
+                    range: Range<StableRowIndex>,
+                ) -> anyhow::Result<Pdu> {
                     let mux = Mux::get().unwrap();
                     let pane = mux
                         .get_pane(pane_id)
                         .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
 
-                    pane.search(pattern).await.map(|results| {
+                    pane.search(pattern, range).await.map(|results| {
                         Pdu::SearchScrollbackResponse(SearchScrollbackResponse { results })
                     })
                 }
 
                 spawn_into_main_thread(async move {
                     promise::spawn::spawn(async move {
-                        let result = do_search(pane_id, pattern).await;
+                        let result = do_search(pane_id, pattern, range).await;
                         send_response(result);
                     })
                     .detach();
@@ -294,6 +447,36 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::SetViewedTabs(SetViewedTabs { tabs }) => {
+                let client_id = self.client_id;
+                let viewed_tabs = Arc::clone(&self.viewed_tabs);
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let mut viewed_tabs = viewed_tabs.lock().unwrap();
+                            for tab_id in viewed_tabs.drain(..) {
+                                if tabs.iter().any(|(id, _)| *id == tab_id) {
+                                    continue;
+                                }
+                                if let Some(tab) = mux.get_tab(tab_id) {
+                                    tab.set_client_size(client_id, None);
+                                }
+                            }
+                            for (tab_id, size) in tabs {
+                                if let Some(tab) = mux.get_tab(tab_id) {
+                                    tab.set_client_size(client_id, Some(size));
+                                    viewed_tabs.push(tab_id);
+                                }
+                            }
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::SetPaneZoomed(SetPaneZoomed {
                 containing_tab_id,
                 pane_id,
@@ -331,11 +514,15 @@ impl SessionHandler {
                             let pane = mux
                                 .get_pane(pane_id)
                                 .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
-                            pane.resize(size)?;
                             let tab = mux
                                 .get_tab(containing_tab_id)
                                 .ok_or_else(|| anyhow!("no such tab {}", containing_tab_id))?;
-                            tab.rebuild_splits_sizes_from_contained_panes();
+                            // When several clients are displaying the tab, its
+                            // size is determined by SetViewedTabs instead
+                            if tab.count_viewing_clients() <= 1 {
+                                pane.resize(size)?;
+                                tab.rebuild_splits_sizes_from_contained_panes();
+                            }
                             Ok(Pdu::UnitResponse(UnitResponse {}))
                         },
                         send_response,
@@ -439,7 +626,11 @@ impl SessionHandler {
                 .detach();
             }
 
-            Pdu::GetLines(GetLines { pane_id, lines }) => {
+            Pdu::GetLines(GetLines {
+                pane_id,
+                lines,
+                forget_images,
+            }) => {
                 let per_pane = self.per_pane(pane_id);
                 spawn_into_main_thread(async move {
                     catch(
@@ -450,6 +641,7 @@ impl SessionHandler {
                                 .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
                             let mut lines_and_indices = vec![];
                             let mut per_pane = per_pane.lock().unwrap();
+                            per_pane.sent_images.forget(&forget_images);
 
                             for range in lines {
                                 let (first_row, lines) = pane.get_lines(range);
@@ -461,7 +653,10 @@ impl SessionHandler {
                             }
                             Ok(Pdu::GetLinesResponse(GetLinesResponse {
                                 pane_id,
-                                lines: lines_and_indices.into(),
+                                lines: SerializedLines::with_images(
+                                    lines_and_indices,
+                                    &mut per_pane.sent_images,
+                                ),
                             }))
                         },
                         send_response,
@@ -491,6 +686,163 @@ impl SessionHandler {
                 );
             }
 
+            Pdu::GetClientList(GetClientList {}) => {
+                send_response(Ok(Pdu::GetClientListResponse(GetClientListResponse {
+                    clients: mux::sshforward::ssh_client_list(),
+                })))
+            }
+
+            // These are only meaningful in the gui process, which holds
+            // the ssh sessions; the operations may block on the network
+            // so they run on their own thread.
+            Pdu::SftpStat(SftpStat { domain, path }) => {
+                std::thread::spawn(move || {
+                    send_response(mux::sftp::stat(&domain, &path).map(|info| {
+                        Pdu::SftpStatResponse(match info {
+                            Some(info) => SftpStatResponse {
+                                exists: true,
+                                is_dir: info.is_dir,
+                                size: info.size,
+                            },
+                            None => SftpStatResponse {
+                                exists: false,
+                                is_dir: false,
+                                size: 0,
+                            },
+                        })
+                    }))
+                });
+            }
+
+            Pdu::SftpRead(SftpRead {
+                domain,
+                path,
+                offset,
+                len,
+            }) => {
+                std::thread::spawn(move || {
+                    send_response(
+                        mux::sftp::read(&domain, &path, offset, len)
+                            .map(|data| Pdu::SftpReadResponse(SftpReadResponse { data })),
+                    )
+                });
+            }
+
+            Pdu::SftpWrite(SftpWrite {
+                domain,
+                path,
+                offset,
+                data,
+            }) => {
+                std::thread::spawn(move || {
+                    send_response(
+                        mux::sftp::write(&domain, &path, offset, &data)
+                            .map(|_| Pdu::UnitResponse(UnitResponse {})),
+                    )
+                });
+            }
+
+            Pdu::SetLogFilter(SetLogFilter { filter }) => send_response(
+                env_bootstrap::logging::set_log_filter(&filter)
+                    .map(|_| Pdu::UnitResponse(UnitResponse {})),
+            ),
+
+            Pdu::RestoreSession(RestoreSession {}) => {
+                spawn_into_main_thread(async move {
+                    schedule_restore_session(send_response);
+                })
+                .detach();
+            }
+
+            Pdu::CapturePane(CapturePane {
+                pane_id,
+                format,
+                scrollback,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            let text = if scrollback {
+                                mux::export::export_scrollback(&*pane, format, false)
+                            } else {
+                                mux::export::export_viewport(&*pane, format)
+                            };
+                            Ok(Pdu::CapturePaneResponse(CapturePaneResponse { text }))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::WaitPane(WaitPane { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    send_response(wait_for_pane(pane_id).await);
+                })
+                .detach();
+            }
+
+            Pdu::ChoosePane(ChoosePane { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    send_response(choose_pane(pane_id).await);
+                })
+                .detach();
+            }
+
+            Pdu::SwapPanes(SwapPanes {
+                pane_id,
+                other_pane_id,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            mux.swap_panes(pane_id, other_pane_id)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::MovePane(MovePane {
+                pane_id,
+                target_pane_id,
+                side,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            mux.move_pane_beside(pane_id, target_pane_id, side)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::SubscribeToMuxEvents(SubscribeToMuxEvents {}) => {
+                self.subscribed_to_mux_events = true;
+                send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+            }
+
+            Pdu::GetStats(GetStats {}) => {
+                // The pane memory estimates need the mux
+                spawn_into_main_thread(async move {
+                    send_response(Ok(Pdu::GetStatsResponse(GetStatsResponse {
+                        stats: mux::stats::snapshot(),
+                    })))
+                })
+                .detach();
+            }
+
             Pdu::Invalid { .. } => send_response(Err(anyhow!("invalid PDU {:?}", decoded.pdu))),
             Pdu::Pong { .. }
             | Pdu::ListPanesResponse { .. }
@@ -503,6 +855,15 @@ impl SessionHandler {
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
             | Pdu::GetTlsCredsResponse { .. }
+            | Pdu::GetClientListResponse { .. }
+            | Pdu::SftpStatResponse { .. }
+            | Pdu::SftpReadResponse { .. }
+            | Pdu::GetStatsResponse { .. }
+            | Pdu::RestoreSessionResponse { .. }
+            | Pdu::MuxEventPdu { .. }
+            | Pdu::CapturePaneResponse { .. }
+            | Pdu::WaitPaneResponse { .. }
+            | Pdu::ChoosePaneResponse { .. }
             | Pdu::ErrorResponse { .. } => {
                 send_response(Err(anyhow!("expected a request, got {:?}", decoded.pdu)))
             }
@@ -510,6 +871,50 @@ impl SessionHandler {
     }
 }
 
+/// Resolves once the pane has been removed from the mux, which happens
+/// shortly after its process exits.  A pane whose process has already
+/// exited resolves immediately.
+async fn wait_for_pane(pane_id: PaneId) -> anyhow::Result<Pdu> {
+    // The mux must not be held across an await, as the future
+    // needs to be Send
+    fn is_present(pane_id: PaneId) -> bool {
+        Mux::get().unwrap().get_pane(pane_id).is_some()
+    }
+    fn exit_code(pane_id: PaneId) -> Option<u32> {
+        Mux::get()
+            .unwrap()
+            .removed_pane_exit_status(pane_id)
+            .map(|status| status.exit_code())
+    }
+
+    if !is_present(pane_id) && exit_code(pane_id).is_none() {
+        anyhow::bail!("no such pane {}", pane_id);
+    }
+    while is_present(pane_id) {
+        smol::Timer::after(Duration::from_millis(200)).await;
+    }
+    Ok(Pdu::WaitPaneResponse(WaitPaneResponse {
+        exit_code: exit_code(pane_id),
+    }))
+}
+
+/// Resolves to the pane that the user picks from the labels that the
+/// gui overlays on the panes of the tab that holds `pane_id`
+async fn choose_pane(pane_id: PaneId) -> anyhow::Result<Pdu> {
+    let choice = Mux::get().unwrap().choose_pane(pane_id)?;
+    // The gui gives up on its own after a while, so if nothing has
+    // arrived by now then there is no gui showing the pane
+    let chosen = smol::future::or(async { choice.recv().await.ok() }, async {
+        smol::Timer::after(mux::PANE_CHOOSER_TIMEOUT * 2).await;
+        None
+    })
+    .await
+    .ok_or_else(|| anyhow!("pane {} is not shown in a gui window", pane_id))?;
+    Ok(Pdu::ChoosePaneResponse(ChoosePaneResponse {
+        pane_id: chosen,
+    }))
+}
+
 // Dancing around a little bit here; we can't directly spawn_into_main_thread the domain_spawn
 // function below because the compiler thinks that all of its locals then need to be Send.
 // We need to shimmy through this helper to break that aspect of the compiler flow
@@ -528,6 +933,24 @@ where
     promise::spawn::spawn(async move { send_response(split_pane(split, sender).await) }).detach();
 }
 
+fn schedule_restore_session<SND>(send_response: SND)
+where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(restore_session().await) }).detach();
+}
+
+async fn restore_session() -> anyhow::Result<Pdu> {
+    let mux = Mux::get().unwrap();
+    let path = mux::snapshot::snapshot_path()
+        .ok_or_else(|| anyhow!("session snapshots are not enabled on this server"))?;
+    let snapshot = mux::snapshot::MuxSnapshot::load(&path)?;
+    let tabs_restored = snapshot.restore(&mux.default_domain()).await?;
+    Ok(Pdu::RestoreSessionResponse(RestoreSessionResponse {
+        tabs_restored,
+    }))
+}
+
 struct RemoteClipboard {
     sender: PduSender,
     pane_id: TabId,
@@ -49,7 +49,7 @@ fn main() {
 fn run() -> anyhow::Result<()> {
     env_bootstrap::bootstrap();
 
-    //stats::Stats::init()?;
+    mux::stats::Stats::init()?;
     config::designate_this_as_the_main_thread();
     let _saver = umask::UmaskSaver::new();
 
@@ -178,6 +178,12 @@ async fn async_run(cmd: Option<CommandBuilder>) -> anyhow::Result<()> {
     domain.attach().await?;
 
     let config = config::configuration();
+    let snapshot_path = mux::snapshot::default_snapshot_path("mux");
+    mux::snapshot::start_snapshots(snapshot_path.clone());
+    if cmd.is_none() && mux::snapshot::restore_on_startup(&snapshot_path, &domain).await {
+        return Ok(());
+    }
+
     let window_id = mux.new_empty_window();
     let _tab = mux
         .default_domain()
@@ -200,6 +206,14 @@ pub fn spawn_listener() -> anyhow::Result<()> {
         thread::spawn(move || {
             listener.run();
         });
+
+        if let Some(mut listener) =
+            wezterm_mux_server_impl::local::LocalListener::read_only_with_domain(unix_dom)?
+        {
+            thread::spawn(move || {
+                listener.run();
+            });
+        }
     }
 
     for tls_server in &config.tls_servers {
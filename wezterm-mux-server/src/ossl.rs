@@ -84,9 +84,10 @@ impl OpenSSLNetListener {
                             }
                             spawn_into_main_thread(async move {
                                 log::error!("Making new AsyncSslStream");
-                                wezterm_mux_server_impl::dispatch::process(AsyncSslStream::new(
-                                    stream,
-                                ))
+                                wezterm_mux_server_impl::dispatch::process(
+                                    AsyncSslStream::new(stream),
+                                    false,
+                                )
                                 .await
                                 .map_err(|e| {
                                     log::error!("process: {:?}", e);
@@ -1,66 +1,104 @@
 mod macos;
 
-#[allow(unused_variables)]
-pub fn persistent_toast_notification_with_click_to_open_url(title: &str, message: &str, url: &str) {
-    #[cfg(target_os = "macos")]
-    {
-        macos::show_notif(title, message, Some(url));
-    }
+use std::time::Duration;
 
-    #[cfg(all(not(target_os = "macos"), not(windows)))]
-    {
-        let title = title.to_owned();
-        let message = message.to_owned();
-        let url = url.to_owned();
+/// Called when the user clicks on a notification
+pub type ClickHandler = Box<dyn FnOnce() + Send + 'static>;
 
-        std::thread::spawn(move || {
-            if let Ok(notif) = notify_rust::Notification::new()
-                .appname("wezterm")
-                .summary(&title)
-                .body(&message)
-                .icon("org.wezfurlong.wezterm")
-                .hint(notify_rust::Hint::Resident(true))
-                .timeout(notify_rust::Timeout::Never)
-                .action("Show", "Show")
-                .show()
-            {
-                notif.wait_for_action(move |action| {
-                    if action == "Show" {
-                        let _ = open::that(&*url);
-                    }
-                });
-            }
-        });
+pub struct ToastNotification {
+    pub title: String,
+    pub message: String,
+    /// Opened in the browser when the notification is clicked
+    pub url: Option<String>,
+    /// Called when the notification is clicked.
+    /// This is not supported on Windows.
+    pub on_click: Option<ClickHandler>,
+    /// How long the notification is shown for; if None, it
+    /// persists until it is dismissed
+    pub timeout: Option<Duration>,
+}
+
+impl ToastNotification {
+    pub fn new(title: &str, message: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            message: message.to_owned(),
+            url: None,
+            on_click: None,
+            timeout: None,
+        }
     }
 
-    // No impl for the other OS's at this time
+    #[cfg(not(windows))]
+    fn activate(url: Option<String>, on_click: Option<ClickHandler>) {
+        if let Some(url) = url {
+            let _ = open::that(&*url);
+        }
+        if let Some(on_click) = on_click {
+            on_click();
+        }
+    }
 }
 
-pub fn persistent_toast_notification(title: &str, message: &str) {
+/// Shows a notification using the native notification system:
+/// the freedesktop notification service on unix systems,
+/// the notification center on macOS and toasts on Windows.
+pub fn show(notif: ToastNotification) {
     #[cfg(target_os = "macos")]
     {
-        macos::show_notif(title, message, None);
+        macos::show_notif(notif);
     }
 
     #[cfg(all(not(target_os = "macos"), not(windows)))]
     {
-        let mut notif = notify_rust::Notification::new();
-        notif
-            .appname("wezterm")
-            .summary(title)
-            .body(message)
-            .icon("org.wezfurlong.wezterm")
-            .hint(notify_rust::Hint::Resident(true))
-            .timeout(notify_rust::Timeout::Never)
-            .show()
-            .ok();
+        std::thread::spawn(move || {
+            let ToastNotification {
+                title,
+                message,
+                url,
+                on_click,
+                timeout,
+            } = notif;
+            let clickable = url.is_some() || on_click.is_some();
+
+            let mut builder = notify_rust::Notification::new();
+            builder
+                .appname("wezterm")
+                .summary(&title)
+                .body(&message)
+                .icon("org.wezfurlong.wezterm");
+            match timeout {
+                Some(timeout) => {
+                    builder.timeout(notify_rust::Timeout::Milliseconds(
+                        timeout.as_millis() as u32
+                    ));
+                }
+                None => {
+                    builder
+                        .hint(notify_rust::Hint::Resident(true))
+                        .timeout(notify_rust::Timeout::Never);
+                }
+            }
+            if clickable {
+                // Most notification servers send the "default" action
+                // when the body of the notification is clicked
+                builder.action("default", "Show");
+            }
+
+            if let Ok(handle) = builder.show() {
+                if clickable {
+                    handle.wait_for_action(move |action| {
+                        if action == "default" {
+                            ToastNotification::activate(url, on_click);
+                        }
+                    });
+                }
+            }
+        });
     }
 
     #[cfg(windows)]
     {
-        let title = title.to_owned();
-        let message = message.to_owned();
-
         // We need to be in a different thread from the caller
         // in case we get called in the guts of a windows message
         // loop dispatch and are unable to pump messages
@@ -68,11 +106,25 @@ pub fn persistent_toast_notification(title: &str, message: &str) {
             use winrt_notification::Toast;
 
             Toast::new(Toast::POWERSHELL_APP_ID)
-                .title(&title)
-                .text1(&message)
-                .duration(winrt_notification::Duration::Long)
+                .title(&notif.title)
+                .text1(&notif.message)
+                .duration(if notif.timeout.is_some() {
+                    winrt_notification::Duration::Short
+                } else {
+                    winrt_notification::Duration::Long
+                })
                 .show()
                 .ok();
         });
     }
 }
+
+pub fn persistent_toast_notification_with_click_to_open_url(title: &str, message: &str, url: &str) {
+    let mut notif = ToastNotification::new(title, message);
+    notif.url = Some(url.to_owned());
+    show(notif);
+}
+
+pub fn persistent_toast_notification(title: &str, message: &str) {
+    show(ToastNotification::new(title, message));
+}
@@ -1,5 +1,6 @@
 #![cfg(target_os = "macos")]
 
+use crate::{ClickHandler, ToastNotification};
 use cocoa::base::*;
 use cocoa::foundation::{NSDictionary, NSString};
 use core_foundation::dictionary::CFMutableDictionary;
@@ -8,9 +9,38 @@ use objc::declare::ClassDecl;
 use objc::rc::StrongPtr;
 use objc::runtime::{Class, Object, Protocol, Sel};
 use objc::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 const DELEGATE_CLS_NAME: &str = "WezTermNotifDelegate";
 
+lazy_static::lazy_static! {
+    /// The click handlers of the notifications that are still being
+    /// shown, keyed by the `click_id` in their userInfo
+    static ref CLICK_HANDLERS: Mutex<HashMap<usize, ClickHandler>> = Mutex::new(HashMap::new());
+}
+static NEXT_CLICK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the string value of `key` in the userInfo of `notif`
+unsafe fn user_info_value(notif: id, key: &str) -> Option<String> {
+    let info: *mut Object = msg_send![notif, userInfo];
+    if info.is_null() {
+        return None;
+    }
+    let value = info.valueForKey_(*nsstring(key));
+    if value.is_null() {
+        return None;
+    }
+    let value = std::slice::from_raw_parts(value.UTF8String() as *const u8, value.len());
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+unsafe fn take_click_handler(notif: id) -> Option<ClickHandler> {
+    let click_id = user_info_value(notif, "click_id")?.parse::<usize>().ok()?;
+    CLICK_HANDLERS.lock().unwrap().remove(&click_id)
+}
+
 struct NotifDelegate {}
 
 impl NotifDelegate {
@@ -49,6 +79,7 @@ impl NotifDelegate {
 
     extern "C" fn did_dismiss_alert(_: &mut Object, _sel: Sel, center: id, notif: id) {
         unsafe {
+            take_click_handler(notif);
             let () = msg_send![center, removeDeliveredNotification: notif];
         }
     }
@@ -57,15 +88,11 @@ impl NotifDelegate {
 
     extern "C" fn did_activate_notif(_: &mut Object, _sel: Sel, center: id, notif: id) {
         unsafe {
-            let info: *mut Object = msg_send![notif, userInfo];
-
-            // If the notification had an associated URL, open it!
-            let url = info.valueForKey_(*nsstring("url"));
-            if !url.is_null() {
-                let url = std::slice::from_raw_parts(url.UTF8String() as *const u8, url.len());
-                let url = String::from_utf8_lossy(url);
-                let _ = open::that(&*url);
-            }
+            // If the notification had an associated URL or click
+            // handler, activate it!
+            let url = user_info_value(notif, "url");
+            let on_click = take_click_handler(notif);
+            ToastNotification::activate(url, on_click);
             let () = msg_send![center, removeDeliveredNotification: notif];
         }
     }
@@ -82,7 +109,15 @@ fn nsstring(s: &str) -> StrongPtr {
     unsafe { StrongPtr::new(NSString::alloc(nil).init_str(s)) }
 }
 
-pub fn show_notif(title: &str, message: &str, url: Option<&str>) {
+pub fn show_notif(notif: ToastNotification) {
+    let ToastNotification {
+        title,
+        message,
+        url,
+        on_click,
+        // The notification center decides how long to show it for
+        timeout: _,
+    } = notif;
     unsafe {
         let center: id = msg_send![
             class!(NSUserNotificationCenter),
@@ -91,12 +126,22 @@ pub fn show_notif(title: &str, message: &str, url: Option<&str>) {
         let notif: id = msg_send![class!(NSUserNotification), alloc];
         let notif: id = msg_send![notif, init];
 
-        let () = msg_send![notif, setTitle: nsstring(title)];
-        let () = msg_send![notif, setInformativeText: nsstring(message)];
+        let () = msg_send![notif, setTitle: nsstring(&title)];
+        let () = msg_send![notif, setInformativeText: nsstring(&message)];
 
         let mut info = CFMutableDictionary::new();
-        if let Some(url) = url {
+        if let Some(url) = &url {
             info.set(CFString::from_static_string("url"), CFString::new(url));
+        }
+        if let Some(on_click) = on_click {
+            let click_id = NEXT_CLICK_ID.fetch_add(1, Ordering::Relaxed);
+            CLICK_HANDLERS.lock().unwrap().insert(click_id, on_click);
+            info.set(
+                CFString::from_static_string("click_id"),
+                CFString::new(&click_id.to_string()),
+            );
+        }
+        if info.len() > 0 {
             let () = msg_send![notif, setUserInfo: info];
         }
 
@@ -0,0 +1,337 @@
+//! Implements `wezterm bench`, which replays recorded terminal output
+//! through the parser, the terminal model and the font shaper without
+//! creating any windows, and reports how quickly it went.
+use anyhow::{anyhow, Context};
+use config::{wezterm_version, TermConfig};
+use std::ffi::OsString;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tabout::{tabulate_output, Alignment, Column};
+use termwiz::escape::parser::Parser;
+use wezterm_term::{Terminal, TerminalSize};
+
+/// The size of the reads that the mux makes from a pty
+const CHUNK_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchCommand {
+    #[structopt(subcommand)]
+    sub: BenchSubCommand,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct CorpusOptions {
+    /// How many times to replay each corpus; the fastest run is reported
+    #[structopt(long = "iterations", default_value = "3")]
+    iterations: usize,
+
+    /// Files containing the output to replay, such as a file recorded
+    /// by `script`.  If omitted, some built-in samples of plain ASCII,
+    /// UTF-8 and heavily colored text are used.
+    #[structopt(parse(from_os_str))]
+    files: Vec<OsString>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+struct ModelOptions {
+    /// The number of rows in the simulated terminal
+    #[structopt(long = "rows", default_value = "50")]
+    rows: usize,
+
+    /// The number of columns in the simulated terminal
+    #[structopt(long = "cols", default_value = "200")]
+    cols: usize,
+
+    /// How much output to process between each frame
+    #[structopt(long = "frame-bytes", default_value = "65536")]
+    frame_bytes: usize,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+enum BenchSubCommand {
+    #[structopt(
+        name = "parser",
+        about = "Measure the throughput of the escape sequence parser"
+    )]
+    Parser {
+        #[structopt(flatten)]
+        corpus: CorpusOptions,
+    },
+
+    #[structopt(
+        name = "render",
+        about = "Measure the throughput of the terminal model, and the \
+                 time taken to prepare the visible lines for each frame"
+    )]
+    Render {
+        #[structopt(flatten)]
+        corpus: CorpusOptions,
+        #[structopt(flatten)]
+        model: ModelOptions,
+    },
+
+    #[structopt(
+        name = "shaping",
+        about = "Measure the time taken to shape the visible lines for each frame, \
+                 using the fonts from your configuration and without any caching"
+    )]
+    Shaping {
+        #[structopt(flatten)]
+        corpus: CorpusOptions,
+        #[structopt(flatten)]
+        model: ModelOptions,
+    },
+}
+
+struct Corpus {
+    name: String,
+    data: Vec<u8>,
+}
+
+/// The measurements from one replay of a corpus
+#[derive(Default)]
+struct Sample {
+    elapsed: Duration,
+    /// How long each frame took to prepare, if the benchmark has frames
+    frames: Vec<Duration>,
+    /// A count of whatever the benchmark produces; actions, clusters, glyphs
+    items: usize,
+}
+
+impl BenchCommand {
+    pub fn run(&self) -> anyhow::Result<()> {
+        match &self.sub {
+            BenchSubCommand::Parser { corpus } => run_bench(corpus, "ACTIONS", bench_parser),
+            BenchSubCommand::Render { corpus, model } => {
+                run_bench(corpus, "CLUSTERS", |data| bench_render(data, model))
+            }
+            BenchSubCommand::Shaping { corpus, model } => {
+                let fonts = wezterm_font::FontConfiguration::new()?;
+                run_bench(corpus, "GLYPHS", |data| bench_shaping(data, model, &fonts))
+            }
+        }
+    }
+}
+
+fn load_corpora(options: &CorpusOptions) -> anyhow::Result<Vec<Corpus>> {
+    if options.files.is_empty() {
+        return Ok(builtin_corpora());
+    }
+    options
+        .files
+        .iter()
+        .map(|file| {
+            let data = std::fs::read(file)
+                .with_context(|| format!("reading {}", file.to_string_lossy()))?;
+            Ok(Corpus {
+                name: file.to_string_lossy().to_string(),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Repeats `line` until it produces roughly 4MiB of output
+fn repeat_line(name: &str, line: &str) -> Corpus {
+    Corpus {
+        name: name.to_string(),
+        data: line.repeat((4 * 1024 * 1024) / line.len()).into_bytes(),
+    }
+}
+
+fn builtin_corpora() -> Vec<Corpus> {
+    vec![
+        repeat_line(
+            "ascii",
+            "The quick brown fox jumps over the lazy dog; 0123456789 again.\r\n",
+        ),
+        repeat_line(
+            "utf8",
+            "Příliš žluťoučký kůň úpěl ďábelské ódy — 日本語のテキスト ☃ 🦀\r\n",
+        ),
+        repeat_line(
+            "sgr",
+            "\x1b[1;32mok\x1b[0m  \x1b[38;2;255;128;0msrc/main.rs\x1b[0m:\x1b[33m42\x1b[0m: \
+             warning\r\n",
+        ),
+    ]
+}
+
+fn run_bench<F>(options: &CorpusOptions, items_name: &str, mut bench: F) -> anyhow::Result<()>
+where
+    F: FnMut(&[u8]) -> anyhow::Result<Sample>,
+{
+    if options.iterations == 0 {
+        return Err(anyhow!("--iterations must be at least 1"));
+    }
+
+    let cols = vec![
+        Column {
+            name: "CORPUS".to_string(),
+            alignment: Alignment::Left,
+        },
+        Column {
+            name: "SIZE".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "TIME".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "THROUGHPUT".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: items_name.to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "FRAME P50".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "FRAME P99".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "FRAME MAX".to_string(),
+            alignment: Alignment::Right,
+        },
+    ];
+    let mut data = vec![];
+
+    for corpus in load_corpora(options)? {
+        let mut best: Option<Sample> = None;
+        for _ in 0..options.iterations {
+            let sample = bench(&corpus.data)?;
+            if best
+                .as_ref()
+                .map(|best| sample.elapsed < best.elapsed)
+                .unwrap_or(true)
+            {
+                best.replace(sample);
+            }
+        }
+        let mut best = best.unwrap();
+        best.frames.sort();
+
+        let mib = corpus.data.len() as f64 / (1024. * 1024.);
+        data.push(vec![
+            corpus.name,
+            format!("{:.1}MiB", mib),
+            format_duration(best.elapsed),
+            format!("{:.1}MiB/s", mib / best.elapsed.as_secs_f64()),
+            best.items.to_string(),
+            percentile(&best.frames, 50.),
+            percentile(&best.frames, 99.),
+            percentile(&best.frames, 100.),
+        ]);
+    }
+
+    tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+/// Returns the `pct` percentile of the sorted `durations`
+fn percentile(durations: &[Duration], pct: f64) -> String {
+    if durations.is_empty() {
+        return "-".to_string();
+    }
+    let idx = ((durations.len() - 1) as f64 * pct / 100.).round() as usize;
+    format_duration(durations[idx])
+}
+
+fn format_duration(d: Duration) -> String {
+    if d >= Duration::from_secs(1) {
+        format!("{:.2}s", d.as_secs_f64())
+    } else if d >= Duration::from_millis(1) {
+        format!("{:.2}ms", d.as_secs_f64() * 1000.)
+    } else {
+        format!("{}µs", d.as_micros())
+    }
+}
+
+fn bench_parser(data: &[u8]) -> anyhow::Result<Sample> {
+    let mut parser = Parser::new();
+    let mut items = 0;
+    let start = Instant::now();
+    for chunk in data.chunks(CHUNK_SIZE) {
+        parser.parse(chunk, |_action| items += 1);
+    }
+    Ok(Sample {
+        elapsed: start.elapsed(),
+        frames: vec![],
+        items,
+    })
+}
+
+fn new_terminal(model: &ModelOptions) -> Terminal {
+    Terminal::new(
+        TerminalSize {
+            physical_rows: model.rows,
+            physical_cols: model.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        },
+        Arc::new(TermConfig::new()),
+        "WezTerm",
+        wezterm_version(),
+        Box::new(std::io::sink()),
+    )
+}
+
+/// Feeds `data` to a terminal in pty sized chunks, and calls `frame`
+/// with the visible lines after each `model.frame_bytes` of output
+/// and at the end.  `frame` returns the number of items it produced.
+fn replay<F>(data: &[u8], model: &ModelOptions, mut frame: F) -> anyhow::Result<Sample>
+where
+    F: FnMut(Vec<wezterm_term::Line>) -> anyhow::Result<usize>,
+{
+    let mut term = new_terminal(model);
+    let mut sample = Sample::default();
+    let mut since_frame = 0;
+    let start = Instant::now();
+
+    let chunks = data.chunks(CHUNK_SIZE);
+    let num_chunks = chunks.len();
+    for (idx, chunk) in chunks.enumerate() {
+        term.advance_bytes(chunk);
+        since_frame += chunk.len();
+        if since_frame >= model.frame_bytes || idx + 1 == num_chunks {
+            since_frame = 0;
+            let frame_start = Instant::now();
+            sample.items += frame(term.screen().visible_lines())?;
+            sample.frames.push(frame_start.elapsed());
+        }
+    }
+
+    sample.elapsed = start.elapsed();
+    Ok(sample)
+}
+
+fn bench_render(data: &[u8], model: &ModelOptions) -> anyhow::Result<Sample> {
+    replay(data, model, |lines| {
+        Ok(lines.iter().map(|line| line.cluster().len()).sum())
+    })
+}
+
+fn bench_shaping(
+    data: &[u8],
+    model: &ModelOptions,
+    fonts: &wezterm_font::FontConfiguration,
+) -> anyhow::Result<Sample> {
+    let config = config::configuration();
+    replay(data, model, |lines| {
+        let mut glyphs = 0;
+        for line in &lines {
+            for cluster in line.cluster() {
+                let style = fonts.match_style(&config, &cluster.attrs);
+                let font = fonts.resolve_font(style)?;
+                glyphs += font.shape(&cluster.text)?.len();
+            }
+        }
+        Ok(glyphs)
+    })
+}
@@ -0,0 +1,186 @@
+//! Selects and formats the panes that `wezterm cli list` shows.
+//! Each pane is described by a set of named fields, which can be
+//! used both to choose the columns of the output and to filter it.
+use anyhow::{anyhow, bail};
+use codec::ListPanesResponse;
+use mux::tab::PaneEntry;
+use regex::Regex;
+use serde_json::{Map, Value};
+use tabout::{tabulate_output_as_string, Alignment, Column};
+
+/// The fields that describe a pane, along with the heading and
+/// alignment of their column in the table
+const FIELDS: &[(&str, &str, Alignment)] = &[
+    ("window_id", "WINID", Alignment::Right),
+    ("tab_id", "TABID", Alignment::Right),
+    ("pane_id", "PANEID", Alignment::Right),
+    ("domain", "DOMAIN", Alignment::Left),
+    ("size", "SIZE", Alignment::Left),
+    ("title", "TITLE", Alignment::Left),
+    ("cwd", "CWD", Alignment::Left),
+    ("is_active", "ACTIVE", Alignment::Left),
+    ("is_zoomed", "ZOOMED", Alignment::Left),
+];
+
+const DEFAULT_FIELDS: &[&str] = &["window_id", "tab_id", "pane_id", "size", "title", "cwd"];
+
+fn check_field(field: &str) -> anyhow::Result<()> {
+    if FIELDS.iter().any(|(name, _, _)| *name == field) {
+        Ok(())
+    } else {
+        let names: Vec<&str> = FIELDS.iter().map(|(name, _, _)| *name).collect();
+        bail!(
+            "unknown field `{}`; the fields are {}",
+            field,
+            names.join(", ")
+        )
+    }
+}
+
+/// Returns the fields to show; the default fields if none were given
+pub fn parse_fields(fields: &[String]) -> anyhow::Result<Vec<String>> {
+    if fields.is_empty() {
+        return Ok(DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect());
+    }
+    for field in fields {
+        check_field(field)?;
+    }
+    Ok(fields.to_vec())
+}
+
+fn field_value(entry: &PaneEntry, field: &str) -> Value {
+    match field {
+        "window_id" => entry.window_id.into(),
+        "tab_id" => entry.tab_id.into(),
+        "pane_id" => entry.pane_id.into(),
+        "domain" => entry.domain_name.clone().into(),
+        "size" => format!("{}x{}", entry.size.cols, entry.size.rows).into(),
+        "title" => entry.title.clone().into(),
+        "cwd" => entry
+            .working_dir
+            .as_ref()
+            .map(|url| url.url.as_str())
+            .unwrap_or("")
+            .into(),
+        "is_active" => entry.is_active_pane.into(),
+        "is_zoomed" => entry.is_zoomed_pane.into(),
+        _ => Value::Null,
+    }
+}
+
+fn value_as_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Selects the panes whose field either equals a value, as in
+/// `domain=local`, or matches a regex, as in `title~vim`
+pub enum Filter {
+    Equals { field: String, value: String },
+    Matches { field: String, regex: Regex },
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> anyhow::Result<Self> {
+        let idx = filter.find(|c: char| c == '=' || c == '~').ok_or_else(|| {
+            anyhow!(
+                "filter `{}` must be in the form FIELD=VALUE or FIELD~REGEX",
+                filter
+            )
+        })?;
+        let field = filter[..idx].to_string();
+        let value = &filter[idx + 1..];
+        check_field(&field)?;
+        if filter[idx..].starts_with('=') {
+            Ok(Self::Equals {
+                field,
+                value: value.to_string(),
+            })
+        } else {
+            let regex =
+                Regex::new(value).map_err(|err| anyhow!("in filter `{}`: {}", filter, err))?;
+            Ok(Self::Matches { field, regex })
+        }
+    }
+
+    fn matches(&self, entry: &PaneEntry) -> bool {
+        match self {
+            Self::Equals { field, value } => value_as_string(&field_value(entry, field)) == *value,
+            Self::Matches { field, regex } => {
+                regex.is_match(&value_as_string(&field_value(entry, field)))
+            }
+        }
+    }
+}
+
+/// Returns the panes, in the order in which they appear in their tabs,
+/// that match all of the filters
+pub fn select_panes(panes: ListPanesResponse, filters: &[Filter]) -> Vec<PaneEntry> {
+    let mut entries = vec![];
+    for tabroot in panes.tabs {
+        let mut cursor = tabroot.into_tree().cursor();
+
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                if filters.iter().all(|filter| filter.matches(entry)) {
+                    entries.push(entry.clone());
+                }
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+    }
+    entries
+}
+
+/// Formats the panes as a JSON array of objects that hold the fields.
+/// The array is on a single line unless `pretty` is true.
+pub fn format_json(
+    entries: &[PaneEntry],
+    fields: &[String],
+    pretty: bool,
+) -> anyhow::Result<String> {
+    let objects: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let mut object = Map::new();
+            for field in fields {
+                object.insert(field.clone(), field_value(entry, field));
+            }
+            Value::Object(object)
+        })
+        .collect();
+    let mut json = if pretty {
+        serde_json::to_string_pretty(&objects)?
+    } else {
+        serde_json::to_string(&objects)?
+    };
+    json.push('\n');
+    Ok(json)
+}
+
+/// Formats the panes as a table with a column for each of the fields
+pub fn format_table(entries: &[PaneEntry], fields: &[String]) -> anyhow::Result<String> {
+    let cols: Vec<Column> = fields
+        .iter()
+        .filter_map(|field| FIELDS.iter().find(|(name, _, _)| *name == field.as_str()))
+        .map(|(_, heading, alignment)| Column {
+            name: heading.to_string(),
+            alignment: *alignment,
+        })
+        .collect();
+    let data: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            fields
+                .iter()
+                .map(|field| value_as_string(&field_value(entry, field)))
+                .collect()
+        })
+        .collect();
+    Ok(tabulate_output_as_string(&cols, &data)?)
+}
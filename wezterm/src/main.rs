@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Context};
+use config::keyassignment::{PaneDirection, ScrollbackExportFormat};
 use config::wezterm_version;
 use mux::activity::Activity;
 use mux::pane::PaneId;
@@ -14,6 +15,10 @@ use umask::UmaskSaver;
 use wezterm_client::client::{unix_connect_with_retry, Client};
 use wezterm_gui_subcommands::*;
 
+mod bench;
+mod list;
+mod sendkeys;
+
 //    let message = "; ❤ 😍🤢\n\x1b[91;mw00t\n\x1b[37;104;m bleet\x1b[0;m.";
 //    terminal.advance_bytes(message);
 // !=
@@ -59,6 +64,25 @@ enum SubCommand {
                  emitting an OSC 7 escape sequence"
     )]
     SetCwd(SetCwdCommand),
+
+    #[structopt(
+        name = "ssh-hostkeys",
+        about = "Manage the ssh host keys in your known_hosts file"
+    )]
+    SshHostKeys(SshHostKeysCommand),
+
+    #[structopt(
+        name = "plugin",
+        about = "Manage the plugins that are loaded by wezterm.plugin.require"
+    )]
+    Plugin(PluginCommand),
+
+    #[structopt(
+        name = "bench",
+        about = "Replay terminal output through the parser, terminal model \
+                 or font shaper and report how quickly it was processed"
+    )]
+    Bench(bench::BenchCommand),
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -73,15 +97,168 @@ struct CliCommand {
 
 #[derive(Debug, StructOpt, Clone)]
 enum CliSubCommand {
-    #[structopt(name = "list", about = "list windows, tabs and panes")]
-    List,
+    #[structopt(
+        name = "list",
+        about = "list windows, tabs and panes.
+Panes can be filtered by their fields, which are window_id, tab_id,
+pane_id, domain, size, title, cwd, is_active and is_zoomed"
+    )]
+    List {
+        /// Set the format of the output.  The default is table.
+        #[structopt(long = "format", possible_values = &["table", "json"])]
+        format: Option<String>,
+
+        /// Only list the panes whose FIELD equals VALUE, when given as
+        /// FIELD=VALUE, or matches the regex REGEX, when given as
+        /// FIELD~REGEX.  May be given more than once, in which case
+        /// panes must match all of the filters.
+        #[structopt(long = "filter", number_of_values = 1)]
+        filters: Vec<String>,
+
+        /// The comma separated fields to output
+        #[structopt(long = "fields", use_delimiter = true)]
+        fields: Vec<String>,
+
+        /// Keep running, and list the panes again each time that
+        /// the mux changes
+        #[structopt(long = "watch")]
+        watch: bool,
+    },
+
+    #[structopt(
+        name = "list-clients",
+        about = "list the ssh sessions held by the mux and the state of their port forwards"
+    )]
+    ListClients,
 
     #[structopt(name = "proxy", about = "start rpc proxy pipe")]
     Proxy,
 
+    #[structopt(
+        name = "scp",
+        about = "copy a file to or from the remote host of a connected ssh domain.
+The existing ssh session is used, so no authentication is required.
+Remote files are written as DOMAIN:PATH"
+    )]
+    Scp {
+        /// The file to copy; either a local path or DOMAIN:PATH
+        src: String,
+        /// Where to copy it to; either a local path or DOMAIN:PATH.
+        /// If it is a directory, the file is copied into it.
+        dest: String,
+    },
+
+    #[structopt(
+        name = "set-log-level",
+        about = "change which messages the gui or mux server logs, without restarting it.
+FILTER has the same syntax as the WEZTERM_LOG environment variable;
+for example `info,mux=debug`"
+    )]
+    SetLogLevel {
+        /// The new log filter
+        filter: String,
+    },
+
+    #[structopt(
+        name = "stats",
+        about = "show the frame times, parser throughput, rpc latencies and
+per-pane memory usage recorded by the gui or mux server"
+    )]
+    Stats {
+        /// Output the stats as JSON rather than as tables
+        #[structopt(long = "json")]
+        json: bool,
+    },
+
     #[structopt(name = "tlscreds", about = "obtain tls credentials")]
     TlsCreds,
 
+    #[structopt(
+        name = "restore",
+        about = "recreate the windows, tabs and panes from the most recent
+session snapshot saved by the gui or mux server.
+Snapshots are saved when session_snapshot_interval_secs is set"
+    )]
+    Restore,
+
+    #[structopt(
+        name = "send-keys",
+        about = "send keys to a pane as though they were typed into it.
+Each KEY is either the name of a key, optionally with modifiers,
+such as `CTRL+c`, `Enter` or `F5`, using the same names as key
+assignments, or otherwise text that is typed as-is"
+    )]
+    SendKeys {
+        /// Specify the pane that should receive the keys.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Type each KEY as text, even if it is the name of a key
+        #[structopt(long = "literal")]
+        literal: bool,
+
+        /// The keys to send, in order
+        #[structopt(name = "KEY", required = true)]
+        keys: Vec<String>,
+    },
+
+    #[structopt(
+        name = "capture-pane",
+        about = "print the text in the viewport of a pane, or in its whole
+scrollback, as plain text, as text with the escape sequences that
+reproduce its colors and styles, or as an HTML document that uses
+the colors of the pane"
+    )]
+    CapturePane {
+        /// Specify the pane to capture.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Capture the scrollback as well as the viewport
+        #[structopt(long = "scrollback")]
+        scrollback: bool,
+
+        /// Set the format of the output.  The default is text.
+        #[structopt(long = "format", possible_values = &["text", "escapes", "html"])]
+        format: Option<String>,
+
+        /// Write the output to FILE rather than to stdout
+        #[structopt(long = "output", short = "o", parse(from_os_str))]
+        output: Option<OsString>,
+    },
+
+    #[structopt(
+        name = "wait-pane",
+        about = "wait for the process in a pane to exit, then exit with
+the same exit status.  Panes whose domain doesn't report the exit
+status of their process are treated as having exited successfully"
+    )]
+    WaitPane {
+        /// Specify the pane to wait for.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Give up after waiting this many seconds, and exit with
+        /// an error
+        #[structopt(long = "timeout")]
+        timeout: Option<u64>,
+    },
+
+    #[structopt(
+        name = "subscribe",
+        about = "print the events that happen in the mux, such as panes,
+tabs and windows being created and removed, output, title changes,
+bells and user vars being set, as one line of JSON per event,
+until interrupted"
+    )]
+    Subscribe,
+
     #[structopt(
         name = "split-pane",
         about = "split the current pane.
@@ -109,6 +286,68 @@ Outputs the pane-id for the newly created pane on success"
         #[structopt(parse(from_os_str))]
         prog: Vec<OsString>,
     },
+
+    #[structopt(
+        name = "swap-pane",
+        about = "exchange the positions of two panes, which may be
+in different tabs"
+    )]
+    SwapPane {
+        /// Specify the pane to swap.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Specify the pane to swap it with
+        #[structopt(
+            long = "target-pane-id",
+            required_unless = "interactive",
+            conflicts_with = "interactive"
+        )]
+        target_pane_id: Option<PaneId>,
+
+        /// Rather than specifying the target pane, label the panes
+        /// of the tab in the gui and pick the target by typing its
+        /// label or clicking on it
+        #[structopt(long = "interactive")]
+        interactive: bool,
+    },
+
+    #[structopt(
+        name = "move-pane",
+        about = "move a pane out of its tab and split it in beside
+another pane, which may be in a different tab"
+    )]
+    MovePane {
+        /// Specify the pane to move.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Specify the pane to move it beside
+        #[structopt(
+            long = "target-pane-id",
+            required_unless = "interactive",
+            conflicts_with = "interactive"
+        )]
+        target_pane_id: Option<PaneId>,
+
+        /// Rather than specifying the target pane, label the panes
+        /// of the tab in the gui and pick the target by typing its
+        /// label or clicking on it
+        #[structopt(long = "interactive")]
+        interactive: bool,
+
+        /// Which side of the target pane to place it on
+        #[structopt(
+            long = "side",
+            default_value = "right",
+            possible_values = &["left", "right", "up", "down"]
+        )]
+        side: String,
+    },
 }
 
 use termwiz::escape::osc::{
@@ -203,6 +442,147 @@ impl SetCwdCommand {
     }
 }
 
+#[derive(Debug, StructOpt, Clone)]
+enum SshHostKeysCommand {
+    #[structopt(name = "list", about = "list the known host keys")]
+    List,
+
+    #[structopt(
+        name = "remove",
+        about = "remove the known host keys for a host, so that its key \
+                 will be verified again on the next connection"
+    )]
+    Remove {
+        /// The host whose keys should be removed.
+        /// Hosts that use a port other than 22 are written
+        /// as `[host]:port`.
+        host: String,
+    },
+}
+
+impl SshHostKeysCommand {
+    fn run(&self) -> anyhow::Result<()> {
+        match self {
+            Self::List => {
+                let cols = vec![
+                    Column {
+                        name: "HOST".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "TYPE".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "FINGERPRINT".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                ];
+                let data: Vec<Vec<String>> = mux::knownhosts::list_known_hosts()?
+                    .into_iter()
+                    .map(|entry| {
+                        vec![
+                            entry.host.unwrap_or_else(|| "(hashed)".to_string()),
+                            entry.key_type,
+                            entry.fingerprint,
+                        ]
+                    })
+                    .collect();
+                tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+            }
+            Self::Remove { host } => {
+                let removed = mux::knownhosts::remove_known_host(host)?;
+                if removed == 0 {
+                    anyhow::bail!(
+                        "no known host keys found for {}.  \
+                         Hashed entries cannot be matched by name; \
+                         use `ssh-keygen -R {}` to remove those",
+                        host,
+                        host
+                    );
+                }
+                println!("removed {} host key(s) for {}", removed, host);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt, Clone)]
+enum PluginCommand {
+    #[structopt(
+        name = "list",
+        about = "list the plugins in the lockfile and the commits that they use"
+    )]
+    List,
+
+    #[structopt(
+        name = "update",
+        about = "fetch the plugins and move each of them to the latest \
+                 commit that matches its tag, branch or commit, \
+                 recording the commits in the lockfile"
+    )]
+    Update {
+        /// Update only the plugin with this url
+        url: Option<String>,
+    },
+
+    #[structopt(
+        name = "remove",
+        about = "delete the copy of a plugin and remove it from the lockfile"
+    )]
+    Remove {
+        /// The url of the plugin to remove
+        url: String,
+    },
+}
+
+impl PluginCommand {
+    fn run(&self) -> anyhow::Result<()> {
+        match self {
+            Self::List => {
+                let cols = vec![
+                    Column {
+                        name: "URL".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "SPEC".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "COMMIT".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                ];
+                let data: Vec<Vec<String>> = config::plugin::Lockfile::load()?
+                    .plugin
+                    .into_iter()
+                    .map(|locked| vec![locked.url, locked.spec.describe(), locked.commit])
+                    .collect();
+                tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+            }
+            Self::Update { url } => {
+                let updated = config::plugin::update(url.as_deref())?;
+                if updated.is_empty() {
+                    println!("all plugins are up to date");
+                }
+                for plugin in updated {
+                    println!(
+                        "{}: {} -> {}",
+                        plugin.url, plugin.old_commit, plugin.new_commit
+                    );
+                }
+            }
+            Self::Remove { url } => {
+                config::plugin::remove(url)?;
+                println!("removed {}", url);
+            }
+        }
+        Ok(())
+    }
+}
+
 fn terminate_with_error_message(err: &str) -> ! {
     log::error!("{}; terminating", err);
     std::process::exit(1);
@@ -244,6 +624,9 @@ fn run() -> anyhow::Result<()> {
         | SubCommand::Connect(_) => delegate_to_gui(saver),
         SubCommand::ImageCat(cmd) => cmd.run(),
         SubCommand::SetCwd(cmd) => cmd.run(),
+        SubCommand::SshHostKeys(cmd) => cmd.run(),
+        SubCommand::Plugin(cmd) => cmd.run(),
+        SubCommand::Bench(cmd) => cmd.run(),
         SubCommand::Cli(cli) => run_cli(config, cli),
     }
 }
@@ -295,59 +678,105 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
     let mut ui = mux::connui::ConnectionUI::new_headless();
     let client = Client::new_default_unix_domain(initial, &mut ui)?;
     match cli.sub {
-        CliSubCommand::List => {
+        CliSubCommand::List {
+            format,
+            filters,
+            fields,
+            watch,
+        } => {
+            let filters = filters
+                .iter()
+                .map(|filter| list::Filter::parse(filter))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let fields = list::parse_fields(&fields)?;
+            let json = format.as_deref() == Some("json");
+            let render = |panes| {
+                let entries = list::select_panes(panes, &filters);
+                if json {
+                    // Watch mode emits one line per change
+                    list::format_json(&entries, &fields, !watch)
+                } else {
+                    list::format_table(&entries, &fields)
+                }
+            };
+
+            let mut stdout = std::io::stdout();
+            if !watch {
+                stdout.write_all(render(client.list_panes().await?)?.as_bytes())?;
+                return Ok(());
+            }
+
+            let events = client.subscribe_to_mux_events().await?;
+            let mut last = String::new();
+            loop {
+                let output = render(client.list_panes().await?)?;
+                if output != last {
+                    if !json && !last.is_empty() {
+                        writeln!(stdout)?;
+                    }
+                    stdout.write_all(output.as_bytes())?;
+                    stdout.flush()?;
+                    last = output;
+                }
+                if events.recv().await.is_err() {
+                    anyhow::bail!("lost the connection to the mux server");
+                }
+                // A burst of events only needs to be listed once
+                while events.try_recv().is_ok() {}
+            }
+        }
+        CliSubCommand::ListClients => {
             let cols = vec![
                 Column {
-                    name: "WINID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "TABID".to_string(),
-                    alignment: Alignment::Right,
+                    name: "DOMAIN".to_string(),
+                    alignment: Alignment::Left,
                 },
                 Column {
-                    name: "PANEID".to_string(),
-                    alignment: Alignment::Right,
+                    name: "REMOTE".to_string(),
+                    alignment: Alignment::Left,
                 },
                 Column {
-                    name: "SIZE".to_string(),
+                    name: "FORWARD".to_string(),
                     alignment: Alignment::Left,
                 },
                 Column {
-                    name: "TITLE".to_string(),
+                    name: "STATE".to_string(),
                     alignment: Alignment::Left,
                 },
                 Column {
-                    name: "CWD".to_string(),
-                    alignment: Alignment::Left,
+                    name: "CONNS".to_string(),
+                    alignment: Alignment::Right,
                 },
             ];
             let mut data = vec![];
-            let panes = client.list_panes().await?;
-
-            for tabroot in panes.tabs {
-                let mut cursor = tabroot.into_tree().cursor();
-
-                loop {
-                    if let Some(entry) = cursor.leaf_mut() {
-                        data.push(vec![
-                            entry.window_id.to_string(),
-                            entry.tab_id.to_string(),
-                            entry.pane_id.to_string(),
-                            format!("{}x{}", entry.size.cols, entry.size.rows),
-                            entry.title.clone(),
-                            entry
-                                .working_dir
-                                .as_ref()
-                                .map(|url| url.url.as_str())
-                                .unwrap_or("")
-                                .to_string(),
-                        ]);
-                    }
-                    match cursor.preorder_next() {
-                        Ok(c) => cursor = c,
-                        Err(_) => break,
-                    }
+            let clients = client.list_clients().await?;
+
+            for ssh_client in clients.clients {
+                if ssh_client.forwards.is_empty() {
+                    data.push(vec![
+                        ssh_client.domain_name.clone(),
+                        ssh_client.remote_address.clone(),
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                    ]);
+                }
+                for forward in &ssh_client.forwards {
+                    let kind = match forward.kind {
+                        mux::sshforward::ForwardKind::Local => "-L",
+                        mux::sshforward::ForwardKind::Remote => "-R",
+                        mux::sshforward::ForwardKind::Dynamic => "-D",
+                    };
+                    data.push(vec![
+                        ssh_client.domain_name.clone(),
+                        ssh_client.remote_address.clone(),
+                        format!("{} {}", kind, forward.description),
+                        forward.state.to_string(),
+                        format!(
+                            "{}/{}",
+                            forward.active_connections, forward.total_connections
+                        ),
+                    ]);
                 }
             }
 
@@ -393,6 +822,178 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             log::debug!("{:?}", spawned);
             println!("{}", spawned.pane_id);
         }
+        CliSubCommand::SwapPane {
+            pane_id,
+            target_pane_id,
+            interactive: _,
+        } => {
+            let pane_id: PaneId = match pane_id {
+                Some(p) => p,
+                None => std::env::var("WEZTERM_PANE")
+                    .map_err(|_| {
+                        anyhow!(
+                            "--pane-id was not specified and $WEZTERM_PANE
+                                    is not set in the environment"
+                        )
+                    })?
+                    .parse()?,
+            };
+            let other_pane_id = target_pane(&client, pane_id, target_pane_id).await?;
+            client
+                .swap_panes(codec::SwapPanes {
+                    pane_id,
+                    other_pane_id,
+                })
+                .await?;
+        }
+        CliSubCommand::MovePane {
+            pane_id,
+            target_pane_id,
+            interactive: _,
+            side,
+        } => {
+            let pane_id: PaneId = match pane_id {
+                Some(p) => p,
+                None => std::env::var("WEZTERM_PANE")
+                    .map_err(|_| {
+                        anyhow!(
+                            "--pane-id was not specified and $WEZTERM_PANE
+                                    is not set in the environment"
+                        )
+                    })?
+                    .parse()?,
+            };
+            let side = match side.as_str() {
+                "left" => PaneDirection::Left,
+                "up" => PaneDirection::Up,
+                "down" => PaneDirection::Down,
+                _ => PaneDirection::Right,
+            };
+            let target_pane_id = target_pane(&client, pane_id, target_pane_id).await?;
+            client
+                .move_pane(codec::MovePane {
+                    pane_id,
+                    target_pane_id,
+                    side,
+                })
+                .await?;
+        }
+        CliSubCommand::SendKeys {
+            pane_id,
+            literal,
+            keys,
+        } => {
+            let pane_id: PaneId = match pane_id {
+                Some(p) => p,
+                None => std::env::var("WEZTERM_PANE")
+                    .map_err(|_| {
+                        anyhow!(
+                            "--pane-id was not specified and $WEZTERM_PANE
+                                    is not set in the environment"
+                        )
+                    })?
+                    .parse()?,
+            };
+
+            // Parse everything before sending anything, so that a
+            // mistake doesn't leave the pane with half of the input
+            let mut events = vec![];
+            for key in &keys {
+                if literal {
+                    events.extend(sendkeys::literal_text(key));
+                } else {
+                    events.extend(sendkeys::parse_keys(key)?);
+                }
+            }
+            for event in events {
+                client
+                    .key_down(codec::SendKeyDown {
+                        pane_id,
+                        event,
+                        input_serial: codec::InputSerial::now(),
+                    })
+                    .await?;
+            }
+        }
+        CliSubCommand::CapturePane {
+            pane_id,
+            scrollback,
+            format,
+            output,
+        } => {
+            let pane_id: PaneId = match pane_id {
+                Some(p) => p,
+                None => std::env::var("WEZTERM_PANE")
+                    .map_err(|_| {
+                        anyhow!(
+                            "--pane-id was not specified and $WEZTERM_PANE
+                                    is not set in the environment"
+                        )
+                    })?
+                    .parse()?,
+            };
+            let format = match format.as_deref() {
+                Some("escapes") => ScrollbackExportFormat::Escapes,
+                Some("html") => ScrollbackExportFormat::Html,
+                _ => ScrollbackExportFormat::Text,
+            };
+
+            let captured = client
+                .capture_pane(codec::CapturePane {
+                    pane_id,
+                    format,
+                    scrollback,
+                })
+                .await?;
+            match output {
+                Some(path) => std::fs::write(&path, captured.text)
+                    .with_context(|| format!("writing {}", path.to_string_lossy()))?,
+                None => {
+                    let mut stdout = std::io::stdout();
+                    stdout.write_all(captured.text.as_bytes())?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        CliSubCommand::WaitPane { pane_id, timeout } => {
+            let pane_id: PaneId = match pane_id {
+                Some(p) => p,
+                None => std::env::var("WEZTERM_PANE")
+                    .map_err(|_| {
+                        anyhow!(
+                            "--pane-id was not specified and $WEZTERM_PANE
+                                    is not set in the environment"
+                        )
+                    })?
+                    .parse()?,
+            };
+
+            let wait = client.wait_pane(codec::WaitPane { pane_id });
+            let waited = match timeout {
+                Some(secs) => {
+                    let timeout = async move {
+                        smol::Timer::after(std::time::Duration::from_secs(secs)).await;
+                        Err(anyhow!("timed out waiting for pane {} to exit", pane_id))
+                    };
+                    smol::future::or(wait, timeout).await?
+                }
+                None => wait.await?,
+            };
+            if let Some(code) = waited.exit_code {
+                if code != 0 {
+                    std::process::exit(code as i32);
+                }
+            }
+        }
+        CliSubCommand::Subscribe => {
+            let events = client.subscribe_to_mux_events().await?;
+            let mut stdout = std::io::stdout();
+            while let Ok(event) = events.recv().await {
+                writeln!(stdout, "{}", mux_event_json(&event)?)?;
+                stdout.flush()?;
+            }
+            anyhow::bail!("lost the connection to the mux server");
+        }
         CliSubCommand::Proxy => {
             // The client object we created above will have spawned
             // the server if needed, so now all we need to do is turn
@@ -423,6 +1024,35 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
                 consume_stream_then_exit_process(stdin.lock(), stream);
             });
         }
+        CliSubCommand::Scp { src, dest } => {
+            match (split_remote_path(&src), split_remote_path(&dest)) {
+                ((None, local), (Some(domain), remote)) => {
+                    scp_upload(&client, local, domain, remote).await?
+                }
+                ((Some(domain), remote), (None, local)) => {
+                    scp_download(&client, domain, remote, local).await?
+                }
+                _ => anyhow::bail!("exactly one of SRC and DEST must be a remote DOMAIN:PATH"),
+            }
+        }
+        CliSubCommand::SetLogLevel { filter } => {
+            client
+                .set_log_filter(codec::SetLogFilter { filter })
+                .await?;
+        }
+        CliSubCommand::Stats { json } => {
+            let stats = client.get_stats().await?.stats;
+            if json {
+                serde_json::to_writer_pretty(std::io::stdout().lock(), &stats)?;
+                println!();
+            } else {
+                print_stats(&stats)?;
+            }
+        }
+        CliSubCommand::Restore => {
+            let restored = client.restore_session(codec::RestoreSession {}).await?;
+            println!("restored {} tabs", restored.tabs_restored);
+        }
         CliSubCommand::TlsCreds => {
             let creds = client.get_tls_creds().await?;
             codec::Pdu::GetTlsCredsResponse(creds).encode(std::io::stdout().lock(), 0)?;
@@ -431,6 +1061,308 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
     Ok(())
 }
 
+/// Formats a histogram value; durations are recorded in nanoseconds
+/// and the values of `.size` metrics in bytes
+fn format_stat_value(name: &str, value: f64) -> String {
+    if name.contains(".size") {
+        format_bytes(value)
+    } else {
+        format!("{:.2?}", std::time::Duration::from_nanos(value as u64))
+    }
+}
+
+fn format_bytes(value: f64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut value = value;
+    let mut unit = 0;
+    while value >= 1024. && unit + 1 < UNITS.len() {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", value, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// Returns `target_pane_id` if it was specified, or else the pane that
+/// the user picks from the labels that the gui shows on the panes of
+/// the tab that holds `pane_id`
+async fn target_pane(
+    client: &Client,
+    pane_id: PaneId,
+    target_pane_id: Option<PaneId>,
+) -> anyhow::Result<PaneId> {
+    if let Some(target_pane_id) = target_pane_id {
+        return Ok(target_pane_id);
+    }
+    client
+        .choose_pane(codec::ChoosePane { pane_id })
+        .await?
+        .pane_id
+        .ok_or_else(|| anyhow!("no pane was picked"))
+}
+
+/// Formats `event` as a single line of JSON, such as
+/// `{"event":"TitleChanged","pane_id":0,"title":"vim"}`
+fn mux_event_json(event: &codec::MuxEvent) -> anyhow::Result<String> {
+    let mut fields = serde_json::Map::new();
+    if let serde_json::Value::Object(variant) = serde_json::to_value(event)? {
+        for (name, value) in variant {
+            fields.insert("event".to_string(), serde_json::Value::String(name));
+            if let serde_json::Value::Object(value) = value {
+                fields.extend(value);
+            }
+        }
+    }
+    Ok(serde_json::to_string(&fields)?)
+}
+
+fn print_stats(stats: &mux::stats::StatsSnapshot) -> anyhow::Result<()> {
+    let mut out = std::io::stdout();
+
+    let cols = vec![
+        Column {
+            name: "STAT".to_string(),
+            alignment: Alignment::Left,
+        },
+        Column {
+            name: "COUNT".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "MEAN".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "p50".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "p95".to_string(),
+            alignment: Alignment::Right,
+        },
+        Column {
+            name: "MAX".to_string(),
+            alignment: Alignment::Right,
+        },
+    ];
+    let data: Vec<Vec<String>> = stats
+        .histograms
+        .iter()
+        .map(|h| {
+            vec![
+                h.name.clone(),
+                h.count.to_string(),
+                format_stat_value(&h.name, h.mean),
+                format_stat_value(&h.name, h.p50 as f64),
+                format_stat_value(&h.name, h.p95 as f64),
+                format_stat_value(&h.name, h.max as f64),
+            ]
+        })
+        .collect();
+    tabulate_output(&cols, &data, &mut out)?;
+
+    if !stats.counters.is_empty() {
+        let cols = vec![
+            Column {
+                name: "COUNTER".to_string(),
+                alignment: Alignment::Left,
+            },
+            Column {
+                name: "VALUE".to_string(),
+                alignment: Alignment::Right,
+            },
+        ];
+        let data: Vec<Vec<String>> = stats
+            .counters
+            .iter()
+            .map(|c| vec![c.name.clone(), c.value.to_string()])
+            .collect();
+        println!();
+        tabulate_output(&cols, &data, &mut out)?;
+    }
+
+    if let Some(throughput) = stats.parse_throughput() {
+        println!();
+        println!("parse throughput: {}/s", format_bytes(throughput));
+    }
+
+    if !stats.panes.is_empty() {
+        let cols = vec![
+            Column {
+                name: "PANEID".to_string(),
+                alignment: Alignment::Right,
+            },
+            Column {
+                name: "DOMAIN".to_string(),
+                alignment: Alignment::Left,
+            },
+            Column {
+                name: "LINES".to_string(),
+                alignment: Alignment::Right,
+            },
+            Column {
+                name: "MEMORY".to_string(),
+                alignment: Alignment::Right,
+            },
+            Column {
+                name: "TITLE".to_string(),
+                alignment: Alignment::Left,
+            },
+        ];
+        let data: Vec<Vec<String>> = stats
+            .panes
+            .iter()
+            .map(|p| {
+                vec![
+                    p.pane_id.to_string(),
+                    p.domain.clone(),
+                    p.lines.to_string(),
+                    p.bytes
+                        .map(|bytes| format_bytes(bytes as f64))
+                        .unwrap_or_else(|| "-".to_string()),
+                    p.title.clone(),
+                ]
+            })
+            .collect();
+        println!();
+        tabulate_output(&cols, &data, &mut out)?;
+    }
+
+    Ok(())
+}
+
+/// Splits a `DOMAIN:PATH` scp location into its domain and path.
+/// Local paths, including Windows paths such as `C:\\foo`, have no domain.
+fn split_remote_path(location: &str) -> (Option<&str>, &str) {
+    match location.find(':') {
+        Some(idx) if idx > 1 && !location[..idx].contains(|c| c == '/' || c == '\\') => {
+            (Some(&location[..idx]), &location[idx + 1..])
+        }
+        _ => (None, location),
+    }
+}
+
+/// The amount of data transferred by each request
+const SCP_CHUNK_SIZE: usize = 256 * 1024;
+
+fn scp_progress(name: &str, done: u64, total: u64) {
+    let percent = if total == 0 { 100 } else { done * 100 / total };
+    eprint!("\r{} {}/{} bytes ({}%)", name, done, total, percent);
+    std::io::stderr().flush().ok();
+}
+
+async fn scp_upload(
+    client: &Client,
+    local: &str,
+    domain: &str,
+    remote: &str,
+) -> anyhow::Result<()> {
+    let name = std::path::Path::new(local)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("{} is not a file", local))?;
+    let mut file = std::fs::File::open(local).with_context(|| format!("opening {}", local))?;
+    let total = file.metadata()?.len();
+
+    let target = client
+        .sftp_stat(codec::SftpStat {
+            domain: domain.to_string(),
+            path: remote.to_string(),
+        })
+        .await?;
+    let remote = if target.is_dir || remote.is_empty() || remote.ends_with('/') {
+        let dir = remote.trim_end_matches('/');
+        if dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", dir, name)
+        }
+    } else {
+        remote.to_string()
+    };
+
+    let mut offset = 0u64;
+    let mut buf = vec![0u8; SCP_CHUNK_SIZE];
+    loop {
+        scp_progress(name, offset, total);
+        let len = file.read(&mut buf)?;
+        // An empty file still needs to be created
+        if len == 0 && offset > 0 {
+            break;
+        }
+        client
+            .sftp_write(codec::SftpWrite {
+                domain: domain.to_string(),
+                path: remote.clone(),
+                offset,
+                data: buf[..len].to_vec(),
+            })
+            .await?;
+        offset += len as u64;
+        if len == 0 {
+            break;
+        }
+    }
+    eprintln!();
+    Ok(())
+}
+
+async fn scp_download(
+    client: &Client,
+    domain: &str,
+    remote: &str,
+    local: &str,
+) -> anyhow::Result<()> {
+    let info = client
+        .sftp_stat(codec::SftpStat {
+            domain: domain.to_string(),
+            path: remote.to_string(),
+        })
+        .await?;
+    if !info.exists {
+        anyhow::bail!("{}:{} does not exist", domain, remote);
+    }
+    if info.is_dir {
+        anyhow::bail!("{}:{} is a directory", domain, remote);
+    }
+    let name = std::path::Path::new(remote)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(remote);
+
+    let local = std::path::Path::new(local);
+    let local = if local.is_dir() {
+        local.join(name)
+    } else {
+        local.to_path_buf()
+    };
+    let mut file =
+        std::fs::File::create(&local).with_context(|| format!("creating {}", local.display()))?;
+
+    let mut offset = 0u64;
+    loop {
+        scp_progress(name, offset, info.size);
+        let chunk = client
+            .sftp_read(codec::SftpRead {
+                domain: domain.to_string(),
+                path: remote.to_string(),
+                offset,
+                len: SCP_CHUNK_SIZE,
+            })
+            .await?;
+        if chunk.data.is_empty() {
+            break;
+        }
+        file.write_all(&chunk.data)?;
+        offset += chunk.data.len() as u64;
+    }
+    eprintln!();
+    Ok(())
+}
+
 fn run_cli(config: config::ConfigHandle, cli: CliCommand) -> anyhow::Result<()> {
     let executor = promise::spawn::SimpleExecutor::new();
     promise::spawn::spawn(async move {
@@ -0,0 +1,156 @@
+//! Turns the key descriptions given to `wezterm cli send-keys` into
+//! key events.  Keys are named in the same way as in key assignments,
+//! and are encoded by the pane in the same way as keys that are
+//! pressed in the gui.
+use anyhow::{anyhow, bail};
+use config::configuration;
+use termwiz::input::{KeyCode as KC, KeyEvent, Modifiers};
+use wezterm_input_types::{KeyCode as WK, Modifiers as WM};
+
+fn key_code(key: &WK) -> Option<KC> {
+    let swap = configuration().swap_backspace_and_delete;
+    Some(match key {
+        WK::Char('\r') => KC::Enter,
+        WK::Char('\t') => KC::Tab,
+        WK::Char('\u{08}') if swap => KC::Delete,
+        WK::Char('\u{08}') => KC::Backspace,
+        WK::Char('\u{7f}') if swap => KC::Backspace,
+        WK::Char('\u{7f}') => KC::Delete,
+        WK::Char('\u{1b}') => KC::Escape,
+        WK::Char(c) => KC::Char(*c),
+        WK::Function(f) => KC::Function(*f),
+        WK::LeftArrow => KC::LeftArrow,
+        WK::RightArrow => KC::RightArrow,
+        WK::UpArrow => KC::UpArrow,
+        WK::DownArrow => KC::DownArrow,
+        WK::Home => KC::Home,
+        WK::End => KC::End,
+        WK::PageUp => KC::PageUp,
+        WK::PageDown => KC::PageDown,
+        WK::Insert => KC::Insert,
+        WK::Cancel => KC::Cancel,
+        WK::Clear => KC::Clear,
+        WK::Pause => KC::Pause,
+        WK::Select => KC::Select,
+        WK::Print => KC::Print,
+        WK::Execute => KC::Execute,
+        WK::PrintScreen => KC::PrintScreen,
+        WK::Help => KC::Help,
+        WK::Applications => KC::Applications,
+        WK::Multiply => KC::Multiply,
+        WK::Add => KC::Add,
+        WK::Separator => KC::Separator,
+        WK::Subtract => KC::Subtract,
+        WK::Decimal => KC::Decimal,
+        WK::Divide => KC::Divide,
+        WK::Numpad(0) => KC::Numpad0,
+        WK::Numpad(1) => KC::Numpad1,
+        WK::Numpad(2) => KC::Numpad2,
+        WK::Numpad(3) => KC::Numpad3,
+        WK::Numpad(4) => KC::Numpad4,
+        WK::Numpad(5) => KC::Numpad5,
+        WK::Numpad(6) => KC::Numpad6,
+        WK::Numpad(7) => KC::Numpad7,
+        WK::Numpad(8) => KC::Numpad8,
+        WK::Numpad(9) => KC::Numpad9,
+        WK::ApplicationLeftArrow => KC::ApplicationLeftArrow,
+        WK::ApplicationRightArrow => KC::ApplicationRightArrow,
+        WK::ApplicationUpArrow => KC::ApplicationUpArrow,
+        WK::ApplicationDownArrow => KC::ApplicationDownArrow,
+        // Modifier keys on their own, media keys and raw codes
+        // don't produce any input for the pane
+        _ => return None,
+    })
+}
+
+fn modifiers(mods: WM) -> Modifiers {
+    let mut result = Modifiers::NONE;
+    if mods.contains(WM::SHIFT) {
+        result.insert(Modifiers::SHIFT);
+    }
+    if mods.intersects(WM::ALT | WM::LEFT_ALT | WM::RIGHT_ALT) {
+        result.insert(Modifiers::ALT);
+    }
+    if mods.contains(WM::CTRL) {
+        result.insert(Modifiers::CTRL);
+    }
+    if mods.contains(WM::SUPER) {
+        result.insert(Modifiers::SUPER);
+    }
+    result
+}
+
+/// Returns the key events that type `text` as-is
+pub fn literal_text(text: &str) -> Vec<KeyEvent> {
+    text.chars()
+        .map(|c| KeyEvent {
+            key: KC::Char(c),
+            modifiers: Modifiers::NONE,
+        })
+        .collect()
+}
+
+/// Returns the key events for `arg`, which is either a key with
+/// optional modifiers, such as `CTRL+c` or `Enter`, or otherwise
+/// literal text that is sent one character at a time.
+/// Text that looks like it was meant to be a key with modifiers, but
+/// isn't one, is an error rather than being typed, so that a mistake
+/// such as `CTLR+c` doesn't end up in the pane.
+pub fn parse_keys(arg: &str) -> anyhow::Result<Vec<KeyEvent>> {
+    match config::parse_key_combo(arg) {
+        Ok((key, mods)) => {
+            let key =
+                key_code(&key).ok_or_else(|| anyhow!("{} does not produce any input", arg))?;
+            Ok(vec![KeyEvent {
+                key,
+                modifiers: modifiers(mods),
+            }])
+        }
+        Err(err) if arg.contains(&['+', '|'][..]) => {
+            bail!("{}; use --literal to type {} as text", err, arg)
+        }
+        Err(_) => Ok(literal_text(arg)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: KC, modifiers: Modifiers) -> Vec<KeyEvent> {
+        vec![KeyEvent { key, modifiers }]
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_keys("CTRL++").unwrap(),
+            key(KC::Char('+'), Modifiers::CTRL)
+        );
+        assert_eq!(
+            parse_keys("CTRL|SHIFT+F5").unwrap(),
+            key(KC::Function(5), Modifiers::CTRL | Modifiers::SHIFT)
+        );
+        assert_eq!(
+            parse_keys("Enter").unwrap(),
+            key(KC::Enter, Modifiers::NONE)
+        );
+        assert_eq!(
+            parse_keys("a").unwrap(),
+            key(KC::Char('a'), Modifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(parse_keys("ls -l").unwrap(), literal_text("ls -l"));
+        assert_eq!(literal_text("Enter").len(), 5);
+    }
+
+    #[test]
+    fn mistakes() {
+        assert!(parse_keys("CTLR+c").is_err());
+        assert!(parse_keys("CTRL+Entr").is_err());
+        assert!(parse_keys("CTRL|SHFT+a").is_err());
+    }
+}
@@ -27,6 +27,10 @@ fn main() {
             "EGL_MESA_platform_gbm",
             "EGL_EXT_platform_wayland",
             "EGL_EXT_platform_device",
+            "EGL_EXT_device_base",
+            "EGL_EXT_device_enumeration",
+            "EGL_EXT_device_query",
+            "EGL_EXT_device_drm",
             "EGL_KHR_swap_buffers_with_damage",
         ],
     );
@@ -38,6 +42,38 @@ fn main() {
     }
     .unwrap();
 
+    #[cfg(feature = "wayland")]
+    {
+        // These protocols are newer than the ones that are
+        // bundled with wayland-protocols, so generate their bindings here
+        for (protocol, api) in &[
+            (
+                "protocols/fractional-scale-v1.xml",
+                "fractional_scale_v1_client_api.rs",
+            ),
+            (
+                "protocols/xdg-activation-v1.xml",
+                "xdg_activation_v1_client_api.rs",
+            ),
+            (
+                "protocols/wlr-layer-shell-unstable-v1.xml",
+                "wlr_layer_shell_unstable_v1_client_api.rs",
+            ),
+            (
+                "protocols/alpha-modifier-v1.xml",
+                "alpha_modifier_v1_client_api.rs",
+            ),
+            ("protocols/kde-blur.xml", "kde_blur_client_api.rs"),
+            (
+                "protocols/ext-background-effect-v1.xml",
+                "ext_background_effect_v1_client_api.rs",
+            ),
+        ] {
+            println!("cargo:rerun-if-changed={}", protocol);
+            wayland_scanner::generate_code(protocol, dest.join(api), wayland_scanner::Side::Client);
+        }
+    }
+
     if target.contains("apple") {
         println!("cargo:rustc-link-lib=framework=Carbon");
     }
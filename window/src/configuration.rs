@@ -1,5 +1,20 @@
 use std::sync::{Arc, Mutex};
 
+/// Describes a window that is attached to the top of the screen,
+/// and that can slide in and out of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropDownSettings {
+    /// The height of the window, as a percentage of the height
+    /// of the screen
+    pub height_percent: u8,
+    pub margin_top: u32,
+    pub margin_left: u32,
+    pub margin_right: u32,
+    /// If true, the window takes the keyboard focus while it is
+    /// shown, otherwise it is focused like any other window
+    pub exclusive_keyboard: bool,
+}
+
 pub trait WindowConfiguration {
     fn use_ime(&self) -> bool {
         false
@@ -23,10 +38,41 @@ pub trait WindowConfiguration {
         true
     }
 
+    /// If true, and Wayland is enabled but either the connection
+    /// or EGL cannot be initialized, use X11 instead
+    fn wayland_fallback_to_x11(&self) -> bool {
+        false
+    }
+
     fn prefer_egl(&self) -> bool {
         true
     }
 
+    /// Selects the EGL device to render with on systems that have
+    /// more than one GPU; see `egl::EglDevice::matches`
+    fn prefer_egl_device(&self) -> Option<String> {
+        None
+    }
+
+    /// If false, windows are created without a title bar and borders.
+    /// This is currently only respected on X11 and Wayland.
+    fn window_decorations(&self) -> bool {
+        true
+    }
+
+    /// If Some, the first window is shown as a drop-down.
+    /// This is currently only respected on Wayland, and requires
+    /// the compositor to support wlr-layer-shell.
+    fn drop_down(&self) -> Option<DropDownSettings> {
+        None
+    }
+
+    /// Returns the DPI to use for the named screen, overriding the
+    /// DPI that the system suggests for it
+    fn dpi_by_screen(&self, _screen: &str) -> Option<f64> {
+        None
+    }
+
     fn native_macos_fullscreen_mode(&self) -> bool {
         false
     }
@@ -40,6 +86,20 @@ pub trait WindowConfiguration {
     fn window_background_opacity(&self) -> f32 {
         1.0
     }
+
+    /// The opacity of the whole window, which is applied by the
+    /// compositor rather than by the application.
+    /// This is currently only respected on Wayland, and requires the
+    /// compositor to support alpha-modifier-v1.
+    fn window_opacity(&self) -> f32 {
+        1.0
+    }
+
+    /// If true, ask the compositor to blur whatever is behind
+    /// the window.  This is currently only respected on Wayland.
+    fn wayland_background_blur(&self) -> bool {
+        false
+    }
 }
 
 lazy_static::lazy_static! {
@@ -1,4 +1,4 @@
-use crate::Connection;
+use crate::{Connection, ScreenRect};
 use anyhow::Result as Fallible;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -11,6 +11,18 @@ pub fn shutdown() {
     CONN.with(|m| drop(m.borrow_mut().take()));
 }
 
+/// Describes a monitor that is attached to the system
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenInfo {
+    /// The name of the monitor, eg: `DP-1`
+    pub name: String,
+    /// The position and size of the monitor, in the coordinate
+    /// space that the system uses to arrange its monitors
+    pub rect: ScreenRect,
+    /// The factor by which the system scales windows on this monitor
+    pub scale: f64,
+}
+
 pub trait ConnectionOps {
     fn get() -> Option<Rc<Connection>> {
         let mut res = None;
@@ -37,6 +49,18 @@ pub trait ConnectionOps {
     /// focus away from it.
     fn hide_application(&self) {}
 
+    /// Returns the monitors that are attached to the system
+    fn screens(&self) -> Fallible<Vec<ScreenInfo>> {
+        anyhow::bail!("enumerating screens is not supported on this system");
+    }
+
+    /// Returns false if the windowing system doesn't tell us where our
+    /// windows are on the screen, in which case the `screen_coords` of
+    /// mouse events can't be compared between windows
+    fn has_screen_coordinates(&self) -> bool {
+        true
+    }
+
     // TODO: return a handle that can be used to cancel the timer
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F);
 }
@@ -1,6 +1,8 @@
 use crate::{is_swrast_preferred, prefer_swrast};
 use anyhow::{anyhow, bail, ensure, Error};
-use std::ffi::c_void;
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 #[allow(non_camel_case_types, clippy::unreadable_literal)]
@@ -40,11 +42,115 @@ pub mod ffi {
     pub type EGLNativeWindowType = *const raw::c_void;
 }
 
+/// `EGL_RENDERER_EXT` from `EGL_EXT_device_query_name`, which is
+/// newer than the registry that the bindings are generated from
+const RENDERER_EXT: ffi::EGLint = 0x335F;
+
 struct EglWrapper {
     _lib: libloading::Library,
     egl: ffi::Egl,
 }
 
+/// An EGL device, as reported by `EGL_EXT_device_enumeration`.
+/// On systems with hybrid graphics there is one for each GPU.
+#[derive(Debug, Clone)]
+pub struct EglDevice {
+    handle: ffi::types::EGLDeviceEXT,
+    /// eg: `/dev/dri/card1`
+    pub drm_device: Option<String>,
+    pub vendor: Option<String>,
+    pub renderer: Option<String>,
+    /// eg: `0000:01:00.0`
+    pub pci_address: Option<String>,
+}
+
+/// Returns the sysfs directory describing the device that backs
+/// a DRM device node such as `/dev/dri/renderD128`
+fn sysfs_device_dir(drm_device: &str) -> Option<PathBuf> {
+    let name = Path::new(drm_device).file_name()?;
+    Some(Path::new("/sys/class/drm").join(name).join("device"))
+}
+
+fn pci_address(drm_device: &str) -> Option<String> {
+    let target = std::fs::read_link(sysfs_device_dir(drm_device)?).ok()?;
+    Some(target.file_name()?.to_str()?.to_string())
+}
+
+fn pci_vendor_name(drm_device: &str) -> Option<String> {
+    let vendor = std::fs::read_to_string(sysfs_device_dir(drm_device)?.join("vendor")).ok()?;
+    let name = match vendor.trim() {
+        "0x8086" => "Intel",
+        "0x10de" => "NVIDIA",
+        "0x1002" => "AMD",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+impl EglDevice {
+    /// GPUs that are part of the CPU or chipset sit on the root PCI
+    /// bus, while discrete GPUs are behind a PCIe bridge on another bus.
+    /// Returns None if the PCI address of the device is not known.
+    pub fn is_integrated(&self) -> Option<bool> {
+        // domain:bus:device.function
+        let bus = self.pci_address.as_ref()?.split(':').nth(1)?;
+        Some(bus == "00")
+    }
+
+    /// Returns true if the device satisfies the `prefer_egl_device`
+    /// configuration, which may be "integrated", "discrete", the
+    /// path of a DRM device node, a PCI address, or a case insensitive
+    /// substring of the vendor or renderer name.
+    pub fn matches(&self, preference: &str) -> bool {
+        let preference = preference.trim();
+        if preference.eq_ignore_ascii_case("integrated") {
+            return self.is_integrated() == Some(true);
+        }
+        if preference.eq_ignore_ascii_case("discrete") {
+            return self.is_integrated() == Some(false);
+        }
+        if preference.starts_with('/') {
+            // The card and render nodes of a GPU are different paths,
+            // so compare the devices that back them
+            return self.drm_device.as_deref() == Some(preference)
+                || match (pci_address(preference), &self.pci_address) {
+                    (Some(a), Some(b)) => a == *b,
+                    _ => false,
+                };
+        }
+        if let Some(address) = &self.pci_address {
+            if address.eq_ignore_ascii_case(preference) {
+                return true;
+            }
+        }
+        let preference = preference.to_lowercase();
+        [&self.vendor, &self.renderer]
+            .iter()
+            .filter_map(|s| s.as_ref())
+            .any(|s| s.to_lowercase().contains(&preference))
+    }
+}
+
+impl std::fmt::Display for EglDevice {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} {} {}",
+            self.drm_device.as_deref().unwrap_or("?"),
+            self.vendor.as_deref().unwrap_or("unknown vendor"),
+            self.renderer.as_deref().unwrap_or("unknown renderer"),
+        )?;
+        if let Some(address) = &self.pci_address {
+            write!(fmt, " at {}", address)?;
+        }
+        match self.is_integrated() {
+            Some(true) => write!(fmt, " (integrated)"),
+            Some(false) => write!(fmt, " (discrete)"),
+            None => Ok(()),
+        }
+    }
+}
+
 pub struct GlConnection {
     egl: EglWrapper,
     display: ffi::types::EGLDisplay,
@@ -58,6 +164,28 @@ impl std::ops::Deref for GlConnection {
     }
 }
 
+impl GlConnection {
+    /// Loads EGL and initializes it for `display`, without
+    /// creating a surface
+    #[cfg(all(unix, feature = "wayland", not(target_os = "macos")))]
+    pub fn create(
+        platform: Option<ffi::types::EGLenum>,
+        display: Option<ffi::EGLNativeDisplayType>,
+    ) -> anyhow::Result<Rc<Self>> {
+        GlState::with_egl_lib(|egl| {
+            let egl_display = egl.get_display(platform, display)?;
+
+            let (major, minor) = egl.initialize_and_get_version(egl_display)?;
+            log::trace!("initialized EGL version {}.{}", major, minor);
+
+            Ok(Rc::new(Self {
+                display: egl_display,
+                egl,
+            }))
+        })
+    }
+}
+
 impl Drop for GlConnection {
     fn drop(&mut self) {
         unsafe {
@@ -70,6 +198,11 @@ pub struct GlState {
     connection: Rc<GlConnection>,
     surface: ffi::types::EGLSurface,
     context: ffi::types::EGLContext,
+    /// True if the display supports EGL_KHR_swap_buffers_with_damage
+    swap_with_damage: bool,
+    /// The rectangles to pass with the next swap, as x, y, width and
+    /// height, with y measured from the bottom of the surface
+    damage: RefCell<Vec<ffi::EGLint>>,
 }
 
 impl Drop for GlState {
@@ -107,10 +240,189 @@ impl EglWrapper {
         Ok(Self { _lib: lib, egl })
     }
 
+    fn has_client_extension(&self, name: &str) -> bool {
+        self.has_display_extension(ffi::NO_DISPLAY, name)
+    }
+
+    fn has_display_extension(&self, display: ffi::types::EGLDisplay, name: &str) -> bool {
+        let extensions = unsafe {
+            self.egl
+                .QueryString(display, ffi::EXTENSIONS as ffi::EGLint)
+        };
+        if extensions.is_null() {
+            return false;
+        }
+        unsafe { CStr::from_ptr(extensions) }
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|ext| ext == name)
+    }
+
+    fn query_device_string(
+        &self,
+        device: ffi::types::EGLDeviceEXT,
+        name: ffi::EGLint,
+    ) -> Option<String> {
+        let value = unsafe { self.egl.QueryDeviceStringEXT(device, name) };
+        if value.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(value) }
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        }
+    }
+
+    /// Returns the devices known to the EGL implementation, or an
+    /// empty list if it doesn't support `EGL_EXT_device_enumeration`
+    pub fn enumerate_devices(&self) -> Vec<EglDevice> {
+        if !self.egl.QueryDevicesEXT.is_loaded() || !self.egl.QueryDeviceStringEXT.is_loaded() {
+            return vec![];
+        }
+
+        let mut num_devices = 0;
+        if unsafe {
+            self.egl
+                .QueryDevicesEXT(0, std::ptr::null_mut(), &mut num_devices)
+        } == 0
+            || num_devices <= 0
+        {
+            return vec![];
+        }
+        let mut handles = Vec::with_capacity(num_devices as usize);
+        unsafe {
+            if self
+                .egl
+                .QueryDevicesEXT(num_devices, handles.as_mut_ptr(), &mut num_devices)
+                == 0
+            {
+                return vec![];
+            }
+            handles.set_len(num_devices as usize);
+        }
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                let extensions = self
+                    .query_device_string(handle, ffi::EXTENSIONS as ffi::EGLint)
+                    .unwrap_or_default();
+                let has_extension = |name: &str| extensions.split_whitespace().any(|e| e == name);
+
+                let drm_device = if has_extension("EGL_EXT_device_drm") {
+                    self.query_device_string(handle, ffi::DRM_DEVICE_FILE_EXT as ffi::EGLint)
+                } else {
+                    None
+                };
+                let (vendor, renderer) = if has_extension("EGL_EXT_device_query_name") {
+                    (
+                        self.query_device_string(handle, ffi::VENDOR as ffi::EGLint),
+                        self.query_device_string(handle, RENDERER_EXT),
+                    )
+                } else {
+                    (None, None)
+                };
+                let vendor = vendor.or_else(|| drm_device.as_deref().and_then(pci_vendor_name));
+                let pci_address = drm_device.as_deref().and_then(pci_address);
+
+                EglDevice {
+                    handle,
+                    drm_device,
+                    vendor,
+                    renderer,
+                    pci_address,
+                }
+            })
+            .collect()
+    }
+
+    /// Applies the `prefer_egl_device` configuration.
+    /// Returns a display for the preferred device if it could be
+    /// selected explicitly.  Otherwise, the environment may have been
+    /// adjusted so that the default display uses that device.
+    fn get_display_for_device(
+        &self,
+        platform: Option<ffi::types::EGLenum>,
+        display: Option<ffi::EGLNativeDisplayType>,
+        preference: &str,
+    ) -> Option<ffi::types::EGLDisplay> {
+        let devices = self.enumerate_devices();
+        if devices.is_empty() {
+            log::warn!(
+                "prefer_egl_device is set, but this EGL implementation \
+                 is unable to enumerate its devices"
+            );
+            return None;
+        }
+        for (idx, device) in devices.iter().enumerate() {
+            log::info!("EGL device {}: {}", idx, device);
+        }
+
+        let device = match devices.iter().find(|device| device.matches(preference)) {
+            Some(device) => device,
+            None => {
+                log::warn!(
+                    "prefer_egl_device = {:?} doesn't match any EGL device; \
+                     using the default device",
+                    preference
+                );
+                return None;
+            }
+        };
+        log::info!(
+            "prefer_egl_device = {:?} selected EGL device {}",
+            preference,
+            device
+        );
+
+        if let Some(platform) = platform {
+            if self.has_client_extension("EGL_EXT_explicit_device")
+                && self.egl.GetPlatformDisplay.is_loaded()
+            {
+                let attributes = [
+                    ffi::DEVICE_EXT as ffi::types::EGLAttrib,
+                    device.handle as ffi::types::EGLAttrib,
+                    ffi::NONE as ffi::types::EGLAttrib,
+                ];
+                let egl_display = unsafe {
+                    self.egl.GetPlatformDisplay(
+                        platform,
+                        display.unwrap_or(ffi::DEFAULT_DISPLAY) as *mut _,
+                        attributes.as_ptr(),
+                    )
+                };
+                if !egl_display.is_null() {
+                    return Some(egl_display);
+                }
+                log::warn!("{:#}", self.error("egl GetPlatformDisplay"));
+            }
+        }
+
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            // Without EGL_EXT_explicit_device, Mesa can still be steered
+            // to a particular GPU when it opens the display
+            if let Some(address) = &device.pci_address {
+                let tag = format!("pci-{}", address.replace(':', "_").replace('.', "_"));
+                log::info!("setting DRI_PRIME={} to select that device", tag);
+                std::env::set_var("DRI_PRIME", tag);
+            }
+        }
+        None
+    }
+
     fn get_display(
         &self,
+        platform: Option<ffi::types::EGLenum>,
         display: Option<ffi::EGLNativeDisplayType>,
     ) -> anyhow::Result<ffi::types::EGLDisplay> {
+        if let Some(preference) = crate::configuration::config().prefer_egl_device() {
+            if let Some(egl_display) = self.get_display_for_device(platform, display, &preference) {
+                return Ok(egl_display);
+            }
+        }
+
         let display = unsafe { self.egl.GetDisplay(display.unwrap_or(ffi::DEFAULT_DISPLAY)) };
         if display.is_null() {
             Err(self.error("egl GetDisplay"))
@@ -374,9 +686,9 @@ impl GlState {
         &self.connection
     }
 
-    fn with_egl_lib<F: FnMut(EglWrapper) -> anyhow::Result<Self>>(
+    fn with_egl_lib<T, F: FnMut(EglWrapper) -> anyhow::Result<T>>(
         mut func: F,
-    ) -> anyhow::Result<Self> {
+    ) -> anyhow::Result<T> {
         let mut paths: Vec<std::path::PathBuf> = vec![
             #[cfg(target_os = "windows")]
             "libEGL.dll".into(),
@@ -455,15 +767,19 @@ impl GlState {
         display: Option<ffi::EGLNativeDisplayType>,
         wegl_surface: &wayland_egl::WlEglSurface,
     ) -> anyhow::Result<Self> {
-        Self::create(display, wegl_surface.ptr())
+        Self::create(Some(ffi::PLATFORM_WAYLAND_KHR), display, wegl_surface.ptr())
     }
 
+    /// Creates a new connection and a surface for `window`.
+    /// `platform` identifies the kind of `display` and is used
+    /// to select a device when `prefer_egl_device` is configured.
     pub fn create(
+        platform: Option<ffi::types::EGLenum>,
         display: Option<ffi::EGLNativeDisplayType>,
         window: ffi::EGLNativeWindowType,
     ) -> anyhow::Result<Self> {
         Self::with_egl_lib(|egl| {
-            let egl_display = egl.get_display(display)?;
+            let egl_display = egl.get_display(platform, display)?;
 
             let (major, minor) = egl.initialize_and_get_version(egl_display)?;
             log::trace!("initialized EGL version {}.{}", major, minor);
@@ -576,10 +892,15 @@ impl GlState {
 
             log::trace!("Successfully created a surface using this configuration");
             connection.egl.log_config_info(connection.display, config);
+            let swap_with_damage = connection
+                .egl
+                .has_display_extension(connection.display, "EGL_KHR_swap_buffers_with_damage");
             return Ok(Self {
                 connection: Rc::clone(connection),
                 context,
                 surface,
+                swap_with_damage,
+                damage: RefCell::new(vec![]),
             });
         }
 
@@ -587,11 +908,42 @@ impl GlState {
     }
 }
 
+impl GlState {
+    /// Limits the next swap to the parts of the surface that changed, so
+    /// that the compositor needn't recomposite the rest.  The whole frame
+    /// must still be painted.  The rectangles are in pixels, measured from
+    /// the top left; an empty slice means that the whole surface changed.
+    pub fn set_damage(&self, rects: &[crate::Rect]) {
+        let mut damage = self.damage.borrow_mut();
+        damage.clear();
+        if !self.swap_with_damage {
+            return;
+        }
+        let (_, height) = glium::backend::Backend::get_framebuffer_dimensions(self);
+        for rect in rects {
+            damage.push(rect.origin.x as ffi::EGLint);
+            damage.push(height as ffi::EGLint - rect.max_y() as ffi::EGLint);
+            damage.push(rect.size.width as ffi::EGLint);
+            damage.push(rect.size.height as ffi::EGLint);
+        }
+    }
+}
+
 unsafe impl glium::backend::Backend for GlState {
     fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        let damage = std::mem::take(&mut *self.damage.borrow_mut());
         let res = unsafe {
-            self.connection
-                .SwapBuffers(self.connection.display, self.surface)
+            if damage.is_empty() {
+                self.connection
+                    .SwapBuffers(self.connection.display, self.surface)
+            } else {
+                self.connection.SwapBuffersWithDamageKHR(
+                    self.connection.display,
+                    self.surface,
+                    damage.as_ptr() as _,
+                    (damage.len() / 4) as ffi::EGLint,
+                )
+            }
         };
         if res != 1 {
             Err(match unsafe { self.connection.GetError() } as u32 {
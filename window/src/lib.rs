@@ -6,6 +6,8 @@ pub mod color;
 pub mod configuration;
 pub mod connection;
 pub mod os;
+#[cfg(any(windows, target_os = "macos", feature = "wayland"))]
+mod primary_selection;
 mod spawn;
 mod timerlist;
 
@@ -69,6 +71,31 @@ pub struct Dimensions {
 
 pub type Rect = euclid::Rect<isize, PixelUnit>;
 pub type Size = euclid::Size2D<isize, PixelUnit>;
+pub type ScreenRect = euclid::Rect<isize, ScreenPixelUnit>;
+
+/// Describes the steps by which the window should grow and shrink
+/// when the user resizes it, so that it holds a whole number of cells.
+/// The sizes are in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeIncrement {
+    pub x: u16,
+    pub y: u16,
+    /// The size of the window when it holds no cells at all,
+    /// such as the space taken by padding and the tab bar
+    pub base_width: u16,
+    pub base_height: u16,
+}
+
+/// Describes when a painted frame was actually shown on the screen,
+/// as reported by the windowing system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePresentation {
+    /// The time between starting to paint the frame and it being shown
+    pub latency: std::time::Duration,
+    /// The refresh interval of the output that showed the frame,
+    /// if it is known
+    pub refresh: Option<std::time::Duration>,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MouseCursor {
@@ -104,6 +131,23 @@ pub trait WindowCallbacks: Any {
         frame.clear_color_srgb(0.25, 0.125, 0.375, 1.0);
     }
 
+    /// Called after `paint` to find out which parts of the window
+    /// changed in that paint, in pixels.  Returning None means that
+    /// the whole window changed.  Where the windowing system supports
+    /// it, only the changed parts are recomposited.
+    fn damaged_region(&mut self) -> Option<Vec<Rect>> {
+        None
+    }
+
+    /// Called when a frame that was painted has been shown on the
+    /// screen.  This is only called if the windowing system can tell
+    /// us when that happens.
+    fn frame_presented(&mut self, presentation: FramePresentation) {}
+
+    /// Called when a frame that was painted was never shown, such
+    /// as when it was replaced by a newer frame before being shown
+    fn frame_discarded(&mut self) {}
+
     /// Called if the opengl context is lost
     fn opengl_context_lost(&mut self, _window: &dyn WindowOps) -> anyhow::Result<()> {
         Ok(())
@@ -121,6 +165,14 @@ pub trait WindowCallbacks: Any {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
 
+    /// Called when files are dragged from another application
+    /// and dropped onto the window
+    fn dropped_file(&mut self, paths: Vec<std::path::PathBuf>) {}
+
+    /// Called when text is dragged from another application
+    /// and dropped onto the window
+    fn dropped_string(&mut self, text: String) {}
+
     /// Called when the window is created and allows the embedding
     /// app to reference the window and operate upon it.
     fn created(
@@ -146,6 +198,19 @@ pub trait WindowOps {
     /// Hide a visible window
     fn hide(&self) -> Future<()>;
 
+    /// Ask the windowing system to raise the window and give it
+    /// the focus.  The windowing system may decline, in which case
+    /// it may indicate that the window wants attention instead.
+    fn focus(&self) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Ask the windowing system to draw the attention of the
+    /// user to the window, eg: by highlighting it in the task bar
+    fn request_attention(&self) -> Future<()> {
+        Future::ok(())
+    }
+
     /// Schedule the window to be closed
     fn close(&self) -> Future<()>;
 
@@ -205,6 +270,45 @@ pub trait WindowOps {
         Future::ok(())
     }
 
+    /// Set the steps by which an interactive resize changes
+    /// the size of the window
+    fn set_resize_increments(&self, _incr: ResizeIncrement) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Ask the windowing system to maximize the window
+    fn maximize(&self) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Restore a maximized window to its prior size
+    fn restore(&self) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Slide a drop-down window into or out of view.
+    /// This has no effect on other windows.
+    fn toggle_drop_down(&self) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// While inhibited, the keys that the windowing system would
+    /// otherwise use for its own shortcuts, such as Alt+Tab, are
+    /// delivered to the window instead
+    fn set_keyboard_shortcuts_inhibited(&self, _inhibit: bool) -> Future<()> {
+        Future::ok(())
+    }
+
+    fn toggle_keyboard_shortcuts_inhibit(&self) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// While inhibited, the system won't blank the screen, lock the
+    /// session or go to sleep because the user is idle
+    fn set_idle_inhibited(&self, _inhibit: bool) -> Future<()> {
+        Future::ok(())
+    }
+
     fn config_did_change(&self) -> Future<()> {
         Future::ok(())
     }
@@ -217,6 +321,13 @@ pub trait WindowOpsMut {
     /// Hide a visible window
     fn hide(&mut self);
 
+    /// Ask the windowing system to raise the window and give it the focus
+    fn focus(&mut self) {}
+
+    /// Ask the windowing system to draw the attention of the
+    /// user to the window
+    fn request_attention(&mut self) {}
+
     /// Schedule the window to be closed
     fn close(&mut self);
 
@@ -250,6 +361,29 @@ pub trait WindowOpsMut {
 
     fn toggle_fullscreen(&mut self) {}
 
+    /// Set the steps by which an interactive resize changes
+    /// the size of the window
+    fn set_resize_increments(&mut self, _incr: ResizeIncrement) {}
+
+    /// Ask the windowing system to maximize the window
+    fn maximize(&mut self) {}
+
+    /// Restore a maximized window to its prior size
+    fn restore(&mut self) {}
+
+    fn toggle_drop_down(&mut self) {}
+
+    /// While inhibited, the keys that the windowing system would
+    /// otherwise use for its own shortcuts, such as Alt+Tab, are
+    /// delivered to the window instead
+    fn set_keyboard_shortcuts_inhibited(&mut self, _inhibit: bool) {}
+
+    fn toggle_keyboard_shortcuts_inhibit(&mut self) {}
+
+    /// While inhibited, the system won't blank the screen, lock the
+    /// session or go to sleep because the user is idle
+    fn set_idle_inhibited(&mut self, _inhibit: bool) {}
+
     fn config_did_change(&mut self) {}
 }
 
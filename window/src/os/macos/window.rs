@@ -317,6 +317,14 @@ pub(crate) struct WindowInner {
     window_id: usize,
     view: StrongPtr,
     window: StrongPtr,
+    /// The IOPMAssertionID that is held while idle is inhibited
+    idle_assertion: Option<u32>,
+}
+
+impl Drop for WindowInner {
+    fn drop(&mut self) {
+        self.set_idle_inhibited(false);
+    }
 }
 
 fn function_key_to_keycode(function_key: char) -> KeyCode {
@@ -445,6 +453,7 @@ impl Window {
                 window_id,
                 window,
                 view,
+                idle_assertion: None,
             }));
             conn.windows
                 .borrow_mut()
@@ -533,6 +542,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_idle_inhibited(&self, inhibit: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_idle_inhibited(inhibit);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + FnMut(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         mut func: F,
@@ -552,8 +568,13 @@ impl WindowOps for Window {
         })
     }
 
-    fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         use clipboard::ClipboardProvider;
+        if clipboard == Clipboard::PrimarySelection {
+            if let Some(text) = crate::primary_selection::get() {
+                return Future::ok(text);
+            }
+        }
         Future::result(
             clipboard::ClipboardContext::new()
                 .and_then(|mut ctx| ctx.get_contents())
@@ -561,8 +582,12 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, _clipboard: Clipboard, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         use clipboard::ClipboardProvider;
+        if clipboard == Clipboard::PrimarySelection {
+            crate::primary_selection::set(text);
+            return Future::ok(());
+        }
         Future::result(
             clipboard::ClipboardContext::new()
                 .and_then(|mut ctx| ctx.set_contents(text))
@@ -813,6 +838,37 @@ impl WindowOpsMut for WindowInner {
         }
     }
 
+    fn set_idle_inhibited(&mut self, inhibit: bool) {
+        if !inhibit {
+            if let Some(assertion) = self.idle_assertion.take() {
+                unsafe {
+                    IOPMAssertionRelease(assertion);
+                }
+            }
+            return;
+        }
+        if self.idle_assertion.is_some() {
+            return;
+        }
+
+        let assertion_type: CFString = FromStr::from_str("PreventUserIdleDisplaySleep").unwrap();
+        let reason: CFString = FromStr::from_str("wezterm is running a process").unwrap();
+        let mut assertion = 0;
+        let result = unsafe {
+            IOPMAssertionCreateWithName(
+                assertion_type.as_concrete_TypeRef(),
+                kIOPMAssertionLevelOn,
+                reason.as_concrete_TypeRef(),
+                &mut assertion,
+            )
+        };
+        if result == 0 {
+            self.idle_assertion.replace(assertion);
+        } else {
+            log::warn!("IOPMAssertionCreateWithName failed: {:#x}", result);
+        }
+    }
+
     fn toggle_fullscreen(&mut self) {
         let native_fullscreen = config().native_macos_fullscreen_mode();
 
@@ -883,6 +939,20 @@ const kUCKeyActionUp: u16 = 1;
 /// auto-key down
 #[allow(non_upper_case_globals, dead_code)]
 const kUCKeyActionAutoKey: u16 = 2;
+#[allow(non_upper_case_globals)]
+const kIOPMAssertionLevelOn: u32 = 255;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOPMAssertionCreateWithName(
+        assertion_type: CFStringRef,
+        level: u32,
+        name: CFStringRef,
+        assertion_id: *mut u32,
+    ) -> i32;
+    fn IOPMAssertionRelease(assertion_id: u32) -> i32;
+}
+
 /// get information for key display (as in Key Caps)
 #[allow(non_upper_case_globals)]
 const kUCKeyActionDisplay: u16 = 3;
@@ -1,25 +1,54 @@
 #![allow(dead_code)]
 use super::keyboard::KeyboardDispatcher;
+use super::output::OutputTracker;
 use super::pointer::*;
+use super::protocols::alpha_modifier::wp_alpha_modifier_v1::WpAlphaModifierV1;
+use super::protocols::background_effect::ext_background_effect_manager_v1::{
+    Capability, Event as BackgroundEffectEvent, ExtBackgroundEffectManagerV1,
+};
+use super::protocols::fractional_scale::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use super::protocols::kde_blur::org_kde_kwin_blur_manager::OrgKdeKwinBlurManager;
+use super::protocols::layer_shell::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+use super::protocols::xdg_activation::xdg_activation_v1::XdgActivationV1;
+use super::touch::TouchDispatcher;
 use super::window::*;
-use crate::connection::ConnectionOps;
+use crate::connection::{ConnectionOps, ScreenInfo};
 use crate::spawn::*;
 use crate::timerlist::{TimerEntry, TimerList};
-use crate::Connection;
+use crate::{Connection, WindowOpsMut};
 use anyhow::{anyhow, bail, Context};
 use smithay_client_toolkit as toolkit;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use toolkit::environment::Environment;
+use toolkit::output::{with_output_info, OutputStatusListener};
 use toolkit::reexports::calloop::{EventLoop, EventSource, Interest, Mode, Poll, Readiness, Token};
-use toolkit::reexports::client::Display;
+use toolkit::reexports::client::protocol::wl_seat::WlSeat;
+use toolkit::reexports::client::{Attached, Display, Main};
+use toolkit::reexports::protocols::presentation_time::client::wp_presentation::{
+    Event as PresentationEvent, WpPresentation,
+};
+use toolkit::reexports::protocols::unstable::idle_inhibit::v1::client::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1;
+use toolkit::reexports::protocols::unstable::keyboard_shortcuts_inhibit::v1::client::zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1;
+use toolkit::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1;
+use toolkit::reexports::protocols::unstable::xdg_output::v1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1;
+use toolkit::reexports::protocols::viewporter::client::wp_viewporter::WpViewporter;
 use toolkit::WaylandSource;
 
 toolkit::default_environment!(MyEnvironment, desktop);
 
+/// Set when SIGUSR1 is received, to slide the drop-down
+/// window into or out of view
+static TOGGLE_DROP_DOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_drop_down_toggle(_signal: libc::c_int) {
+    TOGGLE_DROP_DOWN.store(true, Ordering::Relaxed);
+}
+
 pub struct WaylandConnection {
     should_terminate: RefCell<bool>,
     timers: RefCell<TimerList>,
@@ -37,6 +66,42 @@ pub struct WaylandConnection {
     pub(crate) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
     pub(crate) pointer: PointerDispatcher,
     pub(crate) keyboard: KeyboardDispatcher,
+    pub(crate) touch: TouchDispatcher,
+    pub(crate) outputs: Rc<RefCell<OutputTracker>>,
+    output_listener: OutputStatusListener,
+    /// None if the compositor doesn't support the primary selection protocol
+    pub(crate) primary_selection_manager: Option<Main<ZwpPrimarySelectionDeviceManagerV1>>,
+    /// Both of these are required to render at a fractional scale;
+    /// without them we use the integer buffer scale
+    pub(crate) fractional_scale_manager: Option<Main<WpFractionalScaleManagerV1>>,
+    pub(crate) viewporter: Option<Main<WpViewporter>>,
+    /// None if the compositor doesn't support xdg-activation,
+    /// in which case we cannot focus or highlight our windows
+    pub(crate) xdg_activation: Option<Main<XdgActivationV1>>,
+    /// The seat that input events, and their serials, come from
+    pub(crate) seat: Option<Attached<WlSeat>>,
+    /// None if the compositor doesn't support wlr-layer-shell,
+    /// which is required to show a drop-down window
+    pub(crate) layer_shell: Option<Main<ZwlrLayerShellV1>>,
+    /// None if the compositor doesn't support alpha-modifier,
+    /// in which case window_opacity cannot be applied
+    pub(crate) alpha_modifier: Option<Main<WpAlphaModifierV1>>,
+    /// Either of these can blur the background of our windows;
+    /// ext-background-effect is preferred when the compositor
+    /// says that it can blur
+    pub(crate) background_effect: Option<Main<ExtBackgroundEffectManagerV1>>,
+    pub(crate) background_effect_can_blur: Arc<AtomicBool>,
+    pub(crate) kde_blur_manager: Option<Main<OrgKdeKwinBlurManager>>,
+    /// None if the compositor doesn't allow its shortcuts to be inhibited
+    pub(crate) shortcuts_inhibit_manager: Option<Main<ZwpKeyboardShortcutsInhibitManagerV1>>,
+    /// None if the compositor doesn't support idle-inhibit
+    pub(crate) idle_inhibit_manager: Option<Main<ZwpIdleInhibitManagerV1>>,
+    /// None if the compositor doesn't report when frames are presented
+    pub(crate) presentation: Option<Main<WpPresentation>>,
+    /// The clock that presentation times are reported in
+    pub(crate) presentation_clock: Arc<AtomicU32>,
+    /// Only the first window is shown as a drop-down
+    pub(crate) drop_down_created: Cell<bool>,
     pub(crate) environment: RefCell<Environment<MyEnvironment>>,
     event_q: RefCell<EventLoop<()>>,
     pub(crate) display: RefCell<Display>,
@@ -49,15 +114,144 @@ impl WaylandConnection {
         let event_loop = toolkit::reexports::calloop::EventLoop::<()>::new()?;
 
         let keyboard = KeyboardDispatcher::new();
+        let mut touch = TouchDispatcher::new();
         let mut pointer = None;
 
-        for seat in environment.get_all_seats() {
-            if let Some((has_kbd, has_ptr)) = toolkit::seat::with_seat_data(&seat, |seat_data| {
-                (
-                    seat_data.has_keyboard && !seat_data.defunct,
-                    seat_data.has_pointer && !seat_data.defunct,
+        let primary_selection_manager = environment
+            .manager
+            .instantiate_range::<ZwpPrimarySelectionDeviceManagerV1>(1, 1)
+            .map_err(|err| {
+                log::debug!(
+                    "primary selection protocol is not available, \
+                     so it will be emulated: {:?}",
+                    err
                 )
-            }) {
+            })
+            .ok();
+
+        let fractional_scale_manager = environment
+            .manager
+            .instantiate_range::<WpFractionalScaleManagerV1>(1, 1)
+            .ok();
+        let viewporter = environment
+            .manager
+            .instantiate_range::<WpViewporter>(1, 1)
+            .ok();
+        log::debug!(
+            "fractional scaling is {}available",
+            if fractional_scale_manager.is_some() && viewporter.is_some() {
+                ""
+            } else {
+                "not "
+            }
+        );
+
+        let xdg_activation = environment
+            .manager
+            .instantiate_range::<XdgActivationV1>(1, 1)
+            .map_err(|err| log::debug!("xdg-activation is not available: {:?}", err))
+            .ok();
+
+        let layer_shell = environment
+            .manager
+            .instantiate_range::<ZwlrLayerShellV1>(1, 4)
+            .map_err(|err| log::debug!("wlr-layer-shell is not available: {:?}", err))
+            .ok();
+
+        let alpha_modifier = environment
+            .manager
+            .instantiate_range::<WpAlphaModifierV1>(1, 1)
+            .map_err(|err| log::debug!("alpha-modifier is not available: {:?}", err))
+            .ok();
+
+        let background_effect_can_blur = Arc::new(AtomicBool::new(false));
+        let background_effect = environment
+            .manager
+            .instantiate_range::<ExtBackgroundEffectManagerV1>(1, 1)
+            .map_err(|err| log::debug!("ext-background-effect is not available: {:?}", err))
+            .ok();
+        if let Some(manager) = background_effect.as_ref() {
+            manager.quick_assign({
+                let can_blur = Arc::clone(&background_effect_can_blur);
+                move |_manager, event, _dispatch_data| {
+                    if let BackgroundEffectEvent::Capabilities { flags } = event {
+                        can_blur.store(flags.contains(Capability::Blur), Ordering::Relaxed);
+                        // This usually arrives after the first window
+                        // has been created, so revisit the windows
+                        if let Some(conn) = Connection::get() {
+                            for window in conn.wayland().windows.borrow().values() {
+                                window.borrow_mut().apply_background_blur();
+                            }
+                        }
+                    }
+                }
+            });
+        }
+        let kde_blur_manager = environment
+            .manager
+            .instantiate_range::<OrgKdeKwinBlurManager>(1, 1)
+            .map_err(|err| log::debug!("KDE blur is not available: {:?}", err))
+            .ok();
+
+        let shortcuts_inhibit_manager = environment
+            .manager
+            .instantiate_range::<ZwpKeyboardShortcutsInhibitManagerV1>(1, 1)
+            .map_err(|err| log::debug!("keyboard-shortcuts-inhibit is not available: {:?}", err))
+            .ok();
+
+        let idle_inhibit_manager = environment
+            .manager
+            .instantiate_range::<ZwpIdleInhibitManagerV1>(1, 1)
+            .map_err(|err| log::debug!("idle-inhibit is not available: {:?}", err))
+            .ok();
+
+        // The compositor announces its clock when we bind, but all of
+        // the common compositors use CLOCK_MONOTONIC
+        let presentation_clock = Arc::new(AtomicU32::new(libc::CLOCK_MONOTONIC as u32));
+        let presentation = environment
+            .manager
+            .instantiate_range::<WpPresentation>(1, 1)
+            .map_err(|err| log::debug!("presentation-time is not available: {:?}", err))
+            .ok();
+        if let Some(presentation) = presentation.as_ref() {
+            presentation.quick_assign({
+                let presentation_clock = Arc::clone(&presentation_clock);
+                move |_presentation, event, _dispatch_data| {
+                    if let PresentationEvent::ClockId { clk_id } = event {
+                        presentation_clock.store(clk_id, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+
+        let xdg_output_manager = environment
+            .manager
+            .instantiate_range::<ZxdgOutputManagerV1>(1, 3)
+            .map_err(|err| log::debug!("xdg-output is not available: {:?}", err))
+            .ok();
+        let outputs = Rc::new(RefCell::new(OutputTracker::new(xdg_output_manager)));
+        for output in environment.get_all_outputs() {
+            with_output_info(&output, |info| outputs.borrow_mut().update(&output, info));
+        }
+        let output_listener = environment.listen_for_outputs({
+            let outputs = Rc::clone(&outputs);
+            move |output, info, _dispatch_data| outputs.borrow_mut().update(&output, info)
+        });
+
+        let mut input_seat = None;
+        for seat in environment.get_all_seats() {
+            if let Some((has_kbd, has_ptr, has_touch)) =
+                toolkit::seat::with_seat_data(&seat, |seat_data| {
+                    (
+                        seat_data.has_keyboard && !seat_data.defunct,
+                        seat_data.has_pointer && !seat_data.defunct,
+                        seat_data.has_touch && !seat_data.defunct,
+                    )
+                })
+            {
+                if has_kbd || has_ptr {
+                    input_seat.replace(seat.clone());
+                }
                 if has_kbd {
                     keyboard.register(event_loop.handle(), &seat)?;
                 }
@@ -67,8 +261,12 @@ impl WaylandConnection {
                         environment.require_global(),
                         environment.require_global(),
                         environment.require_global(),
+                        primary_selection_manager.as_deref(),
                     )?);
                 }
+                if has_touch {
+                    touch.register(&seat);
+                }
             }
         }
 
@@ -85,11 +283,64 @@ impl WaylandConnection {
             next_window_id: AtomicUsize::new(1),
             windows: RefCell::new(HashMap::new()),
             keyboard,
+            touch,
+            outputs,
+            output_listener,
             pointer: pointer.unwrap(),
+            primary_selection_manager,
+            fractional_scale_manager,
+            viewporter,
+            xdg_activation,
+            seat: input_seat,
+            layer_shell,
+            alpha_modifier,
+            background_effect,
+            background_effect_can_blur,
+            kde_blur_manager,
+            shortcuts_inhibit_manager,
+            idle_inhibit_manager,
+            presentation,
+            presentation_clock,
+            drop_down_created: Cell::new(false),
             gl_connection: RefCell::new(None),
         })
     }
 
+    /// Initializes EGL for this connection ahead of creating any
+    /// windows, so that a problem with it can be detected early.
+    /// The resulting connection is shared by the windows.
+    pub(crate) fn init_egl(&self) -> anyhow::Result<()> {
+        if !wayland_egl::is_available() {
+            bail!("wayland-egl is not available");
+        }
+        let gl_connection = crate::egl::GlConnection::create(
+            Some(crate::egl::ffi::PLATFORM_WAYLAND_KHR),
+            Some(self.display.borrow().get_display_ptr() as *const _),
+        )?;
+        self.gl_connection.borrow_mut().replace(gl_connection);
+        Ok(())
+    }
+
+    /// Returns true if the compositor supports the primary selection
+    /// protocol.  Must be called on the gui thread.
+    pub(crate) fn has_primary_selection() -> bool {
+        Connection::get()
+            .map(|conn| conn.wayland().primary_selection_manager.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Arranges for SIGUSR1 to toggle the drop-down window, so that
+    /// it can be brought back into view by a hotkey that is bound
+    /// in the compositor, even though it doesn't have the focus
+    pub(crate) fn toggle_drop_down_on_signal(&self) {
+        unsafe {
+            libc::signal(
+                libc::SIGUSR1,
+                request_drop_down_toggle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+
     pub(crate) fn next_window_id(&self) -> usize {
         self.next_window_id
             .fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
@@ -170,6 +421,15 @@ impl ConnectionOps for WaylandConnection {
         *self.should_terminate.borrow_mut() = true;
     }
 
+    fn has_screen_coordinates(&self) -> bool {
+        false
+    }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenInfo>> {
+        let outputs = self.environment.borrow().get_all_outputs();
+        Ok(self.outputs.borrow().screens(&outputs))
+    }
+
     fn run_message_loop(&self) -> anyhow::Result<()> {
         self.flush()?;
 
@@ -187,6 +447,12 @@ impl ConnectionOps for WaylandConnection {
         while !*self.should_terminate.borrow() {
             self.timers.borrow_mut().run_ready();
 
+            if TOGGLE_DROP_DOWN.swap(false, Ordering::Relaxed) {
+                for window in self.windows.borrow().values() {
+                    window.borrow_mut().toggle_drop_down();
+                }
+            }
+
             // Check the spawn queue before we try to sleep; there may
             // be work pending and we don't guarantee that there is a
             // 1:1 wakeup to queued function, so we need to be assertive
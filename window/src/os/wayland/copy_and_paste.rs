@@ -2,14 +2,22 @@ use anyhow::{anyhow, Error};
 use filedescriptor::{FileDescriptor, Pipe};
 use smithay_client_toolkit as toolkit;
 use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use toolkit::reexports::client::protocol::wl_data_offer::{Event as DataOfferEvent, WlDataOffer};
+use toolkit::reexports::client::protocol::wl_data_offer::WlDataOffer;
 use toolkit::reexports::client::protocol::wl_data_source::WlDataSource;
+use toolkit::reexports::protocols::unstable::primary_selection::v1::client::{
+    zwp_primary_selection_offer_v1::{
+        Event as PrimarySelectionOfferEvent, ZwpPrimarySelectionOfferV1,
+    },
+    zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+};
 use wayland_client::Attached;
 
 #[derive(Default)]
 pub struct CopyAndPaste {
     data_offer: Option<WlDataOffer>,
+    primary_selection_offer: Option<ZwpPrimarySelectionOfferV1>,
     last_serial: u32,
 }
 
@@ -18,17 +26,74 @@ impl std::fmt::Debug for CopyAndPaste {
         fmt.debug_struct("CopyAndPaste")
             .field("last_serial", &self.last_serial)
             .field("data_offer", &self.data_offer.is_some())
+            .field(
+                "primary_selection_offer",
+                &self.primary_selection_offer.is_some(),
+            )
             .finish()
     }
 }
 
 pub const TEXT_MIME_TYPE: &str = "text/plain;charset=utf-8";
+/// Used for files that are dragged from file managers
+pub const URI_LIST_MIME_TYPE: &str = "text/uri-list";
+
+/// Returns the mime type that we'd like to receive when something
+/// that offers `mime_types` is dropped onto a window
+pub fn choose_drop_mime_type(mime_types: &[String]) -> Option<&'static str> {
+    [URI_LIST_MIME_TYPE, TEXT_MIME_TYPE, "text/plain"]
+        .iter()
+        .find(|candidate| mime_types.iter().any(|m| m == *candidate))
+        .copied()
+}
+
+/// Extracts the local file paths from a text/uri-list, which holds
+/// one percent-encoded URI per line, and may include comments
+pub fn parse_uri_list(list: &str) -> Vec<PathBuf> {
+    fn hex(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|d| d as u8)
+    }
+
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|uri| {
+            // The host is usually empty, but may be the local hostname
+            let path = uri.strip_prefix("file://")?;
+            let path = &path[path.find('/')?..];
+
+            let bytes = path.as_bytes();
+            let mut decoded = Vec::with_capacity(bytes.len());
+            let mut i = 0;
+            while i < bytes.len() {
+                match (bytes[i], bytes.get(i + 1), bytes.get(i + 2)) {
+                    (b'%', Some(&hi), Some(&lo)) if hex(hi).is_some() && hex(lo).is_some() => {
+                        decoded.push(hex(hi).unwrap() << 4 | hex(lo).unwrap());
+                        i += 3;
+                    }
+                    (b, _, _) => {
+                        decoded.push(b);
+                        i += 1;
+                    }
+                }
+            }
+
+            use std::os::unix::ffi::OsStringExt;
+            Some(PathBuf::from(std::ffi::OsString::from_vec(decoded)))
+        })
+        .collect()
+}
 
 impl CopyAndPaste {
     pub fn create() -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Default::default()))
     }
 
+    /// Returns the serial of the most recent input event
+    pub fn last_serial(&self) -> u32 {
+        self.last_serial
+    }
+
     pub fn update_last_serial(&mut self, serial: u32) {
         if serial != 0 {
             self.last_serial = serial;
@@ -45,27 +110,6 @@ impl CopyAndPaste {
         Ok(pipe.read)
     }
 
-    pub fn handle_data_offer(&mut self, event: DataOfferEvent, offer: WlDataOffer) {
-        match event {
-            DataOfferEvent::Offer { mime_type } => {
-                if mime_type == TEXT_MIME_TYPE {
-                    offer.accept(self.last_serial, Some(mime_type));
-                    self.data_offer.replace(offer);
-                } else {
-                    // Refuse other mime types
-                    offer.accept(self.last_serial, None);
-                }
-            }
-            DataOfferEvent::SourceActions { source_actions } => {
-                log::error!("Offer source_actions {}", source_actions);
-            }
-            DataOfferEvent::Action { dnd_action } => {
-                log::error!("Offer dnd_action {}", dnd_action);
-            }
-            _ => {}
-        }
-    }
-
     pub fn confirm_selection(&mut self, offer: WlDataOffer) {
         self.data_offer.replace(offer);
     }
@@ -79,4 +123,45 @@ impl CopyAndPaste {
             .data_device
             .set_selection(Some(&source), self.last_serial);
     }
+
+    pub fn get_primary_selection_data(&mut self) -> anyhow::Result<FileDescriptor> {
+        let offer = self
+            .primary_selection_offer
+            .as_ref()
+            .ok_or_else(|| anyhow!("no primary selection offer"))?;
+        let pipe = Pipe::new().map_err(Error::msg)?;
+        offer.receive(TEXT_MIME_TYPE.to_string(), pipe.write.as_raw_fd());
+        Ok(pipe.read)
+    }
+
+    pub fn handle_primary_selection_offer(
+        &mut self,
+        event: PrimarySelectionOfferEvent,
+        offer: ZwpPrimarySelectionOfferV1,
+    ) {
+        // Unlike data offers, there is nothing to accept or refuse
+        if let PrimarySelectionOfferEvent::Offer { mime_type } = event {
+            if mime_type == TEXT_MIME_TYPE {
+                self.primary_selection_offer.replace(offer);
+            }
+        }
+    }
+
+    pub fn confirm_primary_selection(&mut self, offer: Option<ZwpPrimarySelectionOfferV1>) {
+        // None means that the primary selection was cleared
+        self.primary_selection_offer = offer;
+    }
+
+    pub fn set_primary_selection(&mut self, source: &Attached<ZwpPrimarySelectionSourceV1>) {
+        use crate::connection::ConnectionOps;
+        if let Some(device) = crate::Connection::get()
+            .unwrap()
+            .wayland()
+            .pointer
+            .primary_selection_device
+            .as_ref()
+        {
+            device.set_selection(Some(&source), self.last_serial);
+        }
+    }
 }
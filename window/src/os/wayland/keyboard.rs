@@ -16,6 +16,12 @@ use wezterm_input_types::*;
 struct Inner {
     active_surface_id: u32,
     surface_to_window_id: HashMap<u32, usize>,
+    /// The raw key code of the key that is auto-repeating, and the
+    /// window that it was pressed in.  The repeats are generated by
+    /// the toolkit at the rate and delay advertised by the compositor,
+    /// but we only deliver them while that key is held down and that
+    /// window still has the focus.
+    repeat: Option<(u32, usize)>,
 }
 
 impl Inner {
@@ -26,11 +32,30 @@ impl Inner {
         // that were created by the window decorations and we don't
         // want to suppress keyboard input if the user clicked in
         // the titlebar.
-        if let KbEvent::Enter { surface, .. } = &evt {
-            let id = surface.as_ref().id();
-            if self.surface_to_window_id.contains_key(&id) {
-                self.active_surface_id = id;
+        match &evt {
+            KbEvent::Enter { surface, .. } => {
+                let id = surface.as_ref().id();
+                if self.surface_to_window_id.contains_key(&id) {
+                    self.active_surface_id = id;
+                }
+                self.repeat.take();
             }
+            KbEvent::Leave { .. } => {
+                self.repeat.take();
+            }
+            KbEvent::Key { rawkey, state, .. } => {
+                if *state == KeyState::Pressed {
+                    self.repeat = self.active_window_id().map(|id| (*rawkey, id));
+                } else if self.repeat.map(|(key, _)| key) == Some(*rawkey) {
+                    self.repeat.take();
+                }
+            }
+            KbEvent::Repeat { rawkey, .. } => {
+                if self.repeat != self.active_window_id().map(|id| (*rawkey, id)) {
+                    return;
+                }
+            }
+            KbEvent::Modifiers { .. } => {}
         }
 
         if let Some(event) = KeyboardEvent::from_event(evt) {
@@ -38,6 +63,12 @@ impl Inner {
         }
     }
 
+    fn active_window_id(&self) -> Option<usize> {
+        self.surface_to_window_id
+            .get(&self.active_surface_id)
+            .copied()
+    }
+
     fn dispatch_to_window(&mut self, evt: KeyboardEvent) {
         if let Some(window_id) = self.surface_to_window_id.get(&self.active_surface_id) {
             let mut evt = Some(evt);
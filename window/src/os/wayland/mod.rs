@@ -6,4 +6,7 @@ pub use self::window::*;
 pub use connection::*;
 mod copy_and_paste;
 mod keyboard;
+mod output;
 mod pointer;
+mod protocols;
+mod touch;
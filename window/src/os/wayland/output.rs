@@ -0,0 +1,130 @@
+//! Tracks the outputs (monitors) that are attached to the compositor,
+//! using xdg-output, when it is available, to learn their names
+//! and their positions in the compositor's logical coordinate space.
+use crate::connection::ScreenInfo;
+use crate::ScreenRect;
+use smithay_client_toolkit as toolkit;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use toolkit::output::{with_output_info, OutputInfo};
+use toolkit::reexports::client::protocol::wl_output::WlOutput;
+use toolkit::reexports::client::Main;
+use toolkit::reexports::protocols::unstable::xdg_output::v1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1;
+use toolkit::reexports::protocols::unstable::xdg_output::v1::client::zxdg_output_v1::{
+    Event as XdgOutputEvent, ZxdgOutputV1,
+};
+
+/// What xdg-output has told us about an output
+#[derive(Default, Debug, Clone)]
+struct XdgOutputInfo {
+    name: Option<String>,
+    position: Option<(i32, i32)>,
+    size: Option<(i32, i32)>,
+}
+
+pub struct OutputTracker {
+    manager: Option<Main<ZxdgOutputManagerV1>>,
+    xdg_outputs: HashMap<u32, Main<ZxdgOutputV1>>,
+    info: Arc<Mutex<HashMap<u32, XdgOutputInfo>>>,
+}
+
+impl OutputTracker {
+    pub fn new(manager: Option<Main<ZxdgOutputManagerV1>>) -> Self {
+        Self {
+            manager,
+            xdg_outputs: HashMap::new(),
+            info: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Called as outputs appear and disappear
+    pub fn update(&mut self, output: &WlOutput, info: &OutputInfo) {
+        if info.obsolete {
+            if let Some(xdg_output) = self.xdg_outputs.remove(&info.id) {
+                xdg_output.destroy();
+            }
+            self.info.lock().unwrap().remove(&info.id);
+            return;
+        }
+
+        let manager = match self.manager.as_ref() {
+            Some(manager) => manager,
+            None => return,
+        };
+        if self.xdg_outputs.contains_key(&info.id) {
+            return;
+        }
+
+        let xdg_output = manager.get_xdg_output(output);
+        xdg_output.quick_assign({
+            let xdg_info = Arc::clone(&self.info);
+            let id = info.id;
+            move |_xdg_output, event, _dispatch_data| {
+                let mut xdg_info = xdg_info.lock().unwrap();
+                let entry = xdg_info.entry(id).or_insert_with(XdgOutputInfo::default);
+                match event {
+                    XdgOutputEvent::Name { name } => {
+                        entry.name.replace(name);
+                    }
+                    XdgOutputEvent::LogicalPosition { x, y } => {
+                        entry.position.replace((x, y));
+                    }
+                    XdgOutputEvent::LogicalSize { width, height } => {
+                        entry.size.replace((width, height));
+                    }
+                    _ => {}
+                }
+            }
+        });
+        self.xdg_outputs.insert(info.id, xdg_output);
+    }
+
+    /// Returns the name of the output, preferring the connector name
+    /// that xdg-output reports, as that is what the user is likely to
+    /// see in their compositor configuration
+    pub fn output_name(&self, output: &WlOutput) -> Option<String> {
+        let info = with_output_info(output, Clone::clone)?;
+        Some(self.screen_info(&info).name)
+    }
+
+    pub fn screens(&self, outputs: &[WlOutput]) -> Vec<ScreenInfo> {
+        outputs
+            .iter()
+            .filter_map(|output| with_output_info(output, Clone::clone))
+            .filter(|info| !info.obsolete)
+            .map(|info| self.screen_info(&info))
+            .collect()
+    }
+
+    fn screen_info(&self, info: &OutputInfo) -> ScreenInfo {
+        let xdg = self
+            .info
+            .lock()
+            .unwrap()
+            .get(&info.id)
+            .cloned()
+            .unwrap_or_default();
+
+        let scale = info.scale_factor.max(1);
+        let name = xdg
+            .name
+            .unwrap_or_else(|| format!("{} {}", info.make, info.model));
+        let (x, y) = xdg.position.unwrap_or(info.location);
+        let (width, height) = xdg.size.unwrap_or_else(|| {
+            info.modes
+                .iter()
+                .find(|mode| mode.is_current)
+                .map(|mode| (mode.dimensions.0 / scale, mode.dimensions.1 / scale))
+                .unwrap_or((0, 0))
+        });
+
+        ScreenInfo {
+            name,
+            rect: ScreenRect::new(
+                euclid::point2(x as isize, y as isize),
+                euclid::size2(width as isize, height as isize),
+            ),
+            scale: scale as f64,
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use super::copy_and_paste::*;
+use super::window::read_pipe_with_timeout;
 use crate::os::wayland::connection::WaylandConnection;
+use filedescriptor::Pipe;
 use smithay_client_toolkit as toolkit;
 use std::collections::HashMap;
+use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
 use toolkit::reexports::client::protocol::wl_data_device::{
     Event as DataDeviceEvent, WlDataDevice,
@@ -11,19 +14,38 @@ use toolkit::reexports::client::protocol::wl_pointer::{
     self, Axis, AxisSource, Event as PointerEvent,
 };
 use toolkit::reexports::client::protocol::wl_surface::WlSurface;
+use toolkit::reexports::protocols::unstable::primary_selection::v1::client::{
+    zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+    zwp_primary_selection_device_v1::{
+        Event as PrimarySelectionDeviceEvent, ZwpPrimarySelectionDeviceV1,
+    },
+    zwp_primary_selection_offer_v1::{
+        Event as PrimarySelectionOfferEvent, ZwpPrimarySelectionOfferV1,
+    },
+};
 use toolkit::seat::pointer::{ThemeManager, ThemeSpec, ThemedPointer};
 use wayland_client::protocol::wl_compositor::WlCompositor;
-use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::protocol::wl_data_device_manager::{DndAction, WlDataDeviceManager};
 use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::wl_shm::WlShm;
 use wayland_client::{Attached, Main};
 use wezterm_input_types::*;
 
+/// A drag and drop operation whose target is one of our windows
+struct DragAndDrop {
+    offer: WlDataOffer,
+    mime_type: &'static str,
+    window_id: usize,
+}
+
 #[derive(Default)]
 struct Inner {
     active_surface_id: u32,
     surface_to_pending: HashMap<u32, Arc<Mutex<PendingMouse>>>,
     serial: u32,
+    /// The mime types advertised by each data offer, keyed by its id
+    offer_mime_types: HashMap<u32, Vec<String>>,
+    drag_and_drop: Option<DragAndDrop>,
 }
 
 impl Inner {
@@ -55,14 +77,98 @@ impl Inner {
     }
 
     fn route_data_offer(&mut self, event: DataOfferEvent, offer: WlDataOffer) {
-        if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
-            copy_and_paste
-                .lock()
-                .unwrap()
-                .handle_data_offer(event, offer);
+        // The offer may be used for either the selection or for drag
+        // and drop; we don't know which until the data device tells us,
+        // so just remember what it has to offer for now
+        if let DataOfferEvent::Offer { mime_type } = event {
+            self.offer_mime_types
+                .entry(offer.as_ref().id())
+                .or_insert_with(Vec::new)
+                .push(mime_type);
         }
     }
 
+    fn drag_enter(&mut self, serial: u32, surface: &WlSurface, offer: Option<WlDataOffer>) {
+        self.drag_leave();
+        let offer = match offer {
+            Some(offer) => offer,
+            None => return,
+        };
+        let mime_types = self
+            .offer_mime_types
+            .remove(&offer.as_ref().id())
+            .unwrap_or_else(Vec::new);
+        let window_id = self
+            .surface_to_pending
+            .get(&surface.as_ref().id())
+            .map(|pending| pending.lock().unwrap().window_id);
+
+        match (choose_drop_mime_type(&mime_types), window_id) {
+            (Some(mime_type), Some(window_id)) => {
+                offer.accept(serial, Some(mime_type.to_string()));
+                if offer.as_ref().version() >= 3 {
+                    offer.set_actions(DndAction::Copy, DndAction::Copy);
+                }
+                self.drag_and_drop.replace(DragAndDrop {
+                    offer,
+                    mime_type,
+                    window_id,
+                });
+            }
+            _ => {
+                // Nothing that we know how to paste
+                offer.accept(serial, None);
+                offer.destroy();
+            }
+        }
+    }
+
+    fn drag_leave(&mut self) {
+        if let Some(dnd) = self.drag_and_drop.take() {
+            dnd.offer.destroy();
+        }
+    }
+
+    fn drag_drop(&mut self) {
+        let DragAndDrop {
+            offer,
+            mime_type,
+            window_id,
+        } = match self.drag_and_drop.take() {
+            Some(dnd) => dnd,
+            None => return,
+        };
+
+        let read = match Pipe::new() {
+            Ok(pipe) => {
+                offer.receive(mime_type.to_string(), pipe.write.as_raw_fd());
+                // Our copy of the write end must be closed, otherwise
+                // we'll never see EOF on the read end
+                pipe.read
+            }
+            Err(err) => {
+                log::error!("failed to create pipe for dropped data: {:#}", err);
+                offer.destroy();
+                return;
+            }
+        };
+
+        std::thread::spawn(move || {
+            let result = read_pipe_with_timeout(read);
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                if offer.as_ref().version() >= 3 {
+                    offer.finish();
+                }
+                offer.destroy();
+                match &result {
+                    Ok(data) => inner.dispatch_dropped_data(mime_type, data),
+                    Err(err) => log::error!("while reading dropped data: {:#}", err),
+                }
+                Ok(())
+            });
+        });
+    }
+
     fn handle_data_event(&mut self, event: DataDeviceEvent, inner: &Arc<Mutex<Self>>) {
         match event {
             DataDeviceEvent::DataOffer { id } => {
@@ -74,13 +180,25 @@ impl Inner {
                     }
                 });
             }
-            DataDeviceEvent::Enter { .. }
-            | DataDeviceEvent::Leave { .. }
-            | DataDeviceEvent::Motion { .. }
-            | DataDeviceEvent::Drop => {}
+            DataDeviceEvent::Enter {
+                serial,
+                surface,
+                id,
+                ..
+            } => {
+                self.drag_enter(serial, &surface, id);
+            }
+            DataDeviceEvent::Leave => {
+                self.drag_leave();
+            }
+            DataDeviceEvent::Motion { .. } => {}
+            DataDeviceEvent::Drop => {
+                self.drag_drop();
+            }
 
             DataDeviceEvent::Selection { id } => {
                 if let Some(offer) = id {
+                    self.offer_mime_types.remove(&offer.as_ref().id());
                     if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
                         copy_and_paste.lock().unwrap().confirm_selection(offer);
                     }
@@ -89,11 +207,51 @@ impl Inner {
             _ => {}
         }
     }
+
+    fn route_primary_selection_offer(
+        &mut self,
+        event: PrimarySelectionOfferEvent,
+        offer: ZwpPrimarySelectionOfferV1,
+    ) {
+        if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
+            copy_and_paste
+                .lock()
+                .unwrap()
+                .handle_primary_selection_offer(event, offer);
+        }
+    }
+
+    fn handle_primary_selection_event(
+        &mut self,
+        event: PrimarySelectionDeviceEvent,
+        inner: &Arc<Mutex<Self>>,
+    ) {
+        match event {
+            PrimarySelectionDeviceEvent::DataOffer { offer } => {
+                offer.quick_assign({
+                    let inner = Arc::clone(inner);
+                    move |offer, event, _dispatch_data| {
+                        let mut inner = inner.lock().unwrap();
+                        inner.route_primary_selection_offer(event, offer.detach());
+                    }
+                });
+            }
+            PrimarySelectionDeviceEvent::Selection { id } => {
+                if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
+                    copy_and_paste.lock().unwrap().confirm_primary_selection(id);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 pub struct PointerDispatcher {
     inner: Arc<Mutex<Inner>>,
     pub(crate) data_device: Main<WlDataDevice>,
+    /// None if the compositor doesn't support the primary selection
+    /// protocol, in which case the primary selection is emulated
+    pub(crate) primary_selection_device: Option<Main<ZwpPrimarySelectionDeviceV1>>,
     auto_pointer: ThemedPointer,
     #[allow(dead_code)]
     themer: ThemeManager,
@@ -214,6 +372,7 @@ impl PointerDispatcher {
         compositor: Attached<WlCompositor>,
         shm: Attached<WlShm>,
         dev_mgr: Attached<WlDataDeviceManager>,
+        primary_select_mgr: Option<&Attached<ZwpPrimarySelectionDeviceManagerV1>>,
     ) -> anyhow::Result<Self> {
         let inner = Arc::new(Mutex::new(Inner::default()));
         let pointer = seat.get_pointer();
@@ -235,9 +394,24 @@ impl PointerDispatcher {
             }
         });
 
+        let primary_selection_device = primary_select_mgr.map(|mgr| {
+            let device = mgr.get_device(seat);
+            device.quick_assign({
+                let inner = Arc::clone(&inner);
+                move |_device, event, _| {
+                    inner
+                        .lock()
+                        .unwrap()
+                        .handle_primary_selection_event(event, &inner);
+                }
+            });
+            device
+        });
+
         Ok(Self {
             inner,
             data_device,
+            primary_selection_device,
             themer,
             auto_pointer,
         })
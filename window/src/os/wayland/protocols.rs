@@ -0,0 +1,83 @@
+//! Client side bindings for the protocols that are newer than the ones
+//! bundled with wayland-protocols.  build.rs generates them from the xml
+//! files in the protocols directory, and they are included here in the
+//! same way that wayland-protocols includes the bindings for its own
+//! protocols: each in a module that provides the names that the
+//! generated code expects to find in its parent.
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(missing_docs, clippy::all)]
+
+macro_rules! wayland_protocol {
+    ($(#[$meta:meta])* $name:ident, $api:literal, { $($imports:item)* }) => {
+        $(#[$meta])*
+        pub mod $name {
+            pub(crate) use wayland_client::sys;
+            pub(crate) use wayland_client::{AnonymousObject, Attached, Main, Proxy, ProxyMap};
+            pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+            pub(crate) use wayland_commons::smallvec;
+            pub(crate) use wayland_commons::wire::{Argument, ArgumentType, Message, MessageDesc};
+            pub(crate) use wayland_commons::{Interface, MessageGroup};
+            $($imports)*
+
+            include!(concat!(env!("OUT_DIR"), "/", $api));
+        }
+    };
+}
+
+wayland_protocol!(
+    /// wp-fractional-scale-v1
+    fractional_scale,
+    "fractional_scale_v1_client_api.rs",
+    {
+        pub(crate) use wayland_client::protocol::wl_surface;
+    }
+);
+
+wayland_protocol!(
+    /// xdg-activation-v1
+    xdg_activation,
+    "xdg_activation_v1_client_api.rs",
+    {
+        pub(crate) use wayland_client::protocol::{wl_seat, wl_surface};
+    }
+);
+
+wayland_protocol!(
+    /// wlr-layer-shell-unstable-v1
+    layer_shell,
+    "wlr_layer_shell_unstable_v1_client_api.rs",
+    {
+        pub(crate) use bitflags::bitflags;
+        pub(crate) use smithay_client_toolkit::reexports::protocols::xdg_shell::client::xdg_popup;
+        pub(crate) use wayland_client::protocol::{wl_output, wl_surface};
+    }
+);
+
+wayland_protocol!(
+    /// alpha-modifier-v1
+    alpha_modifier,
+    "alpha_modifier_v1_client_api.rs",
+    {
+        pub(crate) use wayland_client::protocol::wl_surface;
+    }
+);
+
+wayland_protocol!(
+    /// The KDE blur protocol
+    kde_blur,
+    "kde_blur_client_api.rs",
+    {
+        pub(crate) use wayland_client::protocol::{wl_region, wl_surface};
+    }
+);
+
+wayland_protocol!(
+    /// ext-background-effect-v1
+    background_effect,
+    "ext_background_effect_v1_client_api.rs",
+    {
+        pub(crate) use bitflags::bitflags;
+        pub(crate) use wayland_client::protocol::{wl_region, wl_surface};
+    }
+);
@@ -0,0 +1,271 @@
+//! Turns wl_touch events into mouse events, so that a touchscreen
+//! can be used to click, select text and scroll.
+//! Unlike X11 and Windows, Wayland compositors don't emulate a
+//! pointer for touch input, so we have to recognize the gestures.
+use crate::os::wayland::connection::WaylandConnection;
+use smithay_client_toolkit as toolkit;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use toolkit::reexports::client::protocol::wl_touch::{Event as TouchEvent, WlTouch};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Attached, Main};
+use wezterm_input_types::MousePress;
+
+/// A finger that moves further than this, in surface coordinates,
+/// is dragging rather than tapping
+const DRAG_THRESHOLD: f64 = 8.0;
+/// A finger that is held still for at least this many milliseconds
+/// is a long press rather than a tap
+const LONG_PRESS_MS: u32 = 500;
+/// How far two fingers move, in surface coordinates, to scroll by one
+const SCROLL_STEP: f64 = 16.0;
+
+/// The mouse actions that the touch gestures amount to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchAction {
+    /// Move the mouse to these surface coordinates
+    MoveTo(f64, f64),
+    Press(MousePress),
+    Release(MousePress),
+    /// Scroll by this many steps; positive values scroll up
+    Scroll(i16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Gesture {
+    Idle,
+    /// A single finger is down, and hasn't moved far enough to be
+    /// a drag.  Releasing it is either a tap or a long press.
+    Touching {
+        id: i32,
+        x: f64,
+        y: f64,
+        time: u32,
+    },
+    /// A single finger is dragging, with the left button held
+    Dragging {
+        id: i32,
+    },
+    /// Two or more fingers are scrolling
+    Scrolling {
+        y: f64,
+    },
+}
+
+impl Default for Gesture {
+    fn default() -> Self {
+        Gesture::Idle
+    }
+}
+
+/// Recognizes taps, long presses, drags and scrolls
+#[derive(Default)]
+struct GestureRecognizer {
+    points: HashMap<i32, (f64, f64)>,
+    gesture: Gesture,
+}
+
+impl GestureRecognizer {
+    fn average_y(&self) -> f64 {
+        self.points.values().map(|(_, y)| y).sum::<f64>() / self.points.len().max(1) as f64
+    }
+
+    fn down(&mut self, id: i32, time: u32, x: f64, y: f64) -> Vec<TouchAction> {
+        self.points.insert(id, (x, y));
+        match self.gesture {
+            Gesture::Idle => {
+                self.gesture = Gesture::Touching { id, x, y, time };
+                vec![TouchAction::MoveTo(x, y)]
+            }
+            Gesture::Touching { .. } => {
+                self.gesture = Gesture::Scrolling {
+                    y: self.average_y(),
+                };
+                vec![]
+            }
+            Gesture::Dragging { .. } => {
+                self.gesture = Gesture::Scrolling {
+                    y: self.average_y(),
+                };
+                vec![TouchAction::Release(MousePress::Left)]
+            }
+            Gesture::Scrolling { .. } => {
+                // The average moves when a finger is added
+                self.gesture = Gesture::Scrolling {
+                    y: self.average_y(),
+                };
+                vec![]
+            }
+        }
+    }
+
+    fn motion(&mut self, id: i32, x: f64, y: f64) -> Vec<TouchAction> {
+        if let Some(point) = self.points.get_mut(&id) {
+            *point = (x, y);
+        } else {
+            return vec![];
+        }
+        match self.gesture {
+            Gesture::Touching {
+                id: touch_id,
+                x: start_x,
+                y: start_y,
+                ..
+            } if touch_id == id => {
+                if (x - start_x).hypot(y - start_y) < DRAG_THRESHOLD {
+                    return vec![];
+                }
+                self.gesture = Gesture::Dragging { id };
+                vec![
+                    TouchAction::Press(MousePress::Left),
+                    TouchAction::MoveTo(x, y),
+                ]
+            }
+            Gesture::Dragging { id: drag_id } if drag_id == id => vec![TouchAction::MoveTo(x, y)],
+            Gesture::Scrolling { y: last_y } => {
+                let steps = ((self.average_y() - last_y) / SCROLL_STEP).trunc();
+                if steps == 0. {
+                    return vec![];
+                }
+                self.gesture = Gesture::Scrolling {
+                    y: last_y + steps * SCROLL_STEP,
+                };
+                vec![TouchAction::Scroll(steps as i16)]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn up(&mut self, id: i32, time: u32) -> Vec<TouchAction> {
+        self.points.remove(&id);
+        match self.gesture {
+            Gesture::Touching {
+                id: touch_id,
+                time: start,
+                ..
+            } if touch_id == id => {
+                self.gesture = Gesture::Idle;
+                let button = if time.wrapping_sub(start) >= LONG_PRESS_MS {
+                    MousePress::Right
+                } else {
+                    MousePress::Left
+                };
+                vec![TouchAction::Press(button), TouchAction::Release(button)]
+            }
+            Gesture::Dragging { id: drag_id } if drag_id == id => {
+                self.gesture = Gesture::Idle;
+                vec![TouchAction::Release(MousePress::Left)]
+            }
+            Gesture::Scrolling { .. } => {
+                if self.points.is_empty() {
+                    self.gesture = Gesture::Idle;
+                } else {
+                    self.gesture = Gesture::Scrolling {
+                        y: self.average_y(),
+                    };
+                }
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The compositor has taken over the touch sequence, eg: for
+    /// one of its own gestures, so abandon ours
+    fn cancel(&mut self) -> Vec<TouchAction> {
+        self.points.clear();
+        match std::mem::take(&mut self.gesture) {
+            Gesture::Dragging { .. } => vec![TouchAction::Release(MousePress::Left)],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    surface_to_window_id: HashMap<u32, usize>,
+    /// The window that the current touch sequence started in;
+    /// the whole sequence is delivered to it
+    window_id: Option<usize>,
+    recognizer: GestureRecognizer,
+}
+
+impl Inner {
+    fn handle_event(&mut self, event: TouchEvent) {
+        let mut serial = None;
+        let actions = match event {
+            TouchEvent::Down {
+                serial: down_serial,
+                time,
+                surface,
+                id,
+                x,
+                y,
+            } => {
+                if self.recognizer.points.is_empty() {
+                    self.window_id = self
+                        .surface_to_window_id
+                        .get(&surface.as_ref().id())
+                        .copied();
+                }
+                serial.replace(down_serial);
+                self.recognizer.down(id, time, x, y)
+            }
+            TouchEvent::Motion { id, x, y, .. } => self.recognizer.motion(id, x, y),
+            TouchEvent::Up {
+                serial: up_serial,
+                time,
+                id,
+            } => {
+                serial.replace(up_serial);
+                self.recognizer.up(id, time)
+            }
+            TouchEvent::Cancel => self.recognizer.cancel(),
+            _ => vec![],
+        };
+
+        if actions.is_empty() {
+            return;
+        }
+        if let Some(window_id) = self.window_id {
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                inner.dispatch_touch_actions(serial, &actions);
+                Ok(())
+            });
+        }
+    }
+}
+
+pub struct TouchDispatcher {
+    inner: Arc<Mutex<Inner>>,
+    touch: Vec<Main<WlTouch>>,
+}
+
+impl TouchDispatcher {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::default())),
+            touch: vec![],
+        }
+    }
+
+    pub fn register(&mut self, seat: &Attached<WlSeat>) {
+        let touch = seat.get_touch();
+        touch.quick_assign({
+            let inner = Arc::clone(&self.inner);
+            move |_touch, event, _dispatch_data| {
+                inner.lock().unwrap().handle_event(event);
+            }
+        });
+        self.touch.push(touch);
+    }
+
+    pub fn add_window(&self, window_id: usize, surface: &WlSurface) {
+        self.inner
+            .lock()
+            .unwrap()
+            .surface_to_window_id
+            .insert(surface.as_ref().id(), window_id);
+    }
+}
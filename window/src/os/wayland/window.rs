@@ -1,12 +1,25 @@
 use super::copy_and_paste::*;
 use super::keyboard::KeyboardEvent;
 use super::pointer::*;
+use super::protocols::alpha_modifier::wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1;
+use super::protocols::background_effect::ext_background_effect_surface_v1::ExtBackgroundEffectSurfaceV1;
+use super::protocols::fractional_scale::wp_fractional_scale_v1::{
+    Event as FractionalScaleEvent, WpFractionalScaleV1,
+};
+use super::protocols::kde_blur::org_kde_kwin_blur::OrgKdeKwinBlur;
+use super::protocols::layer_shell::zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1};
+use super::protocols::layer_shell::zwlr_layer_surface_v1::{
+    Anchor, Event as LayerSurfaceEvent, KeyboardInteractivity, ZwlrLayerSurfaceV1,
+};
+use super::protocols::xdg_activation::xdg_activation_token_v1::Event as ActivationTokenEvent;
+use super::touch::TouchAction;
+use crate::configuration::DropDownSettings;
 use crate::connection::ConnectionOps;
 use crate::os::wayland::connection::WaylandConnection;
 use crate::os::xkeysyms::keysym_to_keycode;
 use crate::{
-    Clipboard, Connection, Dimensions, MouseCursor, Point, ScreenPoint, Window, WindowCallbacks,
-    WindowOps, WindowOpsMut,
+    config, Clipboard, Connection, Dimensions, FramePresentation, MouseCursor, Point,
+    ResizeIncrement, ScreenPoint, Window, WindowCallbacks, WindowOps, WindowOpsMut,
 };
 use anyhow::{anyhow, bail, Context};
 use filedescriptor::FileDescriptor;
@@ -18,15 +31,45 @@ use std::convert::TryInto;
 use std::io::{Read, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use toolkit::get_surface_scale_factor;
 use toolkit::reexports::client::protocol::wl_data_source::Event as DataSourceEvent;
 use toolkit::reexports::client::protocol::wl_surface::WlSurface;
-use toolkit::window::{ButtonColorSpec, ColorSpec, ConceptConfig, ConceptFrame, Event};
+use toolkit::reexports::protocols::presentation_time::client::wp_presentation_feedback::Event as PresentationFeedbackEvent;
+use toolkit::reexports::protocols::unstable::idle_inhibit::v1::client::zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1;
+use toolkit::reexports::protocols::unstable::keyboard_shortcuts_inhibit::v1::client::zwp_keyboard_shortcuts_inhibitor_v1::{
+    Event as ShortcutsInhibitorEvent, ZwpKeyboardShortcutsInhibitorV1,
+};
+use toolkit::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_source_v1::Event as PrimarySelectionSourceEvent;
+use toolkit::reexports::protocols::viewporter::client::wp_viewport::WpViewport;
+use toolkit::window::{
+    ButtonColorSpec, ColorSpec, ConceptConfig, ConceptFrame, Decorations, Event, State,
+};
+use wayland_client::protocol::wl_compositor::WlCompositor;
 use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::Main;
 use wayland_egl::{is_available as egl_is_available, WlEglSurface};
 use wezterm_input_types::*;
 
+/// How long it takes for the drop-down to slide into or out of view
+const DROP_DOWN_SLIDE_DURATION: Duration = Duration::from_millis(150);
+const DROP_DOWN_SLIDE_STEPS: u32 = 10;
+
+/// Returns the current time of the clock that the compositor uses
+/// for presentation feedback
+fn presentation_clock_now(clock_id: u32) -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(clock_id as libc::clockid_t, &mut now);
+    }
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
 const DARK_GRAY: [u8; 4] = [0xff, 0x35, 0x35, 0x35];
 const DARK_PURPLE: [u8; 4] = [0xff, 0x2b, 0x20, 0x42];
 const PURPLE: [u8; 4] = [0xff, 0x3b, 0x30, 0x52];
@@ -81,9 +124,40 @@ pub struct WaylandWindowInner {
     window_id: usize,
     callbacks: Box<dyn WindowCallbacks>,
     surface: WlSurface,
+    app_id: String,
     copy_and_paste: Arc<Mutex<CopyAndPaste>>,
     window: Option<toolkit::window::Window<ConceptFrame>>,
+    /// Set instead of `window` when the window is a drop-down
+    drop_down: Option<DropDown>,
     dimensions: Dimensions,
+    /// true while the compositor dictates the size of the window,
+    /// such as when it is maximized, fullscreen or tiled
+    size_is_constrained: bool,
+    full_screen: bool,
+    maximized: bool,
+    /// The surface size to return to when the window is no longer
+    /// maximized or fullscreen.  The compositor leaves it to us to
+    /// pick the size in that case.
+    restore_size: Option<(u32, u32)>,
+    /// When set, the size that the window is configured to is
+    /// rounded down to a whole number of these steps
+    resize_increments: Option<ResizeIncrement>,
+    /// The scale that the compositor prefers, when it supports
+    /// wp-fractional-scale-v1.  If None, the integer buffer scale is used.
+    fractional_scale_factor: Option<f64>,
+    fractional_scale: Option<Main<WpFractionalScaleV1>>,
+    viewport: Option<Main<WpViewport>>,
+    /// Applies window_opacity; None if the compositor doesn't
+    /// support alpha-modifier
+    alpha_modifier: Option<Main<WpAlphaModifierSurfaceV1>>,
+    /// Set while the compositor is asked to blur the background,
+    /// by whichever protocol it supports
+    background_effect: Option<Main<ExtBackgroundEffectSurfaceV1>>,
+    kde_blur: Option<Main<OrgKdeKwinBlur>>,
+    /// Set while the compositor's shortcuts are inhibited
+    shortcuts_inhibitor: Option<Main<ZwpKeyboardShortcutsInhibitorV1>>,
+    /// Set while the system is prevented from going idle
+    idle_inhibitor: Option<Main<ZwpIdleInhibitorV1>>,
     need_paint: bool,
     last_mouse_coords: Point,
     mouse_buttons: MouseButtons,
@@ -95,6 +169,8 @@ pub struct WaylandWindowInner {
     // libraries will segfault on shutdown
     wegl_surface: Option<WlEglSurface>,
     gl_state: Option<Rc<glium::backend::Context>>,
+    /// The backend of gl_state, through which damage is reported
+    egl_state: Option<Rc<crate::egl::GlState>>,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -102,7 +178,11 @@ struct PendingEvent {
     close: bool,
     refresh_decorations: bool,
     configure: Option<(u32, u32)>,
+    size_is_constrained: Option<bool>,
+    full_screen: Option<bool>,
+    maximized: Option<bool>,
     dpi: Option<i32>,
+    fractional_scale: Option<f64>,
 }
 
 impl PendingEvent {
@@ -124,7 +204,25 @@ impl PendingEvent {
                     false
                 }
             }
-            Event::Configure { new_size, .. } => {
+            Event::Configure { new_size, states } => {
+                self.size_is_constrained.replace(states.iter().any(|state| {
+                    matches!(
+                        state,
+                        State::Maximized
+                            | State::Fullscreen
+                            | State::TiledLeft
+                            | State::TiledRight
+                            | State::TiledTop
+                            | State::TiledBottom
+                    )
+                }));
+                self.full_screen.replace(
+                    states
+                        .iter()
+                        .any(|state| matches!(state, State::Fullscreen)),
+                );
+                self.maximized
+                    .replace(states.iter().any(|state| matches!(state, State::Maximized)));
                 let changed;
                 if let Some(new_size) = new_size {
                     changed = self.configure.is_none();
@@ -139,6 +237,114 @@ impl PendingEvent {
     }
 }
 
+/// The state of a window that is shown as a drop-down,
+/// using a wlr-layer-shell surface rather than a toplevel
+struct DropDown {
+    layer_surface: Main<ZwlrLayerSurfaceV1>,
+    settings: DropDownSettings,
+    /// true if the compositor supports on-demand keyboard focus
+    on_demand_keyboard: bool,
+    /// The height in surface coordinates; None until the
+    /// compositor has told us the size of the screen
+    height: Option<u32>,
+    /// A buffer must not be attached until the compositor
+    /// has configured the final size of the surface
+    configured: bool,
+    /// true if the window is shown, or is sliding into view
+    visible: bool,
+    /// The current top margin; it is negative while the
+    /// window is partially or completely out of view
+    margin_top: i32,
+    /// Incremented when the window starts to slide, so that an
+    /// unfinished slide can tell that it has been superseded
+    slide_generation: usize,
+}
+
+impl DropDown {
+    fn create(
+        layer_shell: &Main<ZwlrLayerShellV1>,
+        surface: &WlSurface,
+        namespace: &str,
+        settings: DropDownSettings,
+        window_id: usize,
+        pending_event: &Arc<Mutex<PendingEvent>>,
+    ) -> Self {
+        let layer_surface =
+            layer_shell.get_layer_surface(surface, None, Layer::Top, namespace.to_string());
+        // Fill the screen so that the first configure event tells us
+        // how large it is; the height is then derived from that
+        layer_surface.set_anchor(Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right);
+        layer_surface.set_size(0, 0);
+        layer_surface.quick_assign({
+            let pending_event = Arc::clone(pending_event);
+            move |_, event, _dispatch_data| match event {
+                LayerSurfaceEvent::Configure {
+                    serial,
+                    width,
+                    height,
+                } => {
+                    WaylandConnection::with_window_inner(window_id, move |inner| {
+                        inner.configure_drop_down(serial, width, height);
+                        Ok(())
+                    });
+                }
+                LayerSurfaceEvent::Closed => {
+                    if pending_event.lock().unwrap().queue(Event::Close) {
+                        WaylandConnection::with_window_inner(window_id, move |inner| {
+                            inner.dispatch_pending_event();
+                            Ok(())
+                        });
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let drop_down = Self {
+            layer_surface,
+            settings,
+            on_demand_keyboard: layer_shell.as_ref().version() >= 4,
+            height: None,
+            configured: false,
+            visible: true,
+            margin_top: settings.margin_top as i32,
+            slide_generation: 0,
+        };
+        drop_down.apply_margins();
+        drop_down
+            .layer_surface
+            .set_keyboard_interactivity(drop_down.keyboard_interactivity());
+        // The initial commit must not have a buffer attached
+        surface.commit();
+        drop_down
+    }
+
+    fn keyboard_interactivity(&self) -> KeyboardInteractivity {
+        if !self.visible {
+            KeyboardInteractivity::None
+        } else if self.settings.exclusive_keyboard || !self.on_demand_keyboard {
+            KeyboardInteractivity::Exclusive
+        } else {
+            KeyboardInteractivity::OnDemand
+        }
+    }
+
+    fn apply_margins(&self) {
+        self.layer_surface.set_margin(
+            self.margin_top,
+            self.settings.margin_right as i32,
+            0,
+            self.settings.margin_left as i32,
+        );
+    }
+}
+
+impl Drop for DropDown {
+    fn drop(&mut self) {
+        self.layer_surface.destroy();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct WaylandWindow(usize);
 
@@ -180,55 +386,148 @@ impl WaylandWindow {
                 }
             });
 
-        let dimensions = Dimensions {
-            pixel_width: width,
-            pixel_height: height,
-            dpi: crate::DEFAULT_DPI as usize,
-        };
-
-        let mut window = conn
-            .environment
-            .borrow()
-            .create_window::<ConceptFrame, _>(
-                surface.clone().detach(),
-                (
-                    dimensions.pixel_width as u32,
-                    dimensions.pixel_height as u32,
-                ),
-                {
+        let (fractional_scale, viewport) = match (
+            conn.fractional_scale_manager.as_ref(),
+            conn.viewporter.as_ref(),
+        ) {
+            (Some(manager), Some(viewporter)) => {
+                let fractional_scale = manager.get_fractional_scale(&surface);
+                fractional_scale.quick_assign({
                     let pending_event = Arc::clone(&pending_event);
-                    move |evt, mut _dispatch_data| {
-                        if pending_event.lock().unwrap().queue(evt) {
+                    move |_, event, _dispatch_data| {
+                        if let FractionalScaleEvent::PreferredScale { scale } = event {
+                            // The scale is expressed in 120ths
+                            let scale = scale as f64 / 120.;
+                            log::debug!("window id={} preferred scale is {}", window_id, scale);
+                            pending_event
+                                .lock()
+                                .unwrap()
+                                .fractional_scale
+                                .replace(scale);
                             WaylandConnection::with_window_inner(window_id, move |inner| {
                                 inner.dispatch_pending_event();
                                 Ok(())
                             });
                         }
                     }
-                },
-            )
-            .context("Failed to create window")?;
+                });
+                let viewport = viewporter.get_viewport(&surface);
+                (Some(fractional_scale), Some(viewport))
+            }
+            _ => (None, None),
+        };
 
-        window.set_app_id(class_name.to_string());
-        window.set_resizable(true);
-        window.set_title(name.to_string());
-        window.set_frame_config(frame_config());
+        let alpha_modifier = conn
+            .alpha_modifier
+            .as_ref()
+            .map(|manager| manager.get_surface(&surface));
+
+        let dimensions = Dimensions {
+            pixel_width: width,
+            pixel_height: height,
+            dpi: crate::DEFAULT_DPI as usize,
+        };
+
+        let drop_down = match config().drop_down() {
+            Some(settings) if !conn.drop_down_created.get() => match conn.layer_shell.as_ref() {
+                Some(layer_shell) => {
+                    conn.drop_down_created.set(true);
+                    conn.toggle_drop_down_on_signal();
+                    Some(DropDown::create(
+                        layer_shell,
+                        &surface,
+                        class_name,
+                        settings,
+                        window_id,
+                        &pending_event,
+                    ))
+                }
+                None => {
+                    log::warn!(
+                        "the compositor doesn't support wlr-layer-shell, \
+                         so the window cannot be shown as a drop-down"
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let window = if drop_down.is_some() {
+            None
+        } else {
+            let mut window = conn
+                .environment
+                .borrow()
+                .create_window::<ConceptFrame, _>(
+                    surface.clone().detach(),
+                    (
+                        dimensions.pixel_width as u32,
+                        dimensions.pixel_height as u32,
+                    ),
+                    {
+                        let pending_event = Arc::clone(&pending_event);
+                        move |evt, mut _dispatch_data| {
+                            if pending_event.lock().unwrap().queue(evt) {
+                                WaylandConnection::with_window_inner(window_id, move |inner| {
+                                    inner.dispatch_pending_event();
+                                    Ok(())
+                                });
+                            }
+                        }
+                    },
+                )
+                .context("Failed to create window")?;
+
+            window.set_app_id(class_name.to_string());
+            window.set_resizable(true);
+            window.set_title(name.to_string());
+            window.set_frame_config(frame_config());
+            // Compositors such as GNOME's don't decorate windows, in which
+            // case FollowServer uses our own frame, which has a title bar
+            // that can be dragged, and minimize, maximize and close buttons
+            window.set_decorate(if config().window_decorations() {
+                Decorations::FollowServer
+            } else {
+                Decorations::None
+            });
+            Some(window)
+        };
 
         // window.new_seat(&conn.seat);
         conn.keyboard.add_window(window_id, &surface);
+        conn.touch.add_window(window_id, &surface);
 
         let copy_and_paste = CopyAndPaste::create();
         let pending_mouse = PendingMouse::create(window_id, &copy_and_paste);
 
         conn.pointer.add_window(&surface, &pending_mouse);
 
+        // The size of a drop-down is set by the configuration
+        let size_is_constrained = drop_down.is_some();
+
         let inner = Rc::new(RefCell::new(WaylandWindowInner {
             copy_and_paste,
             window_id,
             callbacks,
             surface: surface.detach(),
-            window: Some(window),
+            app_id: class_name.to_string(),
+            window,
+            drop_down,
             dimensions,
+            size_is_constrained,
+            full_screen: false,
+            maximized: false,
+            restore_size: None,
+            resize_increments: None,
+            fractional_scale_factor: None,
+            fractional_scale,
+            viewport,
+            alpha_modifier,
+            background_effect: None,
+            kde_blur: None,
+            shortcuts_inhibitor: None,
+            idle_inhibitor: None,
             need_paint: true,
             last_mouse_coords: Point::new(0, 0),
             mouse_buttons: MouseButtons::NONE,
@@ -237,6 +536,7 @@ impl WaylandWindow {
             pending_mouse,
             gl_state: None,
             wegl_surface: None,
+            egl_state: None,
         }));
 
         let window_handle = Window::Wayland(WaylandWindow(window_id));
@@ -244,6 +544,18 @@ impl WaylandWindow {
         conn.windows.borrow_mut().insert(window_id, inner.clone());
 
         inner.borrow_mut().enable_opengl()?;
+        inner.borrow().apply_opacity();
+        inner.borrow_mut().apply_background_blur();
+
+        // When we were launched from eg: a launcher or a notification,
+        // it may have passed us a token that allows us to take the focus.
+        // Consume it so that it doesn't leak into our child processes.
+        if let Ok(token) = std::env::var("XDG_ACTIVATION_TOKEN") {
+            std::env::remove_var("XDG_ACTIVATION_TOKEN");
+            if let Some(activation) = conn.xdg_activation.as_ref() {
+                activation.activate(token, &inner.borrow().surface);
+            }
+        }
 
         Ok(window_handle)
     }
@@ -318,6 +630,60 @@ impl WaylandWindowInner {
         }
     }
 
+    /// Asks the compositor to activate the window via xdg-activation.
+    /// The token is only honored if it carries the serial of a recent
+    /// input event; otherwise compositors such as sway and kwin mark
+    /// the window as demanding attention instead, which is what
+    /// request_attention relies upon.
+    fn request_activation(&mut self, with_serial: bool) {
+        let conn = Connection::get().unwrap().wayland();
+        let activation = match conn.xdg_activation.as_ref() {
+            Some(activation) => activation,
+            None => {
+                log::debug!("xdg-activation is not available");
+                return;
+            }
+        };
+
+        let token = activation.get_activation_token();
+        token.set_app_id(self.app_id.clone());
+        token.set_surface(&self.surface);
+        if with_serial {
+            if let Some(seat) = conn.seat.as_ref() {
+                let serial = self.copy_and_paste.lock().unwrap().last_serial();
+                token.set_serial(serial, seat);
+            }
+        }
+
+        let window_id = self.window_id;
+        token.quick_assign(move |token, event, _dispatch_data| {
+            if let ActivationTokenEvent::Done { token: value } = event {
+                token.destroy();
+                WaylandConnection::with_window_inner(window_id, move |inner| {
+                    let conn = Connection::get().unwrap().wayland();
+                    if let Some(activation) = conn.xdg_activation.as_ref() {
+                        activation.activate(value, &inner.surface);
+                    }
+                    Ok(())
+                });
+            }
+        });
+        token.commit();
+    }
+
+    /// Called when the data from a drag and drop operation
+    /// targeting this window has been received
+    pub(crate) fn dispatch_dropped_data(&mut self, mime_type: &str, data: &str) {
+        if mime_type == URI_LIST_MIME_TYPE {
+            let paths = parse_uri_list(data);
+            if !paths.is_empty() {
+                self.callbacks.dropped_file(paths);
+            }
+        } else {
+            self.callbacks.dropped_string(data.replace("\r\n", "\n"));
+        }
+    }
+
     pub(crate) fn dispatch_pending_mouse(&mut self) {
         // Dancing around the borrow checker and the call to self.refresh_frame()
         let pending_mouse = Arc::clone(&self.pending_mouse);
@@ -344,16 +710,10 @@ impl WaylandWindowInner {
         }
 
         while let Some((button, state)) = PendingMouse::next_button(&pending_mouse) {
-            let button_mask = match button {
-                MousePress::Left => MouseButtons::LEFT,
-                MousePress::Right => MouseButtons::RIGHT,
-                MousePress::Middle => MouseButtons::MIDDLE,
-            };
-
             if state == DebuggableButtonState::Pressed {
-                self.mouse_buttons |= button_mask;
+                self.mouse_buttons |= button_mask(button);
             } else {
-                self.mouse_buttons -= button_mask;
+                self.mouse_buttons -= button_mask(button);
             }
 
             let event = MouseEvent {
@@ -374,7 +734,7 @@ impl WaylandWindowInner {
         }
 
         if let Some((value_x, value_y)) = PendingMouse::scroll(&pending_mouse) {
-            let factor = self.get_dpi_factor() as f64;
+            let factor = self.get_scale_factor();
             let discrete_x = value_x.trunc() * factor;
             if discrete_x != 0. {
                 let event = MouseEvent {
@@ -409,19 +769,172 @@ impl WaylandWindowInner {
         }
     }
 
-    fn get_dpi_factor(&self) -> i32 {
-        self.dimensions.dpi as i32 / crate::DEFAULT_DPI as i32
+    /// Delivers the mouse events that a touch gesture amounts to
+    pub(crate) fn dispatch_touch_actions(&mut self, serial: Option<u32>, actions: &[TouchAction]) {
+        if let Some(serial) = serial {
+            self.copy_and_paste
+                .lock()
+                .unwrap()
+                .update_last_serial(serial);
+        }
+
+        for action in actions {
+            let kind = match *action {
+                TouchAction::MoveTo(x, y) => {
+                    self.last_mouse_coords = Point::new(
+                        self.surface_to_pixels(x as i32) as isize,
+                        self.surface_to_pixels(y as i32) as isize,
+                    );
+                    MouseEventKind::Move
+                }
+                TouchAction::Press(button) => {
+                    self.mouse_buttons |= button_mask(button);
+                    MouseEventKind::Press(button)
+                }
+                TouchAction::Release(button) => {
+                    self.mouse_buttons -= button_mask(button);
+                    MouseEventKind::Release(button)
+                }
+                TouchAction::Scroll(amount) => MouseEventKind::VertWheel(amount),
+            };
+            let event = MouseEvent {
+                kind,
+                coords: self.last_mouse_coords,
+                screen_coords: ScreenPoint::new(
+                    self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
+                    self.last_mouse_coords.y + self.dimensions.pixel_height as isize,
+                ),
+                mouse_buttons: self.mouse_buttons,
+                modifiers: self.modifiers,
+            };
+            self.callbacks
+                .mouse_event(&event, &Window::Wayland(WaylandWindow(self.window_id)));
+        }
+    }
+
+    /// Asks the compositor to apply window_opacity to the whole surface.
+    /// This takes effect when the surface is next committed.
+    fn apply_opacity(&self) {
+        if let Some(alpha_modifier) = self.alpha_modifier.as_ref() {
+            let opacity = config().window_opacity().max(0.).min(1.) as f64;
+            alpha_modifier.set_multiplier((opacity * u32::MAX as f64) as u32);
+        }
+    }
+
+    /// Asks the compositor to blur whatever is behind the window, or
+    /// to stop doing so, according to wayland_background_blur.
+    /// This takes effect when the surface is next committed.
+    pub(crate) fn apply_background_blur(&mut self) {
+        let conn = match Connection::get() {
+            Some(conn) => conn.wayland(),
+            None => return,
+        };
+        let enable = config().wayland_background_blur();
+
+        if let Some(manager) = conn.background_effect.as_ref().filter(|_| {
+            conn.background_effect_can_blur
+                .load(std::sync::atomic::Ordering::Relaxed)
+        }) {
+            if let Some(blur) = self.kde_blur.take() {
+                if let Some(kde) = conn.kde_blur_manager.as_ref() {
+                    kde.unset(&self.surface);
+                }
+                blur.release();
+            }
+            if enable {
+                if self.background_effect.is_none() {
+                    self.background_effect
+                        .replace(manager.get_background_effect(&self.surface));
+                }
+                // The region is clipped to the surface, so this
+                // covers the window whatever its size
+                let region = conn
+                    .environment
+                    .borrow()
+                    .require_global::<WlCompositor>()
+                    .create_region();
+                region.add(0, 0, i32::MAX, i32::MAX);
+                if let Some(effect) = self.background_effect.as_ref() {
+                    effect.set_blur_region(Some(&region));
+                }
+                region.destroy();
+            } else if let Some(effect) = self.background_effect.take() {
+                effect.destroy();
+            }
+        } else if let Some(manager) = conn.kde_blur_manager.as_ref() {
+            if enable {
+                if self.kde_blur.is_none() {
+                    let blur = manager.create(&self.surface);
+                    // No region means the whole window
+                    blur.set_region(None);
+                    blur.commit();
+                    self.kde_blur.replace(blur);
+                }
+            } else if let Some(blur) = self.kde_blur.take() {
+                manager.unset(&self.surface);
+                blur.release();
+            }
+        }
+    }
+
+    /// Wayland has no way to tell the compositor about resize increments,
+    /// but a window that isn't maximized, fullscreen or tiled may choose
+    /// to be smaller than the size it was configured with, so we round
+    /// that size down to a whole number of cells
+    fn apply_resize_increments(&self, w: u32, h: u32) -> (u32, u32) {
+        let incr = match self.resize_increments {
+            Some(incr) if !self.size_is_constrained && self.window.is_some() => incr,
+            _ => return (w, h),
+        };
+
+        fn snap(pixels: i32, base: u16, step: u16) -> i32 {
+            let (base, step) = (base as i32, (step as i32).max(1));
+            if pixels <= base {
+                pixels
+            } else {
+                base + ((pixels - base) / step) * step
+            }
+        }
+
+        let width = snap(self.surface_to_pixels(w as i32), incr.base_width, incr.x);
+        let height = snap(self.surface_to_pixels(h as i32), incr.base_height, incr.y);
+        (
+            self.pixels_to_surface(width) as u32,
+            self.pixels_to_surface(height) as u32,
+        )
+    }
+
+    /// Returns the dpi that the configuration assigns to the output
+    /// that the window is on, if any
+    fn screen_dpi(&self) -> Option<usize> {
+        let conn = Connection::get()?;
+        let output = toolkit::get_surface_outputs(&self.surface)
+            .into_iter()
+            .next()?;
+        let name = conn.wayland().outputs.borrow().output_name(&output)?;
+        config()
+            .dpi_by_screen(&name)
+            .map(|dpi| dpi.round() as usize)
+    }
+
+    fn get_scale_factor(&self) -> f64 {
+        match self.fractional_scale_factor {
+            Some(scale) => scale,
+            None => (self.dimensions.dpi as i32 / crate::DEFAULT_DPI as i32) as f64,
+        }
     }
 
     fn surface_to_pixels(&self, surface: i32) -> i32 {
-        surface * self.get_dpi_factor()
+        // Fractional scales round halfway away from zero, as
+        // wp-fractional-scale-v1 specifies for toplevel surfaces
+        ((surface as f64) * self.get_scale_factor()).round() as i32
     }
 
     fn pixels_to_surface(&self, pixels: i32) -> i32 {
         // Take care to round up, otherwise we can lose a pixel
         // and that can effectively lose the final row of the
         // terminal
-        ((pixels as f64) / (self.get_dpi_factor() as f64)).ceil() as i32
+        ((pixels as f64) / self.get_scale_factor()).ceil() as i32
     }
 
     fn dispatch_pending_event(&mut self) {
@@ -431,41 +944,94 @@ impl WaylandWindowInner {
             pending = pending_events.clone();
             *pending_events = PendingEvent::default();
         }
+        if let Some(constrained) = pending.size_is_constrained {
+            if constrained && !self.size_is_constrained && self.window.is_some() {
+                self.restore_size.replace((
+                    self.pixels_to_surface(self.dimensions.pixel_width as i32) as u32,
+                    self.pixels_to_surface(self.dimensions.pixel_height as i32) as u32,
+                ));
+            } else if !constrained {
+                let restore_size = self.restore_size.take();
+                if pending.configure.is_none() {
+                    pending.configure = restore_size;
+                }
+            }
+            self.size_is_constrained = constrained;
+        }
+        if let Some(full_screen) = pending.full_screen {
+            self.full_screen = full_screen;
+        }
+        if let Some(maximized) = pending.maximized {
+            self.maximized = maximized;
+        }
+
         if pending.close && self.callbacks.can_close() {
             self.callbacks.destroy();
             self.window.take();
+            self.drop_down.take();
         }
 
-        if pending.configure.is_none() && pending.dpi.is_some() {
-            // Synthesize a pending configure event for the dpi change
+        if pending.configure.is_none()
+            && (pending.dpi.is_some() || pending.fractional_scale.is_some())
+        {
+            // Synthesize a pending configure event for the dpi change.
+            // This uses the prior scale to find the surface size.
             pending.configure.replace((
                 self.pixels_to_surface(self.dimensions.pixel_width as i32) as u32,
                 self.pixels_to_surface(self.dimensions.pixel_height as i32) as u32,
             ));
             log::debug!("synthesize configure with {:?}", pending.configure);
         }
+        if let Some(scale) = pending.fractional_scale {
+            self.fractional_scale_factor.replace(scale);
+        }
 
         if let Some((w, h)) = pending.configure.take() {
-            if self.window.is_some() {
-                let factor = get_surface_scale_factor(&self.surface);
-
-                let pixel_width = self.surface_to_pixels(w.try_into().unwrap());
-                let pixel_height = self.surface_to_pixels(h.try_into().unwrap());
-
-                // Avoid blurring by matching the scaling factor of the
-                // compositor; if it is going to double the size then
-                // we render at double the size anyway and tell it that
-                // the buffer is already doubled
-                self.surface.set_buffer_scale(factor);
+            if self.is_open() {
+                let (w, h) = self.apply_resize_increments(w, h);
+                let (pixel_width, pixel_height, dpi) = match self.fractional_scale_factor {
+                    Some(scale) => {
+                        // Render at the true fractional scale: the buffer
+                        // is sized in device pixels and the viewport tells
+                        // the compositor to present it at the surface size
+                        self.surface.set_buffer_scale(1);
+                        if let Some(viewport) = self.viewport.as_ref() {
+                            viewport.set_destination(w.try_into().unwrap(), h.try_into().unwrap());
+                        }
+                        (
+                            self.surface_to_pixels(w.try_into().unwrap()),
+                            self.surface_to_pixels(h.try_into().unwrap()),
+                            (scale * crate::DEFAULT_DPI).round() as usize,
+                        )
+                    }
+                    None => {
+                        let factor = get_surface_scale_factor(&self.surface);
+
+                        // Avoid blurring by matching the scaling factor of the
+                        // compositor; if it is going to double the size then
+                        // we render at double the size anyway and tell it that
+                        // the buffer is already doubled
+                        self.surface.set_buffer_scale(factor);
+
+                        (
+                            self.surface_to_pixels(w.try_into().unwrap()),
+                            self.surface_to_pixels(h.try_into().unwrap()),
+                            factor as usize * crate::DEFAULT_DPI as usize,
+                        )
+                    }
+                };
+                let dpi = self.screen_dpi().unwrap_or(dpi);
 
                 // Update the window decoration size
-                self.window.as_mut().unwrap().resize(w, h);
+                if let Some(window) = self.window.as_mut() {
+                    window.resize(w, h);
+                }
 
                 // Compute the new pixel dimensions
                 let new_dimensions = Dimensions {
                     pixel_width: pixel_width.try_into().unwrap(),
                     pixel_height: pixel_height.try_into().unwrap(),
-                    dpi: factor as usize * crate::DEFAULT_DPI as usize,
+                    dpi,
                 };
                 // Only trigger a resize if the new dimensions are different;
                 // this makes things more efficient and a little more smooth
@@ -492,6 +1058,96 @@ impl WaylandWindowInner {
         if let Some(window) = self.window.as_mut() {
             window.refresh();
             self.surface.commit();
+        } else if self.drop_down.is_some() {
+            self.surface.commit();
+        }
+    }
+
+    /// Returns true until the window has been closed
+    fn is_open(&self) -> bool {
+        self.window.is_some() || self.drop_down.is_some()
+    }
+
+    fn configure_drop_down(&mut self, serial: u32, width: u32, height: u32) {
+        let fallback_height = self.pixels_to_surface(self.dimensions.pixel_height as i32) as u32;
+        let drop_down = match self.drop_down.as_mut() {
+            Some(drop_down) => drop_down,
+            None => return,
+        };
+        drop_down.layer_surface.ack_configure(serial);
+
+        if drop_down.height.is_none() {
+            // This is the response to filling the screen; attach the
+            // window to the top of the screen at the configured height
+            let screen_height = if height > 0 { height } else { fallback_height };
+            let surface_height =
+                (u64::from(screen_height) * u64::from(drop_down.settings.height_percent) / 100)
+                    .max(1) as u32;
+            drop_down.height.replace(surface_height);
+            drop_down
+                .layer_surface
+                .set_anchor(Anchor::Top | Anchor::Left | Anchor::Right);
+            drop_down.layer_surface.set_size(0, surface_height);
+            self.surface.commit();
+            return;
+        }
+
+        drop_down.configured = true;
+        if width > 0 && height > 0 {
+            self.pending_event
+                .lock()
+                .unwrap()
+                .configure
+                .replace((width, height));
+            self.dispatch_pending_event();
+        }
+    }
+
+    /// Slides the drop-down into or out of view, by moving it
+    /// above the top of the screen in a series of steps
+    fn slide_drop_down(&mut self, visible: bool) {
+        let drop_down = match self.drop_down.as_mut() {
+            Some(drop_down) => drop_down,
+            None => return,
+        };
+        let height = match drop_down.height {
+            Some(height) if drop_down.visible != visible => height,
+            _ => return,
+        };
+        drop_down.visible = visible;
+        drop_down.slide_generation += 1;
+        // This takes effect with the first step of the slide
+        drop_down
+            .layer_surface
+            .set_keyboard_interactivity(drop_down.keyboard_interactivity());
+
+        let generation = drop_down.slide_generation;
+        let from = drop_down.margin_top;
+        let to = if visible {
+            drop_down.settings.margin_top as i32
+        } else {
+            -(height as i32)
+        };
+        let window_id = self.window_id;
+        std::thread::spawn(move || {
+            for step in 1..=DROP_DOWN_SLIDE_STEPS {
+                std::thread::sleep(DROP_DOWN_SLIDE_DURATION / DROP_DOWN_SLIDE_STEPS);
+                let margin_top = from + (to - from) * step as i32 / DROP_DOWN_SLIDE_STEPS as i32;
+                WaylandConnection::with_window_inner(window_id, move |inner| {
+                    inner.set_drop_down_margin(generation, margin_top);
+                    Ok(())
+                });
+            }
+        });
+    }
+
+    fn set_drop_down_margin(&mut self, generation: usize, margin_top: i32) {
+        if let Some(drop_down) = self.drop_down.as_mut() {
+            if drop_down.slide_generation == generation {
+                drop_down.margin_top = margin_top;
+                drop_down.apply_margins();
+                self.surface.commit();
+            }
         }
     }
 
@@ -525,6 +1181,7 @@ impl WaylandWindowInner {
                 .gl_connection
                 .borrow_mut()
                 .replace(Rc::clone(state.get_connection()));
+            self.egl_state.replace(Rc::clone(&state));
             Ok(glium::backend::Context::new(
                 Rc::clone(&state),
                 true,
@@ -542,7 +1199,62 @@ impl WaylandWindowInner {
         self.callbacks.created(&window, gl_state)
     }
 
+    /// Asks the compositor to tell us when the next commit is shown,
+    /// so that the application can learn the latency of its frames
+    fn request_presentation_feedback(&self) {
+        let conn = Connection::get().unwrap().wayland();
+        let presentation = match conn.presentation.as_ref() {
+            Some(presentation) => presentation,
+            None => return,
+        };
+        let clock_id = conn.presentation_clock.load(Ordering::Relaxed);
+        let paint_start = presentation_clock_now(clock_id);
+        let window_id = self.window_id;
+
+        let feedback = presentation.feedback(&self.surface);
+        feedback.quick_assign(move |_feedback, event, _dispatch_data| match event {
+            PresentationFeedbackEvent::Presented {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+                refresh,
+                ..
+            } => {
+                let tv_sec = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+                let presented = Duration::new(tv_sec, tv_nsec);
+                let presentation = FramePresentation {
+                    latency: presented
+                        .checked_sub(paint_start)
+                        .unwrap_or_else(|| Duration::from_secs(0)),
+                    refresh: if refresh == 0 {
+                        None
+                    } else {
+                        Some(Duration::from_nanos(refresh as u64))
+                    },
+                };
+                WaylandConnection::with_window_inner(window_id, move |inner| {
+                    inner.callbacks.frame_presented(presentation);
+                    Ok(())
+                });
+            }
+            PresentationFeedbackEvent::Discarded => {
+                WaylandConnection::with_window_inner(window_id, |inner| {
+                    inner.callbacks.frame_discarded();
+                    Ok(())
+                });
+            }
+            _ => {}
+        });
+    }
+
     fn do_paint(&mut self) -> anyhow::Result<()> {
+        if let Some(drop_down) = self.drop_down.as_ref() {
+            if !drop_down.configured {
+                // Attaching a buffer before then is a protocol error
+                return Ok(());
+            }
+        }
+
         if let Some(gl_context) = self.gl_state.as_ref() {
             if gl_context.is_context_lost() {
                 log::error!("opengl context was lost; should reinit");
@@ -551,6 +1263,10 @@ impl WaylandWindowInner {
                 return self.do_paint();
             }
 
+            // The feedback has to be requested before the buffer swap
+            // commits the surface
+            self.request_presentation_feedback();
+
             let mut frame = glium::Frame::new(
                 Rc::clone(&gl_context),
                 (
@@ -560,6 +1276,10 @@ impl WaylandWindowInner {
             );
 
             self.callbacks.paint(&mut frame);
+            if let Some(egl_state) = self.egl_state.as_ref() {
+                let damage = self.callbacks.damaged_region().unwrap_or_default();
+                egl_state.set_damage(&damage);
+            }
             frame.finish()?;
             // self.damage();
             self.refresh_frame();
@@ -592,6 +1312,83 @@ impl WindowOps for WaylandWindow {
         })
     }
 
+    fn focus(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.focus();
+            Ok(())
+        })
+    }
+
+    fn request_attention(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.request_attention();
+            Ok(())
+        })
+    }
+
+    fn toggle_fullscreen(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.toggle_fullscreen();
+            Ok(())
+        })
+    }
+
+    fn config_did_change(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.config_did_change();
+            Ok(())
+        })
+    }
+
+    fn set_resize_increments(&self, incr: ResizeIncrement) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(incr);
+            Ok(())
+        })
+    }
+
+    fn maximize(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.maximize();
+            Ok(())
+        })
+    }
+
+    fn restore(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.restore();
+            Ok(())
+        })
+    }
+
+    fn toggle_drop_down(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.toggle_drop_down();
+            Ok(())
+        })
+    }
+
+    fn set_keyboard_shortcuts_inhibited(&self, inhibit: bool) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_keyboard_shortcuts_inhibited(inhibit);
+            Ok(())
+        })
+    }
+
+    fn toggle_keyboard_shortcuts_inhibit(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.toggle_keyboard_shortcuts_inhibit();
+            Ok(())
+        })
+    }
+
+    fn set_idle_inhibited(&self, inhibit: bool) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_idle_inhibited(inhibit);
+            Ok(())
+        })
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         WaylandConnection::with_window_inner(self.0, move |inner| {
             inner.set_cursor(cursor);
@@ -642,12 +1439,27 @@ impl WindowOps for WaylandWindow {
         })
     }
 
-    fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
+        let use_primary_selection =
+            clipboard == Clipboard::PrimarySelection && WaylandConnection::has_primary_selection();
+        if clipboard == Clipboard::PrimarySelection && !use_primary_selection {
+            if let Some(text) = crate::primary_selection::get() {
+                return Future::ok(text);
+            }
+        }
         let mut promise = Promise::new();
         let future = promise.get_future().unwrap();
         let promise = Arc::new(Mutex::new(promise));
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            let read = inner.copy_and_paste.lock().unwrap().get_clipboard_data()?;
+            let read = if use_primary_selection {
+                inner
+                    .copy_and_paste
+                    .lock()
+                    .unwrap()
+                    .get_primary_selection_data()?
+            } else {
+                inner.copy_and_paste.lock().unwrap().get_clipboard_data()?
+            };
             let promise = Arc::clone(&promise);
             std::thread::spawn(move || {
                 let mut promise = promise.lock().unwrap();
@@ -669,7 +1481,38 @@ impl WindowOps for WaylandWindow {
         future
     }
 
-    fn set_clipboard(&self, _clipboard: Clipboard, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
+        if clipboard == Clipboard::PrimarySelection {
+            if !WaylandConnection::has_primary_selection() {
+                crate::primary_selection::set(text);
+                return Future::ok(());
+            }
+            return WaylandConnection::with_window_inner(self.0, move |inner| {
+                let text = text.clone();
+                let conn = Connection::get().unwrap().wayland();
+
+                let source = match conn.primary_selection_manager.as_ref() {
+                    Some(mgr) => mgr.create_source(),
+                    None => return Ok(()),
+                };
+                source.quick_assign(move |_source, event, _dispatch_data| {
+                    if let PrimarySelectionSourceEvent::Send { fd, .. } = event {
+                        let fd = unsafe { FileDescriptor::from_raw_fd(fd) };
+                        if let Err(e) = write_pipe_with_timeout(fd, text.as_bytes()) {
+                            log::error!("while sending primary selection to pipe: {}", e);
+                        }
+                    }
+                });
+                source.offer(TEXT_MIME_TYPE.to_string());
+                inner
+                    .copy_and_paste
+                    .lock()
+                    .unwrap()
+                    .set_primary_selection(&source);
+
+                Ok(())
+            });
+        }
         WaylandConnection::with_window_inner(self.0, move |inner| {
             let text = text.clone();
             let conn = Connection::get().unwrap().wayland();
@@ -695,6 +1538,14 @@ impl WindowOps for WaylandWindow {
     }
 }
 
+fn button_mask(button: MousePress) -> MouseButtons {
+    match button {
+        MousePress::Left => MouseButtons::LEFT,
+        MousePress::Right => MouseButtons::RIGHT,
+        MousePress::Middle => MouseButtons::MIDDLE,
+    }
+}
+
 fn write_pipe_with_timeout(mut file: FileDescriptor, data: &[u8]) -> anyhow::Result<()> {
     file.set_non_blocking(true)?;
     let mut pfd = libc::pollfd {
@@ -724,7 +1575,7 @@ fn write_pipe_with_timeout(mut file: FileDescriptor, data: &[u8]) -> anyhow::Res
     Ok(())
 }
 
-fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
+pub(crate) fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
     let mut result = Vec::new();
 
     file.set_non_blocking(true)?;
@@ -759,15 +1610,22 @@ impl WindowOpsMut for WaylandWindowInner {
     fn close(&mut self) {
         self.callbacks.destroy();
         self.window.take();
+        self.drop_down.take();
     }
 
     fn hide(&mut self) {
-        if let Some(window) = self.window.as_ref() {
+        if self.drop_down.is_some() {
+            self.slide_drop_down(false);
+        } else if let Some(window) = self.window.as_ref() {
             window.set_minimized();
         }
     }
 
     fn show(&mut self) {
+        if self.drop_down.is_some() {
+            self.slide_drop_down(true);
+            return;
+        }
         if self.window.is_none() {
             return;
         }
@@ -786,6 +1644,130 @@ impl WindowOpsMut for WaylandWindowInner {
         }
     }
 
+    fn focus(&mut self) {
+        if self.drop_down.is_some() {
+            self.slide_drop_down(true);
+        } else {
+            self.request_activation(true);
+        }
+    }
+
+    fn toggle_drop_down(&mut self) {
+        if let Some(drop_down) = self.drop_down.as_ref() {
+            let visible = !drop_down.visible;
+            self.slide_drop_down(visible);
+        }
+    }
+
+    fn set_keyboard_shortcuts_inhibited(&mut self, inhibit: bool) {
+        if !inhibit {
+            if let Some(inhibitor) = self.shortcuts_inhibitor.take() {
+                inhibitor.destroy();
+            }
+            return;
+        }
+        if self.shortcuts_inhibitor.is_some() {
+            return;
+        }
+
+        let conn = Connection::get().unwrap().wayland();
+        let (manager, seat) = match (conn.shortcuts_inhibit_manager.as_ref(), conn.seat.as_ref()) {
+            (Some(manager), Some(seat)) => (manager, seat),
+            _ => {
+                log::warn!(
+                    "cannot inhibit shortcuts: the compositor doesn't \
+                     support keyboard-shortcuts-inhibit"
+                );
+                return;
+            }
+        };
+        let inhibitor = manager.inhibit_shortcuts(&self.surface, seat);
+        let window_id = self.window_id;
+        inhibitor.quick_assign(move |_inhibitor, event, _dispatch_data| match event {
+            // The compositor may decline, or may later give the user
+            // a way to override the inhibitor, so this is just logged
+            ShortcutsInhibitorEvent::Active => {
+                log::debug!("window id={} compositor shortcuts are inhibited", window_id)
+            }
+            ShortcutsInhibitorEvent::Inactive => {
+                log::debug!("window id={} compositor shortcuts are active", window_id)
+            }
+            _ => {}
+        });
+        self.shortcuts_inhibitor.replace(inhibitor);
+    }
+
+    fn toggle_keyboard_shortcuts_inhibit(&mut self) {
+        let inhibit = self.shortcuts_inhibitor.is_none();
+        self.set_keyboard_shortcuts_inhibited(inhibit);
+    }
+
+    fn set_idle_inhibited(&mut self, inhibit: bool) {
+        if !inhibit {
+            if let Some(inhibitor) = self.idle_inhibitor.take() {
+                inhibitor.destroy();
+            }
+            return;
+        }
+        if self.idle_inhibitor.is_some() {
+            return;
+        }
+
+        let conn = Connection::get().unwrap().wayland();
+        match conn.idle_inhibit_manager.as_ref() {
+            // The compositor only honors this while the surface is visible
+            Some(manager) => {
+                self.idle_inhibitor
+                    .replace(manager.create_inhibitor(&self.surface));
+            }
+            None => log::warn!("cannot inhibit idle: the compositor doesn't support idle-inhibit"),
+        }
+    }
+
+    fn request_attention(&mut self) {
+        self.request_activation(false);
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if let Some(window) = self.window.as_ref() {
+            if self.full_screen {
+                window.unset_fullscreen();
+            } else {
+                // Fill the output that the window is on, rather
+                // than whichever one the compositor prefers
+                let output = toolkit::get_surface_outputs(&self.surface)
+                    .into_iter()
+                    .next();
+                window.set_fullscreen(output.as_ref());
+            }
+        }
+    }
+
+    fn set_resize_increments(&mut self, incr: ResizeIncrement) {
+        self.resize_increments.replace(incr);
+    }
+
+    fn config_did_change(&mut self) {
+        self.apply_opacity();
+        self.apply_background_blur();
+    }
+
+    fn maximize(&mut self) {
+        if let Some(window) = self.window.as_ref() {
+            window.set_maximized();
+        }
+    }
+
+    fn restore(&mut self) {
+        if let Some(window) = self.window.as_ref() {
+            if self.full_screen {
+                window.unset_fullscreen();
+            } else if self.maximized {
+                window.unset_maximized();
+            }
+        }
+    }
+
     fn set_cursor(&mut self, cursor: Option<MouseCursor>) {
         let cursor = match cursor {
             Some(MouseCursor::Arrow) => "arrow",
@@ -805,8 +1787,15 @@ impl WindowOpsMut for WaylandWindowInner {
     }
 
     fn set_inner_size(&mut self, width: usize, height: usize) {
-        let pixel_width = width as i32;
-        let pixel_height = height as i32;
+        if self.size_is_constrained {
+            // The compositor would ignore the new size, and the
+            // synthetic configure event below would leave us out
+            // of sync with the real size of the window
+            log::debug!("not resizing a maximized, fullscreen or tiled window");
+            return;
+        }
+        let pixel_width = width.max(1) as i32;
+        let pixel_height = height.max(1) as i32;
         let surface_width = self.pixels_to_surface(pixel_width) as u32;
         let surface_height = self.pixels_to_surface(pixel_height) as u32;
         // window.resize() doesn't generate a configure event,
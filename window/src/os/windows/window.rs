@@ -18,11 +18,14 @@ use std::io::{self, Error as IoError};
 use std::os::windows::ffi::OsStringExt;
 use std::ptr::{null, null_mut};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use winapi::shared::minwindef::*;
 use winapi::shared::ntdef::*;
 use winapi::shared::windef::*;
 use winapi::um::imm::*;
 use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winbase::SetThreadExecutionState;
+use winapi::um::winnt::{ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED};
 use winapi::um::winuser::*;
 use winreg::{enums::HKEY_CURRENT_USER, RegKey};
 
@@ -31,6 +34,10 @@ extern "system" {
     pub fn ImmGetCompositionStringW(himc: HIMC, index: DWORD, buf: LPVOID, buflen: DWORD) -> LONG;
 }
 
+/// The execution state applies to the whole of the gui thread,
+/// so this counts how many windows currently want to inhibit idle
+static IDLE_INHIBITORS: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct HWindow(HWND);
 unsafe impl Send for HWindow {}
@@ -48,6 +55,7 @@ pub(crate) struct WindowInner {
     last_size: Option<Dimensions>,
     in_size_move: bool,
     dead_pending: Option<(Modifiers, u32)>,
+    idle_inhibited: bool,
 
     keyboard_info: KeyboardLayoutInfo,
 }
@@ -122,7 +130,7 @@ impl WindowInner {
 
         let gl_state = if config().prefer_egl() {
             match conn.gl_connection.borrow().as_ref() {
-                None => crate::egl::GlState::create(None, self.hwnd.0),
+                None => crate::egl::GlState::create(None, None, self.hwnd.0),
                 Some(glconn) => {
                     crate::egl::GlState::create_with_existing_connection(glconn, self.hwnd.0)
                 }
@@ -303,6 +311,7 @@ impl Window {
             last_size: None,
             in_size_move: false,
             dead_pending: None,
+            idle_inhibited: false,
         }));
 
         // Careful: `raw` owns a ref to inner, but there is no Drop impl
@@ -431,6 +440,26 @@ impl WindowOpsMut for WindowInner {
         let imc = ImmContext::get(self.hwnd.0);
         imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
     }
+
+    fn set_idle_inhibited(&mut self, inhibit: bool) {
+        if inhibit == self.idle_inhibited {
+            return;
+        }
+        self.idle_inhibited = inhibit;
+        let inhibitors = if inhibit {
+            IDLE_INHIBITORS.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            IDLE_INHIBITORS.fetch_sub(1, Ordering::SeqCst) - 1
+        };
+        let state = if inhibitors > 0 {
+            ES_CONTINUOUS | ES_SYSTEM_REQUIRED | ES_DISPLAY_REQUIRED
+        } else {
+            ES_CONTINUOUS
+        };
+        unsafe {
+            SetThreadExecutionState(state);
+        }
+    }
 }
 
 impl WindowOps for Window {
@@ -494,6 +523,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_idle_inhibited(&self, inhibit: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_idle_inhibited(inhibit);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + FnMut(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         mut func: F,
@@ -508,7 +544,12 @@ impl WindowOps for Window {
         })
     }
 
-    fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
+        if clipboard == Clipboard::PrimarySelection {
+            if let Some(text) = crate::primary_selection::get() {
+                return Future::ok(text);
+            }
+        }
         Future::result(
             clipboard_win::get_clipboard_string()
                 .map(|s| s.replace("\r\n", "\n"))
@@ -516,7 +557,11 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, _clipboard: Clipboard, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
+        if clipboard == Clipboard::PrimarySelection {
+            crate::primary_selection::set(text);
+            return Future::ok(());
+        }
         Future::result(
             clipboard_win::set_clipboard_string(&text).context("Error setting clipboard"),
         )
@@ -549,6 +594,7 @@ unsafe fn wm_ncdestroy(
         let inner = take_rc_from_pointer(raw);
         let mut inner = inner.borrow_mut();
         inner.callbacks.borrow_mut().destroy();
+        inner.set_idle_inhibited(false);
         inner.hwnd = HWindow(null_mut());
         SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
     }
@@ -4,8 +4,9 @@ use crate::connection::ConnectionOps;
 use crate::os::xkeysyms;
 use crate::os::{Connection, Window};
 use crate::{
-    Clipboard, Dimensions, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress,
-    Point, Rect, ScreenPoint, Size, WindowCallbacks, WindowOps, WindowOpsMut,
+    config, Clipboard, Dimensions, MouseButtons, MouseCursor, MouseEvent, MouseEventKind,
+    MousePress, Point, Rect, ResizeIncrement, ScreenPoint, Size, WindowCallbacks, WindowOps,
+    WindowOpsMut,
 };
 use anyhow::{anyhow, Context as _};
 use promise::{Future, Promise};
@@ -99,6 +100,7 @@ impl XWindowInner {
 
         let gl_state = match conn.gl_connection.borrow().as_ref() {
             None => crate::egl::GlState::create(
+                Some(crate::egl::ffi::PLATFORM_X11_KHR),
                 Some(conn.conn.get_raw_dpy() as *const _),
                 self.window_id as *mut _,
             ),
@@ -785,7 +787,9 @@ impl XWindow {
             &[conn.atom_delete],
         );
 
-        // window.lock().unwrap().disable_decorations()?;
+        if !config().window_decorations() {
+            window.lock().unwrap().adjust_decorations(false)?;
+        }
 
         let window_handle = Window::X11(XWindow::from_id(window_id));
 
@@ -832,6 +836,14 @@ impl WindowOpsMut for XWindowInner {
         self.set_fullscreen_hint(!fullscreen).ok();
     }
 
+    fn set_resize_increments(&mut self, incr: ResizeIncrement) {
+        let hints = xcb_util::icccm::SizeHints::empty()
+            .resize(incr.x.into(), incr.y.into())
+            .base(incr.base_width.into(), incr.base_height.into())
+            .build();
+        xcb_util::icccm::set_wm_normal_hints(self.conn().conn(), self.window_id, &hints);
+    }
+
     fn set_inner_size(&mut self, width: usize, height: usize) {
         xcb::configure_window(
             self.conn().conn(),
@@ -914,6 +926,13 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn set_resize_increments(&self, incr: ResizeIncrement) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(incr);
+            Ok(())
+        })
+    }
+
     fn show(&self) -> Future<()> {
         XConnection::with_window_inner(self.0, |inner| {
             inner.show();
@@ -1,13 +1,15 @@
 #![cfg(all(unix, not(target_os = "macos")))]
 
-use crate::connection::ConnectionOps;
+use crate::connection::{ConnectionOps, ScreenInfo};
 #[cfg(feature = "wayland")]
 use crate::os::wayland::connection::WaylandConnection;
 #[cfg(feature = "wayland")]
 use crate::os::wayland::window::WaylandWindow;
 use crate::os::x11::connection::XConnection;
 use crate::os::x11::window::XWindow;
-use crate::{config, Clipboard, MouseCursor, ScreenPoint, WindowCallbacks, WindowOps};
+use crate::{
+    config, Clipboard, MouseCursor, ResizeIncrement, ScreenPoint, WindowCallbacks, WindowOps,
+};
 use promise::*;
 use std::any::Any;
 use std::rc::Rc;
@@ -30,11 +32,18 @@ impl Connection {
         #[cfg(feature = "wayland")]
         {
             if config().enable_wayland() {
-                match WaylandConnection::create_new() {
+                match Self::create_wayland() {
                     Ok(w) => {
                         log::debug!("Using wayland connection!");
                         return Ok(Connection::Wayland(Rc::new(w)));
                     }
+                    Err(e) if config().wayland_fallback_to_x11() => {
+                        log::warn!(
+                            "Unable to use Wayland, falling back to X11 \
+                             (XWayland): {:#}",
+                            e
+                        );
+                    }
                     Err(e) => {
                         log::debug!("Failed to init wayland: {}", e);
                     }
@@ -44,6 +53,18 @@ impl Connection {
         Ok(Connection::X11(Rc::new(XConnection::create_new()?)))
     }
 
+    #[cfg(feature = "wayland")]
+    fn create_wayland() -> anyhow::Result<WaylandConnection> {
+        let conn = WaylandConnection::create_new()?;
+        if config().wayland_fallback_to_x11() {
+            // Partially configured compositors may accept the connection
+            // but be unable to provide EGL; find out now, while we can
+            // still use X11 instead
+            conn.init_egl()?;
+        }
+        Ok(conn)
+    }
+
     pub fn new_window(
         &self,
         class_name: &str,
@@ -94,6 +115,23 @@ impl ConnectionOps for Connection {
             Self::Wayland(w) => w.run_message_loop(),
         }
     }
+
+    fn screens(&self) -> anyhow::Result<Vec<ScreenInfo>> {
+        match self {
+            Self::X11(x) => x.screens(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.screens(),
+        }
+    }
+
+    fn has_screen_coordinates(&self) -> bool {
+        match self {
+            Self::X11(x) => x.has_screen_coordinates(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.has_screen_coordinates(),
+        }
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         match self {
             Self::X11(x) => x.schedule_timer(interval, callback),
@@ -142,6 +180,70 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_resize_increments(&self, incr: ResizeIncrement) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_resize_increments(incr),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_resize_increments(incr),
+        }
+    }
+
+    fn maximize(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.maximize(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.maximize(),
+        }
+    }
+
+    fn restore(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.restore(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.restore(),
+        }
+    }
+
+    fn config_did_change(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.config_did_change(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.config_did_change(),
+        }
+    }
+
+    fn toggle_drop_down(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.toggle_drop_down(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.toggle_drop_down(),
+        }
+    }
+
+    fn set_keyboard_shortcuts_inhibited(&self, inhibit: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_keyboard_shortcuts_inhibited(inhibit),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_keyboard_shortcuts_inhibited(inhibit),
+        }
+    }
+
+    fn toggle_keyboard_shortcuts_inhibit(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.toggle_keyboard_shortcuts_inhibit(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.toggle_keyboard_shortcuts_inhibit(),
+        }
+    }
+
+    fn set_idle_inhibited(&self, inhibit: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_idle_inhibited(inhibit),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_idle_inhibited(inhibit),
+        }
+    }
+
     fn show(&self) -> Future<()> {
         match self {
             Self::X11(x) => x.show(),
@@ -150,6 +252,22 @@ impl WindowOps for Window {
         }
     }
 
+    fn focus(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.focus(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.focus(),
+        }
+    }
+
+    fn request_attention(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.request_attention(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.request_attention(),
+        }
+    }
+
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()> {
         match self {
             Self::X11(x) => x.set_cursor(cursor),
@@ -0,0 +1,21 @@
+//! Systems other than X11 have no primary selection of their own.
+//! We emulate one within the process so that selecting text and
+//! pasting it with the middle mouse button works the same way
+//! everywhere.
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    static ref PRIMARY_SELECTION: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Replaces the contents of the emulated primary selection
+pub fn set(text: String) {
+    PRIMARY_SELECTION.lock().unwrap().replace(text);
+}
+
+/// Returns the contents of the emulated primary selection, or None
+/// if nothing has been selected yet, in which case the caller should
+/// fall back to the clipboard
+pub fn get() -> Option<String> {
+    PRIMARY_SELECTION.lock().unwrap().clone()
+}